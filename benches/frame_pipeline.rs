@@ -0,0 +1,56 @@
+//! Measures the stdout frame loop's throughput in frames per second, reading
+//! uncompressed `rgb24` frames from memory at 1080p and 4K, so that FFmpeg
+//! itself isn't part of the measurement.
+//!
+//! ```console
+//! cargo bench --bench frame_pipeline --features bench-internals
+//! ```
+
+use std::io::Cursor;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use ffmpeg_sidecar::{
+  bench_internals::read_rawvideo_frames,
+  event::{Stream, StreamTypeSpecificData, VideoStream},
+};
+
+const FRAMES: usize = 30;
+
+fn rawvideo_stream(width: u32, height: u32) -> Stream {
+  Stream {
+    format: "rawvideo".to_string(),
+    language: String::new(),
+    parent_index: 0,
+    stream_index: 0,
+    bitrate_kbps: None,
+    encoder: None,
+    raw_log_message: String::new(),
+    type_specific_data: StreamTypeSpecificData::Video(VideoStream {
+      pix_fmt: "rgb24".to_string(),
+      width,
+      height,
+      fps: 25.0,
+    }),
+  }
+}
+
+fn read_frames(c: &mut Criterion) {
+  let mut group = c.benchmark_group("frame_pipeline");
+  group.throughput(Throughput::Elements(FRAMES as u64));
+  group.sample_size(20);
+  for (label, width, height) in [("1080p", 1920, 1080), ("4k", 3840, 2160)] {
+    let stream = rawvideo_stream(width, height);
+    let data = vec![0x80u8; (width * height * 3) as usize * FRAMES];
+    group.bench_with_input(BenchmarkId::new("rgb24", label), &data, |b, data| {
+      b.iter(|| {
+        let mut frames = 0usize;
+        read_rawvideo_frames(Cursor::new(data.as_slice()), &stream, |_| frames += 1).unwrap();
+        assert_eq!(frames, FRAMES);
+      })
+    });
+  }
+  group.finish();
+}
+
+criterion_group!(benches, read_frames);
+criterion_main!(benches);
@@ -0,0 +1,72 @@
+//! Measures `FfmpegLogParser` throughput in lines per second, over a typical
+//! stderr log: a banner and stream descriptions followed by progress updates
+//! (which FFmpeg terminates with `\r`) interleaved with warnings.
+//!
+//! ```console
+//! cargo bench --bench log_parser
+//! ```
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use ffmpeg_sidecar::{event::FfmpegEvent, log_parser::FfmpegLogParser};
+
+const HEADER: &str = "\
+[info] ffmpeg version 7.1 Copyright (c) 2000-2024 the FFmpeg developers
+[info]   built with gcc 14.2.0 (GCC)
+[info]   configuration: --enable-gpl --enable-version3 --enable-libx264 --enable-libx265 --enable-libvpx --enable-libopus
+[info]   libavutil      59. 39.100 / 59. 39.100
+[info]   libavcodec     61. 19.100 / 61. 19.100
+[info]   libavformat    61.  7.100 / 61.  7.100
+[info] Input #0, lavfi, from 'testsrc=duration=60':
+[info]   Duration: N/A, start: 0.000000, bitrate: N/A
+[info]   Stream #0:0: Video: wrapped_avframe, rgb24, 1920x1080 [SAR 1:1 DAR 16:9], 25 fps, 25 tbr, 25 tbn
+[info] Stream mapping:
+[info]   Stream #0:0 -> #0:0 (wrapped_avframe (native) -> rawvideo (native))
+[info] Output #0, rawvideo, to 'pipe:':
+[info]   Metadata:
+[info]     encoder         : Lavf61.7.100
+[info]   Stream #0:0: Video: rawvideo (RGB[24] / 0x18424752), rgb24(progressive), 1920x1080 [SAR 1:1 DAR 16:9], q=2-31, 1244160 kb/s, 25 fps, 25 tbn
+[info]     Metadata:
+[info]       encoder         : Lavc61.19.100 rawvideo
+";
+
+const PROGRESS_LINES: usize = 10_000;
+
+/// Build a log of `HEADER` plus `PROGRESS_LINES` further lines, returning it
+/// with its total line count.
+fn sample_log() -> (String, u64) {
+  let mut log = HEADER.to_string();
+  for i in 0..PROGRESS_LINES {
+    match i % 10 {
+      9 => log.push_str("[warning] Past duration 0.999992 too large\n"),
+      _ => log.push_str(&format!(
+        "[info] frame={i:>5} fps= 25 q=-0.0 size= {:>8}KiB time=00:{:02}:{:02}.{:02} bitrate=1244160.0kbits/s speed=1.00x\r",
+        i * 6075,
+        i / 1500,
+        (i / 25) % 60,
+        (i % 25) * 4,
+      )),
+    }
+  }
+  let lines = HEADER.lines().count() + PROGRESS_LINES;
+  (log, lines as u64)
+}
+
+fn parse_log(c: &mut Criterion) {
+  let (log, lines) = sample_log();
+  let mut group = c.benchmark_group("log_parser");
+  group.throughput(Throughput::Elements(lines));
+  group.bench_function("parse_next_event", |b| {
+    b.iter(|| {
+      let mut parser = FfmpegLogParser::new(log.as_bytes());
+      let mut events = 0usize;
+      while !matches!(parser.parse_next_event(), Ok(FfmpegEvent::LogEOF) | Err(_)) {
+        events += 1;
+      }
+      events
+    })
+  });
+  group.finish();
+}
+
+criterion_group!(benches, parse_log);
+criterion_main!(benches);
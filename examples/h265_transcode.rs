@@ -1,8 +1,9 @@
-use std::{io::Write, path::Path, thread};
+use std::path::Path;
 
 use ffmpeg_sidecar::{
   command::FfmpegCommand,
   event::{FfmpegEvent, LogLevel},
+  frame_pump::InputFramePump,
 };
 
 /// 1. Read an H265 source video from file
@@ -29,7 +30,9 @@ fn main() {
 
   // Frames can be transformed by Iterator `.map()`.
   // This example is a no-op, with frames passed through unaltered.
-  let transformed_frames = input.iter().unwrap().filter_frames();
+  let mut input_iter = input.iter().unwrap();
+  let metadata = input_iter.collect_metadata().unwrap();
+  let transformed_frames = input_iter.filter_frames();
 
   // You could easily add some "middleware" processing here:
   // - overlay or composite another RGB image (or even another Ffmpeg Iterator)
@@ -39,25 +42,24 @@ fn main() {
   // control, debuggability, and modularity -- you can pull in any Rust crate
   // you need.
 
-  // A second instance encodes the updated frames back to H265
+  // A second instance encodes the updated frames back to H265, with its input
+  // format inferred from the decoded output stream of the first instance
+  let pump = InputFramePump::like(&metadata.output_streams[0]).unwrap();
   let mut output = FfmpegCommand::new()
-    .args([
-      "-f", "rawvideo", "-pix_fmt", "rgb24", "-s", "600x800", "-r", "30",
-    ]) // note: should be possible to infer these params from the source input stream
-    .input("-")
+    .frame_input(&pump)
     .args(["-c:v", "libx265"])
     .args(["-y", "output/h265_overlay.mp4"])
     .spawn()
     .unwrap();
 
-  // Connect the two instances
-  let mut stdin = output.take_stdin().unwrap();
-  thread::spawn(move || {
-    // `for_each` blocks through the end of the iterator,
-    // so we run it in another thread.
-    transformed_frames.for_each(|f| {
-      stdin.write_all(&f.data).ok();
-    });
+  // Connect the two instances; frames are written on a background thread,
+  // which waits whenever the encoder falls behind
+  let mut writer = output.frame_writer().unwrap();
+  std::thread::spawn(move || {
+    for frame in transformed_frames {
+      writer.write_frame(&frame)?;
+    }
+    writer.finish()
   });
 
   // On the main thread, run the output instance to completion
@@ -2,6 +2,7 @@ use anyhow::{Context, Result};
 use ffmpeg_sidecar::{
   command::FfmpegCommand,
   event::{FfmpegEvent, LogLevel},
+  extract::LogExtractor,
 };
 use std::{cmp::max, iter::repeat};
 
@@ -45,6 +46,7 @@ pub fn main() -> Result<()> {
     .args("-af ebur128=metadata=1,ametadata=print".split(' '))
     .format("null")
     .output("-")
+    .extract(LogExtractor::key_value("loudness", "lavfi.r128.M"))
     .spawn()?
     .iter()?;
 
@@ -61,37 +63,37 @@ pub fn main() -> Result<()> {
       FfmpegEvent::Error(e) | FfmpegEvent::Log(LogLevel::Error | LogLevel::Fatal, e) => {
         eprintln!("{e}");
       }
-      FfmpegEvent::Log(LogLevel::Info, msg) if msg.contains("lavfi.r128.M=") => {
-        if let Some(volume) = msg.split("lavfi.r128.M=").last() {
-          // Sample log output: [Parsed_ametadata_1 @ 0000024c27effdc0] [info] lavfi.r128.M=-120.691
-          // M = "momentary loudness"; a sliding time window of 400ms
-          // Volume scale is roughly -70 to 0 LUFS. Anything below -70 is silence.
-          // See <https://en.wikipedia.org/wiki/EBU_R_128#Metering>
-          let volume_f32 = volume.parse::<f32>().context("Failed to parse volume")?;
-          let volume_normalized: usize = max(((volume_f32 / 5.0).round() as i32) + 14, 0) as usize;
-          let volume_percent = ((volume_normalized as f32 / 14.0) * 100.0).round();
+      FfmpegEvent::Extracted(loudness) => {
+        // Sample log output: [Parsed_ametadata_1 @ 0000024c27effdc0] [info] lavfi.r128.M=-120.691
+        // M = "momentary loudness"; a sliding time window of 400ms
+        // Volume scale is roughly -70 to 0 LUFS. Anything below -70 is silence.
+        // See <https://en.wikipedia.org/wiki/EBU_R_128#Metering>
+        let volume_f32 = loudness
+          .parse::<f32>("lavfi.r128.M")
+          .context("Failed to parse volume")?;
+        let volume_normalized: usize = max(((volume_f32 / 5.0).round() as i32) + 14, 0) as usize;
+        let volume_percent = ((volume_normalized as f32 / 14.0) * 100.0).round();
 
-          // Clear previous line of output
-          if !first_volume_event {
-            print!("\x1b[1A\x1b[2K");
-          } else {
-            first_volume_event = false;
-          }
+        // Clear previous line of output
+        if !first_volume_event {
+          print!("\x1b[1A\x1b[2K");
+        } else {
+          first_volume_event = false;
+        }
 
-          // Blinking red dot to indicate recording
-          let time = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-          let recording_indicator = if time % 2 == 0 { "🔴" } else { "  " };
+        // Blinking red dot to indicate recording
+        let time = std::time::SystemTime::now()
+          .duration_since(std::time::UNIX_EPOCH)
+          .unwrap()
+          .as_secs();
+        let recording_indicator = if time % 2 == 0 { "🔴" } else { "  " };
 
-          println!(
-            "{} {} {}%",
-            recording_indicator,
-            repeat('█').take(volume_normalized).collect::<String>(),
-            volume_percent
-          );
-        }
+        println!(
+          "{} {} {}%",
+          recording_indicator,
+          repeat('█').take(volume_normalized).collect::<String>(),
+          volume_percent
+        );
       }
       _ => {}
     }
@@ -15,9 +15,9 @@ fn main() -> anyhow::Result<()> {
   use ffmpeg_sidecar::event::{FfmpegEvent, LogLevel};
   use ffmpeg_sidecar::named_pipes::NamedPipe;
   use ffmpeg_sidecar::pipe_name;
-  use std::io::Read;
   use std::sync::mpsc;
   use std::thread;
+  use std::time::Duration;
 
   const VIDEO_PIPE_NAME: &str = pipe_name!("ffmpeg_video");
   const AUDIO_PIPE_NAME: &str = pipe_name!("ffmpeg_audio");
@@ -71,8 +71,9 @@ fn main() -> anyhow::Result<()> {
         ready_receiver.recv()?;
 
         // Read continuously until finished
-        // Note that if the stream of output is interrupted or paused,
-        // you may need additional logic to keep the read loop alive.
+        // The timeout prevents the thread from hanging forever if FFmpeg exits
+        // without ever opening the pipe, or stalls partway through. Increase
+        // it if the stream of output may legitimately pause for longer.
         println!("[{pipe_name}] reading from pipe");
         let mut buf = vec![0; 1920 * 1080 * 3];
         let mut total_bytes_read = 0;
@@ -85,7 +86,7 @@ fn main() -> anyhow::Result<()> {
         };
 
         loop {
-          match pipe.read(&mut buf) {
+          match pipe.read_timeout(&mut buf, Duration::from_secs(10)) {
             Ok(bytes_read) => {
               total_bytes_read += bytes_read;
 
@@ -131,33 +132,26 @@ fn main() -> anyhow::Result<()> {
     })
     .collect::<Result<Vec<_>>>()?;
 
-  // Start FFmpeg
-  let mut ready_signal_sent = false;
-  command
-    .print_command()
-    .spawn()?
-    .iter()?
-    .for_each(|event| match event {
-      // Signal threads when output is ready
-      FfmpegEvent::Progress(_) if !ready_signal_sent => {
-        threads.iter().for_each(|(_, sender)| {
-          sender.send(()).ok();
-        });
-        ready_signal_sent = true;
-      }
-
-      // Verify output size from FFmpeg logs (video/audio KiB)
-      FfmpegEvent::Log(LogLevel::Info, msg) if msg.starts_with("[out#") => {
-        println!("{msg}");
-      }
-
-      // Log any unexpected errors
-      FfmpegEvent::Log(LogLevel::Warning | LogLevel::Error | LogLevel::Fatal, msg) => {
-        eprintln!("{msg}");
-      }
-
-      _ => {}
-    });
+  // Start FFmpeg, and signal the threads once it's writing output
+  let mut child = command.print_command().spawn()?;
+  child.wait_until_writing(Duration::from_secs(10))?;
+  threads.iter().for_each(|(_, sender)| {
+    sender.send(()).ok();
+  });
+
+  child.iter()?.for_each(|event| match event {
+    // Verify output size from FFmpeg logs (video/audio KiB)
+    FfmpegEvent::Log(LogLevel::Info, msg) if msg.starts_with("[out#") => {
+      println!("{msg}");
+    }
+
+    // Log any unexpected errors
+    FfmpegEvent::Log(LogLevel::Warning | LogLevel::Error | LogLevel::Fatal, msg) => {
+      eprintln!("{msg}");
+    }
+
+    _ => {}
+  });
 
   for (thread, _) in threads {
     thread.join().unwrap()?;
@@ -0,0 +1,70 @@
+use anyhow::Result;
+use ffmpeg_sidecar::{
+  gpu::Hw,
+  recorder::{MicSource, Recorder, RecorderEvent},
+};
+use std::time::{Duration, Instant};
+
+/// Record the screen and microphone for 15 seconds, with a 5 second pause in
+/// the middle, printing the recorder's events as a UI would display them.
+///
+/// Hardware encoding is attempted with NVENC, falling back to `libx264` if
+/// it's unavailable. The output is split into 5 second segments.
+pub fn main() -> Result<()> {
+  let mut recording = Recorder::new("recordings")
+    .mic(MicSource::platform_default()?)
+    .hardware(Hw::Nvidia)
+    .segment_time(Duration::from_secs(5))
+    .start()?;
+
+  let start = Instant::now();
+  let mut paused_at = None;
+  loop {
+    for event in recording.events() {
+      print_event(&event);
+    }
+
+    let elapsed = start.elapsed();
+    if elapsed > Duration::from_secs(20) {
+      break;
+    } else if elapsed > Duration::from_secs(10) && recording.is_paused() {
+      recording.resume()?;
+    } else if elapsed > Duration::from_secs(5) && paused_at.is_none() {
+      recording.pause()?;
+      paused_at = Some(elapsed);
+    }
+    std::thread::sleep(Duration::from_millis(100));
+  }
+
+  recording.stop()?;
+  for event in recording.events() {
+    print_event(&event);
+  }
+  Ok(())
+}
+
+fn print_event(event: &RecorderEvent) {
+  match event {
+    RecorderEvent::EncoderSelected { encoder, fallback } => match fallback {
+      Some(reason) => println!("Encoding with {encoder} ({reason})"),
+      None => println!("Encoding with {encoder}"),
+    },
+    RecorderEvent::Started => println!("🔴 Recording"),
+    RecorderEvent::Paused => println!("⏸️ Paused"),
+    RecorderEvent::Resumed => println!("🔴 Resumed"),
+    RecorderEvent::Progress { recorded, .. } => {
+      // Overwrite the previous progress line
+      print!("\r{:.1}s recorded", recorded.as_secs_f32());
+      std::io::Write::flush(&mut std::io::stdout()).ok();
+    }
+    RecorderEvent::FileStarted(path) => println!("\nWriting {}", path.display()),
+    RecorderEvent::FileFinished(path) => println!("\nFinished {}", path.display()),
+    RecorderEvent::Error(e) => eprintln!("\n{e}"),
+    RecorderEvent::Stopped { files, recorded } => println!(
+      "\nStopped after {:.1}s, in {} files",
+      recorded.as_secs_f32(),
+      files.len()
+    ),
+    _ => {}
+  }
+}
@@ -1,23 +1,17 @@
-use anyhow::{bail, Result};
+use anyhow::Result;
 use ffmpeg_sidecar::command::FfmpegCommand;
 use ffmpeg_sidecar::event::{FfmpegEvent, LogLevel};
+use ffmpeg_sidecar::tcp_output::tcp_outputs;
 use std::io::Read;
-use std::net::{TcpListener, TcpStream};
-use std::sync::mpsc::{channel, Receiver};
-use std::thread;
+use std::net::TcpStream;
 use std::time::Duration;
 
 fn main() -> Result<()> {
-  // Set up a TCP listener
-  const TCP_PORT: u32 = 3000;
-  let (exit_sender, exit_receiver) = channel::<()>();
-  let listener_thread = thread::spawn(|| listen_for_connections(TCP_PORT, exit_receiver));
-
-  // Wait for the listener to start
-  thread::sleep(Duration::from_millis(1000));
+  // Set up one loopback listener per output, on ports chosen by the OS
+  let [video, audio, subtitles] = <[_; 3]>::try_from(tcp_outputs(3)?).unwrap();
 
   // Prepare an FFmpeg command with separate outputs for video, audio, and subtitles.
-  FfmpegCommand::new()
+  let mut child = FfmpegCommand::new()
     // Global flags
     .hide_banner()
     .overwrite() // <- overwrite required on windows
@@ -36,88 +30,56 @@ fn main() -> Result<()> {
     .map("0:v")
     .format("rawvideo")
     .pix_fmt("rgb24")
-    .output(format!("tcp://127.0.0.1:{TCP_PORT}"))
+    .output(video.url())
     // Audio output
     .map("1:a")
     .format("s16le")
-    .output(format!("tcp://127.0.0.1:{TCP_PORT}"))
+    .output(audio.url())
     // Subtitles output
     .map("2:s")
     .format("srt")
-    .output(format!("tcp://127.0.0.1:{TCP_PORT}"))
+    .output(subtitles.url())
     .print_command()
-    .spawn()?
-    .iter()?
-    .for_each(|event| match event {
-      // Verify output size from FFmpeg logs (video/audio KiB)
-      FfmpegEvent::Log(LogLevel::Info, msg) if msg.starts_with("[out#") => {
-        println!("{msg}");
-      }
+    .spawn()?;
 
-      // Log any unexpected errors
-      FfmpegEvent::Log(LogLevel::Warning | LogLevel::Error | LogLevel::Fatal, msg) => {
-        eprintln!("{msg}");
-      }
+  // Read each output on its own thread, failing if FFmpeg never connects
+  let timeout = Duration::from_secs(10);
+  let readers = [
+    video.spawn_reader(timeout, handle_connection),
+    audio.spawn_reader(timeout, handle_connection),
+    subtitles.spawn_reader(timeout, handle_connection),
+  ];
 
-      // _ => {}
-      e => {
-        println!("{:?}", e);
-      }
-    });
-  exit_sender.send(())?;
-  listener_thread.join().unwrap()?;
-  Ok(())
-}
-
-fn listen_for_connections(tcp_port: u32, exit_receiver: Receiver<()>) -> Result<()> {
-  let listener = TcpListener::bind(format!("127.0.0.1:{tcp_port}"))?;
-  listener.set_nonblocking(true)?;
-  println!("Server listening on port {tcp_port}");
+  child.iter()?.for_each(|event| match event {
+    // Verify output size from FFmpeg logs (video/audio KiB)
+    FfmpegEvent::Log(LogLevel::Info, msg) if msg.starts_with("[out#") => {
+      println!("{msg}");
+    }
 
-  let mut handler_threads = Vec::new();
-  loop {
-    if exit_receiver.try_recv().is_ok() {
-      break;
+    // Log any unexpected errors
+    FfmpegEvent::Log(LogLevel::Warning | LogLevel::Error | LogLevel::Fatal, msg) => {
+      eprintln!("{msg}");
     }
-    match listener.accept() {
-      Ok((stream, _)) => {
-        handler_threads.push(thread::spawn(move || handle_connection(stream)));
-      }
-      Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-        thread::sleep(Duration::from_millis(10));
-      }
-      Err(e) => {
-        bail!(e);
-      }
+
+    // _ => {}
+    e => {
+      println!("{:?}", e);
     }
-  }
+  });
 
-  for handler in handler_threads {
-    handler.join().unwrap()?;
+  for reader in readers {
+    reader.join().unwrap()?;
   }
-
-  println!("Listener thread exiting");
   Ok(())
 }
 
-fn handle_connection(mut stream: TcpStream) -> Result<()> {
+fn handle_connection(stream: &mut TcpStream) -> std::io::Result<()> {
   let mut buffer = [0; 1024];
   let mut total_bytes_read = 0;
   loop {
-    match stream.read(&mut buffer) {
-      Ok(bytes_read) if bytes_read > 0 => {
-        total_bytes_read += bytes_read;
-      }
-      Ok(0) => {
-        break;
-      }
-      Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-        thread::sleep(Duration::from_millis(10));
-      }
-      Err(e) => {
-        bail!(e);
-      }
-      _ => {}
+    match stream.read(&mut buffer)? {
+      0 => break,
+      bytes_read => total_bytes_read += bytes_read,
     }
   }
   let bytes_str = if total_bytes_read < 1024 {
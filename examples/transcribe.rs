@@ -0,0 +1,29 @@
+use anyhow::Result;
+use ffmpeg_sidecar::transcribe::{transcribe, TranscribeOptions};
+
+/// Print subtitles for the speech in a media file, using FFmpeg's `whisper`
+/// filter. Requires FFmpeg 8.0+ built with `--enable-whisper`, and a
+/// whisper.cpp model such as `ggml-base.en.bin` from
+/// <https://huggingface.co/ggerganov/whisper.cpp>.
+///
+/// ```console
+/// cargo run --example transcribe -- input.mp4 ggml-base.en.bin
+/// ```
+pub fn main() -> Result<()> {
+  let mut args = std::env::args().skip(1);
+  let (Some(input), Some(model)) = (args.next(), args.next()) else {
+    anyhow::bail!("Usage: transcribe <input> <model>");
+  };
+
+  let options = TranscribeOptions::default().language("en");
+  for segment in transcribe(input, model, &options)? {
+    let segment = segment?;
+    println!(
+      "[{:>7.2}s -> {:>7.2}s] {}",
+      segment.start.as_secs_f32(),
+      segment.end.as_secs_f32(),
+      segment.text
+    );
+  }
+  Ok(())
+}
@@ -0,0 +1,734 @@
+//! Analysis passes which run a detection filter over an input and summarize
+//! its log output.
+
+use std::{collections::HashMap, ffi::OsStr, fmt, ops::RangeInclusive, time::Duration};
+
+use anyhow::Context;
+
+use crate::{
+  command::FfmpegCommand,
+  event::{FfmpegEvent, FilterMetadata, LogLevel},
+};
+
+/// A crop rectangle, as suggested by the `cropdetect` filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CropRect {
+  pub width: u32,
+  pub height: u32,
+  pub x: u32,
+  pub y: u32,
+}
+
+impl CropRect {
+  /// A `crop` filter which applies this rectangle, e.g. `crop=1920:800:0:140`.
+  pub fn filter(&self) -> String {
+    self.to_string()
+  }
+}
+
+impl fmt::Display for CropRect {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "crop={}:{}:{}:{}",
+      self.width, self.height, self.x, self.y
+    )
+  }
+}
+
+/// Parse the suggested crop from a `cropdetect` log line.
+///
+/// ```rust
+/// use ffmpeg_sidecar::analysis::{try_parse_crop, CropRect};
+///
+/// let line = "[Parsed_cropdetect_0 @ 0x6000] [info] x1:0 x2:1919 y1:138 y2:941 w:1920 h:800 x:0 y:140 pts:3003 t:0.100100 limit:0.094118 crop=1920:800:0:140";
+/// assert_eq!(
+///   try_parse_crop(line),
+///   Some(CropRect { width: 1920, height: 800, x: 0, y: 140 })
+/// );
+/// ```
+pub fn try_parse_crop(string: &str) -> Option<CropRect> {
+  if !string.contains("cropdetect") {
+    return None;
+  }
+  let crop = string.split("crop=").nth(1)?.split_whitespace().next()?;
+  let mut values = crop.split(':').map(|value| value.parse::<u32>().ok());
+  Some(CropRect {
+    width: values.next()??,
+    height: values.next()??,
+    x: values.next()??,
+    y: values.next()??,
+  })
+}
+
+/// Run `cropdetect` over the first `sample_duration` of `input` and return
+/// the most frequently suggested crop rectangle, e.g. to remove letterboxing:
+///
+/// ```rust,no_run
+/// use ffmpeg_sidecar::{analysis::detect_crop, command::FfmpegCommand};
+/// use std::time::Duration;
+///
+/// let crop = detect_crop("input.mp4", Duration::from_secs(30))?;
+/// FfmpegCommand::new()
+///   .input("input.mp4")
+///   .filter(crop.filter())
+///   .output("cropped.mp4")
+///   .spawn()?
+///   .wait()?;
+/// # anyhow::Ok(())
+/// ```
+///
+/// Using the dominant suggestion rather than the last or largest one avoids
+/// being misled by fades and dark scenes.
+pub fn detect_crop<S: AsRef<OsStr>>(
+  input: S,
+  sample_duration: Duration,
+) -> anyhow::Result<CropRect> {
+  let mut counts = HashMap::<CropRect, usize>::new();
+  let mut errors = Vec::new();
+
+  FfmpegCommand::new()
+    .duration(sample_duration)
+    .input(input.as_ref())
+    .filter("cropdetect")
+    .args(["-an", "-f", "null", "-"])
+    .spawn()?
+    .iter()?
+    .for_each(|event| match event {
+      FfmpegEvent::Log(LogLevel::Error | LogLevel::Fatal, e) | FfmpegEvent::Error(e) => {
+        errors.push(e)
+      }
+      FfmpegEvent::Log(_, line) => {
+        if let Some(crop) = try_parse_crop(&line) {
+          *counts.entry(crop).or_default() += 1;
+        }
+      }
+      _ => {}
+    });
+
+  counts
+    .into_iter()
+    .max_by_key(|(crop, count)| (*count, crop.width * crop.height))
+    .map(|(crop, _)| crop)
+    .with_context(|| format!("No crop detected: {}", errors.join("\n")))
+}
+
+/// The maximum number of frames analyzed by [`detect_interlace`].
+pub const INTERLACE_SAMPLE_FRAMES: u32 = 1000;
+
+/// Frame counts from one of the `idet` filter's detection methods.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IdetCounts {
+  /// Top field first.
+  pub tff: u64,
+  /// Bottom field first.
+  pub bff: u64,
+  pub progressive: u64,
+  pub undetermined: u64,
+}
+
+/// The field order of interlaced video.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldOrder {
+  TopFieldFirst,
+  BottomFieldFirst,
+}
+
+/// Deinterlacing filters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Deinterlacer {
+  /// "Yet another deinterlacing filter"; fast and widely used.
+  Yadif,
+  /// "Bob weaver deinterlacing filter"; based on yadif, with higher quality.
+  Bwdif,
+}
+
+impl Deinterlacer {
+  /// The filter string, outputting one frame per input frame. The field order
+  /// is detected from frame flags when `parity` is `None`.
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::analysis::{Deinterlacer, FieldOrder};
+  ///
+  /// assert_eq!(
+  ///   Deinterlacer::Bwdif.filter(Some(FieldOrder::TopFieldFirst)),
+  ///   "bwdif=mode=send_frame:parity=tff"
+  /// );
+  /// assert_eq!(Deinterlacer::Yadif.filter(None), "yadif=mode=send_frame:parity=auto");
+  /// ```
+  pub fn filter(&self, parity: Option<FieldOrder>) -> String {
+    let name = match self {
+      Deinterlacer::Yadif => "yadif",
+      Deinterlacer::Bwdif => "bwdif",
+    };
+    let parity = match parity {
+      Some(FieldOrder::TopFieldFirst) => "tff",
+      Some(FieldOrder::BottomFieldFirst) => "bff",
+      None => "auto",
+    };
+    format!("{name}=mode=send_frame:parity={parity}")
+  }
+}
+
+/// The summary of an `idet` (interlace detection) pass.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InterlaceDetection {
+  /// Per-frame detection, comparing each frame with its neighbours.
+  pub single_frame: IdetCounts,
+  /// Detection which also takes previous results into account; more reliable.
+  pub multi_frame: IdetCounts,
+}
+
+impl InterlaceDetection {
+  /// The dominant field order, or `None` if most frames are progressive.
+  pub fn field_order(&self) -> Option<FieldOrder> {
+    let IdetCounts {
+      tff,
+      bff,
+      progressive,
+      ..
+    } = self.multi_frame;
+    match tff + bff > progressive {
+      false => None,
+      true if tff >= bff => Some(FieldOrder::TopFieldFirst),
+      true => Some(FieldOrder::BottomFieldFirst),
+    }
+  }
+
+  pub fn is_interlaced(&self) -> bool {
+    self.field_order().is_some()
+  }
+
+  /// A deinterlacing filter for the detected field order, or `None` if the
+  /// input is progressive and should be left alone.
+  pub fn deinterlace_filter(&self, deinterlacer: Deinterlacer) -> Option<String> {
+    self
+      .field_order()
+      .map(|order| deinterlacer.filter(Some(order)))
+  }
+
+  /// Update the counts from an `idet` summary log line. Returns `false` if the
+  /// line isn't an `idet` summary.
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::analysis::{FieldOrder, InterlaceDetection};
+  ///
+  /// let mut detection = InterlaceDetection::default();
+  /// detection.update("[Parsed_idet_0 @ 0x6000] [info] Single frame detection: TFF:    92 BFF:     0 Progressive:     3 Undetermined:     5");
+  /// detection.update("[Parsed_idet_0 @ 0x6000] [info] Multi frame detection: TFF:    99 BFF:     0 Progressive:     1 Undetermined:     0");
+  /// assert_eq!(detection.single_frame.undetermined, 5);
+  /// assert_eq!(detection.field_order(), Some(FieldOrder::TopFieldFirst));
+  /// ```
+  pub fn update(&mut self, line: &str) -> bool {
+    if !line.contains("idet") {
+      return false;
+    }
+    let counts = match () {
+      _ if line.contains("Single frame detection:") => &mut self.single_frame,
+      _ if line.contains("Multi frame detection:") => &mut self.multi_frame,
+      _ => return false,
+    };
+    let value_after = |key: &str| -> Option<u64> {
+      line
+        .split(key)
+        .nth(1)?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+    };
+    match (
+      value_after("TFF:"),
+      value_after("BFF:"),
+      value_after("Progressive:"),
+      value_after("Undetermined:"),
+    ) {
+      (Some(tff), Some(bff), Some(progressive), Some(undetermined)) => {
+        *counts = IdetCounts {
+          tff,
+          bff,
+          progressive,
+          undetermined,
+        };
+        true
+      }
+      _ => false,
+    }
+  }
+}
+
+/// Run the `idet` filter over the first [`INTERLACE_SAMPLE_FRAMES`] frames of
+/// `input` and return its summary counters.
+///
+/// ```rust,no_run
+/// use ffmpeg_sidecar::{
+///   analysis::{detect_interlace, Deinterlacer},
+///   command::FfmpegCommand,
+/// };
+///
+/// let detection = detect_interlace("archive.mpg")?;
+/// let mut command = FfmpegCommand::new();
+/// command.input("archive.mpg");
+/// if let Some(filter) = detection.deinterlace_filter(Deinterlacer::Bwdif) {
+///   command.filter(filter);
+/// }
+/// command.output("normalized.mp4").spawn()?.wait()?;
+/// # anyhow::Ok(())
+/// ```
+pub fn detect_interlace<S: AsRef<OsStr>>(input: S) -> anyhow::Result<InterlaceDetection> {
+  let mut detection = InterlaceDetection::default();
+  let mut found = false;
+  let mut errors = Vec::new();
+
+  FfmpegCommand::new()
+    .input(input.as_ref())
+    .filter("idet")
+    .frames(INTERLACE_SAMPLE_FRAMES)
+    .args(["-an", "-f", "null", "-"])
+    .spawn()?
+    .iter()?
+    .for_each(|event| match event {
+      FfmpegEvent::Log(LogLevel::Error | LogLevel::Fatal, e) | FfmpegEvent::Error(e) => {
+        errors.push(e)
+      }
+      FfmpegEvent::Log(_, line) => found |= detection.update(&line),
+      _ => {}
+    });
+
+  match found {
+    true => Ok(detection),
+    false => anyhow::bail!("No interlace detection summary: {}", errors.join("\n")),
+  }
+}
+
+/// Per-channel (or overall) statistics from the `astats` filter.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ChannelStats {
+  /// The lowest sample value, in the sample format's own scale.
+  pub min_level: f64,
+  /// The highest sample value, in the sample format's own scale.
+  pub max_level: f64,
+  /// The highest absolute sample value in dBFS. Float samples can exceed 0.
+  pub peak_db: f64,
+  pub rms_db: f64,
+  /// How many times the signal reached `min_level` or `max_level`. A run of
+  /// consecutive samples at that level counts once, so this isn't the
+  /// number of clipped samples.
+  pub peak_count: u64,
+}
+
+impl ChannelStats {
+  /// Whether the peak reached full scale (within 0.01 dB), which almost
+  /// always means that samples were clipped.
+  pub fn is_clipping(&self) -> bool {
+    self.peak_db >= -0.01
+  }
+
+  /// The [`peak_count`](Self::peak_count) if the peak reached full scale,
+  /// i.e. how many times the channel clipped, and zero otherwise.
+  pub fn clipping_peak_count(&self) -> u64 {
+    match self.is_clipping() {
+      true => self.peak_count,
+      false => 0,
+    }
+  }
+}
+
+/// The summary printed by the `astats` filter at the end of a run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AudioStats {
+  pub channels: Vec<ChannelStats>,
+  pub overall: ChannelStats,
+  /// The number of samples per channel.
+  pub samples: u64,
+  /// Whether the `Overall` section of the summary has started.
+  in_overall: bool,
+}
+
+impl AudioStats {
+  /// Whether any channel clipped.
+  pub fn is_clipping(&self) -> bool {
+    self.channels.iter().any(ChannelStats::is_clipping)
+  }
+
+  /// The total [`ChannelStats::clipping_peak_count`] across channels.
+  pub fn clipping_peak_count(&self) -> u64 {
+    self
+      .channels
+      .iter()
+      .map(ChannelStats::clipping_peak_count)
+      .sum()
+  }
+
+  /// Update the stats from an `astats` summary log line, which is either a
+  /// section header (`Channel: 1`, `Overall`) or a value of the current
+  /// section. Returns `false` if the line isn't part of an `astats` summary.
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::analysis::AudioStats;
+  ///
+  /// let mut stats = AudioStats::default();
+  /// for line in [
+  ///   "[Parsed_astats_1 @ 0x6000] [info] Channel: 1",
+  ///   "[Parsed_astats_1 @ 0x6000] [info] Peak level dB: 0.000000",
+  ///   "[Parsed_astats_1 @ 0x6000] [info] Peak count: 412",
+  ///   "[Parsed_astats_1 @ 0x6000] [info] Overall",
+  ///   "[Parsed_astats_1 @ 0x6000] [info] Number of samples: 48000",
+  /// ] {
+  ///   assert!(stats.update(line));
+  /// }
+  /// assert!(stats.is_clipping());
+  /// assert_eq!(stats.clipping_peak_count(), 412);
+  /// assert_eq!(stats.samples, 48000);
+  /// ```
+  pub fn update(&mut self, line: &str) -> bool {
+    if !line.contains("astats") {
+      return false;
+    }
+    let Some(message) = line.rsplit("] ").next() else {
+      return false;
+    };
+    let message = message.trim();
+    if message == "Overall" {
+      self.in_overall = true;
+      return true;
+    }
+    let Some((key, value)) = message.split_once(": ") else {
+      return false;
+    };
+    if key == "Channel" {
+      self.channels.push(ChannelStats::default());
+      self.in_overall = false;
+      return true;
+    }
+
+    let value = value.trim();
+    if key == "Number of samples" {
+      self.samples = value.parse().unwrap_or_default();
+      return true;
+    }
+    let stats = match self.channels.last_mut() {
+      _ if self.in_overall => &mut self.overall,
+      Some(stats) => stats,
+      None => return false,
+    };
+    let float = || value.parse::<f64>().ok();
+    match key {
+      "Min level" => stats.min_level = float().unwrap_or_default(),
+      "Max level" => stats.max_level = float().unwrap_or_default(),
+      "Peak level dB" => stats.peak_db = float().unwrap_or(f64::NEG_INFINITY),
+      "RMS level dB" => stats.rms_db = float().unwrap_or(f64::NEG_INFINITY),
+      "Peak count" => stats.peak_count = float().unwrap_or_default() as u64,
+      _ => {}
+    }
+    true
+  }
+}
+
+/// Measure the audio of `input` with the `astats` filter, e.g. to check a
+/// finished output for clipping after downmixing or changing its volume.
+///
+/// ```rust,no_run
+/// use ffmpeg_sidecar::{analysis::audio_stats, command::FfmpegCommand};
+///
+/// FfmpegCommand::new()
+///   .input("surround.mkv")
+///   .pan("stereo", &[[1.0, 0.0, 0.7, 0.0, 0.7, 0.0], [0.0, 1.0, 0.7, 0.0, 0.0, 0.7]])
+///   .output("stereo.mkv")
+///   .spawn()?
+///   .wait()?;
+///
+/// let stats = audio_stats("stereo.mkv")?;
+/// if stats.is_clipping() {
+///   println!("Clipped {} times; re-running with a limiter", stats.clipping_peak_count());
+///   // ... `.filter("pan=...,alimiter")`
+/// }
+/// # anyhow::Ok(())
+/// ```
+pub fn audio_stats<S: AsRef<OsStr>>(input: S) -> anyhow::Result<AudioStats> {
+  audio_stats_with_filter(input, None)
+}
+
+/// Like [`audio_stats`], but measures the output of an audio `filter` such as
+/// a `pan` downmix or `volume` change without encoding anything, e.g. to
+/// check for clipping before running the real job.
+pub fn audio_stats_with_filter<S: AsRef<OsStr>>(
+  input: S,
+  filter: Option<&str>,
+) -> anyhow::Result<AudioStats> {
+  let mut stats = AudioStats::default();
+  let mut found = false;
+  let mut errors = Vec::new();
+
+  let filter = match filter {
+    Some(filter) => format!("{filter},astats"),
+    None => "astats".to_string(),
+  };
+  FfmpegCommand::new()
+    .input(input.as_ref())
+    .args(["-filter:a", &filter])
+    .args(["-vn", "-sn", "-dn", "-f", "null", "-"])
+    .spawn()?
+    .iter()?
+    .for_each(|event| match event {
+      FfmpegEvent::Log(LogLevel::Error | LogLevel::Fatal, e) | FfmpegEvent::Error(e) => {
+        errors.push(e)
+      }
+      FfmpegEvent::Log(_, line) => found |= stats.update(&line),
+      _ => {}
+    });
+
+  match found {
+    true => Ok(stats),
+    false => anyhow::bail!("No audio stats summary: {}", errors.join("\n")),
+  }
+}
+
+/// The `signalstats` filter with the optional temporal outlier (`TOUT`),
+/// vertical line repetition (`VREP`) and broadcast range (`BRNG`) checks
+/// enabled, printing its values for each frame.
+pub const SIGNAL_STATS_FILTER: &str = "signalstats=stat=tout+vrep+brng,metadata=print";
+
+/// The `signalstats` values of one video frame. Values are in the scale of the
+/// input's bit depth, e.g. 0-255 for 8-bit video.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SignalStatsFrame {
+  pub pts_time: Option<f64>,
+  /// The lowest, average and highest luma (brightness).
+  pub y_min: f64,
+  pub y_avg: f64,
+  pub y_max: f64,
+  pub sat_avg: f64,
+  pub sat_max: f64,
+  /// The average hue, in degrees.
+  pub hue_avg: f64,
+  /// The fraction of temporal outlier pixels, typical of tape dropouts and
+  /// decoding corruption.
+  pub tout: f64,
+  /// The fraction of repeated lines, typical of dropouts in analog captures.
+  pub vrep: f64,
+  /// The fraction of pixels outside of the broadcast legal range.
+  pub brng: f64,
+}
+
+/// Summary quantiles of one value across all frames.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Quantiles {
+  pub min: f64,
+  pub p05: f64,
+  pub median: f64,
+  pub p95: f64,
+  pub max: f64,
+}
+
+impl Quantiles {
+  /// The nearest-rank quantiles of `values`, or `None` if there are no values.
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::analysis::Quantiles;
+  ///
+  /// let quantiles = Quantiles::from_values((1..=100).map(f64::from)).unwrap();
+  /// assert_eq!((quantiles.min, quantiles.median, quantiles.max), (1.0, 50.0, 100.0));
+  /// assert_eq!((quantiles.p05, quantiles.p95), (5.0, 95.0));
+  /// ```
+  pub fn from_values<I: IntoIterator<Item = f64>>(values: I) -> Option<Self> {
+    let mut values: Vec<f64> = values.into_iter().filter(|v| !v.is_nan()).collect();
+    if values.is_empty() {
+      return None;
+    }
+    values.sort_by(f64::total_cmp);
+    let quantile = |q: f64| {
+      let rank = (q * values.len() as f64).ceil() as usize;
+      values[rank.clamp(1, values.len()) - 1]
+    };
+    Some(Self {
+      min: values[0],
+      p05: quantile(0.05),
+      median: quantile(0.5),
+      p95: quantile(0.95),
+      max: values[values.len() - 1],
+    })
+  }
+}
+
+/// The per-frame output of a `signalstats` pass, with summaries for automated
+/// quality control.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SignalStats {
+  pub frames: Vec<SignalStatsFrame>,
+}
+
+impl SignalStats {
+  /// Below this average luma, an 8-bit frame is considered black; limited
+  /// range black is 16.
+  pub const BLACK_Y_AVG: f64 = 20.0;
+  /// Above this maximum saturation, an 8-bit frame exceeds the broadcast
+  /// legal limit.
+  pub const MAX_LEGAL_SATURATION: f64 = 118.2;
+  /// Above this fraction of temporal outliers, a frame is considered
+  /// corrupted.
+  pub const MAX_TOUT: f64 = 0.005;
+
+  /// Add a value printed by [`SIGNAL_STATS_FILTER`]. Values belong to the
+  /// current frame until the `pts_time` changes. Returns `false` if the value
+  /// isn't from `signalstats`.
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::{analysis::SignalStats, event::FilterMetadata};
+  ///
+  /// let mut stats = SignalStats::default();
+  /// for (pts_time, key, value) in [
+  ///   (0.0, "lavfi.signalstats.YAVG", "16.2"),
+  ///   (0.0, "lavfi.signalstats.SATMAX", "0"),
+  ///   (0.04, "lavfi.signalstats.YAVG", "128.5"),
+  ///   (0.04, "lavfi.signalstats.SATMAX", "126"),
+  /// ] {
+  ///   stats.update(&FilterMetadata {
+  ///     filter: "Parsed_metadata_1".to_string(),
+  ///     key: key.to_string(),
+  ///     value: value.to_string(),
+  ///     pts_time: Some(pts_time),
+  ///     raw_log_message: String::new(),
+  ///   });
+  /// }
+  /// assert_eq!(stats.frames.len(), 2);
+  /// assert_eq!(stats.black_segments(), vec![0.0..=0.0]);
+  /// assert_eq!(stats.oversaturated_segments(), vec![0.04..=0.04]);
+  /// assert_eq!(stats.brightness().unwrap().max, 128.5);
+  /// ```
+  pub fn update(&mut self, metadata: &FilterMetadata) -> bool {
+    let Some(key) = metadata.key.strip_prefix("lavfi.signalstats.") else {
+      return false;
+    };
+    let Ok(value) = metadata.value.trim().parse::<f64>() else {
+      return false;
+    };
+    let frame = match self.frames.last_mut() {
+      Some(frame) if frame.pts_time == metadata.pts_time => frame,
+      _ => {
+        self.frames.push(SignalStatsFrame {
+          pts_time: metadata.pts_time,
+          ..Default::default()
+        });
+        self.frames.last_mut().unwrap()
+      }
+    };
+    match key {
+      "YMIN" => frame.y_min = value,
+      "YAVG" => frame.y_avg = value,
+      "YMAX" => frame.y_max = value,
+      "SATAVG" => frame.sat_avg = value,
+      "SATMAX" => frame.sat_max = value,
+      "HUEAVG" => frame.hue_avg = value,
+      "TOUT" => frame.tout = value,
+      "VREP" => frame.vrep = value,
+      "BRNG" => frame.brng = value,
+      _ => {}
+    }
+    true
+  }
+
+  /// Quantiles of any per-frame value.
+  pub fn quantiles<F: Fn(&SignalStatsFrame) -> f64>(&self, value: F) -> Option<Quantiles> {
+    Quantiles::from_values(self.frames.iter().map(value))
+  }
+
+  /// Quantiles of the average luma.
+  pub fn brightness(&self) -> Option<Quantiles> {
+    self.quantiles(|frame| frame.y_avg)
+  }
+
+  /// Quantiles of the average saturation.
+  pub fn saturation(&self) -> Option<Quantiles> {
+    self.quantiles(|frame| frame.sat_avg)
+  }
+
+  /// Quantiles of the average hue.
+  pub fn hue(&self) -> Option<Quantiles> {
+    self.quantiles(|frame| frame.hue_avg)
+  }
+
+  /// The `pts_time` ranges of consecutive frames matching `predicate`, from
+  /// the first to the last matching frame of each run.
+  pub fn segments<F: Fn(&SignalStatsFrame) -> bool>(
+    &self,
+    predicate: F,
+  ) -> Vec<RangeInclusive<f64>> {
+    let mut segments = Vec::new();
+    let mut current: Option<RangeInclusive<f64>> = None;
+    for frame in &self.frames {
+      let pts_time = frame.pts_time.unwrap_or_default();
+      current = match (predicate(frame), current.take()) {
+        (true, Some(segment)) => Some(*segment.start()..=pts_time),
+        (true, None) => Some(pts_time..=pts_time),
+        (false, segment) => {
+          segments.extend(segment);
+          None
+        }
+      };
+    }
+    segments.extend(current);
+    segments
+  }
+
+  /// Whether every frame is black. `false` if there are no frames.
+  pub fn is_all_black(&self) -> bool {
+    !self.frames.is_empty() && self.frames.iter().all(|f| f.y_avg < Self::BLACK_Y_AVG)
+  }
+
+  /// Runs of black frames, see [`SignalStats::BLACK_Y_AVG`].
+  pub fn black_segments(&self) -> Vec<RangeInclusive<f64>> {
+    self.segments(|frame| frame.y_avg < Self::BLACK_Y_AVG)
+  }
+
+  /// Runs of frames exceeding [`SignalStats::MAX_LEGAL_SATURATION`].
+  pub fn oversaturated_segments(&self) -> Vec<RangeInclusive<f64>> {
+    self.segments(|frame| frame.sat_max > Self::MAX_LEGAL_SATURATION)
+  }
+
+  /// Runs of frames with more than [`SignalStats::MAX_TOUT`] temporal
+  /// outliers.
+  pub fn corrupted_segments(&self) -> Vec<RangeInclusive<f64>> {
+    self.segments(|frame| frame.tout > Self::MAX_TOUT)
+  }
+}
+
+/// Run the `signalstats` filter over the video of `input` and collect its
+/// values for every frame, e.g. as an automated QC gate:
+///
+/// ```rust,no_run
+/// use ffmpeg_sidecar::analysis::signal_stats;
+///
+/// let stats = signal_stats("delivery.mov")?;
+/// anyhow::ensure!(!stats.is_all_black(), "delivery is black");
+/// for segment in stats.corrupted_segments() {
+///   println!("possible corruption from {:?}s to {:?}s", segment.start(), segment.end());
+/// }
+/// println!("median brightness: {:?}", stats.brightness().map(|b| b.median));
+/// # anyhow::Ok(())
+/// ```
+pub fn signal_stats<S: AsRef<OsStr>>(input: S) -> anyhow::Result<SignalStats> {
+  let mut stats = SignalStats::default();
+  let mut errors = Vec::new();
+
+  FfmpegCommand::new()
+    .input(input.as_ref())
+    .filter(SIGNAL_STATS_FILTER)
+    .args(["-an", "-sn", "-dn", "-f", "null", "-"])
+    .spawn()?
+    .iter()?
+    .for_each(|event| match event {
+      FfmpegEvent::Log(LogLevel::Error | LogLevel::Fatal, e) | FfmpegEvent::Error(e) => {
+        errors.push(e)
+      }
+      FfmpegEvent::FilterMetadata(metadata) => {
+        stats.update(&metadata);
+      }
+      _ => {}
+    });
+
+  match stats.frames.is_empty() {
+    false => Ok(stats),
+    true => anyhow::bail!("No signal stats: {}", errors.join("\n")),
+  }
+}
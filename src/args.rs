@@ -0,0 +1,517 @@
+//! Structured view of an FFmpeg argument list.
+//!
+//! FFmpeg arguments are positional: global options apply to the whole
+//! process, while every other option applies to the next input (`-i`) or
+//! output file on the command line. [`ArgModel`] groups a flat argument list
+//! into those sections, so that it can be inspected and validated.
+
+/// Options which always apply to the whole process, regardless of position.
+const GLOBAL_OPTIONS: &[&str] = &[
+  "-y",
+  "-n",
+  "-loglevel",
+  "-v",
+  "-hide_banner",
+  "-report",
+  "-filter_complex",
+  "-filter_complex_script",
+  "-lavfi",
+  "-filter_threads",
+  "-progress",
+  "-stats",
+  "-nostats",
+  "-stats_period",
+  "-stdin",
+  "-nostdin",
+  "-benchmark",
+  "-benchmark_all",
+  "-xerror",
+  "-abort_on",
+  "-ignore_unknown",
+  "-copy_unknown",
+  "-max_error_rate",
+  "-init_hw_device",
+  "-filter_hw_device",
+  "-sdp_file",
+  "-vstats",
+  "-vstats_file",
+  "-debug_ts",
+  "-dump",
+  "-hex",
+  "-max_alloc",
+  "-cpuflags",
+  "-cpucount",
+  "-recast_media",
+];
+
+/// Options which take a value, even one which looks like a flag, such as a
+/// filtergraph starting with a dash. Stream specifiers are ignored, e.g.
+/// `-c:v` matches `-c`.
+const VALUE_OPTIONS: &[&str] = &[
+  "-loglevel",
+  "-v",
+  "-filter_complex",
+  "-filter_complex_script",
+  "-lavfi",
+  "-progress",
+  "-f",
+  "-c",
+  "-codec",
+  "-vcodec",
+  "-acodec",
+  "-scodec",
+  "-map",
+  "-filter",
+  "-filter_script",
+  "-vf",
+  "-af",
+  "-ss",
+  "-sseof",
+  "-t",
+  "-to",
+  "-itsoffset",
+  "-stream_loop",
+  "-metadata",
+  "-disposition",
+  "-r",
+  "-s",
+  "-pix_fmt",
+  "-b",
+  "-ar",
+  "-ac",
+  "-frames",
+];
+
+/// Options which don't take a value. Any other option is assumed to be
+/// followed by exactly one value, unless it's missing from `VALUE_OPTIONS`
+/// and the next argument is itself a flag.
+const BOOLEAN_OPTIONS: &[&str] = &[
+  "-y",
+  "-n",
+  "-hide_banner",
+  "-report",
+  "-stats",
+  "-stdin",
+  "-benchmark",
+  "-benchmark_all",
+  "-xerror",
+  "-ignore_unknown",
+  "-copy_unknown",
+  "-vstats",
+  "-debug_ts",
+  "-dump",
+  "-hex",
+  "-recast_media",
+  "-copyts",
+  "-start_at_zero",
+  "-shortest",
+  "-re",
+  "-an",
+  "-vn",
+  "-sn",
+  "-dn",
+  "-accurate_seek",
+  "-autorotate",
+  "-autoscale",
+  "-seek_timestamp",
+  "-bitexact",
+  "-copyinkf",
+  "-fix_sub_duration",
+  "-fix_sub_duration_heartbeat",
+  "-find_stream_info",
+  "-qphist",
+  "-psnr",
+  "-print_graphs",
+  "-version",
+  "-buildconf",
+  "-formats",
+  "-muxers",
+  "-demuxers",
+  "-devices",
+  "-codecs",
+  "-decoders",
+  "-encoders",
+  "-bsfs",
+  "-protocols",
+  "-filters",
+  "-pix_fmts",
+  "-layouts",
+  "-sample_fmts",
+  "-dispositions",
+  "-colors",
+  "-hwaccels",
+  "-L",
+];
+
+/// A single option flag and its value, if it takes one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OptionArg {
+  /// The flag including its leading dash, e.g. `-c:v`.
+  pub flag: String,
+  pub value: Option<String>,
+  /// The index of the flag in the flat argument list.
+  pub position: usize,
+}
+
+/// An input or output file, along with the options that apply to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileArgs {
+  /// The path or URL, e.g. `input.mp4` or `-` for stdin/stdout.
+  pub url: String,
+  pub options: Vec<OptionArg>,
+  /// The index of the url in the flat argument list.
+  pub position: usize,
+}
+
+impl FileArgs {
+  /// The value of the last occurrence of `flag`, if present.
+  pub fn option(&self, flag: &str) -> Option<&str> {
+    self
+      .options
+      .iter()
+      .rfind(|option| option.flag == flag)
+      .and_then(|option| option.value.as_deref())
+  }
+
+  /// The codec or encoder requested for one stream of this output by the last
+  /// matching `-c`/`-codec` option (or the legacy `-vcodec`, `-acodec` and
+  /// `-scodec`). The stream is identified by its `index` in the output, its
+  /// `media` type (`v`, `a`, `s`, `d` or `t`), and its `type_index` among
+  /// the streams of that type.
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::args::ArgModel;
+  ///
+  /// let model = ArgModel::from_args([
+  ///   "-i", "in.mkv", "-c", "copy", "-c:v", "h264_nvenc", "-c:a:1", "aac", "out.mkv",
+  /// ]);
+  /// let output = &model.outputs[0];
+  /// assert_eq!(output.codec_for('v', 0, 0), Some("h264_nvenc"));
+  /// assert_eq!(output.codec_for('a', 1, 0), Some("copy"));
+  /// assert_eq!(output.codec_for('a', 2, 1), Some("aac"));
+  /// ```
+  pub fn codec_for(&self, media: char, index: u32, type_index: u32) -> Option<&str> {
+    let matches = |flag: &str| {
+      let specifier = match flag {
+        "-vcodec" => ":v",
+        "-acodec" => ":a",
+        "-scodec" => ":s",
+        flag => flag.strip_prefix("-codec").or(flag.strip_prefix("-c"))?,
+      };
+      let parts: Vec<&str> = specifier.split(':').collect();
+      Some(match parts[..] {
+        [""] => true,
+        ["", kind] if kind.chars().all(char::is_alphabetic) => kind.starts_with(media),
+        ["", stream] => stream.parse() == Ok(index),
+        ["", kind, stream] => kind.starts_with(media) && stream.parse() == Ok(type_index),
+        _ => false,
+      })
+    };
+    self
+      .options
+      .iter()
+      .rfind(|option| matches(&option.flag) == Some(true))
+      .and_then(|option| option.value.as_deref())
+  }
+
+  /// The options which ffprobe needs to open this input the way FFmpeg
+  /// does: its format, and the parameters of a raw input, renamed from
+  /// FFmpeg's input options to the demuxer options ffprobe understands.
+  pub(crate) fn probe_options(&self) -> Vec<&str> {
+    let mut probe_options = Vec::new();
+    for option in &self.options {
+      let flag = match option.flag.as_str() {
+        "-f" => "-f",
+        "-s" | "-video_size" => "-video_size",
+        "-pix_fmt" | "-pixel_format" => "-pixel_format",
+        "-r" | "-framerate" => "-framerate",
+        "-ar" | "-sample_rate" => "-sample_rate",
+        _ => continue,
+      };
+      if let Some(value) = &option.value {
+        probe_options.extend([flag, value]);
+      }
+    }
+    probe_options
+  }
+}
+
+/// Arguments grouped into global options, inputs, and outputs.
+///
+/// ```rust
+/// use ffmpeg_sidecar::args::ArgModel;
+///
+/// let model = ArgModel::from_args([
+///   "-y", "-ss", "5", "-i", "input.mp4", "-c:v", "libx264", "output.mp4",
+/// ]);
+/// assert_eq!(model.global[0].flag, "-y");
+/// assert_eq!(model.inputs[0].url, "input.mp4");
+/// assert_eq!(model.inputs[0].option("-ss"), Some("5"));
+/// assert_eq!(model.outputs[0].url, "output.mp4");
+/// assert_eq!(model.outputs[0].option("-c:v"), Some("libx264"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ArgModel {
+  pub global: Vec<OptionArg>,
+  pub inputs: Vec<FileArgs>,
+  pub outputs: Vec<FileArgs>,
+  /// Options which haven't (yet) been followed by an input or output. If the
+  /// argument list ends here, FFmpeg ignores them as trailing options.
+  pub pending: Vec<OptionArg>,
+  len: usize,
+  expecting: Option<Expecting>,
+  /// Positions of options marked with `mark_input_only`.
+  input_only: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Expecting {
+  InputUrl,
+  GlobalValue,
+  PendingValue,
+}
+
+impl ArgModel {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn from_args<I, S>(args: I) -> Self
+  where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+  {
+    let mut model = Self::new();
+    for arg in args {
+      model.push(arg.as_ref());
+    }
+    model
+  }
+
+  /// Append the next argument from the flat argument list.
+  pub fn push(&mut self, arg: &str) {
+    let position = self.len;
+    self.len += 1;
+
+    // An unlisted boolean option mustn't take the next flag as its value, or
+    // every following argument would be misplaced
+    let awaiting_value = match self.expecting {
+      Some(Expecting::GlobalValue) => self.global.last(),
+      Some(Expecting::PendingValue) => self.pending.last(),
+      _ => None,
+    };
+    if awaiting_value.is_some_and(|option| !is_known_value_option(&option.flag)) && is_flag(arg) {
+      self.expecting = None;
+    }
+
+    match self.expecting.take() {
+      Some(Expecting::InputUrl) => {
+        let options = std::mem::take(&mut self.pending);
+        let url = arg.to_string();
+        self.inputs.push(FileArgs {
+          url,
+          options,
+          position,
+        });
+      }
+      Some(Expecting::GlobalValue) => set_value(&mut self.global, arg),
+      Some(Expecting::PendingValue) => set_value(&mut self.pending, arg),
+      None if arg == "-i" => self.expecting = Some(Expecting::InputUrl),
+      None if arg.starts_with('-') && arg.len() > 1 => {
+        let option = OptionArg {
+          flag: arg.to_string(),
+          value: None,
+          position,
+        };
+        let global = GLOBAL_OPTIONS.contains(&arg);
+        if !is_boolean(arg) {
+          self.expecting = Some(match global {
+            true => Expecting::GlobalValue,
+            false => Expecting::PendingValue,
+          });
+        }
+        match global {
+          true => self.global.push(option),
+          false => self.pending.push(option),
+        }
+      }
+      None => {
+        let options = std::mem::take(&mut self.pending);
+        let url = arg.to_string();
+        self.outputs.push(FileArgs {
+          url,
+          options,
+          position,
+        });
+      }
+    }
+  }
+
+  /// The number of arguments pushed so far.
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  /// Mark the last argument pushed, an option flag, as one which FFmpeg only
+  /// accepts before an input.
+  pub(crate) fn mark_input_only(&mut self) {
+    if let Some(position) = self.len.checked_sub(1) {
+      self.input_only.push(position);
+    }
+  }
+
+  /// The first option marked with `mark_input_only` which doesn't apply to
+  /// an input, because no `-i` followed it.
+  pub(crate) fn misplaced_input_option(&self) -> Option<&OptionArg> {
+    let outputs = self.outputs.iter().flat_map(|output| &output.options);
+    outputs
+      .chain(&self.pending)
+      .filter(|option| self.input_only.contains(&option.position))
+      .min_by_key(|option| option.position)
+  }
+
+  /// Parse `args`, an edited copy of the arguments of this model, keeping
+  /// the options marked with `mark_input_only` which are still present.
+  pub(crate) fn reparse<I, S>(&self, args: I) -> Self
+  where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+  {
+    let mut model = Self::from_args(args);
+    model.input_only.clone_from(&self.input_only);
+    model.input_only.retain(|position| *position < model.len);
+    model
+  }
+}
+
+fn is_boolean(flag: &str) -> bool {
+  // Boolean options can also be negated with a `no` prefix, e.g. `-noautorotate`
+  BOOLEAN_OPTIONS.contains(&flag)
+    || flag
+      .strip_prefix("-no")
+      .is_some_and(|rest| BOOLEAN_OPTIONS.contains(&format!("-{rest}").as_str()))
+}
+
+fn is_known_value_option(flag: &str) -> bool {
+  let flag = flag.split(':').next().unwrap_or(flag);
+  VALUE_OPTIONS.contains(&flag)
+}
+
+/// Whether `arg` is an option flag rather than a value: a dash followed by a
+/// letter, unlike `-` for stdin or a negative number like `-1`.
+fn is_flag(arg: &str) -> bool {
+  arg
+    .strip_prefix('-')
+    .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_alphabetic()))
+}
+
+fn set_value(options: &mut [OptionArg], value: &str) {
+  if let Some(option) = options.last_mut() {
+    option.value = Some(value.to_string());
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_arg_model_sections() {
+    let model = ArgModel::from_args([
+      "-loglevel",
+      "level+info",
+      "-stream_loop",
+      "-1",
+      "-i",
+      "a.mp4",
+      "-f",
+      "lavfi",
+      "-i",
+      "sine",
+      "-noautorotate",
+      "-map",
+      "0:v",
+      "-",
+      "-an",
+      "b.mp4",
+      "-t",
+    ]);
+
+    assert_eq!(model.global.len(), 1);
+    assert_eq!(model.global[0].value.as_deref(), Some("level+info"));
+    assert_eq!(model.inputs.len(), 2);
+    assert_eq!(model.inputs[0].option("-stream_loop"), Some("-1"));
+    assert_eq!(model.inputs[1].option("-f"), Some("lavfi"));
+    assert_eq!(model.outputs.len(), 2);
+    assert_eq!(model.outputs[0].url, "-");
+    assert_eq!(model.outputs[0].options.len(), 2);
+    assert_eq!(model.outputs[1].options[0].flag, "-an");
+    assert_eq!(model.pending[0].flag, "-t");
+    assert_eq!(model.pending[0].position, 16);
+  }
+
+  #[test]
+  fn test_unknown_boolean_option() {
+    // Neither is listed, but neither takes the next flag as its value
+    let model = ArgModel::from_args([
+      "-i",
+      "in.mp4",
+      "-made_up_switch",
+      "-another_switch",
+      "-c:v",
+      "libx264",
+      "-stream_loop",
+      "-1",
+      "out.mp4",
+    ]);
+    let output = &model.outputs[0];
+    assert_eq!(output.url, "out.mp4");
+    assert_eq!(output.options[0].value, None);
+    assert_eq!(output.options[1].value, None);
+    assert_eq!(output.option("-c:v"), Some("libx264"));
+    assert_eq!(output.option("-stream_loop"), Some("-1"));
+    assert_eq!(
+      ArgModel::from_args(["-i", "in.mp4", "-copyinkf", "out.mp4"]).outputs[0].url,
+      "out.mp4"
+    );
+  }
+
+  #[test]
+  fn test_misplaced_input_option() {
+    let mut model = ArgModel::from_args(["-f", "lavfi", "-i", "testsrc"]);
+    model.push("-stream_loop");
+    model.mark_input_only();
+    model.push("2");
+    assert_eq!(model.misplaced_input_option().unwrap().flag, "-stream_loop");
+    let reparsed = model.reparse(["-f", "lavfi", "-i", "testsrc", "-stream_loop", "2", "-"]);
+    assert_eq!(reparsed.misplaced_input_option().unwrap().position, 4);
+
+    model.push("-i");
+    model.push("in.mp4");
+    assert_eq!(model.misplaced_input_option(), None);
+  }
+
+  #[test]
+  fn test_probe_options() {
+    let model = ArgModel::from_args([
+      "-re", "-f", "rawvideo", "-pix_fmt", "gray", "-s", "64x48", "-r", "25", "-i", "-",
+    ]);
+    assert_eq!(
+      model.inputs[0].probe_options(),
+      [
+        "-f",
+        "rawvideo",
+        "-pixel_format",
+        "gray",
+        "-video_size",
+        "64x48",
+        "-framerate",
+        "25"
+      ]
+    );
+  }
+}
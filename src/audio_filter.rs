@@ -0,0 +1,96 @@
+//! Filter strings for common audio adjustments.
+
+/// The per-instance range of the `atempo` filter supported by all FFmpeg
+/// versions.
+const ATEMPO_RANGE: (f64, f64) = (0.5, 2.0);
+
+/// Split a tempo factor into a chain of factors within the range supported by
+/// a single `atempo` filter.
+///
+/// ```rust
+/// use ffmpeg_sidecar::audio_filter::atempo_factors;
+///
+/// assert_eq!(atempo_factors(1.5), vec![1.5]);
+/// assert_eq!(atempo_factors(5.0), vec![2.0, 2.0, 1.25]);
+/// assert_eq!(atempo_factors(0.2), vec![0.5, 0.5, 0.8]);
+/// ```
+///
+/// # Panics
+///
+/// If `factor` is not a positive, finite number.
+pub fn atempo_factors(mut factor: f64) -> Vec<f64> {
+  assert!(
+    factor.is_finite() && factor > 0.0,
+    "tempo factor must be positive, got {factor}"
+  );
+  let (min, max) = ATEMPO_RANGE;
+  let mut factors = Vec::new();
+  while factor > max {
+    factors.push(max);
+    factor /= max;
+  }
+  while factor < min {
+    factors.push(min);
+    factor /= min;
+  }
+  factors.push(factor);
+  factors
+}
+
+/// Change the speed of audio without changing its pitch, chaining as many
+/// `atempo` filters as needed.
+///
+/// ```rust
+/// use ffmpeg_sidecar::audio_filter::tempo_filter;
+///
+/// assert_eq!(tempo_filter(3.0), "atempo=2.000000,atempo=1.500000");
+/// ```
+///
+/// # Panics
+///
+/// If `factor` is not a positive, finite number.
+pub fn tempo_filter(factor: f64) -> String {
+  atempo_factors(factor)
+    .iter()
+    .map(|factor| format!("atempo={factor:.6}"))
+    .collect::<Vec<_>>()
+    .join(",")
+}
+
+/// Shift the pitch of audio by `semitones` (positive or negative) without
+/// changing its speed. `sample_rate` must be the sample rate of the input
+/// audio (see `AudioStream::sample_rate`), which is restored afterwards.
+///
+/// The audio is resampled to change its pitch and speed together, and the
+/// speed change is then reversed with `atempo`.
+///
+/// ```rust
+/// use ffmpeg_sidecar::audio_filter::pitch_filter;
+///
+/// assert_eq!(
+///   pitch_filter(12.0, 48000),
+///   "asetrate=96000,aresample=48000,atempo=0.500000"
+/// );
+/// ```
+///
+/// # Panics
+///
+/// If `semitones` is not finite, `sample_rate` is 0, or the shift is so large
+/// that the shifted sample rate rounds to 0.
+pub fn pitch_filter(semitones: f64, sample_rate: u32) -> String {
+  assert!(
+    semitones.is_finite(),
+    "semitones must be finite, got {semitones}"
+  );
+  assert!(sample_rate > 0, "sample rate must be positive");
+  let ratio = 2f64.powf(semitones / 12.0);
+  let shifted_rate = (sample_rate as f64 * ratio).round() as u32;
+  assert!(
+    shifted_rate > 0,
+    "shifting {sample_rate} Hz by {semitones} semitones leaves no samples"
+  );
+  format!(
+    "asetrate={shifted_rate},aresample={sample_rate},{}",
+    tempo_filter(sample_rate as f64 / shifted_rate as f64)
+  )
+}
@@ -0,0 +1,350 @@
+//! Decoding and RMS and peak level metering of raw PCM output, computed in
+//! Rust.
+
+use std::time::Duration;
+
+use crate::{
+  event::{AudioSamples, OutputAudioSamples, Stream},
+  pan::layout_channels,
+};
+
+/// The level of one channel over one metering window, in dBFS (decibels
+/// relative to full scale). Silence is `f32::NEG_INFINITY`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioLevel {
+  /// The index of the channel within the output stream's layout.
+  pub channel: usize,
+  /// The root mean square level; a rough measure of perceived loudness.
+  pub rms_db: f32,
+  /// The level of the loudest sample.
+  pub peak_db: f32,
+  /// The start of the window, relative to the start of the output.
+  pub time: Duration,
+}
+
+/// Raw PCM sample formats which can be decoded and metered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+  U8,
+  S16Le,
+  S32Le,
+  F32Le,
+  F64Le,
+}
+
+impl SampleFormat {
+  /// The format of a raw PCM codec name such as `pcm_s16le`, as it appears in
+  /// the stream info.
+  pub fn from_codec(codec: &str) -> Option<Self> {
+    match codec {
+      "pcm_u8" => Some(SampleFormat::U8),
+      "pcm_s16le" => Some(SampleFormat::S16Le),
+      "pcm_s32le" => Some(SampleFormat::S32Le),
+      "pcm_f32le" => Some(SampleFormat::F32Le),
+      "pcm_f64le" => Some(SampleFormat::F64Le),
+      _ => None,
+    }
+  }
+
+  pub fn bytes_per_sample(&self) -> usize {
+    match self {
+      SampleFormat::U8 => 1,
+      SampleFormat::S16Le => 2,
+      SampleFormat::S32Le | SampleFormat::F32Le => 4,
+      SampleFormat::F64Le => 8,
+    }
+  }
+
+  /// Decode one sample, normalized to `-1.0..=1.0`.
+  fn decode(&self, bytes: &[u8]) -> f64 {
+    match self {
+      SampleFormat::U8 => (bytes[0] as f64 - 128.0) / 128.0,
+      SampleFormat::S16Le => i16::from_le_bytes([bytes[0], bytes[1]]) as f64 / 32768.0,
+      SampleFormat::S32Le => i32::from_le_bytes(bytes.try_into().unwrap()) as f64 / 2147483648.0,
+      SampleFormat::F32Le => f32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+      SampleFormat::F64Le => f64::from_le_bytes(bytes.try_into().unwrap()),
+    }
+  }
+
+  /// Decode whole samples into their native type.
+  fn decode_all(&self, bytes: &[u8]) -> AudioSamples {
+    let samples = bytes.chunks_exact(self.bytes_per_sample());
+    match self {
+      SampleFormat::U8 => AudioSamples::U8(bytes.to_vec()),
+      SampleFormat::S16Le => AudioSamples::S16(
+        samples
+          .map(|b| i16::from_le_bytes(b.try_into().unwrap()))
+          .collect(),
+      ),
+      SampleFormat::S32Le => AudioSamples::S32(
+        samples
+          .map(|b| i32::from_le_bytes(b.try_into().unwrap()))
+          .collect(),
+      ),
+      SampleFormat::F32Le => AudioSamples::F32(
+        samples
+          .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+          .collect(),
+      ),
+      SampleFormat::F64Le => AudioSamples::F64(
+        samples
+          .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+          .collect(),
+      ),
+    }
+  }
+}
+
+/// The sample format, rate and channel count of a raw PCM output stream, or
+/// `None` if it isn't audio in a supported format and channel layout.
+fn pcm_params(stream: &Stream) -> Option<(SampleFormat, u32, usize)> {
+  let audio = stream.audio_data()?;
+  let format = SampleFormat::from_codec(&stream.format)?;
+  let channels = layout_channels(&audio.channels)?.len();
+  (channels > 0 && audio.sample_rate > 0).then_some((format, audio.sample_rate, channels))
+}
+
+fn to_db(amplitude: f64) -> f32 {
+  (20.0 * amplitude.log10()) as f32
+}
+
+/// Accumulates interleaved PCM bytes and emits an [`AudioLevel`] per channel
+/// for every complete window.
+///
+/// ```rust
+/// use ffmpeg_sidecar::audio_levels::{AudioLevelMeter, SampleFormat};
+/// use std::time::Duration;
+///
+/// let mut meter = AudioLevelMeter::new(SampleFormat::S16Le, 1000, 1, Duration::from_millis(2));
+/// let half_scale = 16384i16.to_le_bytes();
+/// let levels = meter.push(&half_scale.repeat(2));
+/// assert_eq!(levels.len(), 1);
+/// assert!((levels[0].peak_db - -6.02).abs() < 0.01);
+/// ```
+#[derive(Debug, Clone)]
+pub struct AudioLevelMeter {
+  format: SampleFormat,
+  channels: usize,
+  sample_rate: u32,
+  window_frames: u64,
+  sum_squares: Vec<f64>,
+  peaks: Vec<f64>,
+  frames_in_window: u64,
+  windows_emitted: u64,
+  remainder: Vec<u8>,
+}
+
+impl AudioLevelMeter {
+  /// # Panics
+  ///
+  /// If `channels` or `sample_rate` is zero.
+  pub fn new(format: SampleFormat, sample_rate: u32, channels: usize, window: Duration) -> Self {
+    assert!(channels > 0 && sample_rate > 0);
+    let window_frames = (window.as_secs_f64() * sample_rate as f64).round().max(1.0) as u64;
+    Self {
+      format,
+      channels,
+      sample_rate,
+      window_frames,
+      sum_squares: vec![0.0; channels],
+      peaks: vec![0.0; channels],
+      frames_in_window: 0,
+      windows_emitted: 0,
+      remainder: Vec::new(),
+    }
+  }
+
+  /// A meter for a raw PCM output stream, or `None` if the stream isn't audio
+  /// in a supported sample format and channel layout.
+  pub fn for_stream(stream: &Stream, window: Duration) -> Option<Self> {
+    let (format, sample_rate, channels) = pcm_params(stream)?;
+    Some(Self::new(format, sample_rate, channels, window))
+  }
+
+  /// Feed the next chunk of interleaved samples, returning the levels of
+  /// every window completed by it. Partial samples are kept for the next
+  /// chunk.
+  pub fn push(&mut self, chunk: &[u8]) -> Vec<AudioLevel> {
+    let mut levels = Vec::new();
+    let mut buffer = std::mem::take(&mut self.remainder);
+    buffer.extend_from_slice(chunk);
+    let frame_size = self.format.bytes_per_sample() * self.channels;
+    let complete = buffer.len() / frame_size * frame_size;
+
+    let format = self.format;
+    for frame in buffer[..complete].chunks_exact(frame_size) {
+      let samples = frame.chunks_exact(format.bytes_per_sample());
+      self.push_frame(samples.map(|bytes| format.decode(bytes)), &mut levels);
+    }
+
+    buffer.drain(..complete);
+    self.remainder = buffer;
+    levels
+  }
+
+  /// Like [`push`](Self::push), for samples which have already been decoded,
+  /// e.g. by an [`AudioSampleDecoder`]. They must have the channel count the
+  /// meter was created with.
+  pub fn push_samples(&mut self, samples: &AudioSamples) -> Vec<AudioLevel> {
+    let mut levels = Vec::new();
+    for frame in samples.to_f32().chunks_exact(self.channels) {
+      self.push_frame(frame.iter().map(|&sample| sample as f64), &mut levels);
+    }
+    levels
+  }
+
+  fn push_frame(&mut self, samples: impl Iterator<Item = f64>, levels: &mut Vec<AudioLevel>) {
+    for (channel, sample) in samples.enumerate() {
+      self.sum_squares[channel] += sample * sample;
+      self.peaks[channel] = self.peaks[channel].max(sample.abs());
+    }
+    self.frames_in_window += 1;
+    if self.frames_in_window == self.window_frames {
+      levels.extend(self.finish_window());
+    }
+  }
+
+  fn finish_window(&mut self) -> Vec<AudioLevel> {
+    let start_frame = self.windows_emitted * self.window_frames;
+    let time = Duration::from_secs_f64(start_frame as f64 / self.sample_rate as f64);
+    let levels = (0..self.channels)
+      .map(|channel| AudioLevel {
+        channel,
+        rms_db: to_db((self.sum_squares[channel] / self.frames_in_window as f64).sqrt()),
+        peak_db: to_db(self.peaks[channel]),
+        time,
+      })
+      .collect();
+    self.sum_squares.fill(0.0);
+    self.peaks.fill(0.0);
+    self.frames_in_window = 0;
+    self.windows_emitted += 1;
+    levels
+  }
+}
+
+/// Turns interleaved PCM bytes, as read in arbitrary chunks, into
+/// [`OutputAudioSamples`] of whole sample frames.
+///
+/// ```rust
+/// use ffmpeg_sidecar::{audio_levels::{AudioSampleDecoder, SampleFormat}, event::AudioSamples};
+///
+/// let mut decoder = AudioSampleDecoder::new(SampleFormat::S16Le, 8000, 1, 0);
+/// assert!(decoder.push(&[0x00]).is_none());
+/// let samples = decoder.push(&[0x40, 0xff, 0xff]).unwrap();
+/// assert_eq!(samples.samples, AudioSamples::S16(vec![0x4000, -1]));
+/// ```
+#[derive(Debug, Clone)]
+pub struct AudioSampleDecoder {
+  format: SampleFormat,
+  sample_rate: u32,
+  channels: usize,
+  output_index: u32,
+  frames_decoded: u64,
+  remainder: Vec<u8>,
+}
+
+impl AudioSampleDecoder {
+  /// # Panics
+  ///
+  /// If `channels` or `sample_rate` is zero.
+  pub fn new(format: SampleFormat, sample_rate: u32, channels: usize, output_index: u32) -> Self {
+    assert!(channels > 0 && sample_rate > 0);
+    Self {
+      format,
+      sample_rate,
+      channels,
+      output_index,
+      frames_decoded: 0,
+      remainder: Vec::new(),
+    }
+  }
+
+  /// A decoder for a raw PCM output stream, or `None` if the stream isn't
+  /// audio in a supported sample format and channel layout.
+  pub fn for_stream(stream: &Stream) -> Option<Self> {
+    let (format, sample_rate, channels) = pcm_params(stream)?;
+    Some(Self::new(
+      format,
+      sample_rate,
+      channels,
+      stream.parent_index,
+    ))
+  }
+
+  /// Decode the next chunk, returning `None` if it didn't complete a sample
+  /// frame. Partial frames are kept for the next chunk.
+  pub fn push(&mut self, chunk: &[u8]) -> Option<OutputAudioSamples> {
+    let mut buffer = std::mem::take(&mut self.remainder);
+    buffer.extend_from_slice(chunk);
+    let frame_size = self.format.bytes_per_sample() * self.channels;
+    let frames = buffer.len() / frame_size;
+    self.remainder = buffer.split_off(frames * frame_size);
+    if frames == 0 {
+      return None;
+    }
+
+    let timestamp = self.frames_decoded as f64 / self.sample_rate as f64;
+    self.frames_decoded += frames as u64;
+    Some(OutputAudioSamples {
+      sample_rate: self.sample_rate,
+      channels: self.channels,
+      sample_format: self.format,
+      output_index: self.output_index,
+      samples: self.format.decode_all(&buffer),
+      timestamp: timestamp as f32,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_meter_windows_and_channels() {
+    let window = Duration::from_millis(10);
+    let mut meter = AudioLevelMeter::new(SampleFormat::F32Le, 1000, 2, window);
+
+    // 25 stereo frames: full-scale square wave on the left, silence on the right
+    let bytes: Vec<u8> = (0..25)
+      .flat_map(|i| {
+        let left = if i % 2 == 0 { 1.0f32 } else { -1.0 };
+        [left.to_le_bytes(), 0f32.to_le_bytes()].concat()
+      })
+      .collect();
+
+    // Split mid-sample to exercise the remainder handling
+    let mut levels = meter.push(&bytes[..101]);
+    levels.extend(meter.push(&bytes[101..]));
+
+    assert_eq!(levels.len(), 4);
+    assert_eq!(levels[0].channel, 0);
+    assert_eq!(levels[0].rms_db, 0.0);
+    assert_eq!(levels[0].peak_db, 0.0);
+    assert_eq!(levels[1].channel, 1);
+    assert_eq!(levels[1].peak_db, f32::NEG_INFINITY);
+    assert_eq!(levels[2].time, Duration::from_millis(10));
+  }
+
+  #[test]
+  fn test_decoder_remainder_and_timestamps() {
+    let mut decoder = AudioSampleDecoder::new(SampleFormat::F32Le, 1000, 2, 1);
+    let bytes: Vec<u8> = (0..10).flat_map(|i| (i as f32).to_le_bytes()).collect();
+
+    // One and a half stereo frames
+    let first = decoder.push(&bytes[..12]).unwrap();
+    assert_eq!(first.samples, AudioSamples::F32(vec![0.0, 1.0]));
+    assert_eq!(first.output_index, 1);
+    assert_eq!(first.timestamp, 0.0);
+    assert_eq!(first.frames(), 1);
+
+    let rest = decoder.push(&bytes[12..]).unwrap();
+    assert_eq!(
+      rest.samples,
+      AudioSamples::F32((2..10).map(|i| i as f32).collect())
+    );
+    assert_eq!(rest.timestamp, 0.001);
+    assert_eq!(rest.duration(), Duration::from_millis(4));
+  }
+}
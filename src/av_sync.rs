@@ -0,0 +1,296 @@
+//! Merge raw video and PCM audio, written by one FFmpeg process to separate
+//! outputs, into frames paired with the audio samples which play during them.
+
+use std::{
+  io::{self, Read},
+  sync::mpsc::{channel, sync_channel, Receiver},
+  thread,
+};
+
+use anyhow::Context;
+
+use crate::{
+  command::FfmpegCommand,
+  event::{OutputVideoFrame, VideoStream},
+  pix_fmt::get_bytes_per_frame,
+};
+
+/// How many decoded video frames are buffered ahead of the consumer. Audio is
+/// buffered without a limit, since FFmpeg would otherwise stall on a full
+/// audio pipe while the consumer waits for video.
+const VIDEO_BUFFER_FRAMES: usize = 8;
+
+/// How many bytes of audio are read at a time.
+const AUDIO_CHUNK_SIZE: usize = 16 * 1024;
+
+/// The format of an interleaved PCM audio output, e.g. `-f s16le -ar 48000
+/// -ac 2`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PcmFormat {
+  /// A raw PCM muxer, e.g. `s16le` or `f32le`.
+  pub format: String,
+  pub sample_rate: u32,
+  pub channels: u32,
+}
+
+impl PcmFormat {
+  pub fn new<S: AsRef<str>>(format: S, sample_rate: u32, channels: u32) -> Self {
+    Self {
+      format: format.as_ref().to_string(),
+      sample_rate,
+      channels,
+    }
+  }
+
+  /// The size in bytes of one sample of one channel, or `None` if `format`
+  /// isn't a raw PCM muxer.
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::av_sync::PcmFormat;
+  ///
+  /// assert_eq!(PcmFormat::new("s16le", 48000, 2).bytes_per_sample(), Some(2));
+  /// assert_eq!(PcmFormat::new("f64be", 48000, 2).bytes_per_sample(), Some(8));
+  /// assert_eq!(PcmFormat::new("mp3", 48000, 2).bytes_per_sample(), None);
+  /// ```
+  pub fn bytes_per_sample(&self) -> Option<usize> {
+    match self.format.as_str() {
+      "u8" | "s8" | "alaw" | "mulaw" => Some(1),
+      "u16le" | "u16be" | "s16le" | "s16be" => Some(2),
+      "u24le" | "u24be" | "s24le" | "s24be" => Some(3),
+      "u32le" | "u32be" | "s32le" | "s32be" | "f32le" | "f32be" => Some(4),
+      "f64le" | "f64be" => Some(8),
+      _ => None,
+    }
+  }
+
+  /// The size in bytes of one sample of every channel.
+  pub fn bytes_per_frame(&self) -> Option<usize> {
+    Some(self.bytes_per_sample()? * self.channels as usize)
+  }
+
+  /// Add `-f <format> -ar <sample_rate> -ac <channels>` to the command, to be
+  /// followed by the audio output.
+  pub fn configure<'a>(&self, command: &'a mut FfmpegCommand) -> &'a mut FfmpegCommand {
+    command
+      .format(&self.format)
+      .args(["-ar", &self.sample_rate.to_string()])
+      .args(["-ac", &self.channels.to_string()])
+  }
+}
+
+/// A video frame, and the audio samples which play from its timestamp until
+/// the next frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AvPair {
+  pub video_frame: OutputVideoFrame,
+  /// Interleaved PCM in the [`PcmFormat`] of the audio output. Empty once the
+  /// audio has ended.
+  pub audio_samples: Vec<u8>,
+}
+
+/// An iterator over [`AvPair`]s, read from a raw video output and a PCM
+/// audio output of the same FFmpeg process.
+///
+/// Both outputs are read on background threads, buffering whichever runs
+/// ahead, so that FFmpeg never blocks writing one while the other is waited
+/// on. Both timelines are assumed to start at zero; frame `n` is paired with
+/// the samples from `n / fps` to `(n + 1) / fps`. The iterator ends with the
+/// video, dropping any audio left over.
+///
+/// ```rust,no_run
+/// use ffmpeg_sidecar::{
+///   av_sync::{PcmFormat, SyncedAvIterator},
+///   command::FfmpegCommand,
+///   event::VideoStream,
+///   tcp_output::tcp_outputs,
+/// };
+/// use std::time::Duration;
+///
+/// let video_format = VideoStream {
+///   pix_fmt: "rgb24".to_string(),
+///   width: 320,
+///   height: 240,
+///   fps: 25.0,
+/// };
+/// let audio_format = PcmFormat::new("s16le", 48000, 2);
+///
+/// let [video, audio] = <[_; 2]>::try_from(tcp_outputs(2)?).unwrap();
+/// let mut command = FfmpegCommand::new();
+/// command
+///   .input("input.mp4")
+///   .map("0:v")
+///   .format("rawvideo")
+///   .pix_fmt("rgb24")
+///   .size(320, 240)
+///   .rate(25.0)
+///   .output(video.url());
+/// audio_format.configure(command.map("0:a")).output(audio.url());
+/// let mut child = command.spawn()?;
+///
+/// let timeout = Duration::from_secs(5);
+/// let pairs = SyncedAvIterator::new(
+///   video.accept(timeout)?,
+///   &video_format,
+///   audio.accept(timeout)?,
+///   &audio_format,
+/// )?;
+/// for pair in pairs {
+///   let pair = pair?;
+///   println!("frame {}: {} bytes of audio", pair.video_frame.frame_num, pair.audio_samples.len());
+/// }
+/// child.wait()?;
+/// # anyhow::Ok(())
+/// ```
+pub struct SyncedAvIterator {
+  video: Receiver<io::Result<OutputVideoFrame>>,
+  audio: Receiver<io::Result<Vec<u8>>>,
+  audio_buffer: Vec<u8>,
+  /// The index of the first sample in `audio_buffer`.
+  audio_position: u64,
+  audio_ended: bool,
+  fps: f64,
+  sample_rate: f64,
+  bytes_per_frame: usize,
+}
+
+impl SyncedAvIterator {
+  /// Start reading `video` frames of `video_format`, and `audio` in
+  /// `audio_format`. Fails if either format has no fixed frame size.
+  pub fn new<V, A>(
+    video: V,
+    video_format: &VideoStream,
+    audio: A,
+    audio_format: &PcmFormat,
+  ) -> anyhow::Result<Self>
+  where
+    V: Read + Send + 'static,
+    A: Read + Send + 'static,
+  {
+    let frame_size = get_bytes_per_frame(video_format)
+      .with_context(|| format!("Unsupported pixel format: {}", video_format.pix_fmt))?
+      as usize;
+    let bytes_per_frame = audio_format
+      .bytes_per_frame()
+      .filter(|size| *size > 0)
+      .with_context(|| format!("Unsupported PCM format: {}", audio_format.format))?;
+    anyhow::ensure!(
+      video_format.fps > 0.0,
+      "The video frame rate must be positive"
+    );
+    anyhow::ensure!(
+      audio_format.sample_rate > 0,
+      "The sample rate must be positive"
+    );
+
+    let (video_tx, video_rx) = sync_channel(VIDEO_BUFFER_FRAMES);
+    let format = video_format.clone();
+    thread::spawn(move || {
+      let mut video = video;
+      for frame_num in 0.. {
+        let mut data = vec![0; frame_size];
+        match video.read_exact(&mut data) {
+          Ok(()) => {}
+          Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+          Err(e) => {
+            video_tx.send(Err(e)).ok();
+            break;
+          }
+        }
+        let frame = OutputVideoFrame {
+          width: format.width,
+          height: format.height,
+          pix_fmt: format.pix_fmt.clone(),
+          output_index: 0,
+          data,
+          frame_num,
+          timestamp: frame_num as f32 / format.fps,
+          output_tag: None,
+        };
+        if video_tx.send(Ok(frame)).is_err() {
+          break;
+        }
+      }
+    });
+
+    let (audio_tx, audio_rx) = channel();
+    thread::spawn(move || {
+      let mut audio = audio;
+      loop {
+        let mut chunk = vec![0; AUDIO_CHUNK_SIZE];
+        match audio.read(&mut chunk) {
+          Ok(0) => break,
+          Ok(len) => {
+            chunk.truncate(len);
+            if audio_tx.send(Ok(chunk)).is_err() {
+              break;
+            }
+          }
+          Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+          Err(e) => {
+            audio_tx.send(Err(e)).ok();
+            break;
+          }
+        }
+      }
+    });
+
+    Ok(Self {
+      video: video_rx,
+      audio: audio_rx,
+      audio_buffer: Vec::new(),
+      audio_position: 0,
+      audio_ended: false,
+      fps: video_format.fps as f64,
+      sample_rate: audio_format.sample_rate as f64,
+      bytes_per_frame,
+    })
+  }
+
+  /// The index of the first sample played during video frame `frame_num`.
+  fn first_sample(&self, frame_num: u32) -> u64 {
+    (frame_num as f64 * self.sample_rate / self.fps).round() as u64
+  }
+
+  /// Take the samples from `start` to `end` out of the buffer, reading more
+  /// audio until it's long enough or the audio ends.
+  fn take_samples(&mut self, start: u64, end: u64) -> io::Result<Vec<u8>> {
+    let buffered_end =
+      |this: &Self| this.audio_position + (this.audio_buffer.len() / this.bytes_per_frame) as u64;
+    while !self.audio_ended && buffered_end(self) < end {
+      match self.audio.recv() {
+        Ok(chunk) => self.audio_buffer.extend_from_slice(&chunk?),
+        Err(_) => self.audio_ended = true,
+      }
+    }
+
+    let skip = start
+      .saturating_sub(self.audio_position)
+      .min(buffered_end(self) - self.audio_position);
+    let take = end
+      .min(buffered_end(self))
+      .saturating_sub(self.audio_position + skip);
+    let skip_bytes = skip as usize * self.bytes_per_frame;
+    let take_bytes = take as usize * self.bytes_per_frame;
+    let samples = self.audio_buffer[skip_bytes..skip_bytes + take_bytes].to_vec();
+    self.audio_buffer.drain(..skip_bytes + take_bytes);
+    self.audio_position += skip + take;
+    Ok(samples)
+  }
+}
+
+impl Iterator for SyncedAvIterator {
+  type Item = io::Result<AvPair>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let video_frame = match self.video.recv().ok()? {
+      Ok(frame) => frame,
+      Err(e) => return Some(Err(e)),
+    };
+    let start = self.first_sample(video_frame.frame_num);
+    let end = self.first_sample(video_frame.frame_num + 1);
+    Some(self.take_samples(start, end).map(|audio_samples| AvPair {
+      video_frame,
+      audio_samples,
+    }))
+  }
+}
@@ -0,0 +1,111 @@
+//! The process behind an `FfmpegChild`, abstracted so that other backends,
+//! such as in-memory fakes for tests, can stand in for `std::process::Child`.
+
+use std::{
+  io::{self, Read, Write},
+  process::{Child, ChildStderr, ChildStdin, ChildStdout, ExitStatus},
+};
+
+/// A running FFmpeg process, as driven by [`FfmpegChild`](crate::child::FfmpegChild)
+/// and [`FfmpegIterator`](crate::iter::FfmpegIterator).
+///
+/// The stdio accessors mirror the public fields of [`std::process::Child`]:
+/// `None` once taken, or if the stream wasn't piped. Stderr must be available
+/// for the iterator to parse events.
+///
+/// An in-memory backend replays a canned log and output:
+///
+/// ```rust
+/// use ffmpeg_sidecar::{backend::ProcessBackend, child::FfmpegChild};
+/// use std::{io::{self, Cursor}, process::ExitStatus};
+///
+/// struct FakeProcess {
+///   stdin: Option<io::Sink>,
+///   stdout: Option<Cursor<Vec<u8>>>,
+///   stderr: Option<Cursor<Vec<u8>>>,
+/// }
+///
+/// impl ProcessBackend for FakeProcess {
+///   type Stdin = io::Sink;
+///   type Stdout = Cursor<Vec<u8>>;
+///   type Stderr = Cursor<Vec<u8>>;
+///
+///   fn stdin(&mut self) -> &mut Option<Self::Stdin> { &mut self.stdin }
+///   fn stdout(&mut self) -> &mut Option<Self::Stdout> { &mut self.stdout }
+///   fn stderr(&mut self) -> &mut Option<Self::Stderr> { &mut self.stderr }
+///   fn id(&self) -> Option<u32> { None }
+///   fn kill(&mut self) -> io::Result<()> { Ok(()) }
+///   fn wait(&mut self) -> io::Result<ExitStatus> { Ok(ExitStatus::default()) }
+///   fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> { Ok(Some(ExitStatus::default())) }
+/// }
+///
+/// let mut child = FfmpegChild::from_backend(FakeProcess {
+///   stdin: Some(io::sink()),
+///   stdout: Some(Cursor::new(Vec::new())),
+///   stderr: Some(Cursor::new(b"[error] Something went wrong\n".to_vec())),
+/// });
+/// let errors: Vec<String> = child.iter()?.filter_errors().collect();
+/// assert!(errors.iter().any(|e| e.contains("Something went wrong")));
+/// # anyhow::Ok(())
+/// ```
+pub trait ProcessBackend: Send + 'static {
+  type Stdin: Write + Send + 'static;
+  type Stdout: Read + Send + 'static;
+  type Stderr: Read + Send + 'static;
+
+  /// The process' stdin, if piped and not yet taken.
+  fn stdin(&mut self) -> &mut Option<Self::Stdin>;
+
+  /// The process' stdout, if piped and not yet taken.
+  fn stdout(&mut self) -> &mut Option<Self::Stdout>;
+
+  /// The process' stderr, if piped and not yet taken.
+  fn stderr(&mut self) -> &mut Option<Self::Stderr>;
+
+  /// The OS process ID, if there is a real process. Resource sampling and
+  /// the stall watchdog's `kill` are unavailable without one.
+  fn id(&self) -> Option<u32>;
+
+  /// Forcibly terminate the process.
+  fn kill(&mut self) -> io::Result<()>;
+
+  /// Wait for the process to exit.
+  fn wait(&mut self) -> io::Result<ExitStatus>;
+
+  /// The exit status, if the process has already exited.
+  fn try_wait(&mut self) -> io::Result<Option<ExitStatus>>;
+}
+
+impl ProcessBackend for Child {
+  type Stdin = ChildStdin;
+  type Stdout = ChildStdout;
+  type Stderr = ChildStderr;
+
+  fn stdin(&mut self) -> &mut Option<ChildStdin> {
+    &mut self.stdin
+  }
+
+  fn stdout(&mut self) -> &mut Option<ChildStdout> {
+    &mut self.stdout
+  }
+
+  fn stderr(&mut self) -> &mut Option<ChildStderr> {
+    &mut self.stderr
+  }
+
+  fn id(&self) -> Option<u32> {
+    Some(Child::id(self))
+  }
+
+  fn kill(&mut self) -> io::Result<()> {
+    Child::kill(self)
+  }
+
+  fn wait(&mut self) -> io::Result<ExitStatus> {
+    Child::wait(self)
+  }
+
+  fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+    Child::try_wait(self)
+  }
+}
@@ -0,0 +1,54 @@
+//! Internal hot paths exposed for the `benches/` suite. Not covered by semver;
+//! enable with the `bench-internals` feature.
+
+use std::io::Read;
+
+use crate::{
+  event::{FfmpegEvent, OutputVideoFrame, Stream},
+  iter::{read_frames, StdoutConfig, ThroughputMeter},
+  pix_fmt::get_bytes_per_frame,
+};
+
+/// Run the stdout thread's frame loop over `reader`, as if it were the
+/// rawvideo output of `stream`, passing each frame to `on_frame` until EOF.
+pub fn read_rawvideo_frames<R, F>(
+  mut reader: R,
+  stream: &Stream,
+  mut on_frame: F,
+) -> anyhow::Result<()>
+where
+  R: Read,
+  F: FnMut(OutputVideoFrame),
+{
+  let video_data = stream
+    .video_data()
+    .ok_or_else(|| anyhow::anyhow!("not a video stream"))?;
+  let bytes_per_frame = get_bytes_per_frame(video_data)
+    .ok_or_else(|| anyhow::anyhow!("unsupported pix_fmt `{}`", video_data.pix_fmt))?;
+
+  let config = StdoutConfig::default();
+  let mut meter = ThroughputMeter::new(&config);
+  let mut frame_buffers = [vec![0u8; bytes_per_frame as usize]];
+  let mut error = None;
+  read_frames(
+    &mut reader,
+    std::slice::from_ref(stream),
+    &mut frame_buffers,
+    &config,
+    &mut meter,
+    |event| {
+      match event {
+        FfmpegEvent::OutputFrame(frame) => on_frame(frame),
+        FfmpegEvent::Error(e) => {
+          error.get_or_insert(e);
+        }
+        _ => {}
+      }
+      true
+    },
+  );
+  match error {
+    Some(e) => anyhow::bail!(e),
+    None => Ok(()),
+  }
+}
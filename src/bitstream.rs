@@ -0,0 +1,452 @@
+//! Lightweight inspection of encoded output chunks (H.264/HEVC Annex B and
+//! MPEG-TS), used to locate keyframes without a second probing pass.
+
+/// The encoded format of the data arriving in `FfmpegEvent::OutputChunk`s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkFormat {
+  /// Raw H.264 elementary stream in Annex B format (`-f h264`).
+  H264,
+  /// Raw HEVC elementary stream in Annex B format (`-f hevc`).
+  Hevc,
+  /// MPEG transport stream (`-f mpegts`).
+  MpegTs,
+}
+
+/// An output chunk annotated with the keyframe and timing information found
+/// inside of it.
+#[derive(Clone, PartialEq)]
+pub struct TaggedChunk {
+  /// The raw chunk data, exactly as it was received from stdout.
+  pub data: Vec<u8>,
+  /// Whether an IDR/IRAP picture (or a random access point, for MPEG-TS)
+  /// starts inside this chunk.
+  pub keyframe: bool,
+  /// Approximate presentation timestamp in seconds of the keyframe in this
+  /// chunk, or of the first picture that starts in it.
+  ///
+  /// For MPEG-TS this is read from the PES header. For Annex B streams it is
+  /// estimated by counting pictures, and requires a known frame rate.
+  pub pts: Option<f64>,
+}
+
+impl std::fmt::Debug for TaggedChunk {
+  /// Omit the `data` field from the debug output
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("TaggedChunk")
+      .field("len", &self.data.len())
+      .field("keyframe", &self.keyframe)
+      .field("pts", &self.pts)
+      .finish()
+  }
+}
+
+const TS_PACKET_SIZE: usize = 188;
+const TS_SYNC_BYTE: u8 = 0x47;
+
+/// Number of trailing bytes of each chunk that must be kept around so that
+/// Annex B start codes split across chunk boundaries are still detected. A
+/// start code is 3 bytes, followed by at most 3 bytes needed for inspection.
+const ANNEX_B_CARRY: usize = 5;
+
+/// A stateful parser which tags consecutive output chunks with keyframe
+/// information. State is carried across chunks, so every chunk of the stream
+/// must be passed through the same tagger, in order.
+///
+/// ## Example
+///
+/// ```rust
+/// use ffmpeg_sidecar::bitstream::{ChunkFormat, ChunkTagger};
+///
+/// let mut tagger = ChunkTagger::new(ChunkFormat::H264).with_frame_rate(25.0);
+/// // SPS, then an IDR slice (first_mb_in_slice = 0)
+/// let chunk = vec![0, 0, 0, 1, 0x67, 0x42, 0, 0, 1, 0x65, 0x88, 0x84];
+/// let tagged = tagger.tag(chunk);
+/// assert!(tagged.keyframe);
+/// assert_eq!(tagged.pts, Some(0.0));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ChunkTagger {
+  format: ChunkFormat,
+  carry: Vec<u8>,
+  pictures_seen: u64,
+  frame_rate: Option<f32>,
+  video_pids: Vec<u16>,
+}
+
+impl ChunkTagger {
+  pub fn new(format: ChunkFormat) -> Self {
+    Self {
+      format,
+      carry: Vec::new(),
+      pictures_seen: 0,
+      frame_rate: None,
+      video_pids: Vec::new(),
+    }
+  }
+
+  /// Set the frame rate used to estimate timestamps of Annex B streams, which
+  /// carry no timing information of their own. Has no effect on MPEG-TS.
+  pub fn with_frame_rate(mut self, fps: f32) -> Self {
+    self.set_frame_rate(fps);
+    self
+  }
+
+  pub fn set_frame_rate(&mut self, fps: f32) {
+    if fps > 0.0 {
+      self.frame_rate = Some(fps);
+    }
+  }
+
+  pub fn format(&self) -> ChunkFormat {
+    self.format
+  }
+
+  /// Inspect the next chunk of the stream.
+  pub fn tag(&mut self, data: Vec<u8>) -> TaggedChunk {
+    let (keyframe, pts) = match self.format {
+      ChunkFormat::H264 | ChunkFormat::Hevc => self.scan_annex_b(&data),
+      ChunkFormat::MpegTs => self.scan_mpegts(&data),
+    };
+    TaggedChunk {
+      data,
+      keyframe,
+      pts,
+    }
+  }
+
+  fn scan_annex_b(&mut self, data: &[u8]) -> (bool, Option<f64>) {
+    let carried = self.carry.len();
+    let mut buf = std::mem::take(&mut self.carry);
+    buf.extend_from_slice(data);
+
+    // Bytes of NAL header, plus one byte of slice header
+    let needed = match self.format {
+      ChunkFormat::Hevc => 3,
+      _ => 2,
+    };
+
+    let mut keyframe_pts = None;
+    let mut first_pts = None;
+    let mut keyframe = false;
+    let mut i = 0;
+    while i + 3 + needed <= buf.len() {
+      if buf[i] != 0 || buf[i + 1] != 0 || buf[i + 2] != 1 {
+        i += 1;
+        continue;
+      }
+      let nal = &buf[i + 3..i + 3 + needed];
+      i += 3;
+
+      // Skip NAL units which were already fully inspected with the previous chunk
+      if i + needed <= carried {
+        continue;
+      }
+
+      let (is_vcl, is_keyframe, first_slice) = match self.format {
+        ChunkFormat::Hevc => {
+          let nal_type = (nal[0] >> 1) & 0x3f;
+          (
+            nal_type < 32,
+            (16..=21).contains(&nal_type),
+            nal[2] & 0x80 != 0,
+          )
+        }
+        _ => {
+          let nal_type = nal[0] & 0x1f;
+          (
+            (1..=5).contains(&nal_type),
+            nal_type == 5,
+            nal[1] & 0x80 != 0,
+          )
+        }
+      };
+
+      if is_vcl && first_slice {
+        let pts = self
+          .frame_rate
+          .map(|fps| self.pictures_seen as f64 / fps as f64);
+        self.pictures_seen += 1;
+        first_pts = first_pts.or(pts);
+        if is_keyframe && !keyframe {
+          keyframe = true;
+          keyframe_pts = pts;
+        }
+      }
+    }
+
+    let keep = buf.len().min(ANNEX_B_CARRY);
+    self.carry = buf.split_off(buf.len() - keep);
+    (keyframe, if keyframe { keyframe_pts } else { first_pts })
+  }
+
+  fn scan_mpegts(&mut self, data: &[u8]) -> (bool, Option<f64>) {
+    let mut buf = std::mem::take(&mut self.carry);
+    buf.extend_from_slice(data);
+
+    let mut keyframe_pts = None;
+    let mut first_pts = None;
+    let mut keyframe = false;
+    let mut i = 0;
+    while i + TS_PACKET_SIZE <= buf.len() {
+      if buf[i] != TS_SYNC_BYTE {
+        // Lost sync; advance until the next sync byte
+        i += 1;
+        continue;
+      }
+      let packet = &buf[i..i + TS_PACKET_SIZE];
+      i += TS_PACKET_SIZE;
+
+      if let Some((random_access, pts)) = self.parse_ts_packet(packet) {
+        first_pts = first_pts.or(pts);
+        if random_access && !keyframe {
+          keyframe = true;
+          keyframe_pts = pts;
+        }
+      }
+    }
+
+    self.carry = buf.split_off(i);
+    (keyframe, if keyframe { keyframe_pts } else { first_pts })
+  }
+
+  /// Returns the random access indicator and PTS (in seconds) of a video PES
+  /// packet start, or `None` for any other packet.
+  fn parse_ts_packet(&mut self, packet: &[u8]) -> Option<(bool, Option<f64>)> {
+    let payload_unit_start = packet[1] & 0x40 != 0;
+    let pid = (((packet[1] & 0x1f) as u16) << 8) | packet[2] as u16;
+    let adaptation_field_control = (packet[3] >> 4) & 0x3;
+
+    let mut random_access = false;
+    let mut payload_offset = 4;
+    if adaptation_field_control & 0x2 != 0 {
+      let adaptation_field_length = packet[4] as usize;
+      if adaptation_field_length > 0 {
+        random_access = packet[5] & 0x40 != 0;
+      }
+      payload_offset += 1 + adaptation_field_length;
+    }
+
+    if !payload_unit_start || adaptation_field_control & 0x1 == 0 {
+      return None;
+    }
+    let pes = packet.get(payload_offset..)?;
+    if pes.len() < 9 || pes[0..3] != [0, 0, 1] {
+      return None;
+    }
+
+    // Only video elementary streams are relevant for keyframes
+    let stream_id = pes[3];
+    if !(0xe0..=0xef).contains(&stream_id) {
+      return None;
+    }
+    if !self.video_pids.contains(&pid) {
+      self.video_pids.push(pid);
+    }
+
+    let has_pts = pes[7] & 0x80 != 0;
+    let pts = match pes.get(9..14) {
+      Some(p) if has_pts => {
+        let ticks = ((p[0] as u64 >> 1) & 0x7) << 30
+          | (p[1] as u64) << 22
+          | (p[2] as u64 >> 1) << 15
+          | (p[3] as u64) << 7
+          | (p[4] as u64 >> 1);
+        Some(ticks as f64 / 90_000.0)
+      }
+      _ => None,
+    };
+
+    Some((random_access, pts))
+  }
+
+  /// PIDs identified as carrying video so far (MPEG-TS only).
+  pub fn video_pids(&self) -> &[u16] {
+    &self.video_pids
+  }
+}
+
+/// Codec configuration (parameter sets) found in an Annex B bitstream, as
+/// emitted by the `extract_extradata` bitstream filter or in-band by most
+/// encoders before each keyframe.
+///
+/// Each parameter set is stored without its start code, and including the
+/// NAL unit header.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CodecConfig {
+  /// HEVC video parameter sets (always empty for H.264).
+  pub vps: Vec<Vec<u8>>,
+  /// Sequence parameter sets.
+  pub sps: Vec<Vec<u8>>,
+  /// Picture parameter sets.
+  pub pps: Vec<Vec<u8>>,
+}
+
+impl CodecConfig {
+  /// Collect the parameter sets contained in an Annex B buffer. Returns `None`
+  /// if no SPS or PPS is found.
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::bitstream::{ChunkFormat, CodecConfig};
+  ///
+  /// let data = [0, 0, 0, 1, 0x67, 0x42, 0xc0, 0x1e, 0, 0, 1, 0x68, 0xce, 0x3c, 0x80];
+  /// let config = CodecConfig::from_annex_b(&data, ChunkFormat::H264).unwrap();
+  /// assert_eq!(config.sps, vec![vec![0x67, 0x42, 0xc0, 0x1e]]);
+  /// assert_eq!(config.pps, vec![vec![0x68, 0xce, 0x3c, 0x80]]);
+  /// assert_eq!(config.codec_string().as_deref(), Some("avc1.42C01E"));
+  /// ```
+  pub fn from_annex_b(data: &[u8], format: ChunkFormat) -> Option<Self> {
+    let mut config = Self::default();
+    for nal in split_annex_b(data) {
+      match format {
+        ChunkFormat::Hevc => match (nal[0] >> 1) & 0x3f {
+          32 => config.vps.push(nal.to_vec()),
+          33 => config.sps.push(nal.to_vec()),
+          34 => config.pps.push(nal.to_vec()),
+          _ => {}
+        },
+        _ => match nal[0] & 0x1f {
+          7 => config.sps.push(nal.to_vec()),
+          8 => config.pps.push(nal.to_vec()),
+          _ => {}
+        },
+      }
+    }
+
+    match config.sps.is_empty() && config.pps.is_empty() {
+      true => None,
+      false => Some(config),
+    }
+  }
+
+  /// The RFC 6381 codec string of an H.264 stream (e.g. `avc1.42C01E`), as
+  /// expected by MSE `addSourceBuffer` or WebRTC SDP negotiation. Derived from
+  /// the profile, constraint flags and level of the first SPS.
+  ///
+  /// Returns `None` for HEVC, which requires a full SPS parse.
+  pub fn codec_string(&self) -> Option<String> {
+    if !self.vps.is_empty() {
+      return None;
+    }
+    let sps = self.sps.first()?;
+    let (profile, constraints, level) = (sps.get(1)?, sps.get(2)?, sps.get(3)?);
+    Some(format!("avc1.{profile:02X}{constraints:02X}{level:02X}"))
+  }
+}
+
+/// Split an Annex B buffer into NAL units, removing the start codes.
+/// Trailing zero bytes belonging to the next 4-byte start code are trimmed.
+///
+/// ```rust
+/// use ffmpeg_sidecar::bitstream::split_annex_b;
+///
+/// let data = [0, 0, 0, 1, 0x09, 0xf0, 0, 0, 1, 0x65, 0x88];
+/// let nals: Vec<&[u8]> = split_annex_b(&data).collect();
+/// assert_eq!(nals, vec![&[0x09, 0xf0][..], &[0x65, 0x88][..]]);
+/// ```
+pub fn split_annex_b(data: &[u8]) -> impl Iterator<Item = &[u8]> {
+  let mut starts = Vec::new();
+  let mut i = 0;
+  while i + 3 <= data.len() {
+    if data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 1 {
+      starts.push(i + 3);
+      i += 3;
+    } else {
+      i += 1;
+    }
+  }
+
+  let ends = starts
+    .iter()
+    .skip(1)
+    .map(|&next| next - 3)
+    .chain(std::iter::once(data.len()))
+    .collect::<Vec<_>>();
+
+  starts
+    .into_iter()
+    .zip(ends)
+    .map(move |(start, end)| {
+      let mut end = end;
+      while end > start && data[end - 1] == 0 {
+        end -= 1;
+      }
+      &data[start..end]
+    })
+    .filter(|nal| !nal.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn ts_packet(pid: u16, random_access: bool, pts_ticks: Option<u64>) -> Vec<u8> {
+    let mut packet = vec![
+      TS_SYNC_BYTE,
+      0x40 | ((pid >> 8) as u8 & 0x1f),
+      pid as u8,
+      0x30, // adaptation field + payload
+      1,    // adaptation field length
+      if random_access { 0x40 } else { 0x00 },
+    ];
+    packet.extend_from_slice(&[0, 0, 1, 0xe0, 0, 0, 0x80]);
+    match pts_ticks {
+      Some(t) => {
+        packet.extend_from_slice(&[
+          0x80,
+          5,
+          0x21 | (((t >> 30) & 0x7) as u8) << 1,
+          (t >> 22) as u8,
+          (((t >> 15) as u8) << 1) | 1,
+          (t >> 7) as u8,
+          ((t as u8) << 1) | 1,
+        ]);
+      }
+      None => packet.extend_from_slice(&[0, 0]),
+    }
+    packet.resize(TS_PACKET_SIZE, 0xff);
+    packet
+  }
+
+  #[test]
+  fn test_annex_b_split_start_code() {
+    let mut tagger = ChunkTagger::new(ChunkFormat::H264).with_frame_rate(10.0);
+    let first = tagger.tag(vec![0x41, 0x9a, 0, 0]);
+    let second = tagger.tag(vec![1, 0x65, 0x88, 0x00]);
+    assert!(!first.keyframe);
+    assert!(second.keyframe);
+    assert_eq!(second.pts, Some(0.0));
+
+    // A following non-IDR picture is counted, but isn't a keyframe
+    let third = tagger.tag(vec![0, 0, 1, 0x41, 0x9a, 0x00]);
+    assert!(!third.keyframe);
+    assert_eq!(third.pts, Some(0.1));
+  }
+
+  #[test]
+  fn test_hevc_irap() {
+    let mut tagger = ChunkTagger::new(ChunkFormat::Hevc);
+    // IDR_W_RADL (type 19) with first_slice_segment_in_pic_flag set
+    let tagged = tagger.tag(vec![0, 0, 0, 1, 19 << 1, 0x01, 0xaf, 0x00]);
+    assert!(tagged.keyframe);
+    assert_eq!(tagged.pts, None);
+  }
+
+  #[test]
+  fn test_mpegts_random_access() {
+    let mut tagger = ChunkTagger::new(ChunkFormat::MpegTs);
+    let mut stream = ts_packet(0x100, false, Some(90_000));
+    stream.extend(ts_packet(0x100, true, Some(180_000)));
+
+    // Split in the middle of the second packet
+    let (a, b) = stream.split_at(200);
+    let first = tagger.tag(a.to_vec());
+    let second = tagger.tag(b.to_vec());
+    assert!(!first.keyframe);
+    assert_eq!(first.pts, Some(1.0));
+    assert!(second.keyframe);
+    assert_eq!(second.pts, Some(2.0));
+    assert_eq!(tagger.video_pids(), &[0x100]);
+  }
+}
@@ -1,20 +1,55 @@
 //! Wrapper around `std::process::Child` containing a spawned FFmpeg command.
 
-use crate::iter::FfmpegIterator;
+use crate::{
+  backend::ProcessBackend,
+  command::{CommandConfig, StdinMode},
+  event::{FfmpegEvent, LogLevel},
+  frame_pump::FrameWriter,
+  iter::FfmpegIterator,
+  stderr_recorder::{CarriageReturnPolicy, RecordingReader, StderrRecorder},
+  tcp_output::TcpOutput,
+  watchdog::{ProcessKiller, StallWatchdogConfig},
+};
 use anyhow::Context;
 use std::{
-  io::{self, copy, sink, Write},
-  process::{Child, ChildStderr, ChildStdin, ChildStdout, ExitStatus},
+  fmt,
+  fs::File,
+  io::{self, copy, sink, Read, Write},
+  net::TcpStream,
+  process::{Child, ExitStatus},
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+  },
+  thread::JoinHandle,
+  time::{Duration, Instant},
 };
 
 /// A wrapper around [`std::process::Child`] containing a spawned FFmpeg command.
 /// Provides interfaces for reading parsed metadata, progress updates, warnings and errors, and
 /// piped output frames if applicable.
-pub struct FfmpegChild {
-  inner: Child,
+///
+/// Generic over the [`ProcessBackend`] which runs FFmpeg, which is a real
+/// process by default; see [`from_backend`](Self::from_backend).
+pub struct FfmpegChild<B: ProcessBackend = Child> {
+  inner: B,
+  config: CommandConfig,
+  spawned_at: Instant,
+  stderr_recorder: Option<StderrRecorder<Box<dyn Write + Send>>>,
+  /// The iterator created by `wait_until_writing`, returned by `iter`.
+  events: Option<Box<FfmpegIterator<B>>>,
+  /// The listener for `FfmpegCommand::progress_over_tcp`, until taken by
+  /// the iterator.
+  progress_output: Option<TcpOutput>,
+  /// Set by the iterator's stdout thread once nothing receives its output.
+  consumer_closed: Arc<AtomicBool>,
+  /// Shared with the iterator's threads, and disarmed before reaping.
+  killer: ProcessKiller,
+  #[cfg(feature = "named_pipes")]
+  pipes: crate::named_pipes::ManagedPipes,
 }
 
-impl FfmpegChild {
+impl<B: ProcessBackend> FfmpegChild<B> {
   /// Creates an iterator over events emitted by FFmpeg. Functions similarly to
   /// `Lines` from [`std::io::BufReader`], but providing a variety of parsed
   /// events:
@@ -23,29 +58,258 @@ impl FfmpegChild {
   /// - Progress updates
   /// - Errors and warnings
   /// - Raw output frames
-  pub fn iter(&mut self) -> anyhow::Result<FfmpegIterator> {
-    FfmpegIterator::new(self)
+  ///
+  /// The pipes needn't all be available. If stdout was taken or redirected,
+  /// only the log's events are emitted. If stderr was, only the output and
+  /// `LogEOF` are: a `rawvideo` output is described by its `-pix_fmt`, `-s`
+  /// and `-r` options, or else by probing the first input with ffprobe, and
+  /// any other output is read in chunks. Without either pipe, an
+  /// [`IterError`](crate::iter::IterError) is returned.
+  pub fn iter(&mut self) -> anyhow::Result<FfmpegIterator<B>> {
+    match self.events.take() {
+      Some(events) => Ok(*events),
+      None => FfmpegIterator::new(self),
+    }
+  }
+
+  /// Like [`iter`](Self::iter), but the iterator takes ownership of the child
+  /// process. Once both stderr and stdout have closed, the child is reaped
+  /// and its exit status is reported in the final `FfmpegEvent::Completed`
+  /// event.
+  ///
+  /// Take stdin first with [`take_stdin`](Self::take_stdin) if it is used as
+  /// an input, since FFmpeg won't finish reading it until it is closed.
+  ///
+  /// ```rust,no_run
+  /// use ffmpeg_sidecar::{command::FfmpegCommand, event::FfmpegEvent};
+  ///
+  /// let events = FfmpegCommand::new().testsrc().rawvideo().spawn()?.into_events()?;
+  /// for event in events {
+  ///   if let FfmpegEvent::Completed { exit_status, .. } = event {
+  ///     assert!(exit_status.unwrap().success());
+  ///   }
+  /// }
+  /// # anyhow::Ok(())
+  /// ```
+  pub fn into_events(mut self) -> anyhow::Result<FfmpegIterator<B>> {
+    Ok(self.iter()?.with_child(self))
+  }
+
+  /// Emit `FfmpegEvent::Stalled` from the iterator when FFmpeg hasn't written
+  /// a progress update or any output for `timeout`, e.g. because a live input
+  /// stopped delivering data without FFmpeg exiting. With `kill`, the process
+  /// is also terminated, so that the iterator then finishes.
+  ///
+  /// Activity is timed as FFmpeg's output is read, not as the events are
+  /// consumed, so a slow consumer isn't mistaken for a stall.
+  ///
+  /// Must be called before [`iter`](Self::iter). The stall is reported once,
+  /// and again only after activity resumes and stops again. If progress
+  /// updates are disabled with `no_stats` or `quiet`, any log event counts as
+  /// activity.
+  ///
+  /// ```rust,no_run
+  /// use ffmpeg_sidecar::{command::FfmpegCommand, event::FfmpegEvent};
+  /// use std::time::Duration;
+  ///
+  /// let mut child = FfmpegCommand::new()
+  ///   .input("rtmp://localhost/live/stream")
+  ///   .rawvideo()
+  ///   .spawn()?;
+  /// child.stall_watchdog(Duration::from_secs(10), true);
+  /// for event in child.iter()? {
+  ///   if let FfmpegEvent::Stalled { since } = event {
+  ///     eprintln!("No data for {since:?}, restarting");
+  ///   }
+  /// }
+  /// # anyhow::Ok(())
+  /// ```
+  pub fn stall_watchdog(&mut self, timeout: Duration, kill: bool) -> &mut Self {
+    self.config.stall_watchdog = Some(StallWatchdogConfig { timeout, kill });
+    self
+  }
+
+  /// Block until FFmpeg has started writing its output, as signaled by the
+  /// first progress update or the first frame or chunk on stdout, e.g. before
+  /// connecting the readers of named pipes or telling a client that a stream
+  /// is live.
+  ///
+  /// The events received while waiting aren't lost: they are returned first
+  /// by the next call to [`iter`](Self::iter) or
+  /// [`into_events`](Self::into_events).
+  ///
+  /// If progress updates are disabled with `no_stats`, the output having been
+  /// opened counts instead. With `quiet`, only output on stdout is detected.
+  ///
+  /// ```rust,no_run
+  /// use ffmpeg_sidecar::{child::ReadinessError, command::FfmpegCommand};
+  /// use std::time::Duration;
+  ///
+  /// let mut child = FfmpegCommand::new()
+  ///   .input("rtsp://camera.local/stream")
+  ///   .output("recording.mp4")
+  ///   .spawn()?;
+  /// match child.wait_until_writing(Duration::from_secs(10)) {
+  ///   Ok(()) => println!("Recording"),
+  ///   Err(ReadinessError::Timeout(_)) => child.kill()?,
+  ///   Err(e) => eprintln!("Failed to start recording: {e}"),
+  /// }
+  /// for event in child.iter()? {
+  ///   // ...
+  /// }
+  /// # anyhow::Ok(())
+  /// ```
+  pub fn wait_until_writing(&mut self, timeout: Duration) -> Result<(), ReadinessError> {
+    let mut events = self
+      .iter()
+      .map_err(|e| ReadinessError::Unavailable(e.to_string()))?;
+    let no_stats = self.config.no_stats;
+    let deadline = Instant::now() + timeout;
+    let mut received = Vec::new();
+    let result = loop {
+      let event = match events.next_until(deadline) {
+        Ok(Some(event)) => event,
+        Ok(None) => break Err(ReadinessError::Exited(errors(&received))),
+        Err(_) => break Err(ReadinessError::Timeout(timeout)),
+      };
+      let is_writing = match &event {
+        FfmpegEvent::Progress(_)
+        | FfmpegEvent::OutputFrame(_)
+        | FfmpegEvent::OutputAudioSamples(_)
+        | FfmpegEvent::OutputChunk(_)
+        | FfmpegEvent::OutputPipeChunk { .. } => true,
+        FfmpegEvent::ParsedOutputStream(_) => no_stats && events.metadata_completed(),
+        FfmpegEvent::Completed { .. } => {
+          received.push(event);
+          break Err(ReadinessError::Exited(errors(&received)));
+        }
+        _ => false,
+      };
+      received.push(event);
+      if is_writing {
+        break Ok(());
+      }
+    };
+    events.replay(received);
+    self.events = Some(Box::new(events));
+    result
+  }
+
+  /// Write FFmpeg's raw stderr to `writer` as it is read, e.g. to keep a log
+  /// file of every job next to its output. Progress updates, which FFmpeg
+  /// overwrites with `\r`, are kept according to `policy`.
+  ///
+  /// Must be called before [`iter`](Self::iter) or [`wait`](Self::wait).
+  ///
+  /// ```rust,no_run
+  /// use ffmpeg_sidecar::{command::FfmpegCommand, stderr_recorder::CarriageReturnPolicy};
+  /// use std::fs::File;
+  ///
+  /// FfmpegCommand::new()
+  ///   .input("input.mp4")
+  ///   .output("output.webm")
+  ///   .spawn()?
+  ///   .record_stderr(File::create("output.log")?, CarriageReturnPolicy::default())
+  ///   .wait()?;
+  /// # anyhow::Ok(())
+  /// ```
+  pub fn record_stderr<W: Write + Send + 'static>(
+    &mut self,
+    writer: W,
+    policy: CarriageReturnPolicy,
+  ) -> &mut Self {
+    self.stderr_recorder = Some(StderrRecorder::new(Box::new(writer), policy));
+    self
+  }
+
+  /// Like [`take_stderr`](Self::take_stderr), but passing everything read
+  /// through the recorder from [`record_stderr`](Self::record_stderr), if any.
+  pub(crate) fn take_recorded_stderr(&mut self) -> Option<Box<dyn Read + Send>> {
+    let stderr = self.take_stderr()?;
+    Some(match self.stderr_recorder.take() {
+      Some(recorder) => Box::new(RecordingReader::new(stderr, recorder)),
+      None => Box::new(stderr),
+    })
+  }
+
+  /// Discard the rest of stderr, and any events already read from it, on
+  /// background threads, so that FFmpeg doesn't block on a full stderr pipe
+  /// while nothing else reads it. Does nothing once stderr was taken.
+  pub(crate) fn drain_stderr(&mut self) {
+    if let Some(events) = self.events.take() {
+      std::thread::spawn(move || events.for_each(drop));
+    }
+    if let Some(mut stderr) = self.take_recorded_stderr() {
+      std::thread::spawn(move || copy(&mut stderr, &mut sink()));
+    }
   }
 
   /// Escape hatch to manually control the process' stdout channel.
   /// Calling this method takes ownership of the stdout channel, so
   /// the iterator will no longer include output frames in the stream of events.
-  pub fn take_stdout(&mut self) -> Option<ChildStdout> {
-    self.inner.stdout.take()
+  pub fn take_stdout(&mut self) -> Option<B::Stdout> {
+    self.inner.stdout().take()
+  }
+
+  /// Stream the process' stdout directly into a file, socket, or other writer
+  /// on a background thread, returning a handle which resolves to the total
+  /// number of bytes copied once FFmpeg closes stdout.
+  ///
+  /// Files and TCP streams are kept as concrete types so that
+  /// [`std::io::copy`] can use its platform fast path: on Linux, data moves
+  /// from the pipe to the destination with `splice`/`sendfile` inside the
+  /// kernel, without being copied through user space. Other writers (and other
+  /// platforms) fall back to a regular buffered copy.
+  ///
+  /// Like [`take_stdout`](Self::take_stdout), this takes ownership of the
+  /// stdout channel, so the iterator will no longer emit output frames or
+  /// chunks. Log, progress, and metadata events are unaffected.
+  pub fn output_writer<W: Into<OutputWriter>>(
+    &mut self,
+    writer: W,
+  ) -> anyhow::Result<JoinHandle<io::Result<u64>>> {
+    let mut stdout = self
+      .take_stdout()
+      .context("No stdout channel\n - Did you call `take_stdout` elsewhere?")?;
+    let writer = writer.into();
+    Ok(std::thread::spawn(move || match writer {
+      OutputWriter::File(mut file) => copy(&mut stdout, &mut file),
+      OutputWriter::TcpStream(mut stream) => copy(&mut stdout, &mut stream),
+      OutputWriter::Writer(mut writer) => copy(&mut stdout, &mut writer),
+    }))
   }
 
   /// Escape hatch to manually control the process' stderr channel.
   /// This method is mutually exclusive with `events_iter`, which relies on
   /// the stderr channel to parse events.
-  pub fn take_stderr(&mut self) -> Option<ChildStderr> {
-    self.inner.stderr.take()
+  pub fn take_stderr(&mut self) -> Option<B::Stderr> {
+    self.inner.stderr().take()
   }
 
   /// Escape hatch to manually control the process' stdin channel.
   /// This method is mutually exclusive with `send_stdin_command` and `quit`,
-  /// which use the stdin channel to send commands to ffmpeg.
-  pub fn take_stdin(&mut self) -> Option<ChildStdin> {
-    self.inner.stdin.take()
+  /// which use the stdin channel to send commands to ffmpeg. Always `None`
+  /// unless stdin is piped; see `FfmpegCommand::stdin_mode`.
+  pub fn take_stdin(&mut self) -> Option<B::Stdin> {
+    self.inner.stdin().take()
+  }
+
+  /// Take stdin as a [`FrameWriter`] for the raw video input added with
+  /// `FfmpegCommand::frame_input`. Fails if there is none, if its frame size
+  /// is unknown, or if stdin has already been taken.
+  pub fn frame_writer(&mut self) -> anyhow::Result<FrameWriter<B::Stdin>> {
+    let pump = self
+      .config
+      .frame_input
+      .clone()
+      .context("No frame input; see `FfmpegCommand::frame_input`")?;
+    anyhow::ensure!(
+      pump.frame_size() > 0,
+      "Unsupported pixel format for a frame input: {}",
+      pump.pix_fmt
+    );
+    let stdin = self.take_stdin().context("stdin has already been taken")?;
+    Ok(FrameWriter::new(stdin, pump))
   }
 
   /// Send a command to ffmpeg over stdin, used during interactive mode.
@@ -68,9 +332,16 @@ impl FfmpegChild {
   /// s      Show QP histogram
   /// ```
   pub fn send_stdin_command(&mut self, command: &[u8]) -> anyhow::Result<()> {
-    let mut stdin = self.inner.stdin.take().context("Missing child stdin")?;
+    let mut stdin = self
+      .inner
+      .stdin()
+      .take()
+      .with_context(|| match self.config.stdin_mode {
+        StdinMode::Piped => "Missing child stdin".to_string(),
+        mode => format!("stdin isn't piped but {mode:?}; see `FfmpegCommand::stdin_mode`"),
+      })?;
     stdin.write_all(command)?;
-    self.inner.stdin.replace(stdin);
+    self.inner.stdin().replace(stdin);
     Ok(())
   }
 
@@ -84,6 +355,66 @@ impl FfmpegChild {
     self.send_stdin_command(b"q")
   }
 
+  /// Ask FFmpeg to quit with [`quit`](Self::quit), then kill it if it is
+  /// still running after `grace`, and wait for it to exit.
+  ///
+  /// Useful when the output is no longer needed, but FFmpeg may be blocked on
+  /// its input and never read the `q` command.
+  pub fn quit_or_kill(&mut self, grace: Duration) -> io::Result<ExitStatus> {
+    self.stop_within(grace)?;
+    self.wait()
+  }
+
+  fn stop_within(&mut self, grace: Duration) -> io::Result<()> {
+    // Fails if stdin was taken or FFmpeg has already exited
+    self.quit().ok();
+    let deadline = Instant::now() + grace;
+    while self.try_wait()?.is_none() {
+      if Instant::now() >= deadline {
+        return self.inner.kill();
+      }
+      std::thread::sleep(QUIT_POLL_INTERVAL);
+    }
+    Ok(())
+  }
+
+  /// Like [`wait`](Self::wait), but giving up after `timeout`, returning
+  /// `None` and leaving FFmpeg running if it hasn't exited by then.
+  ///
+  /// If stderr hasn't been taken, e.g. by [`iter`](Self::iter), it's read
+  /// and discarded in the background so that FFmpeg doesn't block on it, as
+  /// are the events read ahead by
+  /// [`wait_until_writing`](Self::wait_until_writing).
+  ///
+  /// ```rust,no_run
+  /// use ffmpeg_sidecar::command::FfmpegCommand;
+  /// use std::time::Duration;
+  ///
+  /// let mut child = FfmpegCommand::new()
+  ///   .input("rtmp://localhost/live/stream")
+  ///   .output("recording.mp4")
+  ///   .spawn()?;
+  /// if child.wait_timeout(Duration::from_secs(60))?.is_none() {
+  ///   child.kill()?;
+  ///   child.wait()?;
+  /// }
+  /// # anyhow::Ok(())
+  /// ```
+  pub fn wait_timeout(&mut self, timeout: Duration) -> io::Result<Option<ExitStatus>> {
+    let deadline = Instant::now() + timeout;
+    self.drain_stderr();
+    loop {
+      if self.try_wait()?.is_some() {
+        return self.wait().map(Some);
+      }
+      let remaining = deadline.saturating_duration_since(Instant::now());
+      if remaining.is_zero() {
+        return Ok(None);
+      }
+      std::thread::sleep(remaining.min(QUIT_POLL_INTERVAL));
+    }
+  }
+
   /// Forcibly terminate the inner child process.
   ///
   /// Alternatively, you may choose to gracefully stop the child process by
@@ -96,39 +427,278 @@ impl FfmpegChild {
 
   /// Waits for the inner child process to finish execution.
   ///
-  /// Identical to `wait` in [`std::process::Child`].
+  /// Identical to `wait` in [`std::process::Child`], except that if the
+  /// output stopped being read early, e.g. because the iterator was dropped,
+  /// FFmpeg is stopped with [`quit_or_kill`](Self::quit_or_kill) rather than
+  /// left running until it notices. Events read ahead by
+  /// [`wait_until_writing`](Self::wait_until_writing) which were never
+  /// iterated are read to the end and discarded.
   pub fn wait(&mut self) -> io::Result<ExitStatus> {
+    // The events read ahead by `wait_until_writing` hold stderr and stdout,
+    // so FFmpeg would block on them once their pipes fill up
+    if let Some(events) = self.events.take() {
+      events.for_each(drop);
+    }
+
+    if self.consumer_closed.swap(false, Ordering::Relaxed) {
+      self.stop_within(CONSUMER_CLOSED_GRACE)?;
+    }
+
+    // Unread progress reports would fill the socket's buffer and block
+    // FFmpeg, so they're discarded in the background.
+    if let Some(output) = self.progress_output.take() {
+      std::thread::spawn(move || {
+        if let Ok(mut stream) = output.accept(PROGRESS_ACCEPT_TIMEOUT) {
+          copy(&mut stream, &mut sink()).ok();
+        }
+      });
+    }
+
     // If stderr hasn't already been consumed by a method like `iter()`,
     // we need to run it to completion to avoid a deadlock.
-    if let Some(mut stderr) = self.take_stderr() {
+    if let Some(mut stderr) = self.take_recorded_stderr() {
       copy(&mut stderr, &mut sink())?;
     };
 
-    self.inner.wait()
+    // Once reaped, the process' ID may be reused, so the killer used by
+    // other threads is disarmed first. On Windows, it isn't reused while the
+    // child's handle is open.
+    #[cfg(unix)]
+    {
+      if let Some(pid) = self.inner.id() {
+        wait_without_reaping(pid);
+      }
+      self.killer.disarm();
+    }
+    let status = self.inner.wait();
+    #[cfg(not(unix))]
+    self.killer.disarm();
+    #[cfg(feature = "named_pipes")]
+    self.pipes.close();
+    status
   }
 
-  /// Wrap a [`std::process::Child`] in a `FfmpegChild`. Should typically only
-  /// be called by `FfmpegCommand::spawn`.
-  ///
-  /// ## Panics
+  /// The exit status, reaping the child, if it has already exited.
+  pub(crate) fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+    let inner = &mut self.inner;
+    self.killer.try_reap(|| inner.try_wait())
+  }
+
+  /// The named pipes created for `FfmpegCommand::named_pipe_output`, which
+  /// are removed when the child is reaped with [`wait`](Self::wait).
+  #[cfg(feature = "named_pipes")]
+  #[cfg_attr(docsrs, doc(cfg(feature = "named_pipes")))]
+  pub fn pipes(&mut self) -> &mut crate::named_pipes::ManagedPipes {
+    &mut self.pipes
+  }
+
+  #[cfg(feature = "named_pipes")]
+  pub(crate) fn with_pipes(mut self, pipes: crate::named_pipes::ManagedPipes) -> Self {
+    self.pipes = pipes;
+    self
+  }
+
+  /// Wrap a process from another [`ProcessBackend`], e.g. an in-memory fake
+  /// for tests, as if it had been spawned by a default `FfmpegCommand`.
+  pub fn from_backend(inner: B) -> Self {
+    Self::from_inner(inner, CommandConfig::default())
+  }
+
+  /// Wrap a process in a `FfmpegChild`. Should typically only be called by
+  /// `FfmpegCommand::spawn`.
   ///
-  /// Panics if the any of the child process's stdio channels were not piped.
-  /// This could be because ffmpeg was spawned with `-nostdin`, or if the
-  /// `Child` instance was not configured with `stdin(Stdio::piped())`.
-  pub(crate) fn from_inner(inner: Child) -> Self {
-    assert!(inner.stdin.is_some(), "stdin was not piped");
-    assert!(inner.stdout.is_some(), "stdout was not piped");
-    assert!(inner.stderr.is_some(), "stderr was not piped");
-    Self { inner }
+  /// Any of stdin, stdout and stderr may have been redirected elsewhere,
+  /// e.g. by `FfmpegCommand::input_from_stdio` or `output_to_stdio`; see
+  /// [`iter`](Self::iter) for how that affects the events.
+  pub(crate) fn from_inner(inner: B, config: CommandConfig) -> Self {
+    Self {
+      killer: ProcessKiller::new(inner.id()),
+      inner,
+      config,
+      spawned_at: Instant::now(),
+      stderr_recorder: None,
+      events: None,
+      progress_output: None,
+      consumer_closed: Arc::new(AtomicBool::new(false)),
+      #[cfg(feature = "named_pipes")]
+      pipes: Default::default(),
+    }
+  }
+
+  pub(crate) fn with_progress_output(mut self, output: Option<TcpOutput>) -> Self {
+    self.progress_output = output;
+    self
   }
 
-  /// Escape hatch to access the inner `Child`.
-  pub fn as_inner(&mut self) -> &Child {
+  /// The listener for `FfmpegCommand::progress_over_tcp`, if it hasn't been
+  /// taken yet.
+  pub(crate) fn take_progress_output(&mut self) -> Option<TcpOutput> {
+    self.progress_output.take()
+  }
+
+  /// The configuration carried over from the `FfmpegCommand` that spawned
+  /// this child.
+  pub(crate) fn config(&self) -> &CommandConfig {
+    &self.config
+  }
+
+  /// The flag raised when the output's consumer goes away, shared with the
+  /// iterator's threads.
+  pub(crate) fn consumer_closed(&self) -> Arc<AtomicBool> {
+    self.consumer_closed.clone()
+  }
+
+  /// Kills the process from other threads until it is reaped.
+  pub(crate) fn killer(&self) -> ProcessKiller {
+    self.killer.clone()
+  }
+
+  /// When the process was spawned, the reference point of
+  /// `FfmpegEvent::StartupTimings`.
+  pub(crate) fn spawned_at(&self) -> Instant {
+    self.spawned_at
+  }
+
+  /// Escape hatch to access the inner `Child`, or other backend.
+  pub fn as_inner(&mut self) -> &B {
     &self.inner
   }
 
-  /// Escape hatch to mutably access the inner `Child`.
-  pub fn as_inner_mut(&mut self) -> &mut Child {
+  /// Escape hatch to mutably access the inner `Child`, or other backend.
+  pub fn as_inner_mut(&mut self) -> &mut B {
     &mut self.inner
   }
 }
+
+impl<B: ProcessBackend> Drop for FfmpegChild<B> {
+  /// FFmpeg may not have opened the scripts from
+  /// `FfmpegCommand::filter_complex_auto` yet, so while it's running, they're
+  /// kept until it closes stderr. An iterator's stderr thread holds its own
+  /// references.
+  fn drop(&mut self) {
+    let scripts = std::mem::take(&mut self.config.filter_scripts);
+    if scripts.is_empty() || !matches!(self.inner.try_wait(), Ok(None)) {
+      return;
+    }
+    if let Some(mut stderr) = self.take_recorded_stderr() {
+      std::thread::spawn(move || {
+        copy(&mut stderr, &mut sink()).ok();
+        drop(scripts);
+      });
+    }
+  }
+}
+
+/// How long `wait` gives FFmpeg to connect to the `progress_over_tcp`
+/// listener if the iterator didn't.
+const PROGRESS_ACCEPT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long FFmpeg is given to quit after its output's consumer goes away,
+/// before it is killed.
+pub(crate) const CONSUMER_CLOSED_GRACE: Duration = Duration::from_secs(2);
+
+/// How often `quit_or_kill` checks whether FFmpeg has exited.
+const QUIT_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Block until the process `pid` has exited, leaving it to be reaped.
+#[cfg(unix)]
+fn wait_without_reaping(pid: u32) {
+  loop {
+    // SAFETY: waitid only writes to `info`, a valid `siginfo_t`.
+    let result = unsafe {
+      let mut info: libc::siginfo_t = std::mem::zeroed();
+      libc::waitid(
+        libc::P_PID,
+        pid as libc::id_t,
+        &mut info,
+        libc::WEXITED | libc::WNOWAIT,
+      )
+    };
+    // Also returns once the process was already reaped, e.g. through
+    // `as_inner_mut`
+    if result == 0 || io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+      return;
+    }
+  }
+}
+
+/// The messages of the error events among `events`.
+fn errors(events: &[FfmpegEvent]) -> Vec<String> {
+  events
+    .iter()
+    .filter_map(|event| match event {
+      FfmpegEvent::Error(e) | FfmpegEvent::Log(LogLevel::Error | LogLevel::Fatal, e) => {
+        Some(e.clone())
+      }
+      _ => None,
+    })
+    .collect()
+}
+
+/// Why [`FfmpegChild::wait_until_writing`] failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReadinessError {
+  /// No output was written within the timeout. FFmpeg may still be
+  /// running, e.g. waiting for a network input.
+  Timeout(Duration),
+  /// FFmpeg finished without writing any output, with these errors.
+  Exited(Vec<String>),
+  /// FFmpeg's events couldn't be read, e.g. because stderr was taken.
+  Unavailable(String),
+}
+
+impl fmt::Display for ReadinessError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ReadinessError::Timeout(timeout) => {
+        write!(f, "FFmpeg didn't start writing output within {timeout:?}")
+      }
+      ReadinessError::Exited(errors) => {
+        write!(
+          f,
+          "FFmpeg exited without writing output: {}",
+          errors.join("\n")
+        )
+      }
+      ReadinessError::Unavailable(e) => write!(f, "Can't read FFmpeg's events: {e}"),
+    }
+  }
+}
+
+impl std::error::Error for ReadinessError {}
+
+/// A destination for [`FfmpegChild::output_writer`].
+pub enum OutputWriter {
+  /// Eligible for zero-copy transfer on Linux.
+  File(File),
+  /// Eligible for zero-copy transfer on Linux.
+  TcpStream(TcpStream),
+  /// Any other writer, copied through a user space buffer.
+  Writer(Box<dyn Write + Send>),
+}
+
+impl OutputWriter {
+  /// Wrap an arbitrary writer. Prefer passing a `File` or `TcpStream`
+  /// directly, which preserves the zero-copy fast path.
+  pub fn writer<W: Write + Send + 'static>(writer: W) -> Self {
+    Self::Writer(Box::new(writer))
+  }
+}
+
+impl From<File> for OutputWriter {
+  fn from(file: File) -> Self {
+    Self::File(file)
+  }
+}
+
+impl From<TcpStream> for OutputWriter {
+  fn from(stream: TcpStream) -> Self {
+    Self::TcpStream(stream)
+  }
+}
+
+impl From<Box<dyn Write + Send>> for OutputWriter {
+  fn from(writer: Box<dyn Write + Send>) -> Self {
+    Self::Writer(writer)
+  }
+}
@@ -1,12 +1,43 @@
 //! Builder interface for FFmpeg commands.
 
-use crate::{child::FfmpegChild, paths::ffmpeg_path};
+#[cfg(feature = "serde")]
+use crate::job_spec::JobSpec;
+#[cfg(unix)]
+use crate::resource_limits::ResourceLimits;
+use crate::{
+  analysis::Deinterlacer,
+  args::ArgModel,
+  audio_filter::{pitch_filter, tempo_filter},
+  child::FfmpegChild,
+  event::{FfmpegInput, Stream},
+  gpu::GpuDevice,
+  hw_transcode::TranscodePipeline,
+  lint::{lint_model_with, LintWarning},
+  map::{validate_maps, MapWarning},
+  pan::{channel_map_filter, pan_filter, validate_pan_filters, PanWarning},
+  paths::{ffmpeg_path, file_arg, long_path},
+  tcp_output::TcpOutput,
+  temp_file::TempFile,
+  template::CommandTemplate,
+  time_range::{validate_time_ranges, FfmpegTimeDuration, TimeRangeWarning},
+};
+use anyhow::Context;
 use std::{
+  borrow::Cow,
+  collections::BTreeMap,
   ffi::OsStr,
   fmt, io,
+  path::{Path, PathBuf},
   process::{Command, CommandArgs, Stdio},
+  time::Duration,
 };
 
+/// The longest filtergraph which `FfmpegCommand::filter_complex_auto` passes
+/// as an argument. Well within Linux's limit of 128 KiB per argument, and
+/// leaves room for the rest of the command in Windows' limit of 32767
+/// characters for the whole command line.
+pub const MAX_INLINE_FILTERGRAPH_LEN: usize = 16_384;
+
 /// A wrapper around [`std::process::Command`] with some convenient preset
 /// argument sets and customization for `ffmpeg` specifically.
 ///
@@ -15,6 +46,142 @@ use std::{
 /// exhaustive list of possible arguments.
 pub struct FfmpegCommand {
   inner: Command,
+  args: ArgModel,
+  /// Set by `as_inner_mut`, after which `args` may be missing arguments added
+  /// to `inner` directly.
+  raw_edits: bool,
+  config: CommandConfig,
+}
+
+/// Settings which don't correspond to FFmpeg arguments, but configure how the
+/// spawned process and its output are handled. Carried from the command to the
+/// `FfmpegChild` and on to the `FfmpegIterator`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CommandConfig {
+  /// Buffer size for reads from stdout in chunked mode.
+  pub(crate) stdout_chunk_size: Option<usize>,
+  /// Set by `audio_samples`.
+  pub(crate) audio_samples: bool,
+  /// Polling interval for `FfmpegEvent::ResourceUsage` events.
+  pub(crate) resource_sample_interval: Option<Duration>,
+  /// Minimum interval between `FfmpegEvent::Throughput` events.
+  pub(crate) throughput_interval: Option<Duration>,
+  /// `-itsoffset` values from `sync_inputs`, keyed by input index, which
+  /// haven't yet been applied to an input.
+  pub(crate) pending_input_offsets: BTreeMap<usize, Duration>,
+  /// Misplaced arguments detected by the typed builder methods, reported when
+  /// the command is spawned.
+  pub(crate) placement_errors: Vec<ArgPlacementError>,
+  /// Set by `expect_no_output`: finishing without any output streams or
+  /// stdout data is not an error.
+  pub(crate) expect_no_output: bool,
+  /// The options of each input, recorded on spawn to describe stdout when
+  /// stderr isn't available.
+  pub(crate) input_args: Vec<crate::args::FileArgs>,
+  /// The options of each output, recorded on spawn to compare the encoders
+  /// FFmpeg reports against the requested ones.
+  pub(crate) output_args: Vec<crate::args::FileArgs>,
+  /// The FFmpeg binary, recorded on spawn to remember the codecs its muxers
+  /// reject.
+  pub(crate) ffmpeg_path: Option<std::ffi::OsString>,
+  /// Registered with `extract`.
+  pub(crate) extractors: Vec<crate::extract::LogExtractor>,
+  /// Set by `error_blocks`.
+  pub(crate) error_blocks: bool,
+  /// Set by `raw_log_lines`.
+  pub(crate) raw_log_lines: bool,
+  /// Set by `env_clear`, which `Command::get_envs` doesn't reflect.
+  pub(crate) env_clear: bool,
+  /// Applied by `resource_limits`, recorded to be applied again to commands
+  /// instantiated from a `CommandTemplate`.
+  #[cfg(unix)]
+  pub(crate) resource_limits: Vec<ResourceLimits>,
+  /// The standard streams connected to handles by `input_from_stdio` or
+  /// `output_to_stdio`, which a `CommandTemplate` can't reproduce.
+  pub(crate) redirected_stdio: Vec<&'static str>,
+  /// Tags from `scaled_outputs`, keyed by output index.
+  pub(crate) output_tags: BTreeMap<u32, String>,
+  /// Set on spawn when the log level is below `info`, so that inputs and
+  /// outputs aren't described. See `quiet`.
+  pub(crate) quiet: bool,
+  /// Set on spawn when progress updates are disabled. See `no_stats`.
+  pub(crate) no_stats: bool,
+  /// Set by `progress_over_tcp`.
+  pub(crate) progress_over_tcp: bool,
+  /// Set by `structured_progress`.
+  pub(crate) structured_progress: bool,
+  /// Set by `stdin_mode`.
+  pub(crate) stdin_mode: StdinMode,
+  /// Set by `frame_input`.
+  pub(crate) frame_input: Option<crate::frame_pump::InputFramePump>,
+  /// Scripts written by `filter_complex_auto`, kept until FFmpeg exits.
+  pub(crate) filter_scripts: Vec<std::sync::Arc<crate::temp_file::TempFile>>,
+  /// Registered with `capture_filter_output`.
+  pub(crate) filter_outputs: Vec<crate::filter_output::FilterOutputCapture>,
+  /// Added with `output_pipe`.
+  pub(crate) output_pipes: Vec<crate::output_pipe::OutputPipe>,
+  /// Why a listener for `output_pipe` couldn't be bound, reported on spawn.
+  pub(crate) output_pipe_error: Option<String>,
+  /// Set by `FfmpegChild::stall_watchdog`.
+  pub(crate) stall_watchdog: Option<crate::watchdog::StallWatchdogConfig>,
+  /// Paths of named pipes to create when the command is spawned.
+  #[cfg(feature = "named_pipes")]
+  pub(crate) named_pipes: Vec<PathBuf>,
+}
+
+/// An argument which was added at a position where FFmpeg would reject it or
+/// silently apply it to the wrong file. Returned from
+/// [`FfmpegCommand::spawn`] wrapped in an `io::Error` of kind
+/// `InvalidInput`, and can be recovered with `get_ref()` and `downcast_ref()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArgPlacementError {
+  /// An input option (such as `-stream_loop`) was not followed by any `-i`.
+  InputOptionWithoutInput(String),
+  /// An output option (such as `-shortest`) was added before any `-i`, where
+  /// FFmpeg would treat it as an input option.
+  OutputOptionBeforeInput(String),
+}
+
+impl fmt::Display for ArgPlacementError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ArgPlacementError::InputOptionWithoutInput(arg) => {
+        write!(
+          f,
+          "`{arg}` is an input option and must precede an `-i` input"
+        )
+      }
+      ArgPlacementError::OutputOptionBeforeInput(arg) => {
+        write!(f, "`{arg}` is an output option and must follow the inputs")
+      }
+    }
+  }
+}
+
+impl std::error::Error for ArgPlacementError {}
+
+/// How FFmpeg's stdin is connected; see [`FfmpegCommand::stdin_mode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StdinMode {
+  /// A pipe written by this process, used by `FfmpegChild::take_stdin` and
+  /// interactive commands like `FfmpegChild::quit`.
+  #[default]
+  Piped,
+  /// Connected to nothing, so reading it ends immediately.
+  Null,
+  /// Shared with this process, e.g. so that FFmpeg reads keys or an
+  /// overwrite confirmation from the terminal.
+  Inherit,
+}
+
+impl StdinMode {
+  pub(crate) fn stdio(self) -> Stdio {
+    match self {
+      StdinMode::Piped => Stdio::piped(),
+      StdinMode::Null => Stdio::null(),
+      StdinMode::Inherit => Stdio::inherit(),
+    }
+  }
 }
 
 impl FfmpegCommand {
@@ -33,6 +200,134 @@ impl FfmpegCommand {
     self
   }
 
+  /// Alias for `-loglevel level+error`, overriding the default log level of
+  /// `info`.
+  ///
+  /// Only errors are logged, so the iterator no longer receives
+  /// the descriptions of inputs and outputs, nor any progress updates. It
+  /// adapts by reading stdout in chunks (`FfmpegEvent::OutputChunk`) right
+  /// away, rather than waiting for the output description to find the size
+  /// of each frame, and `collect_metadata` fails immediately instead of
+  /// waiting for metadata which never arrives. The same applies when the log
+  /// level is lowered with `args`.
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::command::FfmpegCommand;
+  ///
+  /// let mut command = FfmpegCommand::new();
+  /// command.quiet();
+  /// let args: Vec<_> = command.get_args().collect();
+  /// assert_eq!(args[2..], ["-loglevel", "level+error"]);
+  /// ```
+  pub fn quiet(&mut self) -> &mut Self {
+    self.args(["-loglevel", "level+error"]);
+    self
+  }
+
+  /// Alias for `-nostats`.
+  ///
+  /// Disables the progress updates (`FfmpegEvent::Progress`) which FFmpeg
+  /// logs once or twice a second, while keeping all other logs and metadata.
+  /// The `stall_watchdog` of the spawned child then counts any event as
+  /// activity, since progress updates are no longer a sign of life.
+  pub fn no_stats(&mut self) -> &mut Self {
+    self.arg("-nostats");
+    self
+  }
+
+  /// Receive progress updates over a loopback TCP connection rather than
+  /// from stderr. On spawn, a listener is bound to an ephemeral port on
+  /// `127.0.0.1` and passed to FFmpeg as `-progress tcp://127.0.0.1:<port>`,
+  /// along with `-nostats`. The iterator parses the `key=value` reports sent
+  /// there into the usual `FfmpegEvent::Progress`.
+  ///
+  /// This avoids an extra pipe on platforms where one is awkward (Windows),
+  /// and keeps progress updates flowing even with `quiet`. The
+  /// `raw_log_message` of each update is the whole `key=value` block.
+  ///
+  /// ```rust,no_run
+  /// use ffmpeg_sidecar::command::FfmpegCommand;
+  ///
+  /// let iter = FfmpegCommand::new()
+  ///   .input("input.mp4")
+  ///   .output("output.webm")
+  ///   .quiet()
+  ///   .progress_over_tcp()
+  ///   .spawn()?
+  ///   .iter()?;
+  /// for progress in iter.filter_progress() {
+  ///   println!("{} ({}x)", progress.time, progress.speed);
+  /// }
+  /// # anyhow::Ok(())
+  /// ```
+  pub fn progress_over_tcp(&mut self) -> &mut Self {
+    self.config.progress_over_tcp = true;
+    self
+  }
+
+  /// Receive progress updates as the `key=value` reports of `-progress
+  /// pipe:2` rather than the `frame=... time=...` line of the log. On spawn,
+  /// `-nostats -progress pipe:2` is appended, and the reports are parsed from
+  /// stderr into the usual `FfmpegEvent::Progress`.
+  ///
+  /// The reports are stable across FFmpeg versions, and give values the log
+  /// line lacks: the position in microseconds (`out_time_us`), the exact
+  /// output size (`total_size`), and the number of duplicated and dropped
+  /// frames. [`progress_over_tcp`](Self::progress_over_tcp) delivers the
+  /// same reports over a dedicated connection instead.
+  ///
+  /// ```rust,no_run
+  /// use ffmpeg_sidecar::command::FfmpegCommand;
+  ///
+  /// let iter = FfmpegCommand::new()
+  ///   .input("input.mp4")
+  ///   .output("output.webm")
+  ///   .structured_progress()
+  ///   .spawn()?
+  ///   .iter()?;
+  /// for progress in iter.filter_progress() {
+  ///   println!("{:?} us, {:?} bytes", progress.out_time_us, progress.total_size);
+  /// }
+  /// # anyhow::Ok(())
+  /// ```
+  pub fn structured_progress(&mut self) -> &mut Self {
+    self.config.structured_progress = true;
+    self
+  }
+
+  /// Stream each line written to `capture`'s destination back as an
+  /// `FfmpegEvent::FilterOutput`. The destination must also be passed to the
+  /// filter or option which writes it; see [`FilterOutputCapture`].
+  ///
+  /// ```rust,no_run
+  /// use ffmpeg_sidecar::{command::FfmpegCommand, filter_output::FilterOutputCapture};
+  ///
+  /// let transcript = FilterOutputCapture::tcp("whisper")?;
+  /// let filter = format!(
+  ///   "whisper=model=ggml-base.en.bin:format=srt:destination={}",
+  ///   transcript.filter_value()
+  /// );
+  /// FfmpegCommand::new()
+  ///   .input("speech.wav")
+  ///   .args(["-af", &filter])
+  ///   .format("null")
+  ///   .output("-")
+  ///   .capture_filter_output(&transcript)
+  ///   .spawn()?
+  ///   .iter()?
+  ///   .for_each(|event| println!("{event:?}"));
+  /// # anyhow::Ok(())
+  /// ```
+  ///
+  /// [`FilterOutputCapture`]: crate::filter_output::FilterOutputCapture
+  pub fn capture_filter_output(
+    &mut self,
+    capture: &crate::filter_output::FilterOutputCapture,
+  ) -> &mut Self {
+    self.config.filter_outputs.push(capture.clone());
+    self
+  }
+
   //// Main option aliases
   //// https://ffmpeg.org/ffmpeg.html#Main-options
 
@@ -49,10 +344,16 @@ impl FfmpegCommand {
 
   /// Alias for `-i` argument, the input file path or URL.
   ///
-  /// To take input from stdin, use the value `-` or `pipe:0`.
-  pub fn input<S: AsRef<str>>(&mut self, path_or_url: S) -> &mut Self {
+  /// To take input from stdin, use the value `-` or `pipe:0`. For a local
+  /// file whose name FFmpeg could take for a URL, use
+  /// [`input_path`](Self::input_path).
+  pub fn input<S: AsRef<OsStr>>(&mut self, path_or_url: S) -> &mut Self {
+    let input_index = self.get_args().filter(|arg| *arg == "-i").count();
+    if let Some(offset) = self.config.pending_input_offsets.remove(&input_index) {
+      self.itsoffset(format!("{:.6}", offset.as_secs_f64()));
+    }
     self.arg("-i");
-    self.arg(path_or_url.as_ref());
+    self.arg(long_path(path_or_url.as_ref()));
     self
   }
 
@@ -64,11 +365,53 @@ impl FfmpegCommand {
   /// preceding it, it is equivalent to calling `.arg()` directly. However,
   /// using this command helps label the purpose of the argument, and makes the
   /// code more readable at a glance.
-  pub fn output<S: AsRef<str>>(&mut self, path_or_url: S) -> &mut Self {
-    self.arg(path_or_url.as_ref());
+  ///
+  /// Codecs selected for the output which its container can't store, like
+  /// `pcm_s16le` in MP4, are reported by [`lint`](Self::lint).
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::{command::FfmpegCommand, lint::LintWarning};
+  ///
+  /// let warnings = FfmpegCommand::new()
+  ///   .input("input.wav")
+  ///   .codec_audio("pcm_s16le")
+  ///   .output("output.mp4")
+  ///   .lint();
+  /// assert!(matches!(&warnings[..], [LintWarning::UnsupportedCodec { container, .. }] if container == "mp4"));
+  /// ```
+  pub fn output<S: AsRef<OsStr>>(&mut self, path_or_url: S) -> &mut Self {
+    self.arg(long_path(path_or_url.as_ref()));
     self
   }
 
+  /// Like [`input`](Self::input), but always read as a local file, even if
+  /// the name starts with a `-` or looks like a URL, e.g. `10:30.mp4`. See
+  /// [`file_arg`].
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::command::FfmpegCommand;
+  /// use std::path::Path;
+  ///
+  /// let mut command = FfmpegCommand::new();
+  /// command
+  ///   .input_path("rtmp:backup.flv")
+  ///   .output_path("-final.mp4");
+  /// let args: Vec<_> = command.get_args().collect();
+  /// assert_eq!(
+  ///   args[args.len() - 3..],
+  ///   ["-i".as_ref(), Path::new(".").join("rtmp:backup.flv").as_os_str(), Path::new(".").join("-final.mp4").as_os_str()]
+  /// );
+  /// ```
+  pub fn input_path<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+    self.input(file_arg(path.as_ref()))
+  }
+
+  /// Like [`output`](Self::output), but always written as a local file. See
+  /// [`input_path`](Self::input_path).
+  pub fn output_path<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+    self.output(file_arg(path.as_ref()))
+  }
+
   /// Alias for `-y` argument: overwrite output files without asking.
   pub fn overwrite(&mut self) -> &mut Self {
     self.arg("-y");
@@ -131,9 +474,13 @@ impl FfmpegCommand {
   /// manual](https://ffmpeg.org/ffmpeg-utils.html#time-duration-syntax).
   ///
   /// `-to` and `-t` are mutually exclusive and -t has priority.
-  pub fn duration<S: AsRef<str>>(&mut self, duration: S) -> &mut Self {
+  ///
+  /// A [`Duration`] is also accepted; see [`FfmpegTimeDuration`], and
+  /// [`validate_time_ranges`](Self::validate_time_ranges) to check the range
+  /// against the input.
+  pub fn duration<T: Into<FfmpegTimeDuration>>(&mut self, duration: T) -> &mut Self {
     self.arg("-t");
-    self.arg(duration.as_ref());
+    self.arg(duration.into().as_str());
     self
   }
 
@@ -146,9 +493,9 @@ impl FfmpegCommand {
   ///
   /// `-to` and `-t` (aka `duration()`) are mutually exclusive and `-t` has
   /// priority.
-  pub fn to<S: AsRef<str>>(&mut self, position: S) -> &mut Self {
+  pub fn to<T: Into<FfmpegTimeDuration>>(&mut self, position: T) -> &mut Self {
     self.arg("-to");
-    self.arg(position.as_ref());
+    self.arg(position.into().as_str());
     self
   }
 
@@ -179,9 +526,9 @@ impl FfmpegCommand {
   /// `position` must be a time duration specification, see [(ffmpeg-utils)the
   /// Time duration section in the ffmpeg-utils(1)
   /// manual](https://ffmpeg.org/ffmpeg-utils.html#time-duration-syntax).
-  pub fn seek<S: AsRef<str>>(&mut self, position: S) -> &mut Self {
+  pub fn seek<T: Into<FfmpegTimeDuration>>(&mut self, position: T) -> &mut Self {
     self.arg("-ss");
-    self.arg(position.as_ref());
+    self.arg(position.into().as_str());
     self
   }
 
@@ -189,9 +536,231 @@ impl FfmpegCommand {
   ///
   /// Like the `-ss` option but relative to the "end of file". That is negative
   /// values are earlier in the file, 0 is at EOF.
-  pub fn seek_eof<S: AsRef<str>>(&mut self, position: S) -> &mut Self {
+  pub fn seek_eof<T: Into<FfmpegTimeDuration>>(&mut self, position: T) -> &mut Self {
     self.arg("-sseof");
-    self.arg(position.as_ref());
+    self.arg(position.into().as_str());
+    self
+  }
+
+  /// Alias for `-stream_loop` argument. Must be used as an input option
+  /// (before `-i`); spawning the command fails with
+  /// [`ArgPlacementError::InputOptionWithoutInput`] if no input follows it.
+  ///
+  /// Set number of times input stream shall be looped. Loop 0 means no loop,
+  /// loop -1 means infinite loop.
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::command::FfmpegCommand;
+  ///
+  /// let mut command = FfmpegCommand::new();
+  /// command.stream_loop(-1).input("input.mp4").shortest().output("-");
+  /// let args: Vec<_> = command.get_args().map(|a| a.to_str().unwrap()).collect();
+  /// assert!(args.ends_with(&["-stream_loop", "-1", "-i", "input.mp4", "-shortest", "-"]));
+  /// ```
+  pub fn stream_loop(&mut self, count: i32) -> &mut Self {
+    self.input_option("-stream_loop");
+    self.arg(count.to_string());
+    self
+  }
+
+  /// Alias for `-loop 1`. Must be used as an input option (before `-i`).
+  ///
+  /// Loop over the input images of the `image2` demuxer, e.g. to turn a
+  /// single still image into a video stream. Usually paired with
+  /// [`FfmpegCommand::shortest`] or [`FfmpegCommand::duration`] on the output
+  /// to bound the length of the result.
+  pub fn loop_input(&mut self) -> &mut Self {
+    self.input_option("-loop");
+    self.arg("1");
+    self
+  }
+
+  /// Alias for `-shortest` argument. Must be used as an output option (after
+  /// the inputs); spawning the command fails with
+  /// [`ArgPlacementError::OutputOptionBeforeInput`] if it is added before any
+  /// `-i`.
+  ///
+  /// Finish encoding when the shortest output stream ends.
+  pub fn shortest(&mut self) -> &mut Self {
+    self.output_option("-shortest");
+    self
+  }
+
+  /// Alias for `-itsoffset` argument. Must be used as an input option (before
+  /// `-i`).
+  ///
+  /// Set the input time offset. `offset` must be a time duration
+  /// specification, see [(ffmpeg-utils)the Time duration section in the
+  /// ffmpeg-utils(1)
+  /// manual](https://ffmpeg.org/ffmpeg-utils.html#time-duration-syntax).
+  ///
+  /// The offset is added to the timestamps of the input files. Specifying a
+  /// positive offset means that the corresponding streams are delayed by the
+  /// time duration specified in `offset`.
+  pub fn itsoffset<S: AsRef<str>>(&mut self, offset: S) -> &mut Self {
+    self.arg("-itsoffset");
+    self.arg(offset.as_ref());
+    self
+  }
+
+  /// Alias for `-copyts` argument.
+  ///
+  /// Do not process input timestamps, but keep their values without trying to
+  /// sanitize them. In particular, do not remove the initial start time
+  /// offset value.
+  ///
+  /// Note that, depending on the `vsync` option or on specific muxer
+  /// processing (e.g. in case the format option `avoid_negative_ts` is
+  /// enabled) the output timestamps may mismatch with the input timestamps
+  /// even when this option is selected.
+  pub fn copyts(&mut self) -> &mut Self {
+    self.arg("-copyts");
+    self
+  }
+
+  /// Alias for `-start_at_zero` argument.
+  ///
+  /// When used with `copyts`, shift input timestamps so they start at zero.
+  /// This means that using e.g. `-ss 50` will make output timestamps start at
+  /// 50 seconds, regardless of what timestamp the input file started at.
+  pub fn start_at_zero(&mut self) -> &mut Self {
+    self.arg("-start_at_zero");
+    self
+  }
+
+  /// Alias for `-use_wallclock_as_timestamps 1`. Must be used as an input
+  /// option (before `-i`).
+  ///
+  /// Replace the timestamps of the input's packets with the wall clock time
+  /// at which they were received. Useful for capture devices and network
+  /// streams whose own timestamps are missing, unreliable, or relative to an
+  /// arbitrary epoch, when downstream systems need absolute times.
+  ///
+  /// Note that the timestamps reflect arrival time, so any jitter in delivery
+  /// becomes jitter in the output timing. The `time=` of
+  /// [`FfmpegProgress`](crate::event::FfmpegProgress) is still relative to the
+  /// start of the output, unless combined with [`copyts`](Self::copyts).
+  pub fn use_wallclock_timestamps(&mut self) -> &mut Self {
+    self.args(["-use_wallclock_as_timestamps", "1"]);
+    self
+  }
+
+  /// Alias for `-fflags +genpts`. Must be used as an input option (before
+  /// `-i`).
+  ///
+  /// Generate missing presentation timestamps from decode timestamps, which is
+  /// needed for raw elementary streams or broken inputs that would otherwise
+  /// produce "Timestamps are unset" errors when remuxing.
+  ///
+  /// Repeated `-fflags` arguments for the same input override each other; to
+  /// combine flags, pass them together, e.g. `.args(["-fflags",
+  /// "+genpts+nobuffer"])`.
+  pub fn gen_pts(&mut self) -> &mut Self {
+    self.args(["-fflags", "+genpts"]);
+    self
+  }
+
+  /// Alias for `-timecode` argument. Must be used as an output option.
+  ///
+  /// Set the starting SMPTE timecode written to the output, in the format
+  /// `HH:MM:SS:FF` (or `HH:MM:SS;FF` for drop-frame timecode). Supported by
+  /// containers like MOV/MP4 (as a `tmcd` track) and MXF.
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::command::FfmpegCommand;
+  /// let mut command = FfmpegCommand::new();
+  /// command.timecode("01:00:00:00");
+  /// assert!(command.get_args().any(|arg| arg == "01:00:00:00"));
+  /// ```
+  pub fn timecode<S: AsRef<str>>(&mut self, timecode: S) -> &mut Self {
+    self.arg("-timecode");
+    self.arg(timecode.as_ref());
+    self
+  }
+
+  /// Alias for `-probesize` argument. Must be used as an input option (before
+  /// `-i`).
+  ///
+  /// Set the maximum number of bytes of the input which are read while
+  /// probing for stream information. Lower values reduce startup latency for
+  /// live inputs, at the risk of missing streams or parameters that only
+  /// appear later in the input. The default is 5,000,000 bytes.
+  pub fn probesize(&mut self, bytes: u64) -> &mut Self {
+    self.arg("-probesize");
+    self.arg(bytes.to_string());
+    self
+  }
+
+  /// Alias for `-analyzeduration` argument. Must be used as an input option
+  /// (before `-i`).
+  ///
+  /// Set how much of the input (in media time) is analyzed while probing for
+  /// stream information. Like [`probesize`](Self::probesize), lowering this
+  /// trades robustness of stream detection for faster startup. The default is
+  /// 5 seconds.
+  pub fn analyzeduration(&mut self, duration: Duration) -> &mut Self {
+    self.arg("-analyzeduration");
+    self.arg(duration.as_micros().to_string());
+    self
+  }
+
+  /// Preset for minimizing latency on a live input. Must be used before the
+  /// input (`-i`) it applies to. Equivalent to `-probesize 32
+  /// -analyzeduration 0 -fflags nobuffer -flags low_delay`.
+  ///
+  /// - `-probesize 32` and `-analyzeduration 0` start decoding as soon as
+  ///   possible, instead of buffering input to probe it first.
+  /// - `-fflags nobuffer` disables buffering of packets during the initial
+  ///   stream analysis.
+  /// - `-flags low_delay` asks decoders to output frames as soon as they are
+  ///   decoded, instead of reordering.
+  ///
+  /// Since the input is barely probed, parameters such as the format (and for
+  /// raw inputs, size and pixel format) may need to be set explicitly. This
+  /// preset sets `-fflags` itself, so it can't be combined with
+  /// [`gen_pts`](Self::gen_pts) on the same input; pass `-fflags
+  /// +genpts+nobuffer` manually instead.
+  pub fn low_latency_input(&mut self) -> &mut Self {
+    self.probesize(32);
+    self.analyzeduration(Duration::ZERO);
+    self.args(["-fflags", "nobuffer", "-flags", "low_delay"]);
+    self
+  }
+
+  /// Align separately captured inputs (e.g. an audio and a video device) by
+  /// delaying each input by the given offset, keyed by input index (starting
+  /// at 0, in the order inputs are added).
+  ///
+  /// Because `-itsoffset` must precede the `-i` it applies to, this method
+  /// must be called **before** the corresponding inputs are added with
+  /// [`input`](Self::input). Offsets that are never applied (because the input
+  /// was added earlier, added with raw `args`, or doesn't exist) cause
+  /// [`spawn`](Self::spawn) to fail with [`io::ErrorKind::InvalidInput`].
+  ///
+  /// Suitable offsets can be measured with
+  /// [`estimate_input_offsets`](crate::input_sync::estimate_input_offsets).
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::command::FfmpegCommand;
+  /// use std::time::Duration;
+  ///
+  /// let mut command = FfmpegCommand::new();
+  /// command
+  ///   .sync_inputs([(1, Duration::from_millis(120))])
+  ///   .format("lavfi")
+  ///   .input("testsrc")
+  ///   .format("lavfi")
+  ///   .input("sine");
+  /// let args: Vec<_> = command.get_args().collect();
+  /// assert!(args.windows(4).any(|w| w == ["-itsoffset", "0.120000", "-i", "sine"]));
+  /// ```
+  pub fn sync_inputs<I: IntoIterator<Item = (usize, Duration)>>(
+    &mut self,
+    offsets: I,
+  ) -> &mut Self {
+    self.config.pending_input_offsets.extend(offsets);
     self
   }
 
@@ -215,6 +784,154 @@ impl FfmpegCommand {
     self
   }
 
+  /// Preset for deinterlacing video with the `bwdif` filter, detecting the
+  /// field order from frame flags. Equivalent to `-filter:v
+  /// bwdif=mode=send_frame:parity=auto`.
+  ///
+  /// To only deinterlace inputs which are actually interlaced, run
+  /// [`detect_interlace`](crate::analysis::detect_interlace) first and apply
+  /// its `deinterlace_filter` instead.
+  pub fn deinterlace(&mut self) -> &mut Self {
+    self.arg("-filter:v");
+    self.arg(Deinterlacer::Bwdif.filter(None));
+    self
+  }
+
+  /// Crop the output video to a region of interest, so that FFmpeg only
+  /// converts and pipes those pixels instead of every frame being cropped in
+  /// Rust. Equivalent to `-filter:v crop=w:h:x:y:exact=1`; must precede the
+  /// output it applies to.
+  ///
+  /// Output frames have the size of the region, since their dimensions are
+  /// taken from the output stream reported by FFmpeg.
+  ///
+  /// Like the other `-filter:v` presets such as [`deinterlace`](Self::deinterlace),
+  /// this replaces any earlier video filter for the same output, which
+  /// [`lint`](Self::lint) reports as `LintWarning::OverriddenFilter`. To
+  /// combine them, pass a single chain to [`filter`](Self::filter).
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::{command::FfmpegCommand, lint::LintWarning};
+  ///
+  /// let mut command = FfmpegCommand::new();
+  /// command.input("4k.mp4").roi(1920, 1080, 640, 360).rawvideo();
+  /// let args: Vec<_> = command.get_args().collect();
+  /// assert_eq!(args[4..6], ["-filter:v", "crop=640:360:1920:1080:exact=1"]);
+  ///
+  /// let warnings = FfmpegCommand::new()
+  ///   .input("4k.mp4")
+  ///   .deinterlace()
+  ///   .roi(1920, 1080, 640, 360)
+  ///   .output("roi.mp4")
+  ///   .lint();
+  /// assert!(matches!(warnings[..], [LintWarning::OverriddenFilter { .. }]));
+  /// ```
+  pub fn roi(&mut self, x: u32, y: u32, width: u32, height: u32) -> &mut Self {
+    self.arg("-filter:v");
+    self.arg(format!("crop={width}:{height}:{x}:{y}:exact=1"))
+  }
+
+  /// Preset for keeping audio in sync with its timestamps during long live
+  /// captures, where the capture device's clock drifts relative to the system
+  /// clock. Equivalent to `-filter:a aresample=async=1:min_hard_comp=0.100000:first_pts=0`.
+  ///
+  /// - `async=1` stretches or squeezes the audio to follow the timestamps.
+  /// - `min_hard_comp=0.1` inserts silence or drops samples instead when the
+  ///   drift exceeds 100ms, e.g. after a dropout.
+  /// - `first_pts=0` pads the start so that the audio begins at zero.
+  ///
+  /// Every correction is logged at the `verbose` level. To receive them as
+  /// `FfmpegEvent::ParsedDriftCompensation` events, also raise the log level
+  /// with `.args(["-loglevel", "level+verbose"])`.
+  pub fn audio_drift_compensation(&mut self) -> &mut Self {
+    self.arg("-filter:a");
+    self.arg("aresample=async=1:min_hard_comp=0.100000:first_pts=0");
+    self
+  }
+
+  /// Change the audio speed by `factor` (e.g. `1.5` for 50% faster) without
+  /// changing its pitch. Factors outside the 0.5-2.0 range of a single
+  /// `atempo` filter are split into a chain automatically.
+  ///
+  /// Sets the audio filtergraph (`-filter:a`), so use [`tempo_filter`]
+  /// directly to combine it with other audio filters.
+  ///
+  /// # Panics
+  ///
+  /// If `factor` is not a positive, finite number.
+  pub fn tempo(&mut self, factor: f64) -> &mut Self {
+    self.arg("-filter:a");
+    self.arg(tempo_filter(factor));
+    self
+  }
+
+  /// Shift the audio pitch by `semitones` without changing its speed, using
+  /// `asetrate`, `aresample` and `atempo`. `sample_rate` must match the input
+  /// audio, e.g. from the `AudioStream` parsed from a previous run.
+  ///
+  /// Sets the audio filtergraph (`-filter:a`), so use [`pitch_filter`]
+  /// directly to combine it with other audio filters.
+  ///
+  /// # Panics
+  ///
+  /// If `semitones` is not finite or `sample_rate` is 0. See [`pitch_filter`].
+  pub fn pitch(&mut self, semitones: f64, sample_rate: u32) -> &mut Self {
+    self.arg("-filter:a");
+    self.arg(pitch_filter(semitones, sample_rate));
+    self
+  }
+
+  /// Remix the audio channels with a `pan` filter, given a gain matrix with
+  /// one row per output channel and one gain per input channel. See
+  /// [`pan_filter`].
+  ///
+  /// Sets the audio filtergraph (`-filter:a`). Use [`Self::validate_pan`] to
+  /// check it against the source channel layout.
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::command::FfmpegCommand;
+  ///
+  /// let mut command = FfmpegCommand::new();
+  /// command.input("stereo.wav").pan("mono", &[[0.5, 0.5]]);
+  /// let args: Vec<_> = command.get_args().collect();
+  /// assert_eq!(args[4..], ["-filter:a", "pan=mono|c0=0.5*c0+0.5*c1"]);
+  /// ```
+  pub fn pan<S: AsRef<str>, G: AsRef<[f64]>>(&mut self, layout: S, gains: &[G]) -> &mut Self {
+    self.arg("-filter:a");
+    self.arg(pan_filter(layout.as_ref(), gains));
+    self
+  }
+
+  /// Route input channels to output channels unchanged, given as
+  /// `(input_channel, output_channel)` pairs. See [`channel_map_filter`].
+  ///
+  /// Sets the audio filtergraph (`-filter:a`). Use [`Self::validate_pan`] to
+  /// check it against the source channel layout. Fails if `mapping` is
+  /// empty.
+  pub fn map_channels(&mut self, mapping: &[(usize, usize)]) -> anyhow::Result<&mut Self> {
+    let filter = channel_map_filter(mapping)?;
+    self.arg("-filter:a");
+    Ok(self.arg(filter))
+  }
+
+  /// Check every `pan` filter added so far (including those from
+  /// [`Self::pan`] and [`Self::map_channels`]) against the channel layout of
+  /// the first audio stream of the inputs, as parsed from a previous run
+  /// (`FfmpegMetadata::input_streams`). See
+  /// [`crate::pan::validate_pan_filters`].
+  pub fn validate_pan(&self, input_streams: &[Stream]) -> Vec<PanWarning> {
+    let args = self.arg_model();
+    let outputs = args.outputs.iter().flat_map(|output| &output.options);
+    let filters = outputs
+      .chain(&args.pending)
+      .filter(|option| {
+        ["-af", "-filter", "-filter:a"].contains(&option.flag.as_str())
+          || option.flag.starts_with("-filter:a:")
+      })
+      .filter_map(|option| option.value.as_deref());
+    validate_pan_filters(filters, input_streams)
+  }
+
   //// Video option aliases
   //// https://ffmpeg.org/ffmpeg.html#Video-Options
 
@@ -385,6 +1102,85 @@ impl FfmpegCommand {
     self
   }
 
+  /// Decode the next input on a specific GPU, with the matching `-hwaccel`
+  /// and `-hwaccel_device` or `-qsv_device` options. Must precede the `-i`.
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::{command::FfmpegCommand, gpu::{GpuAllocator, Hw}};
+  ///
+  /// let allocator = GpuAllocator::new(Hw::Nvidia, 2);
+  /// let gpu = allocator.acquire().unwrap();
+  /// let mut command = FfmpegCommand::new();
+  /// command
+  ///   .gpu_decode(&gpu)
+  ///   .input("in.mp4")
+  ///   .codec_video("h264_nvenc")
+  ///   .gpu_encode(&gpu)
+  ///   .output("out.mp4");
+  /// let args: Vec<_> = command.get_args().collect();
+  /// assert_eq!(args[2..6], ["-hwaccel", "cuda", "-hwaccel_device", "0"]);
+  /// assert_eq!(args[10..12], ["-gpu", "0"]);
+  /// ```
+  pub fn gpu_decode(&mut self, device: &GpuDevice) -> &mut Self {
+    self.args(device.decode_args())
+  }
+
+  /// Encode the next output on a specific GPU. Only has an effect for NVENC
+  /// (`-gpu`), since QSV and VA-API encoders use the device chosen with
+  /// [`gpu_decode`](Self::gpu_decode).
+  pub fn gpu_encode(&mut self, device: &GpuDevice) -> &mut Self {
+    self.args(device.encode_args())
+  }
+
+  /// Transcode `input` to `output` with frames kept on the GPU throughout:
+  /// hardware decoding into GPU memory, a GPU scaling filter, and the
+  /// matching hardware encoder. Pass a [`TranscodePipeline`] from
+  /// [`HwTranscode::or_software`] to fall back to `libx264`/`libx265` when
+  /// the GPU or encoder isn't available.
+  ///
+  /// Inputs with nothing to decode, i.e. a `-f lavfi` or `-f rawvideo`
+  /// format given before this, are uploaded to the GPU instead (see
+  /// [`HwTranscode::upload`]).
+  ///
+  /// ```rust,no_run
+  /// use ffmpeg_sidecar::command::FfmpegCommand;
+  /// use ffmpeg_sidecar::gpu::Hw;
+  /// use ffmpeg_sidecar::hw_transcode::{HwTranscode, TranscodePipeline, VideoCodec};
+  ///
+  /// let pipeline = HwTranscode::new(Hw::Nvidia, VideoCodec::Hevc)
+  ///   .size(1280, 720)
+  ///   .or_software();
+  /// if let TranscodePipeline::Software { reason, .. } = &pipeline {
+  ///   eprintln!("Transcoding in software: {reason}");
+  /// }
+  /// FfmpegCommand::new()
+  ///   .hw_transcode_preset(pipeline, "in.mp4", "out.mp4")
+  ///   .spawn()?
+  ///   .wait()?;
+  /// # anyhow::Ok(())
+  /// ```
+  ///
+  /// [`HwTranscode::or_software`]: crate::hw_transcode::HwTranscode::or_software
+  /// [`HwTranscode::upload`]: crate::hw_transcode::HwTranscode::upload
+  pub fn hw_transcode_preset<P: Into<TranscodePipeline>, I: AsRef<OsStr>, O: AsRef<OsStr>>(
+    &mut self,
+    pipeline: P,
+    input: I,
+    output: O,
+  ) -> &mut Self {
+    let mut pipeline = pipeline.into();
+    let args = self.arg_model();
+    let format = args.pending.iter().rev().find(|o| o.flag == "-f");
+    let is_raw = format.is_some_and(|o| matches!(o.value.as_deref(), Some("lavfi" | "rawvideo")));
+    if let (TranscodePipeline::Hardware(preset), true) = (&mut pipeline, is_raw) {
+      preset.upload = true;
+    }
+    self.args(pipeline.input_args());
+    self.input(input);
+    self.args(pipeline.output_args());
+    self.output(output)
+  }
+
   //// Audio option aliases
   //// https://ffmpeg.org/ffmpeg.html#Audio-Options
 
@@ -445,6 +1241,190 @@ impl FfmpegCommand {
     self
   }
 
+  /// Alias for `-map_metadata` argument. Must be used as an output option.
+  ///
+  /// Set the metadata of the next output file from `spec`, e.g. `1` to copy
+  /// the global metadata of input #1 (such as an `ffmetadata` file, see
+  /// [`crate::ffmetadata`]), or `-1` to strip all global metadata.
+  pub fn map_metadata<S: AsRef<str>>(&mut self, spec: S) -> &mut Self {
+    self.arg("-map_metadata");
+    self.arg(spec.as_ref());
+    self
+  }
+
+  /// Alias for `-map_chapters` argument. Must be used as an output option.
+  ///
+  /// Copy chapters from the input with index `input_index` to the next output
+  /// file. Pass `-1` to disable chapter copying.
+  pub fn map_chapters(&mut self, input_index: i32) -> &mut Self {
+    self.arg("-map_chapters");
+    self.arg(input_index.to_string());
+    self
+  }
+
+  /// Negative mapping: `-map -{spec}`. Removes streams matching `spec` from
+  /// the mappings added before it, e.g. `.map("0").unmap("0:s")` to copy
+  /// everything except subtitles.
+  pub fn unmap<S: AsRef<str>>(&mut self, spec: S) -> &mut Self {
+    self.map(format!("-{}", spec.as_ref()))
+  }
+
+  /// Optional mapping: `-map {spec}?`. The mapping is ignored instead of
+  /// failing if it matches no streams, e.g. to include audio only when the
+  /// input has an audio track.
+  pub fn map_optional<S: AsRef<str>>(&mut self, spec: S) -> &mut Self {
+    self.map(format!("{}?", spec.as_ref()))
+  }
+
+  /// Check every `-map` added so far against the streams of the inputs, as
+  /// parsed from a previous run (`FfmpegMetadata::input_streams`). Returns a
+  /// warning for each mapping that can't match anything. See
+  /// [`crate::map::validate_maps`].
+  pub fn validate_maps(&self, input_streams: &[Stream]) -> Vec<MapWarning> {
+    let args = self.arg_model();
+    let outputs = args.outputs.iter().flat_map(|output| &output.options);
+    let maps = outputs
+      .chain(&args.pending)
+      .filter(|option| option.flag == "-map")
+      .filter_map(|option| option.value.as_deref());
+    validate_maps(maps, input_streams)
+  }
+
+  /// Check the ranges selected by `-ss`, `-t` and `-to` so far against the
+  /// durations of the inputs, as parsed from a previous run
+  /// (`FfmpegMetadata::inputs`). Returns a warning for each range which
+  /// reaches past the end of its input, which FFmpeg silently truncates or
+  /// leaves empty. See [`crate::time_range::validate_time_ranges`].
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::{command::FfmpegCommand, event::FfmpegInput};
+  /// use std::time::Duration;
+  ///
+  /// let input = FfmpegInput {
+  ///   index: 0,
+  ///   duration: Some(30.0),
+  ///   start_time: Some(0.0),
+  ///   raw_log_message: String::new(),
+  /// };
+  /// let warnings = FfmpegCommand::new()
+  ///   .seek(Duration::from_secs(20))
+  ///   .duration("15")
+  ///   .input("clip.mp4")
+  ///   .output("out.mp4")
+  ///   .validate_time_ranges(&[input]);
+  /// assert_eq!(warnings.len(), 1);
+  /// ```
+  pub fn validate_time_ranges(&self, inputs: &[FfmpegInput]) -> Vec<TimeRangeWarning> {
+    let args = self.arg_model();
+    let durations: Vec<Option<f64>> = (0..args.inputs.len())
+      .map(|index| {
+        let input = inputs.iter().find(|input| input.index as usize == index);
+        input.and_then(|input| input.duration)
+      })
+      .collect();
+    validate_time_ranges(&args, &durations)
+  }
+
+  /// Like [`validate_time_ranges`](Self::validate_time_ranges), but probing
+  /// the duration of each input with ffprobe first.
+  pub fn validate_time_ranges_probed(&self) -> anyhow::Result<Vec<TimeRangeWarning>> {
+    let durations = self
+      .args
+      .inputs
+      .iter()
+      .map(|input| {
+        crate::ffprobe::ffprobe_duration(self.probe_url(input), input.option("-f"))
+          .with_context(|| format!("Can't probe the duration of {}", input.url))
+      })
+      .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(validate_time_ranges(&self.args, &durations))
+  }
+
+  /// The URL to give ffprobe for `input`: its path resolved against the
+  /// working directory if it's an existing file, and its URL as-is
+  /// otherwise. Sources like `-f lavfi -i testsrc` aren't files, despite
+  /// looking like relative paths.
+  fn probe_url(&self, input: &crate::args::FileArgs) -> std::ffi::OsString {
+    crate::paths::resolve_file_arg(&input.url, self.inner.get_current_dir())
+      .filter(|path| path.exists())
+      .map_or_else(|| input.url.clone().into(), |path| path.into_os_string())
+  }
+
+  /// Select the streams of the program with `program_id` from the last input
+  /// added, e.g. one channel of a DVB or ATSC transport stream. Equivalent to
+  /// `-map <input>:p:<program_id>`; see
+  /// [`ffprobe_programs`](crate::ffprobe::ffprobe_programs) for the IDs.
+  /// Fails if no input was added yet.
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::command::FfmpegCommand;
+  ///
+  /// let mut command = FfmpegCommand::new();
+  /// command
+  ///   .input("logo.png")
+  ///   .input("capture.ts")
+  ///   .select_program(2)?
+  ///   .codec_video("copy")
+  ///   .output("channel.ts");
+  /// let args: Vec<_> = command.get_args().collect();
+  /// assert_eq!(args[6..8], ["-map", "1:p:2"]);
+  /// assert!(FfmpegCommand::new().select_program(2).is_err());
+  /// # anyhow::Ok(())
+  /// ```
+  pub fn select_program(&mut self, program_id: u32) -> anyhow::Result<&mut Self> {
+    let input_index = self
+      .args
+      .inputs
+      .len()
+      .checked_sub(1)
+      .context("No input to select a program from")?;
+    Ok(self.map(format!("{input_index}:p:{program_id}")))
+  }
+
+  /// Like [`select_program`](Self::select_program), but probing the programs
+  /// of the last input with ffprobe first, opened with its format options.
+  /// Fails with a
+  /// [`ProgramNotFound`](crate::ffprobe::ProgramNotFound), listing the
+  /// programs there are, if it has none with `program_id`, rather than
+  /// leaving FFmpeg to fail with `Stream map '0:p:3' matches no streams`.
+  ///
+  /// ```rust,no_run
+  /// use ffmpeg_sidecar::{command::FfmpegCommand, ffprobe::ProgramNotFound};
+  ///
+  /// let mut command = FfmpegCommand::new();
+  /// command.input("capture.ts");
+  /// if let Err(e) = command.select_program_probed(3) {
+  ///   match e.downcast_ref::<ProgramNotFound>() {
+  ///     Some(not_found) => println!("Pick one of {} programs", not_found.available.len()),
+  ///     None => return Err(e),
+  ///   }
+  /// }
+  /// # anyhow::Ok(())
+  /// ```
+  pub fn select_program_probed(&mut self, program_id: u32) -> anyhow::Result<&mut Self> {
+    let input = self
+      .args
+      .inputs
+      .last()
+      .context("No input to select a program from")?;
+    let programs =
+      crate::ffprobe::ffprobe_input_programs(&self.probe_url(input), &input.probe_options())
+        .with_context(|| format!("Can't probe the programs of {}", input.url))?;
+    if !programs
+      .iter()
+      .any(|program| program.program_id == program_id)
+    {
+      return Err(
+        crate::ffprobe::ProgramNotFound {
+          requested: program_id,
+          available: programs,
+        }
+        .into(),
+      );
+    }
+    self.select_program(program_id)
+  }
+
   /// Alias for `-readrate` argument.
   ///
   /// Limit input read speed.
@@ -514,6 +1494,42 @@ impl FfmpegCommand {
     self
   }
 
+  /// Alias for `-bsf:v h264_mp4toannexb`.
+  ///
+  /// Convert an H.264 bitstream from length prefixed mode (as stored in MP4,
+  /// MKV or FLV) to start code prefixed Annex B mode, as required when
+  /// remuxing with `-c:v copy` into a raw `h264` or `mpegts` output.
+  ///
+  /// Note that repeated `-bsf:v` arguments override each other. To apply
+  /// several bitstream filters, pass a comma-separated list to
+  /// [`bitstream_filter_video`](Self::bitstream_filter_video) instead.
+  pub fn h264_mp4toannexb(&mut self) -> &mut Self {
+    self.bitstream_filter_video("h264_mp4toannexb")
+  }
+
+  /// Alias for `-bsf:v extract_extradata`.
+  ///
+  /// Extract the in-band codec configuration (SPS/PPS/VPS) of each packet and
+  /// export it as extradata. When combined with a raw Annex B output, the
+  /// parameter sets can be obtained from the first output chunk with
+  /// [`CodecConfig::from_annex_b`](crate::bitstream::CodecConfig::from_annex_b).
+  pub fn extract_extradata(&mut self) -> &mut Self {
+    self.bitstream_filter_video("extract_extradata")
+  }
+
+  /// Alias for `-bsf:v hevc_metadata=<options>`.
+  ///
+  /// Modify metadata embedded in an HEVC stream, e.g.
+  /// `colour_primaries=1:transfer_characteristics=1:matrix_coefficients=1` or
+  /// `aud=insert`. `options` is a `:`-separated list of `key=value` pairs; an
+  /// empty string applies the filter with its defaults.
+  pub fn hevc_metadata<S: AsRef<str>>(&mut self, options: S) -> &mut Self {
+    match options.as_ref() {
+      "" => self.bitstream_filter_video("hevc_metadata"),
+      options => self.bitstream_filter_video(format!("hevc_metadata={options}")),
+    }
+  }
+
   /// Alias for `-filter_complex` argument.
   ///
   /// Define a complex filtergraph, i.e. one with arbitrary number of inputs
@@ -539,6 +1555,58 @@ impl FfmpegCommand {
     self
   }
 
+  /// Alias for `-filter_complex_script` argument.
+  ///
+  /// Like [`filter_complex`](Self::filter_complex), but reading the
+  /// filtergraph from the file at `path`, e.g. one too long to pass as an
+  /// argument.
+  pub fn filter_complex_script<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+    self.arg("-filter_complex_script");
+    self.arg(file_arg(path.as_ref()));
+    self
+  }
+
+  /// Alias for `-filter_script` argument.
+  ///
+  /// Like [`filter`](Self::filter), but reading the filtergraph from the
+  /// file at `path`.
+  pub fn filter_script<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+    self.arg("-filter_script");
+    self.arg(file_arg(path.as_ref()));
+    self
+  }
+
+  /// Like [`filter_complex`](Self::filter_complex), but a filtergraph longer
+  /// than [`MAX_INLINE_FILTERGRAPH_LEN`] is written to a temporary script
+  /// instead, passed with `-filter_complex_script` and removed once FFmpeg
+  /// has closed stderr and both the spawned `FfmpegChild` and its iterator
+  /// are dropped. Generated graphs, like a `concat` of
+  /// hundreds of inputs, would otherwise exceed the operating system's limit
+  /// on the length of a command line and fail to spawn (`E2BIG`).
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::command::FfmpegCommand;
+  ///
+  /// let graph = (0..3000).map(|i| format!("[{i}:v]")).collect::<String>()
+  ///   + "concat=n=3000:v=1:a=0[v]";
+  /// let mut command = FfmpegCommand::new();
+  /// command.filter_complex_auto(&graph)?;
+  /// let args: Vec<_> = command.get_args().collect();
+  /// let flag = args.iter().position(|arg| *arg == "-filter_complex_script");
+  /// assert_eq!(std::fs::read_to_string(args[flag.unwrap() + 1])?, graph);
+  /// # anyhow::Ok(())
+  /// ```
+  pub fn filter_complex_auto<S: AsRef<str>>(&mut self, filtergraph: S) -> io::Result<&mut Self> {
+    let filtergraph = filtergraph.as_ref();
+    if filtergraph.len() <= MAX_INLINE_FILTERGRAPH_LEN {
+      return Ok(self.filter_complex(filtergraph));
+    }
+    let script = TempFile::create("filter_complex", "txt", filtergraph.as_bytes())?;
+    self.filter_complex_script(script.path());
+    self.config.filter_scripts.push(std::sync::Arc::new(script));
+    Ok(self)
+  }
+
   //// Preset argument sets for common use cases.
 
   /// Generate a procedural test video. Equivalent to `ffmpeg -f lavfi -i
@@ -552,6 +1620,116 @@ impl FfmpegCommand {
     self
   }
 
+  /// Generate SMPTE color bars. Equivalent to `ffmpeg -f lavfi -i
+  /// smptebars=duration=10`, which is `320x240` at `25` fps.
+  pub fn smptebars(&mut self) -> &mut Self {
+    self.lavfi_input("smptebars=duration=10")
+  }
+
+  /// Generate full-intensity EBU color bars. Equivalent to `ffmpeg -f lavfi -i
+  /// pal100bars=duration=10`, which is `320x240` at `25` fps.
+  pub fn colorbars(&mut self) -> &mut Self {
+    self.lavfi_input("pal100bars=duration=10")
+  }
+
+  /// Generate a zoom into the Mandelbrot set, which is expensive to encode
+  /// and useful for stress tests. Equivalent to `ffmpeg -f lavfi -t 10 -i
+  /// mandelbrot`, which is `640x480` at `25` fps.
+  pub fn mandelbrot(&mut self) -> &mut Self {
+    self.args(["-f", "lavfi", "-t", "10", "-i", "mandelbrot"]);
+    self
+  }
+
+  /// Generate a mono sine wave tone at `frequency` Hz. Equivalent to `ffmpeg
+  /// -f lavfi -i sine=frequency=<frequency>:duration=<duration>`, which is
+  /// sampled at `44100` Hz.
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::command::FfmpegCommand;
+  /// use std::time::Duration;
+  ///
+  /// let mut command = FfmpegCommand::new();
+  /// command.sine(440.0, Duration::from_millis(1500));
+  /// let args: Vec<_> = command.get_args().collect();
+  /// assert_eq!(args[2..], ["-f", "lavfi", "-i", "sine=frequency=440:duration=1.5"]);
+  /// ```
+  pub fn sine(&mut self, frequency: f64, duration: Duration) -> &mut Self {
+    let duration = duration.as_secs_f64();
+    self.lavfi_input(format!("sine=frequency={frequency}:duration={duration}"))
+  }
+
+  /// Generate white noise audio. Equivalent to `ffmpeg -f lavfi -i
+  /// anoisesrc=duration=10`, which is mono at `48000` Hz. The noise is seeded
+  /// randomly; use `anoisesrc=seed=<seed>` directly for reproducible audio.
+  pub fn anoisesrc(&mut self) -> &mut Self {
+    self.lavfi_input("anoisesrc=duration=10")
+  }
+
+  /// Generate reproducible random noise video, e.g. for byte-exact golden
+  /// tests. The same `seed` produces the same frames on every platform, since
+  /// the `noise` filter uses FFmpeg's own PRNG. The video is `yuv420p` at `25`
+  /// fps.
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::command::FfmpegCommand;
+  /// use std::time::Duration;
+  ///
+  /// let mut command = FfmpegCommand::new();
+  /// command.noise_source(42, (64, 48), Duration::from_secs(1));
+  /// let args: Vec<_> = command.get_args().collect();
+  /// assert_eq!(
+  ///   args[2..],
+  ///   [
+  ///     "-f",
+  ///     "lavfi",
+  ///     "-i",
+  ///     "color=c=gray:s=64x48:r=25:d=1,format=yuv420p,noise=alls=100:allf=t+u:all_seed=42"
+  ///   ]
+  /// );
+  /// ```
+  pub fn noise_source(&mut self, seed: u32, size: (u32, u32), duration: Duration) -> &mut Self {
+    let (width, height) = size;
+    let duration = duration.as_secs_f64();
+    self.lavfi_input(format!(
+      "color=c=gray:s={width}x{height}:r=25:d={duration},format=yuv420p,noise=alls=100:allf=t+u:all_seed={seed}"
+    ))
+  }
+
+  /// Generate a test video with a matching test tone, as two inputs: a
+  /// `testsrc` video (`320x240` at `25` fps) and a `1000` Hz `sine` audio
+  /// stream. Both streams are mapped to the next output.
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::command::FfmpegCommand;
+  /// use std::time::Duration;
+  ///
+  /// let mut command = FfmpegCommand::new();
+  /// command.test_av_source(Duration::from_secs(2));
+  /// let args: Vec<_> = command.get_args().collect();
+  /// assert_eq!(
+  ///   args[2..],
+  ///   [
+  ///     "-f", "lavfi", "-i", "testsrc=duration=2",
+  ///     "-f", "lavfi", "-i", "sine=frequency=1000:duration=2",
+  ///     "-map", "0:v", "-map", "1:a",
+  ///   ]
+  /// );
+  /// ```
+  pub fn test_av_source(&mut self, duration: Duration) -> &mut Self {
+    let duration = duration.as_secs_f64();
+    self.lavfi_input(format!("testsrc=duration={duration}"));
+    self.sine(1000.0, Duration::from_secs_f64(duration));
+    let first_input = self.arg_model().inputs.len() - 2;
+    self.map(format!("{first_input}:v"));
+    self.map(format!("{}:a", first_input + 1))
+  }
+
+  /// Add a `lavfi` virtual input from a source filter description.
+  fn lavfi_input<S: AsRef<str>>(&mut self, source: S) -> &mut Self {
+    self.args(["-f", "lavfi", "-i", source.as_ref()]);
+    self
+  }
+
   /// Preset for emitting raw decoded video frames on stdout. Equivalent to `-f
   /// rawvideo -pix_fmt rgb24 -`.
   pub fn rawvideo(&mut self) -> &mut Self {
@@ -559,6 +1737,181 @@ impl FfmpegCommand {
     self
   }
 
+  /// Add an output which writes to a named pipe, created when the command is
+  /// spawned and removed again when the child is reaped or dropped. `name` is
+  /// prefixed with `\\.\pipe\` on Windows if needed. Read the pipe with
+  /// [`FfmpegChild::pipes`].
+  ///
+  /// ```rust,no_run
+  /// use ffmpeg_sidecar::command::FfmpegCommand;
+  /// use std::io::Read;
+  ///
+  /// let mut child = FfmpegCommand::new()
+  ///   .testsrc()
+  ///   .map("0:v")
+  ///   .format("rawvideo")
+  ///   .named_pipe_output("video")
+  ///   .spawn()?;
+  /// let video = child.pipes().spawn_reader("video", |pipe| {
+  ///   let mut frames = Vec::new();
+  ///   pipe.read_to_end(&mut frames).map(|_| frames)
+  /// })?;
+  /// child.wait()?;
+  /// let frames = video.join().unwrap()?;
+  /// # anyhow::Ok(())
+  /// ```
+  #[cfg(feature = "named_pipes")]
+  #[cfg_attr(docsrs, doc(cfg(feature = "named_pipes")))]
+  pub fn named_pipe_output<S: AsRef<OsStr>>(&mut self, name: S) -> &mut Self {
+    let path = crate::named_pipes::pipe_path(name);
+    self.config.named_pipes.push(path.clone());
+    self.output(path)
+  }
+
+  /// Add an output which FFmpeg writes to a loopback TCP connection of its
+  /// own, read by the iterator on a separate thread and reported with
+  /// `label`. Unlike several outputs on stdout, which must be interleaved
+  /// frame by frame, pipes can have any frame rate, format and stream type.
+  ///
+  /// A single `rawvideo` stream is read as `OutputFrame`s with `label` as
+  /// their `output_tag`, a single raw PCM stream as `OutputAudioSamples`
+  /// with [`audio_samples`](Self::audio_samples), and anything else as
+  /// `FfmpegEvent::OutputPipeChunk`s. Precede this with the output's options,
+  /// as for [`output`](Self::output).
+  ///
+  /// ```rust,no_run
+  /// use ffmpeg_sidecar::{command::FfmpegCommand, event::FfmpegEvent};
+  ///
+  /// let iter = FfmpegCommand::new()
+  ///   .input("input.mp4")
+  ///   .args(["-map", "0:v", "-f", "rawvideo", "-pix_fmt", "rgb24", "-r", "30"])
+  ///   .output_pipe("video")
+  ///   .args(["-map", "0:v", "-f", "rawvideo", "-pix_fmt", "gray", "-s", "160x90", "-r", "1"])
+  ///   .output_pipe("thumbnails")
+  ///   .args(["-map", "0:a", "-f", "s16le"])
+  ///   .output_pipe("audio")
+  ///   .audio_samples()
+  ///   .spawn()?
+  ///   .iter()?;
+  /// for event in iter {
+  ///   match event {
+  ///     FfmpegEvent::OutputFrame(frame) => println!("{:?}: {}", frame.output_tag, frame.frame_num),
+  ///     FfmpegEvent::OutputAudioSamples(samples) => println!("audio: {}", samples.frames()),
+  ///     _ => {}
+  ///   }
+  /// }
+  /// # anyhow::Ok(())
+  /// ```
+  pub fn output_pipe<S: Into<String>>(&mut self, label: S) -> &mut Self {
+    let listener = match TcpOutput::bind() {
+      Ok(listener) => listener,
+      Err(e) => {
+        self.config.output_pipe_error.get_or_insert(e.to_string());
+        return self;
+      }
+    };
+    let url = listener.url().to_string();
+    self
+      .config
+      .output_pipes
+      .push(crate::output_pipe::OutputPipe {
+        label: label.into(),
+        output_index: self.arg_model().outputs.len() as u32,
+        listener: std::sync::Arc::new(listener),
+      });
+    self.output(url)
+  }
+
+  /// Add a single `tee` output which muxes the encoded streams to every
+  /// destination, so they are only encoded once. Equivalent to `-f tee
+  /// "[f=flv]rtmp://...|[f=mp4]file.mp4"`, with escaping handled by
+  /// [`tee_arg`](crate::tee::tee_arg).
+  ///
+  /// The tee muxer doesn't select streams by itself, so precede this with
+  /// `-map` for each stream to include.
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::{command::FfmpegCommand, tee::{TeeDest, TeeOnFail}};
+  ///
+  /// let mut command = FfmpegCommand::new();
+  /// command
+  ///   .input("in.mp4")
+  ///   .map("0")
+  ///   .codec_video("libx264")
+  ///   .tee_outputs(&[
+  ///     TeeDest::new("rtmp://cdn.example.com/live/key")
+  ///       .format("flv")
+  ///       .on_fail(TeeOnFail::Ignore),
+  ///     TeeDest::new("archive.mp4"),
+  ///   ]);
+  /// let args: Vec<_> = command.get_args().collect();
+  /// assert_eq!(
+  ///   args[args.len() - 3..],
+  ///   ["-f", "tee", "[f=flv:onfail=ignore]rtmp://cdn.example.com/live/key|archive.mp4"]
+  /// );
+  /// ```
+  pub fn tee_outputs(&mut self, destinations: &[crate::tee::TeeDest]) -> &mut Self {
+    self.format("tee");
+    self.output(crate::tee::tee_arg(destinations))
+  }
+
+  /// Add an output for each of `outputs`, all scaled from a single decode of
+  /// the first input's video with a generated `split`/`scale` filtergraph.
+  /// Frames read from stdout carry the tag of their output in
+  /// `OutputVideoFrame::output_tag`. Fails if `outputs` is empty.
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::{command::FfmpegCommand, scaled_outputs::ScaledOutput};
+  ///
+  /// let mut command = FfmpegCommand::new();
+  /// command.input("camera.mp4").scaled_outputs(&[
+  ///   ScaledOutput::new("full", 1920, 1080),
+  ///   ScaledOutput::new("thumb", 224, 224).pix_fmt("gray"),
+  /// ])?;
+  /// let args: Vec<_> = command.get_args().collect();
+  /// assert_eq!(
+  ///   args[6..],
+  ///   [
+  ///     "-map", "[scaled0]", "-f", "rawvideo", "-pix_fmt", "rgb24", "-",
+  ///     "-map", "[scaled1]", "-f", "rawvideo", "-pix_fmt", "gray", "-",
+  ///   ]
+  /// );
+  /// assert!(FfmpegCommand::new().scaled_outputs(&[]).is_err());
+  /// # anyhow::Ok(())
+  /// ```
+  ///
+  /// Several outputs on stdout are interleaved frame by frame, which the
+  /// iterator supports since `split` keeps their frame rates equal.
+  pub fn scaled_outputs(
+    &mut self,
+    outputs: &[crate::scaled_outputs::ScaledOutput],
+  ) -> anyhow::Result<&mut Self> {
+    use crate::scaled_outputs::{scaled_outputs_filter, ScaledOutput};
+
+    anyhow::ensure!(!outputs.is_empty(), "No outputs to scale");
+    self.filter_complex(scaled_outputs_filter("0:v", outputs));
+    for (i, output) in outputs.iter().enumerate() {
+      let index = self.arg_model().outputs.len() as u32;
+      self.config.output_tags.insert(index, output.tag.clone());
+      self.map(ScaledOutput::label(i));
+      self.args(["-f", "rawvideo", "-pix_fmt", &output.pix_fmt]);
+      self.output(&output.url);
+    }
+    Ok(self)
+  }
+
+  /// Add an output which writes to the shared-memory file of `reader`, to be
+  /// consumed frame by frame with [`ShmFrameReader::next_frame`]. Implies
+  /// [`overwrite`](Self::overwrite), since the reader creates the file first.
+  ///
+  /// [`ShmFrameReader::next_frame`]: crate::shm_transport::ShmFrameReader::next_frame
+  #[cfg(target_os = "linux")]
+  #[cfg_attr(docsrs, doc(cfg(target_os = "linux")))]
+  pub fn shm_output(&mut self, reader: &crate::shm_transport::ShmFrameReader) -> &mut Self {
+    self.overwrite();
+    self.output(reader.path())
+  }
+
   /// Configure the ffmpeg command to produce output on stdout.
   ///
   /// Synchronizes two changes:
@@ -567,6 +1920,336 @@ impl FfmpegCommand {
   pub fn pipe_stdout(&mut self) -> &mut Self {
     self.arg("-");
     self.inner.stdout(Stdio::piped());
+    self
+      .config
+      .redirected_stdio
+      .retain(|&stream| stream != "stdout");
+    self
+  }
+
+  /// Connect FFmpeg's stdin to a pipe (the default), to nothing, or to this
+  /// process' own stdin. Without a pipe, `FfmpegChild::take_stdin` returns
+  /// `None`, and interactive commands like `FfmpegChild::quit` fail.
+  ///
+  /// ```rust,no_run
+  /// use ffmpeg_sidecar::command::{FfmpegCommand, StdinMode};
+  ///
+  /// // Press `q` in the terminal to stop early
+  /// FfmpegCommand::new()
+  ///   .stdin_mode(StdinMode::Inherit)
+  ///   .input("input.mp4")
+  ///   .output("output.mkv")
+  ///   .spawn()?
+  ///   .wait()?;
+  /// # anyhow::Ok(())
+  /// ```
+  pub fn stdin_mode(&mut self, mode: StdinMode) -> &mut Self {
+    self.inner.stdin(mode.stdio());
+    self.config.stdin_mode = mode;
+    self
+      .config
+      .redirected_stdio
+      .retain(|&stream| stream != "stdin");
+    self
+  }
+
+  /// Add an input of raw video frames in the format of `pump`, written to
+  /// stdin with [`FfmpegChild::frame_writer`]. Equivalent to
+  /// [`InputFramePump::configure`], along with a piped stdin.
+  ///
+  /// ```rust,no_run
+  /// use ffmpeg_sidecar::{command::FfmpegCommand, frame_pump::InputFramePump};
+  ///
+  /// let pump = InputFramePump {
+  ///   pix_fmt: "rgb24".to_string(),
+  ///   width: 320,
+  ///   height: 240,
+  ///   fps: 30.0,
+  /// };
+  /// let mut child = FfmpegCommand::new()
+  ///   .frame_input(&pump)
+  ///   .codec_video("libx264")
+  ///   .output("output.mp4")
+  ///   .spawn()?;
+  /// let mut writer = child.frame_writer()?;
+  /// let frames = std::thread::spawn(move || {
+  ///   for i in 0..90u32 {
+  ///     writer.write_raw(&vec![(i * 2) as u8; 320 * 240 * 3])?;
+  ///   }
+  ///   writer.finish()
+  /// });
+  /// child.iter()?.collect_errors()?;
+  /// println!("{} frames", frames.join().unwrap()?);
+  /// # anyhow::Ok(())
+  /// ```
+  ///
+  /// [`FfmpegChild::frame_writer`]: crate::child::FfmpegChild::frame_writer
+  /// [`InputFramePump::configure`]: crate::frame_pump::InputFramePump::configure
+  pub fn frame_input(&mut self, pump: &crate::frame_pump::InputFramePump) -> &mut Self {
+    self.stdin_mode(StdinMode::Piped);
+    self.config.frame_input = Some(pump.clone());
+    pump.configure(self)
+  }
+
+  /// Add an input read from stdin, which is connected directly to `source`
+  /// instead of a pipe written by this process. Any `Into<Stdio>` works,
+  /// such as a `File`, a `ChildStdout` or an `OwnedFd`/`OwnedHandle`.
+  /// Equivalent to `-i pipe:0`, so it must come after the input options.
+  ///
+  /// `FfmpegChild::take_stdin` returns `None` for the spawned child, and
+  /// interactive commands like `FfmpegChild::quit` are unavailable.
+  pub fn input_from_stdio<S: Into<Stdio>>(&mut self, source: S) -> &mut Self {
+    self.inner.stdin(source.into());
+    self.config.redirected_stdio.push("stdin");
+    self.input("pipe:0")
+  }
+
+  /// Add an input from the stdout of another process, such as `yt-dlp -o -`
+  /// or `curl`. The pipe is handed straight to FFmpeg, so no data passes
+  /// through this process. See [`input_from_stdio`](Self::input_from_stdio).
+  ///
+  /// ```rust,no_run
+  /// use ffmpeg_sidecar::command::FfmpegCommand;
+  /// use std::process::{Command, Stdio};
+  ///
+  /// let mut curl = Command::new("curl")
+  ///   .args(["-sL", "https://example.com/video.mp4"])
+  ///   .stdout(Stdio::piped())
+  ///   .spawn()?;
+  /// FfmpegCommand::new()
+  ///   .input_from_child_stdout(&mut curl)
+  ///   .output("video.mkv")
+  ///   .spawn()?
+  ///   .wait()?;
+  /// curl.wait()?;
+  /// # anyhow::Ok(())
+  /// ```
+  ///
+  /// # Panics
+  ///
+  /// If `child` wasn't spawned with `Stdio::piped()` stdout, or its stdout
+  /// has already been taken.
+  pub fn input_from_child_stdout(&mut self, child: &mut std::process::Child) -> &mut Self {
+    let stdout = child
+      .stdout
+      .take()
+      .expect("child stdout must be piped and not yet taken");
+    self.input_from_stdio(stdout)
+  }
+
+  /// Add an output written to stdout, which is connected directly to `target`
+  /// instead of a pipe read by this process. Equivalent to `pipe:1`, so the
+  /// format must be set explicitly with [`format`](Self::format).
+  ///
+  /// The iterator still reports progress and logs, but no `OutputFrame` or
+  /// `OutputChunk` events.
+  pub fn output_to_stdio<S: Into<Stdio>>(&mut self, target: S) -> &mut Self {
+    self.inner.stdout(target.into());
+    self.config.redirected_stdio.push("stdout");
+    self.output("pipe:1")
+  }
+
+  /// Write the output straight into an open file, e.g. one created with
+  /// special permissions or already unlinked. See
+  /// [`output_to_stdio`](Self::output_to_stdio).
+  ///
+  /// ```rust,no_run
+  /// use ffmpeg_sidecar::command::FfmpegCommand;
+  /// use std::fs::File;
+  ///
+  /// FfmpegCommand::new()
+  ///   .testsrc()
+  ///   .codec_video("libx264")
+  ///   .format("mpegts")
+  ///   .output_to_file(File::create("out.ts")?)
+  ///   .spawn()?
+  ///   .wait()?;
+  /// # anyhow::Ok(())
+  /// ```
+  pub fn output_to_file(&mut self, file: std::fs::File) -> &mut Self {
+    self.output_to_stdio(file)
+  }
+
+  /// Write the output straight to a file descriptor, such as a connected
+  /// socket. See [`output_to_stdio`](Self::output_to_stdio).
+  #[cfg(unix)]
+  #[cfg_attr(docsrs, doc(cfg(unix)))]
+  pub fn output_to_fd(&mut self, fd: std::os::fd::OwnedFd) -> &mut Self {
+    self.output_to_stdio(fd)
+  }
+
+  /// Write the output straight to a handle, such as a pipe or file. See
+  /// [`output_to_stdio`](Self::output_to_stdio).
+  #[cfg(windows)]
+  #[cfg_attr(docsrs, doc(cfg(windows)))]
+  pub fn output_to_fd(&mut self, handle: std::os::windows::io::OwnedHandle) -> &mut Self {
+    self.output_to_stdio(handle)
+  }
+
+  /// Set the size in bytes of the buffer used to read `OutputChunk`s from
+  /// stdout, when frame boundaries are unknown (e.g. encoded or container
+  /// output). Defaults to [`DEFAULT_CHUNK_SIZE`](crate::iter::DEFAULT_CHUNK_SIZE).
+  ///
+  /// Larger chunks reduce per-event overhead for high-bitrate streams, while
+  /// smaller chunks reduce latency. Chunks may always be smaller than this
+  /// size, depending on how much data is available from each read. To avoid
+  /// allocating a new buffer for every chunk, return consumed chunks to the
+  /// iterator's [`ChunkPool`](crate::iter::ChunkPool).
+  pub fn stdout_chunk_size(&mut self, bytes: usize) -> &mut Self {
+    self.config.stdout_chunk_size = Some(bytes.max(1));
+    self
+  }
+
+  /// Decode raw PCM audio on stdout (e.g. `-f s16le -` or `-f f32le -`) into
+  /// `FfmpegEvent::OutputAudioSamples`, carrying the sample rate, channel
+  /// count and typed samples, instead of emitting `OutputChunk`s. Applies
+  /// when stdout carries exactly one audio stream in a supported sample
+  /// format; see [`SampleFormat`](crate::audio_levels::SampleFormat).
+  ///
+  /// ```rust,no_run
+  /// use ffmpeg_sidecar::command::FfmpegCommand;
+  ///
+  /// let iter = FfmpegCommand::new()
+  ///   .input("input.mp3")
+  ///   .args(["-f", "f32le", "-"])
+  ///   .audio_samples()
+  ///   .spawn()?
+  ///   .iter()?;
+  /// for block in iter.filter_audio() {
+  ///   println!("{} frames at {}s", block.frames(), block.timestamp);
+  /// }
+  /// # anyhow::Ok(())
+  /// ```
+  pub fn audio_samples(&mut self) -> &mut Self {
+    self.config.audio_samples = true;
+    self
+  }
+
+  /// Run `extractor` on every log line, emitting an `FfmpegEvent::Extracted`
+  /// for each line it matches. Useful for filters which print their results
+  /// to the log, such as `ametadata=print`:
+  ///
+  /// ```rust,no_run
+  /// use ffmpeg_sidecar::{command::FfmpegCommand, event::FfmpegEvent, extract::LogExtractor};
+  ///
+  /// let iter = FfmpegCommand::new()
+  ///   .input("speech.wav")
+  ///   .args(["-af", "ebur128=metadata=1,ametadata=print"])
+  ///   .format("null")
+  ///   .output("-")
+  ///   .extract(LogExtractor::key_value("loudness", "lavfi.r128.M"))
+  ///   .spawn()?
+  ///   .iter()?;
+  /// for event in iter {
+  ///   if let FfmpegEvent::Extracted(loudness) = event {
+  ///     println!("{:?} LUFS", loudness.parse::<f32>("lavfi.r128.M"));
+  ///   }
+  /// }
+  /// # anyhow::Ok(())
+  /// ```
+  pub fn extract(&mut self, extractor: crate::extract::LogExtractor) -> &mut Self {
+    self.config.extractors.push(extractor);
+    self
+  }
+
+  /// Emit an `FfmpegEvent::ErrorBlock` for every run of consecutive error
+  /// lines from the same component, so that an error dialog can show the
+  /// whole message rather than its last fragment. The individual
+  /// `FfmpegEvent::Log` events are still emitted as usual.
+  ///
+  /// ```rust,no_run
+  /// use ffmpeg_sidecar::{command::FfmpegCommand, event::FfmpegEvent};
+  ///
+  /// let iter = FfmpegCommand::new()
+  ///   .testsrc()
+  ///   .codec_video("rawvideo")
+  ///   .output("output.mp4")
+  ///   .error_blocks()
+  ///   .spawn()?
+  ///   .iter()?;
+  /// for event in iter {
+  ///   if let FfmpegEvent::ErrorBlock { component, lines } = event {
+  ///     eprintln!("{}: {}", component.unwrap_or_default(), lines.join("\n"));
+  ///   }
+  /// }
+  /// # anyhow::Ok(())
+  /// ```
+  pub fn error_blocks(&mut self) -> &mut Self {
+    self.config.error_blocks = true;
+    self
+  }
+
+  /// Emit an `FfmpegEvent::RawLogLine` with the original bytes of every log
+  /// line which isn't valid UTF-8, after the event parsed from its lossily
+  /// decoded text, e.g. to decode metadata tags written in another encoding.
+  ///
+  /// ```rust,no_run
+  /// use ffmpeg_sidecar::{command::FfmpegCommand, event::FfmpegEvent};
+  ///
+  /// let iter = FfmpegCommand::new()
+  ///   .input("input.mp4")
+  ///   .output("output.mp4")
+  ///   .raw_log_lines()
+  ///   .spawn()?
+  ///   .iter()?;
+  /// for event in iter {
+  ///   if let FfmpegEvent::RawLogLine(bytes) = event {
+  ///     eprintln!("Latin-1 line: {}", bytes.iter().map(|&b| b as char).collect::<String>());
+  ///   }
+  /// }
+  /// # anyhow::Ok(())
+  /// ```
+  pub fn raw_log_lines(&mut self) -> &mut Self {
+    self.config.raw_log_lines = true;
+    self
+  }
+
+  /// Hint that the command may legitimately produce no output at all, e.g.
+  /// `-frames:v 0`, `-t 0` or a `-f null` output used for analysis. The
+  /// iterator then ends with `FfmpegEvent::Done` instead of reporting "No
+  /// output streams found" when FFmpeg exits before describing any output
+  /// streams, or an error when nothing is written to stdout.
+  ///
+  /// ```rust,no_run
+  /// use ffmpeg_sidecar::{command::FfmpegCommand, event::FfmpegEvent};
+  ///
+  /// let done = FfmpegCommand::new()
+  ///   .testsrc()
+  ///   .format("null")
+  ///   .output("-")
+  ///   .expect_no_output()
+  ///   .spawn()?
+  ///   .iter()?
+  ///   .any(|event| matches!(event, FfmpegEvent::Done));
+  /// assert!(done);
+  /// # anyhow::Ok(())
+  /// ```
+  pub fn expect_no_output(&mut self) -> &mut Self {
+    self.config.expect_no_output = true;
+    self
+  }
+
+  /// Poll the CPU and memory usage of the spawned FFmpeg process every
+  /// `interval`, emitting `FfmpegEvent::ResourceUsage` events from the
+  /// iterator. Useful for correlating dips in encoding speed with resource
+  /// saturation.
+  ///
+  /// Supported on Linux (via `/proc`), MacOS (via `proc_pidinfo`) and
+  /// Windows (via `GetProcessTimes`). On other platforms, a single
+  /// `FfmpegEvent::Error` is emitted instead.
+  pub fn sample_resource_usage(&mut self, interval: Duration) -> &mut Self {
+    self.config.resource_sample_interval = Some(interval);
+    self
+  }
+
+  /// Measure the rate of data read from stdout, emitting
+  /// `FfmpegEvent::Throughput` at most once per `interval`. Events are only
+  /// emitted as data arrives, so the first one after a stall averages over
+  /// the whole gap. The running total is also available from
+  /// [`FfmpegIterator::bytes_read`](crate::iter::FfmpegIterator::bytes_read)
+  /// without enabling this.
+  pub fn sample_throughput(&mut self, interval: Duration) -> &mut Self {
+    self.config.throughput_interval = Some(interval);
     self
   }
 
@@ -595,6 +2278,8 @@ impl FfmpegCommand {
   ///
   /// Identical to `arg` in [`std::process::Command`].
   pub fn arg<S: AsRef<OsStr>>(&mut self, arg: S) -> &mut Self {
+    self.sync_arg_model();
+    self.args.push(&arg.as_ref().to_string_lossy());
     self.inner.arg(arg.as_ref());
     self
   }
@@ -620,6 +2305,60 @@ impl FfmpegCommand {
     self.inner.get_args()
   }
 
+  /// The arguments grouped into global options, inputs, and outputs,
+  /// including those added directly to the inner `Command` via
+  /// [`FfmpegCommand::as_inner_mut`].
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::command::FfmpegCommand;
+  ///
+  /// let mut command = FfmpegCommand::new();
+  /// command.testsrc().rawvideo();
+  /// let model = command.get_arg_model();
+  /// assert_eq!(model.inputs[0].option("-f"), Some("lavfi"));
+  /// assert_eq!(model.outputs[0].option("-pix_fmt"), Some("rgb24"));
+  /// ```
+  pub fn get_arg_model(&self) -> Cow<'_, ArgModel> {
+    self.arg_model()
+  }
+
+  /// The arguments grouped by `args`, parsed again if the inner `Command`
+  /// may have been edited since.
+  fn arg_model(&self) -> Cow<'_, ArgModel> {
+    match self.raw_edits {
+      true => Cow::Owned(self.args.reparse(self.raw_args())),
+      false => Cow::Borrowed(&self.args),
+    }
+  }
+
+  /// Catch `args` up with any edits made to the inner `Command`.
+  fn sync_arg_model(&mut self) {
+    if self.raw_edits {
+      self.args = self.args.reparse(self.raw_args());
+      self.raw_edits = false;
+    }
+  }
+
+  fn raw_args(&self) -> impl Iterator<Item = Cow<'_, str>> {
+    self.inner.get_args().map(|arg| arg.to_string_lossy())
+  }
+
+  /// Append an input-only flag, which must be followed by an `-i`.
+  fn input_option(&mut self, flag: &str) -> &mut Self {
+    self.arg(flag);
+    self.args.mark_input_only();
+    self
+  }
+
+  /// Append an output-only flag, recording an error if no input precedes it.
+  fn output_option(&mut self, flag: &str) -> &mut Self {
+    if !self.get_args().any(|arg| arg == "-i") {
+      let error = ArgPlacementError::OutputOptionBeforeInput(flag.to_string());
+      self.config.placement_errors.push(error);
+    }
+    self.arg(flag)
+  }
+
   /// Appends `-n` (no overwrite) to the args list if needed.
   /// The interactive "Would you like to overwrite?" prompt is problematic,
   /// since it won't be parsed by the log parser and the process will appear
@@ -632,6 +2371,33 @@ impl FfmpegCommand {
     self
   }
 
+  /// Record whether the arguments lower the log level below `info` or
+  /// disable progress updates, which the iterator can't wait for.
+  fn detect_verbosity(&mut self) {
+    let mut log_level = LOG_LEVEL_INFO;
+    let mut stats = true;
+    let args: Vec<String> = self
+      .get_args()
+      .map(|arg| arg.to_string_lossy().into_owned())
+      .collect();
+    let mut args = args.iter().map(String::as_str);
+    while let Some(arg) = args.next() {
+      match arg {
+        "-loglevel" | "-v" => {
+          if let Some(level) = args.next().and_then(parse_log_level) {
+            log_level = level;
+          }
+        }
+        "-stats" => stats = true,
+        "-nostats" => stats = false,
+        _ => {}
+      }
+    }
+    self.config.quiet = log_level < LOG_LEVEL_INFO;
+    let progress_reports = self.config.progress_over_tcp || self.config.structured_progress;
+    self.config.no_stats = (self.config.quiet || !stats) && !progress_reports;
+  }
+
   /// Spawn the ffmpeg command as a child process, wrapping it in a
   /// `FfmpegChild` interface.
   ///
@@ -641,8 +2407,76 @@ impl FfmpegCommand {
   ///
   /// Identical to `spawn` in [`std::process::Command`].
   pub fn spawn(&mut self) -> io::Result<FfmpegChild> {
+    self.sync_arg_model();
+    let dangling = self.args.misplaced_input_option();
+    let dangling = dangling.map(|arg| ArgPlacementError::InputOptionWithoutInput(arg.flag.clone()));
+    if let Some(error) = self.config.placement_errors.first().cloned().or(dangling) {
+      return Err(io::Error::new(io::ErrorKind::InvalidInput, error));
+    }
+    if let Some(error) = &self.config.output_pipe_error {
+      return Err(io::Error::new(
+        io::ErrorKind::Other,
+        format!("Can't listen for an output pipe: {error}"),
+      ));
+    }
+    if let Some((index, _)) = self.config.pending_input_offsets.first_key_value() {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("`sync_inputs` offset for input #{index} was never applied; it must be set before the input is added"),
+      ));
+    }
     self.prevent_overwrite_prompt();
-    self.inner.spawn().map(FfmpegChild::from_inner)
+    self.detect_verbosity();
+    let progress_output = match self.config.progress_over_tcp {
+      true => {
+        let output = TcpOutput::bind()?;
+        self.args(["-nostats", "-progress", output.url()]);
+        Some(output)
+      }
+      false => None,
+    };
+    if self.config.structured_progress && progress_output.is_none() {
+      self.args(["-nostats", "-progress", "pipe:2"]);
+    }
+    // Created first so that FFmpeg can open them, and removed again on drop if
+    // spawning fails
+    #[cfg(feature = "named_pipes")]
+    let pipes = crate::named_pipes::ManagedPipes::create_all(&self.config.named_pipes)
+      .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    self.config.input_args.clone_from(&self.args.inputs);
+    self.config.output_args.clone_from(&self.args.outputs);
+    self.config.ffmpeg_path = Some(self.inner.get_program().to_owned());
+    let config = self.config.clone();
+    let child = self
+      .inner
+      .spawn()
+      .map(|inner| FfmpegChild::from_inner(inner, config))?
+      .with_progress_output(progress_output);
+    #[cfg(feature = "named_pipes")]
+    let child = child.with_pipes(pipes);
+    Ok(child)
+  }
+
+  /// Check the accumulated arguments for well-known ordering mistakes, such as
+  /// output options before any input, input-only options after the last
+  /// input, an output `-ss` combined with stream copy, a video filter
+  /// replaced by another for the same output, or a codec which its output's
+  /// container can't store, including those FFmpeg has rejected from this
+  /// command's binary before. Returns an empty `Vec` if no problems were
+  /// found. See [`crate::lint`].
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::command::FfmpegCommand;
+  ///
+  /// let warnings = FfmpegCommand::new()
+  ///   .input("input.mp4")
+  ///   .itsoffset("1.5")
+  ///   .output("output.mp4")
+  ///   .lint();
+  /// assert_eq!(warnings.len(), 1);
+  /// ```
+  pub fn lint(&self) -> Vec<LintWarning> {
+    lint_model_with(&self.arg_model(), Some(self.inner.get_program()))
   }
 
   /// Print a command that can be copy-pasted to run in the terminal. Requires
@@ -687,6 +2521,131 @@ impl FfmpegCommand {
     self
   }
 
+  //// Process environment
+
+  /// Set the working directory of the FFmpeg process. Relative input and
+  /// output paths are resolved against it.
+  ///
+  /// Identical to `current_dir` in [`std::process::Command`]. A relative
+  /// `dir` is resolved when FFmpeg is spawned; see [`Self::workdir`].
+  pub fn current_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut Self {
+    self.inner.current_dir(dir);
+    self
+  }
+
+  /// Run FFmpeg in `dir`, so that relative input and output paths are
+  /// resolved against it rather than the working directory of the whole
+  /// process, which is shared by every thread of a service. Unlike
+  /// [`Self::current_dir`], a relative `dir` is made absolute right away.
+  ///
+  /// Checks made on the Rust side, such as
+  /// [`validate_time_ranges_probed`](Self::validate_time_ranges_probed) and
+  /// [`output_files`](Self::output_files), resolve paths the same way.
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::command::FfmpegCommand;
+  /// use std::path::Path;
+  ///
+  /// let mut command = FfmpegCommand::new();
+  /// command
+  ///   .workdir("/srv/jobs/1234")
+  ///   .input("upload.mov")
+  ///   .output("renditions/720p.mp4");
+  /// assert_eq!(
+  ///   command.output_files(),
+  ///   vec![Path::new("/srv/jobs/1234/renditions/720p.mp4")]
+  /// );
+  /// ```
+  pub fn workdir<P: AsRef<Path>>(&mut self, dir: P) -> &mut Self {
+    let dir = dir.as_ref();
+    match std::path::absolute(dir) {
+      Ok(dir) => self.inner.current_dir(dir),
+      Err(_) => self.inner.current_dir(dir),
+    };
+    self
+  }
+
+  /// `path` as FFmpeg will see it: relative paths are joined to the
+  /// directory set with [`Self::workdir`] or [`Self::current_dir`], if any.
+  pub fn resolve_path<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+    match self.inner.get_current_dir() {
+      Some(dir) if path.as_ref().is_relative() => dir.join(path),
+      _ => path.as_ref().to_path_buf(),
+    }
+  }
+
+  /// The local files written by the outputs, resolved like
+  /// [`resolve_path`](Self::resolve_path). Standard output, URLs, and the
+  /// `null` and `tee` muxers are skipped.
+  pub fn output_files(&self) -> Vec<PathBuf> {
+    let workdir = self.inner.get_current_dir();
+    self
+      .args
+      .outputs
+      .iter()
+      .filter(|output| !matches!(output.option("-f"), Some("null" | "tee")))
+      .filter_map(|output| crate::paths::resolve_file_arg(&output.url, workdir))
+      .collect()
+  }
+
+  /// Start the FFmpeg process with an empty environment, e.g. to keep secrets
+  /// in the parent's environment away from untrusted jobs. Variables added
+  /// with [`Self::env`] are still passed.
+  ///
+  /// If the binary was located through `PATH` (the default), pass an absolute
+  /// path to [`Self::new_with_path`] or re-add `PATH` with [`Self::env`].
+  ///
+  /// Identical to `env_clear` in [`std::process::Command`].
+  pub fn env_clear(&mut self) -> &mut Self {
+    self.inner.env_clear();
+    self.config.env_clear = true;
+    self
+  }
+
+  /// Set an environment variable for the FFmpeg process, e.g.
+  /// `FONTCONFIG_FILE` or `CUDA_VISIBLE_DEVICES`.
+  ///
+  /// Identical to `env` in [`std::process::Command`].
+  pub fn env<K: AsRef<OsStr>, V: AsRef<OsStr>>(&mut self, key: K, value: V) -> &mut Self {
+    self.inner.env(key, value);
+    self
+  }
+
+  /// Remove an environment variable inherited from the parent process.
+  ///
+  /// Identical to `env_remove` in [`std::process::Command`].
+  pub fn env_remove<K: AsRef<OsStr>>(&mut self, key: K) -> &mut Self {
+    self.inner.env_remove(key);
+    self
+  }
+
+  /// Constrain the CPU time, memory and output file size of the FFmpeg
+  /// process with `setrlimit`, applied in the child before FFmpeg starts.
+  /// Can be called more than once; every set of limits is applied in order.
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::{command::FfmpegCommand, resource_limits::ResourceLimits};
+  /// use std::time::Duration;
+  ///
+  /// FfmpegCommand::new()
+  ///   .env_clear()
+  ///   .current_dir("/srv/jobs/1234")
+  ///   .resource_limits(
+  ///     ResourceLimits::default()
+  ///       .cpu_time(Duration::from_secs(300))
+  ///       .file_size(512 * 1024 * 1024),
+  ///   )
+  ///   .input("upload.mov")
+  ///   .output("output.mp4");
+  /// ```
+  #[cfg(unix)]
+  #[cfg_attr(docsrs, doc(cfg(unix)))]
+  pub fn resource_limits(&mut self, limits: ResourceLimits) -> &mut Self {
+    limits.apply(&mut self.inner);
+    self.config.resource_limits.push(limits);
+    self
+  }
+
   //// Constructors
   pub fn new() -> Self {
     Self::new_with_path(ffmpeg_path())
@@ -700,12 +2659,94 @@ impl FfmpegCommand {
     inner.stdout(Stdio::piped());
 
     // Configure `FfmpegCommand`
-    let mut ffmpeg_command = Self { inner };
+    let mut ffmpeg_command = Self {
+      inner,
+      args: ArgModel::new(),
+      raw_edits: false,
+      config: CommandConfig::default(),
+    };
     ffmpeg_command.set_expected_loglevel();
     ffmpeg_command.create_no_window();
     ffmpeg_command
   }
 
+  /// Build a command from a declarative [`JobSpec`], e.g. one deserialized
+  /// from a JSON or TOML job description. Fails if the spec is missing inputs
+  /// or outputs.
+  #[cfg(feature = "serde")]
+  #[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+  pub fn from_job_spec(spec: &JobSpec) -> anyhow::Result<Self> {
+    let mut command = Self::new();
+    spec.apply(&mut command)?;
+    Ok(command)
+  }
+
+  /// Like [`from_job_spec`](Self::from_job_spec), but running FFmpeg in
+  /// `workdir` (see [`workdir`](Self::workdir)). Fails if a path of the spec
+  /// is absolute, leaves `workdir` with `..`, uses any protocol, or a format
+  /// which opens other files, like `tee` (see [`JobSpec::validate_relative`]),
+  /// so that jobs from different sources can't read or overwrite each other's
+  /// files, or make requests to other hosts.
+  #[cfg(feature = "serde")]
+  #[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+  pub fn from_job_spec_in<P: AsRef<Path>>(spec: &JobSpec, workdir: P) -> anyhow::Result<Self> {
+    Self::from_job_spec_in_allowing(spec, workdir, &[])
+  }
+
+  /// Like [`from_job_spec_in`](Self::from_job_spec_in), but also allowing
+  /// network URLs whose protocol is one of `protocols`, e.g. `["rtmp"]`. See
+  /// [`JobSpec::validate_relative_allowing`].
+  #[cfg(feature = "serde")]
+  #[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+  pub fn from_job_spec_in_allowing<P: AsRef<Path>>(
+    spec: &JobSpec,
+    workdir: P,
+    protocols: &[&str],
+  ) -> anyhow::Result<Self> {
+    spec.validate_relative_allowing(protocols)?;
+    let mut command = Self::new();
+    command.workdir(workdir);
+    spec.apply_sandboxed(&mut command)?;
+    Ok(command)
+  }
+
+  /// Capture the program, arguments, environment and settings of this
+  /// command in a [`CommandTemplate`], which can be cloned and instantiated
+  /// into fresh commands any number of times, optionally substituting
+  /// `{name}` placeholders in the arguments.
+  ///
+  /// Call this before `spawn`, which adds arguments of its own. Fails if
+  /// stdin or stdout is connected to a handle with `input_from_stdio` or
+  /// `output_to_stdio`, which can only be used once. Changes made directly to
+  /// the inner `Command` with [`as_inner_mut`](Self::as_inner_mut), other
+  /// than arguments and the environment, aren't captured.
+  pub fn to_template(&self) -> anyhow::Result<CommandTemplate> {
+    if let Some(stream) = self.config.redirected_stdio.first() {
+      anyhow::bail!("{stream} is connected to a handle, which a template can't reuse");
+    }
+    if !self.config.output_pipes.is_empty() {
+      anyhow::bail!("An output pipe accepts a single connection, which a template can't reuse");
+    }
+    Ok(CommandTemplate::capture(
+      &self.inner,
+      &self.arg_model(),
+      &self.config,
+    ))
+  }
+
+  /// Replace the settings, e.g. with those captured by a `CommandTemplate`.
+  pub(crate) fn with_config(mut self, config: CommandConfig) -> Self {
+    self.config = config;
+    self
+  }
+
+  /// Parse the arguments like `args`, the model of the command they were
+  /// captured from, keeping its knowledge of which options are input-only.
+  pub(crate) fn with_arg_model_of(mut self, args: &ArgModel) -> Self {
+    self.args = args.reparse(self.raw_args());
+    self
+  }
+
   //// Escape hatches
 
   /// Escape hatch to access the inner `Command`.
@@ -713,12 +2754,34 @@ impl FfmpegCommand {
     &self.inner
   }
 
-  /// Escape hatch to mutably access the inner `Command`.
+  /// Escape hatch to mutably access the inner `Command`. Arguments added
+  /// through it are parsed again before the arg model is next used.
   pub fn as_inner_mut(&mut self) -> &mut Command {
+    self.raw_edits = true;
     &mut self.inner
   }
 }
 
+/// The numeric value of FFmpeg's `info` log level.
+const LOG_LEVEL_INFO: i32 = 32;
+
+/// Parse the numeric log level from a `-loglevel` value like `level+error`
+/// or `24`, ignoring any flags. Returns `None` if only flags are set.
+fn parse_log_level(value: &str) -> Option<i32> {
+  value.split('+').rev().find_map(|part| match part {
+    "quiet" => Some(-8),
+    "panic" => Some(0),
+    "fatal" => Some(8),
+    "error" => Some(16),
+    "warning" => Some(24),
+    "info" => Some(LOG_LEVEL_INFO),
+    "verbose" => Some(40),
+    "debug" => Some(48),
+    "trace" => Some(56),
+    number => number.parse().ok(),
+  })
+}
+
 impl Default for FfmpegCommand {
   fn default() -> Self {
     Self::new()
@@ -728,8 +2791,22 @@ impl Default for FfmpegCommand {
 impl fmt::Debug for FfmpegCommand {
   /// Format the program and arguments of a Command for display. Any non-utf8
   /// data is lossily converted using the utf8 replacement character.
+  ///
+  /// The alternate form (`{:#?}`) shows the arguments grouped into global
+  /// options, inputs, and outputs instead.
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    self.inner.fmt(f)
+    let args = self.arg_model();
+    match f.alternate() {
+      true => f
+        .debug_struct("FfmpegCommand")
+        .field("program", &self.inner.get_program())
+        .field("global", &args.global)
+        .field("inputs", &args.inputs)
+        .field("outputs", &args.outputs)
+        .field("pending", &args.pending)
+        .finish(),
+      false => self.inner.fmt(f),
+    }
   }
 }
 
@@ -739,7 +2816,13 @@ impl From<Command> for FfmpegCommand {
   /// `set_expected_loglevel()` is not automatically applied, which can have
   /// unexpected effects on log parsing.
   fn from(inner: Command) -> Self {
-    Self { inner }
+    let args = ArgModel::from_args(inner.get_args().map(|arg| arg.to_string_lossy()));
+    Self {
+      inner,
+      args,
+      raw_edits: false,
+      config: CommandConfig::default(),
+    }
   }
 }
 
@@ -0,0 +1,309 @@
+//! Which codecs each container format can store, to catch combinations like
+//! `pcm_s16le` in MP4 before FFmpeg fails on them, after opening every input
+//! and encoder. [`FfmpegCommand::lint`](crate::command::FfmpegCommand::lint)
+//! reports them as `LintWarning::UnsupportedCodec`.
+//!
+//! The table only covers containers with a restricted set of codecs; anything
+//! else, like Matroska, is assumed to store every codec. It's complemented at
+//! runtime by the muxer errors FFmpeg logs, e.g. `[mp4 @ 0x...] Could not
+//! find tag for codec pcm_s16le in stream #1, codec not currently supported
+//! in container`, which are remembered for the FFmpeg binary which logged
+//! them, since another build may have a different set of muxers.
+
+use std::{
+  ffi::{OsStr, OsString},
+  sync::Mutex,
+};
+
+/// The codecs stored by each container, by muxer name. A pattern ending in
+/// `*` matches any codec with that prefix.
+const CONTAINER_CODECS: &[(&str, &[&str])] = &[
+  (
+    "mp4",
+    &[
+      "h264",
+      "hevc",
+      "av1",
+      "vp9",
+      "vvc",
+      "mpeg4",
+      "mpeg2video",
+      "mpeg1video",
+      "mjpeg",
+      "png",
+      "aac",
+      "mp3",
+      "mp2",
+      "ac3",
+      "eac3",
+      "opus",
+      "flac",
+      "alac",
+      "dts",
+      "truehd",
+      "vorbis",
+      "mov_text",
+      "dvd_subtitle",
+    ],
+  ),
+  (
+    "ipod",
+    &["h264", "mpeg4", "aac", "alac", "ac3", "eac3", "mov_text"],
+  ),
+  (
+    "3gp",
+    &[
+      "h263", "h264", "mpeg4", "aac", "amr_nb", "amr_wb", "mov_text",
+    ],
+  ),
+  (
+    "3g2",
+    &[
+      "h263", "h264", "mpeg4", "aac", "amr_nb", "amr_wb", "mov_text",
+    ],
+  ),
+  ("webm", &["vp8", "vp9", "av1", "vorbis", "opus", "webvtt"]),
+  (
+    "flv",
+    &[
+      "h264",
+      "hevc",
+      "av1",
+      "vp9",
+      "flv1",
+      "vp6f",
+      "mp3",
+      "aac",
+      "opus",
+      "flac",
+      "ac3",
+      "eac3",
+      "speex",
+      "nellymoser",
+      "adpcm_swf",
+      "pcm_s16le",
+      "pcm_u8",
+    ],
+  ),
+  (
+    "mpegts",
+    &[
+      "h264",
+      "hevc",
+      "vvc",
+      "av1",
+      "mpeg2video",
+      "mpeg1video",
+      "mpeg4",
+      "cavs",
+      "dirac",
+      "aac",
+      "mp2",
+      "mp3",
+      "ac3",
+      "eac3",
+      "dts",
+      "truehd",
+      "opus",
+      "s302m",
+      "dvb_subtitle",
+      "dvb_teletext",
+      "smpte_klv",
+    ],
+  ),
+  ("ogg", &["theora", "vp8", "vorbis", "opus", "flac", "speex"]),
+  (
+    "wav",
+    &[
+      "pcm_*", "adpcm_*", "mp3", "mp2", "ac3", "eac3", "dts", "aac", "flac", "gsm_ms",
+    ],
+  ),
+  ("mp3", &["mp3", "mjpeg", "png"]),
+  ("adts", &["aac"]),
+  ("flac", &["flac", "mjpeg", "png"]),
+];
+
+/// File extensions whose muxer differs from the extension itself.
+const EXTENSION_MUXERS: &[(&str, &str)] = &[
+  ("m4v", "mp4"),
+  ("m4a", "ipod"),
+  ("m4b", "ipod"),
+  ("mkv", "matroska"),
+  ("mka", "matroska"),
+  ("ts", "mpegts"),
+  ("m2ts", "mpegts"),
+  ("mts", "mpegts"),
+  ("ogv", "ogg"),
+  ("oga", "ogg"),
+  ("aac", "adts"),
+];
+
+/// Encoders which aren't named after their codec.
+const ENCODER_CODECS: &[(&str, &str)] = &[
+  ("libx264", "h264"),
+  ("libx264rgb", "h264"),
+  ("libopenh264", "h264"),
+  ("libx265", "hevc"),
+  ("libkvazaar", "hevc"),
+  ("libvvenc", "vvc"),
+  ("libvpx", "vp8"),
+  ("libvpx-vp9", "vp9"),
+  ("libaom-av1", "av1"),
+  ("libsvtav1", "av1"),
+  ("librav1e", "av1"),
+  ("libxvid", "mpeg4"),
+  ("libtheora", "theora"),
+  ("prores_ks", "prores"),
+  ("prores_aw", "prores"),
+  ("flv", "flv1"),
+  ("libopus", "opus"),
+  ("libvorbis", "vorbis"),
+  ("libmp3lame", "mp3"),
+  ("libshine", "mp3"),
+  ("libfdk_aac", "aac"),
+  ("libtwolame", "mp2"),
+  ("libspeex", "speex"),
+  ("srt", "subrip"),
+  ("ssa", "ass"),
+];
+
+/// Suffixes of hardware encoders, e.g. `hevc_nvenc`, which are named after
+/// their codec otherwise.
+const HARDWARE_SUFFIXES: &[&str] = &[
+  "nvenc",
+  "qsv",
+  "vaapi",
+  "videotoolbox",
+  "amf",
+  "v4l2m2m",
+  "mf",
+  "vulkan",
+  "omx",
+  "rkmpp",
+  "mediacodec",
+  "d3d12va",
+  "at",
+];
+
+/// Combinations which a muxer has rejected at runtime, along with the FFmpeg
+/// binary it belongs to.
+static UNSUPPORTED: Mutex<Vec<(OsString, String, String)>> = Mutex::new(Vec::new());
+
+/// The muxer of a container given by its muxer name or file extension, e.g.
+/// `matroska` for `mkv`.
+fn muxer_name(container: &str) -> String {
+  let container = container.to_ascii_lowercase();
+  EXTENSION_MUXERS
+    .iter()
+    .find(|(extension, _)| *extension == container)
+    .map_or(container, |(_, muxer)| muxer.to_string())
+}
+
+/// The codec produced by `encoder`, e.g. `hevc` for `libx265` or
+/// `hevc_nvenc`. Names which are already codecs are returned as they are.
+///
+/// ```rust
+/// use ffmpeg_sidecar::container::encoder_codec;
+///
+/// assert_eq!(encoder_codec("libx265"), "hevc");
+/// assert_eq!(encoder_codec("h264_nvenc"), "h264");
+/// assert_eq!(encoder_codec("mpeg2_qsv"), "mpeg2video");
+/// assert_eq!(encoder_codec("pcm_s16le"), "pcm_s16le");
+/// ```
+pub fn encoder_codec(encoder: &str) -> &str {
+  if let Some((_, codec)) = ENCODER_CODECS.iter().find(|(name, _)| *name == encoder) {
+    return codec;
+  }
+  match encoder.rsplit_once('_') {
+    Some(("mpeg2", suffix)) if HARDWARE_SUFFIXES.contains(&suffix) => "mpeg2video",
+    Some((codec, suffix)) if HARDWARE_SUFFIXES.contains(&suffix) => codec,
+    _ => encoder,
+  }
+}
+
+/// The container of an output: its `-f` format if given, and otherwise its
+/// file extension. `None` if neither is known.
+pub fn output_container(url: &str, format: Option<&str>) -> Option<String> {
+  if let Some(format) = format {
+    return Some(muxer_name(format));
+  }
+  let (_, extension) = url.rsplit_once('.')?;
+  let valid = !extension.is_empty() && extension.chars().all(|c| c.is_ascii_alphanumeric());
+  valid.then(|| muxer_name(extension))
+}
+
+/// Whether the container, given by its muxer name (`-f`) or file extension,
+/// can store `codec`, which may also be named by its encoder. Containers
+/// which aren't in the table are assumed to store every codec.
+///
+/// ```rust
+/// use ffmpeg_sidecar::container::container_supports;
+///
+/// assert!(!container_supports("mp4", "pcm_s16le"));
+/// assert!(container_supports("mov", "pcm_s16le"));
+/// assert!(container_supports("mp4", "libx264"));
+/// assert!(!container_supports("webm", "aac"));
+/// assert!(container_supports("mkv", "pcm_s16le"));
+/// ```
+pub fn container_supports(container: &str, codec: &str) -> bool {
+  let muxer = muxer_name(container);
+  let codec = encoder_codec(codec);
+  let Some((_, codecs)) = CONTAINER_CODECS.iter().find(|(name, _)| *name == muxer) else {
+    return true;
+  };
+  codecs
+    .iter()
+    .any(|pattern| match pattern.strip_suffix('*') {
+      Some(prefix) => codec.starts_with(prefix),
+      None => *pattern == codec,
+    })
+}
+
+/// Like [`container_supports`], but also `false` if a muxer of the FFmpeg
+/// binary at `ffmpeg` has rejected the combination since the process started.
+pub fn container_supports_with<P: AsRef<OsStr>>(ffmpeg: P, container: &str, codec: &str) -> bool {
+  let muxer = muxer_name(container);
+  let rejected = UNSUPPORTED.lock().is_ok_and(|unsupported| {
+    unsupported.iter().any(|(binary, container, rejected)| {
+      binary == ffmpeg.as_ref() && *container == muxer && rejected == encoder_codec(codec)
+    })
+  });
+  !rejected && container_supports(container, codec)
+}
+
+/// Remember that the muxer `container` of the FFmpeg binary at `ffmpeg`
+/// rejected `codec`, as reported by `FfmpegError::UnsupportedCodec`.
+pub(crate) fn record_unsupported(ffmpeg: &OsStr, container: &str, codec: &str) {
+  let entry = (ffmpeg.to_owned(), muxer_name(container), codec.to_string());
+  if let Ok(mut unsupported) = UNSUPPORTED.lock() {
+    if !unsupported.contains(&entry) {
+      unsupported.push(entry);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_record_unsupported() {
+    // Rejections logged by FFmpeg apply from then on, to the same binary
+    assert!(container_supports_with("ffmpeg-a", "avi", "test_codec"));
+    record_unsupported(OsStr::new("ffmpeg-a"), "avi", "test_codec");
+    assert!(!container_supports_with("ffmpeg-a", "avi", "test_codec"));
+    assert!(container_supports_with("ffmpeg-b", "avi", "test_codec"));
+    assert!(container_supports("avi", "test_codec"));
+  }
+
+  #[test]
+  fn test_mp4_variants() {
+    assert!(!container_supports("m4a", "pcm_s16le"));
+    assert!(container_supports("m4a", "alac"));
+    assert!(!container_supports("3gp", "pcm_s16le"));
+    assert!(container_supports("3gp", "amr_nb"));
+    // QuickTime stores nearly anything, so it isn't checked
+    assert!(container_supports("mov", "dvvideo"));
+    assert!(container_supports("mov", "hap"));
+  }
+}
@@ -0,0 +1,359 @@
+//! Compile an edit decision list (a list of segments to keep) into FFmpeg
+//! commands.
+//!
+//! A [`Cutlist`] can be executed in two ways:
+//! - [`Cutlist::apply_filtergraph`] re-encodes in a single pass, using a
+//!   `trim`/`atrim` + `concat` filtergraph. This is frame-accurate and
+//!   supports per-segment speed changes and filters.
+//! - [`Cutlist::copy_plan`] stream-copies each segment into a temporary file,
+//!   then joins them with the `concat` demuxer. This is fast and lossless, but
+//!   cuts snap to keyframes and segments can't be filtered.
+
+use std::{
+  fmt::Write as _,
+  fs,
+  path::{Path, PathBuf},
+  time::Duration,
+};
+
+use anyhow::{bail, Context};
+
+use crate::{audio_filter::tempo_filter, command::FfmpegCommand};
+
+/// A range of the input to keep, with optional processing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+  pub start: Duration,
+  pub end: Duration,
+  /// Playback speed multiplier, applied to both audio and video; e.g. `2.0`
+  /// for double speed.
+  pub speed: Option<f64>,
+  /// Extra video filters for this segment only, e.g. `hflip`.
+  pub video_filter: Option<String>,
+  /// Extra audio filters for this segment only, e.g. `volume=0.5`.
+  pub audio_filter: Option<String>,
+}
+
+impl Segment {
+  pub fn new(start: Duration, end: Duration) -> Self {
+    Self {
+      start,
+      end,
+      speed: None,
+      video_filter: None,
+      audio_filter: None,
+    }
+  }
+
+  pub fn speed(mut self, speed: f64) -> Self {
+    self.speed = Some(speed);
+    self
+  }
+
+  pub fn video_filter<S: Into<String>>(mut self, filter: S) -> Self {
+    self.video_filter = Some(filter.into());
+    self
+  }
+
+  pub fn audio_filter<S: Into<String>>(mut self, filter: S) -> Self {
+    self.audio_filter = Some(filter.into());
+    self
+  }
+
+  fn has_processing(&self) -> bool {
+    self.speed.is_some() || self.video_filter.is_some() || self.audio_filter.is_some()
+  }
+}
+
+/// An ordered list of segments to extract from a single input and join.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cutlist {
+  pub segments: Vec<Segment>,
+  /// Whether the input has a video stream to cut.
+  pub video: bool,
+  /// Whether the input has an audio stream to cut.
+  pub audio: bool,
+}
+
+impl Default for Cutlist {
+  fn default() -> Self {
+    Self {
+      segments: Vec::new(),
+      video: true,
+      audio: true,
+    }
+  }
+}
+
+impl Cutlist {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn segment(&mut self, segment: Segment) -> &mut Self {
+    self.segments.push(segment);
+    self
+  }
+
+  /// Cut video only, for inputs without an audio stream.
+  pub fn no_audio(&mut self) -> &mut Self {
+    self.audio = false;
+    self
+  }
+
+  /// Cut audio only, for inputs without a video stream.
+  pub fn no_video(&mut self) -> &mut Self {
+    self.video = false;
+    self
+  }
+
+  /// Check that there is at least one segment and stream, and that every
+  /// segment has a positive duration and speed.
+  pub fn validate(&self) -> anyhow::Result<()> {
+    if self.segments.is_empty() {
+      bail!("Cutlist has no segments");
+    }
+    if !self.video && !self.audio {
+      bail!("Cutlist has neither video nor audio enabled");
+    }
+    for (index, segment) in self.segments.iter().enumerate() {
+      if segment.end <= segment.start {
+        bail!("Segment #{index} ends before it starts");
+      }
+      if segment
+        .speed
+        .is_some_and(|speed| !speed.is_finite() || speed <= 0.0)
+      {
+        bail!("Segment #{index} has an invalid speed");
+      }
+    }
+    Ok(())
+  }
+
+  /// A filtergraph which trims each segment from input #0 and concatenates
+  /// them into the output labels `[v]` and `[a]`.
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::cutlist::{Cutlist, Segment};
+  /// use std::time::Duration;
+  ///
+  /// let graph = Cutlist::new()
+  ///   .no_audio()
+  ///   .segment(Segment::new(Duration::from_secs(1), Duration::from_secs(2)))
+  ///   .segment(Segment::new(Duration::from_secs(5), Duration::from_secs(6)).speed(2.0))
+  ///   .filtergraph()?;
+  /// assert_eq!(
+  ///   graph,
+  ///   "[0:v]trim=start=1.000000:end=2.000000,setpts=PTS-STARTPTS[v0];\
+  ///    [0:v]trim=start=5.000000:end=6.000000,setpts=PTS-STARTPTS,setpts=PTS/2.000000[v1];\
+  ///    [v0][v1]concat=n=2:v=1:a=0[v]"
+  /// );
+  /// # anyhow::Ok(())
+  /// ```
+  pub fn filtergraph(&self) -> anyhow::Result<String> {
+    self.validate()?;
+    let mut graph = String::new();
+    let mut concat_inputs = String::new();
+
+    for (index, segment) in self.segments.iter().enumerate() {
+      let start = segment.start.as_secs_f64();
+      let end = segment.end.as_secs_f64();
+
+      if self.video {
+        write!(
+          graph,
+          "[0:v]trim=start={start:.6}:end={end:.6},setpts=PTS-STARTPTS"
+        )?;
+        if let Some(speed) = segment.speed {
+          write!(graph, ",setpts=PTS/{speed:.6}")?;
+        }
+        if let Some(filter) = &segment.video_filter {
+          write!(graph, ",{filter}")?;
+        }
+        write!(graph, "[v{index}];")?;
+        write!(concat_inputs, "[v{index}]")?;
+      }
+
+      if self.audio {
+        write!(
+          graph,
+          "[0:a]atrim=start={start:.6}:end={end:.6},asetpts=PTS-STARTPTS"
+        )?;
+        if let Some(speed) = segment.speed {
+          write!(graph, ",{}", tempo_filter(speed))?;
+        }
+        if let Some(filter) = &segment.audio_filter {
+          write!(graph, ",{filter}")?;
+        }
+        write!(graph, "[a{index}];")?;
+        write!(concat_inputs, "[a{index}]")?;
+      }
+    }
+
+    let (v, a) = (self.video as u8, self.audio as u8);
+    let n = self.segments.len();
+    write!(graph, "{concat_inputs}concat=n={n}:v={v}:a={a}")?;
+    if self.video {
+      graph.push_str("[v]");
+    }
+    if self.audio {
+      graph.push_str("[a]");
+    }
+    Ok(graph)
+  }
+
+  /// Add the cutlist's filtergraph and output mappings to a command which
+  /// already has the source as its first input. Output options (codecs etc.)
+  /// and the output path can be added afterwards.
+  pub fn apply_filtergraph<'a>(
+    &self,
+    command: &'a mut FfmpegCommand,
+  ) -> anyhow::Result<&'a mut FfmpegCommand> {
+    command.filter_complex_auto(self.filtergraph()?)?;
+    if self.video {
+      command.map("[v]");
+    }
+    if self.audio {
+      command.map("[a]");
+    }
+    Ok(command)
+  }
+
+  /// Plan a stream-copy workflow: one command per segment writing to
+  /// `work_dir`, followed by a `concat` demuxer pass into `output`. Fails if
+  /// any segment has a speed change or filters, since those require
+  /// re-encoding.
+  ///
+  /// Segment files use the same extension as `output`.
+  pub fn copy_plan<I: AsRef<Path>, O: AsRef<Path>, W: AsRef<Path>>(
+    &self,
+    input: I,
+    output: O,
+    work_dir: W,
+  ) -> anyhow::Result<CopyPlan> {
+    self.validate()?;
+    if self.segments.iter().any(Segment::has_processing) {
+      bail!("Segments with speed changes or filters can't be stream copied");
+    }
+
+    let input = input.as_ref();
+    let output = output.as_ref().to_path_buf();
+    let work_dir = work_dir.as_ref().to_path_buf();
+    let extension = output
+      .extension()
+      .map(|ext| ext.to_string_lossy().to_string())
+      .unwrap_or_else(|| "mkv".to_string());
+
+    let segment_paths = (0..self.segments.len())
+      .map(|index| work_dir.join(format!("segment_{index:04}.{extension}")))
+      .collect::<Vec<_>>();
+
+    let segment_commands = self
+      .segments
+      .iter()
+      .zip(&segment_paths)
+      .map(|(segment, path)| {
+        let mut command = FfmpegCommand::new();
+        command
+          .overwrite()
+          .seek(segment.start)
+          .duration(segment.end - segment.start)
+          .input_path(input);
+        if !self.video {
+          command.no_video();
+        }
+        if !self.audio {
+          command.no_audio();
+        }
+        command
+          .args(["-c", "copy", "-avoid_negative_ts", "make_zero"])
+          .output_path(path);
+        command
+      })
+      .collect();
+
+    // Paths in the list are resolved relative to the list file itself
+    let mut concat_list = String::new();
+    for path in &segment_paths {
+      let name = path.file_name().unwrap_or_default().to_string_lossy();
+      writeln!(concat_list, "file '{}'", name.replace('\'', "'\\''"))?;
+    }
+
+    Ok(CopyPlan {
+      segment_commands,
+      segment_paths,
+      concat_list,
+      concat_list_path: work_dir.join("concat.txt"),
+      output,
+    })
+  }
+}
+
+/// The commands making up a stream-copy cutlist, from [`Cutlist::copy_plan`].
+#[derive(Debug)]
+pub struct CopyPlan {
+  /// One command per segment, each writing to the matching `segment_paths`.
+  pub segment_commands: Vec<FfmpegCommand>,
+  pub segment_paths: Vec<PathBuf>,
+  /// The contents of the `concat` demuxer list file.
+  pub concat_list: String,
+  pub concat_list_path: PathBuf,
+  pub output: PathBuf,
+}
+
+impl CopyPlan {
+  /// The final command, joining the segment files listed in
+  /// `concat_list_path` into `output`.
+  pub fn concat_command(&self) -> FfmpegCommand {
+    let mut command = FfmpegCommand::new();
+    command
+      .overwrite()
+      .format("concat")
+      .args(["-safe", "0"])
+      .input_path(&self.concat_list_path)
+      .args(["-c", "copy"])
+      .output_path(&self.output);
+    command
+  }
+
+  /// Run every segment command in order, write the list file, then join the
+  /// segments. Fails on the first command which exits unsuccessfully. The
+  /// segment files are left in place.
+  pub fn run(mut self) -> anyhow::Result<()> {
+    if let Some(parent) = self.concat_list_path.parent() {
+      fs::create_dir_all(parent)?;
+    }
+    for (index, command) in self.segment_commands.iter_mut().enumerate() {
+      let status = command.spawn()?.wait()?;
+      if !status.success() {
+        bail!("Failed to extract segment #{index}: {status}");
+      }
+    }
+    fs::write(&self.concat_list_path, &self.concat_list)
+      .with_context(|| format!("Failed to write {}", self.concat_list_path.display()))?;
+    let status = self.concat_command().spawn()?.wait()?;
+    if !status.success() {
+      bail!("Failed to concatenate segments: {status}");
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_cutlist_validation() {
+    let second = Duration::from_secs(1);
+    assert!(Cutlist::new().filtergraph().is_err());
+    assert!(Cutlist::new()
+      .segment(Segment::new(second, second))
+      .filtergraph()
+      .is_err());
+    assert!(Cutlist::new()
+      .segment(Segment::new(Duration::ZERO, second).speed(2.0))
+      .copy_plan("in.mp4", "out.mp4", "work")
+      .is_err());
+  }
+}
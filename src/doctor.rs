@@ -0,0 +1,316 @@
+//! A self-test of the local FFmpeg installation, for diagnosing user
+//! environments at startup or from a support screen.
+
+use std::{
+  fmt,
+  path::PathBuf,
+  process::{Command, ExitStatus, Stdio},
+  sync::mpsc,
+  time::{Duration, Instant},
+};
+
+use anyhow::Context;
+
+use crate::{
+  child::FfmpegChild,
+  command::{BackgroundCommand, FfmpegCommand},
+  event::FfmpegEvent,
+  log_parser::FfmpegLogParser,
+  paths::ffmpeg_path,
+  version::VersionInfo,
+};
+
+/// How long each check may take before it fails, e.g. because the binary
+/// hangs instead of exiting. The FFmpeg processes of the checks are killed
+/// then.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The outcome of one [`Check`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckStatus {
+  /// Passed, with details such as the version found.
+  Passed(String),
+  /// Failed, with the error.
+  Failed(String),
+  /// Not run, with the reason, e.g. because FFmpeg wasn't found at all.
+  Skipped(String),
+}
+
+/// One check run by [`doctor`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+  pub name: &'static str,
+  pub status: CheckStatus,
+  pub duration: Duration,
+}
+
+/// The results of every check run by [`doctor`], in order. Displays as one
+/// line per check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DoctorReport {
+  /// The path of the FFmpeg binary which was tested.
+  pub ffmpeg_path: PathBuf,
+  pub checks: Vec<Check>,
+}
+
+impl DoctorReport {
+  /// Whether no check failed. Skipped checks don't count as failures.
+  pub fn is_ok(&self) -> bool {
+    self.failures().next().is_none()
+  }
+
+  /// The checks which failed.
+  pub fn failures(&self) -> impl Iterator<Item = &Check> {
+    self
+      .checks
+      .iter()
+      .filter(|check| matches!(check.status, CheckStatus::Failed(_)))
+  }
+
+  /// The check named `name`, e.g. `"version"`.
+  pub fn check(&self, name: &str) -> Option<&Check> {
+    self.checks.iter().find(|check| check.name == name)
+  }
+}
+
+impl fmt::Display for DoctorReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(f, "ffmpeg: {}", self.ffmpeg_path.display())?;
+    for check in &self.checks {
+      let (label, details) = match &check.status {
+        CheckStatus::Passed(details) => ("ok", details),
+        CheckStatus::Failed(details) => ("FAILED", details),
+        CheckStatus::Skipped(details) => ("skipped", details),
+      };
+      writeln!(
+        f,
+        "[{label}] {} ({:?}): {details}",
+        check.name, check.duration
+      )?;
+    }
+    Ok(())
+  }
+}
+
+/// Run a battery of quick checks on the FFmpeg installation:
+///
+/// - `binary`: `ffmpeg -version` runs.
+/// - `version`: its version string can be parsed.
+/// - `lavfi`: a `testsrc` input can be decoded.
+/// - `rawvideo_pipe`: raw frames arrive intact over stdout.
+/// - `named_pipe`: a named pipe can be created (with the `named_pipes`
+///   feature).
+///
+/// The remaining checks are skipped if the binary isn't found. Each check
+/// fails if it takes longer than 10 seconds.
+///
+/// ```rust,no_run
+/// let report = ffmpeg_sidecar::doctor();
+/// if !report.is_ok() {
+///   eprintln!("FFmpeg isn't working correctly:\n{report}");
+/// }
+/// ```
+pub fn doctor() -> DoctorReport {
+  let mut checks = Vec::new();
+  let installed = run_check(&mut checks, "binary", CHECK_TIMEOUT, check_binary);
+
+  let steps: [(&'static str, CheckFn); 3] = [
+    ("version", check_version),
+    ("lavfi", check_lavfi),
+    ("rawvideo_pipe", check_rawvideo_pipe),
+  ];
+  for (name, step) in steps {
+    match installed {
+      true => {
+        run_check(&mut checks, name, CHECK_TIMEOUT, step);
+      }
+      false => skip_check(&mut checks, name, "FFmpeg not found"),
+    }
+  }
+
+  #[cfg(feature = "named_pipes")]
+  run_check(&mut checks, "named_pipe", CHECK_TIMEOUT, check_named_pipe);
+  #[cfg(not(feature = "named_pipes"))]
+  skip_check(
+    &mut checks,
+    "named_pipe",
+    "requires the `named_pipes` feature",
+  );
+
+  DoctorReport {
+    ffmpeg_path: ffmpeg_path(),
+    checks,
+  }
+}
+
+type CheckFn = fn() -> anyhow::Result<String>;
+
+/// Run `check` on its own thread, recording its outcome, or a failure if it
+/// doesn't finish within `timeout`. Returns whether it passed.
+fn run_check<F>(checks: &mut Vec<Check>, name: &'static str, timeout: Duration, check: F) -> bool
+where
+  F: FnOnce() -> anyhow::Result<String> + Send + 'static,
+{
+  let start = Instant::now();
+  let (tx, rx) = mpsc::channel();
+  std::thread::spawn(move || tx.send(check()));
+  let status = match rx.recv_timeout(timeout) {
+    Ok(Ok(details)) => CheckStatus::Passed(details),
+    Ok(Err(e)) => CheckStatus::Failed(format!("{e:#}")),
+    Err(_) => CheckStatus::Failed(format!("timed out after {timeout:?}")),
+  };
+  let passed = matches!(status, CheckStatus::Passed(_));
+  checks.push(Check {
+    name,
+    status,
+    duration: start.elapsed(),
+  });
+  passed
+}
+
+fn skip_check(checks: &mut Vec<Check>, name: &'static str, reason: &str) {
+  checks.push(Check {
+    name,
+    status: CheckStatus::Skipped(reason.to_string()),
+    duration: Duration::ZERO,
+  });
+}
+
+/// Wait for `child` to exit, killing it if it doesn't within the timeout.
+fn wait_or_kill<B: crate::backend::ProcessBackend>(
+  mut child: FfmpegChild<B>,
+) -> anyhow::Result<ExitStatus> {
+  if let Some(status) = child.wait_timeout(CHECK_TIMEOUT)? {
+    return Ok(status);
+  }
+  child.kill()?;
+  child.wait()?;
+  anyhow::bail!("didn't exit within {CHECK_TIMEOUT:?}")
+}
+
+fn check_binary() -> anyhow::Result<String> {
+  let inner = Command::new(ffmpeg_path())
+    .arg("-version")
+    .create_no_window()
+    .stdin(Stdio::null())
+    .stdout(Stdio::null())
+    .stderr(Stdio::null())
+    .spawn()
+    .context("`ffmpeg -version` failed to run")?;
+  let status = wait_or_kill(FfmpegChild::from_backend(inner)).context("`ffmpeg -version`")?;
+  anyhow::ensure!(status.success(), "`ffmpeg -version` failed with {status}");
+  Ok("found".to_string())
+}
+
+/// Like `ffmpeg_version_ex`, but killing `ffmpeg -version` if it hangs.
+fn check_version() -> anyhow::Result<String> {
+  let mut inner = Command::new(ffmpeg_path())
+    .arg("-version")
+    .create_no_window()
+    .stdin(Stdio::null())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::null())
+    .spawn()
+    .context("`ffmpeg -version` failed to run")?;
+  let stdout = inner.stdout.take().context("No standard output channel")?;
+  // Read on another thread, so that killing FFmpeg unblocks the read
+  let reader = std::thread::spawn(move || {
+    let mut parser = FfmpegLogParser::new(stdout);
+    while let Ok(event) = parser.parse_next_event() {
+      match event {
+        FfmpegEvent::ParsedVersion(v) => return Some(v.version),
+        FfmpegEvent::LogEOF => break,
+        _ => {}
+      }
+    }
+    None
+  });
+  let status = wait_or_kill(FfmpegChild::from_backend(inner)).context("`ffmpeg -version`")?;
+  anyhow::ensure!(status.success(), "`ffmpeg -version` failed with {status}");
+  let version = reader
+    .join()
+    .ok()
+    .flatten()
+    .context("Failed to parse ffmpeg version")?;
+  VersionInfo::parse(&version)
+    .with_context(|| format!("Unrecognized ffmpeg version: {version}"))?;
+  Ok(version)
+}
+
+fn check_lavfi() -> anyhow::Result<String> {
+  let child = FfmpegCommand::new()
+    .testsrc()
+    .frames(1)
+    .format("null")
+    .output("-")
+    .spawn()?;
+  let status = wait_or_kill(child)?;
+  anyhow::ensure!(status.success(), "`testsrc` failed with {status}");
+  Ok("decoded `testsrc`".to_string())
+}
+
+const PIPE_TEST_FRAMES: usize = 2;
+const PIPE_TEST_SIZE: u32 = 32;
+
+fn check_rawvideo_pipe() -> anyhow::Result<String> {
+  let frames: Vec<_> = FfmpegCommand::new()
+    .format("lavfi")
+    .input(format!("testsrc=size={PIPE_TEST_SIZE}x{PIPE_TEST_SIZE}"))
+    .frames(PIPE_TEST_FRAMES as u32)
+    .rawvideo()
+    .spawn()?
+    .iter()?
+    .with_timeout(CHECK_TIMEOUT)
+    .filter_frames()
+    .collect();
+  anyhow::ensure!(
+    frames.len() == PIPE_TEST_FRAMES,
+    "received {} of {PIPE_TEST_FRAMES} frames",
+    frames.len()
+  );
+  let expected_size = (PIPE_TEST_SIZE * PIPE_TEST_SIZE * 3) as usize;
+  let frame = frames
+    .iter()
+    .find(|frame| frame.data.len() != expected_size);
+  if let Some(frame) = frame {
+    anyhow::bail!(
+      "frame {} has {} bytes instead of {expected_size}",
+      frame.frame_num,
+      frame.data.len()
+    );
+  }
+  Ok(format!("received {PIPE_TEST_FRAMES} frames"))
+}
+
+#[cfg(feature = "named_pipes")]
+fn check_named_pipe() -> anyhow::Result<String> {
+  use crate::named_pipes::{pipe_path, NamedPipe};
+
+  let name = format!("ffmpeg_sidecar_doctor_{}", std::process::id());
+  let path = match cfg!(windows) {
+    true => pipe_path(name),
+    false => std::env::temp_dir().join(name),
+  };
+  NamedPipe::new(&path).with_context(|| format!("failed to create `{}`", path.display()))?;
+  Ok(format!("created `{}`", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_check_timeout() {
+    let mut checks = Vec::new();
+    let passed = run_check(&mut checks, "hang", Duration::from_millis(50), || {
+      std::thread::sleep(Duration::from_secs(5));
+      Ok("finished".to_string())
+    });
+    assert!(!passed);
+    assert_eq!(
+      checks[0].status,
+      CheckStatus::Failed("timed out after 50ms".to_string())
+    );
+    assert!(checks[0].duration < Duration::from_secs(5));
+  }
+}
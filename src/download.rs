@@ -164,7 +164,7 @@ pub fn download_ffmpeg_package(url: &str, download_dir: &Path) -> Result<PathBuf
 /// After downloading, unpacks the archive to a folder, moves the binaries to
 /// their final location, and deletes the archive and temporary folder.
 #[cfg(feature = "download_ffmpeg")]
-pub fn unpack_ffmpeg(from_archive: &PathBuf, binary_folder: &Path) -> Result<()> {
+pub fn unpack_ffmpeg(from_archive: &Path, binary_folder: &Path) -> Result<()> {
   use anyhow::Context;
   use std::{
     fs::{create_dir_all, read_dir, remove_dir_all, remove_file, rename, File},
@@ -203,9 +203,9 @@ pub fn unpack_ffmpeg(from_archive: &PathBuf, binary_folder: &Path) -> Result<()>
       .next()
       .context("Failed to get inner folder")??;
     (
-      inner_folder.path().join("bin/ffmpeg.exe"),
-      inner_folder.path().join("bin/ffplay.exe"),
-      inner_folder.path().join("bin/ffprobe.exe"),
+      inner_folder.path().join("bin").join("ffmpeg.exe"),
+      inner_folder.path().join("bin").join("ffplay.exe"),
+      inner_folder.path().join("bin").join("ffprobe.exe"),
     )
   } else if cfg!(target_os = "linux") {
     let inner_folder = read_dir(&temp_folder)?
@@ -231,7 +231,7 @@ pub fn unpack_ffmpeg(from_archive: &PathBuf, binary_folder: &Path) -> Result<()>
     let file_name = binary_folder.join(
       path
         .file_name()
-        .with_context(|| format!("Path {} does not have a file_name", path.to_string_lossy()))?,
+        .with_context(|| format!("Path {} does not have a file_name", path.display()))?,
     );
     rename(path, file_name)?;
     anyhow::Ok(())
@@ -1,31 +1,313 @@
 //! Any event that occurs during the execution of an FFmpeg command.
 
+use std::{process::ExitStatus, time::Duration};
+
+use crate::{audio_levels::SampleFormat, extract::Extracted, time_range::FfmpegTimeDuration};
+
 /// Any event that occurs during the execution of an FFmpeg command,
 /// including log messages, parsed metadata, progress updates, and output.
+///
+/// New variants may be added in minor releases, so matches outside this crate
+/// need a wildcard arm. Prefer the accessor methods such as
+/// [`FfmpegEvent::as_error`] over matching on specific log variants, since
+/// those have stayed stable as the variants changed.
 #[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum FfmpegEvent {
   ParsedVersion(FfmpegVersion),
   ParsedConfiguration(FfmpegConfiguration),
-  ParsedStreamMapping(String),
+  ParsedStreamMapping(StreamMapping),
   ParsedInput(FfmpegInput),
   ParsedOutput(FfmpegOutput),
   ParsedInputStream(Stream),
   ParsedOutputStream(Stream),
+  /// The encoder of an output stream, e.g. `libx264`, from the metadata
+  /// logged beneath its stream line, emitted right before the `Log` event of
+  /// that line. Streams which aren't re-encoded have none.
+  ParsedOutputStreamEncoder {
+    output_index: u32,
+    stream_index: u32,
+    encoder: String,
+  },
   ParsedDuration(FfmpegDuration),
+  /// A timestamp drift correction by the `aresample` filter in async mode;
+  /// see `FfmpegCommand::audio_drift_compensation`.
+  ParsedDriftCompensation(DriftCompensation),
+  /// A value printed by a `metadata` or `ametadata` filter in `print` mode,
+  /// which is how filters like `ebur128`, `blackdetect` and `signalstats`
+  /// export their per-frame data.
+  FilterMetadata(FilterMetadata),
   Log(LogLevel, String),
+  /// The stderr log stream has closed. Output may still be arriving on stdout;
+  /// see `Completed` for the end of the whole process.
   LogEOF,
   /// An error that didn't originate from the ffmpeg logs
   Error(String),
   Progress(FfmpegProgress),
   OutputFrame(OutputVideoFrame),
+  /// Decoded raw PCM samples, emitted instead of `OutputChunk`s for a raw
+  /// audio output on stdout when enabled with `FfmpegCommand::audio_samples`.
+  OutputAudioSamples(OutputAudioSamples),
   /// A chunk of data that may not correspond to a complete frame.
   /// For example, it may contain encoded h264.
   /// These chunks will need to be handled manually, or piped directly to
   /// another FFmpeg instance.
   OutputChunk(Vec<u8>),
+  /// Data read from an output added with `FfmpegCommand::output_pipe`, when
+  /// it isn't a single raw video or decoded audio stream.
+  OutputPipeChunk {
+    /// The label given to `output_pipe`.
+    label: String,
+    chunk: Vec<u8>,
+  },
+  /// The stdout output stream has closed. Log messages may still be arriving
+  /// on stderr; see `Completed` for the end of the whole process.
   Done,
+  /// A periodic sample of the FFmpeg process' resource usage, enabled with
+  /// `FfmpegCommand::sample_resource_usage`.
+  ResourceUsage {
+    /// CPU usage since the previous sample, as a percentage of a single core.
+    /// Multi-threaded encoders can exceed 100%.
+    cpu_percent: f32,
+    /// Resident set size (physical memory in use) in bytes.
+    rss_bytes: u64,
+  },
+  /// The rate at which data is being read from stdout, enabled with
+  /// `FfmpegCommand::sample_throughput`. Unlike the bitrate reported in
+  /// `Progress`, this is measured on the Rust side and works for every
+  /// muxer.
+  Throughput {
+    /// Average rate since the previous `Throughput` event.
+    bytes_per_sec: f64,
+    /// Cumulative bytes read from stdout.
+    total_bytes: u64,
+  },
+  /// How long the process took to reach each startup milestone. Emitted once,
+  /// just before the first `OutputFrame` or `OutputChunk`, or before
+  /// `Completed` if there was no output on stdout.
+  StartupTimings(StartupTimings),
+  /// An output stream is produced by a different encoder than the `-c` option
+  /// requested, e.g. `libx264` instead of `h264_nvenc`. Emitted right after
+  /// the `ParsedOutputStream` event of that stream, or after its
+  /// `ParsedOutputStreamEncoder` if the stream mapping didn't name one. A request for a codec
+  /// (e.g. `-c:v h264`) rather than a specific encoder is satisfied by any
+  /// encoder of that codec.
+  EncoderFallback {
+    output_index: u32,
+    stream_index: u32,
+    requested: String,
+    actual: String,
+  },
+  /// Fields matched in a log line by a `LogExtractor` registered with
+  /// `FfmpegCommand::extract`, emitted right after the `Log` event.
+  Extracted(Extracted),
+  /// A common failure recognized in an error logged by FFmpeg, emitted right
+  /// after the `Log` event of that line.
+  ParsedError(FfmpegError),
+  /// The undecoded bytes of a log line which wasn't valid UTF-8, e.g. a
+  /// metadata tag in another encoding, enabled with
+  /// `FfmpegCommand::raw_log_lines`. Emitted right after the event parsed
+  /// from that line, whose text has replacement characters instead.
+  RawLogLine(Vec<u8>),
+  /// Consecutive error lines from the same component, e.g. a muxer's
+  /// breadcrumbs leading up to its final failure, enabled with
+  /// `FfmpegCommand::error_blocks`. Emitted after the `Log` event of the last
+  /// line, once the next line shows that the block has ended.
+  ErrorBlock {
+    /// The component which logged the lines, e.g. `mov` for
+    /// `[mov @ 0x55d8c2a4e880]`, or `None` for lines without one.
+    component: Option<String>,
+    /// The messages, without their component and level prefixes.
+    lines: Vec<String>,
+  },
+  /// No progress update or output has been received for `since`, which
+  /// exceeds the timeout set with `FfmpegChild::stall_watchdog`.
+  Stalled {
+    since: Duration,
+  },
+  /// The consumer of the output stopped reading it early, e.g. by dropping
+  /// the iterator or the `FrameReceiver`. FFmpeg is asked to quit, and the
+  /// `Broken pipe` errors it logs as a result are replaced by this event.
+  ConsumerClosed,
+  /// A line written to a destination registered with
+  /// `FfmpegCommand::capture_filter_output`.
+  FilterOutput {
+    /// The name of the `FilterOutputCapture`.
+    name: String,
+    line: String,
+  },
+  /// Emitted exactly once as the final event, after both stderr and stdout
+  /// have closed, by iterators created with `FfmpegChild::into_events` or
+  /// `FfmpegIterator::with_completed`.
+  Completed {
+    /// The exit status of the process, if the iterator owns the child and was
+    /// able to reap it (see `FfmpegChild::into_events`). Always `None` for
+    /// iterators created with `FfmpegChild::iter`, since the child is still
+    /// owned by the caller; use `FfmpegChild::wait` instead.
+    exit_status: Option<ExitStatus>,
+    /// Whether any `OutputFrame`, `OutputChunk` or `OutputPipeChunk` events
+    /// were emitted.
+    had_output: bool,
+  },
+}
+
+impl FfmpegEvent {
+  /// The message of an error event: either `Error`, or a `Log` at the `Error`
+  /// or `Fatal` level.
+  ///
+  /// This replaces matching on the removed `LogError` variant:
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::event::{FfmpegEvent, LogLevel};
+  ///
+  /// let event = FfmpegEvent::Log(LogLevel::Error, "Invalid argument".into());
+  /// // Previously: `if let FfmpegEvent::LogError(message) = &event`
+  /// if let Some(message) = event.as_error() {
+  ///   assert_eq!(message, "Invalid argument");
+  /// }
+  /// ```
+  pub fn as_error(&self) -> Option<&str> {
+    match self {
+      FfmpegEvent::Error(message)
+      | FfmpegEvent::Log(LogLevel::Error | LogLevel::Fatal, message) => Some(message),
+      _ => None,
+    }
+  }
+
+  /// The common failure described by an error event, if it's recognized;
+  /// see [`try_parse_error`](crate::log_parser::try_parse_error).
+  pub fn as_ffmpeg_error(&self) -> Option<FfmpegError> {
+    match self {
+      FfmpegEvent::ParsedError(error) => Some(error.clone()),
+      FfmpegEvent::Log(LogLevel::Error | LogLevel::Fatal, line) => {
+        crate::log_parser::try_parse_error(line)
+      }
+      _ => None,
+    }
+  }
+
+  /// The level and message of a `Log` event.
+  pub fn as_log(&self) -> Option<(&LogLevel, &str)> {
+    match self {
+      FfmpegEvent::Log(level, message) => Some((level, message)),
+      _ => None,
+    }
+  }
+
+  /// Alias for constructing the removed `LogError` variant.
+  #[deprecated(note = "use `FfmpegEvent::Log(LogLevel::Error, message)`, or `as_error` to match")]
+  #[allow(non_snake_case)]
+  pub fn LogError(message: String) -> Self {
+    FfmpegEvent::Log(LogLevel::Error, message)
+  }
+
+  /// Alias for constructing the removed `LogWarning` variant.
+  #[deprecated(note = "use `FfmpegEvent::Log(LogLevel::Warning, message)`")]
+  #[allow(non_snake_case)]
+  pub fn LogWarning(message: String) -> Self {
+    FfmpegEvent::Log(LogLevel::Warning, message)
+  }
+
+  /// Alias for constructing the removed `LogInfo` variant.
+  #[deprecated(note = "use `FfmpegEvent::Log(LogLevel::Info, message)`")]
+  #[allow(non_snake_case)]
+  pub fn LogInfo(message: String) -> Self {
+    FfmpegEvent::Log(LogLevel::Info, message)
+  }
+}
+
+/// A common failure, classified from an error logged by FFmpeg. Paths and
+/// names are as FFmpeg printed them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FfmpegError {
+  /// An input or output file, or its directory, doesn't exist. The path is
+  /// `None` if FFmpeg didn't print it on the same line.
+  FileNotFound { path: Option<String> },
+  /// The requested encoder isn't part of this FFmpeg build, e.g. `libfdk_aac`.
+  UnknownEncoder { encoder: String },
+  /// An option, or its value, was rejected. The option is `None` for errors
+  /// which don't name it, like `Error opening output files: Invalid argument`.
+  InvalidArgument {
+    option: Option<String>,
+    message: String,
+  },
+  /// An input or output file can't be opened with the process' permissions.
+  PermissionDenied { path: Option<String> },
+  /// A capture device is in use by another process. Worth retrying.
+  DeviceBusy { device: Option<String> },
+  /// The pixel format is unknown, or not supported by the encoder, which is
+  /// `None` if FFmpeg didn't name it.
+  UnsupportedPixelFormat {
+    pix_fmt: String,
+    encoder: Option<String>,
+  },
+  /// The output container can't store the codec, e.g. `pcm_s16le` in MP4.
+  /// The container is the muxer's name, or `None` if FFmpeg didn't print it.
+  /// See [`crate::container::container_supports`].
+  UnsupportedCodec {
+    codec: String,
+    container: Option<String>,
+  },
 }
 
+impl FfmpegError {
+  /// Whether the failure may go away by itself, so that running the same
+  /// command again later could succeed.
+  pub fn is_retryable(&self) -> bool {
+    matches!(self, FfmpegError::DeviceBusy { .. })
+  }
+}
+
+impl std::fmt::Display for FfmpegError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let or_file = |path: &Option<String>| path.clone().unwrap_or_else(|| "file".to_string());
+    match self {
+      FfmpegError::FileNotFound { path } => write!(f, "{} not found", or_file(path)),
+      FfmpegError::UnknownEncoder { encoder } => {
+        write!(f, "Encoder {encoder} isn't available in this FFmpeg build")
+      }
+      FfmpegError::InvalidArgument {
+        option: Some(option),
+        message,
+      } => write!(f, "Invalid argument for {option}: {message}"),
+      FfmpegError::InvalidArgument {
+        option: None,
+        message,
+      } => write!(f, "Invalid argument: {message}"),
+      FfmpegError::PermissionDenied { path } => {
+        write!(f, "Permission denied to open {}", or_file(path))
+      }
+      FfmpegError::DeviceBusy { device } => write!(
+        f,
+        "{} is in use by another process",
+        device.as_deref().unwrap_or("The device")
+      ),
+      FfmpegError::UnsupportedPixelFormat {
+        pix_fmt,
+        encoder: Some(encoder),
+      } => write!(f, "Pixel format {pix_fmt} isn't supported by {encoder}"),
+      FfmpegError::UnsupportedPixelFormat {
+        pix_fmt,
+        encoder: None,
+      } => write!(f, "Pixel format {pix_fmt} isn't supported"),
+      FfmpegError::UnsupportedCodec {
+        codec,
+        container: Some(container),
+      } => write!(
+        f,
+        "Codec {codec} can't be stored in the {container} container"
+      ),
+      FfmpegError::UnsupportedCodec {
+        codec,
+        container: None,
+      } => write!(f, "Codec {codec} can't be stored in the output container"),
+    }
+  }
+}
+
+impl std::error::Error for FfmpegError {}
+
 /// The internal log level designated by FFmpeg on each message.
 #[derive(Debug, Clone, PartialEq)]
 pub enum LogLevel {
@@ -36,17 +318,62 @@ pub enum LogLevel {
   Unknown,
 }
 
+/// A correction applied by the audio resampler to keep audio timestamps in
+/// sync with the clock, parsed from its `verbose` log messages.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DriftCompensation {
+  pub action: DriftAction,
+  pub raw_log_message: String,
+}
+
+/// One `key=value` line printed by a `metadata=print` or `ametadata=print`
+/// filter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterMetadata {
+  /// The filter instance which printed the value, e.g. `Parsed_metadata_1`.
+  pub filter: String,
+  /// e.g. `lavfi.signalstats.YAVG`
+  pub key: String,
+  pub value: String,
+  /// The timestamp of the frame the value belongs to, from the `frame:`
+  /// line the filter prints before each frame's values.
+  pub pts_time: Option<f64>,
+  pub raw_log_message: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DriftAction {
+  /// Soft compensation: the audio is stretched or squeezed by `compensation`
+  /// samples over the next `duration` samples, to correct `drift` seconds.
+  Stretch {
+    drift: f64,
+    compensation: i32,
+    duration: i32,
+  },
+  /// Hard compensation: `samples` of silence were inserted to fill a gap.
+  InsertSilence { samples: u64 },
+  /// Hard compensation: `samples` were dropped to catch up.
+  Discard { samples: u64 },
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct FfmpegInput {
   pub index: u32,
+  /// In seconds, or `None` if unknown, e.g. for a live source.
   pub duration: Option<f64>,
+  /// The timestamp of the input's first packet, in seconds.
+  pub start_time: Option<f64>,
   pub raw_log_message: String,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct FfmpegDuration {
   pub input_index: u32,
-  pub duration: f64,
+  /// As logged, e.g. `00:00:05.00`, or `N/A` for a live source.
+  pub duration: FfmpegTimeDuration,
+  /// The `start:` timestamp of the input's first packet, in seconds, which
+  /// live sources report even without a duration.
+  pub start_time: Option<f64>,
   pub raw_log_message: String,
 }
 
@@ -54,16 +381,62 @@ pub struct FfmpegDuration {
 pub struct FfmpegOutput {
   pub to: String,
   pub index: u32,
+  /// The muxer, e.g. `mp4`, `rawvideo`, `null` or `tee`.
+  pub format: Option<String>,
+  /// The destinations of a `tee` output, each with the format given by its
+  /// `[f=...]` option. They share the streams of this output rather than
+  /// having streams of their own. Empty for other muxers.
+  pub children: Vec<FfmpegOutput>,
   pub raw_log_message: String,
 }
 
 impl FfmpegOutput {
-  /// Detects one of several identifiers which indicate output to stdout
+  /// Detects one of several identifiers which indicate output to stdout. A
+  /// `tee` output counts if any of its destinations is stdout, while the
+  /// `null` muxer never writes anything even when pointed at stdout.
   pub fn is_stdout(&self) -> bool {
-    ["pipe", "pipe:", "pipe:1"].contains(&self.to.as_str())
+    if self.is_null() {
+      return false;
+    }
+    ["pipe", "pipe:", "pipe:1", "-"].contains(&self.to.as_str())
+      || self.children.iter().any(|child| child.is_stdout())
+  }
+
+  /// Whether the output discards everything, either via the `null` muxer
+  /// (`-f null -`) or by writing to the null device.
+  pub fn is_null(&self) -> bool {
+    self.format.as_deref() == Some("null")
+      || ["/dev/null", "NUL", "nul"].contains(&self.to.as_str())
+  }
+
+  pub fn is_tee(&self) -> bool {
+    self.format.as_deref() == Some("tee")
   }
 }
 
+/// One line of the "Stream mapping:" section, which shows how an input
+/// stream is routed to an output stream, e.g. `Stream #0:0 -> #0:0 (h264
+/// (native) -> libx264)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamMapping {
+  pub from_input: u32,
+  pub from_stream: u32,
+  /// The output file index, or `None` if the stream feeds a filtergraph.
+  pub to_output: Option<u32>,
+  /// The output stream index, or `None` if the stream feeds a filtergraph.
+  pub to_stream: Option<u32>,
+  /// The filtergraph input the stream is connected to, e.g. `overlay` or
+  /// `[in]`, when using `-filter_complex`.
+  pub filter: Option<String>,
+  /// The decoder implementation, e.g. `h264` or `libdav1d`.
+  pub decoder: Option<String>,
+  /// The encoder implementation, e.g. `libx264`.
+  pub encoder: Option<String>,
+  /// Whether the stream is copied without re-encoding (`-c copy`).
+  pub copy: bool,
+  pub raw_log_message: String,
+}
+
 /// Represents metadata about a stream.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Stream {
@@ -75,6 +448,15 @@ pub struct Stream {
   pub parent_index: u32,
   /// The index of the stream inside the input.
   pub stream_index: u32,
+  /// The bitrate from the stream line, e.g. `46080 kb/s`. For output streams
+  /// this is the target bitrate of the encoder, if it has one.
+  pub bitrate_kbps: Option<f32>,
+  /// The encoder which actually produces an output stream, e.g. `libx264`,
+  /// from the `encoder` metadata echoed beneath the stream line. Always
+  /// `None` in `ParsedOutputStream` events, which are emitted before that
+  /// metadata is logged; it follows as a `ParsedOutputStreamEncoder` event,
+  /// and is filled in for the streams of `FfmpegMetadata`.
+  pub encoder: Option<String>,
   /// The stderr line that this stream was parsed from.
   pub raw_log_message: String,
   // Data that is specific to a certain stream type.
@@ -168,8 +550,14 @@ pub struct FfmpegProgress {
   /// Current total size of the output in kilobytes
   pub size_kb: u32,
 
-  /// The raw time string in a format like `00:03:29.04`
-  pub time: String,
+  /// The position in the output as logged, in a format like `00:03:29.04`,
+  /// or `N/A` before the first timestamp.
+  pub time: FfmpegTimeDuration,
+
+  /// Whether `time` was negative, like the bogus `-577014:32:22.77` some
+  /// versions of FFmpeg report before the first timestamp is known, and so is
+  /// [`elapsed`](Self::elapsed) clamped to zero.
+  pub time_clamped: bool,
 
   /// Bitrate in kilo**bits** per second
   pub bitrate_kbps: f32,
@@ -180,10 +568,38 @@ pub struct FfmpegProgress {
   /// - 2x means 2 seconds of input are processed in 1 second of wall clock time
   pub speed: f32,
 
+  /// The position in the output in microseconds, as reported by `-progress`
+  /// (`out_time_us`). See `FfmpegCommand::structured_progress`.
+  pub out_time_us: Option<i64>,
+
+  /// Exact total size of the output in bytes, as reported by `-progress`
+  /// (`total_size`).
+  pub total_size: Option<u64>,
+
+  /// Frames duplicated to keep the output frame rate, if reported.
+  pub dup_frames: Option<u64>,
+
+  /// Frames dropped to keep the output frame rate, if reported.
+  pub drop_frames: Option<u64>,
+
   /// The line that this progress was parsed from
   pub raw_log_message: String,
 }
 
+impl FfmpegProgress {
+  /// The position in the output, or `None` before the first timestamp.
+  /// Negative times are clamped to zero; see `time_clamped`.
+  pub fn elapsed(&self) -> Option<Duration> {
+    if let Some(micros) = self.out_time_us {
+      return Some(Duration::from_micros(micros.max(0) as u64));
+    }
+    match self.time.as_secs_f64()? < 0.0 {
+      true => Some(Duration::ZERO),
+      false => self.time.as_duration(),
+    }
+  }
+}
+
 #[derive(Clone, PartialEq)]
 pub struct OutputVideoFrame {
   /// The width of this video frame in pixels
@@ -203,6 +619,43 @@ pub struct OutputVideoFrame {
   pub frame_num: u32,
   /// Output frame timestamp in seconds
   pub timestamp: f32,
+  /// The tag of the output set with `FfmpegCommand::scaled_outputs`, or the
+  /// label of an `FfmpegCommand::output_pipe`, if any.
+  pub output_tag: Option<String>,
+}
+
+/// Durations from spawning the FFmpeg process to each milestone of its
+/// startup, for diagnosing slow starts caused by input probing, network
+/// connections or hardware device initialization. A milestone which wasn't
+/// reached is `None`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StartupTimings {
+  /// The first line logged on stderr.
+  pub first_log: Option<Duration>,
+  /// All input and output streams have been parsed from the logs.
+  pub metadata_complete: Option<Duration>,
+  /// The first frame or chunk of output read from stdout.
+  pub first_output: Option<Duration>,
+}
+
+impl OutputVideoFrame {
+  /// A 64-bit hash of the pixel data, for detecting duplicate frames. Uses
+  /// XXH3 with the `xxhash` feature, which is several times faster on large
+  /// frames, and the standard library's SipHash otherwise. Hashes are only
+  /// comparable within one build.
+  pub fn hash(&self) -> u64 {
+    #[cfg(feature = "xxhash")]
+    {
+      xxhash_rust::xxh3::xxh3_64(&self.data)
+    }
+    #[cfg(not(feature = "xxhash"))]
+    {
+      use std::hash::{Hash, Hasher};
+      let mut hasher = std::collections::hash_map::DefaultHasher::new();
+      self.data.hash(&mut hasher);
+      hasher.finish()
+    }
+  }
 }
 
 impl std::fmt::Debug for OutputVideoFrame {
@@ -213,8 +666,106 @@ impl std::fmt::Debug for OutputVideoFrame {
       .field("height", &self.height)
       .field("pix_fmt", &self.pix_fmt)
       .field("output_index", &self.output_index)
+      .field("output_tag", &self.output_tag)
       .finish()
   }
 }
 
+/// A block of raw PCM audio read from stdout, analogous to
+/// [`OutputVideoFrame`]. Blocks hold whole sample frames (one sample per
+/// channel), but their length depends on how much data each read returned.
+#[derive(Clone, PartialEq)]
+pub struct OutputAudioSamples {
+  pub sample_rate: u32,
+  pub channels: usize,
+  /// The format of the bytes FFmpeg wrote, corresponding to the `-f` or
+  /// `-c:a` option, e.g. `s16le`.
+  pub sample_format: SampleFormat,
+  /// The index of the FFmpeg output stream that emitted these samples.
+  pub output_index: u32,
+  /// Interleaved samples, `channels` per sample frame.
+  pub samples: AudioSamples,
+  /// Timestamp of the first sample frame in seconds, counted from the
+  /// samples read so far.
+  pub timestamp: f32,
+}
+
+impl OutputAudioSamples {
+  /// The number of sample frames, i.e. samples per channel.
+  pub fn frames(&self) -> usize {
+    self.samples.len() / self.channels.max(1)
+  }
+
+  /// The length of audio in this block.
+  pub fn duration(&self) -> Duration {
+    Duration::from_secs_f64(self.frames() as f64 / self.sample_rate.max(1) as f64)
+  }
+}
+
+impl std::fmt::Debug for OutputAudioSamples {
+  /// Omit the sample data from the debug output
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("OutputAudioSamples")
+      .field("sample_rate", &self.sample_rate)
+      .field("channels", &self.channels)
+      .field("sample_format", &self.sample_format)
+      .field("output_index", &self.output_index)
+      .field("frames", &self.frames())
+      .field("timestamp", &self.timestamp)
+      .finish()
+  }
+}
+
+/// Interleaved PCM samples in their native type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AudioSamples {
+  U8(Vec<u8>),
+  S16(Vec<i16>),
+  S32(Vec<i32>),
+  F32(Vec<f32>),
+  F64(Vec<f64>),
+}
+
+impl AudioSamples {
+  /// The number of samples across all channels.
+  pub fn len(&self) -> usize {
+    match self {
+      AudioSamples::U8(samples) => samples.len(),
+      AudioSamples::S16(samples) => samples.len(),
+      AudioSamples::S32(samples) => samples.len(),
+      AudioSamples::F32(samples) => samples.len(),
+      AudioSamples::F64(samples) => samples.len(),
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// The samples normalized to `-1.0..=1.0`, for processing independently of
+  /// the sample format.
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::event::AudioSamples;
+  ///
+  /// let samples = AudioSamples::S16(vec![0, 16384, i16::MIN]);
+  /// assert_eq!(samples.to_f32(), vec![0.0, 0.5, -1.0]);
+  /// ```
+  pub fn to_f32(&self) -> Vec<f32> {
+    match self {
+      AudioSamples::U8(samples) => samples
+        .iter()
+        .map(|&s| (s as f32 - 128.0) / 128.0)
+        .collect(),
+      AudioSamples::S16(samples) => samples.iter().map(|&s| s as f32 / 32768.0).collect(),
+      AudioSamples::S32(samples) => samples
+        .iter()
+        .map(|&s| (s as f64 / 2147483648.0) as f32)
+        .collect(),
+      AudioSamples::F32(samples) => samples.clone(),
+      AudioSamples::F64(samples) => samples.iter().map(|&s| s as f32).collect(),
+    }
+  }
+}
+
 // TODO fix the output for OutputChunk also
@@ -0,0 +1,121 @@
+//! Lifting custom values out of FFmpeg's log output, such as the per-frame
+//! metadata printed by `ametadata=print` or the stats of the `psnr` filter,
+//! without changes to the log parser.
+
+use std::{fmt, str::FromStr, sync::Arc};
+
+type Matcher = dyn Fn(&str) -> Option<Vec<(String, String)>> + Send + Sync;
+
+/// A named matcher which is run on every log line. Each line it matches
+/// produces an `FfmpegEvent::Extracted` right after the `Log` (or
+/// `FilterMetadata`) event of the line. Register extractors with
+/// `FfmpegCommand::extract`.
+///
+/// The matcher returns the fields it found in the line, or `None` if the line
+/// isn't relevant. It can be any parser, such as a `Regex` from the `regex`
+/// crate, or plain string matching:
+///
+/// ```rust
+/// use ffmpeg_sidecar::extract::LogExtractor;
+///
+/// let psnr = LogExtractor::new("psnr", |line| {
+///   let stats = line.split_once("] PSNR ")?.1;
+///   let fields = stats.split_whitespace().filter_map(|field| field.split_once(':'));
+///   Some(fields.map(|(k, v)| (k.to_string(), v.to_string())).collect())
+/// });
+/// let line = "[Parsed_psnr_0 @ 0x55d1] [info] PSNR y:33.21 u:38.90 v:39.04 average:34.38";
+/// let extracted = psnr.extract(line).unwrap();
+/// assert_eq!(extracted.parse::<f64>("average"), Some(34.38));
+/// ```
+#[derive(Clone)]
+pub struct LogExtractor {
+  name: String,
+  matcher: Arc<Matcher>,
+}
+
+impl LogExtractor {
+  pub fn new<S, F>(name: S, matcher: F) -> Self
+  where
+    S: Into<String>,
+    F: Fn(&str) -> Option<Vec<(String, String)>> + Send + Sync + 'static,
+  {
+    Self {
+      name: name.into(),
+      matcher: Arc::new(matcher),
+    }
+  }
+
+  /// Match lines containing `key=value`, as logged by `ametadata=print` and
+  /// `metadata=print`, extracting the value up to the next whitespace as the
+  /// field `key`.
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::extract::LogExtractor;
+  ///
+  /// let momentary = LogExtractor::key_value("loudness", "lavfi.r128.M");
+  /// let line = "[Parsed_ametadata_1 @ 0x5581] [info] lavfi.r128.M=-23.512";
+  /// let extracted = momentary.extract(line).unwrap();
+  /// assert_eq!(extracted.parse::<f32>("lavfi.r128.M"), Some(-23.512));
+  /// assert!(momentary.extract("[info] lavfi.r128.S=-22.1").is_none());
+  /// ```
+  pub fn key_value<S: Into<String>>(name: S, key: &str) -> Self {
+    let key = key.to_string();
+    let pattern = format!("{key}=");
+    Self::new(name, move |line| {
+      let value = line
+        .match_indices(&pattern)
+        .filter(|(i, _)| {
+          let before = line[..*i].chars().next_back();
+          before.map_or(true, |c| c.is_whitespace() || c == ']')
+        })
+        .find_map(|(i, _)| line[i + pattern.len()..].split_whitespace().next())?;
+      Some(vec![(key.clone(), value.to_string())])
+    })
+  }
+
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  /// Run the matcher on one log line.
+  pub fn extract(&self, line: &str) -> Option<Extracted> {
+    let fields = (self.matcher)(line)?;
+    Some(Extracted {
+      name: self.name.clone(),
+      fields,
+      raw_log_message: line.to_string(),
+    })
+  }
+}
+
+impl fmt::Debug for LogExtractor {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("LogExtractor")
+      .field("name", &self.name)
+      .finish_non_exhaustive()
+  }
+}
+
+/// The fields matched in one log line by the [`LogExtractor`] named `name`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Extracted {
+  pub name: String,
+  pub fields: Vec<(String, String)>,
+  pub raw_log_message: String,
+}
+
+impl Extracted {
+  /// The value of the first field named `key`.
+  pub fn get(&self, key: &str) -> Option<&str> {
+    self
+      .fields
+      .iter()
+      .find(|(name, _)| name == key)
+      .map(|(_, value)| value.as_str())
+  }
+
+  /// The value of the first field named `key`, parsed into a typed value.
+  pub fn parse<T: FromStr>(&self, key: &str) -> Option<T> {
+    self.get(key)?.parse().ok()
+  }
+}
@@ -0,0 +1,325 @@
+//! Reader and writer for FFmpeg's metadata file format (`FFMETADATA1`).
+//!
+//! Metadata files hold global tags, per-stream tags and chapters. They can be
+//! exported from a media file with `-f ffmetadata`, and applied to an output by
+//! adding the file as an extra input and selecting it with `-map_metadata` and
+//! `-map_chapters`:
+//!
+//! ```rust,no_run
+//! use ffmpeg_sidecar::{
+//!   command::FfmpegCommand,
+//!   ffmetadata::{self, Chapter, Metadata},
+//! };
+//! use std::time::Duration;
+//!
+//! let mut metadata = Metadata::default();
+//! metadata.tags.push(("title".into(), "Highlights".into()));
+//! metadata.chapters.push(Chapter::new(Duration::ZERO, Duration::from_secs(60), "Intro"));
+//! ffmetadata::write(&metadata, "chapters.txt")?;
+//!
+//! FfmpegCommand::new()
+//!   .input("input.mp4")
+//!   .format("ffmetadata")
+//!   .input("chapters.txt")
+//!   .map("0")
+//!   .map_metadata("1")
+//!   .map_chapters(1)
+//!   .codec_video("copy")
+//!   .codec_audio("copy")
+//!   .output("output.mp4")
+//!   .spawn()?
+//!   .wait()?;
+//! # anyhow::Ok(())
+//! ```
+//!
+//! See <https://ffmpeg.org/ffmpeg-formats.html#Metadata-2>.
+
+use std::{fmt, fs, path::Path, time::Duration};
+
+use anyhow::{bail, Context};
+
+/// The first line of every metadata file.
+pub const HEADER: &str = ";FFMETADATA1";
+
+/// An ordered list of `key=value` tags. Duplicate keys are preserved.
+pub type Tags = Vec<(String, String)>;
+
+/// The contents of a metadata file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+  /// Global (container-level) tags.
+  pub tags: Tags,
+  /// Tags for each `[STREAM]` section, in stream order.
+  pub streams: Vec<Tags>,
+  pub chapters: Vec<Chapter>,
+}
+
+/// A `[CHAPTER]` section. `start` and `end` are expressed in units of
+/// `timebase`, a `(numerator, denominator)` fraction of a second.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chapter {
+  pub timebase: (u64, u64),
+  pub start: u64,
+  pub end: u64,
+  pub tags: Tags,
+}
+
+impl Chapter {
+  /// A chapter with millisecond precision and a `title` tag.
+  pub fn new<S: Into<String>>(start: Duration, end: Duration, title: S) -> Self {
+    Self {
+      timebase: (1, 1000),
+      start: start.as_millis() as u64,
+      end: end.as_millis() as u64,
+      tags: vec![("title".to_string(), title.into())],
+    }
+  }
+
+  pub fn start_time(&self) -> Duration {
+    self.to_duration(self.start)
+  }
+
+  pub fn end_time(&self) -> Duration {
+    self.to_duration(self.end)
+  }
+
+  /// The value of the `title` tag, if any.
+  pub fn title(&self) -> Option<&str> {
+    self
+      .tags
+      .iter()
+      .find(|(key, _)| key == "title")
+      .map(|(_, value)| value.as_str())
+  }
+
+  fn to_duration(&self, ticks: u64) -> Duration {
+    let (num, den) = self.timebase;
+    let nanos = ticks as u128 * num as u128 * 1_000_000_000 / den.max(1) as u128;
+    Duration::from_nanos(nanos as u64)
+  }
+}
+
+/// Read and parse a metadata file.
+pub fn read<P: AsRef<Path>>(path: P) -> anyhow::Result<Metadata> {
+  let path = path.as_ref();
+  let contents =
+    fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+  parse(&contents)
+}
+
+/// Serialize metadata and write it to a file.
+pub fn write<P: AsRef<Path>>(metadata: &Metadata, path: P) -> anyhow::Result<()> {
+  let path = path.as_ref();
+  fs::write(path, metadata.to_string())
+    .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Parse the contents of a metadata file.
+///
+/// ```rust
+/// use ffmpeg_sidecar::ffmetadata::parse;
+///
+/// let metadata = parse(";FFMETADATA1\ntitle=bike\\\\shed\n[CHAPTER]\nTIMEBASE=1/1000\nSTART=0\nEND=60000\ntitle=chapter \\#1\n")?;
+/// assert_eq!(metadata.tags, vec![("title".to_string(), "bike\\shed".to_string())]);
+/// assert_eq!(metadata.chapters[0].title(), Some("chapter #1"));
+/// assert_eq!(metadata.chapters[0].end_time().as_secs(), 60);
+/// # anyhow::Ok(())
+/// ```
+pub fn parse(contents: &str) -> anyhow::Result<Metadata> {
+  let mut lines = logical_lines(contents).into_iter();
+  match lines.next() {
+    Some(line) if unescape(&line) == HEADER => {}
+    _ => bail!("Missing {HEADER} header"),
+  }
+
+  enum Section {
+    Global,
+    Stream,
+    Chapter,
+  }
+  let mut metadata = Metadata::default();
+  let mut section = Section::Global;
+
+  for line in lines {
+    match line.first() {
+      None | Some((';' | '#', false)) => continue,
+      _ => {}
+    }
+    match unescape(&line).as_str() {
+      "[STREAM]" => {
+        metadata.streams.push(Tags::new());
+        section = Section::Stream;
+        continue;
+      }
+      "[CHAPTER]" => {
+        metadata.chapters.push(Chapter {
+          timebase: (1, 1000),
+          start: 0,
+          end: 0,
+          tags: Tags::new(),
+        });
+        section = Section::Chapter;
+        continue;
+      }
+      _ => {}
+    }
+
+    let split = line.iter().position(|&c| c == ('=', false));
+    let Some(split) = split else {
+      bail!("Invalid metadata line: {}", unescape(&line));
+    };
+    let key = unescape(&line[..split]);
+    let value = unescape(&line[split + 1..]);
+
+    match section {
+      Section::Global => metadata.tags.push((key, value)),
+      Section::Stream => {
+        if let Some(tags) = metadata.streams.last_mut() {
+          tags.push((key, value));
+        }
+      }
+      Section::Chapter => {
+        let Some(chapter) = metadata.chapters.last_mut() else {
+          continue;
+        };
+        match key.as_str() {
+          "TIMEBASE" => {
+            let (num, den) = value.split_once('/').context("Invalid chapter TIMEBASE")?;
+            chapter.timebase = (num.trim().parse()?, den.trim().parse()?);
+          }
+          "START" => chapter.start = value.trim().parse().context("Invalid chapter START")?,
+          "END" => chapter.end = value.trim().parse().context("Invalid chapter END")?,
+          _ => chapter.tags.push((key, value)),
+        }
+      }
+    }
+  }
+
+  Ok(metadata)
+}
+
+/// Split into lines, resolving backslash escapes. Each character is paired
+/// with whether it was escaped, so that escaped `=`, `;` and `#` can be told
+/// apart from syntax. An escaped newline continues the current line.
+fn logical_lines(contents: &str) -> Vec<Vec<(char, bool)>> {
+  let mut lines = Vec::new();
+  let mut line = Vec::new();
+  let mut chars = contents.chars();
+  while let Some(c) = chars.next() {
+    match c {
+      '\\' => {
+        if let Some(escaped) = chars.next() {
+          line.push((escaped, true));
+        }
+      }
+      '\n' => lines.push(std::mem::take(&mut line)),
+      '\r' => {}
+      c => line.push((c, false)),
+    }
+  }
+  if !line.is_empty() {
+    lines.push(line);
+  }
+  lines
+}
+
+fn unescape(chars: &[(char, bool)]) -> String {
+  chars.iter().map(|(c, _)| c).collect()
+}
+
+fn escape(value: &str) -> String {
+  let mut escaped = String::with_capacity(value.len());
+  for c in value.chars() {
+    if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+      escaped.push('\\');
+    }
+    escaped.push(c);
+  }
+  escaped
+}
+
+fn write_tags(f: &mut fmt::Formatter<'_>, tags: &Tags) -> fmt::Result {
+  for (key, value) in tags {
+    writeln!(f, "{}={}", escape(key), escape(value))?;
+  }
+  Ok(())
+}
+
+impl fmt::Display for Metadata {
+  /// Serialize in the `FFMETADATA1` format.
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(f, "{HEADER}")?;
+    write_tags(f, &self.tags)?;
+    for stream in &self.streams {
+      writeln!(f, "[STREAM]")?;
+      write_tags(f, stream)?;
+    }
+    for chapter in &self.chapters {
+      writeln!(f, "[CHAPTER]")?;
+      writeln!(f, "TIMEBASE={}/{}", chapter.timebase.0, chapter.timebase.1)?;
+      writeln!(f, "START={}", chapter.start)?;
+      writeln!(f, "END={}", chapter.end)?;
+      write_tags(f, &chapter.tags)?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_ffmetadata() {
+    let contents = [
+      ";FFMETADATA1",
+      "title=bike\\\\shed",
+      ";this is a comment",
+      "artist=FFmpeg troll team",
+      "",
+      "[CHAPTER]",
+      "TIMEBASE=1/1000",
+      "START=0",
+      "#chapter ends at 0:01:00",
+      "END=60000",
+      "title=chapter \\#1",
+      "[STREAM]",
+      "title=multi\\",
+      "line",
+    ]
+    .join("\n");
+    let metadata = parse(&contents).unwrap();
+
+    assert_eq!(metadata.tags.len(), 2);
+    assert_eq!(metadata.tags[0].1, "bike\\shed");
+    assert_eq!(metadata.chapters.len(), 1);
+    assert_eq!(metadata.chapters[0].end_time(), Duration::from_secs(60));
+    assert_eq!(metadata.chapters[0].title(), Some("chapter #1"));
+    assert_eq!(metadata.streams[0][0].1, "multi\nline");
+  }
+
+  #[test]
+  fn test_ffmetadata_round_trip() {
+    let metadata = Metadata {
+      tags: vec![("comment".into(), "a=b; #c\\d\ne".into())],
+      streams: vec![vec![("language".into(), "eng".into())]],
+      chapters: vec![
+        Chapter::new(Duration::ZERO, Duration::from_millis(1500), "One"),
+        Chapter {
+          timebase: (1, 90000),
+          start: 135000,
+          end: 270000,
+          tags: vec![],
+        },
+      ],
+    };
+    let parsed = parse(&metadata.to_string()).unwrap();
+    assert_eq!(parsed, metadata);
+    assert_eq!(parsed.chapters[1].start_time(), Duration::from_millis(1500));
+  }
+
+  #[test]
+  fn test_ffmetadata_missing_header() {
+    assert!(parse("title=test\n").is_err());
+  }
+}
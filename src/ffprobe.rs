@@ -1,13 +1,26 @@
 //! Utilities related to the FFprobe binary.
 
-use crate::command::BackgroundCommand;
+use crate::{
+  command::BackgroundCommand,
+  event::VideoStream,
+  ffmetadata::{Chapter, Tags},
+  json::Json,
+  metadata::parse_rate,
+};
 use anyhow::Context;
-use std::{env::current_exe, ffi::OsStr, path::PathBuf};
+use std::{env::current_exe, ffi::OsStr, fmt, path::PathBuf};
 use std::{
+  io::Read,
   path::Path,
   process::{Command, Stdio},
+  thread,
+  time::{Duration, Instant},
 };
 
+/// How long ffprobe may run before it's killed, e.g. while waiting on a
+/// network input which stopped responding.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// Returns the path of the downloaded FFprobe executable, or falls back to
 /// assuming its installed in the system path. Note that not all FFmpeg
 /// distributions include FFprobe.
@@ -68,3 +81,471 @@ pub fn ffprobe_is_installed() -> bool {
     .map(|s| s.success())
     .unwrap_or_else(|_| false)
 }
+
+/// The duration of the media at `url` in seconds, as probed by ffprobe, or
+/// `None` if it has none, like a live stream. `format` forces the demuxer,
+/// like `-f` before an FFmpeg input.
+pub fn ffprobe_duration<S: AsRef<OsStr>>(
+  url: S,
+  format: Option<&str>,
+) -> anyhow::Result<Option<f64>> {
+  let input_options = format.map(|format| vec!["-f", format]);
+  let json = ffprobe_json(
+    url.as_ref(),
+    input_options.as_deref().unwrap_or_default(),
+    &["-show_entries", "format=duration"],
+  )?;
+  Ok(duration(&json))
+}
+
+/// A program of an MPEG-TS or other broadcast input: a service, with its own
+/// set of streams, multiplexed with others into one transport stream.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProbedProgram {
+  /// The program ID, as used by `-map 0:p:<program_id>`.
+  pub program_id: u32,
+  /// The program number from the program association table.
+  pub program_num: u32,
+  pub pmt_pid: Option<u32>,
+  pub pcr_pid: Option<u32>,
+  /// Tags such as `service_name` and `service_provider`.
+  pub tags: Tags,
+  pub streams: Vec<ProgramStream>,
+}
+
+/// A stream belonging to a [`ProbedProgram`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProgramStream {
+  /// The index of the stream within the input, as in `-map 0:<index>`.
+  pub index: u32,
+  /// The format specific stream ID, e.g. the PID `0x100` in MPEG-TS.
+  pub id: Option<String>,
+  /// `video`, `audio`, `subtitle` or `data`.
+  pub codec_type: String,
+  pub codec_name: Option<String>,
+  pub language: Option<String>,
+}
+
+impl ProbedProgram {
+  /// The value of the tag `key`, if any.
+  pub fn tag(&self, key: &str) -> Option<&str> {
+    self
+      .tags
+      .iter()
+      .find(|(tag, _)| tag == key)
+      .map(|(_, value)| value.as_str())
+  }
+
+  /// The value of the `service_name` tag, if any.
+  pub fn service_name(&self) -> Option<&str> {
+    self.tag("service_name")
+  }
+
+  /// The stream specifier selecting this program's streams from the input
+  /// with index `input_index`, for `-map`.
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::ffprobe::ProbedProgram;
+  ///
+  /// let program = ProbedProgram { program_id: 2, ..Default::default() };
+  /// assert_eq!(program.map_specifier(0), "0:p:2");
+  /// ```
+  pub fn map_specifier(&self, input_index: u32) -> String {
+    format!("{input_index}:p:{}", self.program_id)
+  }
+}
+
+/// The input has no program with the requested ID. Holds the programs it
+/// does have.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProgramNotFound {
+  pub requested: u32,
+  pub available: Vec<ProbedProgram>,
+}
+
+impl fmt::Display for ProgramNotFound {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let requested = self.requested;
+    if self.available.is_empty() {
+      return write!(f, "Program {requested} not found; the input has none");
+    }
+    let programs: Vec<String> = self
+      .available
+      .iter()
+      .map(|program| match program.service_name() {
+        Some(name) => format!("{} ({name})", program.program_id),
+        None => program.program_id.to_string(),
+      })
+      .collect();
+    write!(
+      f,
+      "Program {requested} not found; available: {}",
+      programs.join(", ")
+    )
+  }
+}
+
+impl std::error::Error for ProgramNotFound {}
+
+/// The chapters of the media at `url`, as probed by ffprobe with
+/// `-show_chapters`.
+///
+/// ```rust,no_run
+/// use ffmpeg_sidecar::ffprobe::ffprobe_chapters;
+///
+/// for chapter in ffprobe_chapters("audiobook.m4b")? {
+///   println!("{:?} {}", chapter.start_time(), chapter.title().unwrap_or("-"));
+/// }
+/// # anyhow::Ok(())
+/// ```
+pub fn ffprobe_chapters<S: AsRef<OsStr>>(url: S) -> anyhow::Result<Vec<Chapter>> {
+  Ok(parse_chapters(&ffprobe_json(
+    url.as_ref(),
+    &[],
+    &["-show_chapters"],
+  )?))
+}
+
+/// The programs of the media at `url`, as probed by ffprobe with
+/// `-show_programs`. Inputs without programs, i.e. anything other than
+/// MPEG-TS and similar broadcast formats, have none.
+///
+/// ```rust,no_run
+/// use ffmpeg_sidecar::{command::FfmpegCommand, ffprobe::ffprobe_programs};
+///
+/// let programs = ffprobe_programs("capture.ts")?;
+/// let news = programs
+///   .iter()
+///   .find(|program| program.service_name() == Some("News"))
+///   .ok_or(anyhow::anyhow!("No news channel"))?;
+/// FfmpegCommand::new()
+///   .input("capture.ts")
+///   .map(news.map_specifier(0))
+///   .codec_video("copy")
+///   .codec_audio("copy")
+///   .output("news.ts")
+///   .spawn()?
+///   .wait()?;
+/// # anyhow::Ok(())
+/// ```
+pub fn ffprobe_programs<S: AsRef<OsStr>>(url: S) -> anyhow::Result<Vec<ProbedProgram>> {
+  ffprobe_input_programs(url.as_ref(), &[])
+}
+
+/// Like [`ffprobe_programs`], with `input_options`, like `-f`, given before
+/// the input.
+pub(crate) fn ffprobe_input_programs(
+  url: &OsStr,
+  input_options: &[&str],
+) -> anyhow::Result<Vec<ProbedProgram>> {
+  Ok(parse_programs(&ffprobe_json(
+    url,
+    input_options,
+    &["-show_programs"],
+  )?))
+}
+
+/// The first video stream of `url` and the duration of the input, as probed
+/// by ffprobe. `input_options`, like `-f`, are given before the input.
+pub(crate) fn ffprobe_video(
+  url: &OsStr,
+  input_options: &[&str],
+) -> anyhow::Result<(VideoStream, Option<f64>)> {
+  let json = ffprobe_json(
+    url,
+    input_options,
+    &[
+      "-select_streams",
+      "v:0",
+      "-show_entries",
+      "stream=width,height,pix_fmt,r_frame_rate:format=duration",
+    ],
+  )?;
+  let stream = json.get("streams").map(Json::elements).unwrap_or_default();
+  let stream = stream.first().unwrap_or(&Json::Null);
+  let video = VideoStream {
+    pix_fmt: stream
+      .get("pix_fmt")
+      .and_then(Json::as_str)
+      .unwrap_or_default()
+      .to_string(),
+    width: stream.get("width").and_then(Json::parse_as).unwrap_or(0),
+    height: stream.get("height").and_then(Json::parse_as).unwrap_or(0),
+    fps: stream
+      .get("r_frame_rate")
+      .and_then(Json::as_str)
+      .and_then(parse_rate)
+      .unwrap_or(0.0),
+  };
+  anyhow::ensure!(
+    video.width > 0,
+    "ffprobe found no video stream in {}",
+    url.to_string_lossy()
+  );
+  Ok((video, duration(&json)))
+}
+
+/// Run ffprobe on the input `url`, preceded by `input_options`, asking for
+/// the sections in `show` as JSON. ffprobe is killed if it hasn't finished
+/// within [`PROBE_TIMEOUT`].
+pub(crate) fn ffprobe_json(
+  url: &OsStr,
+  input_options: &[&str],
+  show: &[&str],
+) -> anyhow::Result<Json> {
+  let mut child = Command::new(ffprobe_path())
+    .create_no_window()
+    .args(["-v", "error", "-of", "json"])
+    .args(show)
+    .args(input_options)
+    .arg(url)
+    .stdin(Stdio::null())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .context("Failed to run ffprobe")?;
+  let read_to_end = |mut pipe: Box<dyn Read + Send>| {
+    thread::spawn(move || {
+      let mut bytes = Vec::new();
+      pipe.read_to_end(&mut bytes).ok();
+      bytes
+    })
+  };
+  let stdout = child.stdout.take().map(|pipe| read_to_end(Box::new(pipe)));
+  let stderr = child.stderr.take().map(|pipe| read_to_end(Box::new(pipe)));
+
+  let deadline = Instant::now() + PROBE_TIMEOUT;
+  let status = loop {
+    if let Some(status) = child.try_wait()? {
+      break status;
+    }
+    if Instant::now() >= deadline {
+      child.kill().ok();
+      child.wait().ok();
+      anyhow::bail!("ffprobe timed out after {PROBE_TIMEOUT:?}");
+    }
+    thread::sleep(Duration::from_millis(10));
+  };
+  let joined = |reader: Option<thread::JoinHandle<Vec<u8>>>| {
+    let bytes = reader.and_then(|reader| reader.join().ok());
+    String::from_utf8_lossy(&bytes.unwrap_or_default()).into_owned()
+  };
+  let (stdout, stderr) = (joined(stdout), joined(stderr));
+  anyhow::ensure!(status.success(), "ffprobe failed: {}", stderr.trim());
+  Json::parse(&stdout).context("ffprobe wrote invalid JSON")
+}
+
+/// The `format=duration` entry of ffprobe's output, if known.
+fn duration(json: &Json) -> Option<f64> {
+  json
+    .get("format")
+    .and_then(|format| format.get("duration"))
+    .and_then(Json::parse_as)
+}
+
+/// The `tags` of a section of ffprobe's output.
+fn tags(section: &Json) -> Tags {
+  let tags = section.get("tags").map(Json::members).unwrap_or_default();
+  tags
+    .iter()
+    .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_string())))
+    .collect()
+}
+
+/// Parse the `chapters` of ffprobe's output.
+fn parse_chapters(output: &Json) -> Vec<Chapter> {
+  let chapters = output
+    .get("chapters")
+    .map(Json::elements)
+    .unwrap_or_default();
+  chapters
+    .iter()
+    .map(|chapter| {
+      let time_base = chapter.get("time_base").and_then(Json::as_str);
+      let (num, den) = time_base
+        .and_then(|time_base| time_base.split_once('/'))
+        .unwrap_or(("1", "1"));
+      Chapter {
+        timebase: (num.parse().unwrap_or(1), den.parse().unwrap_or(1)),
+        // Chapters starting before zero are clamped to it
+        start: chapter.get("start").and_then(Json::parse_as).unwrap_or(0),
+        end: chapter.get("end").and_then(Json::parse_as).unwrap_or(0),
+        tags: tags(chapter),
+      }
+    })
+    .collect()
+}
+
+/// Parse the `programs` of ffprobe's output, along with the `streams` nested
+/// inside them.
+fn parse_programs(output: &Json) -> Vec<ProbedProgram> {
+  let programs = output
+    .get("programs")
+    .map(Json::elements)
+    .unwrap_or_default();
+  programs
+    .iter()
+    .map(|program| {
+      let streams = program
+        .get("streams")
+        .map(Json::elements)
+        .unwrap_or_default();
+      ProbedProgram {
+        program_id: program
+          .get("program_id")
+          .and_then(Json::parse_as)
+          .unwrap_or_default(),
+        program_num: program
+          .get("program_num")
+          .and_then(Json::parse_as)
+          .unwrap_or_default(),
+        pmt_pid: program.get("pmt_pid").and_then(Json::parse_as),
+        pcr_pid: program.get("pcr_pid").and_then(Json::parse_as),
+        tags: tags(program),
+        streams: streams
+          .iter()
+          .map(|stream| ProgramStream {
+            index: stream
+              .get("index")
+              .and_then(Json::parse_as)
+              .unwrap_or_default(),
+            id: stream.get("id").and_then(Json::as_str).map(str::to_string),
+            codec_type: stream
+              .get("codec_type")
+              .and_then(Json::as_str)
+              .unwrap_or_default()
+              .to_string(),
+            codec_name: stream
+              .get("codec_name")
+              .and_then(Json::as_str)
+              .map(str::to_string),
+            language: stream
+              .get("tags")
+              .and_then(|tags| tags.get("language"))
+              .and_then(Json::as_str)
+              .map(str::to_string),
+          })
+          .collect(),
+      }
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::time::Duration;
+
+  #[test]
+  fn test_parse_chapters() {
+    let output = r#"{
+    "chapters": [
+        {
+            "id": 0,
+            "time_base": "1/1000",
+            "start": 0,
+            "start_time": "0.000000",
+            "end": 12500,
+            "end_time": "12.500000",
+            "tags": {
+                "title": "Intro"
+            }
+        },
+        {
+            "id": 1,
+            "time_base": "1/44100",
+            "start": 551250,
+            "start_time": "12.500000",
+            "end": 1323000,
+            "end_time": "30.000000"
+        }
+    ]
+}"#;
+    let chapters = parse_chapters(&Json::parse(output).unwrap());
+    assert_eq!(chapters.len(), 2);
+    assert_eq!(chapters[0].title(), Some("Intro"));
+    assert_eq!(chapters[0].end_time(), Duration::from_millis(12500));
+    assert_eq!(chapters[1].title(), None);
+    assert_eq!(chapters[1].start_time(), Duration::from_millis(12500));
+    assert_eq!(chapters[1].end_time(), Duration::from_secs(30));
+  }
+
+  #[test]
+  fn test_parse_programs() {
+    let output = r#"{
+    "programs": [
+        {
+            "program_id": 1,
+            "program_num": 1,
+            "nb_streams": 2,
+            "pmt_pid": 4096,
+            "pcr_pid": 256,
+            "tags": {
+                "service_name": "News",
+                "service_provider": "FFmpeg"
+            },
+            "streams": [
+                {
+                    "index": 0,
+                    "codec_name": "h264",
+                    "codec_type": "video",
+                    "id": "0x100",
+                    "disposition": {
+                        "default": 0
+                    },
+                    "tags": {
+                        "language": "und"
+                    }
+                },
+                {
+                    "index": 1,
+                    "codec_name": "mp2",
+                    "codec_type": "audio",
+                    "id": "0x101",
+                    "tags": {
+                        "language": "eng"
+                    }
+                }
+            ]
+        },
+        {
+            "program_id": 2,
+            "program_num": 2,
+            "nb_streams": 0,
+            "pmt_pid": 4097,
+            "pcr_pid": -1,
+            "streams": [
+
+            ]
+        }
+    ]
+}"#;
+    let programs = parse_programs(&Json::parse(output).unwrap());
+    assert_eq!(programs.len(), 2);
+    assert_eq!(programs[0].program_id, 1);
+    assert_eq!(programs[0].pmt_pid, Some(4096));
+    assert_eq!(programs[0].service_name(), Some("News"));
+    assert_eq!(
+      programs[0].streams[1],
+      ProgramStream {
+        index: 1,
+        id: Some("0x101".to_string()),
+        codec_type: "audio".to_string(),
+        codec_name: Some("mp2".to_string()),
+        language: Some("eng".to_string()),
+      }
+    );
+    assert_eq!(programs[1].pcr_pid, None);
+    assert!(programs[1].streams.is_empty());
+    assert_eq!(programs[1].map_specifier(1), "1:p:2");
+
+    let error = ProgramNotFound {
+      requested: 3,
+      available: programs,
+    };
+    assert_eq!(
+      error.to_string(),
+      "Program 3 not found; available: 1 (News), 2"
+    );
+  }
+}
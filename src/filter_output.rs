@@ -0,0 +1,262 @@
+//! Capture files written by filters and muxers, such as the `whisper`
+//! filter's `destination`, a `-segment_list` or a `-vstats_file`, by giving
+//! FFmpeg a managed destination and streaming what it writes back as events.
+
+use std::{
+  fs::File,
+  io::{self, BufRead, BufReader, Read},
+  path::Path,
+  sync::{
+    mpsc::{channel, Sender, SyncSender, TryRecvError},
+    Arc,
+  },
+  time::Duration,
+};
+
+use crate::{event::FfmpegEvent, tcp_output::TcpOutput, tee::escape, temp_file::TempFile};
+
+/// How long each wait for FFmpeg to connect lasts before checking whether
+/// FFmpeg has exited.
+const ACCEPT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// How often a captured file is checked for new lines.
+const TAIL_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A destination for a file written by FFmpeg, whose lines are emitted as
+/// `FfmpegEvent::FilterOutput` events once registered with
+/// `FfmpegCommand::capture_filter_output`.
+///
+/// Destinations opened through FFmpeg's I/O layer (AVIO), like filter
+/// `destination` options and muxer side files, can use a loopback
+/// [`tcp`](Self::tcp) connection. Options which FFmpeg opens as plain files,
+/// like `-vstats_file`, need a [`file`](Self::file), which is read as it
+/// grows and removed afterwards.
+///
+/// ```rust,no_run
+/// use ffmpeg_sidecar::{
+///   command::FfmpegCommand, event::FfmpegEvent, filter_output::FilterOutputCapture,
+/// };
+///
+/// let vstats = FilterOutputCapture::file("vstats")?;
+/// let iter = FfmpegCommand::new()
+///   .testsrc()
+///   .args(["-vstats_file", &vstats.destination()])
+///   .codec_video("libx264")
+///   .format("null")
+///   .output("-")
+///   .capture_filter_output(&vstats)
+///   .spawn()?
+///   .iter()?;
+/// for event in iter {
+///   if let FfmpegEvent::FilterOutput { name, line } = event {
+///     println!("{name}: {line}");
+///   }
+/// }
+/// # anyhow::Ok(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct FilterOutputCapture {
+  name: String,
+  destination: Destination,
+}
+
+#[derive(Debug, Clone)]
+enum Destination {
+  Tcp(Arc<TcpOutput>),
+  File(Arc<TempFile>),
+}
+
+impl FilterOutputCapture {
+  /// A loopback TCP listener, for destinations which FFmpeg opens as a URL.
+  pub fn tcp<S: Into<String>>(name: S) -> io::Result<Self> {
+    Ok(Self {
+      name: name.into(),
+      destination: Destination::Tcp(Arc::new(TcpOutput::bind()?)),
+    })
+  }
+
+  /// A new, empty temporary file, for destinations which FFmpeg can only
+  /// open as a local path.
+  pub fn file<S: Into<String>>(name: S) -> io::Result<Self> {
+    let name = name.into();
+    let file = TempFile::create(&name, "txt", b"")?;
+    Ok(Self {
+      name,
+      destination: Destination::File(Arc::new(file)),
+    })
+  }
+
+  /// The name reported with each line.
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  /// The URL or path to give FFmpeg as the destination.
+  pub fn destination(&self) -> String {
+    match &self.destination {
+      Destination::Tcp(output) => output.url().to_string(),
+      Destination::File(file) => file.path().to_string_lossy().to_string(),
+    }
+  }
+
+  /// The path of the captured file, if it is one.
+  pub fn path(&self) -> Option<&Path> {
+    match &self.destination {
+      Destination::Tcp(_) => None,
+      Destination::File(file) => Some(file.path()),
+    }
+  }
+
+  /// The destination escaped as the value of a filter option, e.g. in
+  /// `whisper=model=m.bin:destination=...` inside a filtergraph.
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::filter_output::FilterOutputCapture;
+  ///
+  /// let capture = FilterOutputCapture::tcp("whisper")?;
+  /// assert!(capture.filter_value().starts_with(r"tcp\\://127.0.0.1\\:"));
+  /// # anyhow::Ok(())
+  /// ```
+  pub fn filter_value(&self) -> String {
+    escape(&escape(&self.destination(), ":"), "[],;")
+  }
+}
+
+/// A background thread which reads a [`FilterOutputCapture`] and emits an
+/// `FfmpegEvent::FilterOutput` for every line. Dropping this signals that
+/// FFmpeg has exited: a TCP destination stops waiting for connections, and a
+/// file is read to the end one last time.
+pub(crate) struct FilterOutputListener {
+  _stop: Sender<()>,
+}
+
+impl FilterOutputListener {
+  pub(crate) fn spawn(capture: FilterOutputCapture, tx: SyncSender<FfmpegEvent>) -> Self {
+    let (stop, stopped) = channel::<()>();
+    let is_stopped = move || matches!(stopped.try_recv(), Err(TryRecvError::Disconnected));
+    let name = capture.name;
+    let emit = move |line: &[u8]| {
+      let line = String::from_utf8_lossy(line);
+      let line = line.trim_end_matches(['\r', '\n']).to_string();
+      tx.send(FfmpegEvent::FilterOutput {
+        name: name.clone(),
+        line,
+      })
+      .is_ok()
+    };
+
+    std::thread::spawn(move || match capture.destination {
+      // Muxers may reopen their side files, e.g. to rewrite a segment list
+      Destination::Tcp(output) => loop {
+        match output.accept(ACCEPT_TIMEOUT) {
+          Ok(stream) => {
+            if !read_lines(BufReader::new(stream), &emit) {
+              return;
+            }
+          }
+          Err(e) if e.kind() == io::ErrorKind::TimedOut => {}
+          Err(_) => return,
+        }
+        if is_stopped() {
+          return;
+        }
+      },
+      Destination::File(file) => {
+        let Ok(reader) = File::open(file.path()) else {
+          return;
+        };
+        tail_lines(BufReader::new(reader), is_stopped, emit);
+      }
+    });
+
+    Self { _stop: stop }
+  }
+}
+
+/// Emit every line until EOF, returning `false` if `emit` does because the
+/// iterator is gone.
+fn read_lines<R: BufRead>(mut reader: R, emit: impl Fn(&[u8]) -> bool) -> bool {
+  let mut line = Vec::new();
+  loop {
+    line.clear();
+    match reader.read_until(b'\n', &mut line) {
+      Ok(0) | Err(_) => return true,
+      Ok(_) => {
+        if !emit(&line) {
+          return false;
+        }
+      }
+    }
+  }
+}
+
+/// Emit lines as they're appended to the file, until it's stopped and has
+/// been read to the end.
+fn tail_lines<R: Read>(
+  mut reader: BufReader<R>,
+  is_stopped: impl Fn() -> bool,
+  emit: impl Fn(&[u8]) -> bool,
+) {
+  let mut line = Vec::new();
+  loop {
+    // Checked before reading, so that nothing written before FFmpeg exited
+    // is missed
+    let stopped = is_stopped();
+    loop {
+      match reader.read_until(b'\n', &mut line) {
+        Ok(0) | Err(_) => break,
+        Ok(_) if line.ends_with(b"\n") => {
+          if !emit(&line) {
+            return;
+          }
+          line.clear();
+        }
+        // The rest of a partial line hasn't been written yet
+        Ok(_) => {}
+      }
+    }
+    if stopped {
+      if !line.is_empty() {
+        emit(&line);
+      }
+      return;
+    }
+    std::thread::sleep(TAIL_POLL_INTERVAL);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::{fs, io::Write, sync::mpsc::sync_channel};
+
+  #[test]
+  fn test_file_capture() -> anyhow::Result<()> {
+    let capture = FilterOutputCapture::file("vstats")?;
+    let path = capture.path().unwrap().to_path_buf();
+    let (tx, rx) = sync_channel(16);
+    let listener = FilterOutputListener::spawn(capture.clone(), tx);
+
+    let mut file = fs::OpenOptions::new().append(true).open(&path)?;
+    file.write_all(b"frame= 1 q= 2.0\nframe= 2")?;
+    let event = rx.recv_timeout(Duration::from_secs(5))?;
+    assert_eq!(
+      event,
+      FfmpegEvent::FilterOutput {
+        name: "vstats".to_string(),
+        line: "frame= 1 q= 2.0".to_string(),
+      }
+    );
+
+    // The partial line is emitted once FFmpeg is done
+    file.write_all(b" q= 3.0")?;
+    drop(listener);
+    let event = rx.recv_timeout(Duration::from_secs(5))?;
+    assert!(matches!(event, FfmpegEvent::FilterOutput { line, .. } if line == "frame= 2 q= 3.0"));
+    assert!(rx.recv_timeout(Duration::from_secs(5)).is_err());
+
+    drop(capture);
+    assert!(!path.exists());
+    Ok(())
+  }
+}
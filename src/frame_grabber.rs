@@ -0,0 +1,180 @@
+//! Random access to individual video frames.
+
+use std::{
+  collections::VecDeque,
+  path::{Path, PathBuf},
+  time::Duration,
+};
+
+use anyhow::Context;
+
+use crate::{
+  child::FfmpegChild,
+  command::FfmpegCommand,
+  event::{FfmpegEvent, OutputVideoFrame},
+  ffprobe::ffprobe_video,
+  iter::FfmpegIterator,
+  paths::file_arg,
+};
+
+/// Retrieves the frame at an arbitrary timestamp of a video, e.g. for a
+/// scrubbing UI or thumbnail strip.
+///
+/// Frames are decoded by a background FFmpeg process which is kept running
+/// between requests. Requests a short distance ahead of the previous one are
+/// served by decoding forward, while requests further away (or backwards)
+/// respawn the process with an accurate `-ss` seek. Recently returned frames
+/// are kept in a small cache.
+///
+/// ```rust,no_run
+/// use ffmpeg_sidecar::frame_grabber::FrameGrabber;
+/// use std::time::Duration;
+///
+/// let mut grabber = FrameGrabber::open("input.mp4")?;
+/// let frame = grabber.get_frame_at(Duration::from_secs(42))?;
+/// println!("{}x{} frame at {}s", frame.width, frame.height, frame.timestamp);
+/// # anyhow::Ok(())
+/// ```
+pub struct FrameGrabber {
+  path: PathBuf,
+  fps: f32,
+  duration: Option<Duration>,
+  cache: VecDeque<(u64, OutputVideoFrame)>,
+  cache_size: usize,
+  max_forward_frames: u64,
+  decoder: Option<Decoder>,
+}
+
+/// A running FFmpeg process decoding forward from `next_index`.
+struct Decoder {
+  child: FfmpegChild,
+  frames: FfmpegIterator,
+  next_index: u64,
+}
+
+impl FrameGrabber {
+  /// The default number of cached frames.
+  pub const DEFAULT_CACHE_SIZE: usize = 16;
+
+  /// Probe the input's frame rate and duration with ffprobe. Fails if the
+  /// input can't be opened or has no video stream.
+  pub fn open<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+    let path = path.as_ref().to_path_buf();
+    let (video, duration) = ffprobe_video(&file_arg(&path), &[])?;
+    let fps = video.fps;
+    anyhow::ensure!(fps > 0.0, "No video stream with a known frame rate");
+
+    Ok(Self {
+      path,
+      fps,
+      duration: duration.and_then(|duration| Duration::try_from_secs_f64(duration).ok()),
+      cache: VecDeque::new(),
+      cache_size: Self::DEFAULT_CACHE_SIZE,
+      // Decoding up to two seconds forward is usually cheaper than reseeking
+      max_forward_frames: (fps * 2.0).ceil() as u64,
+      decoder: None,
+    })
+  }
+
+  /// Set the maximum number of cached frames. Defaults to
+  /// [`FrameGrabber::DEFAULT_CACHE_SIZE`].
+  pub fn cache_size(&mut self, frames: usize) -> &mut Self {
+    self.cache_size = frames;
+    self.cache.truncate(frames);
+    self
+  }
+
+  /// The frame rate of the first video stream.
+  pub fn fps(&self) -> f32 {
+    self.fps
+  }
+
+  /// The duration of the input, if known.
+  pub fn duration(&self) -> Option<Duration> {
+    self.duration
+  }
+
+  /// The frame displayed at `time`. The returned frame's `frame_num` and
+  /// `timestamp` are relative to the start of the input.
+  pub fn get_frame_at(&mut self, time: Duration) -> anyhow::Result<OutputVideoFrame> {
+    // Small epsilon so that exact frame timestamps don't round down a frame
+    let index = (time.as_secs_f64() * self.fps as f64 + 1e-6).floor() as u64;
+
+    if let Some(position) = self.cache.iter().position(|(i, _)| *i == index) {
+      let entry = self.cache.remove(position).context("cache entry")?;
+      let frame = entry.1.clone();
+      self.cache.push_back(entry);
+      return Ok(frame);
+    }
+
+    let reusable = self.decoder.as_ref().is_some_and(|decoder| {
+      index >= decoder.next_index && index - decoder.next_index <= self.max_forward_frames
+    });
+    if !reusable {
+      self.seek(index)?;
+    }
+
+    let decoder = self.decoder.as_mut().context("No decoder")?;
+    loop {
+      let Some(mut frame) = decoder.frames.by_ref().find_map(|event| match event {
+        FfmpegEvent::OutputFrame(frame) => Some(frame),
+        _ => None,
+      }) else {
+        anyhow::bail!("No frame at {time:?} (end of input)")
+      };
+
+      let frame_index = decoder.next_index;
+      decoder.next_index += 1;
+      frame.frame_num = frame_index as u32;
+      frame.timestamp = frame_index as f32 / self.fps;
+      if frame_index == index {
+        self.insert(index, frame.clone());
+        return Ok(frame);
+      }
+    }
+  }
+
+  /// Replace the decoder with one starting at frame `index`.
+  fn seek(&mut self, index: u64) -> anyhow::Result<()> {
+    self.stop();
+    let position = index as f64 / self.fps as f64;
+    let mut child = FfmpegCommand::new()
+      .seek(format!("{position:.6}"))
+      .input_path(&self.path)
+      .rawvideo()
+      .spawn()?;
+    let frames = child.iter()?;
+    self.decoder = Some(Decoder {
+      child,
+      frames,
+      next_index: index,
+    });
+    Ok(())
+  }
+
+  fn insert(&mut self, index: u64, frame: OutputVideoFrame) {
+    if self.cache_size == 0 {
+      return;
+    }
+    while self.cache.len() >= self.cache_size {
+      self.cache.pop_front();
+    }
+    self.cache.push_back((index, frame));
+  }
+
+  /// Terminate the background FFmpeg process, if any.
+  fn stop(&mut self) {
+    if let Some(mut decoder) = self.decoder.take() {
+      decoder.child.kill().ok();
+      // Drain the iterator so the reader threads can exit
+      decoder.frames.for_each(drop);
+      decoder.child.wait().ok();
+    }
+  }
+}
+
+impl Drop for FrameGrabber {
+  fn drop(&mut self) {
+    self.stop();
+  }
+}
@@ -0,0 +1,463 @@
+//! Feed raw video frames into an FFmpeg process over stdin.
+
+use std::{
+  io::{self, Write},
+  process::ChildStdin,
+  thread::{self, JoinHandle},
+};
+
+use anyhow::Context;
+
+use crate::{
+  command::FfmpegCommand,
+  event::{OutputVideoFrame, Stream, VideoStream},
+  pix_fmt::get_bytes_per_frame,
+};
+
+/// Describes the raw video format expected on an FFmpeg process's stdin, and
+/// writes frames of that format to it.
+///
+/// Typically the format is taken from a stream parsed from another FFmpeg
+/// process, so that frames decoded by one instance can be piped straight into
+/// an encoder without restating their dimensions and pixel format.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use ffmpeg_sidecar::{
+///   command::FfmpegCommand,
+///   frame_pump::{FrameWriter, InputFramePump},
+/// };
+///
+/// let mut decoder = FfmpegCommand::new().input("input.mp4").rawvideo().spawn()?;
+/// let mut frames = decoder.iter()?;
+/// let metadata = frames.collect_metadata()?;
+/// let pump = InputFramePump::like(&metadata.output_streams[0])?;
+///
+/// let mut encoder = FfmpegCommand::new();
+/// pump.configure(&mut encoder);
+/// let mut encoder = encoder.codec_video("libx265").output("output.mp4").spawn()?;
+///
+/// let stdin = encoder.take_stdin().unwrap();
+/// FrameWriter::new(stdin, pump).spawn(frames.filter_frames().map(|f| f.data));
+/// encoder.wait()?;
+/// # anyhow::Ok(())
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputFramePump {
+  pub pix_fmt: String,
+  pub width: u32,
+  pub height: u32,
+  pub fps: f32,
+}
+
+impl InputFramePump {
+  /// Use the same pixel format, dimensions and frame rate as a previously
+  /// parsed video stream. Fails for non-video streams, or for pixel formats
+  /// whose frame size can't be determined.
+  pub fn like(stream: &Stream) -> anyhow::Result<Self> {
+    let video = stream
+      .video_data()
+      .with_context(|| format!("Stream is not a video stream: {}", stream.raw_log_message))?;
+    Self::from_video_stream(video)
+  }
+
+  pub fn from_video_stream(video: &VideoStream) -> anyhow::Result<Self> {
+    get_bytes_per_frame(video)
+      .with_context(|| format!("Unsupported pixel format: {}", video.pix_fmt))?;
+    Ok(Self {
+      pix_fmt: video.pix_fmt.clone(),
+      width: video.width,
+      height: video.height,
+      fps: video.fps,
+    })
+  }
+
+  /// The size in bytes of a single frame.
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::frame_pump::InputFramePump;
+  ///
+  /// let pump = InputFramePump {
+  ///   pix_fmt: "rgb24".to_string(),
+  ///   width: 600,
+  ///   height: 800,
+  ///   fps: 30.0,
+  /// };
+  /// assert_eq!(pump.frame_size(), 600 * 800 * 3);
+  /// ```
+  pub fn frame_size(&self) -> usize {
+    get_bytes_per_frame(&VideoStream {
+      pix_fmt: self.pix_fmt.clone(),
+      width: self.width,
+      height: self.height,
+      fps: self.fps,
+    })
+    .unwrap_or_default() as usize
+  }
+
+  /// The frame size, or an `io::ErrorKind::InvalidInput` error if it's
+  /// unknown, e.g. for an unsupported `pix_fmt` set through the public
+  /// fields. Otherwise every frame but an empty one would be rejected.
+  fn checked_frame_size(&self) -> io::Result<usize> {
+    match self.frame_size() {
+      0 => Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!(
+          "Can't determine the frame size of {}x{} {}",
+          self.width, self.height, self.pix_fmt
+        ),
+      )),
+      frame_size => Ok(frame_size),
+    }
+  }
+
+  /// Add `-f rawvideo -pix_fmt X -s WxH -r F -i -` to the command. This should
+  /// be called at the point where the input would otherwise be added.
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::{command::FfmpegCommand, frame_pump::InputFramePump};
+  ///
+  /// let pump = InputFramePump {
+  ///   pix_fmt: "rgb24".to_string(),
+  ///   width: 600,
+  ///   height: 800,
+  ///   fps: 30.0,
+  /// };
+  /// let mut command = FfmpegCommand::new_with_path("ffmpeg");
+  /// pump.configure(&mut command);
+  /// let args: Vec<_> = command.get_args().map(|a| a.to_str().unwrap()).collect();
+  /// assert_eq!(
+  ///   args[2..],
+  ///   ["-f", "rawvideo", "-pix_fmt", "rgb24", "-s", "600x800", "-r", "30", "-i", "-"]
+  /// );
+  /// ```
+  pub fn configure<'a>(&self, command: &'a mut FfmpegCommand) -> &'a mut FfmpegCommand {
+    command
+      .format("rawvideo")
+      .pix_fmt(&self.pix_fmt)
+      .size(self.width, self.height)
+      .rate(self.fps)
+      .input("-")
+  }
+}
+
+/// Writes raw video frames to the stdin of an FFmpeg process whose input was
+/// added with `FfmpegCommand::frame_input`; see `FfmpegChild::frame_writer`.
+///
+/// Every frame is checked against the input format before it's written, and
+/// writes block while FFmpeg's stdin pipe is full, so a producer never gets
+/// further ahead of the encoder than the pipe's buffer. FFmpeg's log must be
+/// drained meanwhile, e.g. by iterating its events on another thread, or it
+/// stops reading stdin.
+///
+/// ```rust
+/// use ffmpeg_sidecar::frame_pump::{FrameWriter, InputFramePump};
+///
+/// let pump = InputFramePump {
+///   pix_fmt: "gray".to_string(),
+///   width: 4,
+///   height: 2,
+///   fps: 25.0,
+/// };
+/// let mut writer = FrameWriter::new(Vec::new(), pump);
+/// writer.write_raw(&[0; 8])?;
+/// assert!(writer.write_raw(&[0; 7]).is_err());
+/// assert_eq!(writer.finish()?, 1);
+///
+/// // A format whose frame size is unknown rejects every frame
+/// let pump = InputFramePump {
+///   pix_fmt: "unknown".to_string(),
+///   width: 4,
+///   height: 2,
+///   fps: 25.0,
+/// };
+/// let mut writer = FrameWriter::new(Vec::new(), pump);
+/// assert!(writer.write_raw(&[]).is_err());
+/// # std::io::Result::Ok(())
+/// ```
+#[derive(Debug)]
+pub struct FrameWriter<W: Write = ChildStdin> {
+  stdin: W,
+  pump: InputFramePump,
+  frames_written: u64,
+}
+
+impl<W: Write> FrameWriter<W> {
+  /// Write frames of the format described by `pump` to `stdin`.
+  pub fn new(stdin: W, pump: InputFramePump) -> Self {
+    Self {
+      stdin,
+      pump,
+      frames_written: 0,
+    }
+  }
+
+  /// The format every frame must have.
+  pub fn format(&self) -> &InputFramePump {
+    &self.pump
+  }
+
+  /// The number of frames written so far.
+  pub fn frames_written(&self) -> u64 {
+    self.frames_written
+  }
+
+  /// Write the pixels of one frame. Fails with `io::ErrorKind::InvalidData`
+  /// if it isn't exactly one frame long, since a short frame would shift
+  /// every subsequent one, and with `io::ErrorKind::InvalidInput` if the
+  /// frame size of the format is unknown.
+  pub fn write_raw(&mut self, data: &[u8]) -> io::Result<()> {
+    let frame_size = self.pump.checked_frame_size()?;
+    if data.len() != frame_size {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Expected {frame_size} bytes per frame, got {}", data.len()),
+      ));
+    }
+    self.stdin.write_all(data)?;
+    self.frames_written += 1;
+    Ok(())
+  }
+
+  /// Write a frame, e.g. one decoded by another FFmpeg process. Fails with
+  /// `io::ErrorKind::InvalidData` if its dimensions or pixel format differ
+  /// from the input's.
+  pub fn write_frame(&mut self, frame: &OutputVideoFrame) -> io::Result<()> {
+    let pump = &self.pump;
+    if (frame.width, frame.height) != (pump.width, pump.height) || frame.pix_fmt != pump.pix_fmt {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+          "Expected {}x{} {} frames, got {}x{} {}",
+          pump.width, pump.height, pump.pix_fmt, frame.width, frame.height, frame.pix_fmt
+        ),
+      ));
+    }
+    self.write_raw(&frame.data)
+  }
+
+  /// Flush and close stdin, so that FFmpeg finishes the output, and return
+  /// the number of frames written.
+  pub fn finish(mut self) -> io::Result<u64> {
+    self.stdin.flush()?;
+    Ok(self.frames_written)
+  }
+
+  /// Write the pixels of every frame on a background thread, then
+  /// [`finish`](Self::finish). Stops at the first frame rejected by
+  /// [`write_raw`](Self::write_raw).
+  pub fn spawn<I>(mut self, frames: I) -> JoinHandle<io::Result<u64>>
+  where
+    W: Send + 'static,
+    I: IntoIterator<Item = Vec<u8>> + Send + 'static,
+    I::IntoIter: Send,
+  {
+    thread::spawn(move || {
+      for frame in frames {
+        self.write_raw(&frame)?;
+      }
+      self.finish()
+    })
+  }
+}
+
+/// Writes frames which were decoded by one FFmpeg process and transformed in
+/// Rust to an encoder, at the timing of the frames they were made from.
+///
+/// The encoder reads frames at the constant rate set by `-r`, so a pipeline
+/// which drops frames would otherwise drift from the source timing. Instead,
+/// every frame is placed in the encoder slot nearest its `timestamp`: gaps are
+/// filled by holding the previous frame, and a frame landing on an already
+/// filled slot is dropped.
+///
+/// The `timestamp` of a decoded frame is computed from its index and the
+/// stream's frame rate, so the timing kept is that of the decoder's constant
+/// rate output. Variable frame rate sources are not retimed from their own
+/// presentation timestamps.
+///
+/// ```rust,no_run
+/// use ffmpeg_sidecar::{command::FfmpegCommand, frame_pump::TransformedFrameWriter};
+///
+/// let mut decoder = FfmpegCommand::new().input("input.mp4").rawvideo().spawn()?;
+/// let mut frames = decoder.iter()?;
+/// let metadata = frames.collect_metadata()?;
+/// let writer = TransformedFrameWriter::like(&metadata.output_streams[0])?;
+///
+/// let mut encoder = FfmpegCommand::new();
+/// writer.configure(&mut encoder);
+/// let mut encoder = encoder.codec_video("libx264").output("output.mp4").spawn()?;
+///
+/// // Skip every frame with a dark first pixel, and invert the rest
+/// let edited = frames.filter_frames().filter(|f| f.data[0] > 16).map(|mut f| {
+///   f.data.iter_mut().for_each(|byte| *byte = 255 - *byte);
+///   f
+/// });
+/// let stats = writer.spawn(encoder.take_stdin().unwrap(), edited);
+/// encoder.wait()?;
+/// println!("{:?}", stats.join().unwrap()?);
+/// # anyhow::Ok(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct TransformedFrameWriter {
+  pump: InputFramePump,
+  start: Option<f32>,
+  origin: Option<f32>,
+  pending: Option<(u64, OutputVideoFrame)>,
+  stats: RetimeStats,
+}
+
+/// How many frames a [`TransformedFrameWriter`] wrote to the encoder.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RetimeStats {
+  /// Every frame written, including repeats.
+  pub written: u64,
+  /// Repeats of a frame, written to fill a gap before the next one.
+  pub duplicated: u64,
+  /// Frames which landed on a slot that was already filled.
+  pub dropped: u64,
+}
+
+impl TransformedFrameWriter {
+  /// Write frames of the format described by `pump`. Its frame rate is the
+  /// encoder's.
+  pub fn new(pump: InputFramePump) -> Self {
+    Self {
+      pump,
+      start: None,
+      origin: None,
+      pending: None,
+      stats: RetimeStats::default(),
+    }
+  }
+
+  /// Write frames in the same format as a previously parsed video stream.
+  /// Fails in the same cases as [`InputFramePump::like`].
+  pub fn like(stream: &Stream) -> anyhow::Result<Self> {
+    InputFramePump::like(stream).map(Self::new)
+  }
+
+  /// Start the encoder's timeline at the source timestamp `start` rather than
+  /// at the first frame written, e.g. to keep the timing of a source which was
+  /// seeked into. Frames from before `start` are dropped.
+  pub fn start_at(&mut self, start: f32) -> &mut Self {
+    self.start = Some(start.max(0.0));
+    self
+  }
+
+  /// Add the input of the encoder: `-itsoffset` if [`start_at`](Self::start_at)
+  /// was given a positive time, followed by [`InputFramePump::configure`].
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::{command::FfmpegCommand, frame_pump::{InputFramePump, TransformedFrameWriter}};
+  ///
+  /// let mut writer = TransformedFrameWriter::new(InputFramePump {
+  ///   pix_fmt: "gray".to_string(),
+  ///   width: 64,
+  ///   height: 48,
+  ///   fps: 25.0,
+  /// });
+  /// let mut command = FfmpegCommand::new_with_path("ffmpeg");
+  /// writer.start_at(12.5).configure(&mut command);
+  /// let args: Vec<_> = command.get_args().map(|a| a.to_str().unwrap()).collect();
+  /// assert_eq!(args[2..4], ["-itsoffset", "12.500000"]);
+  /// assert_eq!(args[args.len() - 2..], ["-i", "-"]);
+  /// ```
+  pub fn configure<'a>(&self, command: &'a mut FfmpegCommand) -> &'a mut FfmpegCommand {
+    if let Some(start) = self.start.filter(|start| *start > 0.0) {
+      command.itsoffset(format!("{start:.6}"));
+    }
+    self.pump.configure(command)
+  }
+
+  /// Queue the next frame, whose `data` has been transformed and whose
+  /// `timestamp` is still that of the source. Returns the time at which the
+  /// encoder will show it, or `None` if it was dropped.
+  ///
+  /// Each frame is written once the next one arrives, so that it can be
+  /// repeated to fill any gap; call [`finish`](Self::finish) to write the last.
+  /// Frames of the wrong size are rejected with `io::ErrorKind::InvalidData`,
+  /// and every frame with `io::ErrorKind::InvalidInput` if the frame size of
+  /// the format is unknown.
+  pub fn push<W: Write>(
+    &mut self,
+    out: &mut W,
+    frame: OutputVideoFrame,
+  ) -> io::Result<Option<f32>> {
+    let frame_size = self.pump.checked_frame_size()?;
+    if frame.data.len() != frame_size {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+          "Expected {frame_size} bytes per frame, got {}",
+          frame.data.len()
+        ),
+      ));
+    }
+
+    let origin = *self
+      .origin
+      .get_or_insert(self.start.unwrap_or(frame.timestamp));
+    let position = ((frame.timestamp - origin) * self.pump.fps).round();
+    if position < 0.0 {
+      self.stats.dropped += 1;
+      return Ok(None);
+    }
+    let slot = position as u64;
+
+    if let Some((pending_slot, pending)) = self.pending.take() {
+      if slot <= pending_slot {
+        self.pending = Some((pending_slot, pending));
+        self.stats.dropped += 1;
+        return Ok(None);
+      }
+      self.write(out, &pending, slot - pending_slot)?;
+    } else if slot > 0 {
+      // Nothing precedes the first frame, so it's repeated in the slots before it
+      self.write(out, &frame, slot)?;
+      self.stats.duplicated += 1;
+    }
+    self.pending = Some((slot, frame));
+    Ok(Some(
+      self.start.unwrap_or(0.0) + slot as f32 / self.pump.fps,
+    ))
+  }
+
+  /// Write the last queued frame, and return the totals.
+  pub fn finish<W: Write>(&mut self, out: &mut W) -> io::Result<RetimeStats> {
+    if let Some((_, pending)) = self.pending.take() {
+      self.write(out, &pending, 1)?;
+    }
+    out.flush()?;
+    Ok(self.stats)
+  }
+
+  /// Write every frame to stdin on a background thread, closing stdin when the
+  /// frames are exhausted, like [`FrameWriter::spawn`].
+  pub fn spawn<I>(mut self, mut stdin: ChildStdin, frames: I) -> JoinHandle<io::Result<RetimeStats>>
+  where
+    I: IntoIterator<Item = OutputVideoFrame> + Send + 'static,
+    I::IntoIter: Send,
+  {
+    thread::spawn(move || {
+      for frame in frames {
+        self.push(&mut stdin, frame)?;
+      }
+      self.finish(&mut stdin)
+    })
+  }
+
+  fn write<W: Write>(
+    &mut self,
+    out: &mut W,
+    frame: &OutputVideoFrame,
+    times: u64,
+  ) -> io::Result<()> {
+    for _ in 0..times {
+      out.write_all(&frame.data)?;
+    }
+    self.stats.written += times;
+    self.stats.duplicated += times - 1;
+    Ok(())
+  }
+}
@@ -0,0 +1,261 @@
+//! GPU device selection for hardware-accelerated jobs, and a small allocator
+//! for spreading concurrent jobs across several GPUs.
+
+use std::{
+  ffi::OsStr,
+  process::{Command, Stdio},
+  sync::{Arc, Mutex},
+};
+
+use crate::{command::BackgroundCommand, paths::ffmpeg_path};
+
+/// A family of hardware acceleration, which determines the options used to
+/// select a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Hw {
+  /// NVDEC/NVENC through CUDA.
+  Nvidia,
+  /// Intel Quick Sync Video.
+  Intel,
+  /// VA-API on Linux, for AMD and Intel GPUs.
+  Vaapi,
+}
+
+impl Hw {
+  /// The hwaccel and hardware device type name, e.g. `cuda`.
+  pub fn device_type(&self) -> &'static str {
+    match self {
+      Hw::Nvidia => "cuda",
+      Hw::Intel => "qsv",
+      Hw::Vaapi => "vaapi",
+    }
+  }
+}
+
+/// One GPU, identified by its index among the GPUs of the same kind.
+///
+/// ```rust
+/// use ffmpeg_sidecar::gpu::{GpuDevice, Hw};
+///
+/// let gpu = GpuDevice::new(Hw::Nvidia, 1);
+/// assert_eq!(gpu.decode_args(), ["-hwaccel", "cuda", "-hwaccel_device", "1"]);
+/// assert_eq!(gpu.encode_args(), ["-gpu", "1"]);
+///
+/// let gpu = GpuDevice::new(Hw::Vaapi, 1);
+/// assert_eq!(gpu.device_name(), "/dev/dri/renderD129");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GpuDevice {
+  pub hw: Hw,
+  pub index: usize,
+}
+
+impl GpuDevice {
+  pub fn new(hw: Hw, index: usize) -> Self {
+    Self { hw, index }
+  }
+
+  /// The device as FFmpeg's device options expect it: the CUDA ordinal, or a
+  /// DRM render node (the adapter index for QSV on Windows).
+  pub fn device_name(&self) -> String {
+    match self.hw {
+      Hw::Nvidia => self.index.to_string(),
+      Hw::Intel if cfg!(windows) => self.index.to_string(),
+      Hw::Intel | Hw::Vaapi => format!("/dev/dri/renderD{}", 128 + self.index),
+    }
+  }
+
+  /// Input options which decode on this device; they must precede the `-i`.
+  pub fn decode_args(&self) -> Vec<String> {
+    let device_option = match self.hw {
+      Hw::Intel => "-qsv_device",
+      Hw::Nvidia | Hw::Vaapi => "-hwaccel_device",
+    };
+    vec![
+      "-hwaccel".to_string(),
+      self.hw.device_type().to_string(),
+      device_option.to_string(),
+      self.device_name(),
+    ]
+  }
+
+  /// Output options which encode on this device. Only NVENC selects its
+  /// device per encoder; QSV and VA-API encoders use the decoding device.
+  pub fn encode_args(&self) -> Vec<String> {
+    match self.hw {
+      Hw::Nvidia => vec!["-gpu".to_string(), self.index.to_string()],
+      Hw::Intel | Hw::Vaapi => Vec::new(),
+    }
+  }
+
+  /// The value of `-init_hw_device` which opens this device, naming it
+  /// `gpu`.
+  pub(crate) fn init_hw_device(&self) -> String {
+    match self.hw {
+      Hw::Intel => format!("qsv=gpu:hw_any,child_device={}", self.device_name()),
+      Hw::Nvidia | Hw::Vaapi => format!("{}=gpu:{}", self.hw.device_type(), self.device_name()),
+    }
+  }
+
+  /// Whether FFmpeg is able to open this device.
+  pub fn is_available(&self) -> bool {
+    self.is_available_with_path(ffmpeg_path())
+  }
+
+  pub fn is_available_with_path<S: AsRef<OsStr>>(&self, path: S) -> bool {
+    Command::new(path)
+      .create_no_window()
+      .args(["-v", "quiet", "-init_hw_device", &self.init_hw_device()])
+      .args(["-f", "lavfi", "-i", "nullsrc=d=0", "-f", "null", "-"])
+      .stdin(Stdio::null())
+      .stdout(Stdio::null())
+      .stderr(Stdio::null())
+      .status()
+      .is_ok_and(|status| status.success())
+  }
+}
+
+/// Parse the output of `ffmpeg -init_hw_device list`.
+pub fn parse_hw_device_types(output: &str) -> Vec<String> {
+  output
+    .lines()
+    .skip_while(|line| !line.starts_with("Supported hw device types"))
+    .skip(1)
+    .map(str::trim)
+    .filter(|line| !line.is_empty())
+    .map(String::from)
+    .collect()
+}
+
+/// The hardware device types FFmpeg was built with, e.g. `cuda` or `vaapi`.
+/// Whether a device of that type is actually present is checked by
+/// [`GpuDevice::is_available`].
+pub fn hw_device_types() -> anyhow::Result<Vec<String>> {
+  hw_device_types_with_path(ffmpeg_path())
+}
+
+pub fn hw_device_types_with_path<S: AsRef<OsStr>>(path: S) -> anyhow::Result<Vec<String>> {
+  let output = Command::new(path)
+    .create_no_window()
+    .args(["-hide_banner", "-init_hw_device", "list"])
+    .stdin(Stdio::null())
+    .output()?;
+  Ok(parse_hw_device_types(&String::from_utf8_lossy(
+    &output.stdout,
+  )))
+}
+
+/// Hands out GPUs to concurrent jobs, always picking the device with the
+/// fewest active jobs (the earliest one on a tie). Cloned allocators share
+/// the same bookkeeping.
+///
+/// ```rust
+/// use ffmpeg_sidecar::gpu::{GpuAllocator, Hw};
+///
+/// let allocator = GpuAllocator::new(Hw::Nvidia, 2);
+/// let first = allocator.acquire().unwrap();
+/// let second = allocator.acquire().unwrap();
+/// assert_eq!((first.index, second.index), (0, 1));
+///
+/// drop(first);
+/// assert_eq!(allocator.acquire().unwrap().index, 0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct GpuAllocator {
+  devices: Vec<GpuDevice>,
+  active_jobs: Arc<Mutex<Vec<usize>>>,
+}
+
+impl GpuAllocator {
+  /// Allocate across GPUs `0..count` of the given kind.
+  pub fn new(hw: Hw, count: usize) -> Self {
+    Self::from_devices((0..count).map(|index| GpuDevice::new(hw, index)).collect())
+  }
+
+  pub fn from_devices(devices: Vec<GpuDevice>) -> Self {
+    let active_jobs = Arc::new(Mutex::new(vec![0; devices.len()]));
+    Self {
+      devices,
+      active_jobs,
+    }
+  }
+
+  /// Allocate across the GPUs of this kind which FFmpeg can open, probing
+  /// indices in order until one fails or `max` is reached.
+  pub fn detect(hw: Hw, max: usize) -> Self {
+    let devices = (0..max)
+      .map(|index| GpuDevice::new(hw, index))
+      .take_while(GpuDevice::is_available)
+      .collect();
+    Self::from_devices(devices)
+  }
+
+  pub fn devices(&self) -> &[GpuDevice] {
+    &self.devices
+  }
+
+  /// Reserve the least busy GPU until the returned lease is dropped, or
+  /// `None` if there are no GPUs.
+  pub fn acquire(&self) -> Option<GpuLease> {
+    let mut active_jobs = self.active_jobs.lock().ok()?;
+    let (slot, jobs) = active_jobs
+      .iter_mut()
+      .enumerate()
+      .min_by_key(|(_, jobs)| **jobs)?;
+    *jobs += 1;
+    Some(GpuLease {
+      device: self.devices[slot],
+      slot,
+      active_jobs: self.active_jobs.clone(),
+    })
+  }
+}
+
+/// A GPU reserved by [`GpuAllocator::acquire`]. Dereferences to the
+/// [`GpuDevice`], e.g. for `FfmpegCommand::gpu_decode`.
+#[derive(Debug)]
+pub struct GpuLease {
+  device: GpuDevice,
+  slot: usize,
+  active_jobs: Arc<Mutex<Vec<usize>>>,
+}
+
+impl std::ops::Deref for GpuLease {
+  type Target = GpuDevice;
+
+  fn deref(&self) -> &GpuDevice {
+    &self.device
+  }
+}
+
+impl Drop for GpuLease {
+  fn drop(&mut self) {
+    if let Ok(mut active_jobs) = self.active_jobs.lock() {
+      active_jobs[self.slot] -= 1;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_hw_device_types() {
+    let output = "Supported hw device types:\ncuda\nvaapi\nqsv\n\n";
+    assert_eq!(parse_hw_device_types(output), ["cuda", "vaapi", "qsv"]);
+    assert!(parse_hw_device_types("").is_empty());
+  }
+
+  #[test]
+  fn test_allocator_balances_jobs() {
+    let allocator = GpuAllocator::new(Hw::Vaapi, 2);
+    let leases: Vec<_> = (0..4).map(|_| allocator.acquire().unwrap()).collect();
+    let indices: Vec<_> = leases.iter().map(|lease| lease.index).collect();
+    assert_eq!(indices, [0, 1, 0, 1]);
+
+    drop(leases);
+    assert_eq!(allocator.clone().acquire().unwrap().index, 0);
+    assert!(GpuAllocator::new(Hw::Vaapi, 0).acquire().is_none());
+  }
+}
@@ -0,0 +1,344 @@
+//! Full-GPU transcode pipelines, where frames stay in GPU memory from the
+//! decoder through scaling to the encoder, with a software fallback.
+
+use std::{
+  ffi::OsStr,
+  fmt,
+  process::{Command, Stdio},
+};
+
+use crate::{
+  command::BackgroundCommand,
+  gpu::{GpuDevice, Hw},
+  paths::ffmpeg_path,
+};
+
+/// The test frames transcoded by [`HwTranscode::check`].
+const PROBE_SOURCE: &str = "testsrc2=duration=0.2:size=320x240:rate=10";
+
+/// The output video codec of a transcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoCodec {
+  H264,
+  Hevc,
+}
+
+/// A hardware transcode from any input the GPU can decode to `codec`,
+/// optionally scaled to `size`. Inputs which aren't decoded on the GPU, such
+/// as `lavfi` sources or raw video, are uploaded to it first with
+/// [`upload`](Self::upload).
+///
+/// ```rust
+/// use ffmpeg_sidecar::{gpu::Hw, hw_transcode::{HwTranscode, VideoCodec}};
+///
+/// let preset = HwTranscode::new(Hw::Vaapi, VideoCodec::Hevc).size(1280, 720);
+/// assert_eq!(preset.encoder(), "hevc_vaapi");
+/// assert_eq!(preset.filter().as_deref(), Some("scale_vaapi=w=1280:h=720"));
+///
+/// let preset = preset.upload();
+/// assert_eq!(
+///   preset.filter().as_deref(),
+///   Some("format=nv12,hwupload,scale_vaapi=w=1280:h=720")
+/// );
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HwTranscode {
+  pub device: GpuDevice,
+  pub codec: VideoCodec,
+  pub size: Option<(u32, u32)>,
+  /// Decode in software and upload the frames to the GPU before filtering,
+  /// instead of decoding on the GPU.
+  pub upload: bool,
+}
+
+impl HwTranscode {
+  /// A transcode on the first GPU of the given kind.
+  pub fn new(hw: Hw, codec: VideoCodec) -> Self {
+    Self {
+      device: GpuDevice::new(hw, 0),
+      codec,
+      size: None,
+      upload: false,
+    }
+  }
+
+  pub fn device(mut self, device: GpuDevice) -> Self {
+    self.device = device;
+    self
+  }
+
+  pub fn size(mut self, width: u32, height: u32) -> Self {
+    self.size = Some((width, height));
+    self
+  }
+
+  /// Upload software frames to the GPU, for inputs which aren't decoded on
+  /// it. See [`upload`](Self#structfield.upload).
+  pub fn upload(mut self) -> Self {
+    self.upload = true;
+    self
+  }
+
+  /// The hardware encoder, e.g. `h264_nvenc`.
+  pub fn encoder(&self) -> String {
+    let codec = match self.codec {
+      VideoCodec::H264 => "h264",
+      VideoCodec::Hevc => "hevc",
+    };
+    let suffix = match self.device.hw {
+      Hw::Nvidia => "nvenc",
+      Hw::Intel => "qsv",
+      Hw::Vaapi => "vaapi",
+    };
+    format!("{codec}_{suffix}")
+  }
+
+  /// The video filter: the upload of software frames, if uploading, and the
+  /// scaling filter which operates on GPU frames, if scaling.
+  pub fn filter(&self) -> Option<String> {
+    let upload = self.upload.then(|| match self.device.hw {
+      // QSV needs a frame pool large enough for the encoder's lookahead
+      Hw::Intel => "format=nv12,hwupload=extra_hw_frames=64".to_string(),
+      Hw::Nvidia | Hw::Vaapi => "format=nv12,hwupload".to_string(),
+    });
+    let scale = self.size.map(|(width, height)| match self.device.hw {
+      Hw::Nvidia => format!("scale_cuda={width}:{height}"),
+      Hw::Intel => format!("scale_qsv=w={width}:h={height}"),
+      Hw::Vaapi => format!("scale_vaapi=w={width}:h={height}"),
+    });
+    let filters: Vec<String> = upload.into_iter().chain(scale).collect();
+    (!filters.is_empty()).then(|| filters.join(","))
+  }
+
+  /// Input options which decode into GPU memory, or which open the GPU for
+  /// the upload filter; they must precede the `-i`.
+  pub fn input_args(&self) -> Vec<String> {
+    if self.upload {
+      return vec![
+        "-init_hw_device".into(),
+        self.device.init_hw_device(),
+        "-filter_hw_device".into(),
+        "gpu".into(),
+      ];
+    }
+    let mut args = self.device.decode_args();
+    args.extend([
+      "-hwaccel_output_format".into(),
+      self.device.hw.device_type().into(),
+    ]);
+    args
+  }
+
+  /// Output options which scale and encode without leaving the GPU.
+  pub fn output_args(&self) -> Vec<String> {
+    let mut args = Vec::new();
+    if let Some(filter) = self.filter() {
+      args.extend(["-filter:v".into(), filter]);
+    }
+    args.extend(["-c:v".into(), self.encoder()]);
+    args.extend(self.device.encode_args());
+    args
+  }
+
+  /// Verify that FFmpeg has the encoder, can open the device, and can run
+  /// the pipeline's filter and encoder on a few test frames uploaded to the
+  /// GPU.
+  pub fn check(&self) -> Result<(), HwUnavailable> {
+    self.check_with_path(ffmpeg_path())
+  }
+
+  pub fn check_with_path<S: AsRef<OsStr>>(&self, path: S) -> Result<(), HwUnavailable> {
+    let encoder = self.encoder();
+    if !has_encoder(&path, &encoder) {
+      return Err(HwUnavailable::Encoder(encoder));
+    }
+    if !self.device.is_available_with_path(&path) {
+      return Err(HwUnavailable::Device(self.device));
+    }
+    self.probe(&path).map_err(HwUnavailable::Pipeline)
+  }
+
+  /// Transcode a few frames of a test source through the pipeline, returning
+  /// FFmpeg's errors if it fails.
+  fn probe<S: AsRef<OsStr>>(&self, path: S) -> Result<(), String> {
+    let pipeline = self.upload();
+    let output = Command::new(path)
+      .create_no_window()
+      .args(["-hide_banner", "-v", "error"])
+      .args(pipeline.input_args())
+      .args(["-f", "lavfi", "-i", PROBE_SOURCE])
+      .args(pipeline.output_args())
+      .args(["-f", "null", "-"])
+      .stdin(Stdio::null())
+      .stdout(Stdio::null())
+      .output()
+      .map_err(|e| e.to_string())?;
+    match output.status.success() {
+      true => Ok(()),
+      false => Err(String::from_utf8_lossy(&output.stderr).trim().to_string()),
+    }
+  }
+
+  /// [`check`](Self::check) this transcode, falling back to the equivalent
+  /// software pipeline if it's unavailable.
+  pub fn or_software(self) -> TranscodePipeline {
+    match self.check() {
+      Ok(()) => TranscodePipeline::Hardware(self),
+      Err(reason) => TranscodePipeline::Software {
+        codec: self.codec,
+        size: self.size,
+        reason,
+      },
+    }
+  }
+}
+
+/// Whether `ffmpeg -h encoder=NAME` describes the encoder, rather than
+/// reporting it as unrecognized.
+fn has_encoder<S: AsRef<OsStr>>(path: S, encoder: &str) -> bool {
+  Command::new(path)
+    .create_no_window()
+    .args(["-hide_banner", "-h", &format!("encoder={encoder}")])
+    .stdin(Stdio::null())
+    .stderr(Stdio::null())
+    .output()
+    .is_ok_and(|output| {
+      String::from_utf8_lossy(&output.stdout).contains(&format!("Encoder {encoder} "))
+    })
+}
+
+/// Why a [`HwTranscode`] can't run on this machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HwUnavailable {
+  /// FFmpeg was built without this encoder.
+  Encoder(String),
+  /// The device couldn't be opened, e.g. no such GPU or missing drivers.
+  Device(GpuDevice),
+  /// Test frames couldn't be transcoded through the pipeline, with FFmpeg's
+  /// errors, e.g. because the GPU can't encode at this size.
+  Pipeline(String),
+}
+
+impl fmt::Display for HwUnavailable {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      HwUnavailable::Encoder(encoder) => write!(f, "FFmpeg has no {encoder} encoder"),
+      HwUnavailable::Device(device) => write!(
+        f,
+        "Can't open {} device {}",
+        device.hw.device_type(),
+        device.device_name()
+      ),
+      HwUnavailable::Pipeline(errors) => write!(f, "The GPU pipeline failed: {errors}"),
+    }
+  }
+}
+
+impl std::error::Error for HwUnavailable {}
+
+/// A transcode which runs either fully on the GPU, or entirely in software.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TranscodePipeline {
+  Hardware(HwTranscode),
+  /// `libx264`/`libx265` with the `scale` filter, used because of `reason`.
+  Software {
+    codec: VideoCodec,
+    size: Option<(u32, u32)>,
+    reason: HwUnavailable,
+  },
+}
+
+impl TranscodePipeline {
+  pub fn is_hardware(&self) -> bool {
+    matches!(self, TranscodePipeline::Hardware(_))
+  }
+
+  pub fn input_args(&self) -> Vec<String> {
+    match self {
+      TranscodePipeline::Hardware(preset) => preset.input_args(),
+      TranscodePipeline::Software { .. } => Vec::new(),
+    }
+  }
+
+  pub fn output_args(&self) -> Vec<String> {
+    let (codec, size) = match self {
+      TranscodePipeline::Hardware(preset) => return preset.output_args(),
+      TranscodePipeline::Software { codec, size, .. } => (codec, size),
+    };
+    let mut args = Vec::new();
+    if let Some((width, height)) = size {
+      args.extend(["-filter:v".into(), format!("scale={width}:{height}")]);
+    }
+    let encoder = match codec {
+      VideoCodec::H264 => "libx264",
+      VideoCodec::Hevc => "libx265",
+    };
+    args.extend(["-c:v".into(), encoder.into()]);
+    args
+  }
+}
+
+impl From<HwTranscode> for TranscodePipeline {
+  fn from(preset: HwTranscode) -> Self {
+    TranscodePipeline::Hardware(preset)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_pipeline_args() {
+    let preset = HwTranscode::new(Hw::Nvidia, VideoCodec::H264)
+      .device(GpuDevice::new(Hw::Nvidia, 1))
+      .size(1920, 1080);
+    let hardware = TranscodePipeline::from(preset);
+    assert_eq!(
+      hardware.input_args(),
+      [
+        "-hwaccel",
+        "cuda",
+        "-hwaccel_device",
+        "1",
+        "-hwaccel_output_format",
+        "cuda"
+      ]
+    );
+    assert_eq!(
+      hardware.output_args(),
+      [
+        "-filter:v",
+        "scale_cuda=1920:1080",
+        "-c:v",
+        "h264_nvenc",
+        "-gpu",
+        "1"
+      ]
+    );
+
+    let software = TranscodePipeline::Software {
+      codec: VideoCodec::Hevc,
+      size: None,
+      reason: HwUnavailable::Encoder("hevc_nvenc".into()),
+    };
+    assert!(software.input_args().is_empty());
+    assert_eq!(software.output_args(), ["-c:v", "libx265"]);
+
+    // Software frames are uploaded to the device opened for the filters
+    let upload = HwTranscode::new(Hw::Vaapi, VideoCodec::H264).upload();
+    assert_eq!(
+      upload.input_args(),
+      [
+        "-init_hw_device",
+        "vaapi=gpu:/dev/dri/renderD128",
+        "-filter_hw_device",
+        "gpu"
+      ]
+    );
+    assert_eq!(
+      upload.output_args(),
+      ["-filter:v", "format=nv12,hwupload", "-c:v", "h264_vaapi"]
+    );
+  }
+}
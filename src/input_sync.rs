@@ -0,0 +1,111 @@
+//! Measure the initial timestamp gaps between live inputs, to align them with
+//! `FfmpegCommand::sync_inputs`.
+
+use std::time::Duration;
+
+use crate::{
+  command::FfmpegCommand,
+  event::FfmpegEvent,
+  log_parser::{try_parse_input, try_parse_start_time},
+};
+
+/// Probe the start timestamp of every input of `command`, returning the
+/// offset that should be applied to each input (by index) so that all inputs
+/// begin at the same time.
+///
+/// The command is spawned and killed as soon as every input has been probed,
+/// so it only needs to contain the inputs (and their input options); any
+/// outputs are ignored. Inputs without a known start time get an offset of
+/// zero.
+///
+/// The result can be passed directly to
+/// [`FfmpegCommand::sync_inputs`] via `.into_iter().enumerate()`.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use ffmpeg_sidecar::{command::FfmpegCommand, input_sync::estimate_input_offsets};
+///
+/// let offsets = estimate_input_offsets(
+///   FfmpegCommand::new()
+///     .format("v4l2").input("/dev/video0")
+///     .format("pulse").input("default"),
+/// )?;
+///
+/// FfmpegCommand::new()
+///   .sync_inputs(offsets.into_iter().enumerate())
+///   .format("v4l2").input("/dev/video0")
+///   .format("pulse").input("default")
+///   .output("capture.mkv")
+///   .spawn()?;
+/// # anyhow::Ok(())
+/// ```
+pub fn estimate_input_offsets(command: &mut FfmpegCommand) -> anyhow::Result<Vec<Duration>> {
+  let start_times = probe_start_times(command)?;
+  Ok(offsets_from_start_times(&start_times))
+}
+
+/// Probe the `start:` timestamp (in seconds) reported for every input of
+/// `command`. See [`estimate_input_offsets`].
+pub fn probe_start_times(command: &mut FfmpegCommand) -> anyhow::Result<Vec<Option<f64>>> {
+  let mut child = command.spawn()?;
+  let mut start_times: Vec<Option<f64>> = Vec::new();
+  let mut current_input: Option<usize> = None;
+
+  for event in child.iter()? {
+    let line = match &event {
+      FfmpegEvent::ParsedInput(input) => &input.raw_log_message,
+      FfmpegEvent::ParsedDuration(duration) => &duration.raw_log_message,
+      FfmpegEvent::Log(_, line) => line,
+      // Anything after the input sections means probing is complete
+      FfmpegEvent::ParsedStreamMapping(_)
+      | FfmpegEvent::ParsedOutput(_)
+      | FfmpegEvent::Progress(_) => break,
+      _ => continue,
+    };
+
+    if let Some(index) = try_parse_input(line) {
+      let index = index as usize;
+      if start_times.len() <= index {
+        start_times.resize(index + 1, None);
+      }
+      current_input = Some(index);
+    } else if let (Some(index), Some(start)) = (current_input, try_parse_start_time(line)) {
+      start_times[index] = Some(start);
+    }
+  }
+
+  child.kill().ok();
+  child.wait().ok();
+
+  if start_times.is_empty() {
+    anyhow::bail!("No inputs were found while probing start times");
+  }
+  Ok(start_times)
+}
+
+/// Compute the offsets which shift every input to start at the same time as
+/// the latest-starting input.
+///
+/// ```rust
+/// use ffmpeg_sidecar::input_sync::offsets_from_start_times;
+/// use std::time::Duration;
+///
+/// let offsets = offsets_from_start_times(&[Some(100.0), Some(100.25), None]);
+/// assert_eq!(offsets, vec![Duration::from_millis(250), Duration::ZERO, Duration::ZERO]);
+/// ```
+pub fn offsets_from_start_times(start_times: &[Option<f64>]) -> Vec<Duration> {
+  let latest = start_times
+    .iter()
+    .flatten()
+    .copied()
+    .fold(f64::NEG_INFINITY, f64::max);
+
+  start_times
+    .iter()
+    .map(|start| match start {
+      Some(start) if latest.is_finite() => Duration::from_secs_f64((latest - start).max(0.0)),
+      _ => Duration::ZERO,
+    })
+    .collect()
+}
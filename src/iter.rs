@@ -1,43 +1,534 @@
 //! A stream of events from an FFmpeg process.
 
 use std::{
+  collections::{BTreeMap, VecDeque},
+  ffi::OsString,
+  fmt,
   io::{BufReader, ErrorKind, Read},
-  process::{ChildStderr, ChildStdout},
-  sync::mpsc::{sync_channel, Receiver, SyncSender},
+  process::{Child, ChildStderr, ChildStdout},
+  sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    mpsc::{channel, sync_channel, Receiver, RecvTimeoutError, SyncSender, TryRecvError},
+    Arc, Mutex, OnceLock,
+  },
   thread::JoinHandle,
+  time::{Duration, Instant},
 };
 
 use anyhow::Context;
 
 use crate::{
-  child::FfmpegChild,
-  event::{FfmpegEvent, FfmpegOutput, FfmpegProgress, LogLevel, OutputVideoFrame, Stream},
-  log_parser::FfmpegLogParser,
-  metadata::FfmpegMetadata,
+  args::FileArgs,
+  audio_levels::{AudioLevel, AudioLevelMeter, AudioSampleDecoder},
+  backend::ProcessBackend,
+  bitstream::{ChunkFormat, ChunkTagger, TaggedChunk},
+  child::{FfmpegChild, CONSUMER_CLOSED_GRACE},
+  container::record_unsupported,
+  event::{
+    FfmpegError, FfmpegEvent, FfmpegOutput, FfmpegProgress, FilterMetadata, LogLevel,
+    OutputAudioSamples, OutputVideoFrame, StartupTimings, Stream, StreamTypeSpecificData,
+  },
+  extract::LogExtractor,
+  filter_output::FilterOutputListener,
+  log_parser::{
+    try_parse_error, BrokenPipeFilter, ErrorBlockAggregator, FfmpegLogParser, LogStats,
+  },
+  metadata::{FfmpegMetadata, MetadataError},
+  output_pipe::OutputPipeReader,
   pix_fmt::get_bytes_per_frame,
+  progress_listener::ProgressListener,
+  resource_usage::spawn_resource_sampler,
+  temp_file::TempFile,
+  watchdog::{ActivityClock, ProcessKiller, StallWatchdog},
 };
 
+/// Arbitrary default buffer size for receiving indeterminate chunks of any
+/// encoder or container output, when frame boundaries are unknown.
+pub const DEFAULT_CHUNK_SIZE: usize = 65_536;
+
+/// A small pool of byte buffers shared between the stdout thread and the
+/// consumer of `OutputChunk`s. Buffers returned with [`ChunkPool::recycle`] are
+/// reused for subsequent reads instead of allocating a new `Vec` per chunk.
+///
+/// Recycling is optional; chunks which are simply dropped are replaced by
+/// fresh allocations.
+#[derive(Debug, Clone, Default)]
+pub struct ChunkPool {
+  buffers: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl ChunkPool {
+  /// The maximum number of idle buffers retained by the pool.
+  pub const CAPACITY: usize = 8;
+
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Return a consumed chunk to the pool so its allocation can be reused.
+  pub fn recycle(&self, mut buffer: Vec<u8>) {
+    let Ok(mut buffers) = self.buffers.lock() else {
+      return;
+    };
+    if buffers.len() < Self::CAPACITY {
+      buffer.clear();
+      buffers.push(buffer);
+    }
+  }
+
+  /// Obtain a buffer of exactly `size` bytes, reusing a pooled allocation if
+  /// one is available.
+  pub fn take(&self, size: usize) -> Vec<u8> {
+    let mut buffer = self
+      .buffers
+      .lock()
+      .ok()
+      .and_then(|mut buffers| buffers.pop())
+      .unwrap_or_default();
+    buffer.resize(size, 0);
+    buffer
+  }
+
+  /// Shorten `buffer` to its first `len` bytes. A read which filled less than
+  /// half of it is copied out instead, returning the full-size allocation to
+  /// the pool rather than pinning it in a short chunk.
+  fn fit(&self, mut buffer: Vec<u8>, len: usize) -> Vec<u8> {
+    if len >= buffer.capacity() / 2 {
+      buffer.truncate(len);
+      return buffer;
+    }
+    let chunk = buffer[..len].to_vec();
+    self.recycle(buffer);
+    chunk
+  }
+
+  /// The number of idle buffers currently held by the pool.
+  pub fn len(&self) -> usize {
+    self.buffers.lock().map(|b| b.len()).unwrap_or(0)
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+}
+
+/// Options for the stdout thread which are independent of the parsed
+/// metadata.
+#[derive(Debug, Clone)]
+pub(crate) struct StdoutConfig {
+  pub(crate) chunk_size: usize,
+  pub(crate) pool: ChunkPool,
+  /// See `FfmpegCommand::expect_no_output`.
+  pub(crate) expect_no_output: bool,
+  /// See `FfmpegCommand::sample_throughput`.
+  pub(crate) throughput_interval: Option<Duration>,
+  /// Cumulative bytes read from stdout, shared with the iterator.
+  pub(crate) bytes_read: Arc<AtomicU64>,
+  /// See `FfmpegCommand::scaled_outputs`.
+  pub(crate) output_tags: BTreeMap<u32, String>,
+  /// See `FfmpegCommand::quiet`: without any output descriptions, stdout is
+  /// read in chunks as soon as the iterator is created.
+  pub(crate) quiet: bool,
+  /// Raised when nothing receives the output anymore, shared with the child
+  /// and the stderr thread.
+  pub(crate) consumer_closed: Arc<AtomicBool>,
+  /// See `FfmpegCommand::audio_samples`.
+  pub(crate) audio_samples: bool,
+  /// The indices of the outputs added with `FfmpegCommand::output_pipe`,
+  /// which are read by their own threads instead.
+  pub(crate) piped_outputs: Vec<u32>,
+  /// Touched for every read, for the stall watchdog.
+  pub(crate) activity: ActivityClock,
+  /// When the first output was read, shared with the iterator, which may not
+  /// see the output itself once split with `split_channels`.
+  pub(crate) first_output: Arc<OnceLock<Instant>>,
+}
+
+impl Default for StdoutConfig {
+  fn default() -> Self {
+    Self {
+      chunk_size: DEFAULT_CHUNK_SIZE,
+      pool: ChunkPool::new(),
+      expect_no_output: false,
+      throughput_interval: None,
+      bytes_read: Arc::new(AtomicU64::new(0)),
+      output_tags: BTreeMap::new(),
+      quiet: false,
+      consumer_closed: Arc::new(AtomicBool::new(false)),
+      audio_samples: false,
+      piped_outputs: Vec::new(),
+      activity: ActivityClock::default(),
+      first_output: Arc::new(OnceLock::new()),
+    }
+  }
+}
+
+impl StdoutConfig {
+  /// Record that output was read.
+  pub(crate) fn record_read(&self) {
+    self.activity.touch();
+    self.first_output.get_or_init(Instant::now);
+  }
+}
+
+/// Sleeps until each frame is due according to its timestamp; see
+/// `FfmpegIterator::paced`.
+struct FramePacer {
+  realtime: bool,
+  /// The wall clock time and timestamp of the reference frame: the first
+  /// frame in realtime mode, otherwise the previous frame.
+  reference: Option<(Instant, f32)>,
+}
+
+impl FramePacer {
+  fn new(realtime: bool) -> Self {
+    Self {
+      realtime,
+      reference: None,
+    }
+  }
+
+  fn wait(&mut self, timestamp: f32) {
+    let Some((instant, reference_timestamp)) = self.reference else {
+      self.reference = Some((Instant::now(), timestamp));
+      return;
+    };
+    let offset = Duration::from_secs_f32((timestamp - reference_timestamp).max(0.0));
+    if let Some(delay) = (instant + offset).checked_duration_since(Instant::now()) {
+      std::thread::sleep(delay);
+    }
+    if !self.realtime {
+      self.reference = Some((Instant::now(), timestamp));
+    }
+  }
+}
+
+/// Counts the bytes read by the stdout thread, producing a `Throughput` event
+/// whenever the configured interval has elapsed.
+pub(crate) struct ThroughputMeter {
+  total: Arc<AtomicU64>,
+  interval: Option<Duration>,
+  last_instant: Instant,
+  last_total: u64,
+}
+
+impl ThroughputMeter {
+  pub(crate) fn new(config: &StdoutConfig) -> Self {
+    Self {
+      total: config.bytes_read.clone(),
+      interval: config.throughput_interval,
+      last_instant: Instant::now(),
+      last_total: 0,
+    }
+  }
+
+  fn record(&mut self, bytes: usize) -> Option<FfmpegEvent> {
+    let total = self.total.fetch_add(bytes as u64, Ordering::Relaxed) + bytes as u64;
+    let elapsed = self.last_instant.elapsed();
+    if elapsed < self.interval? {
+      return None;
+    }
+    let bytes_per_sec = (total - self.last_total) as f64 / elapsed.as_secs_f64();
+    self.last_instant = Instant::now();
+    self.last_total = total;
+    Some(FfmpegEvent::Throughput {
+      bytes_per_sec,
+      total_bytes: total,
+    })
+  }
+}
+
+/// Why an [`FfmpegIterator`] couldn't be created.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IterError {
+  /// Neither stdout nor stderr is available, e.g. because both were taken
+  /// or never piped.
+  NoPipes,
+  /// Without stderr, the format of stdout couldn't be determined from the
+  /// command's arguments.
+  UnknownOutput(String),
+}
+
+impl fmt::Display for IterError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      IterError::NoPipes => write!(f, "Neither stdout nor stderr of FFmpeg is piped"),
+      IterError::UnknownOutput(e) => write!(f, "Can't describe stdout without stderr: {e}"),
+    }
+  }
+}
+
+impl std::error::Error for IterError {}
+
 /// An iterator over events from an ffmpeg process, including parsed metadata, progress, and raw video frames.
-pub struct FfmpegIterator {
+pub struct FfmpegIterator<B: ProcessBackend = Child> {
   rx: Receiver<FfmpegEvent>,
   tx: Option<SyncSender<FfmpegEvent>>,
-  stdout: Option<ChildStdout>,
+  /// Replaces `tx` for the stdout thread after `split_channels`.
+  frame_tx: Option<SyncSender<FfmpegEvent>>,
+  stdout: Option<B::Stdout>,
   metadata: FfmpegMetadata,
+  stdout_config: StdoutConfig,
+  error_hooks: Vec<ErrorHook>,
+  event_hooks: EventHooks,
+  log_stats: Arc<Mutex<LogStats>>,
+  /// Only present for iterators created by `FfmpegChild::into_events`.
+  child: Option<FfmpegChild<B>>,
+  /// Whether to end with `FfmpegEvent::Completed`; see `with_completed`.
+  emit_completed: bool,
+  completed: bool,
+  spawned_at: Instant,
+  startup_timings: StartupTimings,
+  startup_timings_sent: bool,
+  /// Shared with the stderr thread, for the startup timings.
+  log_read_times: LogReadTimes,
+  /// Events to return before receiving any new ones: those consumed by
+  /// `FfmpegChild::wait_until_writing`, and those held back behind an event
+  /// derived from them, like `StartupTimings` or `EncoderFallback`.
+  replayed: VecDeque<FfmpegEvent>,
+  watchdog: Option<StallWatchdog>,
+  /// Reads the reports of `FfmpegCommand::progress_over_tcp`.
+  progress_listener: Option<ProgressListener>,
+  /// Read the destinations of `FfmpegCommand::capture_filter_output`.
+  filter_outputs: Vec<FilterOutputListener>,
+  /// Read the outputs of `FfmpegCommand::output_pipe`, emitting their
+  /// events once they've been described.
+  pipe_readers: Vec<OutputPipeReader>,
+  /// See `CommandConfig::output_args`.
+  output_args: Vec<FileArgs>,
+  /// Kills FFmpeg when the iterator doesn't own the child.
+  killer: ProcessKiller,
+  /// Touched by the stderr thread for every event it reads. Along with the
+  /// stdout thread's `StdoutConfig::activity`, this times `with_timeout`.
+  log_activity: ActivityClock,
+  /// Set by `with_timeout`.
+  timeout: Option<InactivityTimeout>,
+}
+
+/// The state of [`FfmpegIterator::with_timeout`].
+struct InactivityTimeout {
+  window: Duration,
+  /// FFmpeg has been killed, so the rest of its events are awaited as usual.
+  fired: bool,
 }
 
-impl FfmpegIterator {
-  pub fn new(child: &mut FfmpegChild) -> anyhow::Result<Self> {
-    let stderr = child.take_stderr().context("No stderr channel\n - Did you call `take_stderr` elsewhere?\n - Did you forget to call `.stderr(Stdio::piped)` on the `ChildProcess`?")?;
+/// A callback registered with [`FfmpegIterator::inspect_errs`].
+type ErrorHook = Box<dyn FnMut(&str) + Send>;
+
+/// Callbacks registered with [`FfmpegIterator::on_event`], shared with the
+/// stderr thread which runs them.
+type EventHooks = Arc<Mutex<Vec<Box<dyn FnMut(&FfmpegEvent) -> bool + Send>>>>;
+
+impl<B: ProcessBackend> FfmpegIterator<B> {
+  pub fn new(child: &mut FfmpegChild<B>) -> anyhow::Result<Self> {
+    // Without stderr, only the end of the log is reported and stdout is
+    // described by the arguments instead
+    let stderr = child.take_recorded_stderr();
+    let has_stderr = stderr.is_some();
+    if !has_stderr && child.as_inner_mut().stdout().is_none() {
+      return Err(IterError::NoPipes.into());
+    }
+    let stderr = stderr.unwrap_or_else(|| Box::new(std::io::empty()));
     let (tx, rx) = sync_channel::<FfmpegEvent>(0);
-    spawn_stderr_thread(stderr, tx.clone());
+    let activity = ActivityClock::default();
+    let log_activity = ActivityClock::default();
+    let stderr_config = StderrConfig {
+      extractors: child.config().extractors.clone(),
+      error_blocks: child.config().error_blocks,
+      raw_log_lines: child.config().raw_log_lines,
+      filter_scripts: child.config().filter_scripts.clone(),
+      consumer_closed: child.consumer_closed(),
+      activity: activity.clone(),
+      log_is_activity: child.config().no_stats,
+      log_activity: log_activity.clone(),
+      ffmpeg_path: child.config().ffmpeg_path.clone(),
+      ..Default::default()
+    };
+    let event_hooks = stderr_config.hooks.clone();
+    let log_read_times = stderr_config.read_times.clone();
+    let log_stats = stderr_config.stats.clone();
+    spawn_stderr_thread_with_config(stderr, tx.clone(), stderr_config);
+    let pid = child.as_inner().id();
+    if let (Some(interval), Some(pid)) = (child.config().resource_sample_interval, pid) {
+      spawn_resource_sampler(pid, interval, tx.clone());
+    }
+    let watchdog = child
+      .config()
+      .stall_watchdog
+      .map(|config| StallWatchdog::spawn(child.killer(), config, activity.clone(), tx.clone()));
+    let progress_listener = child
+      .take_progress_output()
+      .map(|output| ProgressListener::spawn(output, activity.clone(), tx.clone()));
+    let filter_outputs = child
+      .config()
+      .filter_outputs
+      .iter()
+      .map(|capture| FilterOutputListener::spawn(capture.clone(), tx.clone()))
+      .collect();
+    let output_pipes = child.config().output_pipes.clone();
     let stdout = child.take_stdout();
+    let stdout_config = StdoutConfig {
+      chunk_size: child
+        .config()
+        .stdout_chunk_size
+        .unwrap_or(DEFAULT_CHUNK_SIZE),
+      pool: ChunkPool::new(),
+      expect_no_output: child.config().expect_no_output,
+      throughput_interval: child.config().throughput_interval,
+      bytes_read: Arc::new(AtomicU64::new(0)),
+      output_tags: child.config().output_tags.clone(),
+      quiet: child.config().quiet,
+      consumer_closed: child.consumer_closed(),
+      audio_samples: child.config().audio_samples,
+      piped_outputs: output_pipes.iter().map(|pipe| pipe.output_index).collect(),
+      activity,
+      first_output: Arc::new(OnceLock::new()),
+    };
+    // Drain the outputs of `output_pipe` from the start, so none of them
+    // fills up and blocks FFmpeg before every output is described
+    let pipe_readers = output_pipes
+      .into_iter()
+      .map(|pipe| OutputPipeReader::spawn(pipe, &stdout_config))
+      .collect();
 
-    Ok(Self {
+    let mut iter = Self {
       rx,
       tx: Some(tx),
+      frame_tx: None,
       stdout,
       metadata: FfmpegMetadata::new(),
-    })
+      stdout_config,
+      error_hooks: Vec::new(),
+      event_hooks,
+      log_stats,
+      child: None,
+      emit_completed: false,
+      completed: false,
+      spawned_at: child.spawned_at(),
+      startup_timings: StartupTimings::default(),
+      startup_timings_sent: false,
+      log_read_times,
+      replayed: VecDeque::new(),
+      watchdog,
+      progress_listener,
+      filter_outputs,
+      pipe_readers,
+      output_args: child.config().output_args.clone(),
+      killer: child.killer(),
+      log_activity,
+      timeout: None,
+    };
+
+    // Nothing to wait for before reading stdout
+    if !has_stderr {
+      let config = child.config();
+      iter.metadata = FfmpegMetadata::from_args(&config.input_args, &config.output_args)?;
+      iter.start_stdout()?;
+    } else if iter.stdout_config.quiet {
+      iter.metadata.finish();
+      iter.start_stdout()?;
+    }
+    Ok(iter)
+  }
+
+  /// Take ownership of the child process, so that it can be reaped and its
+  /// exit status reported in `FfmpegEvent::Completed`.
+  pub(crate) fn with_child(mut self, child: FfmpegChild<B>) -> Self {
+    self.child = Some(child);
+    self.with_completed()
+  }
+
+  /// End with a `FfmpegEvent::Completed` event once both stderr and stdout
+  /// have closed, telling whether there was any output. Always on for
+  /// iterators created by `FfmpegChild::into_events`, and otherwise off, so
+  /// that consumers expecting the iterator to end after `LogEOF` and `Done`
+  /// see no extra event.
+  pub fn with_completed(mut self) -> Self {
+    self.emit_completed = true;
+    self
+  }
+
+  /// A handle to the pool of buffers used for `OutputChunk`s. Consumed chunks
+  /// can be passed to [`ChunkPool::recycle`] to avoid a new allocation for
+  /// every chunk read from stdout. The handle remains valid after the iterator
+  /// itself is consumed by an adapter like `filter_chunks`.
+  pub fn chunk_pool(&self) -> ChunkPool {
+    self.stdout_config.pool.clone()
+  }
+
+  /// The total number of bytes read from stdout so far, as frames or chunks.
+  pub fn bytes_read(&self) -> u64 {
+    self.stdout_config.bytes_read.load(Ordering::Relaxed)
+  }
+
+  /// Deliver output on its own bounded channel, separately from all other
+  /// events, so that a slow consumer of logs and progress never holds up
+  /// frames, and a slow consumer of frames never holds up logs.
+  ///
+  /// The [`FrameReceiver`] yields what is read from stdout: `OutputFrame`s or
+  /// `OutputChunk`s, `Throughput`, and `Done` or stdout errors, along with the
+  /// output of any `FfmpegCommand::output_pipe`. It buffers up
+  /// to [`FrameReceiver::CAPACITY`] events before FFmpeg is made to wait. The
+  /// [`LogReceiver`] yields everything else, ending with `Completed` if
+  /// enabled (see [`with_completed`](Self::with_completed)), and is
+  /// unbounded. Either one may be dropped if unneeded.
+  ///
+  /// ```rust,no_run
+  /// use ffmpeg_sidecar::{command::FfmpegCommand, event::FfmpegEvent};
+  ///
+  /// let iter = FfmpegCommand::new().testsrc().rawvideo().spawn()?.iter()?;
+  /// let (logs, frames) = iter.split_channels();
+  /// std::thread::spawn(move || {
+  ///   for progress in logs.filter_map(|e| match e {
+  ///     FfmpegEvent::Progress(p) => Some(p),
+  ///     _ => None,
+  ///   }) {
+  ///     println!("{}", progress.time);
+  ///   }
+  /// });
+  /// for event in frames {
+  ///   if let FfmpegEvent::OutputFrame(frame) = event {
+  ///     println!("frame {}", frame.frame_num);
+  ///   }
+  /// }
+  /// # anyhow::Ok(())
+  /// ```
+  ///
+  /// The iterator itself keeps running on a background thread, along with any
+  /// hooks registered with `inspect_errs` or `on_event`.
+  pub fn split_channels(mut self) -> (LogReceiver, FrameReceiver) {
+    let (log_tx, log_rx) = channel();
+    let (frame_tx, frame_rx) = sync_channel(FrameReceiver::CAPACITY);
+    self.frame_tx = Some(frame_tx.clone());
+    std::thread::spawn(move || {
+      for event in self {
+        // Output only arrives here if stdout was already being read before
+        // the split.
+        match event {
+          FfmpegEvent::OutputFrame(_)
+          | FfmpegEvent::OutputAudioSamples(_)
+          | FfmpegEvent::OutputChunk(_)
+          | FfmpegEvent::OutputPipeChunk { .. } => frame_tx.send(event).ok(),
+          _ => log_tx.send(event).ok(),
+        };
+      }
+    });
+    (LogReceiver(log_rx), FrameReceiver(frame_rx))
+  }
+
+  /// How many lines have been parsed from FFmpeg's logs so far, and how many
+  /// of them were unrecognized (`LogLevel::Unknown`), with a sample of the
+  /// latter. A rising share of unknown lines after an FFmpeg upgrade suggests
+  /// that its log format changed in a way the parser doesn't handle.
+  ///
+  /// Lines are counted as they're parsed, including those dropped by
+  /// [`on_event`](Self::on_event) hooks.
+  pub fn log_stats(&self) -> LogStats {
+    self
+      .log_stats
+      .lock()
+      .map(|stats| stats.clone())
+      .unwrap_or_default()
   }
 
   /// Called after all metadata has been obtained to spawn the thread that will
@@ -45,19 +536,36 @@ impl FfmpegIterator {
   /// other parameters.
   fn start_stdout(&mut self) -> anyhow::Result<()> {
     // No output detected
-    if self.metadata.output_streams.is_empty() || self.metadata.outputs.is_empty() {
+    let no_output = self.metadata.output_streams.is_empty() || self.metadata.outputs.is_empty();
+    if no_output && !self.stdout_config.expect_no_output && !self.stdout_config.quiet {
       let err = "No output streams found";
       self.tx.take(); // drop the tx so that the channel closes
       anyhow::bail!(err)
     }
 
+    // Handle the outputs of `output_pipe`, each on its own connection
+    if let Some(tx) = self.frame_tx.as_ref().or(self.tx.as_ref()) {
+      for reader in &self.pipe_readers {
+        let streams = self
+          .metadata
+          .output_streams
+          .iter()
+          .filter(|stream| stream.parent_index == reader.output_index())
+          .cloned()
+          .collect();
+        reader.describe(streams, tx.clone());
+      }
+    }
+
     // Handle stdout
     if let Some(stdout) = self.stdout.take() {
-      spawn_stdout_thread(
+      let tx = self.tx.take().context("missing channel tx")?;
+      spawn_stdout_thread_with_config(
         stdout,
-        self.tx.take().context("missing channel tx")?,
+        self.frame_tx.take().unwrap_or(tx),
         self.metadata.output_streams.clone(),
         self.metadata.outputs.clone(),
+        self.stdout_config.clone(),
       );
     }
 
@@ -65,7 +573,14 @@ impl FfmpegIterator {
   }
 
   /// Advance the iterator until all metadata has been collected, returning it.
+  ///
+  /// Fails with a [`MetadataError`] if FFmpeg exits first, e.g. when a
+  /// capture device can't be opened, which can be told apart with
+  /// `downcast_ref`.
   pub fn collect_metadata(&mut self) -> anyhow::Result<FfmpegMetadata> {
+    if self.stdout_config.quiet {
+      return Err(MetadataError::NotLogged.into());
+    }
     let mut event_queue: Vec<FfmpegEvent> = Vec::new();
 
     while !self.metadata.is_completed() {
@@ -79,12 +594,13 @@ impl FfmpegIterator {
               FfmpegEvent::Error(e) | FfmpegEvent::Log(LogLevel::Error, e) => Some(e.to_string()),
               _ => None,
             })
-            .collect::<Vec<String>>()
-            .join("");
+            .collect::<Vec<String>>();
 
-          anyhow::bail!(
-            "Iterator ran out before metadata was gathered. The following errors occurred: {errors}",
-          )
+          let error = match self.metadata.inputs.is_empty() {
+            true => MetadataError::NoInputs(errors),
+            false => MetadataError::Incomplete(errors),
+          };
+          return Err(error.into());
         }
       }
     }
@@ -92,9 +608,123 @@ impl FfmpegIterator {
     Ok(self.metadata.clone())
   }
 
+  //// Error handling
+
+  /// Call `f` with the message of every error event (`FfmpegEvent::Error` and
+  /// `FfmpegEvent::Log(LogLevel::Error, _)`) as it passes through the
+  /// iterator. Events are not otherwise affected, so this can be chained with
+  /// any of the other adapters:
+  ///
+  /// ```rust,no_run
+  /// use ffmpeg_sidecar::command::FfmpegCommand;
+  ///
+  /// let frames = FfmpegCommand::new()
+  ///   .testsrc()
+  ///   .rawvideo()
+  ///   .spawn()?
+  ///   .iter()?
+  ///   .inspect_errs(|e| eprintln!("FFmpeg error: {e}"))
+  ///   .filter_frames();
+  /// # anyhow::Ok(())
+  /// ```
+  pub fn inspect_errs<F: FnMut(&str) + Send + 'static>(mut self, f: F) -> Self {
+    self.error_hooks.push(Box::new(f));
+    self
+  }
+
+  /// Kill FFmpeg and emit an `FfmpegEvent::Error` if no event arrives for
+  /// `timeout`, e.g. because a network input went silent. The events which
+  /// follow, like `LogEOF` and `Done`, are emitted as usual. Periodic events
+  /// like `ResourceUsage` don't count as activity.
+  ///
+  /// The time is measured from when FFmpeg's output was last read by the
+  /// iterator's threads, not from when an event was last consumed, so a slow
+  /// consumer isn't mistaken for a silent FFmpeg.
+  ///
+  /// Unlike [`FfmpegChild::stall_watchdog`], which watches for progress,
+  /// this catches FFmpeg going entirely silent, including while it's still
+  /// opening its inputs. Applies to the events read from this iterator, not
+  /// those of [`split_channels`](Self::split_channels).
+  ///
+  /// ```rust,no_run
+  /// use ffmpeg_sidecar::command::FfmpegCommand;
+  /// use std::time::Duration;
+  ///
+  /// let frames = FfmpegCommand::new()
+  ///   .input("rtsp://camera.local/stream")
+  ///   .rawvideo()
+  ///   .spawn()?
+  ///   .iter()?
+  ///   .with_timeout(Duration::from_secs(15))
+  ///   .filter_frames();
+  /// # anyhow::Ok(())
+  /// ```
+  pub fn with_timeout(mut self, timeout: Duration) -> Self {
+    self.timeout = Some(InactivityTimeout {
+      window: timeout,
+      fired: false,
+    });
+    self
+  }
+
+  /// Call `f` with every event parsed from FFmpeg's logs, on the thread which
+  /// parses them, before the event is sent to this iterator. Returning `false`
+  /// drops the event, so high-volume events like `Progress` or `Log` that
+  /// aren't needed never take up channel capacity or wake the consumer:
+  ///
+  /// ```rust,no_run
+  /// use ffmpeg_sidecar::{command::FfmpegCommand, event::FfmpegEvent};
+  /// use std::sync::atomic::{AtomicUsize, Ordering};
+  /// use std::sync::Arc;
+  ///
+  /// let log_lines = Arc::new(AtomicUsize::new(0));
+  /// let counter = log_lines.clone();
+  /// let frames = FfmpegCommand::new()
+  ///   .testsrc()
+  ///   .rawvideo()
+  ///   .spawn()?
+  ///   .iter()?
+  ///   .on_event(move |event| {
+  ///     counter.fetch_add(1, Ordering::Relaxed);
+  ///     !matches!(event, FfmpegEvent::Progress(_) | FfmpegEvent::Log(..))
+  ///   })
+  ///   .filter_frames();
+  /// # anyhow::Ok(())
+  /// ```
+  ///
+  /// Hooks must be cheap, since parsing (and therefore output) stalls while
+  /// they run. They see events parsed after they're registered, so register
+  /// them before the first call to `next`. Output frames and chunks don't pass
+  /// through hooks. The `LogEOF` event and the metadata events (`ParsedInput`,
+  /// `ParsedOutput`, etc.) are always delivered regardless of the return value,
+  /// since the iterator needs them to locate the output streams.
+  pub fn on_event<F: FnMut(&FfmpegEvent) -> bool + Send + 'static>(self, f: F) -> Self {
+    if let Ok(mut hooks) = self.event_hooks.lock() {
+      hooks.push(Box::new(f));
+    }
+    self
+  }
+
+  /// Print every error message to stderr as it is received. Shorthand for
+  /// `inspect_errs(|e| eprintln!(...))`.
+  pub fn log_errors(self) -> Self {
+    self.inspect_errs(|e| eprintln!("[ffmpeg] Error: {e}"))
+  }
+
+  /// Run the iterator to completion, discarding all other events. Returns
+  /// `Err` with every error message joined by newlines if any errors were
+  /// encountered.
+  pub fn collect_errors(self) -> anyhow::Result<()> {
+    let errors = self.filter_errors().collect::<Vec<String>>();
+    match errors.is_empty() {
+      true => Ok(()),
+      false => anyhow::bail!(errors.join("\n")),
+    }
+  }
+
   //// Iterator filters
 
-  /// Returns an iterator over error messages (`FfmpegEvent::Error` and `FfmpegEvent::LogError`).
+  /// Returns an iterator over error messages (`FfmpegEvent::Error` and `FfmpegEvent::Log` at the `Error` level).
   pub fn filter_errors(self) -> impl Iterator<Item = String> {
     self.filter_map(|event| match event {
       FfmpegEvent::Error(e) | FfmpegEvent::Log(LogLevel::Error, e) => Some(e),
@@ -118,6 +748,36 @@ impl FfmpegIterator {
     })
   }
 
+  /// Like `filter_frames`, but drops every frame whose pixels are identical
+  /// to the previous frame of the same output, as produced by `-fps_mode cfr`
+  /// duplication or a static screen capture. Kept frames retain their
+  /// original `frame_num` and `timestamp`.
+  pub fn dedup_frames(self) -> impl Iterator<Item = OutputVideoFrame> {
+    let mut previous_hashes = std::collections::HashMap::new();
+    self.filter_frames().filter(move |frame| {
+      let hash = frame.hash();
+      previous_hashes.insert(frame.output_index, hash) != Some(hash)
+    })
+  }
+
+  /// Filter out all events except for raw audio samples
+  /// (`FfmpegEvent::OutputAudioSamples`). Without
+  /// `FfmpegCommand::audio_samples`, `OutputChunk`s are decoded here instead,
+  /// using the format of the first parsed audio output stream, which must be
+  /// raw PCM on stdout.
+  pub fn filter_audio(self) -> impl Iterator<Item = OutputAudioSamples> {
+    let mut decoder: Option<AudioSampleDecoder> = None;
+    self.filter_map(move |event| match event {
+      FfmpegEvent::OutputAudioSamples(samples) => Some(samples),
+      FfmpegEvent::ParsedOutputStream(stream) if decoder.is_none() => {
+        decoder = AudioSampleDecoder::for_stream(&stream);
+        None
+      }
+      FfmpegEvent::OutputChunk(chunk) => decoder.as_mut()?.push(&chunk),
+      _ => None,
+    })
+  }
+
   /// Filter out all events except for output chunks (`FfmpegEvent::OutputChunk`).
   pub fn filter_chunks(self) -> impl Iterator<Item = Vec<u8>> {
     self.filter_map(|event| match event {
@@ -126,37 +786,296 @@ impl FfmpegIterator {
     })
   }
 
+  /// Like `filter_chunks`, but parses the encoded bitstream (H.264/HEVC Annex B
+  /// or MPEG-TS) to tag each chunk with keyframe and approximate timestamp
+  /// information. The frame rate used to estimate Annex B timestamps is taken
+  /// from the first parsed video output stream.
+  pub fn filter_tagged_chunks(self, format: ChunkFormat) -> impl Iterator<Item = TaggedChunk> {
+    let mut tagger = ChunkTagger::new(format);
+    let mut frame_rate_known = false;
+    self.filter_map(move |event| match event {
+      FfmpegEvent::ParsedOutputStream(stream) if !frame_rate_known => {
+        if let Some(video_data) = stream.video_data() {
+          tagger.set_frame_rate(video_data.fps);
+          frame_rate_known = true;
+        }
+        None
+      }
+      FfmpegEvent::OutputChunk(vec) => Some(tagger.tag(vec)),
+      _ => None,
+    })
+  }
+
+  /// Meter raw PCM audio output in Rust, yielding the RMS and peak level of
+  /// each channel for every `window` of audio. Unlike the `ebur128` or
+  /// `astats` filters, this works with any FFmpeg build.
+  ///
+  /// The sample format, rate and channel layout are taken from the first
+  /// parsed audio output stream, which must be raw PCM on stdout, e.g. `-f
+  /// s16le -` or `-f f32le -`. Samples decoded with
+  /// `FfmpegCommand::audio_samples` are metered just the same.
+  ///
+  /// ```rust,no_run
+  /// use ffmpeg_sidecar::command::FfmpegCommand;
+  /// use std::time::Duration;
+  ///
+  /// let levels = FfmpegCommand::new()
+  ///   .input("input.mp3")
+  ///   .args(["-f", "s16le", "-"])
+  ///   .spawn()?
+  ///   .iter()?
+  ///   .filter_audio_levels(Duration::from_millis(100));
+  /// for level in levels {
+  ///   println!("{:?} ch{}: {:.1} dBFS", level.time, level.channel, level.rms_db);
+  /// }
+  /// # anyhow::Ok(())
+  /// ```
+  pub fn filter_audio_levels(self, window: Duration) -> impl Iterator<Item = AudioLevel> {
+    let mut meter: Option<AudioLevelMeter> = None;
+    self.flat_map(move |event| match event {
+      FfmpegEvent::ParsedOutputStream(stream) if meter.is_none() => {
+        meter = AudioLevelMeter::for_stream(&stream, window);
+        Vec::new()
+      }
+      FfmpegEvent::OutputChunk(chunk) => match meter.as_mut() {
+        Some(meter) => meter.push(&chunk),
+        None => Vec::new(),
+      },
+      FfmpegEvent::OutputAudioSamples(block) => match meter.as_mut() {
+        Some(meter) => meter.push_samples(&block.samples),
+        None => Vec::new(),
+      },
+      _ => Vec::new(),
+    })
+  }
+
+  /// Slow the iterator down so that `OutputFrame`s are yielded at the speed
+  /// given by their timestamps, for consumers like preview windows or virtual
+  /// cameras when FFmpeg renders faster than realtime. Other events pass
+  /// through as soon as they arrive.
+  ///
+  /// With `realtime`, frames are scheduled against the wall clock from the
+  /// first frame, so a consumer which falls behind catches up without
+  /// sleeping. Otherwise, the gap between consecutive timestamps is always
+  /// waited out after the previous frame, so delays accumulate but no frame
+  /// is ever rushed.
+  ///
+  /// ```rust,no_run
+  /// use ffmpeg_sidecar::{command::FfmpegCommand, event::FfmpegEvent};
+  ///
+  /// let iter = FfmpegCommand::new().testsrc().rawvideo().spawn()?.iter()?;
+  /// for event in iter.paced(true) {
+  ///   if let FfmpegEvent::OutputFrame(frame) = event {
+  ///     println!("showing frame {} at {}s", frame.frame_num, frame.timestamp);
+  ///   }
+  /// }
+  /// # anyhow::Ok(())
+  /// ```
+  pub fn paced(self, realtime: bool) -> impl Iterator<Item = FfmpegEvent> {
+    let mut pacer = FramePacer::new(realtime);
+    self.inspect(move |event| {
+      if let FfmpegEvent::OutputFrame(frame) = event {
+        pacer.wait(frame.timestamp);
+      }
+    })
+  }
+
   /// Iterator over every message from ffmpeg's stderr as a raw string.
   /// Conceptually equivalent to `BufReader::new(ffmpeg_stderr).lines()`.
   pub fn into_ffmpeg_stderr(self) -> impl Iterator<Item = String> {
     self.filter_map(|event| match event {
       FfmpegEvent::ParsedVersion(x) => Some(x.raw_log_message),
       FfmpegEvent::ParsedConfiguration(x) => Some(x.raw_log_message),
-      FfmpegEvent::ParsedStreamMapping(x) => Some(x),
+      FfmpegEvent::ParsedStreamMapping(x) => Some(x.raw_log_message),
       FfmpegEvent::ParsedOutput(x) => Some(x.raw_log_message),
       FfmpegEvent::ParsedInputStream(x) => Some(x.raw_log_message),
       FfmpegEvent::ParsedOutputStream(x) => Some(x.raw_log_message),
+      FfmpegEvent::ParsedOutputStreamEncoder { .. } => None,
       FfmpegEvent::Log(_, x) => Some(x),
       FfmpegEvent::LogEOF => None,
       FfmpegEvent::Error(_) => None,
       FfmpegEvent::Progress(x) => Some(x.raw_log_message),
       FfmpegEvent::OutputFrame(_) => None,
+      FfmpegEvent::OutputAudioSamples(_) => None,
       FfmpegEvent::OutputChunk(_) => None,
+      FfmpegEvent::OutputPipeChunk { .. } => None,
       FfmpegEvent::Done => None,
+      FfmpegEvent::ResourceUsage { .. } => None,
+      FfmpegEvent::Throughput { .. } => None,
+      FfmpegEvent::StartupTimings(_) => None,
+      FfmpegEvent::EncoderFallback { .. } => None,
+      FfmpegEvent::Extracted(_) => None,
+      FfmpegEvent::ParsedError(_) => None,
+      FfmpegEvent::RawLogLine(_) => None,
+      FfmpegEvent::ErrorBlock { .. } => None,
+      FfmpegEvent::Stalled { .. } => None,
+      FfmpegEvent::ConsumerClosed => None,
+      FfmpegEvent::FilterOutput { .. } => None,
+      FfmpegEvent::Completed { .. } => None,
       FfmpegEvent::ParsedInput(input) => Some(input.raw_log_message),
       FfmpegEvent::ParsedDuration(duration) => Some(duration.raw_log_message),
+      FfmpegEvent::ParsedDriftCompensation(x) => Some(x.raw_log_message),
+      FfmpegEvent::FilterMetadata(x) => Some(x.raw_log_message),
     })
   }
 }
 
-impl Iterator for FfmpegIterator {
+impl<B: ProcessBackend> Drop for FfmpegIterator<B> {
+  /// An owned child can't be waited on once its iterator is gone, so if it
+  /// hasn't been reaped yet, it's stopped with `quit_or_kill` in the
+  /// background rather than left running.
+  fn drop(&mut self) {
+    let Some(mut child) = self.child.take() else {
+      return;
+    };
+    self
+      .stdout_config
+      .consumer_closed
+      .store(true, Ordering::Relaxed);
+    // Closing the channel lets the threads reading FFmpeg's output drain it
+    self.rx = sync_channel(0).1;
+    std::thread::spawn(move || child.quit_or_kill(CONSUMER_CLOSED_GRACE));
+  }
+}
+
+impl<B: ProcessBackend> Iterator for FfmpegIterator<B> {
   type Item = FfmpegEvent;
 
   fn next(&mut self) -> Option<Self::Item> {
-    let item = self.rx.recv().ok();
+    if let Some(event) = self.replayed.pop_front() {
+      return Some(event);
+    }
+    let item = match self.timeout.is_some() {
+      true => self.recv_or_time_out(),
+      false => self.rx.recv().ok(),
+    };
+    self.handle_received(item)
+  }
+}
+
+impl<B: ProcessBackend> FfmpegIterator<B> {
+  /// Like `next`, but giving up at `deadline` if no event has arrived.
+  pub(crate) fn next_until(
+    &mut self,
+    deadline: Instant,
+  ) -> Result<Option<FfmpegEvent>, RecvTimeoutError> {
+    if let Some(event) = self.replayed.pop_front() {
+      return Ok(Some(event));
+    }
+    let timeout = deadline.saturating_duration_since(Instant::now());
+    let item = match self.rx.recv_timeout(timeout) {
+      Ok(event) => Some(event),
+      Err(RecvTimeoutError::Disconnected) => None,
+      Err(RecvTimeoutError::Timeout) => return Err(RecvTimeoutError::Timeout),
+    };
+    Ok(self.handle_received(item))
+  }
+
+  /// Receive the next event, or kill FFmpeg and return an error if none
+  /// arrives within the window of `with_timeout`.
+  fn recv_or_time_out(&mut self) -> Option<FfmpegEvent> {
+    let timeout = self.timeout.as_mut()?;
+    if timeout.fired {
+      return self.rx.recv().ok();
+    }
+    let window = timeout.window;
+    loop {
+      let idle = [&self.log_activity, &self.stdout_config.activity]
+        .into_iter()
+        .filter_map(ActivityClock::elapsed)
+        .min()
+        .unwrap_or_default();
+      let remaining = window.saturating_sub(idle);
+      if remaining.is_zero() {
+        break;
+      }
+      match self.rx.recv_timeout(remaining) {
+        Ok(event) => return Some(event),
+        Err(RecvTimeoutError::Disconnected) => return None,
+        // Output may have been read meanwhile, and be waiting to be sent
+        Err(RecvTimeoutError::Timeout) => continue,
+      }
+    }
+    timeout.fired = true;
+    match &mut self.child {
+      Some(child) => {
+        child.kill().ok();
+      }
+      None => self.killer.kill(),
+    }
+    Some(FfmpegEvent::Error(format!(
+      "No events from FFmpeg for {window:?}; killed it"
+    )))
+  }
+
+  /// Whether every output stream has been described.
+  pub(crate) fn metadata_completed(&self) -> bool {
+    self.metadata.is_completed()
+  }
+
+  /// Return `events`, which were already taken from this iterator, again
+  /// before any new ones.
+  pub(crate) fn replay(&mut self, events: Vec<FfmpegEvent>) {
+    self.replayed.extend(events);
+  }
+
+  /// Process an event received from one of the threads, or `None` once all
+  /// of them have finished.
+  fn handle_received(&mut self, item: Option<FfmpegEvent>) -> Option<FfmpegEvent> {
+    let item = self.next_event(item);
+    match &item {
+      Some(FfmpegEvent::Error(e) | FfmpegEvent::Log(LogLevel::Error, e)) => {
+        self.error_hooks.iter_mut().for_each(|hook| hook(e))
+      }
+      Some(FfmpegEvent::ParsedOutputStream(stream)) => {
+        let fallback = self.detect_encoder_fallback(stream);
+        self.replayed.extend(fallback);
+      }
+      Some(FfmpegEvent::ParsedOutputStreamEncoder {
+        output_index,
+        stream_index,
+        encoder,
+      }) => {
+        let stream = self.metadata.output_streams.iter_mut().find(|stream| {
+          stream.parent_index == *output_index && stream.stream_index == *stream_index
+        });
+        if let Some(stream) = stream {
+          stream.encoder = Some(encoder.clone());
+          let stream = stream.clone();
+          // Only if the stream mapping didn't name the encoder already
+          if self.mapped_encoder(*output_index, *stream_index).is_none() {
+            let fallback = self.detect_encoder_fallback(&stream);
+            self.replayed.extend(fallback);
+          }
+        }
+      }
+      _ => {}
+    }
+    self.record_startup(item)
+  }
+}
+
+impl<B: ProcessBackend> FfmpegIterator<B> {
+  fn next_event(&mut self, item: Option<FfmpegEvent>) -> Option<FfmpegEvent> {
+    // All senders have been dropped, so both stderr and stdout are closed
+    if item.is_none() {
+      return self.complete();
+    }
 
     if let Some(FfmpegEvent::LogEOF) = item {
+      // FFmpeg exited without describing every output, which is expected
+      // when it produces nothing at all. Let the stdout thread finish up.
+      if self.stdout_config.expect_no_output && !self.metadata.is_completed() {
+        self.metadata.finish();
+        if let Err(e) = self.start_stdout() {
+          return Some(FfmpegEvent::Error(e.to_string()));
+        }
+      }
       self.tx.take(); // drop the tx so that the receiver can close
+      self.watchdog.take(); // along with the watchdog's copy
+      self.progress_listener.take(); // and any wait for a progress connection
+      self.filter_outputs.clear(); // and let captured files be read to the end
+      self.pipe_readers.clear(); // and any wait for an output pipe connection
     }
 
     if !self.metadata.is_completed() {
@@ -179,12 +1098,180 @@ impl Iterator for FfmpegIterator {
   }
 }
 
+impl<B: ProcessBackend> FfmpegIterator<B> {
+  /// Update the startup timings with `item`, returning the `StartupTimings`
+  /// event in its place (and queueing the item) once startup is over: at
+  /// the first output, or the first event after it once the output is
+  /// received separately with `split_channels`.
+  ///
+  /// The times are those at which the reader threads read each milestone.
+  fn record_startup(&mut self, item: Option<FfmpegEvent>) -> Option<FfmpegEvent> {
+    if self.startup_timings_sent {
+      return item;
+    }
+    let first_output = self.stdout_config.first_output.get().copied();
+    match &item {
+      Some(
+        FfmpegEvent::OutputFrame(_)
+        | FfmpegEvent::OutputAudioSamples(_)
+        | FfmpegEvent::OutputChunk(_)
+        | FfmpegEvent::OutputPipeChunk { .. }
+        | FfmpegEvent::Completed { .. },
+      ) => {}
+      Some(
+        FfmpegEvent::ResourceUsage { .. }
+        | FfmpegEvent::Throughput { .. }
+        | FfmpegEvent::Stalled { .. }
+        | FfmpegEvent::Error(_),
+      )
+      | None => return item,
+      Some(_) => {
+        // The stderr thread is blocked on sending this event, so its latest
+        // read is this one
+        if self.metadata.is_completed() && self.startup_timings.metadata_complete.is_none() {
+          self.startup_timings.metadata_complete = self.since_spawn(self.log_read_times.latest());
+        }
+        if first_output.is_none() {
+          return item;
+        }
+      }
+    }
+    self.startup_timings.first_log = self.since_spawn(self.log_read_times.first());
+    self.startup_timings.first_output = self.since_spawn(first_output);
+    self.startup_timings_sent = true;
+    // Ahead of any event derived from it
+    if let Some(item) = item {
+      self.replayed.push_front(item);
+    }
+    Some(FfmpegEvent::StartupTimings(self.startup_timings))
+  }
+
+  fn since_spawn(&self, instant: Option<Instant>) -> Option<Duration> {
+    instant.map(|instant| instant.saturating_duration_since(self.spawned_at))
+  }
+
+  /// The encoder named by the stream mapping of an output stream.
+  fn mapped_encoder(&self, output_index: u32, stream_index: u32) -> Option<&str> {
+    let mapping = self.metadata.stream_mappings.iter().find(|mapping| {
+      mapping.to_output == Some(output_index) && mapping.to_stream == Some(stream_index)
+    })?;
+    mapping.encoder.as_deref()
+  }
+
+  /// Compare the encoder of a newly parsed output stream with the one
+  /// requested for it, returning an `EncoderFallback` event if they differ.
+  fn detect_encoder_fallback(&self, stream: &Stream) -> Option<FfmpegEvent> {
+    let media = match &stream.type_specific_data {
+      StreamTypeSpecificData::Video(_) => 'v',
+      StreamTypeSpecificData::Audio(_) => 'a',
+      StreamTypeSpecificData::Subtitle() => 's',
+      StreamTypeSpecificData::Other() => 'd',
+    };
+    let type_index = self
+      .metadata
+      .output_streams
+      .iter()
+      .filter(|other| other.parent_index == stream.parent_index)
+      .filter(|other| other.stream_index < stream.stream_index)
+      .filter(|other| {
+        std::mem::discriminant(&other.type_specific_data)
+          == std::mem::discriminant(&stream.type_specific_data)
+      })
+      .count() as u32;
+    let requested = self
+      .output_args
+      .get(stream.parent_index as usize)?
+      .codec_for(media, stream.stream_index, type_index)?;
+
+    // The stream mapping names the encoder of a re-encoded stream before
+    // the stream is described, and the metadata echo after it
+    let actual = stream
+      .encoder
+      .as_deref()
+      .or_else(|| self.mapped_encoder(stream.parent_index, stream.stream_index))?;
+
+    if requested == "copy" || requested == actual || requested == stream.format {
+      return None;
+    }
+    Some(FfmpegEvent::EncoderFallback {
+      output_index: stream.parent_index,
+      stream_index: stream.stream_index,
+      requested: requested.to_string(),
+      actual: actual.to_string(),
+    })
+  }
+
+  /// Produce the final `Completed` event, reaping the child if it is owned.
+  fn complete(&mut self) -> Option<FfmpegEvent> {
+    if self.completed || !self.emit_completed {
+      return None;
+    }
+    self.completed = true;
+    let exit_status = self.child.take().and_then(|mut child| child.wait().ok());
+    Some(FfmpegEvent::Completed {
+      exit_status,
+      had_output: self.stdout_config.first_output.get().is_some(),
+    })
+  }
+}
+
+/// The log, progress and metadata events of an iterator split with
+/// [`FfmpegIterator::split_channels`].
+pub struct LogReceiver(Receiver<FfmpegEvent>);
+
+impl LogReceiver {
+  /// The next event if one is ready, without blocking.
+  pub fn try_recv(&self) -> Result<FfmpegEvent, TryRecvError> {
+    self.0.try_recv()
+  }
+}
+
+impl Iterator for LogReceiver {
+  type Item = FfmpegEvent;
+
+  fn next(&mut self) -> Option<FfmpegEvent> {
+    self.0.recv().ok()
+  }
+}
+
+/// The stdout events of an iterator split with
+/// [`FfmpegIterator::split_channels`].
+pub struct FrameReceiver(Receiver<FfmpegEvent>);
+
+impl FrameReceiver {
+  /// The number of events buffered before the stdout thread blocks.
+  pub const CAPACITY: usize = 2;
+
+  /// The next event if one is ready, without blocking.
+  pub fn try_recv(&self) -> Result<FfmpegEvent, TryRecvError> {
+    self.0.try_recv()
+  }
+}
+
+impl Iterator for FrameReceiver {
+  type Item = FfmpegEvent;
+
+  fn next(&mut self) -> Option<FfmpegEvent> {
+    self.0.recv().ok()
+  }
+}
+
 /// Spawn a thread to read raw output frames from ffmpeg's stdout.
 pub fn spawn_stdout_thread(
   stdout: ChildStdout,
   tx: SyncSender<FfmpegEvent>,
   output_streams: Vec<Stream>,
   outputs: Vec<FfmpegOutput>,
+) -> JoinHandle<()> {
+  spawn_stdout_thread_with_config(stdout, tx, output_streams, outputs, StdoutConfig::default())
+}
+
+pub(crate) fn spawn_stdout_thread_with_config<R: Read + Send + 'static>(
+  stdout: R,
+  tx: SyncSender<FfmpegEvent>,
+  output_streams: Vec<Stream>,
+  outputs: Vec<FfmpegOutput>,
+  config: StdoutConfig,
 ) -> JoinHandle<()> {
   std::thread::spawn(move || {
     // Filter streams which are sent to stdout
@@ -196,14 +1283,22 @@ pub fn spawn_stdout_thread(
     });
 
     // Exit early if nothing is being sent to stdout
-    if stdout_streams.clone().count() == 0 {
-      tx.send(FfmpegEvent::Error("No streams found".to_owned()))
-        .ok();
+    if stdout_streams.clone().count() == 0 && !config.quiet {
+      let discarded = !outputs.is_empty()
+        && outputs
+          .iter()
+          .all(|o| o.is_null() || config.piped_outputs.contains(&o.index));
+      let event = match config.expect_no_output || discarded {
+        true => FfmpegEvent::Done,
+        false => FfmpegEvent::Error("No streams found".to_owned()),
+      };
+      tx.send(event).ok();
       return;
     }
 
     // If the size of a frame can't be determined, it will be read in arbitrary chunks.
-    let mut chunked_mode = false;
+    // Without any output descriptions (`quiet`), it never can be.
+    let mut chunked_mode = config.quiet;
 
     // Immediately default to chunked mode for non-video streams
     let stdout_video_streams = stdout_streams.clone().filter(|stream| stream.is_video());
@@ -268,18 +1363,43 @@ pub fn spawn_stdout_thread(
       chunked_mode = true;
     }
 
+    // A single raw PCM stream can be decoded into samples instead of chunks
+    let mut decoder = match stdout_streams.clone().collect::<Vec<_>>()[..] {
+      [stream] if config.audio_samples => AudioSampleDecoder::for_stream(stream),
+      _ => None,
+    };
+
+    let mut meter = ThroughputMeter::new(&config);
     let mut reader = BufReader::new(stdout);
     if chunked_mode {
-      // Arbitrary default buffer size for receiving indeterminate chunks
-      // of any encoder or container output, when frame boundaries are unknown
-      let mut chunk_buffer = vec![0u8; 65_536];
       loop {
+        let mut chunk_buffer = config.pool.take(config.chunk_size);
         match reader.read(chunk_buffer.as_mut_slice()) {
-          Ok(0) => break,
+          Ok(0) => {
+            config.pool.recycle(chunk_buffer);
+            break;
+          }
           Ok(bytes_read) => {
-            let mut data = vec![0; bytes_read];
-            data.clone_from_slice(&chunk_buffer[..bytes_read]);
-            tx.send(FfmpegEvent::OutputChunk(data)).ok()
+            config.record_read();
+            let chunk_buffer = config.pool.fit(chunk_buffer, bytes_read);
+            let throughput = meter.record(bytes_read);
+            let output = match decoder.as_mut() {
+              Some(decoder) => {
+                let samples = decoder.push(&chunk_buffer);
+                config.pool.recycle(chunk_buffer);
+                samples.map(FfmpegEvent::OutputAudioSamples)
+              }
+              None => Some(FfmpegEvent::OutputChunk(chunk_buffer)),
+            };
+            let sent = throughput
+              .into_iter()
+              .chain(output)
+              .all(|event| tx.send(event).is_ok());
+            if !sent {
+              config.consumer_closed.store(true, Ordering::Relaxed);
+              return;
+            }
+            Some(())
           }
           Err(e) => match e.kind() {
             ErrorKind::UnexpectedEof => break,
@@ -302,36 +1422,18 @@ pub fn spawn_stdout_thread(
         return;
       }
 
-      // Read into buffers
-      let num_frame_buffers = frame_buffers.len();
-      let mut frame_buffer_index = (0..frame_buffers.len()).cycle();
-      let mut frame_num = 0;
-      loop {
-        let i = frame_buffer_index.next().unwrap();
-        let video_stream = &output_streams[i];
-        let video_data = video_stream.video_data().unwrap();
-        let buffer = &mut frame_buffers[i];
-        let output_frame_num = frame_num / num_frame_buffers;
-        let timestamp = output_frame_num as f32 / video_data.fps;
-        frame_num += 1;
-
-        match reader.read_exact(buffer.as_mut_slice()) {
-          Ok(_) => tx
-            .send(FfmpegEvent::OutputFrame(OutputVideoFrame {
-              width: video_data.width,
-              height: video_data.height,
-              pix_fmt: video_data.pix_fmt.clone(),
-              output_index: i as u32,
-              data: buffer.clone(),
-              frame_num: output_frame_num as u32,
-              timestamp,
-            }))
-            .ok(),
-          Err(e) => match e.kind() {
-            ErrorKind::UnexpectedEof => break,
-            e => tx.send(FfmpegEvent::Error(e.to_string())).ok(),
-          },
-        };
+      let complete = read_frames(
+        &mut reader,
+        &output_streams,
+        &mut frame_buffers,
+        &config,
+        &mut meter,
+        |event| tx.send(event).is_ok(),
+      );
+      // Stop reading, so that FFmpeg sees a broken pipe and exits
+      if !complete {
+        config.consumer_closed.store(true, Ordering::Relaxed);
+        return;
       }
     }
 
@@ -339,25 +1441,251 @@ pub fn spawn_stdout_thread(
   })
 }
 
+/// Read whole frames into `frame_buffers` until EOF, cycling between the
+/// interleaved `output_streams` they belong to, and emit each one as an
+/// `OutputFrame` event. Stops early, returning `false`, once `emit` returns
+/// `false` because nothing receives the events.
+pub(crate) fn read_frames<R: Read, F: FnMut(FfmpegEvent) -> bool>(
+  reader: &mut R,
+  output_streams: &[Stream],
+  frame_buffers: &mut [Vec<u8>],
+  config: &StdoutConfig,
+  meter: &mut ThroughputMeter,
+  mut emit: F,
+) -> bool {
+  let num_frame_buffers = frame_buffers.len();
+  let mut frame_buffer_index = (0..frame_buffers.len()).cycle();
+  let mut frame_num = 0;
+  loop {
+    let i = frame_buffer_index.next().unwrap();
+    let video_stream = &output_streams[i];
+    let video_data = video_stream.video_data().unwrap();
+    let buffer = &mut frame_buffers[i];
+    let output_frame_num = frame_num / num_frame_buffers;
+    let timestamp = output_frame_num as f32 / video_data.fps;
+    frame_num += 1;
+
+    match reader.read_exact(buffer.as_mut_slice()) {
+      Ok(_) => {
+        config.record_read();
+        if let Some(throughput) = meter.record(buffer.len()) {
+          if !emit(throughput) {
+            return false;
+          }
+        }
+        let frame = FfmpegEvent::OutputFrame(OutputVideoFrame {
+          width: video_data.width,
+          height: video_data.height,
+          pix_fmt: video_data.pix_fmt.clone(),
+          output_index: i as u32,
+          data: buffer.clone(),
+          frame_num: output_frame_num as u32,
+          timestamp,
+          output_tag: config.output_tags.get(&video_stream.parent_index).cloned(),
+        });
+        if !emit(frame) {
+          return false;
+        }
+      }
+      Err(e) => match e.kind() {
+        ErrorKind::UnexpectedEof => return true,
+        e => {
+          if !emit(FfmpegEvent::Error(e.to_string())) {
+            return false;
+          }
+        }
+      },
+    };
+  }
+}
+
 /// Spawn a thread which reads and parses lines from ffmpeg's stderr channel.
 /// The cadence is controlled by the synchronous `tx` channel, which blocks
 /// until a receiver is ready to receive the next event.
 pub fn spawn_stderr_thread(stderr: ChildStderr, tx: SyncSender<FfmpegEvent>) -> JoinHandle<()> {
+  spawn_stderr_thread_with_config(stderr, tx, StderrConfig::default())
+}
+
+/// Options for the stderr thread.
+#[derive(Clone, Default)]
+pub(crate) struct StderrConfig {
+  /// See `FfmpegIterator::on_event`.
+  pub(crate) hooks: EventHooks,
+  /// See `FfmpegIterator::log_stats`.
+  pub(crate) stats: Arc<Mutex<LogStats>>,
+  /// See `FfmpegCommand::extract`.
+  pub(crate) extractors: Vec<LogExtractor>,
+  /// See `FfmpegCommand::error_blocks`.
+  pub(crate) error_blocks: bool,
+  /// See `FfmpegCommand::raw_log_lines`.
+  pub(crate) raw_log_lines: bool,
+  /// See `FfmpegCommand::filter_complex_auto`. Held until stderr closes, so
+  /// that they outlive the child and the iterator if FFmpeg hasn't read them
+  /// yet.
+  pub(crate) filter_scripts: Vec<Arc<TempFile>>,
+  /// Once raised by the stdout thread, the `Broken pipe` errors which follow
+  /// are replaced by `FfmpegEvent::ConsumerClosed`.
+  pub(crate) consumer_closed: Arc<AtomicBool>,
+  /// Touched for every progress update, for the stall watchdog.
+  pub(crate) activity: ActivityClock,
+  /// Without progress updates, every log event touches `activity` instead.
+  pub(crate) log_is_activity: bool,
+  /// Touched for every event, for `FfmpegIterator::with_timeout`.
+  pub(crate) log_activity: ActivityClock,
+  /// See [`LogReadTimes`].
+  pub(crate) read_times: LogReadTimes,
+  /// The FFmpeg binary, whose muxer rejections are recorded for
+  /// `container_supports_with`.
+  pub(crate) ffmpeg_path: Option<OsString>,
+}
+
+/// When the stderr thread read its first event and its latest one, which is
+/// the one being received while the thread waits on the synchronous channel.
+/// Startup timings are taken from these rather than from when the events are
+/// consumed.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LogReadTimes {
+  first: Arc<OnceLock<Instant>>,
+  latest: Arc<Mutex<Option<Instant>>>,
+}
+
+impl LogReadTimes {
+  fn record(&self) {
+    let now = Instant::now();
+    self.first.get_or_init(|| now);
+    if let Ok(mut latest) = self.latest.lock() {
+      *latest = Some(now);
+    }
+  }
+
+  fn first(&self) -> Option<Instant> {
+    self.first.get().copied()
+  }
+
+  fn latest(&self) -> Option<Instant> {
+    self.latest.lock().ok().and_then(|latest| *latest)
+  }
+}
+
+/// Like [`spawn_stderr_thread`], but recording every event in the log stats,
+/// running the extractors on each log line, and passing every event through
+/// the hooks before sending it.
+fn spawn_stderr_thread_with_config<R: Read + Send + 'static>(
+  stderr: R,
+  tx: SyncSender<FfmpegEvent>,
+  config: StderrConfig,
+) -> JoinHandle<()> {
   std::thread::spawn(move || {
     let reader = BufReader::new(stderr);
     let mut parser = FfmpegLogParser::new(reader);
+    let mut error_blocks = ErrorBlockAggregator::default();
+    let mut broken_pipes = BrokenPipeFilter::default();
     loop {
-      match parser.parse_next_event() {
-        Ok(FfmpegEvent::LogEOF) => {
-          tx.send(FfmpegEvent::LogEOF).ok();
-          break;
-        }
-        Ok(event) => tx.send(event).ok(),
+      let event = match parser.parse_next_event() {
+        Ok(event) => event,
         Err(e) => {
           eprintln!("Error parsing ffmpeg output: {}", e);
           break;
         }
       };
+      config.read_times.record();
+      config.log_activity.touch();
+      if config.log_is_activity || matches!(event, FfmpegEvent::Progress(_)) {
+        config.activity.touch();
+      }
+      if let Ok(mut stats) = config.stats.lock() {
+        stats.record(&event);
+        if parser.raw_line().is_some() {
+          stats.invalid_utf8_lines += 1;
+        }
+      }
+      let event = match config.consumer_closed.load(Ordering::Relaxed) {
+        true => match broken_pipes.filter(event) {
+          Some(event) => event,
+          None => continue,
+        },
+        false => event,
+      };
+      let ended_block = match config.error_blocks {
+        true => error_blocks.push(&event),
+        false => None,
+      };
+      if let Some(block) = ended_block {
+        if run_event_hooks(&config.hooks, &block) {
+          tx.send(block).ok();
+        }
+      }
+      if let FfmpegEvent::LogEOF = event {
+        run_event_hooks(&config.hooks, &event);
+        tx.send(event).ok();
+        break;
+      }
+
+      let parsed_error = match &event {
+        FfmpegEvent::Log(LogLevel::Error | LogLevel::Fatal, line) => try_parse_error(line),
+        _ => None,
+      };
+      let extracted: Vec<FfmpegEvent> = match &event {
+        FfmpegEvent::Log(_, line)
+        | FfmpegEvent::FilterMetadata(FilterMetadata {
+          raw_log_message: line,
+          ..
+        }) => config
+          .extractors
+          .iter()
+          .filter_map(|extractor| extractor.extract(line))
+          .map(FfmpegEvent::Extracted)
+          .collect(),
+        _ => Vec::new(),
+      };
+      if let Some(FfmpegError::UnsupportedCodec {
+        codec,
+        container: Some(container),
+      }) = &parsed_error
+      {
+        if let Some(ffmpeg) = &config.ffmpeg_path {
+          record_unsupported(ffmpeg, container, codec);
+        }
+      }
+      let parsed_error = parsed_error.map(FfmpegEvent::ParsedError);
+      let raw_line = match config.raw_log_lines {
+        true => parser
+          .raw_line()
+          .map(|bytes| FfmpegEvent::RawLogLine(bytes.to_vec())),
+        false => None,
+      };
+      let events = std::iter::once(event).chain(raw_line).chain(parsed_error);
+      for event in events.chain(extracted) {
+        if run_event_hooks(&config.hooks, &event) || is_metadata_event(&event) {
+          tx.send(event).ok();
+        }
+      }
     }
+    // FFmpeg has closed stderr, so it's done reading the scripts
+    drop(config.filter_scripts);
   })
 }
+
+/// Run every hook on `event`, returning whether all of them kept it.
+fn run_event_hooks(hooks: &EventHooks, event: &FfmpegEvent) -> bool {
+  let Ok(mut hooks) = hooks.lock() else {
+    return true;
+  };
+  hooks
+    .iter_mut()
+    .fold(true, |keep, hook| hook(event) && keep)
+}
+
+/// Whether the event is used by `FfmpegMetadata`, and so can't be dropped.
+fn is_metadata_event(event: &FfmpegEvent) -> bool {
+  matches!(
+    event,
+    FfmpegEvent::ParsedStreamMapping(_)
+      | FfmpegEvent::ParsedInput(_)
+      | FfmpegEvent::ParsedOutput(_)
+      | FfmpegEvent::ParsedInputStream(_)
+      | FfmpegEvent::ParsedOutputStream(_)
+      | FfmpegEvent::ParsedOutputStreamEncoder { .. }
+      | FfmpegEvent::ParsedDuration(_)
+  )
+}
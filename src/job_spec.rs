@@ -0,0 +1,646 @@
+//! Declarative transcode job descriptions.
+//!
+//! A [`JobSpec`] describes the inputs, outputs, codecs, filters and metadata
+//! of an FFmpeg job as plain data, which can be deserialized from JSON, TOML,
+//! or any other `serde` format. This allows a service to accept jobs as
+//! configuration, while only exposing a vetted subset of FFmpeg's options
+//! instead of raw argument arrays.
+//!
+//! ```toml
+//! overwrite = true
+//!
+//! [[inputs]]
+//! path = "input.mkv"
+//! seek = "00:01:00"
+//!
+//! [[outputs]]
+//! path = "output.mp4"
+//! video_codec = "libx264"
+//! crf = 23
+//! video_filter = "scale=1280:-2"
+//! metadata = { title = "Clip" }
+//! ```
+
+use std::{
+  collections::BTreeMap,
+  path::{Component, Path},
+};
+
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+use crate::{command::FfmpegCommand, container::output_container};
+
+/// Filters which only process the frames they're given, or generate them from
+/// their options. Any other filter is rejected, since many read or write files
+/// named in their options, like `drawtext` and `psnr`, or load code, like
+/// `ladspa`, so a job could use them to reach anything the process can.
+const PURE_FILTERS: &[&str] = &[
+  // Video
+  "alphaextract",
+  "alphamerge",
+  "blackdetect",
+  "blend",
+  "boxblur",
+  "bwdif",
+  "chromakey",
+  "colorchannelmixer",
+  "colorkey",
+  "colorspace",
+  "concat",
+  "copy",
+  "crop",
+  "cropdetect",
+  "deband",
+  "deblock",
+  "drawbox",
+  "drawgrid",
+  "eq",
+  "fade",
+  "format",
+  "fps",
+  "framerate",
+  "gblur",
+  "geq",
+  "hflip",
+  "hqdn3d",
+  "hstack",
+  "hue",
+  "idet",
+  "lut",
+  "lutrgb",
+  "lutyuv",
+  "minterpolate",
+  "negate",
+  "noformat",
+  "null",
+  "overlay",
+  "pad",
+  "palettegen",
+  "paletteuse",
+  "premultiply",
+  "rotate",
+  "scale",
+  "scdet",
+  "select",
+  "setdar",
+  "setparams",
+  "setpts",
+  "setsar",
+  "settb",
+  "showinfo",
+  "signalstats",
+  "split",
+  "thumbnail",
+  "tile",
+  "tpad",
+  "transpose",
+  "trim",
+  "unsharp",
+  "vflip",
+  "vstack",
+  "xfade",
+  "xstack",
+  "yadif",
+  "zscale",
+  // Audio
+  "acompressor",
+  "acrossfade",
+  "adelay",
+  "afade",
+  "aformat",
+  "amerge",
+  "amix",
+  "anull",
+  "apad",
+  "aresample",
+  "aselect",
+  "asetpts",
+  "asettb",
+  "ashowinfo",
+  "asplit",
+  "atempo",
+  "atrim",
+  "bandpass",
+  "channelmap",
+  "channelsplit",
+  "dynaudnorm",
+  "ebur128",
+  "equalizer",
+  "highpass",
+  "join",
+  "loudnorm",
+  "lowpass",
+  "pan",
+  "showspectrum",
+  "showwaves",
+  "silencedetect",
+  "silenceremove",
+  "volume",
+  "volumedetect",
+  // Sources, e.g. of `lavfi` inputs
+  "aevalsrc",
+  "anoisesrc",
+  "anullsrc",
+  "color",
+  "nullsrc",
+  "rgbtestsrc",
+  "sine",
+  "smptebars",
+  "smptehdbars",
+  "testsrc",
+  "testsrc2",
+];
+
+/// Demuxers which jobs run with [`FfmpegCommand::from_job_spec_in`] may read
+/// inputs with. Demuxers like `concat` and `hls` open further files named in
+/// their input, so they're excluded.
+const SANDBOXED_INPUT_FORMATS: &[&str] = &[
+  "aac", "avi", "flac", "flv", "lavfi", "m4a", "matroska", "mov", "mp3", "mp4", "mpegts", "ogg",
+  "wav", "webm",
+];
+
+/// Muxers which jobs run with [`FfmpegCommand::from_job_spec_in`] may write
+/// outputs with. Muxers like `tee`, `segment` and `hls` write further files
+/// named in their output or options, so they're excluded.
+const SANDBOXED_OUTPUT_FORMATS: &[&str] = &[
+  "3gp", "adts", "avi", "flac", "flv", "ipod", "matroska", "mov", "mp3", "mp4", "mpegts", "ogg",
+  "wav", "webm",
+];
+
+/// A complete FFmpeg job. See the [module documentation](self) for an example.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JobSpec {
+  /// Overwrite existing output files (`-y`). Otherwise, the job fails if an
+  /// output already exists.
+  pub overwrite: bool,
+  pub inputs: Vec<InputSpec>,
+  /// A complex filtergraph (`-filter_complex`) connecting the inputs to
+  /// labelled outputs, which can then be selected with [`OutputSpec::map`].
+  pub filter_complex: Option<String>,
+  pub outputs: Vec<OutputSpec>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InputSpec {
+  pub path: String,
+  /// Force the input format (`-f`), e.g. `lavfi`.
+  pub format: Option<String>,
+  /// Seek position (`-ss`), as an FFmpeg time duration.
+  pub seek: Option<String>,
+  /// Limit the duration read from the input (`-t`).
+  pub duration: Option<String>,
+  /// Number of additional times to loop the input (`-stream_loop`), or -1 to
+  /// loop forever.
+  pub stream_loop: Option<i32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputSpec {
+  pub path: String,
+  /// Force the output format (`-f`), e.g. `mp4` or `mpegts`.
+  pub format: Option<String>,
+  /// Streams to include (`-map`), e.g. `0:v` or a `filter_complex` label.
+  pub map: Vec<String>,
+  pub video_codec: Option<String>,
+  pub audio_codec: Option<String>,
+  pub video_filter: Option<String>,
+  pub audio_filter: Option<String>,
+  pub crf: Option<u32>,
+  pub preset: Option<String>,
+  pub pix_fmt: Option<String>,
+  /// Output frame size as `[width, height]`.
+  pub size: Option<[u32; 2]>,
+  pub rate: Option<f32>,
+  /// Limit the duration of the output (`-t`).
+  pub duration: Option<String>,
+  pub no_video: bool,
+  pub no_audio: bool,
+  /// Container metadata tags (`-metadata key=value`).
+  pub metadata: BTreeMap<String, String>,
+}
+
+impl JobSpec {
+  /// Check that the job has at least one input and output, that every path
+  /// is non-empty and can't be mistaken for an option (`-` alone is stdin or
+  /// stdout), and that every filtergraph only uses filters which can't touch
+  /// files or load code, e.g. not `movie` or `ladspa`.
+  pub fn validate(&self) -> anyhow::Result<()> {
+    if self.inputs.is_empty() {
+      bail!("Job spec has no inputs");
+    }
+    if self.outputs.is_empty() {
+      bail!("Job spec has no outputs");
+    }
+    if let Some(index) = self.inputs.iter().position(|i| i.path.is_empty()) {
+      bail!("Job spec input #{index} has an empty path");
+    }
+    if let Some(index) = self.outputs.iter().position(|o| o.path.is_empty()) {
+      bail!("Job spec output #{index} has an empty path");
+    }
+    for (kind, path) in self.paths() {
+      if path.starts_with('-') && path != "-" {
+        bail!("Job spec {kind} `{path}` looks like an option");
+      }
+    }
+
+    let lavfi_inputs = (self.inputs.iter())
+      .filter(|i| i.format.as_deref() == Some("lavfi"))
+      .map(|i| ("lavfi input", &i.path));
+    let filtergraphs = (self.filter_complex.iter().map(|f| ("filter_complex", f)))
+      .chain(lavfi_inputs)
+      .chain(self.outputs.iter().flat_map(|o| {
+        (o.video_filter.iter().map(|f| ("video filter", f)))
+          .chain(o.audio_filter.iter().map(|f| ("audio filter", f)))
+      }));
+    for (kind, filtergraph) in filtergraphs {
+      if let Some(filter) = filter_names(filtergraph).find(|f| !PURE_FILTERS.contains(f)) {
+        bail!("Job spec {kind} uses the `{filter}` filter, which isn't allowed");
+      }
+    }
+    Ok(())
+  }
+
+  /// Check that every local file of the job is a relative path which stays
+  /// within the working directory, i.e. has no `..` components, for jobs run
+  /// with [`FfmpegCommand::from_job_spec_in`]. No protocol is allowed, e.g.
+  /// `concat:`, `pipe:` or `http://`, and neither is `-` for stdin or stdout.
+  ///
+  /// Only formats which can't open other files are allowed, e.g. not `tee` or
+  /// `hls`: an input's `format` must be one of a fixed set of demuxers, and an
+  /// output's `format`, or the muxer chosen by its extension, one of a fixed
+  /// set of muxers. Inputs without a `format` are only probed as one of the
+  /// same demuxers.
+  pub fn validate_relative(&self) -> anyhow::Result<()> {
+    self.validate_relative_allowing(&[])
+  }
+
+  /// Like [`validate_relative`](Self::validate_relative), but also allowing
+  /// network URLs such as `rtmp://...` whose protocol is one of `protocols`,
+  /// e.g. `["rtmp", "srt"]`. Only allow this for jobs which may make requests
+  /// to any host.
+  pub fn validate_relative_allowing(&self, protocols: &[&str]) -> anyhow::Result<()> {
+    for (kind, path) in self.paths() {
+      if path.starts_with('-') {
+        bail!("Job spec {kind} `{path}` looks like an option");
+      }
+      if path.contains('|') {
+        bail!("Job spec {kind} `{path}` contains a `|`");
+      }
+      if let Some((protocol, rest)) = split_protocol(path) {
+        let protocol = protocol.to_ascii_lowercase();
+        if protocols.contains(&protocol.as_str()) && rest.starts_with("//") {
+          continue;
+        }
+        bail!("Job spec {kind} `{path}` uses the `{protocol}:` protocol");
+      }
+      let escapes = Path::new(path)
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir));
+      if escapes {
+        bail!("Job spec {kind} `{path}` is outside the working directory");
+      }
+    }
+
+    for input in &self.inputs {
+      if let Some(format) = input.format.as_deref() {
+        if !SANDBOXED_INPUT_FORMATS.contains(&format) {
+          bail!("Job spec input `{}` uses the `{format}` format", input.path);
+        }
+      }
+    }
+    for output in &self.outputs {
+      let muxer = output_container(&output.path, output.format.as_deref());
+      match muxer {
+        Some(muxer) if SANDBOXED_OUTPUT_FORMATS.contains(&muxer.as_str()) => {}
+        Some(muxer) => bail!(
+          "Job spec output `{}` uses the `{muxer}` format",
+          output.path
+        ),
+        None => bail!(
+          "Job spec output `{}` has no format or extension",
+          output.path
+        ),
+      }
+    }
+    Ok(())
+  }
+
+  /// Like [`apply`](Self::apply), but restricting inputs without a `format`
+  /// to the demuxers allowed by [`validate_relative`](Self::validate_relative),
+  /// which the caller checks first.
+  pub(crate) fn apply_sandboxed(&self, command: &mut FfmpegCommand) -> anyhow::Result<()> {
+    self.apply_with(command, true)
+  }
+
+  /// The path of every input and output, with which of them it is.
+  fn paths(&self) -> impl Iterator<Item = (&'static str, &String)> {
+    (self.inputs.iter().map(|i| ("input", &i.path)))
+      .chain(self.outputs.iter().map(|o| ("output", &o.path)))
+  }
+
+  /// Append the job's arguments to an existing command.
+  pub fn apply(&self, command: &mut FfmpegCommand) -> anyhow::Result<()> {
+    self.apply_with(command, false)
+  }
+
+  fn apply_with(&self, command: &mut FfmpegCommand, sandboxed: bool) -> anyhow::Result<()> {
+    self.validate()?;
+
+    match self.overwrite {
+      true => command.overwrite(),
+      false => command.no_overwrite(),
+    };
+
+    for input in &self.inputs {
+      if let Some(format) = &input.format {
+        command.format(format);
+      }
+      if let Some(seek) = &input.seek {
+        command.seek(seek);
+      }
+      if let Some(duration) = &input.duration {
+        command.duration(duration);
+      }
+      if let Some(count) = input.stream_loop {
+        command.stream_loop(count);
+      }
+      if sandboxed && input.format.is_none() {
+        command.args(["-format_whitelist", &SANDBOXED_INPUT_FORMATS.join(",")]);
+      }
+      command.input(&input.path);
+    }
+
+    if let Some(filtergraph) = &self.filter_complex {
+      command.filter_complex(filtergraph);
+    }
+
+    for output in &self.outputs {
+      for map in &output.map {
+        command.map(map);
+      }
+      if let Some(codec) = &output.video_codec {
+        command.codec_video(codec);
+      }
+      if let Some(codec) = &output.audio_codec {
+        command.codec_audio(codec);
+      }
+      if let Some(filter) = &output.video_filter {
+        command.args(["-filter:v", filter]);
+      }
+      if let Some(filter) = &output.audio_filter {
+        command.args(["-filter:a", filter]);
+      }
+      if let Some(crf) = output.crf {
+        command.crf(crf);
+      }
+      if let Some(preset) = &output.preset {
+        command.preset(preset);
+      }
+      if let Some(pix_fmt) = &output.pix_fmt {
+        command.pix_fmt(pix_fmt);
+      }
+      if let Some([width, height]) = output.size {
+        command.size(width, height);
+      }
+      if let Some(rate) = output.rate {
+        command.rate(rate);
+      }
+      if let Some(duration) = &output.duration {
+        command.duration(duration);
+      }
+      if output.no_video {
+        command.no_video();
+      }
+      if output.no_audio {
+        command.no_audio();
+      }
+      for (key, value) in &output.metadata {
+        command.args(["-metadata", &format!("{key}={value}")]);
+      }
+      if let Some(format) = &output.format {
+        command.format(format);
+      }
+      command.output(&output.path);
+    }
+
+    Ok(())
+  }
+}
+
+/// The protocol of a URL like `concat:a.ts|b.ts`, and the rest of it. A
+/// single letter is a Windows drive instead.
+fn split_protocol(url: &str) -> Option<(&str, &str)> {
+  let (protocol, rest) = url.split_once(':')?;
+  let is_protocol = !protocol.is_empty()
+    && protocol
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || "+-.,".contains(c));
+  let is_drive = cfg!(windows) && protocol.len() == 1;
+  (is_protocol && !is_drive).then_some((protocol, rest))
+}
+
+/// The name of every filter in a filtergraph, without its input and output
+/// labels or instance name. Splits on every separator, including escaped or
+/// quoted ones, so options may show up as extra names, which errs on the
+/// side of rejecting a graph.
+fn filter_names(filtergraph: &str) -> impl Iterator<Item = &str> {
+  filtergraph.split([';', ',']).map(|filter| {
+    let mut filter = filter.trim_start();
+    while let Some(rest) = filter.strip_prefix('[') {
+      filter = rest
+        .split_once(']')
+        .map_or("", |(_, rest)| rest)
+        .trim_start();
+    }
+    let end = filter.find(['=', '@', '[']).unwrap_or(filter.len());
+    filter[..end].trim()
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_job_spec_from_json() {
+    let spec: JobSpec = serde_json::from_str(
+      r#"{
+        "overwrite": true,
+        "inputs": [{ "path": "input.mkv", "seek": "60" }],
+        "outputs": [{
+          "path": "output.mp4",
+          "video_codec": "libx264",
+          "crf": 23,
+          "size": [1280, 720],
+          "metadata": { "title": "Clip" }
+        }]
+      }"#,
+    )
+    .unwrap();
+
+    let command = FfmpegCommand::from_job_spec(&spec).unwrap();
+    let model = command.get_arg_model();
+    assert_eq!(model.inputs[0].url, "input.mkv");
+    assert_eq!(model.inputs[0].option("-ss"), Some("60"));
+    assert_eq!(model.outputs[0].url, "output.mp4");
+    assert_eq!(model.outputs[0].option("-c:v"), Some("libx264"));
+    assert_eq!(model.outputs[0].option("-s"), Some("1280x720"));
+    assert_eq!(model.outputs[0].option("-metadata"), Some("title=Clip"));
+    assert!(command.lint().is_empty());
+  }
+
+  #[test]
+  fn test_job_spec_rejects_unknown_fields() {
+    let result = serde_json::from_str::<JobSpec>(r#"{ "args": ["-i", "/etc/passwd"] }"#);
+    assert!(result.is_err());
+  }
+
+  #[test]
+  fn test_job_spec_requires_output() {
+    let spec = JobSpec {
+      inputs: vec![InputSpec {
+        path: "input.mp4".into(),
+        ..Default::default()
+      }],
+      ..Default::default()
+    };
+    assert!(FfmpegCommand::from_job_spec(&spec).is_err());
+  }
+
+  #[test]
+  fn test_job_spec_in_workdir() {
+    let spec = |input: &str, output: &str| JobSpec {
+      inputs: vec![InputSpec {
+        path: input.into(),
+        ..Default::default()
+      }],
+      outputs: vec![OutputSpec {
+        path: output.into(),
+        ..Default::default()
+      }],
+      ..Default::default()
+    };
+
+    let command =
+      FfmpegCommand::from_job_spec_in(&spec("in.mov", "out/a.mp4"), "/srv/jobs/1").unwrap();
+    assert_eq!(
+      command.output_files(),
+      vec![std::path::Path::new("/srv/jobs/1/out/a.mp4")]
+    );
+    let network = spec("rtmp://live/key", "srt://127.0.0.1:9000");
+    assert!(FfmpegCommand::from_job_spec_in(&network, "/srv/jobs/1").is_err());
+    let network = JobSpec {
+      outputs: vec![OutputSpec {
+        format: Some("mpegts".into()),
+        ..network.outputs[0].clone()
+      }],
+      ..network
+    };
+    assert!(network.validate_relative_allowing(&["rtmp", "srt"]).is_ok());
+    assert!(network.validate_relative_allowing(&["rtmp"]).is_err());
+
+    for (input, output) in [
+      ("../other/in.mov", "out.mp4"),
+      ("in.mov", "/etc/out.mp4"),
+      ("in.mov", "file:../out.mp4"),
+      ("concat:/etc/passwd", "out.mp4"),
+      ("subfile:,,start,0,end,0,,:/etc/passwd", "out.mp4"),
+      ("cache:../other/in.mov", "out.mp4"),
+      ("file:/etc/passwd", "out.mp4"),
+      ("pipe:3", "out.mp4"),
+      ("in.mov", "-"),
+      ("-i", "out.mp4"),
+      ("rtmp:in.mov", "out.mp4"),
+      ("http://169.254.169.254/latest", "out.mp4"),
+      ("in.mov", "a.mp4|/etc/x"),
+      ("in.mov", "out.m3u8"),
+      ("in.mov", "out"),
+    ] {
+      assert!(
+        FfmpegCommand::from_job_spec_in(&spec(input, output), "/srv/jobs/1").is_err(),
+        "{input} -> {output}"
+      );
+    }
+
+    let formats = |input: Option<&str>, output: Option<&str>| JobSpec {
+      inputs: vec![InputSpec {
+        format: input.map(String::from),
+        ..spec("in.mov", "").inputs[0].clone()
+      }],
+      outputs: vec![OutputSpec {
+        format: output.map(String::from),
+        ..spec("", "a.mp4").outputs[0].clone()
+      }],
+      ..Default::default()
+    };
+    let command = FfmpegCommand::from_job_spec_in(&formats(None, None), "/srv/jobs/1").unwrap();
+    let model = command.get_arg_model();
+    assert!(model.inputs[0].option("-format_whitelist").is_some());
+    assert!(formats(Some("mov"), Some("matroska"))
+      .validate_relative()
+      .is_ok());
+    for (input, output) in [
+      (Some("concat"), None),
+      (Some("hls"), None),
+      (None, Some("tee")),
+      (None, Some("segment")),
+      (None, Some("hls")),
+    ] {
+      assert!(
+        formats(input, output).validate_relative().is_err(),
+        "{input:?} -> {output:?}"
+      );
+    }
+  }
+
+  #[test]
+  fn test_job_spec_rejects_injection() {
+    let spec = |input: &str, output: &str, video_filter: Option<&str>| JobSpec {
+      inputs: vec![InputSpec {
+        path: input.into(),
+        ..Default::default()
+      }],
+      outputs: vec![OutputSpec {
+        path: output.into(),
+        video_filter: video_filter.map(String::from),
+        ..Default::default()
+      }],
+      ..Default::default()
+    };
+
+    assert!(FfmpegCommand::from_job_spec(&spec("in.mov", "-", Some("scale=640:-2"))).is_ok());
+    let filter = "[in]split[a][b];[a]crop=64:64[c];[b][c]overlay@logo=10:10,fps=30";
+    assert!(FfmpegCommand::from_job_spec(&spec("in.mov", "out.mp4", Some(filter))).is_ok());
+    assert!(FfmpegCommand::from_job_spec(&spec("-filter_script", "out.mp4", None)).is_err());
+    assert!(FfmpegCommand::from_job_spec(&spec("in.mov", "-i", None)).is_err());
+    for filter in [
+      "movie=/etc/passwd[x];[in][x]overlay",
+      "scale=640:-2, [in] amovie = secret.wav",
+      "sendcmd@cmds=f=cmds.txt",
+      "drawtext=textfile=/etc/passwd",
+      "ladspa=file=/tmp/plugin.so:amp",
+      "curves=psfile=/etc/passwd",
+      "[in][ref]psnr=stats_file=/tmp/out.log",
+      "scale=640:-2,vidstabdetect=result=/tmp/out.trf",
+    ] {
+      assert!(
+        FfmpegCommand::from_job_spec(&spec("in.mov", "out.mp4", Some(filter))).is_err(),
+        "{filter}"
+      );
+    }
+
+    let lavfi = JobSpec {
+      inputs: vec![InputSpec {
+        path: "movie=/etc/passwd".into(),
+        format: Some("lavfi".into()),
+        ..Default::default()
+      }],
+      ..spec("", "out.mp4", None)
+    };
+    assert!(FfmpegCommand::from_job_spec(&lavfi).is_err());
+  }
+}
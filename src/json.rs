@@ -0,0 +1,230 @@
+//! A minimal reader for the JSON written by ffprobe's `-of json`.
+
+use std::{iter::Peekable, str::Chars, str::FromStr};
+
+/// A parsed JSON value. Numbers are kept as written, so that large integers
+/// like PIDs and timestamps don't lose precision.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Json {
+  Null,
+  Bool(bool),
+  Number(String),
+  String(String),
+  Array(Vec<Json>),
+  Object(Vec<(String, Json)>),
+}
+
+impl Json {
+  /// Parse a complete JSON document, or `None` if it's malformed.
+  pub(crate) fn parse(text: &str) -> Option<Self> {
+    let mut parser = Parser {
+      chars: text.chars().peekable(),
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    parser.chars.peek().is_none().then_some(value)
+  }
+
+  /// The member `key` of an object.
+  pub(crate) fn get(&self, key: &str) -> Option<&Json> {
+    self
+      .members()
+      .iter()
+      .find(|(member, _)| member == key)
+      .map(|(_, value)| value)
+  }
+
+  /// The members of an object, or none for any other value.
+  pub(crate) fn members(&self) -> &[(String, Json)] {
+    match self {
+      Json::Object(members) => members,
+      _ => &[],
+    }
+  }
+
+  /// The elements of an array, or none for any other value.
+  pub(crate) fn elements(&self) -> &[Json] {
+    match self {
+      Json::Array(elements) => elements,
+      _ => &[],
+    }
+  }
+
+  /// A string, or a number as written.
+  pub(crate) fn as_str(&self) -> Option<&str> {
+    match self {
+      Json::String(s) | Json::Number(s) => Some(s),
+      _ => None,
+    }
+  }
+
+  /// A number, or a string holding one, as ffprobe writes durations.
+  pub(crate) fn parse_as<T: FromStr>(&self) -> Option<T> {
+    self.as_str()?.parse().ok()
+  }
+}
+
+struct Parser<'a> {
+  chars: Peekable<Chars<'a>>,
+}
+
+impl Parser<'_> {
+  fn skip_whitespace(&mut self) {
+    while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+  }
+
+  fn value(&mut self) -> Option<Json> {
+    self.skip_whitespace();
+    match *self.chars.peek()? {
+      '{' => {
+        self.chars.next();
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.chars.next_if_eq(&'}').is_none() {
+          loop {
+            let Json::String(key) = self.value()? else {
+              return None;
+            };
+            self.skip_whitespace();
+            self.chars.next_if_eq(&':')?;
+            members.push((key, self.value()?));
+            if self.end_of_list('}')? {
+              break;
+            }
+          }
+        }
+        Some(Json::Object(members))
+      }
+      '[' => {
+        self.chars.next();
+        let mut elements = Vec::new();
+        self.skip_whitespace();
+        if self.chars.next_if_eq(&']').is_none() {
+          loop {
+            elements.push(self.value()?);
+            if self.end_of_list(']')? {
+              break;
+            }
+          }
+        }
+        Some(Json::Array(elements))
+      }
+      '"' => {
+        self.chars.next();
+        self.string().map(Json::String)
+      }
+      c if c == '-' || c.is_ascii_digit() => {
+        let mut number = String::new();
+        while let Some(c) = self
+          .chars
+          .next_if(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+        {
+          number.push(c);
+        }
+        Some(Json::Number(number))
+      }
+      _ => {
+        let mut word = String::new();
+        while let Some(c) = self.chars.next_if(char::is_ascii_alphabetic) {
+          word.push(c);
+        }
+        match word.as_str() {
+          "true" => Some(Json::Bool(true)),
+          "false" => Some(Json::Bool(false)),
+          "null" => Some(Json::Null),
+          _ => None,
+        }
+      }
+    }
+  }
+
+  /// Consume the separator after an element: `Some(false)` for a comma,
+  /// `Some(true)` for `close`.
+  fn end_of_list(&mut self, close: char) -> Option<bool> {
+    self.skip_whitespace();
+    match self.chars.next()? {
+      ',' => Some(false),
+      c if c == close => Some(true),
+      _ => None,
+    }
+  }
+
+  /// The rest of a string whose opening quote was consumed.
+  fn string(&mut self) -> Option<String> {
+    let mut string = String::new();
+    loop {
+      match self.chars.next()? {
+        '"' => return Some(string),
+        '\\' => match self.chars.next()? {
+          'b' => string.push('\u{8}'),
+          'f' => string.push('\u{c}'),
+          'n' => string.push('\n'),
+          'r' => string.push('\r'),
+          't' => string.push('\t'),
+          'u' => {
+            let mut code = self.code_unit()?;
+            // A character outside the BMP, as a surrogate pair
+            if (0xD800..0xDC00).contains(&code) && self.chars.next_if_eq(&'\\').is_some() {
+              self.chars.next_if_eq(&'u')?;
+              code = match self.code_unit()? {
+                low @ 0xDC00..=0xDFFF => 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00),
+                _ => u32::MAX,
+              };
+            }
+            string.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+          }
+          c => string.push(c),
+        },
+        c => string.push(c),
+      }
+    }
+  }
+
+  fn code_unit(&mut self) -> Option<u32> {
+    let code: String = self.chars.by_ref().take(4).collect();
+    u32::from_str_radix(&code, 16).ok()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_json() {
+    let json = Json::parse(
+      r#"{
+        "streams": [{ "index": 0, "r_frame_rate": "30000/1001", "id": "0x100" }],
+        "format": { "duration": "12.500000", "tags": { "title": "Café \"live\" 🎥" } },
+        "empty": [], "none": {}, "flags": [true, false, null], "big": 18446744073709551615
+      }"#,
+    )
+    .unwrap();
+    let stream = &json.get("streams").unwrap().elements()[0];
+    assert_eq!(stream.get("index").and_then(Json::parse_as), Some(0u32));
+    assert_eq!(stream.get("id").and_then(Json::as_str), Some("0x100"));
+    let format = json.get("format").unwrap();
+    assert_eq!(
+      format.get("duration").and_then(Json::parse_as),
+      Some(12.5f64)
+    );
+    let title = format.get("tags").and_then(|tags| tags.get("title"));
+    assert_eq!(title.and_then(Json::as_str), Some("Café \"live\" 🎥"));
+    assert!(json.get("empty").unwrap().elements().is_empty());
+    assert!(json.get("none").unwrap().members().is_empty());
+    assert_eq!(
+      json.get("flags").unwrap().elements(),
+      [Json::Bool(true), Json::Bool(false), Json::Null]
+    );
+    assert_eq!(json.get("big").and_then(Json::parse_as), Some(u64::MAX));
+
+    assert_eq!(
+      Json::parse(r#""\u00e9\ud83c\udfa5\n""#),
+      Some(Json::String("é🎥\n".to_string()))
+    );
+
+    assert_eq!(Json::parse(r#"{"a": 1,}"#), None);
+    assert_eq!(Json::parse(r#"{"a": 1} trailing"#), None);
+    assert_eq!(Json::parse(r#"["unterminated"#), None);
+  }
+}
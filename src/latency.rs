@@ -0,0 +1,232 @@
+//! End-to-end latency measurement for FFmpeg pipelines.
+//!
+//! Raw frames are generated in Rust with a sequence number encoded into their
+//! pixels, piped through a user-configured FFmpeg command, and decoded again
+//! from the output frames. Comparing the time each frame was written to stdin
+//! with the time it was received on stdout gives the per-frame "glass to
+//! glass" latency of the pipeline.
+
+use std::{
+  collections::HashMap,
+  io::Write,
+  sync::{Arc, Mutex},
+  thread,
+  time::{Duration, Instant},
+};
+
+use crate::{command::FfmpegCommand, event::FfmpegEvent};
+
+/// Number of bits of the frame sequence number encoded into each frame.
+const SEQUENCE_BITS: u32 = 32;
+
+/// Encode `sequence` into the top band of an `rgb24` frame as a row of black
+/// and white blocks. The block layout is relative to the frame dimensions, so
+/// it survives scaling as well as moderate compression.
+pub fn encode_sequence(frame: &mut [u8], width: u32, height: u32, sequence: u32) {
+  let (block_width, block_height) = block_size(width, height);
+  for bit in 0..SEQUENCE_BITS {
+    let value = match (sequence >> (SEQUENCE_BITS - 1 - bit)) & 1 {
+      1 => 255,
+      _ => 0,
+    };
+    for y in 0..block_height {
+      for x in bit * block_width..(bit + 1) * block_width {
+        let offset = ((y * width + x) * 3) as usize;
+        if let Some(pixel) = frame.get_mut(offset..offset + 3) {
+          pixel.fill(value);
+        }
+      }
+    }
+  }
+}
+
+/// Decode a sequence number written by [`encode_sequence`] from an `rgb24`
+/// frame, by sampling the center of each block.
+///
+/// ```rust
+/// use ffmpeg_sidecar::latency::{decode_sequence, encode_sequence};
+///
+/// let (width, height) = (320, 240);
+/// let mut frame = vec![128u8; (width * height * 3) as usize];
+/// encode_sequence(&mut frame, width, height, 0xdead_beef);
+/// assert_eq!(decode_sequence(&frame, width, height), Some(0xdead_beef));
+/// ```
+pub fn decode_sequence(frame: &[u8], width: u32, height: u32) -> Option<u32> {
+  let (block_width, block_height) = block_size(width, height);
+  let y = block_height / 2;
+  (0..SEQUENCE_BITS).try_fold(0u32, |sequence, bit| {
+    let x = bit * block_width + block_width / 2;
+    let offset = ((y * width + x) * 3) as usize;
+    let pixel = frame.get(offset..offset + 3)?;
+    let luma = pixel.iter().map(|&c| c as u32).sum::<u32>() / 3;
+    Some((sequence << 1) | (luma >= 128) as u32)
+  })
+}
+
+fn block_size(width: u32, height: u32) -> (u32, u32) {
+  ((width / SEQUENCE_BITS).max(1), (height / 16).max(1))
+}
+
+/// Configuration for a latency measurement run. The generated input is raw
+/// `rgb24` video of the given size and frame rate, written to stdin in real
+/// time.
+#[derive(Debug, Clone)]
+pub struct LatencyTest {
+  pub width: u32,
+  pub height: u32,
+  pub fps: f32,
+  /// The number of frames to send.
+  pub frames: u32,
+}
+
+impl Default for LatencyTest {
+  fn default() -> Self {
+    Self {
+      width: 640,
+      height: 360,
+      fps: 30.0,
+      frames: 150,
+    }
+  }
+}
+
+impl LatencyTest {
+  /// Run the measurement. `configure` receives the command after its stdin
+  /// input has been added, and before the `rawvideo` stdout output is
+  /// appended; it can add any output options, filters, or encoder settings
+  /// to be measured (e.g. a `-vf` chain, or `-flags low_delay`).
+  ///
+  /// The pipeline must preserve the top band of the frame well enough for the
+  /// encoded sequence number to be read back; filters which crop, flip, or
+  /// overlay that area will result in undecodable frames, which are counted
+  /// in [`LatencyReport::undecodable`].
+  ///
+  /// ## Example
+  ///
+  /// ```rust,no_run
+  /// use ffmpeg_sidecar::latency::LatencyTest;
+  ///
+  /// let report = LatencyTest::default().run(|command| {
+  ///   command.args(["-vf", "scale=320:180,scale=640:360"]);
+  /// })?;
+  /// println!("p50: {:?}", report.percentile(50.0));
+  /// # anyhow::Ok(())
+  /// ```
+  pub fn run<F: FnOnce(&mut FfmpegCommand)>(&self, configure: F) -> anyhow::Result<LatencyReport> {
+    let mut command = FfmpegCommand::new();
+    command
+      .hide_banner()
+      .format("rawvideo")
+      .pix_fmt("rgb24")
+      .size(self.width, self.height)
+      .rate(self.fps)
+      .input("-");
+    configure(&mut command);
+    command.rawvideo();
+
+    let mut child = command.spawn()?;
+    let mut stdin = child
+      .take_stdin()
+      .ok_or_else(|| anyhow::anyhow!("Missing child stdin"))?;
+
+    let sent_at: Arc<Mutex<HashMap<u32, Instant>>> = Default::default();
+    let writer_sent_at = sent_at.clone();
+    let (width, height, frames) = (self.width, self.height, self.frames);
+    let frame_interval = Duration::from_secs_f32(1.0 / self.fps);
+    let writer = thread::spawn(move || -> anyhow::Result<()> {
+      let mut frame = vec![96u8; (width * height * 3) as usize];
+      let start = Instant::now();
+      for sequence in 0..frames {
+        // Pace frames in real time, like a live source
+        let due = start + frame_interval * sequence;
+        if let Some(wait) = due.checked_duration_since(Instant::now()) {
+          thread::sleep(wait);
+        }
+        encode_sequence(&mut frame, width, height, sequence);
+        writer_sent_at
+          .lock()
+          .map_err(|_| anyhow::anyhow!("poisoned lock"))?
+          .insert(sequence, Instant::now());
+        stdin.write_all(&frame)?;
+        stdin.flush()?;
+      }
+      Ok(())
+      // stdin is dropped here, signalling EOF to FFmpeg
+    });
+
+    let mut report = LatencyReport {
+      frames_sent: self.frames,
+      ..Default::default()
+    };
+    for event in child.iter()? {
+      if let FfmpegEvent::OutputFrame(frame) = event {
+        let received_at = Instant::now();
+        let sent = decode_sequence(&frame.data, frame.width, frame.height)
+          .and_then(|sequence| sent_at.lock().ok()?.remove(&sequence));
+        match sent {
+          Some(sent) => report.samples.push(received_at.duration_since(sent)),
+          None => report.undecodable += 1,
+        }
+      }
+    }
+
+    writer
+      .join()
+      .map_err(|_| anyhow::anyhow!("Frame writer thread panicked"))??;
+    child.wait()?;
+    Ok(report)
+  }
+}
+
+/// Per-frame latency samples collected by [`LatencyTest::run`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LatencyReport {
+  /// Latency of each received frame, in output order.
+  pub samples: Vec<Duration>,
+  /// The number of frames written to FFmpeg.
+  pub frames_sent: u32,
+  /// Output frames whose sequence number couldn't be decoded (or was
+  /// duplicated by the pipeline).
+  pub undecodable: usize,
+}
+
+impl LatencyReport {
+  pub fn min(&self) -> Option<Duration> {
+    self.samples.iter().min().copied()
+  }
+
+  pub fn max(&self) -> Option<Duration> {
+    self.samples.iter().max().copied()
+  }
+
+  pub fn mean(&self) -> Option<Duration> {
+    let total: Duration = self.samples.iter().sum();
+    (!self.samples.is_empty()).then(|| total / self.samples.len() as u32)
+  }
+
+  /// The latency below which `percent` (0-100) of the samples fall, using the
+  /// nearest-rank method.
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::latency::LatencyReport;
+  /// use std::time::Duration;
+  ///
+  /// let report = LatencyReport {
+  ///   samples: (1..=10).map(Duration::from_millis).collect(),
+  ///   ..Default::default()
+  /// };
+  /// assert_eq!(report.percentile(50.0), Some(Duration::from_millis(5)));
+  /// assert_eq!(report.percentile(100.0), Some(Duration::from_millis(10)));
+  /// ```
+  pub fn percentile(&self, percent: f64) -> Option<Duration> {
+    let mut sorted = self.samples.clone();
+    sorted.sort();
+    let rank = ((percent.clamp(0.0, 100.0) / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted.get(rank.max(1) - 1).copied()
+  }
+
+  /// Frames which were sent but never received.
+  pub fn dropped(&self) -> usize {
+    (self.frames_sent as usize).saturating_sub(self.samples.len())
+  }
+}
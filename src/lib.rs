@@ -24,25 +24,76 @@
 //! }
 //! ```
 
+mod json;
+mod output_pipe;
+mod progress_listener;
+mod temp_file;
 #[cfg(test)]
 mod test;
+mod watchdog;
 
+pub mod analysis;
+pub mod args;
+pub mod audio_filter;
+pub mod audio_levels;
+pub mod av_sync;
+pub mod backend;
+pub mod bitstream;
 pub mod child;
 pub mod comma_iter;
 pub mod command;
+pub mod container;
+pub mod cutlist;
+pub mod doctor;
 pub mod download;
 pub mod event;
+pub mod extract;
+pub mod ffmetadata;
 pub mod ffprobe;
+pub mod filter_output;
+pub mod frame_grabber;
+pub mod frame_pump;
+pub mod gpu;
+pub mod hw_transcode;
+pub mod input_sync;
 pub mod iter;
+pub mod latency;
+pub mod lint;
 pub mod log_parser;
+pub mod map;
 pub mod metadata;
+pub mod pan;
 pub mod paths;
 pub mod pix_fmt;
+pub mod proxy;
 pub mod read_until_any;
+pub mod recorder;
+pub mod resource_usage;
+pub mod scaled_outputs;
+pub mod stderr_recorder;
+pub mod subtitles;
+pub mod tcp_output;
+pub mod tee;
+pub mod template;
+pub mod time_range;
+pub mod transcribe;
 pub mod version;
 
+#[cfg(feature = "bench-internals")]
+#[doc(hidden)]
+pub mod bench_internals;
+#[cfg(feature = "serde")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+pub mod job_spec;
 #[cfg(feature = "named_pipes")]
 #[cfg_attr(docsrs, doc(cfg(feature = "named_pipes")))]
 pub mod named_pipes;
+#[cfg(unix)]
+#[cfg_attr(docsrs, doc(cfg(unix)))]
+pub mod resource_limits;
+#[cfg(target_os = "linux")]
+#[cfg_attr(docsrs, doc(cfg(target_os = "linux")))]
+pub mod shm_transport;
 
 pub use anyhow::Result;
+pub use doctor::doctor;
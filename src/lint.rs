@@ -0,0 +1,341 @@
+//! Detect common argument ordering mistakes before spawning FFmpeg.
+//!
+//! FFmpeg options apply to the next input or output file on the command line,
+//! so an option in the wrong place is often silently applied to a different
+//! file than intended (or rejected with an error which doesn't point back to
+//! the offending argument). See [`FfmpegCommand::lint`](crate::command::FfmpegCommand::lint).
+
+use std::{ffi::OsStr, fmt};
+
+use crate::{
+  args::ArgModel,
+  container::{container_supports, container_supports_with, output_container},
+};
+
+/// Options which are only meaningful before an `-i`.
+const INPUT_ONLY_OPTIONS: &[&str] = &[
+  "-itsoffset",
+  "-itsscale",
+  "-stream_loop",
+  "-sseof",
+  "-re",
+  "-readrate",
+  "-probesize",
+  "-analyzeduration",
+];
+
+/// Options which are only meaningful before an output.
+const OUTPUT_ONLY_OPTIONS: &[&str] = &[
+  "-shortest",
+  "-map",
+  "-map_metadata",
+  "-map_chapters",
+  "-metadata",
+  "-vf",
+  "-af",
+  "-filter:v",
+  "-filter:a",
+  "-b:v",
+  "-b:a",
+  "-crf",
+  "-preset",
+  "-movflags",
+  "-fs",
+  "-vframes",
+  "-frames:v",
+];
+
+/// Options which each set the filter chain of a media type; FFmpeg only uses
+/// the last one given for an output.
+const FILTER_OPTIONS: &[&[&str]] = &[&["-vf", "-filter:v"], &["-af", "-filter:a"]];
+
+const CODEC_OPTIONS: &[&str] = &[
+  "-c", "-codec", "-c:v", "-codec:v", "-vcodec", "-c:a", "-codec:a", "-acodec",
+];
+
+/// A likely mistake in the ordering of a command's arguments. `position` is
+/// the index of the offending argument in the argument list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LintWarning {
+  /// An output option appears before an `-i`, where FFmpeg will treat it as
+  /// an option for that input.
+  OutputOptionBeforeInput { arg: String, position: usize },
+  /// An input option appears after the last `-i`, where FFmpeg will apply it
+  /// to the output (or reject it).
+  InputOptionAfterLastInput { arg: String, position: usize },
+  /// `-ss` is used as an output option together with stream copy. This
+  /// discards packets up to the seek position without regard to keyframes,
+  /// while placing `-ss` before `-i` would give a fast keyframe-aligned seek.
+  OutputSeekWithStreamCopy { position: usize },
+  /// A filter chain option is replaced by a later one for the same output,
+  /// e.g. `roi` after `deinterlace`, since FFmpeg only uses the last. The
+  /// filters should be joined into one chain instead.
+  OverriddenFilter { arg: String, position: usize },
+  /// A codec option selects a codec which the container of its output, from
+  /// its `-f` format or file extension, can't store, e.g. `pcm_s16le` in
+  /// MP4. FFmpeg only reports this once every input and encoder is open. See
+  /// [`crate::container`].
+  UnsupportedCodec {
+    arg: String,
+    codec: String,
+    container: String,
+    position: usize,
+  },
+}
+
+impl fmt::Display for LintWarning {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      LintWarning::OutputOptionBeforeInput { arg, position } => write!(
+        f,
+        "`{arg}` (argument {position}) is an output option, but appears before any input"
+      ),
+      LintWarning::InputOptionAfterLastInput { arg, position } => write!(
+        f,
+        "`{arg}` (argument {position}) is an input option, but appears after the last input"
+      ),
+      LintWarning::OutputSeekWithStreamCopy { position } => write!(
+        f,
+        "`-ss` (argument {position}) is an output option with stream copy; place it before `-i` to seek the input instead"
+      ),
+      LintWarning::OverriddenFilter { arg, position } => write!(
+        f,
+        "`{arg}` (argument {position}) is replaced by a later filter option for the same output; join the filters into one chain"
+      ),
+      LintWarning::UnsupportedCodec {
+        arg,
+        codec,
+        container,
+        position,
+      } => write!(
+        f,
+        "`{arg} {codec}` (argument {position}) selects a codec which the {container} container can't store"
+      ),
+    }
+  }
+}
+
+/// Check a list of FFmpeg arguments for well-known ordering mistakes.
+///
+/// ```rust
+/// use ffmpeg_sidecar::lint::{lint_args, LintWarning};
+///
+/// let warnings = lint_args(["-i", "in.mp4", "-ss", "10", "-c", "copy", "out.mp4"]);
+/// assert_eq!(warnings, vec![LintWarning::OutputSeekWithStreamCopy { position: 2 }]);
+/// ```
+pub fn lint_args<I, S>(args: I) -> Vec<LintWarning>
+where
+  I: IntoIterator<Item = S>,
+  S: AsRef<str>,
+{
+  lint_model(&ArgModel::from_args(args))
+}
+
+/// Check arguments which have already been grouped into inputs and outputs.
+pub fn lint_model(model: &ArgModel) -> Vec<LintWarning> {
+  lint_model_with(model, None)
+}
+
+/// Like [`lint_model`], also reporting the codecs which a muxer of the FFmpeg
+/// binary at `ffmpeg` has rejected at runtime. See
+/// [`container_supports_with`].
+pub(crate) fn lint_model_with(model: &ArgModel, ffmpeg: Option<&OsStr>) -> Vec<LintWarning> {
+  let mut warnings = Vec::new();
+
+  for input in &model.inputs {
+    for option in &input.options {
+      if OUTPUT_ONLY_OPTIONS.contains(&option.flag.as_str()) {
+        warnings.push(LintWarning::OutputOptionBeforeInput {
+          arg: option.flag.clone(),
+          position: option.position,
+        });
+      }
+    }
+  }
+
+  let output_options = model.outputs.iter().flat_map(|output| &output.options);
+  for option in output_options.chain(&model.pending) {
+    if INPUT_ONLY_OPTIONS.contains(&option.flag.as_str()) {
+      warnings.push(LintWarning::InputOptionAfterLastInput {
+        arg: option.flag.clone(),
+        position: option.position,
+      });
+    }
+  }
+
+  for output in &model.outputs {
+    let stream_copy = output.options.iter().any(|option| {
+      CODEC_OPTIONS.contains(&option.flag.as_str()) && option.value.as_deref() == Some("copy")
+    });
+    let seeks = output.options.iter().filter(|option| option.flag == "-ss");
+    for seek in seeks.filter(|_| stream_copy) {
+      let position = seek.position;
+      warnings.push(LintWarning::OutputSeekWithStreamCopy { position });
+    }
+  }
+
+  let output_options = model.outputs.iter().map(|output| &output.options);
+  for options in output_options.chain([&model.pending]) {
+    for flags in FILTER_OPTIONS {
+      let mut filters = options.iter().filter(|o| flags.contains(&o.flag.as_str()));
+      let last = filters.next_back();
+      for option in filters.filter(|_| last.is_some()) {
+        warnings.push(LintWarning::OverriddenFilter {
+          arg: option.flag.clone(),
+          position: option.position,
+        });
+      }
+    }
+  }
+
+  // Stream copy isn't checked, since the codec isn't known
+  for output in &model.outputs {
+    let Some(container) = output_container(&output.url, output.option("-f")) else {
+      continue;
+    };
+    for option in output.options.iter().filter(|o| is_codec_option(&o.flag)) {
+      let Some(codec) = option.value.as_deref().filter(|codec| *codec != "copy") else {
+        continue;
+      };
+      let supported = match ffmpeg {
+        Some(ffmpeg) => container_supports_with(ffmpeg, &container, codec),
+        None => container_supports(&container, codec),
+      };
+      if !supported {
+        warnings.push(LintWarning::UnsupportedCodec {
+          arg: option.flag.clone(),
+          codec: codec.to_string(),
+          container: container.clone(),
+          position: option.position,
+        });
+      }
+    }
+  }
+
+  warnings.sort_by_key(|warning| match warning {
+    LintWarning::OutputOptionBeforeInput { position, .. } => *position,
+    LintWarning::InputOptionAfterLastInput { position, .. } => *position,
+    LintWarning::OutputSeekWithStreamCopy { position } => *position,
+    LintWarning::OverriddenFilter { position, .. } => *position,
+    LintWarning::UnsupportedCodec { position, .. } => *position,
+  });
+  warnings
+}
+
+/// Whether `flag` selects the codec of some or all streams, e.g. `-c:a`.
+fn is_codec_option(flag: &str) -> bool {
+  ["-c", "-codec", "-vcodec", "-acodec", "-scodec"].contains(&flag)
+    || flag.starts_with("-c:")
+    || flag.starts_with("-codec:")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_lint_clean() {
+    let args = [
+      "-ss",
+      "10",
+      "-stream_loop",
+      "-1",
+      "-i",
+      "in.mp4",
+      "-map",
+      "0:v",
+      "-c",
+      "copy",
+      "out.mp4",
+    ];
+    assert_eq!(lint_args(args), vec![]);
+  }
+
+  #[test]
+  fn test_lint_misplaced_options() {
+    let args = [
+      "-shortest",
+      "-i",
+      "a.mp4",
+      "-i",
+      "b.wav",
+      "-itsoffset",
+      "1",
+      "-vf",
+      "-re",
+      "out.mp4",
+    ];
+    assert_eq!(
+      lint_args(args),
+      vec![
+        LintWarning::OutputOptionBeforeInput {
+          arg: "-shortest".into(),
+          position: 0
+        },
+        LintWarning::InputOptionAfterLastInput {
+          arg: "-itsoffset".into(),
+          position: 5
+        },
+      ]
+    );
+  }
+
+  #[test]
+  fn test_lint_overridden_filters() {
+    let args = [
+      "-i",
+      "in.mp4",
+      "-vf",
+      "bwdif",
+      "-af",
+      "volume=2",
+      "-filter:v",
+      "crop=64:64",
+      "a.mp4",
+      "-vf",
+      "scale=64:64",
+      "b.mp4",
+    ];
+    assert_eq!(
+      lint_args(args),
+      vec![LintWarning::OverriddenFilter {
+        arg: "-vf".into(),
+        position: 2
+      }]
+    );
+  }
+
+  #[test]
+  fn test_lint_unsupported_codecs() {
+    let args = ["-i", "in.mp4", "-c:v", "libx264", "-c:a", "aac", "out.mp4"];
+    assert_eq!(lint_args(args), vec![]);
+    assert_eq!(
+      lint_args(["-i", "in.mp4", "-c", "copy", "out.webm"]),
+      vec![]
+    );
+    let args = ["-i", "in.mp4", "-c:v", "h264_nvenc", "-f", "matroska", "-"];
+    assert_eq!(lint_args(args), vec![]);
+
+    let args = [
+      "-i",
+      "in.mp4",
+      "-c:v",
+      "libvpx-vp9",
+      "-c:a",
+      "aac",
+      "out.webm",
+    ];
+    assert_eq!(
+      lint_args(args),
+      vec![LintWarning::UnsupportedCodec {
+        arg: "-c:a".into(),
+        codec: "aac".into(),
+        container: "webm".into(),
+        position: 4
+      }]
+    );
+    // `-f` takes priority over the extension
+    let args = ["-i", "in.mp4", "-c:s", "subrip", "-f", "mp4", "out.mov"];
+    assert_eq!(lint_args(args).len(), 1);
+  }
+}
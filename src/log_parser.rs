@@ -1,14 +1,22 @@
-//! Internal methods for parsing FFmpeg CLI log output.
+//! Methods for parsing FFmpeg CLI log output. Most users get these events
+//! from `FfmpegIterator`; [`iter_events`] runs the same parser over any other
+//! source, like a saved log file.
 
-use std::io::{BufReader, Read};
+use std::{
+  borrow::Cow,
+  collections::{HashMap, VecDeque},
+  io::{BufReader, Read},
+};
 
 use crate::{
   comma_iter::CommaIter,
   event::{
-    AudioStream, FfmpegConfiguration, FfmpegDuration, FfmpegEvent, FfmpegInput, FfmpegOutput,
-    FfmpegProgress, FfmpegVersion, LogLevel, Stream, StreamTypeSpecificData, VideoStream,
+    AudioStream, DriftAction, DriftCompensation, FfmpegConfiguration, FfmpegDuration, FfmpegError,
+    FfmpegEvent, FfmpegInput, FfmpegOutput, FfmpegProgress, FfmpegVersion, FilterMetadata,
+    LogLevel, Stream, StreamMapping, StreamTypeSpecificData, VideoStream,
   },
   read_until_any::read_until_any,
+  time_range::FfmpegTimeDuration,
 };
 
 #[derive(Debug, Clone, PartialEq)]
@@ -22,6 +30,18 @@ enum LogSection {
 pub struct FfmpegLogParser<R: Read> {
   reader: BufReader<R>,
   cur_section: LogSection,
+  /// Lines to parse again before reading any more.
+  pending_lines: VecDeque<Vec<u8>>,
+  /// The indentation, output index and stream index of the last output
+  /// stream, until a line which isn't nested beneath it or its encoder.
+  output_stream: Option<(usize, u32, u32)>,
+  /// The `pts_time` of the frame each `metadata=print` filter instance is
+  /// currently printing.
+  filter_pts_times: HashMap<String, Option<f64>>,
+  /// The bytes of the last line, if they weren't valid UTF-8.
+  raw_line: Option<Vec<u8>>,
+  /// The lines of a `-progress pipe:2` report read so far.
+  progress_block: String,
 }
 
 impl<R: Read> FfmpegLogParser<R> {
@@ -36,21 +56,57 @@ impl<R: Read> FfmpegLogParser<R> {
   /// - `\n` (MacOS),
   /// - `\r\n` (Windows)
   /// - `\r` (Windows, progress updates which overwrite the previous line)
+  ///
+  /// Lines which aren't valid UTF-8, e.g. metadata tags written in another
+  /// encoding or output from a localized build, are decoded lossily with
+  /// replacement characters; see [`raw_line`](Self::raw_line).
   pub fn parse_next_event(&mut self) -> anyhow::Result<FfmpegEvent> {
     let mut buf = Vec::<u8>::new();
-    let bytes_read = read_until_any(&mut self.reader, &[b'\r', b'\n'], &mut buf);
+    let bytes_read = match self.pending_lines.pop_front() {
+      Some(line) => {
+        buf = line;
+        Ok(buf.len())
+      }
+      None => read_until_any(&mut self.reader, &[b'\r', b'\n'], &mut buf),
+    };
     let line_cow = String::from_utf8_lossy(buf.as_slice());
+    self.raw_line = match line_cow {
+      Cow::Borrowed(_) => None,
+      Cow::Owned(_) => {
+        let len = buf
+          .iter()
+          .rposition(|b| !b"\r\n".contains(b))
+          .map_or(0, |i| i + 1);
+        Some(buf[..len].to_vec())
+      }
+    };
     let line = line_cow.trim();
     let raw_log_message = line.to_string();
     match bytes_read? {
       0 => Ok(FfmpegEvent::LogEOF),
       _ => {
+        // The reports of `-progress pipe:2` are collected until their last
+        // line, while any log lines in between are parsed as usual
+        if is_progress_block_line(line) {
+          self.progress_block.push_str(line);
+          self.progress_block.push('\n');
+          if !line.starts_with("progress=") {
+            return self.parse_next_event();
+          }
+          let block = std::mem::take(&mut self.progress_block);
+          if let Some(progress) = try_parse_progress_block(&block) {
+            self.cur_section = LogSection::Other;
+            return Ok(FfmpegEvent::Progress(progress));
+          }
+        }
+
         // Track log section
         if let Some(input_number) = try_parse_input(line) {
           self.cur_section = LogSection::Input(input_number);
           return Ok(FfmpegEvent::ParsedInput(FfmpegInput {
             index: input_number,
             duration: None,
+            start_time: None,
             raw_log_message,
           }));
         } else if let Some(output) = try_parse_output(line) {
@@ -60,6 +116,33 @@ impl<R: Read> FfmpegLogParser<R> {
           self.cur_section = LogSection::StreamMapping;
         }
 
+        // The encoder in the metadata of the last output stream, which is
+        // parsed again as a `Log` event afterwards
+        if let Some((indent, output_index, stream_index)) = self.output_stream {
+          if !line.is_empty() && log_indent(line) <= indent {
+            self.output_stream = None;
+          } else if let Some(value) = try_parse_metadata_value(line, "encoder") {
+            // `Lavc60.2.100 libx264`
+            let encoder = match value.split_once(' ') {
+              Some((version, name)) if version.starts_with("Lavc") => name,
+              _ => value,
+            };
+            let event = FfmpegEvent::ParsedOutputStreamEncoder {
+              output_index,
+              stream_index,
+              encoder: encoder.to_string(),
+            };
+            self.output_stream = None;
+            self.pending_lines.push_front(buf.clone());
+            return Ok(event);
+          }
+        }
+
+        let filter_metadata = try_parse_filter_metadata(line);
+        if let Some((filter, FilterMetadataLine::Frame { pts_time })) = filter_metadata {
+          self.filter_pts_times.insert(filter.to_string(), pts_time);
+        }
+
         // Parse
         if let Some(version) = try_parse_version(line) {
           Ok(FfmpegEvent::ParsedVersion(FfmpegVersion {
@@ -71,29 +154,53 @@ impl<R: Read> FfmpegLogParser<R> {
             configuration,
             raw_log_message,
           }))
-        } else if let Some(duration) = try_parse_duration(line) {
+        } else if let Some(duration) = duration_str(line) {
           match self.cur_section {
             LogSection::Input(input_index) => Ok(FfmpegEvent::ParsedDuration(FfmpegDuration {
               input_index,
-              duration,
+              duration: duration.into(),
+              start_time: try_parse_start_time(line),
               raw_log_message,
             })),
             _ => Ok(FfmpegEvent::Log(LogLevel::Info, line.to_string())),
           }
         } else if self.cur_section == LogSection::StreamMapping && line.contains("  Stream #") {
-          Ok(FfmpegEvent::ParsedStreamMapping(line.to_string()))
+          match try_parse_stream_mapping(line) {
+            Some(mapping) => Ok(FfmpegEvent::ParsedStreamMapping(mapping)),
+            None => Ok(FfmpegEvent::Log(LogLevel::Info, line.to_string())),
+          }
         } else if let Some(stream) = try_parse_stream(line) {
           match self.cur_section {
             LogSection::Input(_) => Ok(FfmpegEvent::ParsedInputStream(stream)),
-            LogSection::Output(_) => Ok(FfmpegEvent::ParsedOutputStream(stream)),
+            LogSection::Output(_) => {
+              // Emitted right away, since a stream which isn't re-encoded
+              // has no encoder metadata to wait for, and FFmpeg may log
+              // nothing more until its output is read
+              let indices = (stream.parent_index, stream.stream_index);
+              self.output_stream = Some((log_indent(line), indices.0, indices.1));
+              Ok(FfmpegEvent::ParsedOutputStream(stream))
+            }
             LogSection::Other | LogSection::StreamMapping => Err(anyhow::Error::msg(format!(
               "Unexpected stream specification: {}",
               line
             ))),
           }
+        } else if let Some(action) = try_parse_drift_compensation(line) {
+          Ok(FfmpegEvent::ParsedDriftCompensation(DriftCompensation {
+            action,
+            raw_log_message,
+          }))
         } else if let Some(progress) = try_parse_progress(line) {
           self.cur_section = LogSection::Other;
           Ok(FfmpegEvent::Progress(progress))
+        } else if let Some((filter, FilterMetadataLine::Value { key, value })) = filter_metadata {
+          Ok(FfmpegEvent::FilterMetadata(FilterMetadata {
+            filter: filter.to_string(),
+            key: key.to_string(),
+            value: value.to_string(),
+            pts_time: self.filter_pts_times.get(filter).copied().flatten(),
+            raw_log_message,
+          }))
         } else if line.contains("[info]") {
           Ok(FfmpegEvent::Log(LogLevel::Info, line.to_string()))
         } else if line.contains("[warning]") {
@@ -113,8 +220,384 @@ impl<R: Read> FfmpegLogParser<R> {
     Self {
       reader: BufReader::new(inner),
       cur_section: LogSection::Other,
+      pending_lines: VecDeque::new(),
+      output_stream: None,
+      filter_pts_times: HashMap::new(),
+      raw_line: None,
+      progress_block: String::new(),
     }
   }
+
+  /// The undecoded bytes of the line behind the last event, without its line
+  /// ending, if they weren't valid UTF-8. `None` if the line was decoded
+  /// without any replacement characters.
+  pub fn raw_line(&self) -> Option<&[u8]> {
+    self.raw_line.as_deref()
+  }
+}
+
+/// Parse a log from any source, e.g. a log file or stderr recorded with
+/// `FfmpegChild::record_stderr`, into the events an `FfmpegIterator` would
+/// have emitted for it, minus the output frames. Ends with
+/// `FfmpegEvent::LogEOF`, or with an `FfmpegEvent::Error` if reading fails.
+///
+/// ```rust
+/// use ffmpeg_sidecar::{event::FfmpegEvent, log_parser::iter_events};
+///
+/// let log = "[info] Input #0, lavfi, from 'testsrc':\n\
+///   [info]   Duration: N/A, start: 0.000000, bitrate: N/A\n\
+///   [info]   Stream #0:0: Video: wrapped_avframe, rgb24, 320x240 [SAR 1:1 DAR 4:3], 25 fps, 25 tbr, 25 tbn\n";
+/// let events: Vec<_> = iter_events(log.as_bytes()).collect();
+/// assert!(matches!(events[0], FfmpegEvent::ParsedInput(_)));
+/// assert!(matches!(events[2], FfmpegEvent::ParsedInputStream(_)));
+/// assert_eq!(events.last(), Some(&FfmpegEvent::LogEOF));
+/// ```
+pub fn iter_events<R: Read>(reader: R) -> impl Iterator<Item = FfmpegEvent> {
+  let mut parser = Some(FfmpegLogParser::new(reader));
+  std::iter::from_fn(move || {
+    let event = parser.as_mut()?.parse_next_event();
+    match event {
+      Ok(FfmpegEvent::LogEOF) | Err(_) => parser = None,
+      Ok(_) => {}
+    }
+    Some(event.unwrap_or_else(|e| FfmpegEvent::Error(format!("Error reading the log: {e}"))))
+  })
+}
+
+/// The indentation of a log line, after any `[level]` prefix.
+fn log_indent(line: &str) -> usize {
+  let line = match line.strip_prefix('[') {
+    Some(rest) => rest.split_once(']').map_or(line, |(_, rest)| rest),
+    None => line,
+  };
+  line.len() - line.trim_start().len()
+}
+
+/// The value of a `key : value` metadata line, if its key is `key`.
+fn try_parse_metadata_value<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+  let (line_key, value) = line.split_once(':')?;
+  let line_key = line_key.rsplit(']').next()?.trim();
+  (line_key == key).then(|| value.trim())
+}
+
+/// The levels which FFmpeg prints in brackets with `-loglevel level+...`.
+const LOG_LEVEL_NAMES: [&str; 8] = [
+  "panic", "fatal", "error", "warning", "info", "verbose", "debug", "trace",
+];
+
+/// Split a log line like `[mov @ 0x55d8c2a4e880] [error] message` into its
+/// component (`mov`) and message, without the level. The component is `None`
+/// for lines without one, like `[error] message`.
+///
+/// ## Example
+/// ```rust
+/// use ffmpeg_sidecar::log_parser::split_log_component;
+/// let line = "[mov @ 0x55d8c2a4e880] [error] Could not find tag for codec pcm_s16le";
+/// assert_eq!(
+///   split_log_component(line),
+///   (Some("mov"), "Could not find tag for codec pcm_s16le")
+/// );
+/// let line = "[fatal] Error opening output files: Invalid argument";
+/// assert_eq!(
+///   split_log_component(line),
+///   (None, "Error opening output files: Invalid argument")
+/// );
+/// ```
+pub fn split_log_component(line: &str) -> (Option<&str>, &str) {
+  let mut component = None;
+  let mut rest = line.trim();
+  while let Some((prefix, after)) = rest.strip_prefix('[').and_then(|rest| rest.split_once(']')) {
+    match prefix.split_once(" @ ") {
+      Some((name, _)) => component = Some(name),
+      None if LOG_LEVEL_NAMES.contains(&prefix) => {}
+      None => break,
+    }
+    rest = after.trim_start();
+  }
+  (component, rest)
+}
+
+/// Classify an error logged by FFmpeg as one of the common failures of
+/// [`FfmpegError`], or `None` if it isn't one of them. Follow-up lines
+/// which only repeat the reason, like `Error opening input file in.mp4.`,
+/// aren't classified.
+///
+/// ## Examples
+///
+/// ```rust
+/// use ffmpeg_sidecar::{event::FfmpegError, log_parser::try_parse_error};
+///
+/// let line = "[out#0/mp4 @ 0x55d8] [error] Error opening output out/clip.mp4: No such file or directory";
+/// assert_eq!(
+///   try_parse_error(line),
+///   Some(FfmpegError::FileNotFound { path: Some("out/clip.mp4".to_string()) })
+/// );
+///
+/// let line = "[vost#0:0 @ 0x55d8] [fatal] Unknown encoder 'libfdk_aac'";
+/// assert_eq!(
+///   try_parse_error(line),
+///   Some(FfmpegError::UnknownEncoder { encoder: "libfdk_aac".to_string() })
+/// );
+///
+/// let line = "[libx264 @ 0x55d8] [error] Specified pixel format yuv410p is invalid or not supported";
+/// assert_eq!(
+///   try_parse_error(line),
+///   Some(FfmpegError::UnsupportedPixelFormat {
+///     pix_fmt: "yuv410p".to_string(),
+///     encoder: Some("libx264".to_string()),
+///   })
+/// );
+/// ```
+pub fn try_parse_error(line: &str) -> Option<FfmpegError> {
+  let (component, message) = split_log_component(line);
+  let message = message.trim();
+  let quoted = |text: &str| Some(text.split('\'').nth(1)?.to_string());
+
+  if let Some(path) = message.strip_suffix(": No such file or directory") {
+    return Some(FfmpegError::FileNotFound {
+      path: error_path(path),
+    });
+  }
+  if let Some(path) = message.strip_suffix(": Permission denied") {
+    return Some(FfmpegError::PermissionDenied {
+      path: error_path(path),
+    });
+  }
+  if let Some(device) = message.strip_suffix(": Device or resource busy") {
+    return Some(FfmpegError::DeviceBusy {
+      device: error_path(device).filter(|device| !device.starts_with("ioctl(")),
+    });
+  }
+  if let Some(rest) = message.strip_prefix("Unknown encoder ") {
+    return Some(FfmpegError::UnknownEncoder {
+      encoder: quoted(rest).unwrap_or_else(|| rest.to_string()),
+    });
+  }
+  if let Some(rest) = message.strip_prefix("Specified pixel format ") {
+    let pix_fmt = rest.split_whitespace().next()?.to_string();
+    let encoder = component.map(str::to_string);
+    return Some(FfmpegError::UnsupportedPixelFormat { pix_fmt, encoder });
+  }
+  if let Some(rest) = message.strip_prefix("Unknown pixel format requested: ") {
+    return Some(FfmpegError::UnsupportedPixelFormat {
+      pix_fmt: rest.trim_end_matches('.').to_string(),
+      encoder: None,
+    });
+  }
+  if let Some(rest) = message.strip_prefix("Could not find tag for codec ") {
+    if rest.contains("not currently supported in container") {
+      return Some(FfmpegError::UnsupportedCodec {
+        codec: rest.split_whitespace().next()?.to_string(),
+        container: component.map(str::to_string),
+      });
+    }
+  }
+
+  let option = if let Some(rest) = message
+    .strip_prefix("Unrecognized option ")
+    .or_else(|| message.strip_prefix("Missing argument for option "))
+  {
+    quoted(rest)
+  } else if message.starts_with("Invalid value ") && message.contains(" for option ") {
+    message.split(" for option ").nth(1).and_then(quoted)
+  } else if let Some(rest) = message.strip_prefix("Option ") {
+    Some(rest.strip_suffix(" not found.")?.to_string())
+  } else if message.ends_with(": Invalid argument") {
+    None
+  } else {
+    return None;
+  };
+  Some(FfmpegError::InvalidArgument {
+    option: option.map(|option| format!("-{}", option.trim_start_matches('-'))),
+    message: message.to_string(),
+  })
+}
+
+/// The path before the `: <reason>` of an error message, without the
+/// explanation FFmpeg puts in front of it, e.g. `Error opening output`.
+fn error_path(message: &str) -> Option<String> {
+  const PREFIXES: &[&str] = &[
+    "Error opening output file ",
+    "Error opening output ",
+    "Error opening input file ",
+    "Cannot open video device ",
+    "Could not open file ",
+  ];
+  if message.starts_with("Error opening input") && !message.starts_with("Error opening input file ")
+  {
+    return None;
+  }
+  let path = PREFIXES
+    .iter()
+    .find_map(|prefix| message.strip_prefix(prefix))
+    .unwrap_or(message)
+    .trim();
+  (!path.is_empty()).then(|| path.to_string())
+}
+
+/// Collects consecutive error lines from the same component into
+/// `FfmpegEvent::ErrorBlock`s; see `FfmpegCommand::error_blocks`.
+#[derive(Debug, Default)]
+pub(crate) struct ErrorBlockAggregator {
+  block: Option<(Option<String>, Vec<String>)>,
+}
+
+impl ErrorBlockAggregator {
+  /// Record the next event parsed from the log, returning the block which it
+  /// ends, if any, to be emitted before it.
+  pub(crate) fn push(&mut self, event: &FfmpegEvent) -> Option<FfmpegEvent> {
+    let FfmpegEvent::Log(LogLevel::Error | LogLevel::Fatal, line) = event else {
+      return self.finish();
+    };
+    let (component, message) = split_log_component(line);
+    match &mut self.block {
+      Some((block_component, lines)) if block_component.as_deref() == component => {
+        lines.push(message.to_string());
+        None
+      }
+      _ => {
+        let ended = self.finish();
+        self.block = Some((component.map(str::to_string), vec![message.to_string()]));
+        ended
+      }
+    }
+  }
+
+  /// Take the block in progress, e.g. at the end of the log.
+  pub(crate) fn finish(&mut self) -> Option<FfmpegEvent> {
+    let (component, lines) = self.block.take()?;
+    Some(FfmpegEvent::ErrorBlock { component, lines })
+  }
+}
+
+/// The errors FFmpeg logs once its output has been closed by the reader, as a
+/// consequence of the first `Broken pipe`.
+const BROKEN_PIPE_FOLLOW_UPS: &[&str] = &[
+  "Broken pipe",
+  "Error muxing a packet",
+  "Error submitting a packet to the muxer",
+  "Error writing trailer",
+  "Error closing file",
+  "Task finished with error code",
+  "Terminating thread with return code",
+  "av_interleaved_write_frame()",
+  "Conversion failed!",
+];
+
+/// Replaces the errors which FFmpeg logs after the consumer of its output has
+/// gone away with a single `FfmpegEvent::ConsumerClosed`.
+#[derive(Debug, Default)]
+pub(crate) struct BrokenPipeFilter {
+  reported: bool,
+}
+
+impl BrokenPipeFilter {
+  /// Map the next event parsed from the log: the first `Broken pipe` error
+  /// becomes `ConsumerClosed`, and the errors which follow from it are
+  /// dropped.
+  pub(crate) fn filter(&mut self, event: FfmpegEvent) -> Option<FfmpegEvent> {
+    let FfmpegEvent::Log(level, line) = &event else {
+      return Some(event);
+    };
+    let (_, message) = split_log_component(line);
+    if !self.reported {
+      let is_error = matches!(level, LogLevel::Error | LogLevel::Fatal | LogLevel::Warning);
+      if is_error && message.contains("Broken pipe") {
+        self.reported = true;
+        return Some(FfmpegEvent::ConsumerClosed);
+      }
+      return Some(event);
+    }
+    match BROKEN_PIPE_FOLLOW_UPS
+      .iter()
+      .any(|follow_up| message.contains(follow_up))
+    {
+      true => None,
+      false => Some(event),
+    }
+  }
+}
+
+/// Counts of the lines parsed from FFmpeg's logs, for monitoring how well the
+/// parser understands them; see `FfmpegIterator::log_stats`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogStats {
+  /// Every line parsed, whether or not it was recognized.
+  pub total_lines: u64,
+  /// Lines parsed as `LogLevel::Unknown`, without a recognized log level.
+  pub unknown_lines: u64,
+  /// The first [`LogStats::SAMPLE_SIZE`] non-empty unknown lines.
+  pub unknown_samples: Vec<String>,
+  /// Lines which weren't valid UTF-8, and were decoded with replacement
+  /// characters.
+  pub invalid_utf8_lines: u64,
+}
+
+impl LogStats {
+  pub const SAMPLE_SIZE: usize = 16;
+
+  /// The fraction of lines which were unknown, between 0 and 1.
+  pub fn unknown_ratio(&self) -> f64 {
+    match self.total_lines {
+      0 => 0.0,
+      total => self.unknown_lines as f64 / total as f64,
+    }
+  }
+
+  pub(crate) fn record(&mut self, event: &FfmpegEvent) {
+    match event {
+      FfmpegEvent::LogEOF => return,
+      FfmpegEvent::Log(LogLevel::Unknown, line) => {
+        self.unknown_lines += 1;
+        if self.unknown_samples.len() < Self::SAMPLE_SIZE && !line.is_empty() {
+          self.unknown_samples.push(line.clone());
+        }
+      }
+      _ => {}
+    }
+    self.total_lines += 1;
+  }
+}
+
+/// A line printed by a `metadata` or `ametadata` filter in `print` mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FilterMetadataLine<'a> {
+  /// `frame:1    pts:1       pts_time:0.04`, before the values of each frame.
+  Frame { pts_time: Option<f64> },
+  /// `lavfi.signalstats.YAVG=123.4`
+  Value { key: &'a str, value: &'a str },
+}
+
+/// Parse a line logged by a `metadata=print` or `ametadata=print` filter,
+/// returning the name of the filter instance along with the line's content:
+///
+/// `[Parsed_metadata_1 @ 0x55d8c2a4e880] [info] lavfi.signalstats.YAVG=123.4`
+fn try_parse_filter_metadata(line: &str) -> Option<(&str, FilterMetadataLine)> {
+  let (context, rest) = line.strip_prefix('[')?.split_once(']')?;
+  let filter = context.split(" @ ").next()?;
+  let instance = filter.strip_prefix("Parsed_")?;
+  if !instance.starts_with("metadata_") && !instance.starts_with("ametadata_") {
+    return None;
+  }
+  // Skip the log level, if present
+  let rest = rest.trim_start();
+  let rest = match rest.strip_prefix('[') {
+    Some(level) => level.split_once(']')?.1.trim(),
+    None => rest.trim(),
+  };
+
+  if rest.starts_with("frame:") {
+    let pts_time = rest
+      .split_whitespace()
+      .find_map(|field| field.strip_prefix("pts_time:"))
+      .and_then(|pts_time| pts_time.parse().ok());
+    return Some((filter, FilterMetadataLine::Frame { pts_time }));
+  }
+  let (key, value) = rest.split_once('=')?;
+  if key.is_empty() || key.contains(char::is_whitespace) {
+    return None;
+  }
+  Some((filter, FilterMetadataLine::Value { key, value }))
 }
 
 /// Parses the ffmpeg version string from the stderr stream,
@@ -210,6 +693,11 @@ pub fn try_parse_input(string: &str) -> Option<u32> {
 /// assert!(duration == None);
 /// ```
 pub fn try_parse_duration(string: &str) -> Option<f64> {
+  duration_str(string).and_then(parse_time_str)
+}
+
+/// The unparsed value of a `Duration:` line, e.g. `00:00:05.00` or `N/A`.
+fn duration_str(string: &str) -> Option<&str> {
   string
     .strip_prefix("[info]")
     .unwrap_or(string)
@@ -218,7 +706,28 @@ pub fn try_parse_duration(string: &str) -> Option<f64> {
     .trim()
     .split(',')
     .next()
-    .and_then(parse_time_str)
+}
+
+/// Parse the start time (in seconds) of an input from its `Duration:` line.
+/// Live sources like capture devices report `Duration: N/A`, but still include
+/// the timestamp of their first packet.
+///
+/// ## Example:
+///
+/// ```rust
+/// use ffmpeg_sidecar::log_parser::try_parse_start_time;
+/// let line = "[info]   Duration: N/A, start: 1234.567000, bitrate: 1536 kb/s\n";
+/// assert!(try_parse_start_time(line) == Some(1234.567));
+/// ```
+pub fn try_parse_start_time(string: &str) -> Option<f64> {
+  string
+    .strip_prefix("[info]")
+    .unwrap_or(string)
+    .trim()
+    .strip_prefix("Duration:")?
+    .split(',')
+    .find_map(|part| part.trim().strip_prefix("start:"))
+    .and_then(|start| start.trim().parse::<f64>().ok())
 }
 
 /// Parse an output section like the following, extracting the index of the input:
@@ -233,10 +742,25 @@ pub fn try_parse_duration(string: &str) -> Option<f64> {
 /// assert!(output == Some(FfmpegOutput {
 ///   index: 0,
 ///   to: "test.mp4".to_string(),
+///   format: Some("mp4".to_string()),
+///   children: Vec::new(),
 ///   raw_log_message: line.to_string(),
 /// }));
 /// ```
 ///
+/// Destinations of the `tee` muxer become children of the output:
+///
+/// ```rust
+/// use ffmpeg_sidecar::log_parser::try_parse_output;
+///
+/// let line = "[info] Output #0, tee, to '[f=flv]rtmp://cdn/live|[f=mp4:movflags=+faststart]out.mp4':";
+/// let output = try_parse_output(line).unwrap();
+/// assert!(output.is_tee());
+/// assert_eq!(output.children.len(), 2);
+/// assert_eq!(output.children[0].to, "rtmp://cdn/live");
+/// assert_eq!(output.children[1].format.as_deref(), Some("mp4"));
+/// ```
+///
 pub fn try_parse_output(mut string: &str) -> Option<FfmpegOutput> {
   let raw_log_message = string.to_string();
 
@@ -252,16 +776,167 @@ pub fn try_parse_output(mut string: &str) -> Option<FfmpegOutput> {
     .and_then(|s| s.split(',').next())
     .and_then(|s| s.parse::<u32>().ok())?;
 
-  let to = string
-    .split(" to '")
-    .nth(1)?
-    .split('\'')
-    .next()?
-    .to_string();
+  let (header, rest) = string.split_once(" to '")?;
+  let to = rest.split('\'').next()?.to_string();
+  let format = header
+    .split(',')
+    .nth(1)
+    .map(|format| format.trim().to_string())
+    .filter(|format| !format.is_empty());
+
+  let children = match format.as_deref() {
+    Some("tee") => split_tee_destinations(&to)
+      .into_iter()
+      .map(|(options, to)| FfmpegOutput {
+        index,
+        format: options
+          .split(':')
+          .find_map(|option| option.strip_prefix("f="))
+          .map(String::from),
+        to,
+        children: Vec::new(),
+        raw_log_message: raw_log_message.clone(),
+      })
+      .collect(),
+    _ => Vec::new(),
+  };
 
   Some(FfmpegOutput {
     index,
     to,
+    format,
+    children,
+    raw_log_message,
+  })
+}
+
+/// Split the filename of a `tee` output into `(options, destination)` pairs,
+/// e.g. `[f=flv]rtmp://a|out.mp4` into `("f=flv", "rtmp://a")` and `("",
+/// "out.mp4")`. Backslash escapes between destinations are resolved, while
+/// the options are left escaped.
+fn split_tee_destinations(to: &str) -> Vec<(String, String)> {
+  let mut destinations = vec![String::new()];
+  let mut chars = to.chars();
+  while let Some(c) = chars.next() {
+    let current = destinations.last_mut().unwrap();
+    match c {
+      '\\' => current.extend(chars.next()),
+      '|' => destinations.push(String::new()),
+      c => current.push(c),
+    }
+  }
+
+  destinations
+    .into_iter()
+    .map(|destination| {
+      let Some(rest) = destination.strip_prefix('[') else {
+        return (String::new(), destination);
+      };
+      let mut escaped = false;
+      let close = rest.char_indices().find_map(|(i, c)| match c {
+        _ if escaped => {
+          escaped = false;
+          None
+        }
+        '\\' => {
+          escaped = true;
+          None
+        }
+        ']' => Some(i),
+        _ => None,
+      });
+      match close {
+        Some(i) => (rest[..i].to_string(), rest[i + 1..].to_string()),
+        None => (String::new(), destination),
+      }
+    })
+    .collect()
+}
+
+/// Parse a line of the "Stream mapping:" section.
+///
+/// ## Examples:
+///
+/// ```rust
+/// use ffmpeg_sidecar::log_parser::try_parse_stream_mapping;
+///
+/// let line = "[info]   Stream #0:1 -> #1:0 (aac (native) -> mp3 (libmp3lame))";
+/// let mapping = try_parse_stream_mapping(line).unwrap();
+/// assert_eq!((mapping.from_input, mapping.from_stream), (0, 1));
+/// assert_eq!((mapping.to_output, mapping.to_stream), (Some(1), Some(0)));
+/// assert_eq!(mapping.decoder.as_deref(), Some("aac"));
+/// assert_eq!(mapping.encoder.as_deref(), Some("libmp3lame"));
+///
+/// let line = "[info]   Stream #0:0 -> #0:0 (copy)";
+/// assert!(try_parse_stream_mapping(line).unwrap().copy);
+///
+/// let line = "[info]   Stream #1:0 (h264) -> overlay:overlay";
+/// let mapping = try_parse_stream_mapping(line).unwrap();
+/// assert_eq!(mapping.to_output, None);
+/// assert_eq!(mapping.filter.as_deref(), Some("overlay:overlay"));
+/// assert_eq!(mapping.decoder.as_deref(), Some("h264"));
+/// ```
+pub fn try_parse_stream_mapping(string: &str) -> Option<StreamMapping> {
+  let raw_log_message = string.to_string();
+  let string = string.strip_prefix("[info]").unwrap_or(string).trim();
+  let rest = string.strip_prefix("Stream #")?;
+
+  let parse_index = |index: &str| -> Option<(u32, u32)> {
+    let (file, stream) = index.split_once(':')?;
+    let stream = stream.split(|c: char| !c.is_ascii_digit()).next()?;
+    Some((file.parse().ok()?, stream.parse().ok()?))
+  };
+  let (source, rest) = rest.split_once(' ')?;
+  let (from_input, from_stream) = parse_index(source)?;
+
+  // A stream feeding a filtergraph: `Stream #0:0 (h264) -> overlay`
+  let (decoder_in_parens, rest) = match rest.trim_start().strip_prefix('(') {
+    Some(rest) => {
+      let (decoder, rest) = rest.split_once(')')?;
+      (Some(decoder.to_string()), rest)
+    }
+    None => (None, rest),
+  };
+  let target = rest.trim_start().strip_prefix("->")?.trim();
+
+  let (to_output, to_stream, filter, codecs) = match target.strip_prefix('#') {
+    Some(target) => {
+      let (index, codecs) = target.split_once(' ').unwrap_or((target, ""));
+      let (output, stream) = parse_index(index)?;
+      (Some(output), Some(stream), None, codecs.trim())
+    }
+    None => (None, None, Some(target.to_string()), ""),
+  };
+
+  // `(h264 (native) -> libx264 (native))` or `(copy)`
+  let codecs = codecs
+    .strip_prefix('(')
+    .and_then(|codecs| codecs.strip_suffix(')'))
+    .unwrap_or(codecs);
+  let copy = codecs == "copy";
+  let implementation = |codec: &str| -> Option<String> {
+    let codec = codec.trim();
+    let (name, details) = codec.split_once(" (").unwrap_or((codec, ""));
+    let details = details.trim_end_matches(')');
+    match details {
+      "" | "native" => (!name.is_empty()).then(|| name.to_string()),
+      details => Some(details.to_string()),
+    }
+  };
+  let (decoder, encoder) = match codecs.split_once(" -> ") {
+    Some((decoder, encoder)) => (implementation(decoder), implementation(encoder)),
+    None => (decoder_in_parens, None),
+  };
+
+  Some(StreamMapping {
+    from_input,
+    from_stream,
+    to_output,
+    to_stream,
+    filter,
+    decoder,
+    encoder,
+    copy,
     raw_log_message,
   })
 }
@@ -354,6 +1029,7 @@ pub fn try_parse_output(mut string: &str) -> Option<FfmpegOutput> {
 /// let audio_data = stream.audio_data().unwrap();
 /// assert!(audio_data.sample_rate == 44100);
 /// assert!(audio_data.channels == "mono");
+/// assert!(stream.bitrate_kbps == Some(384.0));
 /// ```
 ///
 /// ### Subtitle
@@ -414,6 +1090,11 @@ pub fn try_parse_stream(string: &str) -> Option<Stream> {
     .unwrap_or(string)
     .trim()
     .strip_prefix("Stream #")?;
+  let bitrate_kbps = CommaIter::new(string).find_map(|part| {
+    let mut words = part.split_whitespace();
+    let bitrate = words.next()?.parse::<f32>().ok()?;
+    (words.next() == Some("kb/s")).then_some(bitrate)
+  });
   let mut comma_iter = CommaIter::new(string);
   let mut colon_iter = comma_iter.next()?.split(':');
 
@@ -454,6 +1135,8 @@ pub fn try_parse_stream(string: &str) -> Option<Stream> {
     language,
     parent_index,
     stream_index,
+    bitrate_kbps,
+    encoder: None,
     raw_log_message,
     type_specific_data,
   })
@@ -568,7 +1251,7 @@ pub fn try_parse_progress(mut string: &str) -> Option<FfmpegProgress> {
     .nth(1)?
     .split_whitespace()
     .next()?
-    .to_string();
+    .into();
   let bitrate_kbps = string
     .split("bitrate=")
     .nth(1)?
@@ -587,18 +1270,171 @@ pub fn try_parse_progress(mut string: &str) -> Option<FfmpegProgress> {
     .map(|s| s.parse::<f32>().unwrap_or(0.0))
     .unwrap_or(0.0);
 
+  // Only logged once frames have been duplicated or dropped
+  let count = |key: &str| {
+    let value = string.split(key).nth(1)?.split_whitespace().next()?;
+    value.parse::<u64>().ok()
+  };
+
   Some(FfmpegProgress {
     frame,
     fps,
     q,
     size_kb,
+    time_clamped: is_negative(&time),
     time,
     bitrate_kbps,
     speed,
+    out_time_us: None,
+    total_size: None,
+    dup_frames: count(" dup="),
+    drop_frames: count(" drop="),
     raw_log_message,
   })
 }
 
+/// The keys of a `-progress` report, besides the `stream_<file>_<stream>_q`
+/// of each encoded stream.
+const PROGRESS_KEYS: &[&str] = &[
+  "frame",
+  "fps",
+  "bitrate",
+  "total_size",
+  "out_time_us",
+  "out_time_ms",
+  "out_time",
+  "dup_frames",
+  "drop_frames",
+  "speed",
+  "progress",
+];
+
+/// Whether `line` is part of a `-progress` report, e.g. `out_time_us=40000`,
+/// rather than a log message. The progress line of the log has several
+/// `key=value` pairs on one line.
+fn is_progress_block_line(line: &str) -> bool {
+  let Some((key, value)) = line.split_once('=') else {
+    return false;
+  };
+  !value.contains('=')
+    && (PROGRESS_KEYS.contains(&key) || (key.starts_with("stream_") && key.ends_with("_q")))
+}
+
+/// Parse one block of the `key=value` report written by `-progress`, which
+/// ends with a `progress=continue` or `progress=end` line. Fields which are
+/// missing or `N/A`, such as `frame` for an audio-only output, are zero.
+///
+/// ## Example
+/// ```rust
+/// use ffmpeg_sidecar::log_parser::try_parse_progress_block;
+/// let block = "frame=250\nfps=49.85\nstream_0_0_q=-0.0\nbitrate=46080.0kbits/s\ntotal_size=57600000\nout_time_us=10000000\nout_time=00:00:10.000000\nspeed=1.99x\nprogress=continue\n";
+/// let progress = try_parse_progress_block(block).unwrap();
+/// assert!(progress.frame == 250);
+/// assert!(progress.fps == 49.85);
+/// assert!(progress.q == -0.0);
+/// assert!(progress.size_kb == 56250);
+/// assert!(progress.time == "00:00:10.000000");
+/// assert!(progress.bitrate_kbps == 46080.0);
+/// assert!(progress.speed == 1.99);
+/// ```
+pub fn try_parse_progress_block(block: &str) -> Option<FfmpegProgress> {
+  let mut values = HashMap::new();
+  for line in block.lines() {
+    if let Some((key, value)) = line.trim().split_once('=') {
+      values.insert(key, value.trim());
+    }
+  }
+  values.get("progress")?;
+
+  let number = |key: &str| values.get(key).and_then(|value| value.parse::<f64>().ok());
+  let q = values
+    .iter()
+    .find(|(key, _)| key.starts_with("stream_") && key.ends_with("_q"))
+    .and_then(|(_, value)| value.parse::<f32>().ok());
+  let bitrate_kbps = values
+    .get("bitrate")
+    .and_then(|value| value.strip_suffix("kbits/s"))
+    .and_then(|value| value.trim().parse::<f32>().ok());
+  let speed = values
+    .get("speed")
+    .and_then(|value| value.strip_suffix('x'))
+    .and_then(|value| value.trim().parse::<f32>().ok());
+
+  let integer = |key: &str| values.get(key).and_then(|value| value.parse::<i64>().ok());
+  let count = |key: &str| values.get(key).and_then(|value| value.parse::<u64>().ok());
+
+  let time: FfmpegTimeDuration = values.get("out_time").copied().unwrap_or("").into();
+  let out_time_us = integer("out_time_us");
+  Some(FfmpegProgress {
+    frame: number("frame").unwrap_or(0.0) as u32,
+    fps: number("fps").unwrap_or(0.0) as f32,
+    q: q.unwrap_or(0.0),
+    size_kb: (number("total_size").unwrap_or(0.0) / 1024.0) as u32,
+    time_clamped: is_negative(&time) || out_time_us.is_some_and(|micros| micros < 0),
+    time,
+    bitrate_kbps: bitrate_kbps.unwrap_or(0.0),
+    speed: speed.unwrap_or(0.0),
+    out_time_us,
+    total_size: count("total_size"),
+    dup_frames: count("dup_frames"),
+    drop_frames: count("drop_frames"),
+    raw_log_message: block.trim_end().to_string(),
+  })
+}
+
+/// Whether FFmpeg reported a bogus negative time, as it does before the
+/// first timestamp is known.
+fn is_negative(time: &FfmpegTimeDuration) -> bool {
+  time.as_secs_f64().is_some_and(|seconds| seconds < 0.0)
+}
+
+/// Parse a timestamp compensation message logged by the audio resampler
+/// (`aresample=async=...`) at the `verbose` level.
+///
+/// ## Examples
+///
+/// ```rust
+/// use ffmpeg_sidecar::event::DriftAction;
+/// use ffmpeg_sidecar::log_parser::try_parse_drift_compensation;
+///
+/// let line = "[SWR @ 0x5633] [verbose] compensating audio timestamp drift:0.023220 compensation:1 in:1024";
+/// assert_eq!(
+///   try_parse_drift_compensation(line),
+///   Some(DriftAction::Stretch { drift: 0.02322, compensation: 1, duration: 1024 })
+/// );
+///
+/// let line = "[SWR @ 0x5633] [verbose] adding 441 audio samples of silence";
+/// assert_eq!(
+///   try_parse_drift_compensation(line),
+///   Some(DriftAction::InsertSilence { samples: 441 })
+/// );
+///
+/// let line = "[SWR @ 0x5633] [verbose] discarding 96 audio samples";
+/// assert_eq!(
+///   try_parse_drift_compensation(line),
+///   Some(DriftAction::Discard { samples: 96 })
+/// );
+/// ```
+pub fn try_parse_drift_compensation(string: &str) -> Option<DriftAction> {
+  let value_after = |key: &str| string.split(key).nth(1)?.split_whitespace().next();
+
+  if string.contains("compensating audio timestamp drift:") {
+    Some(DriftAction::Stretch {
+      drift: value_after("drift:")?.parse().ok()?,
+      compensation: value_after("compensation:")?.parse().ok()?,
+      duration: value_after(" in:")?.parse().ok()?,
+    })
+  } else if string.contains("audio samples of silence") {
+    let samples = value_after("adding ")?.parse().ok()?;
+    Some(DriftAction::InsertSilence { samples })
+  } else if string.ends_with("audio samples") && string.contains("discarding ") {
+    let samples = value_after("discarding ")?.parse().ok()?;
+    Some(DriftAction::Discard { samples })
+  } else {
+    None
+  }
+}
+
 /// Parse a time string in the format `HOURS:MM:SS.MILLISECONDS` into a number of seconds.
 ///
 /// <https://trac.ffmpeg.org/wiki/Seeking#Timeunitsyntax>
@@ -641,6 +1477,7 @@ mod tests {
   use std::{
     io::{Cursor, Seek, SeekFrom, Write},
     process::{Command, Stdio},
+    time::Duration,
   };
 
   #[test]
@@ -705,6 +1542,163 @@ mod tests {
     assert!(num_events > 1);
   }
 
+  #[test]
+  fn test_parse_output_stream_encoder() {
+    let log = "[info] Output #0, mp4, to 'out.mp4':\n\
+      [info]   Metadata:\n\
+      [info]     encoder         : Lavf60.2.100\n\
+      [info]   Stream #0:0: Video: h264 (avc1 / 0x31637661), yuv420p(progressive), 320x240, q=2-31, 2000 kb/s, 25 fps, 12800 tbn\n\
+      [info]     Metadata:\n\
+      [info]       encoder         : Lavc60.3.100 libx264\n\
+      [info]   Stream #0:1: Audio: aac (LC) (mp4a / 0x6134706D), 48000 Hz, stereo, fltp, 128 kb/s\n";
+    let mut parser = FfmpegLogParser::new(log.as_bytes());
+    let mut events = Vec::new();
+    loop {
+      match parser.parse_next_event().unwrap() {
+        FfmpegEvent::LogEOF => break,
+        event => events.push(event),
+      }
+    }
+
+    let streams: Vec<_> = events
+      .iter()
+      .filter_map(|e| match e {
+        FfmpegEvent::ParsedOutputStream(stream) => Some(stream),
+        _ => None,
+      })
+      .collect();
+    assert_eq!(streams[0].bitrate_kbps, Some(2000.0));
+    assert_eq!(streams[1].bitrate_kbps, Some(128.0));
+    assert_eq!(
+      events[5],
+      FfmpegEvent::ParsedOutputStreamEncoder {
+        output_index: 0,
+        stream_index: 0,
+        encoder: "libx264".to_string(),
+      }
+    );
+
+    // The metadata lines are still logged, in their original order
+    assert_eq!(events.len(), 8);
+    assert!(matches!(&events[6], FfmpegEvent::Log(_, line) if line.contains("libx264")));
+  }
+
+  #[test]
+  fn test_parse_output_stream_encoder_from_slow_log() {
+    /// Delivers one line per read, as if FFmpeg were still writing the rest.
+    struct LineByLine<'a>(std::str::SplitInclusive<'a, char>);
+
+    impl Read for LineByLine<'_> {
+      fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let line = self.0.next().unwrap_or_default();
+        buf[..line.len()].copy_from_slice(line.as_bytes());
+        Ok(line.len())
+      }
+    }
+
+    let log = "[info] Output #0, mp4, to 'out.mp4':\n\
+      [info]   Stream #0:0: Video: h264 (avc1 / 0x31637661), yuv420p(progressive), 320x240, q=2-31, 2000 kb/s, 25 fps, 12800 tbn\n\
+      [info]     Metadata:\n\
+      [info]       handler_name    : VideoHandler\n\
+      [info]       encoder         : Lavc60.3.100 libx264\n\
+      [info]   Stream #0:1: Audio: aac (LC) (mp4a / 0x6134706D), 48000 Hz, stereo, fltp, 128 kb/s\n\
+      [info]     Metadata:\n\
+      [info]       encoder         : Lavc60.3.100 aac\n\
+      [info] frame=    1 fps=0.0 q=0.0 size=       0kB time=00:00:00.04 bitrate=   0.0kbits/s speed=N/A\n";
+    let events: Vec<_> = iter_events(LineByLine(log.split_inclusive('\n'))).collect();
+    let encoders: Vec<_> = events
+      .iter()
+      .filter_map(|e| match e {
+        FfmpegEvent::ParsedOutputStreamEncoder {
+          stream_index,
+          encoder,
+          ..
+        } => Some((*stream_index, encoder.as_str())),
+        _ => None,
+      })
+      .collect();
+    assert_eq!(encoders, [(0, "libx264"), (1, "aac")]);
+    assert_eq!(events.len(), 12);
+    assert!(matches!(events[10], FfmpegEvent::Progress(_)));
+  }
+
+  #[test]
+  fn test_parse_stream_copy_output_without_reading_ahead() {
+    /// Fails any read past the log, as if FFmpeg were waiting for its output
+    /// to be read.
+    struct Blocking<'a>(&'a [u8]);
+
+    impl Read for Blocking<'_> {
+      fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        assert!(!self.0.is_empty(), "read past the log");
+        self.0.read(buf)
+      }
+    }
+
+    let log = "[info] Stream mapping:
+      [info]   Stream #0:0 -> #0:0 (copy)
+      [info] Output #0, mpegts, to 'pipe:':
+      [info]   Stream #0:0: Video: h264 (High), yuv420p(progressive), 320x240, q=2-31, 25 fps, 90k tbn
+";
+    let mut parser = FfmpegLogParser::new(Blocking(log.as_bytes()));
+    let mut events = Vec::new();
+    while events.len() < 4 {
+      events.push(parser.parse_next_event().unwrap());
+    }
+    assert!(
+      matches!(&events[3], FfmpegEvent::ParsedOutputStream(stream) if stream.encoder.is_none())
+    );
+  }
+
+  #[test]
+  fn test_log_stats() {
+    let log = "[info] frame=    1 fps=0.0 q=0.0 size=       0kB time=00:00:00.04 bitrate=   0.0kbits/s speed=N/A\n\
+      something new\n\
+      [warning] careful\n";
+    let mut parser = FfmpegLogParser::new(log.as_bytes());
+    let mut stats = LogStats::default();
+    loop {
+      let event = parser.parse_next_event().unwrap();
+      stats.record(&event);
+      if event == FfmpegEvent::LogEOF {
+        break;
+      }
+    }
+    assert_eq!(stats.total_lines, 3);
+    assert_eq!(stats.unknown_lines, 1);
+    assert_eq!(stats.unknown_samples, ["something new"]);
+  }
+
+  #[test]
+  fn test_parse_filter_metadata() {
+    let log = "[Parsed_metadata_1 @ 0x55d8c2a4e880] [info] frame:1    pts:1       pts_time:0.04\n\
+      [Parsed_metadata_1 @ 0x55d8c2a4e880] [info] lavfi.signalstats.YAVG=123.4\n\
+      [Parsed_ametadata_0 @ 0x55d8c2a4f000] [info] lavfi.r128.M=-23.5\n\
+      [Parsed_scale_0 @ 0x55d8c2a4f100] [info] w=320\n";
+    let mut parser = FfmpegLogParser::new(log.as_bytes());
+    let events: Vec<_> = std::iter::from_fn(|| match parser.parse_next_event().ok()? {
+      FfmpegEvent::LogEOF => None,
+      event => Some(event),
+    })
+    .collect();
+
+    assert!(matches!(events[0], FfmpegEvent::Log(LogLevel::Info, _)));
+    let FfmpegEvent::FilterMetadata(yavg) = &events[1] else {
+      panic!("expected filter metadata, got {:?}", events[1]);
+    };
+    assert_eq!(yavg.filter, "Parsed_metadata_1");
+    assert_eq!(
+      (yavg.key.as_str(), yavg.value.as_str()),
+      ("lavfi.signalstats.YAVG", "123.4")
+    );
+    assert_eq!(yavg.pts_time, Some(0.04));
+    let FfmpegEvent::FilterMetadata(loudness) = &events[2] else {
+      panic!("expected filter metadata, got {:?}", events[2]);
+    };
+    assert_eq!(loudness.pts_time, None);
+    assert!(matches!(events[3], FfmpegEvent::Log(LogLevel::Info, _)));
+  }
+
   /// Test case for https://github.com/nathanbabcock/ffmpeg-sidecar/issues/31
   /// Covers regression in progress parsing introduced in FFmpeg 7.0
   /// The string format for `Lsize` units went from `kB` to `KiB`
@@ -737,6 +1731,151 @@ mod tests {
     assert!(progress.speed == 0.0);
   }
 
+  #[test]
+  fn test_parse_progress_block_audio_only() {
+    let block =
+      "bitrate=N/A\ntotal_size=N/A\nout_time_us=N/A\nout_time=N/A\nspeed=N/A\nprogress=end\n";
+    let progress = try_parse_progress_block(block).unwrap();
+    assert!(progress.frame == 0);
+    assert!(progress.size_kb == 0);
+    assert!(progress.time == "N/A");
+    assert!(progress.bitrate_kbps == 0.0);
+    assert!(progress.speed == 0.0);
+    assert!(try_parse_progress_block("frame=1\nfps=0.00\n").is_none());
+  }
+
+  #[test]
+  fn test_parse_error() {
+    let cases = [
+      (
+        "[error] missing.mp4: No such file or directory",
+        Some(FfmpegError::FileNotFound {
+          path: Some("missing.mp4".to_string()),
+        }),
+      ),
+      // FFmpeg 7 names the file on the following line instead
+      (
+        "[in#0 @ 0x5580] [error] Error opening input: No such file or directory",
+        Some(FfmpegError::FileNotFound { path: None }),
+      ),
+      ("[fatal] Error opening input file missing.mp4.", None),
+      (
+        "[out#0/mp4 @ 0x5582] [error] Error opening output /root/out.mp4: Permission denied",
+        Some(FfmpegError::PermissionDenied {
+          path: Some("/root/out.mp4".to_string()),
+        }),
+      ),
+      (
+        "[video4linux2,v4l2 @ 0x5581] [error] Cannot open video device /dev/video0: Device or resource busy",
+        Some(FfmpegError::DeviceBusy {
+          device: Some("/dev/video0".to_string()),
+        }),
+      ),
+      (
+        "[video4linux2,v4l2 @ 0x5581] [error] ioctl(VIDIOC_STREAMON): Device or resource busy",
+        Some(FfmpegError::DeviceBusy { device: None }),
+      ),
+      (
+        "[fatal] Unrecognized option 'crff'.",
+        Some(FfmpegError::InvalidArgument {
+          option: Some("-crff".to_string()),
+          message: "Unrecognized option 'crff'.".to_string(),
+        }),
+      ),
+      (
+        "[fatal] Error opening output files: Invalid argument",
+        Some(FfmpegError::InvalidArgument {
+          option: None,
+          message: "Error opening output files: Invalid argument".to_string(),
+        }),
+      ),
+      (
+        "[fatal] Unknown pixel format requested: yuv999p.",
+        Some(FfmpegError::UnsupportedPixelFormat {
+          pix_fmt: "yuv999p".to_string(),
+          encoder: None,
+        }),
+      ),
+      (
+        "[mp4 @ 0x5583] [error] Could not find tag for codec pcm_s16le in stream #1, codec not currently supported in container",
+        Some(FfmpegError::UnsupportedCodec {
+          codec: "pcm_s16le".to_string(),
+          container: Some("mp4".to_string()),
+        }),
+      ),
+      ("[error] Conversion failed!", None),
+    ];
+    for (line, expected) in cases {
+      assert_eq!(try_parse_error(line), expected, "{line}");
+    }
+    assert!(FfmpegError::DeviceBusy { device: None }.is_retryable());
+  }
+
+  #[test]
+  fn test_parse_progress_blocks_from_stderr() {
+    let log = "[info] Press [q] to stop, [?] for help\n\
+      frame=25\n\
+      fps=0.00\n\
+      stream_0_0_q=-0.0\n\
+      bitrate=N/A\n\
+      [warning] Past duration 0.999992 too large\n\
+      total_size=1843200\n\
+      out_time_us=1000000\n\
+      out_time_ms=1000000\n\
+      out_time=00:00:01.000000\n\
+      dup_frames=2\n\
+      drop_frames=1\n\
+      speed=   2x\n\
+      progress=end\n";
+    let events: Vec<_> = iter_events(log.as_bytes()).collect();
+    assert!(matches!(&events[0], FfmpegEvent::Log(LogLevel::Info, _)));
+    assert!(matches!(&events[1], FfmpegEvent::Log(LogLevel::Warning, _)));
+    let FfmpegEvent::Progress(progress) = &events[2] else {
+      panic!("{events:?}");
+    };
+    assert_eq!(progress.frame, 25);
+    assert_eq!(progress.out_time_us, Some(1_000_000));
+    assert_eq!(progress.total_size, Some(1_843_200));
+    assert_eq!(progress.size_kb, 1800);
+    assert_eq!(
+      (progress.dup_frames, progress.drop_frames),
+      (Some(2), Some(1))
+    );
+    assert_eq!(progress.speed, 2.0);
+    assert_eq!(progress.elapsed(), Some(Duration::from_secs(1)));
+    assert_eq!(events[3], FfmpegEvent::LogEOF);
+
+    // The log line reports duplicated and dropped frames once there are any
+    let line = "[info] frame=  120 fps= 60 q=-1.0 size=     512KiB time=00:00:02.00 bitrate=N/A dup=3 drop=0 speed=   1x";
+    let progress = try_parse_progress(line).unwrap();
+    assert_eq!(
+      (progress.dup_frames, progress.drop_frames),
+      (Some(3), Some(0))
+    );
+    assert_eq!(progress.total_size, None);
+  }
+
+  #[test]
+  fn test_parse_progress_negative_time() {
+    let line = "[info] frame=    0 fps=0.0 q=0.0 size=       0KiB time=-577014:32:22.77 bitrate=  -0.0kbits/s speed=N/A";
+    let progress = try_parse_progress(line).unwrap();
+    assert!(progress.time == "-577014:32:22.77");
+    assert!(progress.time_clamped);
+    assert_eq!(progress.elapsed(), Some(Duration::ZERO));
+
+    let line = "[info] frame=  250 fps=0.0 q=-0.0 Lsize=   56250KiB time=00:00:10.00 bitrate=46080.0kbits/s speed=  20x";
+    let progress = try_parse_progress(line).unwrap();
+    assert!(!progress.time_clamped);
+    assert_eq!(progress.elapsed(), Some(Duration::from_secs(10)));
+  }
+
+  #[test]
+  fn test_parse_progress_out_of_range_time() {
+    let line = "[info] frame=    0 fps=0.0 q=0.0 size=       0KiB time=99999999999999999999:00:00.00 bitrate=N/A speed=N/A";
+    let progress = try_parse_progress(line).unwrap();
+    assert_eq!(progress.elapsed(), None);
+  }
+
   /// Coverage for non-utf-8 bytes: https://github.com/nathanbabcock/ffmpeg-sidecar/issues/67
   #[test]
   fn test_non_utf8() -> anyhow::Result<()> {
@@ -753,4 +1892,184 @@ mod tests {
 
     Ok(())
   }
+
+  #[test]
+  fn test_invalid_utf8_metadata_tags() -> anyhow::Result<()> {
+    let log: &[u8] = b"[info] Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'caf\xe9.mp4':\n\
+      [info]   Metadata:\n\
+      [info]     title           : Caf\xe9 \xe0 Paris\n\
+      [info]   Duration: 00:00:10.00, start: 0.000000, bitrate: 1205 kb/s\n\
+      [info]   Stream #0:0[0x1](und): Video: h264 (High) (avc1 / 0x31637661), yuv420p(progressive), 320x240, 1200 kb/s, 25 fps, 25 tbr, 12800 tbn (default)\n\
+      [info]     Metadata:\n\
+      [info]       handler_name    : \xff\xfeV\x00i\x00d\x00\r\n\
+      [info] Stream mapping:\n";
+    let mut parser = FfmpegLogParser::new(log);
+
+    let FfmpegEvent::ParsedInput(input) = parser.parse_next_event()? else {
+      panic!("expected an input");
+    };
+    assert_eq!(input.index, 0);
+    assert!(input.raw_log_message.ends_with("from 'caf\u{FFFD}.mp4':"));
+    assert!(parser.raw_line().unwrap().ends_with(b"caf\xe9.mp4':"));
+
+    parser.parse_next_event()?;
+    assert_eq!(parser.raw_line(), None);
+    let event = parser.parse_next_event()?;
+    assert_eq!(
+      event,
+      FfmpegEvent::Log(
+        LogLevel::Info,
+        "[info]     title           : Caf\u{FFFD} \u{FFFD} Paris".to_string()
+      )
+    );
+    assert_eq!(
+      parser.raw_line(),
+      Some(&b"[info]     title           : Caf\xe9 \xe0 Paris"[..])
+    );
+
+    assert!(matches!(
+      parser.parse_next_event()?,
+      FfmpegEvent::ParsedDuration(FfmpegDuration { duration, .. }) if duration.as_secs_f64() == Some(10.0)
+    ));
+    assert!(matches!(
+      parser.parse_next_event()?,
+      FfmpegEvent::ParsedInputStream(_)
+    ));
+    parser.parse_next_event()?;
+    assert!(matches!(
+      parser.parse_next_event()?,
+      FfmpegEvent::Log(LogLevel::Info, line) if line.contains("handler_name")
+    ));
+    assert!(parser.raw_line().unwrap().ends_with(b"\x00d\x00"));
+
+    // Parsing carries on as usual afterwards
+    parser.parse_next_event()?;
+    assert_eq!(parser.raw_line(), None);
+    assert_eq!(parser.parse_next_event()?, FfmpegEvent::LogEOF);
+    Ok(())
+  }
+
+  #[test]
+  fn test_error_blocks() {
+    let log = "[mp4 @ 0x55d8c2a4e880] [error] Could not find tag for codec rawvideo in stream #0, codec not currently supported in container\n\
+      [mp4 @ 0x55d8c2a4e880] [error] Could not write header (incorrect codec parameters ?): Invalid argument\n\
+      [vost#0:0/rawvideo @ 0x55d8c2a4f000] [error] Error initializing output stream: Invalid argument\n\
+      [info] Conversion failed!\n\
+      [fatal] Error opening output files: Invalid argument\n";
+    let mut parser = FfmpegLogParser::new(log.as_bytes());
+    let mut aggregator = ErrorBlockAggregator::default();
+    let mut blocks = Vec::new();
+    loop {
+      let event = parser.parse_next_event().unwrap();
+      blocks.extend(aggregator.push(&event));
+      if event == FfmpegEvent::LogEOF {
+        break;
+      }
+    }
+
+    let blocks: Vec<_> = blocks
+      .into_iter()
+      .map(|block| match block {
+        FfmpegEvent::ErrorBlock { component, lines } => (component, lines),
+        event => panic!("unexpected event: {event:?}"),
+      })
+      .collect();
+    assert_eq!(blocks.len(), 3);
+    assert_eq!(blocks[0].0.as_deref(), Some("mp4"));
+    assert_eq!(
+      blocks[0].1,
+      [
+        "Could not find tag for codec rawvideo in stream #0, codec not currently supported in container",
+        "Could not write header (incorrect codec parameters ?): Invalid argument",
+      ]
+    );
+    assert_eq!(blocks[1].0.as_deref(), Some("vost#0:0/rawvideo"));
+    assert_eq!(
+      blocks[2],
+      (
+        None,
+        vec!["Error opening output files: Invalid argument".to_string()]
+      )
+    );
+  }
+
+  #[test]
+  fn test_broken_pipe_filter() {
+    let log = "[info] frame=   25 fps=0.0 q=-0.0 size=    5625KiB time=00:00:01.00 bitrate=46080.0kbits/s speed=2.1x\n\
+      [vost#0:0/rawvideo @ 0x5581c7a3a2c0] [error] Error submitting a packet to the muxer: Broken pipe\n\
+      [out#0/rawvideo @ 0x5581c7a39f80] [error] Error muxing a packet\n\
+      [out#0/rawvideo @ 0x5581c7a39f80] [error] Task finished with error code: -32 (Broken pipe)\n\
+      [out#0/rawvideo @ 0x5581c7a39f80] [error] Terminating thread with return code -32 (Broken pipe)\n\
+      [out#0/rawvideo @ 0x5581c7a39f80] [error] Error writing trailer: Broken pipe\n\
+      [out#0/rawvideo @ 0x5581c7a39f80] [error] Error closing file: Broken pipe\n\
+      [out#0/rawvideo @ 0x5581c7a39f80] [info] video:5625KiB audio:0KiB subtitle:0KiB other streams:0KiB global headers:0KiB muxing overhead: unknown\n\
+      [error] Conversion failed!\n";
+    let mut parser = FfmpegLogParser::new(log.as_bytes());
+    let mut filter = BrokenPipeFilter::default();
+    let mut events = Vec::new();
+    loop {
+      let event = parser.parse_next_event().unwrap();
+      if event == FfmpegEvent::LogEOF {
+        break;
+      }
+      events.extend(filter.filter(event));
+    }
+
+    assert_eq!(events.len(), 3);
+    assert!(matches!(events[0], FfmpegEvent::Progress(_)));
+    assert_eq!(events[1], FfmpegEvent::ConsumerClosed);
+    assert!(
+      matches!(&events[2], FfmpegEvent::Log(LogLevel::Info, line) if line.contains("muxing overhead"))
+    );
+  }
+
+  #[test]
+  fn test_parse_stream_mapping_v7() {
+    let line = "[info]   Stream #0:0 -> #0:0 (h264 (native) -> h264 (libx264))";
+    let mapping = try_parse_stream_mapping(line).unwrap();
+    assert_eq!(mapping.decoder.as_deref(), Some("h264"));
+    assert_eq!(mapping.encoder.as_deref(), Some("libx264"));
+    assert!(!mapping.copy);
+
+    let line = "[info]   Stream #0:0 -> #0:0 (av1 (libdav1d) -> rawvideo (native))";
+    let mapping = try_parse_stream_mapping(line).unwrap();
+    assert_eq!(mapping.decoder.as_deref(), Some("libdav1d"));
+    assert_eq!(mapping.encoder.as_deref(), Some("rawvideo"));
+  }
+
+  #[test]
+  fn test_parse_null_and_tee_outputs() {
+    let null = try_parse_output("[info] Output #0, null, to 'pipe:':").unwrap();
+    assert!(null.is_null());
+    assert!(!null.is_stdout());
+
+    let dev_null = try_parse_output("[info] Output #1, rawvideo, to '/dev/null':").unwrap();
+    assert!(dev_null.is_null());
+
+    let tee = "[info] Output #0, tee, to '[f=mpegts:onfail=ignore]pipe\\:1|[f=mp4]a\\|b.mp4':";
+    let tee = try_parse_output(tee).unwrap();
+    assert_eq!(tee.children.len(), 2);
+    assert_eq!(tee.children[0].to, "pipe:1");
+    assert_eq!(tee.children[1].to, "a|b.mp4");
+    assert!(tee.is_stdout());
+  }
+
+  #[test]
+  fn test_iter_events_read_error() {
+    struct FailingReader;
+    impl Read for FailingReader {
+      fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+        Err(std::io::Error::other("disconnected"))
+      }
+    }
+
+    let log = Cursor::new(b"[info] hello\n".to_vec()).chain(FailingReader);
+    let events: Vec<_> = iter_events(log).collect();
+    assert_eq!(events.len(), 2);
+    assert_eq!(
+      events[0],
+      FfmpegEvent::Log(LogLevel::Info, "[info] hello".into())
+    );
+    assert!(matches!(&events[1], FfmpegEvent::Error(e) if e.contains("disconnected")));
+  }
 }
@@ -0,0 +1,210 @@
+//! Parsing and validation of `-map` stream selections.
+
+use std::fmt;
+
+use crate::event::Stream;
+
+/// A parsed `-map` argument, e.g. `0:v:1`, `-1:a` or `0:s?`.
+///
+/// ```rust
+/// use ffmpeg_sidecar::map::MapSpec;
+///
+/// let spec = MapSpec::parse("-0:a:1?");
+/// assert!(spec.negative);
+/// assert!(spec.optional);
+/// assert_eq!(spec.input_index, Some(0));
+/// assert_eq!(spec.stream_specifier.as_deref(), Some("a:1"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapSpec {
+  /// The original `-map` argument.
+  pub raw: String,
+  /// A `-` prefix, which removes matching streams from earlier mappings.
+  pub negative: bool,
+  /// A `?` suffix, which ignores the mapping if it matches no streams.
+  pub optional: bool,
+  /// The input file index, or `None` for a `[label]` filtergraph output.
+  pub input_index: Option<u32>,
+  /// Everything after the input index, e.g. `v:0`.
+  pub stream_specifier: Option<String>,
+}
+
+impl MapSpec {
+  pub fn parse(map: &str) -> Self {
+    let raw = map.to_string();
+    let (negative, map) = match map.strip_prefix('-') {
+      Some(rest) => (true, rest),
+      None => (false, map),
+    };
+    let (optional, map) = match map.strip_suffix('?') {
+      Some(rest) => (true, rest),
+      None => (false, map),
+    };
+    let (input_index, stream_specifier) = match map.split_once(':') {
+      _ if map.starts_with('[') => (None, None),
+      Some((index, specifier)) => (index.parse().ok(), Some(specifier.to_string())),
+      None => (map.parse().ok(), None),
+    };
+    Self {
+      raw,
+      negative,
+      optional,
+      input_index,
+      stream_specifier,
+    }
+  }
+
+  /// Whether this mapping selects the given input stream. Specifiers which
+  /// can't be evaluated from the parsed stream info alone (such as program or
+  /// metadata specifiers) are assumed to match.
+  pub fn matches(&self, stream: &Stream) -> bool {
+    if self.input_index != Some(stream.parent_index) {
+      return false;
+    }
+    let Some(specifier) = &self.stream_specifier else {
+      return true;
+    };
+    let mut parts = specifier.split(':');
+    let first = parts.next().unwrap_or_default();
+    match first {
+      "v" | "V" => stream.is_video(),
+      "a" => stream.is_audio(),
+      "s" => stream.is_subtitle(),
+      "d" | "t" => stream.is_other(),
+      index if index.parse::<u32>().is_ok() => index.parse() == Ok(stream.stream_index),
+      _ => true,
+    }
+  }
+
+  /// Whether any stream matches, accounting for a trailing type-relative
+  /// index such as the `1` in `v:1`.
+  fn matches_any(&self, streams: &[Stream]) -> bool {
+    let matching = streams.iter().filter(|stream| self.matches(stream));
+    let type_index = self.stream_specifier.as_deref().and_then(|specifier| {
+      let (kind, index) = specifier.split_once(':')?;
+      ["v", "V", "a", "s", "d", "t"]
+        .contains(&kind)
+        .then_some(index)?;
+      index.parse::<usize>().ok()
+    });
+    match type_index {
+      Some(index) => matching.count() > index,
+      None => matching.count() > 0,
+    }
+  }
+}
+
+/// A `-map` argument which can't select any of the known input streams.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MapWarning {
+  /// The mapping refers to an input which doesn't exist. FFmpeg fails in this
+  /// case even if the mapping is optional.
+  InvalidInputIndex { map: String, input_index: u32 },
+  /// No stream of the input matches the stream specifier. FFmpeg fails in
+  /// this case unless the mapping is optional.
+  NoMatchingStreams { map: String },
+}
+
+impl fmt::Display for MapWarning {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      MapWarning::InvalidInputIndex { map, input_index } => {
+        write!(
+          f,
+          "`-map {map}` refers to input #{input_index}, which doesn't exist"
+        )
+      }
+      MapWarning::NoMatchingStreams { map } => {
+        write!(f, "`-map {map}` doesn't match any input streams")
+      }
+    }
+  }
+}
+
+/// Check each `-map` argument against the streams of the inputs, e.g. from
+/// `FfmpegMetadata::input_streams` or a previous probe of the same files.
+/// Optional mappings are only reported if their input index is invalid, and
+/// filtergraph `[label]` mappings are not checked.
+///
+/// ```rust
+/// use ffmpeg_sidecar::map::{validate_maps, MapWarning};
+///
+/// let warnings = validate_maps(["1:v"], &[]);
+/// assert_eq!(
+///   warnings,
+///   vec![MapWarning::InvalidInputIndex { map: "1:v".into(), input_index: 1 }]
+/// );
+/// ```
+pub fn validate_maps<I, S>(maps: I, input_streams: &[Stream]) -> Vec<MapWarning>
+where
+  I: IntoIterator<Item = S>,
+  S: AsRef<str>,
+{
+  maps
+    .into_iter()
+    .filter_map(|map| {
+      let spec = MapSpec::parse(map.as_ref());
+      let input_index = spec.input_index?;
+      if !input_streams.iter().any(|s| s.parent_index == input_index) {
+        let map = spec.raw;
+        return Some(MapWarning::InvalidInputIndex { map, input_index });
+      }
+      match spec.optional || spec.matches_any(input_streams) {
+        true => None,
+        false => Some(MapWarning::NoMatchingStreams { map: spec.raw }),
+      }
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::event::{AudioStream, StreamTypeSpecificData, VideoStream};
+
+  fn stream(parent_index: u32, stream_index: u32, data: StreamTypeSpecificData) -> Stream {
+    Stream {
+      format: String::new(),
+      language: String::new(),
+      parent_index,
+      stream_index,
+      bitrate_kbps: None,
+      encoder: None,
+      raw_log_message: String::new(),
+      type_specific_data: data,
+    }
+  }
+
+  #[test]
+  fn test_validate_maps() {
+    let streams = [
+      stream(
+        0,
+        0,
+        StreamTypeSpecificData::Video(VideoStream {
+          pix_fmt: "yuv420p".into(),
+          width: 320,
+          height: 240,
+          fps: 25.0,
+        }),
+      ),
+      stream(
+        0,
+        1,
+        StreamTypeSpecificData::Audio(AudioStream {
+          sample_rate: 48000,
+          channels: "stereo".into(),
+        }),
+      ),
+    ];
+
+    let maps = [
+      "0", "0:v", "0:1", "-0:a", "0:s?", "[out]", "0:s", "0:v:1", "0:2",
+    ];
+    let no_match = |map: &str| MapWarning::NoMatchingStreams { map: map.into() };
+    assert_eq!(
+      validate_maps(maps, &streams),
+      vec![no_match("0:s"), no_match("0:v:1"), no_match("0:2")]
+    );
+  }
+}
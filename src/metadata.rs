@@ -1,6 +1,54 @@
 //! Information about an FFmpeg process and its streams.
 
-use crate::event::{FfmpegEvent, FfmpegInput, FfmpegOutput, Stream};
+use std::{ffi::OsStr, fmt};
+
+use crate::{
+  args::FileArgs,
+  event::{
+    FfmpegEvent, FfmpegInput, FfmpegOutput, FfmpegProgress, Stream, StreamMapping,
+    StreamTypeSpecificData, VideoStream,
+  },
+  ffprobe::ffprobe_video,
+  iter::IterError,
+};
+
+/// Why [`FfmpegIterator::collect_metadata`](crate::iter::FfmpegIterator::collect_metadata)
+/// couldn't gather the metadata.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetadataError {
+  /// Inputs and outputs aren't described below the `info` log level, e.g.
+  /// with `FfmpegCommand::quiet`.
+  NotLogged,
+  /// FFmpeg exited without opening any input, e.g. because a capture device
+  /// couldn't be found. Holds the errors it logged.
+  NoInputs(Vec<String>),
+  /// FFmpeg exited before describing every output. Holds the errors it
+  /// logged.
+  Incomplete(Vec<String>),
+}
+
+impl fmt::Display for MetadataError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      MetadataError::NotLogged => write!(
+        f,
+        "Metadata is not logged below the `info` log level, e.g. with `quiet()`"
+      ),
+      MetadataError::NoInputs(errors) => write!(
+        f,
+        "FFmpeg exited without opening any input. The following errors occurred: {}",
+        errors.join("\n")
+      ),
+      MetadataError::Incomplete(errors) => write!(
+        f,
+        "Iterator ran out before metadata was gathered. The following errors occurred: {}",
+        errors.join("\n")
+      ),
+    }
+  }
+}
+
+impl std::error::Error for MetadataError {}
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct FfmpegMetadata {
@@ -9,6 +57,8 @@ pub struct FfmpegMetadata {
   pub output_streams: Vec<Stream>,
   pub inputs: Vec<FfmpegInput>,
   pub input_streams: Vec<Stream>,
+  /// How each input stream is routed to the outputs, in log order.
+  pub stream_mappings: Vec<StreamMapping>,
 
   /// Whether all metadata from the parent process has been gathered into this struct
   completed: bool,
@@ -28,6 +78,7 @@ impl FfmpegMetadata {
       output_streams: Vec::new(),
       inputs: Vec::new(),
       input_streams: Vec::new(),
+      stream_mappings: Vec::new(),
       completed: false,
     }
   }
@@ -40,9 +91,71 @@ impl FfmpegMetadata {
   ///
   /// Usually this is the duration of the first input stream. Theoretically
   /// different streams could have different (or conflicting) durations, but
-  /// this handles the common case.
+  /// this handles the common case. See [`max_duration`](Self::max_duration)
+  /// for commands with several inputs.
   pub fn duration(&self) -> Option<f64> {
-    self.inputs[0].duration
+    self.duration_of(0)
+  }
+
+  /// The duration (in seconds) of the input with index `input_index`, if
+  /// it's known.
+  pub fn duration_of(&self, input_index: u32) -> Option<f64> {
+    self.input(input_index)?.duration
+  }
+
+  /// The `start:` timestamp (in seconds) of the input with index
+  /// `input_index`, which may be far from zero for live sources.
+  pub fn start_time_of(&self, input_index: u32) -> Option<f64> {
+    self.input(input_index)?.start_time
+  }
+
+  /// The duration (in seconds) of the longest input, which is how long the
+  /// output runs unless it's cut short, e.g. by `-shortest`.
+  pub fn max_duration(&self) -> Option<f64> {
+    self
+      .inputs
+      .iter()
+      .filter_map(|input| input.duration)
+      .reduce(f64::max)
+  }
+
+  /// How far along the output `progress` is, from 0 to 1, relative to
+  /// [`max_duration`](Self::max_duration).
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::{event::FfmpegEvent, log_parser::iter_events, metadata::FfmpegMetadata};
+  ///
+  /// let log = "[info] Input #0, mov, from 'a.mov':\n\
+  ///   [info]   Duration: 00:00:10.00, start: 0.000000, bitrate: 1411 kb/s\n\
+  ///   [info] Input #1, mov, from 'b.mov':\n\
+  ///   [info]   Duration: 00:00:40.00, start: 0.000000, bitrate: 1411 kb/s\n\
+  ///   [info] frame=  500 fps=0.0 q=-0.0 size=    3445KiB time=00:00:20.00 bitrate=1411.2kbits/s speed=40x\n";
+  /// let mut metadata = FfmpegMetadata::new();
+  /// let mut fraction = None;
+  /// for event in iter_events(log.as_bytes()) {
+  ///   match event {
+  ///     FfmpegEvent::Progress(progress) => fraction = metadata.progress_fraction(&progress),
+  ///     event => metadata.handle_event(&Some(event))?,
+  ///   }
+  /// }
+  /// assert_eq!(metadata.max_duration(), Some(40.0));
+  /// assert_eq!(fraction, Some(0.5));
+  /// # anyhow::Ok(())
+  /// ```
+  pub fn progress_fraction(&self, progress: &FfmpegProgress) -> Option<f64> {
+    let duration = self.max_duration().filter(|duration| *duration > 0.0)?;
+    let elapsed = progress.elapsed()?.as_secs_f64();
+    Some((elapsed / duration).clamp(0.0, 1.0))
+  }
+
+  fn input(&self, input_index: u32) -> Option<&FfmpegInput> {
+    self.inputs.iter().find(|input| input.index == input_index)
+  }
+
+  /// Mark the metadata as complete with whatever has been gathered so far,
+  /// for processes which exit before describing all of their outputs.
+  pub(crate) fn finish(&mut self) {
+    self.completed = true;
   }
 
   pub fn handle_event(&mut self, item: &Option<FfmpegEvent>) -> anyhow::Result<()> {
@@ -53,11 +166,21 @@ impl FfmpegMetadata {
     match item {
       // Every stream mapping corresponds to one output stream
       // We count these to know when we've received all the output streams
-      Some(FfmpegEvent::ParsedStreamMapping(_)) => self.expected_output_streams += 1,
+      Some(FfmpegEvent::ParsedStreamMapping(mapping)) => {
+        self.expected_output_streams += 1;
+        self.stream_mappings.push(mapping.clone());
+      }
       Some(FfmpegEvent::ParsedInput(input)) => self.inputs.push(input.clone()),
       Some(FfmpegEvent::ParsedOutput(output)) => self.outputs.push(output.clone()),
       Some(FfmpegEvent::ParsedDuration(duration)) => {
-        self.inputs[duration.input_index as usize].duration = Some(duration.duration)
+        if let Some(input) = self
+          .inputs
+          .iter_mut()
+          .find(|input| input.index == duration.input_index)
+        {
+          input.duration = duration.duration.as_secs_f64();
+          input.start_time = duration.start_time;
+        }
       }
       Some(FfmpegEvent::ParsedOutputStream(stream)) => self.output_streams.push(stream.clone()),
       Some(FfmpegEvent::ParsedInputStream(stream)) => self.input_streams.push(stream.clone()),
@@ -72,3 +195,308 @@ impl FfmpegMetadata {
     Ok(())
   }
 }
+
+impl FfmpegMetadata {
+  /// Describe the outputs without FFmpeg's log, for iterators whose stderr
+  /// isn't available. A `rawvideo` output to stdout takes its pixel format,
+  /// size and frame rate from `-pix_fmt`, `-s` and `-r`, and the first video
+  /// stream of the first input (found with ffprobe) otherwise. Other formats
+  /// are read in chunks, so need no description.
+  ///
+  /// A filter on the output (`-vf`, or a `-map` of a `-filter_complex`
+  /// label) may change any of the three, so then they have to be given
+  /// rather than probed.
+  pub(crate) fn from_args(inputs: &[FileArgs], outputs: &[FileArgs]) -> Result<Self, IterError> {
+    let mut metadata = Self::new();
+    metadata.outputs = outputs
+      .iter()
+      .enumerate()
+      .map(|(index, output)| FfmpegOutput {
+        to: output.url.clone(),
+        index: index as u32,
+        format: output.option("-f").map(str::to_string),
+        children: Vec::new(),
+        raw_log_message: String::new(),
+      })
+      .collect();
+    let stdout = metadata
+      .outputs
+      .iter()
+      .find(|output| output.is_stdout())
+      .ok_or_else(|| IterError::UnknownOutput("no output is written to stdout".to_string()))?;
+    let options = &outputs[stdout.index as usize];
+
+    let format = stdout.format.clone().unwrap_or_default();
+    let type_specific_data = match format.as_str() {
+      "rawvideo" => {
+        let pix_fmt = options.option("-pix_fmt");
+        let size = options.option("-s").and_then(parse_size);
+        let fps = options.option("-r").and_then(parse_rate);
+        let described = pix_fmt.is_some() && size.is_some() && fps.is_some();
+        if !described && is_filtered(options) {
+          return Err(IterError::UnknownOutput(
+            "a filtered rawvideo output needs -pix_fmt, -s and -r to be described".to_string(),
+          ));
+        }
+        let mut video = match described {
+          true => VideoStream {
+            pix_fmt: String::new(),
+            width: 0,
+            height: 0,
+            fps: 0.0,
+          },
+          false => probe_video(inputs.first().ok_or_else(|| {
+            IterError::UnknownOutput("no input to probe the video size and rate of".to_string())
+          })?)?,
+        };
+        if let Some(pix_fmt) = pix_fmt {
+          video.pix_fmt = pix_fmt.to_string();
+        }
+        if let Some((width, height)) = size {
+          (video.width, video.height) = (width, height);
+        }
+        if let Some(fps) = fps {
+          video.fps = fps;
+        }
+        if video.width == 0 || video.height == 0 || video.fps <= 0.0 {
+          return Err(IterError::UnknownOutput(format!(
+            "incomplete rawvideo output description: {video:?}"
+          )));
+        }
+        StreamTypeSpecificData::Video(video)
+      }
+      _ => StreamTypeSpecificData::Other(),
+    };
+    metadata.output_streams.push(Stream {
+      format,
+      language: String::new(),
+      parent_index: stdout.index,
+      stream_index: 0,
+      bitrate_kbps: None,
+      encoder: None,
+      raw_log_message: String::new(),
+      type_specific_data,
+    });
+    metadata.finish();
+    Ok(metadata)
+  }
+}
+
+/// The first video stream of `input`, according to ffprobe.
+fn probe_video(input: &FileArgs) -> Result<VideoStream, IterError> {
+  ffprobe_video(OsStr::new(&input.url), &input.probe_options())
+    .map(|(video, _)| video)
+    .map_err(|e| IterError::UnknownOutput(e.to_string()))
+}
+
+/// Whether the video of `output` passes through a filter.
+fn is_filtered(output: &FileArgs) -> bool {
+  output.options.iter().any(|option| {
+    let value = option.value.as_deref().unwrap_or_default();
+    match option.flag.as_str() {
+      "-vf" | "-filter" | "-filter:v" | "-filter_script" | "-filter_script:v" => true,
+      "-map" => value.starts_with('['),
+      _ => false,
+    }
+  })
+}
+
+/// Parse a frame size like `1280x720`, or one of FFmpeg's abbreviations like
+/// `hd720`.
+fn parse_size(size: &str) -> Option<(u32, u32)> {
+  if let Some((_, width, height)) = SIZE_ABBREVIATIONS.iter().find(|(name, ..)| *name == size) {
+    return Some((*width, *height));
+  }
+  let (width, height) = size.split_once('x')?;
+  Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+/// The frame size abbreviations FFmpeg accepts, from `libavutil/parseutils.c`.
+const SIZE_ABBREVIATIONS: &[(&str, u32, u32)] = &[
+  ("ntsc", 720, 480),
+  ("pal", 720, 576),
+  ("qntsc", 352, 240),
+  ("qpal", 352, 288),
+  ("sntsc", 640, 480),
+  ("spal", 768, 576),
+  ("film", 352, 240),
+  ("ntsc-film", 352, 240),
+  ("sqcif", 128, 96),
+  ("qcif", 176, 144),
+  ("cif", 352, 288),
+  ("4cif", 704, 576),
+  ("16cif", 1408, 1152),
+  ("qqvga", 160, 120),
+  ("qvga", 320, 240),
+  ("vga", 640, 480),
+  ("svga", 800, 600),
+  ("xga", 1024, 768),
+  ("uxga", 1600, 1200),
+  ("qxga", 2048, 1536),
+  ("sxga", 1280, 1024),
+  ("qsxga", 2560, 2048),
+  ("hsxga", 5120, 4096),
+  ("wvga", 852, 480),
+  ("wxga", 1366, 768),
+  ("wsxga", 1600, 1024),
+  ("wuxga", 1920, 1200),
+  ("woxga", 2560, 1600),
+  ("wqsxga", 3200, 2048),
+  ("wquxga", 3840, 2400),
+  ("whsxga", 6400, 4096),
+  ("whuxga", 7680, 4800),
+  ("cga", 320, 200),
+  ("ega", 640, 350),
+  ("hd480", 852, 480),
+  ("hd720", 1280, 720),
+  ("hd1080", 1920, 1080),
+  ("2k", 2048, 1080),
+  ("2kdci", 2048, 1080),
+  ("2kflat", 1998, 1080),
+  ("2kscope", 2048, 858),
+  ("4k", 4096, 2160),
+  ("4kdci", 4096, 2160),
+  ("4kflat", 3996, 2160),
+  ("4kscope", 4096, 1716),
+  ("nhd", 640, 360),
+  ("hqvga", 240, 160),
+  ("wqvga", 400, 240),
+  ("fwqvga", 432, 240),
+  ("hvga", 480, 320),
+  ("qhd", 960, 540),
+  ("uhd2160", 3840, 2160),
+  ("uhd4320", 7680, 4320),
+];
+
+/// Parse a frame rate like `25` or `30000/1001`.
+pub(crate) fn parse_rate(rate: &str) -> Option<f32> {
+  match rate.split_once('/') {
+    Some((num, den)) => {
+      let den: f32 = den.parse().ok()?;
+      (den != 0.0).then_some(num.parse::<f32>().ok()? / den)
+    }
+    None => rate.parse().ok(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::args::ArgModel;
+
+  #[test]
+  fn test_from_args() {
+    let model = ArgModel::from_args([
+      "-i",
+      "in.mp4",
+      "-f",
+      "rawvideo",
+      "-pix_fmt",
+      "rgb24",
+      "-s",
+      "320x240",
+      "-r",
+      "30000/1001",
+      "-",
+      "-c",
+      "copy",
+      "copy.mkv",
+    ]);
+    let metadata = FfmpegMetadata::from_args(&model.inputs, &model.outputs).unwrap();
+    assert!(metadata.is_completed());
+    assert_eq!(metadata.outputs.len(), 2);
+    let video = metadata.output_streams[0].video_data().unwrap();
+    assert_eq!(
+      (video.pix_fmt.as_str(), video.width, video.height),
+      ("rgb24", 320, 240)
+    );
+    assert!((video.fps - 29.97).abs() < 0.01);
+
+    let model = ArgModel::from_args(["-i", "in.mp4", "-f", "mpegts", "pipe:1"]);
+    let metadata = FfmpegMetadata::from_args(&model.inputs, &model.outputs).unwrap();
+    assert!(metadata.output_streams[0].is_other());
+
+    let model = ArgModel::from_args(["-i", "in.mp4", "out.mp4"]);
+    assert!(FfmpegMetadata::from_args(&model.inputs, &model.outputs).is_err());
+  }
+
+  #[test]
+  fn test_from_args_named_size() {
+    let model = ArgModel::from_args([
+      "-i", "in.mp4", "-f", "rawvideo", "-pix_fmt", "gray", "-s", "hd720", "-r", "25", "-",
+    ]);
+    let metadata = FfmpegMetadata::from_args(&model.inputs, &model.outputs).unwrap();
+    let video = metadata.output_streams[0].video_data().unwrap();
+    assert_eq!((video.width, video.height), (1280, 720));
+  }
+
+  #[test]
+  fn test_from_args_filtered() {
+    // Probing would report the size of the input rather than the scaled one
+    let model = ArgModel::from_args([
+      "-i",
+      "in.mp4",
+      "-vf",
+      "scale=320:-2",
+      "-f",
+      "rawvideo",
+      "-pix_fmt",
+      "rgb24",
+      "-",
+    ]);
+    let error = FfmpegMetadata::from_args(&model.inputs, &model.outputs).unwrap_err();
+    assert!(error.to_string().contains("filtered"), "{error}");
+
+    let model = ArgModel::from_args([
+      "-i", "in.mp4", "-map", "[out]", "-f", "rawvideo", "-pix_fmt", "rgb24", "-s", "64x48", "-r",
+      "10", "-",
+    ]);
+    let metadata = FfmpegMetadata::from_args(&model.inputs, &model.outputs).unwrap();
+    let video = metadata.output_streams[0].video_data().unwrap();
+    assert_eq!((video.width, video.height, video.fps), (64, 48, 10.0));
+  }
+
+  #[test]
+  fn test_input_durations() -> anyhow::Result<()> {
+    let mut metadata = FfmpegMetadata::new();
+    assert_eq!(metadata.duration(), None);
+    assert_eq!(metadata.max_duration(), None);
+
+    let log = "[info] Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'clip.mp4':\n\
+      [info]   Duration: 00:00:05.00, start: 0.000000, bitrate: 1024 kb/s\n\
+      [info] Input #1, v4l2, from '/dev/video0':\n\
+      [info]   Duration: N/A, start: 1234.567000, bitrate: 147456 kb/s\n";
+    for event in crate::log_parser::iter_events(log.as_bytes()) {
+      metadata.handle_event(&Some(event))?;
+    }
+    assert_eq!(metadata.duration_of(0), Some(5.0));
+    assert_eq!(metadata.duration_of(1), None);
+    assert_eq!(metadata.duration_of(2), None);
+    assert_eq!(metadata.start_time_of(1), Some(1234.567));
+    assert_eq!(metadata.max_duration(), Some(5.0));
+    Ok(())
+  }
+
+  #[test]
+  fn test_capture_failure() -> anyhow::Result<()> {
+    let mut metadata = FfmpegMetadata::new();
+    let log = "[video4linux2,v4l2 @ 0x5581] [error] Cannot open video device /dev/video9: No such file or directory\n\
+      [in#0 @ 0x5580] [error] Error opening input: No such file or directory\n\
+      [error] Error opening input file /dev/video9.\n\
+      [info] frame=    0 fps=0.0 q=-0.0 size=       0KiB time=00:00:01.00 bitrate=N/A speed=N/A\n";
+    let mut progress = None;
+    for event in crate::log_parser::iter_events(log.as_bytes()) {
+      match event {
+        FfmpegEvent::Progress(p) => progress = Some(p),
+        event => metadata.handle_event(&Some(event))?,
+      }
+    }
+    assert!(metadata.inputs.is_empty());
+    assert!(!metadata.is_completed());
+    assert_eq!(metadata.duration(), None);
+    assert_eq!(metadata.start_time_of(0), None);
+    assert_eq!(metadata.max_duration(), None);
+    assert_eq!(metadata.progress_fraction(&progress.unwrap()), None);
+    Ok(())
+  }
+}
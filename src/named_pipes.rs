@@ -4,8 +4,12 @@
 //! For more commentary and end-to-end usage, see `examples/named_pipes.rs`:
 //! <https://github.com/nathanbabcock/ffmpeg-sidecar/blob/main/examples/named_pipes.rs>
 
-use anyhow::Result;
-use std::io::Read;
+use anyhow::{Context, Result};
+use std::ffi::{OsStr, OsString};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
 /// On Windows, prepend the pipe name with `\\.\pipe\`.
 /// On Unix, return the name as-is.
@@ -20,6 +24,21 @@ macro_rules! pipe_name {
   };
 }
 
+/// The full path of a named pipe: on Windows, `name` prefixed with
+/// `\\.\pipe\` unless it already is; on Unix, `name` as-is. The runtime
+/// equivalent of [`pipe_name!`].
+pub fn pipe_path<S: AsRef<OsStr>>(name: S) -> PathBuf {
+  let name = name.as_ref();
+  match cfg!(windows) && !name.to_string_lossy().starts_with(r#"\\.\pipe\"#) {
+    true => {
+      let mut path = OsString::from(r#"\\.\pipe\"#);
+      path.push(name);
+      path.into()
+    }
+    false => name.into(),
+  }
+}
+
 /// Windows-only; an FFI pointer to a named pipe handle.
 #[cfg(windows)]
 pub struct NamedPipeHandle(*mut winapi::ctypes::c_void);
@@ -30,30 +49,68 @@ unsafe impl Send for NamedPipeHandle {}
 
 /// Cross-platform abstraction over Windows async named pipes and Unix FIFO.
 pub struct NamedPipe {
+  /// The path that the pipe was opened with. It will start with `\\.\pipe\`
+  /// on Windows.
+  pub path: PathBuf,
+
   /// The name that the pipe was opened with. It will start with `\\.\pipe\` on Windows.
+  #[deprecated(note = "use `path`, which isn't lossily converted to UTF-8")]
   pub name: String,
 
   /// Windows-only; an FFI pointer to a named pipe handle.
   #[cfg(windows)]
   pub handle: NamedPipeHandle,
 
+  /// Windows-only; whether a client (FFmpeg) has connected to the pipe.
+  #[cfg(windows)]
+  connected: bool,
+
   /// Unix-only; a blocking file handle to the FIFO.
   #[cfg(unix)]
   pub file: std::fs::File,
 }
 
+impl NamedPipe {
+  /// Read into `buf`, waiting at most `timeout` for FFmpeg to connect and
+  /// write something. Fails with `ErrorKind::TimedOut` if nothing arrives in
+  /// time, e.g. because FFmpeg exited before opening its output. As with
+  /// `read`, `Ok(0)` means that FFmpeg has closed the pipe.
+  pub fn read_timeout(&mut self, buf: &mut [u8], timeout: Duration) -> io::Result<usize> {
+    self.read_until(buf, Some(Instant::now() + timeout))
+  }
+
+  /// Fill `buf` completely, waiting at most `timeout` in total. Fails with
+  /// `ErrorKind::TimedOut` if the data doesn't arrive in time, or with
+  /// `ErrorKind::UnexpectedEof` if FFmpeg closes the pipe first.
+  pub fn read_exact_timeout(&mut self, mut buf: &mut [u8], timeout: Duration) -> io::Result<()> {
+    let deadline = Instant::now() + timeout;
+    while !buf.is_empty() {
+      match self.read_until(buf, Some(deadline)) {
+        Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+        Ok(bytes_read) => buf = &mut buf[bytes_read..],
+        Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+        Err(e) => return Err(e),
+      }
+    }
+    Ok(())
+  }
+}
+
 #[cfg(windows)]
 impl NamedPipe {
   /// On Windows the pipe name must be in the format `\\.\pipe\{pipe_name}`.
   /// @see <https://learn.microsoft.com/en-us/windows/win32/api/namedpipeapi/nf-namedpipeapi-createnamedpipew>
-  pub fn new<S: AsRef<str>>(pipe_name: S) -> Result<Self> {
-    use std::ffi::OsStr;
+  pub fn new<P: AsRef<Path>>(pipe_name: P) -> Result<Self> {
     use std::os::windows::ffi::OsStrExt;
     use std::ptr::null_mut;
     use winapi::um::namedpipeapi::CreateNamedPipeW;
-    use winapi::um::winbase::{PIPE_ACCESS_DUPLEX, PIPE_TYPE_BYTE, PIPE_WAIT};
+    use winapi::um::winbase::{
+      FILE_FLAG_OVERLAPPED, PIPE_ACCESS_DUPLEX, PIPE_TYPE_BYTE, PIPE_WAIT,
+    };
 
-    let path_wide: Vec<u16> = OsStr::new(pipe_name.as_ref())
+    let path_wide: Vec<u16> = pipe_name
+      .as_ref()
+      .as_os_str()
       .encode_wide()
       .chain(Some(0))
       .collect();
@@ -61,7 +118,8 @@ impl NamedPipe {
     let handle = unsafe {
       CreateNamedPipeW(
         path_wide.as_ptr(),
-        PIPE_ACCESS_DUPLEX,
+        // Overlapped I/O allows connecting and reading with a timeout
+        PIPE_ACCESS_DUPLEX | FILE_FLAG_OVERLAPPED,
         PIPE_TYPE_BYTE | PIPE_WAIT,
         1,
         1024 * 1024 * 64,
@@ -75,53 +133,173 @@ impl NamedPipe {
       anyhow::bail!("Failed to create named pipe");
     }
 
+    #[allow(deprecated)]
     Ok(Self {
       handle: NamedPipeHandle(handle),
-      name: pipe_name.as_ref().to_string(),
+      path: pipe_name.as_ref().to_path_buf(),
+      name: pipe_name.as_ref().to_string_lossy().into_owned(),
+      connected: false,
     })
   }
-}
 
-#[cfg(windows)]
-impl Drop for NamedPipe {
-  fn drop(&mut self) {
+  /// Wait for a pending overlapped operation to complete, cancelling it if
+  /// the deadline passes first.
+  fn complete_overlapped(
+    &self,
+    overlapped: &mut winapi::um::minwinbase::OVERLAPPED,
+    deadline: Option<Instant>,
+  ) -> io::Result<usize> {
+    use winapi::{
+      shared::{
+        minwindef::{DWORD, TRUE},
+        winerror::WAIT_TIMEOUT,
+      },
+      um::{
+        ioapiset::{CancelIo, GetOverlappedResult},
+        synchapi::WaitForSingleObject,
+        winbase::INFINITE,
+      },
+    };
+
+    let millis = match deadline {
+      Some(deadline) => {
+        let remaining = deadline
+          .saturating_duration_since(Instant::now())
+          .as_millis();
+        remaining.min(u128::from(INFINITE - 1)) as DWORD
+      }
+      None => INFINITE,
+    };
+    let mut bytes: DWORD = 0;
     unsafe {
-      winapi::um::handleapi::CloseHandle(self.handle.0);
+      if WaitForSingleObject(overlapped.hEvent, millis) == WAIT_TIMEOUT {
+        CancelIo(self.handle.0);
+        // The buffer may be written to until the cancellation completes. The
+        // operation may also have completed in the meantime.
+        if GetOverlappedResult(self.handle.0, overlapped, &mut bytes, TRUE) != 0 {
+          return Ok(bytes as usize);
+        }
+        return Err(io::Error::from(io::ErrorKind::TimedOut));
+      }
+      if GetOverlappedResult(self.handle.0, overlapped, &mut bytes, TRUE) == 0 {
+        return Err(io::Error::last_os_error());
+      }
     }
+    Ok(bytes as usize)
   }
-}
 
-#[cfg(windows)]
-impl Read for NamedPipe {
-  fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-    use std::io::Error;
+  /// Wait for FFmpeg to open the pipe.
+  fn connect(&mut self, deadline: Option<Instant>) -> io::Result<()> {
+    use winapi::shared::winerror::{ERROR_IO_PENDING, ERROR_NO_DATA, ERROR_PIPE_CONNECTED};
+    use winapi::um::namedpipeapi::ConnectNamedPipe;
+
+    let event = OverlappedEvent::new()?;
+    let mut overlapped = event.overlapped();
+    if unsafe { ConnectNamedPipe(self.handle.0, &mut overlapped) } == 0 {
+      let error = io::Error::last_os_error();
+      match error.raw_os_error().map(|code| code as u32) {
+        // Connected (and possibly already disconnected) before this call
+        Some(ERROR_PIPE_CONNECTED | ERROR_NO_DATA) => {}
+        Some(ERROR_IO_PENDING) => {
+          self.complete_overlapped(&mut overlapped, deadline)?;
+        }
+        _ => return Err(error),
+      }
+    }
+    self.connected = true;
+    Ok(())
+  }
+
+  fn read_until(&mut self, buf: &mut [u8], deadline: Option<Instant>) -> io::Result<usize> {
     use std::ptr::null_mut;
     use winapi::{
-      shared::minwindef::{DWORD, LPVOID},
+      shared::{
+        minwindef::{DWORD, LPVOID},
+        winerror::{ERROR_BROKEN_PIPE, ERROR_IO_PENDING},
+      },
       um::fileapi::ReadFile,
     };
 
-    let mut bytes_read: DWORD = 0;
-    unsafe {
-      let read_status = ReadFile(
+    if !self.connected {
+      self.connect(deadline)?;
+    }
+
+    let event = OverlappedEvent::new()?;
+    let mut overlapped = event.overlapped();
+    let read_status = unsafe {
+      ReadFile(
         self.handle.0,
         buf.as_mut_ptr() as LPVOID,
         buf.len() as DWORD,
-        &mut bytes_read,
         null_mut(),
-      );
-      if read_status == 0 {
-        let error = Error::last_os_error();
-        if error.raw_os_error() == Some(109) {
-          // pipe has been closed since last read
-          return Ok(0);
-        } else {
-          return std::io::Result::Err(error);
+        &mut overlapped,
+      )
+    };
+    let result = match read_status {
+      0 => match io::Error::last_os_error() {
+        e if e.raw_os_error() == Some(ERROR_IO_PENDING as i32) => {
+          self.complete_overlapped(&mut overlapped, deadline)
         }
-      }
+        e => Err(e),
+      },
+      _ => self.complete_overlapped(&mut overlapped, deadline),
     };
+    match result {
+      // pipe has been closed since last read
+      Err(e) if e.raw_os_error() == Some(ERROR_BROKEN_PIPE as i32) => Ok(0),
+      result => result,
+    }
+  }
+}
+
+/// Windows-only; a manual-reset event signalling the completion of an
+/// overlapped operation, closed on drop.
+#[cfg(windows)]
+struct OverlappedEvent(winapi::um::winnt::HANDLE);
+
+#[cfg(windows)]
+impl OverlappedEvent {
+  fn new() -> io::Result<Self> {
+    use std::ptr::{null, null_mut};
+    use winapi::shared::minwindef::{FALSE, TRUE};
+    use winapi::um::synchapi::CreateEventW;
+
+    let handle = unsafe { CreateEventW(null_mut(), TRUE, FALSE, null()) };
+    match handle.is_null() {
+      true => Err(io::Error::last_os_error()),
+      false => Ok(Self(handle)),
+    }
+  }
+
+  fn overlapped(&self) -> winapi::um::minwinbase::OVERLAPPED {
+    let mut overlapped: winapi::um::minwinbase::OVERLAPPED = unsafe { std::mem::zeroed() };
+    overlapped.hEvent = self.0;
+    overlapped
+  }
+}
+
+#[cfg(windows)]
+impl Drop for OverlappedEvent {
+  fn drop(&mut self) {
+    unsafe {
+      winapi::um::handleapi::CloseHandle(self.0);
+    }
+  }
+}
+
+#[cfg(windows)]
+impl Drop for NamedPipe {
+  fn drop(&mut self) {
+    unsafe {
+      winapi::um::handleapi::CloseHandle(self.handle.0);
+    }
+  }
+}
 
-    Ok(bytes_read as usize)
+#[cfg(windows)]
+impl Read for NamedPipe {
+  fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    self.read_until(buf, None)
   }
 }
 
@@ -129,7 +307,7 @@ impl Read for NamedPipe {
 
 #[cfg(unix)]
 impl NamedPipe {
-  pub fn new<S: AsRef<str>>(pipe_name: S) -> Result<Self> {
+  pub fn new<P: AsRef<Path>>(pipe_name: P) -> Result<Self> {
     use nix::{fcntl::OFlag, sys::stat, unistd};
     use std::os::fd::AsRawFd;
     use std::os::unix::fs::OpenOptionsExt;
@@ -145,11 +323,39 @@ impl NamedPipe {
     let fd = AsRawFd::as_raw_fd(&file);
     nix::fcntl::fcntl(fd, nix::fcntl::FcntlArg::F_SETFL(OFlag::empty()))?;
 
+    #[allow(deprecated)]
     Ok(Self {
       file,
-      name: pipe_name.as_ref().to_string(),
+      path: pipe_name.as_ref().to_path_buf(),
+      name: pipe_name.as_ref().to_string_lossy().into_owned(),
     })
   }
+
+  /// Wait until the FIFO has data or its writer has closed it. A FIFO which
+  /// has never been opened for writing is not readable, so this also waits
+  /// for FFmpeg to connect.
+  fn read_until(&mut self, buf: &mut [u8], deadline: Option<Instant>) -> io::Result<usize> {
+    use nix::{
+      errno::Errno,
+      poll::{poll, PollFd, PollFlags, PollTimeout},
+    };
+    use std::os::fd::AsFd;
+
+    let Some(deadline) = deadline else {
+      return self.file.read(buf);
+    };
+    loop {
+      let remaining = deadline.saturating_duration_since(Instant::now());
+      let timeout = PollTimeout::try_from(remaining).unwrap_or(PollTimeout::MAX);
+      let mut fds = [PollFd::new(self.file.as_fd(), PollFlags::POLLIN)];
+      match poll(&mut fds, timeout) {
+        Ok(0) => return Err(io::Error::from(io::ErrorKind::TimedOut)),
+        Ok(_) => return self.file.read(buf),
+        Err(Errno::EINTR) => continue,
+        Err(errno) => return Err(errno.into()),
+      }
+    }
+  }
 }
 
 #[cfg(unix)]
@@ -162,8 +368,125 @@ impl Read for NamedPipe {
 #[cfg(unix)]
 impl Drop for NamedPipe {
   fn drop(&mut self) {
-    use nix::unistd;
-    use std::path::Path;
-    unistd::unlink(Path::new(&self.name)).ok();
+    nix::unistd::unlink(&self.path).ok();
+  }
+}
+
+/// The named pipes of one FFmpeg process, created by
+/// `FfmpegCommand::named_pipe_output` when the command is spawned and owned by
+/// the resulting `FfmpegChild`.
+///
+/// Every pipe is closed and (on Unix) unlinked when the child is reaped with
+/// `wait`, or when the child or this struct is dropped, so pipes aren't
+/// leaked on error paths. Pipes which were handed out with
+/// [`take`](Self::take) or [`spawn_reader`](Self::spawn_reader) are closed by
+/// their new owner; on Unix their paths are still unlinked here.
+#[derive(Default)]
+pub struct ManagedPipes {
+  pipes: Vec<NamedPipe>,
+  paths: Vec<PathBuf>,
+}
+
+impl ManagedPipes {
+  /// Create a pipe for each path. If any fails, the pipes created so far are
+  /// removed again.
+  pub(crate) fn create_all(paths: &[PathBuf]) -> Result<Self> {
+    let mut managed = Self::default();
+    for path in paths {
+      let pipe = NamedPipe::new(path)
+        .with_context(|| format!("Failed to create named pipe {}", path.display()))?;
+      managed.paths.push(path.clone());
+      managed.pipes.push(pipe);
+    }
+    Ok(managed)
+  }
+
+  /// The paths of every managed pipe, in the order they were added.
+  pub fn paths(&self) -> &[PathBuf] {
+    &self.paths
+  }
+
+  /// Take ownership of a pipe, e.g. to read it on a thread of your own.
+  /// `name` may be given with or without the Windows pipe prefix.
+  pub fn take<S: AsRef<OsStr>>(&mut self, name: S) -> Option<NamedPipe> {
+    let path = pipe_path(name);
+    let index = self.pipes.iter().position(|pipe| pipe.path == path)?;
+    Some(self.pipes.remove(index))
+  }
+
+  /// Read a pipe on a new thread with `reader`, e.g. `|pipe|
+  /// pipe.read_to_end(&mut buf)`. The pipe is closed when `reader` returns.
+  pub fn spawn_reader<S, F, T>(&mut self, name: S, reader: F) -> Result<JoinHandle<io::Result<T>>>
+  where
+    S: AsRef<OsStr>,
+    F: FnOnce(&mut NamedPipe) -> io::Result<T> + Send + 'static,
+    T: Send + 'static,
+  {
+    let name = name.as_ref();
+    let mut pipe = self.take(name).with_context(|| {
+      format!(
+        "No managed pipe named {}\n - Was it already taken?",
+        name.to_string_lossy()
+      )
+    })?;
+    Ok(std::thread::spawn(move || reader(&mut pipe)))
+  }
+
+  /// Close and remove every pipe. Called automatically on drop.
+  pub fn close(&mut self) {
+    self.pipes.clear();
+    #[cfg(unix)]
+    for path in self.paths.drain(..) {
+      nix::unistd::unlink(&path).ok();
+    }
+    #[cfg(not(unix))]
+    self.paths.clear();
+  }
+}
+
+impl Drop for ManagedPipes {
+  fn drop(&mut self) {
+    self.close();
+  }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use super::*;
+  use std::io::Write;
+
+  #[test]
+  fn test_read_timeout_without_writer() {
+    let name = std::env::temp_dir().join("ffmpeg_sidecar_test_timeout");
+    let mut pipe = NamedPipe::new(name).unwrap();
+    let mut buf = [0u8; 16];
+    let error = pipe
+      .read_timeout(&mut buf, Duration::from_millis(50))
+      .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+  }
+
+  #[test]
+  fn test_read_exact_timeout() {
+    let name = std::env::temp_dir().join("ffmpeg_sidecar_test_read_exact");
+    let mut pipe = NamedPipe::new(&name).unwrap();
+    let writer = std::thread::spawn(move || {
+      let mut file = std::fs::OpenOptions::new().write(true).open(name).unwrap();
+      file.write_all(b"hello ").unwrap();
+      std::thread::sleep(Duration::from_millis(20));
+      file.write_all(b"world").unwrap();
+    });
+
+    let mut buf = [0u8; 11];
+    pipe
+      .read_exact_timeout(&mut buf, Duration::from_secs(5))
+      .unwrap();
+    assert_eq!(&buf, b"hello world");
+    writer.join().unwrap();
+
+    let error = pipe
+      .read_exact_timeout(&mut buf, Duration::from_secs(5))
+      .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
   }
 }
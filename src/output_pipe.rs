@@ -0,0 +1,356 @@
+//! Outputs added with `FfmpegCommand::output_pipe`, each written by FFmpeg
+//! to its own loopback TCP connection and read on its own thread, so that
+//! several raw outputs with different frame rates or formats can be consumed
+//! at once without interleaving them on stdout.
+
+use std::{
+  io::{BufReader, Cursor, ErrorKind, Read},
+  sync::{
+    atomic::Ordering,
+    mpsc::{channel, Sender, SyncSender, TryRecvError},
+    Arc,
+  },
+  time::Duration,
+};
+
+use crate::{
+  audio_levels::AudioSampleDecoder,
+  event::{FfmpegEvent, Stream},
+  iter::{read_frames, StdoutConfig, ThroughputMeter},
+  pix_fmt::get_bytes_per_frame,
+  tcp_output::TcpOutput,
+};
+
+/// How long each wait for FFmpeg to connect lasts before checking whether
+/// FFmpeg has exited.
+const ACCEPT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// An output registered with `FfmpegCommand::output_pipe`.
+#[derive(Debug, Clone)]
+pub(crate) struct OutputPipe {
+  pub(crate) label: String,
+  pub(crate) output_index: u32,
+  pub(crate) listener: Arc<TcpOutput>,
+}
+
+/// A background thread which accepts FFmpeg's connection to an
+/// [`OutputPipe`] and emits what it receives: an `OutputFrame` per frame of
+/// a single `rawvideo` stream, `OutputAudioSamples` for a single raw PCM
+/// stream with `FfmpegCommand::audio_samples`, and `OutputPipeChunk`s
+/// otherwise.
+///
+/// The connection is accepted and read as soon as the iterator is created,
+/// buffering whatever arrives until the output is described with
+/// [`describe`](Self::describe). Otherwise a fast output could fill its
+/// socket and block FFmpeg while a later output is still being initialized,
+/// before FFmpeg has described every output. Dropping this stops the reader
+/// if the output wasn't described, and stops the wait for a connection once
+/// FFmpeg has exited without making one, but not the reading of an
+/// established connection to a described output.
+pub(crate) struct OutputPipeReader {
+  output_index: u32,
+  described: Sender<Description>,
+}
+
+/// The parsed streams of an output, and where to send its events.
+type Description = (Vec<Stream>, SyncSender<FfmpegEvent>);
+
+impl OutputPipeReader {
+  /// Start accepting and reading `pipe`.
+  pub(crate) fn spawn(pipe: OutputPipe, config: &StdoutConfig) -> Self {
+    let (described, description) = channel::<Description>();
+    let output_index = pipe.output_index;
+    let config = StdoutConfig {
+      chunk_size: config.chunk_size,
+      audio_samples: config.audio_samples,
+      consumer_closed: config.consumer_closed.clone(),
+      output_tags: [(pipe.output_index, pipe.label.clone())].into(),
+      activity: config.activity.clone(),
+      first_output: config.first_output.clone(),
+      ..Default::default()
+    };
+    std::thread::spawn(move || {
+      // The description, once received
+      let mut received = None;
+      let poll = |received: &mut Option<Description>| match description.try_recv() {
+        Ok(description) => {
+          *received = Some(description);
+          true
+        }
+        Err(TryRecvError::Empty) => true,
+        Err(TryRecvError::Disconnected) => false,
+      };
+
+      let stream = loop {
+        match pipe.listener.accept(ACCEPT_TIMEOUT) {
+          Ok(stream) => break stream,
+          Err(e) if e.kind() == ErrorKind::TimedOut => {
+            if !poll(&mut received) {
+              return;
+            }
+          }
+          Err(e) => {
+            if let Some((_, tx)) = received.or_else(|| description.recv().ok()) {
+              tx.send(FfmpegEvent::Error(e.to_string())).ok();
+            }
+            return;
+          }
+        }
+      };
+
+      // Buffer the output until it's described
+      let mut buffered = Vec::new();
+      if received.is_none() {
+        stream.set_read_timeout(Some(ACCEPT_TIMEOUT)).ok();
+        let mut chunk = vec![0; config.chunk_size];
+        while received.is_none() {
+          match (&stream).read(&mut chunk) {
+            Ok(0) => break,
+            Ok(bytes_read) => {
+              config.record_read();
+              buffered.extend_from_slice(&chunk[..bytes_read]);
+            }
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(_) => break,
+          }
+          if !poll(&mut received) {
+            return;
+          }
+        }
+        stream.set_read_timeout(None).ok();
+      }
+      let Some((streams, tx)) = received.or_else(|| description.recv().ok()) else {
+        return;
+      };
+
+      let reader = BufReader::new(Cursor::new(buffered).chain(stream));
+      let complete = read_pipe(reader, &pipe, &streams, &config, |event| {
+        tx.send(event).is_ok()
+      });
+      if !complete {
+        config.consumer_closed.store(true, Ordering::Relaxed);
+      }
+    });
+
+    Self {
+      output_index,
+      described,
+    }
+  }
+
+  /// The index of the output that this reads.
+  pub(crate) fn output_index(&self) -> u32 {
+    self.output_index
+  }
+
+  /// Start emitting the events of the output, whose parsed streams are
+  /// `streams`, to `tx`.
+  pub(crate) fn describe(&self, streams: Vec<Stream>, tx: SyncSender<FfmpegEvent>) {
+    self.described.send((streams, tx)).ok();
+  }
+}
+
+/// Read everything FFmpeg writes to `pipe`, returning `false` if `emit`
+/// does because nothing receives the events anymore.
+fn read_pipe<R: Read>(
+  mut reader: R,
+  pipe: &OutputPipe,
+  streams: &[Stream],
+  config: &StdoutConfig,
+  mut emit: impl FnMut(FfmpegEvent) -> bool,
+) -> bool {
+  let frame_size = match streams {
+    [stream] if stream.format == "rawvideo" => stream.video_data().and_then(get_bytes_per_frame),
+    _ => None,
+  };
+  if let Some(frame_size) = frame_size {
+    let mut meter = ThroughputMeter::new(config);
+    return read_frames(
+      &mut reader,
+      streams,
+      &mut [vec![0; frame_size as usize]],
+      config,
+      &mut meter,
+      |mut event| {
+        if let FfmpegEvent::OutputFrame(frame) = &mut event {
+          frame.output_index = pipe.output_index;
+        }
+        emit(event)
+      },
+    );
+  }
+
+  let mut decoder = match streams {
+    [stream] if config.audio_samples => AudioSampleDecoder::for_stream(stream),
+    _ => None,
+  };
+  let mut buffer = vec![0; config.chunk_size];
+  loop {
+    let bytes_read = match reader.read(&mut buffer) {
+      Ok(0) => return true,
+      Ok(bytes_read) => {
+        config.record_read();
+        bytes_read
+      }
+      Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+      Err(e) => return emit(FfmpegEvent::Error(e.to_string())),
+    };
+    let event = match decoder.as_mut() {
+      Some(decoder) => decoder
+        .push(&buffer[..bytes_read])
+        .map(FfmpegEvent::OutputAudioSamples),
+      None => Some(FfmpegEvent::OutputPipeChunk {
+        label: pipe.label.clone(),
+        chunk: buffer[..bytes_read].to_vec(),
+      }),
+    };
+    if !event.map_or(true, &mut emit) {
+      return false;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::event::{StreamTypeSpecificData, VideoStream};
+
+  #[test]
+  fn test_read_pipe() -> anyhow::Result<()> {
+    let pipe = OutputPipe {
+      label: "thumb".to_string(),
+      output_index: 1,
+      listener: Arc::new(TcpOutput::bind()?),
+    };
+    let config = StdoutConfig {
+      output_tags: [(1, "thumb".to_string())].into(),
+      ..Default::default()
+    };
+    let video = Stream {
+      format: "rawvideo".to_string(),
+      language: String::new(),
+      parent_index: 1,
+      stream_index: 0,
+      bitrate_kbps: None,
+      encoder: None,
+      type_specific_data: StreamTypeSpecificData::Video(VideoStream {
+        pix_fmt: "gray".to_string(),
+        width: 2,
+        height: 2,
+        fps: 10.0,
+      }),
+      raw_log_message: String::new(),
+    };
+
+    // Whole frames of a single raw video stream
+    let mut events = Vec::new();
+    let data: &[u8] = &[1, 2, 3, 4, 5, 6, 7, 8];
+    assert!(read_pipe(data, &pipe, &[video.clone()], &config, |event| {
+      events.push(event);
+      true
+    }));
+    let frames: Vec<_> = events
+      .iter()
+      .filter_map(|event| match event {
+        FfmpegEvent::OutputFrame(frame) => Some(frame),
+        _ => None,
+      })
+      .collect();
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[1].data, [5, 6, 7, 8]);
+    assert_eq!(frames[1].output_index, 1);
+    assert_eq!(frames[1].output_tag.as_deref(), Some("thumb"));
+    assert_eq!(frames[1].timestamp, 0.1);
+
+    // Chunks of anything else
+    let mut events = Vec::new();
+    let encoded = Stream {
+      format: "h264".to_string(),
+      ..video
+    };
+    assert!(read_pipe(data, &pipe, &[encoded], &config, |event| {
+      events.push(event);
+      true
+    }));
+    assert_eq!(
+      events,
+      [FfmpegEvent::OutputPipeChunk {
+        label: "thumb".to_string(),
+        chunk: data.to_vec(),
+      }]
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn test_output_pipe_reader_buffers_until_described() -> anyhow::Result<()> {
+    use std::{io::Write, net::TcpStream, sync::mpsc::sync_channel};
+
+    let pipe = |label: &str, output_index| -> anyhow::Result<OutputPipe> {
+      Ok(OutputPipe {
+        label: label.to_string(),
+        output_index,
+        listener: Arc::new(TcpOutput::bind()?),
+      })
+    };
+    let (raw, encoded) = (pipe("raw", 0)?, pipe("encoded", 1)?);
+    let (raw_port, encoded_port) = (raw.listener.port()?, encoded.listener.port()?);
+    let config = StdoutConfig {
+      chunk_size: 4096,
+      ..Default::default()
+    };
+    let readers = [
+      OutputPipeReader::spawn(raw, &config),
+      OutputPipeReader::spawn(encoded, &config),
+    ];
+
+    // Far more raw output than a socket holds, all written before either
+    // output is described
+    const FRAME: usize = 64 * 64;
+    const FRAMES: usize = 1024;
+    let mut raw_client = TcpStream::connect(("127.0.0.1", raw_port))?;
+    let mut encoded_client = TcpStream::connect(("127.0.0.1", encoded_port))?;
+    let written = std::thread::spawn(move || -> std::io::Result<()> {
+      raw_client.write_all(&[7; FRAME * FRAMES])?;
+      encoded_client.write_all(b"\0\0\0\x01\x67")
+    });
+    let start = std::time::Instant::now();
+    while !written.is_finished() {
+      assert!(start.elapsed() < Duration::from_secs(10), "output blocked");
+      std::thread::sleep(Duration::from_millis(10));
+    }
+    written.join().unwrap()?;
+
+    let stream = |format: &str, parent_index| Stream {
+      format: format.to_string(),
+      language: String::new(),
+      parent_index,
+      stream_index: 0,
+      bitrate_kbps: None,
+      encoder: None,
+      type_specific_data: StreamTypeSpecificData::Video(VideoStream {
+        pix_fmt: "gray".to_string(),
+        width: 64,
+        height: 64,
+        fps: 25.0,
+      }),
+      raw_log_message: String::new(),
+    };
+    let (tx, rx) = sync_channel(0);
+    readers[0].describe(vec![stream("rawvideo", 0)], tx.clone());
+    readers[1].describe(vec![stream("h264", 1)], tx);
+
+    let (mut frames, mut chunks) = (0, Vec::new());
+    for event in rx.iter().take(FRAMES + 1) {
+      match event {
+        FfmpegEvent::OutputFrame(frame) if frame.data.len() == FRAME => frames += 1,
+        FfmpegEvent::OutputPipeChunk { label, chunk } if label == "encoded" => chunks.push(chunk),
+        event => panic!("unexpected event {event:?}"),
+      }
+    }
+    assert_eq!(frames, FRAMES);
+    assert_eq!(chunks, [b"\0\0\0\x01\x67"]);
+    Ok(())
+  }
+}
@@ -0,0 +1,364 @@
+//! Construction and validation of `pan` filters, which remix audio channels.
+
+use std::fmt;
+
+use anyhow::Context;
+
+use crate::event::Stream;
+
+/// The channels of FFmpeg's standard layouts, in order.
+const STANDARD_LAYOUTS: &[(&str, &[&str])] = &[
+  ("mono", &["FC"]),
+  ("stereo", &["FL", "FR"]),
+  ("2.1", &["FL", "FR", "LFE"]),
+  ("3.0", &["FL", "FR", "FC"]),
+  ("3.0(back)", &["FL", "FR", "BC"]),
+  ("4.0", &["FL", "FR", "FC", "BC"]),
+  ("quad", &["FL", "FR", "BL", "BR"]),
+  ("quad(side)", &["FL", "FR", "SL", "SR"]),
+  ("3.1", &["FL", "FR", "FC", "LFE"]),
+  ("5.0", &["FL", "FR", "FC", "BL", "BR"]),
+  ("5.0(side)", &["FL", "FR", "FC", "SL", "SR"]),
+  ("4.1", &["FL", "FR", "FC", "LFE", "BC"]),
+  ("5.1", &["FL", "FR", "FC", "LFE", "BL", "BR"]),
+  ("5.1(side)", &["FL", "FR", "FC", "LFE", "SL", "SR"]),
+  ("6.0", &["FL", "FR", "FC", "BC", "SL", "SR"]),
+  ("6.0(front)", &["FL", "FR", "FLC", "FRC", "SL", "SR"]),
+  ("hexagonal", &["FL", "FR", "FC", "BL", "BR", "BC"]),
+  ("6.1", &["FL", "FR", "FC", "LFE", "BC", "SL", "SR"]),
+  ("6.1(back)", &["FL", "FR", "FC", "LFE", "BL", "BR", "BC"]),
+  ("6.1(front)", &["FL", "FR", "LFE", "FLC", "FRC", "SL", "SR"]),
+  ("7.0", &["FL", "FR", "FC", "BL", "BR", "SL", "SR"]),
+  ("7.0(front)", &["FL", "FR", "FC", "FLC", "FRC", "SL", "SR"]),
+  ("7.1", &["FL", "FR", "FC", "LFE", "BL", "BR", "SL", "SR"]),
+  (
+    "7.1(wide)",
+    &["FL", "FR", "FC", "LFE", "BL", "BR", "FLC", "FRC"],
+  ),
+  (
+    "7.1(wide-side)",
+    &["FL", "FR", "FC", "LFE", "FLC", "FRC", "SL", "SR"],
+  ),
+  (
+    "octagonal",
+    &["FL", "FR", "FC", "BL", "BR", "BC", "SL", "SR"],
+  ),
+  ("downmix", &["DL", "DR"]),
+];
+
+/// The channel names of a layout, as accepted by FFmpeg and printed in stream
+/// info: a standard layout name (`5.1`), a channel count (`6c`, `6 channels`)
+/// or a list of channels (`FL+FR`). Channels of a bare count are named `c0`,
+/// `c1`, etc. Returns `None` for unrecognized layouts.
+///
+/// ```rust
+/// use ffmpeg_sidecar::pan::layout_channels;
+///
+/// assert_eq!(layout_channels("stereo"), Some(vec!["FL".to_string(), "FR".to_string()]));
+/// assert_eq!(layout_channels("3 channels").map(|c| c.len()), Some(3));
+/// assert_eq!(layout_channels("FC+LFE").map(|c| c.len()), Some(2));
+/// assert_eq!(layout_channels("surround-ish"), None);
+/// ```
+pub fn layout_channels(layout: &str) -> Option<Vec<String>> {
+  let layout = layout.trim();
+  if let Some((_, channels)) = STANDARD_LAYOUTS.iter().find(|(name, _)| *name == layout) {
+    return Some(channels.iter().map(|c| c.to_string()).collect());
+  }
+  let count = layout
+    .strip_suffix(" channels")
+    .or_else(|| layout.strip_suffix('c'))
+    .and_then(|count| count.trim().parse::<usize>().ok());
+  if let Some(count) = count {
+    return Some((0..count).map(|i| format!("c{i}")).collect());
+  }
+  let names: Vec<&str> = layout.split('+').collect();
+  let all_known = names.iter().all(|name| {
+    !name.is_empty()
+      && name
+        .chars()
+        .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+  });
+  all_known.then(|| names.iter().map(|name| name.to_string()).collect())
+}
+
+/// A standard layout name for a channel count, falling back to `<n>c`.
+fn default_layout(channels: usize) -> String {
+  match channels {
+    1 => "mono".to_string(),
+    2 => "stereo".to_string(),
+    6 => "5.1".to_string(),
+    8 => "7.1".to_string(),
+    n => format!("{n}c"),
+  }
+}
+
+/// Build a `pan` filter from a gain matrix, with one row per output channel
+/// and one gain per input channel. Zero gains are left out.
+///
+/// ```rust
+/// use ffmpeg_sidecar::pan::pan_filter;
+///
+/// // Downmix stereo to mono
+/// assert_eq!(pan_filter("mono", &[[0.5, 0.5]]), "pan=mono|c0=0.5*c0+0.5*c1");
+///
+/// // Swap left and right
+/// assert_eq!(
+///   pan_filter("stereo", &[[0.0, 1.0], [1.0, 0.0]]),
+///   "pan=stereo|c0=c1|c1=c0"
+/// );
+/// ```
+pub fn pan_filter<G: AsRef<[f64]>>(layout: &str, gains: &[G]) -> String {
+  let mut filter = format!("pan={layout}");
+  for (output, row) in gains.iter().enumerate() {
+    let terms: Vec<String> = row
+      .as_ref()
+      .iter()
+      .enumerate()
+      .filter(|(_, gain)| **gain != 0.0)
+      .map(|(input, gain)| match gain {
+        gain if *gain == 1.0 => format!("c{input}"),
+        gain => format!("{gain}*c{input}"),
+      })
+      .collect();
+    if !terms.is_empty() {
+      filter.push_str(&format!("|c{output}={}", terms.join("+")));
+    }
+  }
+  filter.replace("+-", "-")
+}
+
+/// Build a `pan` filter which routes input channels to output channels
+/// unchanged, given as `(input_channel, output_channel)` pairs. The output has
+/// as many channels as the highest output channel index requires. Fails if
+/// `mapping` is empty, since the output would have no channels.
+///
+/// ```rust
+/// use ffmpeg_sidecar::pan::channel_map_filter;
+///
+/// // Keep only the center channel of 5.1 audio
+/// assert_eq!(channel_map_filter(&[(2, 0)])?, "pan=mono|c0=c2");
+/// assert!(channel_map_filter(&[]).is_err());
+/// # anyhow::Ok(())
+/// ```
+pub fn channel_map_filter(mapping: &[(usize, usize)]) -> anyhow::Result<String> {
+  let channels = mapping.iter().map(|(_, output)| output + 1).max();
+  let channels = channels.context("No channels to map")?;
+  let mut filter = format!("pan={}", default_layout(channels));
+  for (input, output) in mapping {
+    filter.push_str(&format!("|c{output}=c{input}"));
+  }
+  Ok(filter)
+}
+
+/// A problem with a `pan` filter which FFmpeg doesn't always report, usually
+/// resulting in silent channels instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PanWarning {
+  /// The layout isn't recognized, so the filter can't be checked.
+  UnknownLayout { filter: String, layout: String },
+  /// The filter reads a channel that the source audio doesn't have.
+  MissingInputChannel { filter: String, channel: String },
+  /// The filter writes a channel that the output layout doesn't have.
+  MissingOutputChannel { filter: String, channel: String },
+  /// The filter writes the same output channel more than once; only the last
+  /// definition is used.
+  DuplicateOutputChannel { filter: String, channel: usize },
+  /// No input is routed to an output channel, which will be silent.
+  SilentOutputChannel { filter: String, channel: usize },
+}
+
+impl fmt::Display for PanWarning {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      PanWarning::UnknownLayout { filter, layout } => {
+        write!(f, "`{filter}` uses unknown channel layout `{layout}`")
+      }
+      PanWarning::MissingInputChannel { filter, channel } => {
+        write!(
+          f,
+          "`{filter}` reads {channel}, which the source doesn't have"
+        )
+      }
+      PanWarning::MissingOutputChannel { filter, channel } => {
+        write!(
+          f,
+          "`{filter}` writes {channel}, which the output layout doesn't have"
+        )
+      }
+      PanWarning::DuplicateOutputChannel { filter, channel } => {
+        write!(f, "`{filter}` defines output channel c{channel} twice")
+      }
+      PanWarning::SilentOutputChannel { filter, channel } => {
+        write!(f, "`{filter}` leaves output channel c{channel} silent")
+      }
+    }
+  }
+}
+
+/// The index of a channel given either as `c<n>` or by name.
+fn channel_index(channel: &str, layout: &[String]) -> Option<usize> {
+  let by_number = channel
+    .strip_prefix('c')
+    .and_then(|index| index.parse::<usize>().ok());
+  match by_number {
+    Some(index) => (index < layout.len()).then_some(index),
+    None => layout.iter().position(|name| name == channel),
+  }
+}
+
+/// Check a `pan` filter against the channel layout of its source audio, e.g.
+/// `AudioStream::channels`.
+///
+/// ```rust
+/// use ffmpeg_sidecar::pan::{validate_pan, PanWarning};
+///
+/// assert_eq!(validate_pan("pan=mono|c0=0.5*FL+0.5*FR", "stereo"), vec![]);
+///
+/// let filter = "pan=stereo|c0=c0|c1=c2";
+/// assert_eq!(
+///   validate_pan(filter, "stereo"),
+///   vec![
+///     PanWarning::MissingInputChannel { filter: filter.into(), channel: "c2".into() },
+///     PanWarning::SilentOutputChannel { filter: filter.into(), channel: 1 },
+///   ]
+/// );
+/// ```
+pub fn validate_pan(filter: &str, source_layout: &str) -> Vec<PanWarning> {
+  let mut warnings = Vec::new();
+  let raw = filter.to_string();
+  let mut parts = filter.trim_start_matches("pan=").split('|');
+  let layout = parts.next().unwrap_or_default();
+
+  let unknown = |layout: &str| PanWarning::UnknownLayout {
+    filter: raw.clone(),
+    layout: layout.to_string(),
+  };
+  let Some(inputs) = layout_channels(source_layout) else {
+    return vec![unknown(source_layout)];
+  };
+  let Some(outputs) = layout_channels(layout) else {
+    return vec![unknown(layout)];
+  };
+
+  let mut routed = vec![false; outputs.len()];
+  let mut defined = vec![false; outputs.len()];
+  for definition in parts {
+    let Some(split) = definition.find(['=', '<']) else {
+      continue;
+    };
+    let (output, expression) = definition.split_at(split);
+    let output = output.trim();
+    let Some(output_index) = channel_index(output, &outputs) else {
+      warnings.push(PanWarning::MissingOutputChannel {
+        filter: raw.clone(),
+        channel: output.to_string(),
+      });
+      continue;
+    };
+    if std::mem::replace(&mut defined[output_index], true) {
+      warnings.push(PanWarning::DuplicateOutputChannel {
+        filter: raw.clone(),
+        channel: output_index,
+      });
+    }
+
+    let expression = expression[1..].replace('-', "+-");
+    for term in expression.split('+').map(str::trim) {
+      let (gain, channel) = match term.split_once('*') {
+        Some((gain, channel)) => (gain.trim().parse::<f64>().unwrap_or(1.0), channel.trim()),
+        None => (1.0, term.trim_start_matches('-')),
+      };
+      if channel.is_empty() {
+        continue;
+      }
+      match channel_index(channel, &inputs) {
+        Some(_) => routed[output_index] |= gain != 0.0,
+        None => warnings.push(PanWarning::MissingInputChannel {
+          filter: raw.clone(),
+          channel: channel.to_string(),
+        }),
+      }
+    }
+  }
+
+  for (channel, routed) in routed.iter().enumerate() {
+    if !routed {
+      warnings.push(PanWarning::SilentOutputChannel {
+        filter: raw.clone(),
+        channel,
+      });
+    }
+  }
+  warnings
+}
+
+/// Check every `pan` filter in the given filterchains against the channel
+/// layout of the first audio stream in `input_streams`. Returns no warnings if
+/// there is no audio stream to check against.
+///
+/// Filters are checked against the source layout, so a `pan` preceded by
+/// another filter which changes the layout may be reported incorrectly.
+pub fn validate_pan_filters<I, S>(filterchains: I, input_streams: &[Stream]) -> Vec<PanWarning>
+where
+  I: IntoIterator<Item = S>,
+  S: AsRef<str>,
+{
+  let Some(audio) = input_streams.iter().find_map(|stream| stream.audio_data()) else {
+    return Vec::new();
+  };
+  filterchains
+    .into_iter()
+    .flat_map(|chain| {
+      chain
+        .as_ref()
+        .split(',')
+        .filter(|filter| filter.trim_start().starts_with("pan="))
+        .flat_map(|filter| validate_pan(filter.trim(), &audio.channels))
+        .collect::<Vec<_>>()
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_validate_channel_map() {
+    let filter = channel_map_filter(&[(0, 0), (1, 2)]).unwrap();
+    assert_eq!(filter, "pan=3c|c0=c0|c2=c1");
+    assert_eq!(
+      validate_pan(&filter, "stereo"),
+      vec![PanWarning::SilentOutputChannel { filter, channel: 1 }]
+    );
+
+    let filter = channel_map_filter(&[(0, 0), (1, 0)]).unwrap();
+    assert_eq!(
+      validate_pan(&filter, "mono"),
+      vec![
+        PanWarning::DuplicateOutputChannel {
+          filter: filter.clone(),
+          channel: 0
+        },
+        PanWarning::MissingInputChannel {
+          filter,
+          channel: "c1".into()
+        },
+      ]
+    );
+  }
+
+  #[test]
+  fn test_validate_pan_named_channels() {
+    let filter = "pan=stereo|FL<FL+0.7*FC+0.5*BL|FR<FR+0.7*FC+0.5*BR";
+    assert!(validate_pan(filter, "5.1").is_empty());
+    assert_eq!(validate_pan(filter, "stereo").len(), 4);
+    assert_eq!(
+      validate_pan("pan=stereo|c0=-0.5*c0|c1=0*c1", "stereo"),
+      vec![PanWarning::SilentOutputChannel {
+        filter: "pan=stereo|c0=-0.5*c0|c1=0*c1".into(),
+        channel: 1
+      }]
+    );
+  }
+}
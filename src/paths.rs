@@ -1,11 +1,19 @@
-//! Utilities for locating FFmpeg binaries on the system.
+//! Utilities for locating FFmpeg binaries on the system, and for passing
+//! paths to them.
 
 use anyhow::Context;
 use std::{
+  borrow::Cow,
   env::current_exe,
+  ffi::OsStr,
   path::{Path, PathBuf},
 };
 
+/// The longest path Windows accepts without the `\\?\` prefix: `MAX_PATH`,
+/// less the 12 characters it reserves for an 8.3 file name when creating a
+/// directory.
+const MAX_PATH: usize = 248;
+
 /// Returns the default path of the FFmpeg executable, to be used as the
 /// argument to `Command::new`. It should first attempt to locate an FFmpeg
 /// binary adjacent to the Rust executable. If that fails, it should invoke
@@ -47,3 +55,130 @@ pub fn sidecar_dir() -> anyhow::Result<PathBuf> {
       .to_path_buf(),
   )
 }
+
+/// The `\\?\` (extended-length) form of an absolute Windows path which is
+/// too long for `MAX_PATH`, e.g. `C:\...` becomes `\\?\C:\...` and
+/// `\\server\share\...` becomes `\\?\UNC\server\share\...`. Forward slashes
+/// are converted, since Windows doesn't normalize extended-length paths.
+///
+/// Returns `None` if no prefix is needed or possible: for short paths,
+/// relative paths, URLs, paths which already have a `\\?\` or `\\.\`
+/// prefix, and paths with `.` or `..` components.
+///
+/// ```rust
+/// use ffmpeg_sidecar::paths::windows_long_path;
+///
+/// let dir = "a".repeat(250);
+/// assert_eq!(
+///   windows_long_path(&format!("C:/{dir}/in.mp4")),
+///   Some(format!(r"\\?\C:\{dir}\in.mp4"))
+/// );
+/// assert_eq!(
+///   windows_long_path(&format!(r"\\nas\media\{dir}")),
+///   Some(format!(r"\\?\UNC\nas\media\{dir}"))
+/// );
+/// assert_eq!(windows_long_path(r"C:\in.mp4"), None);
+/// assert_eq!(windows_long_path(&format!("https://example.com/{dir}")), None);
+/// ```
+pub fn windows_long_path(path: &str) -> Option<String> {
+  if path.encode_utf16().count() <= MAX_PATH
+    || path.starts_with(r"\\?\")
+    || path.starts_with(r"\\.\")
+  {
+    return None;
+  }
+  let path = path.replace('/', "\\");
+  if path
+    .split('\\')
+    .any(|component| component == "." || component == "..")
+  {
+    return None;
+  }
+  let bytes = path.as_bytes();
+  if bytes.len() > 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && bytes[2] == b'\\' {
+    return Some(format!(r"\\?\{path}"));
+  }
+  path
+    .strip_prefix(r"\\")
+    .map(|unc| format!(r"\\?\UNC\{unc}"))
+}
+
+/// On Windows, `path` in the form given by [`windows_long_path`] if it needs
+/// one, so that builds of FFmpeg which don't extend long paths themselves can
+/// still open it. Elsewhere, and for paths which aren't valid Unicode, `path`
+/// is returned as-is. Applied by `FfmpegCommand::input` and `output`.
+pub fn long_path(path: &OsStr) -> Cow<'_, OsStr> {
+  #[cfg(windows)]
+  if let Some(extended) = path.to_str().and_then(windows_long_path) {
+    return Cow::Owned(extended.into());
+  }
+  Cow::Borrowed(path)
+}
+
+/// `path` as an argument which FFmpeg reads as a local file: the
+/// [`long_path`] on Windows, with relative paths which FFmpeg would take for
+/// an option (`-take2.mp4`) or a protocol (`10:30.mp4`) prefixed with `./`.
+///
+/// ```rust
+/// use ffmpeg_sidecar::paths::file_arg;
+/// use std::{ffi::OsStr, path::Path};
+///
+/// assert_eq!(file_arg(Path::new("clips/a.mp4")), OsStr::new("clips/a.mp4"));
+/// assert_eq!(file_arg(Path::new("-take2.mp4")), Path::new(".").join("-take2.mp4"));
+/// #[cfg(unix)]
+/// assert_eq!(file_arg(Path::new("10:30.mp4")), OsStr::new("./10:30.mp4"));
+/// ```
+pub fn file_arg(path: &Path) -> Cow<'_, OsStr> {
+  if path.is_relative() && is_ambiguous(&path.to_string_lossy()) {
+    return Cow::Owned(Path::new(".").join(path).into_os_string());
+  }
+  long_path(path.as_os_str())
+}
+
+/// The local file which FFmpeg opens for the input or output argument `url`,
+/// resolved against `workdir` if it's relative. `None` for standard I/O
+/// (`-`), URLs with a protocol other than `file:`, and arguments which FFmpeg
+/// would take for an option.
+///
+/// ```rust
+/// use ffmpeg_sidecar::paths::resolve_file_arg;
+/// use std::path::Path;
+///
+/// let workdir = Path::new("/srv/jobs/1234");
+/// assert_eq!(
+///   resolve_file_arg("out/a.mp4", Some(workdir)),
+///   Some(workdir.join("out/a.mp4"))
+/// );
+/// assert_eq!(
+///   resolve_file_arg("file:b.mp4", Some(workdir)),
+///   Some(workdir.join("b.mp4"))
+/// );
+/// assert_eq!(resolve_file_arg("-", Some(workdir)), None);
+/// assert_eq!(resolve_file_arg("rtmp://live/key", Some(workdir)), None);
+/// ```
+pub fn resolve_file_arg(url: &str, workdir: Option<&Path>) -> Option<PathBuf> {
+  let path = match url.strip_prefix("file:") {
+    Some(path) => path,
+    None if is_ambiguous(url) => return None,
+    None => url,
+  };
+  if path.is_empty() || path == "-" {
+    return None;
+  }
+  let path = Path::new(path);
+  Some(match workdir {
+    Some(workdir) if path.is_relative() => workdir.join(path),
+    _ => path.to_path_buf(),
+  })
+}
+
+/// Whether FFmpeg would parse a relative path as something other than a
+/// file: an option, or a URL with a protocol made of the characters before
+/// the first `:`. Drive-relative Windows paths like `C:clip.mp4` are files.
+fn is_ambiguous(path: &str) -> bool {
+  let scheme_len = path
+    .find(|c: char| !(c.is_ascii_alphanumeric() || "+-.,".contains(c)))
+    .unwrap_or(path.len());
+  let is_drive = cfg!(windows) && scheme_len == 1;
+  path.starts_with('-') || (path[scheme_len..].starts_with(':') && !is_drive)
+}
@@ -0,0 +1,122 @@
+//! Progress updates written by FFmpeg to a loopback TCP connection with
+//! `-progress tcp://...`, instead of being parsed from stderr.
+
+use std::{
+  io::{self, BufRead, BufReader},
+  sync::mpsc::{channel, Sender, SyncSender, TryRecvError},
+  time::Duration,
+};
+
+use crate::{
+  event::FfmpegEvent, log_parser::try_parse_progress_block, tcp_output::TcpOutput,
+  watchdog::ActivityClock,
+};
+
+/// How long each wait for FFmpeg to connect lasts before checking whether
+/// the listener is still wanted.
+const ACCEPT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// A background thread which accepts FFmpeg's `-progress` connection and
+/// emits an `FfmpegEvent::Progress` for every report, touching `activity`
+/// as it's received. Dropping this stops
+/// the wait for a connection, e.g. once FFmpeg has exited without making
+/// one, but not the reading of an established one.
+pub(crate) struct ProgressListener {
+  _stop: Sender<()>,
+}
+
+impl ProgressListener {
+  pub(crate) fn spawn(
+    output: TcpOutput,
+    activity: ActivityClock,
+    tx: SyncSender<FfmpegEvent>,
+  ) -> Self {
+    let (stop, stopped) = channel::<()>();
+    std::thread::spawn(move || {
+      let stream = loop {
+        match output.accept(ACCEPT_TIMEOUT) {
+          Ok(stream) => break stream,
+          Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+            if let Err(TryRecvError::Disconnected) = stopped.try_recv() {
+              return;
+            }
+          }
+          Err(e) => {
+            tx.send(FfmpegEvent::Error(e.to_string())).ok();
+            return;
+          }
+        }
+      };
+
+      let mut reader = BufReader::new(stream);
+      let mut block = String::new();
+      let mut buf = Vec::new();
+      loop {
+        buf.clear();
+        match reader.read_until(b'\n', &mut buf) {
+          Ok(0) | Err(_) => break,
+          Ok(_) => {}
+        }
+        let line = String::from_utf8_lossy(&buf);
+        let line = line.trim_end();
+        let is_last = line.starts_with("progress=");
+        block.push_str(line);
+        block.push('\n');
+        if !is_last {
+          continue;
+        }
+        if let Some(progress) = try_parse_progress_block(&block) {
+          activity.touch();
+          if tx.send(FfmpegEvent::Progress(progress)).is_err() {
+            break;
+          }
+        }
+        block.clear();
+      }
+    });
+
+    Self { _stop: stop }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::{io::Write, net::TcpStream, sync::mpsc::sync_channel};
+
+  #[test]
+  fn test_reports_progress_blocks() -> anyhow::Result<()> {
+    let output = TcpOutput::bind()?;
+    let addr = output.url().trim_start_matches("tcp://").to_string();
+    let (tx, rx) = sync_channel(0);
+    let listener = ProgressListener::spawn(output, ActivityClock::default(), tx);
+
+    let mut stream = TcpStream::connect(addr)?;
+    stream.write_all(b"frame=1\nout_time=00:00:00.040000\nunknown=\xff\nprogress=continue\n")?;
+    stream.write_all(b"frame=2\nout_time=00:00:00.080000\nprogress=end\n")?;
+    drop(stream);
+    drop(listener);
+
+    let frames: Vec<u32> = rx
+      .iter()
+      .map(|event| match event {
+        FfmpegEvent::Progress(progress) => progress.frame,
+        event => panic!("unexpected event: {event:?}"),
+      })
+      .collect();
+    assert_eq!(frames, [1, 2]);
+    Ok(())
+  }
+
+  #[test]
+  fn test_stops_waiting_when_dropped() -> anyhow::Result<()> {
+    let (tx, rx) = sync_channel(0);
+    drop(ProgressListener::spawn(
+      TcpOutput::bind()?,
+      ActivityClock::default(),
+      tx,
+    ));
+    assert!(rx.recv().is_err());
+    Ok(())
+  }
+}
@@ -0,0 +1,183 @@
+//! Generate low-resolution editing proxies.
+//!
+//! Editing tools commonly swap high-resolution source media for lightweight
+//! "proxy" copies while editing. [`make_proxy`] probes the source, picks a
+//! reduced resolution, transcodes with an intra-frame or short-GOP codec that
+//! is cheap to scrub, and writes the result to a predictable path next to the
+//! source (see [`proxy_path`]).
+
+use std::path::{Path, PathBuf};
+
+use anyhow::bail;
+
+use crate::{
+  command::FfmpegCommand,
+  event::{FfmpegEvent, LogLevel},
+  ffprobe::ffprobe_video,
+  paths::file_arg,
+};
+
+/// The largest proxy height produced by [`proxy_size`].
+pub const MAX_PROXY_HEIGHT: u32 = 540;
+
+/// Codec presets for proxy media.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyProfile {
+  /// Avid DNxHR LB in a QuickTime container; intra-frame, widely supported by
+  /// NLEs.
+  DnxhrLb,
+  /// Apple ProRes 422 Proxy in a QuickTime container; intra-frame.
+  ProresProxy,
+  /// Low-bitrate H.264 with a short GOP, for small files and web previews.
+  H264,
+}
+
+impl ProxyProfile {
+  /// The file name suffix identifying proxies of this profile.
+  pub fn suffix(&self) -> &'static str {
+    match self {
+      ProxyProfile::DnxhrLb => "dnxhr_lb",
+      ProxyProfile::ProresProxy => "prores_proxy",
+      ProxyProfile::H264 => "h264",
+    }
+  }
+
+  /// The file extension of proxies of this profile, without the dot.
+  pub fn extension(&self) -> &'static str {
+    match self {
+      ProxyProfile::DnxhrLb | ProxyProfile::ProresProxy => "mov",
+      ProxyProfile::H264 => "mp4",
+    }
+  }
+
+  /// Append the profile's codec options to an output.
+  pub fn apply<'a>(&self, command: &'a mut FfmpegCommand) -> &'a mut FfmpegCommand {
+    match self {
+      ProxyProfile::DnxhrLb => command
+        .codec_video("dnxhd")
+        .args(["-profile:v", "dnxhr_lb"])
+        .pix_fmt("yuv422p")
+        .codec_audio("pcm_s16le"),
+      ProxyProfile::ProresProxy => command
+        .codec_video("prores_ks")
+        .args(["-profile:v", "0"])
+        .pix_fmt("yuv422p10le")
+        .codec_audio("pcm_s16le"),
+      ProxyProfile::H264 => command
+        .codec_video("libx264")
+        .preset("veryfast")
+        .crf(28)
+        .args(["-g", "12"])
+        .pix_fmt("yuv420p")
+        .codec_audio("aac")
+        .args(["-b:a", "128k", "-movflags", "+faststart"]),
+    }
+  }
+}
+
+/// The standard location for a proxy: a `proxies` directory next to the
+/// source, named after the source file and profile.
+///
+/// ```rust
+/// use ffmpeg_sidecar::proxy::{proxy_path, ProxyProfile};
+/// use std::path::Path;
+///
+/// let path = proxy_path("footage/A001.mxf", ProxyProfile::ProresProxy);
+/// assert_eq!(path, Path::new("footage/proxies/A001_prores_proxy.mov"));
+/// ```
+pub fn proxy_path<P: AsRef<Path>>(input: P, profile: ProxyProfile) -> PathBuf {
+  let input = input.as_ref();
+  let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+  let file_name = format!("{stem}_{}.{}", profile.suffix(), profile.extension());
+  input
+    .parent()
+    .unwrap_or(Path::new(""))
+    .join("proxies")
+    .join(file_name)
+}
+
+/// Choose a proxy resolution for a source of the given size: half the width
+/// and height (a quarter of the pixels), limited to [`MAX_PROXY_HEIGHT`], with
+/// the aspect ratio preserved and both dimensions even.
+///
+/// ```rust
+/// use ffmpeg_sidecar::proxy::proxy_size;
+///
+/// assert_eq!(proxy_size(3840, 2160), (960, 540));
+/// assert_eq!(proxy_size(1920, 1080), (960, 540));
+/// assert_eq!(proxy_size(1280, 720), (640, 360));
+/// assert_eq!(proxy_size(1080, 1920), (304, 540));
+/// ```
+pub fn proxy_size(width: u32, height: u32) -> (u32, u32) {
+  let even = |value: f64| ((value / 2.0).round() as u32 * 2).max(2);
+  let target_height = (height / 2).min(MAX_PROXY_HEIGHT) as f64;
+  let target_width = target_height * width as f64 / height.max(1) as f64;
+  (even(target_width), even(target_height))
+}
+
+/// Transcode `input` into a proxy at [`proxy_path`], calling `on_progress`
+/// with the completed fraction (0.0 to 1.0) as encoding proceeds. Returns the
+/// path of the proxy. An existing proxy at that path is overwritten.
+///
+/// ```rust,no_run
+/// use ffmpeg_sidecar::proxy::{make_proxy, ProxyProfile};
+///
+/// let proxy = make_proxy("A001.mov", ProxyProfile::DnxhrLb, |progress| {
+///   println!("{:.0}%", progress * 100.0);
+/// })?;
+/// # anyhow::Ok(())
+/// ```
+pub fn make_proxy<P, F>(
+  input: P,
+  profile: ProxyProfile,
+  mut on_progress: F,
+) -> anyhow::Result<PathBuf>
+where
+  P: AsRef<Path>,
+  F: FnMut(f64),
+{
+  let input = input.as_ref();
+  let (width, height, duration) = probe(input)?;
+  let (proxy_width, proxy_height) = proxy_size(width, height);
+
+  let output = proxy_path(input, profile);
+  if let Some(parent) = output.parent() {
+    std::fs::create_dir_all(parent)?;
+  }
+
+  let mut command = FfmpegCommand::new();
+  command
+    .overwrite()
+    .input_path(input)
+    .map("0:v:0")
+    .map_optional("0:a")
+    .filter(format!("scale={proxy_width}:{proxy_height}"));
+  profile.apply(&mut command).output_path(&output);
+
+  let mut errors = Vec::new();
+  for event in command.spawn()?.into_events()? {
+    match event {
+      FfmpegEvent::Progress(progress) => {
+        if let (Some(time), Some(duration)) = (progress.time.as_secs_f64(), duration) {
+          on_progress((time / duration).clamp(0.0, 1.0));
+        }
+      }
+      FfmpegEvent::Log(LogLevel::Error, e) | FfmpegEvent::Error(e) => errors.push(e),
+      FfmpegEvent::Completed { exit_status, .. } => {
+        if !exit_status.is_some_and(|status| status.success()) {
+          bail!("Failed to create proxy: {}", errors.join("\n"));
+        }
+      }
+      _ => {}
+    }
+  }
+
+  on_progress(1.0);
+  Ok(output)
+}
+
+/// The size of the first video stream and the duration of the input.
+fn probe(input: &Path) -> anyhow::Result<(u32, u32, Option<f64>)> {
+  let (video, duration) = ffprobe_video(&file_arg(input), &[])?;
+  Ok((video.width, video.height, duration))
+}
@@ -0,0 +1,766 @@
+//! A screen and microphone recorder, with hardware encoding when available,
+//! pause and resume, and output split into segments.
+//!
+//! FFmpeg can't pause a capture, so each stretch between a start or resume
+//! and the next pause is a separate FFmpeg session, recorded to its own
+//! files. Progress, new files and errors are reported as [`RecorderEvent`]s
+//! for a UI to poll.
+
+use std::{
+  fs,
+  path::{Path, PathBuf},
+  sync::{
+    mpsc::{channel, Receiver, Sender, TryIter},
+    Arc, Mutex,
+  },
+  thread::JoinHandle,
+  time::Duration,
+};
+
+use anyhow::Context;
+
+use crate::{
+  child::FfmpegChild,
+  command::FfmpegCommand,
+  event::{FfmpegEvent, LogLevel},
+  gpu::Hw,
+  hw_transcode::{HwTranscode, HwUnavailable, TranscodePipeline, VideoCodec},
+  iter::FfmpegIterator,
+};
+
+/// How long FFmpeg is given to finish writing its files after being asked to
+/// stop, before it is killed.
+const STOP_GRACE: Duration = Duration::from_secs(5);
+
+/// The screen to capture, through the grabbing device of each platform.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScreenSource {
+  /// An X11 display through `x11grab`, e.g. `:0.0`.
+  X11 { display: String },
+  /// A screen through macOS' `avfoundation`, by name or index, e.g.
+  /// `Capture screen 0`.
+  AvFoundation { device: String },
+  /// The whole Windows desktop through `gdigrab`.
+  Gdi,
+}
+
+impl ScreenSource {
+  /// The main screen of the current platform: `$DISPLAY` on Linux.
+  pub fn platform_default() -> Self {
+    if cfg!(windows) {
+      ScreenSource::Gdi
+    } else if cfg!(target_os = "macos") {
+      ScreenSource::AvFoundation {
+        device: "Capture screen 0".to_string(),
+      }
+    } else {
+      ScreenSource::X11 {
+        display: std::env::var("DISPLAY").unwrap_or_else(|_| ":0.0".to_string()),
+      }
+    }
+  }
+
+  /// Input options and the `-i` capturing at `framerate`.
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::recorder::ScreenSource;
+  ///
+  /// assert_eq!(
+  ///   ScreenSource::Gdi.input_args(30),
+  ///   ["-f", "gdigrab", "-framerate", "30", "-i", "desktop"]
+  /// );
+  /// ```
+  pub fn input_args(&self, framerate: u32) -> Vec<String> {
+    let (format, input) = match self {
+      ScreenSource::X11 { display } => ("x11grab", display.clone()),
+      ScreenSource::AvFoundation { device } => ("avfoundation", format!("{device}:none")),
+      ScreenSource::Gdi => ("gdigrab", "desktop".to_string()),
+    };
+    let mut args = vec!["-f".to_string(), format.to_string()];
+    args.extend(["-framerate".to_string(), framerate.to_string()]);
+    if let ScreenSource::AvFoundation { .. } = self {
+      args.extend(["-capture_cursor".to_string(), "1".to_string()]);
+    }
+    args.extend(["-i".to_string(), input]);
+    args
+  }
+}
+
+/// The microphone to capture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MicSource {
+  /// A PulseAudio (or PipeWire) source on Linux, e.g. `default`.
+  Pulse { device: String },
+  /// An audio device through macOS' `avfoundation`, by name or index.
+  AvFoundation { device: String },
+  /// A DirectShow audio device on Windows, by name, e.g.
+  /// `Microphone (USB Audio Device)`.
+  DirectShow { device: String },
+}
+
+impl MicSource {
+  /// The default microphone of the current platform. DirectShow has no
+  /// default device, so on Windows the first audio device listed by FFmpeg
+  /// is used.
+  pub fn platform_default() -> anyhow::Result<Self> {
+    if cfg!(windows) {
+      return Ok(MicSource::DirectShow {
+        device: first_dshow_audio_device()?,
+      });
+    }
+    Ok(match cfg!(target_os = "macos") {
+      true => MicSource::AvFoundation {
+        device: "default".to_string(),
+      },
+      false => MicSource::Pulse {
+        device: "default".to_string(),
+      },
+    })
+  }
+
+  /// Input options and the `-i` capturing the microphone.
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::recorder::MicSource;
+  ///
+  /// let mic = MicSource::DirectShow { device: "Headset Microphone".to_string() };
+  /// assert_eq!(
+  ///   mic.input_args(),
+  ///   ["-f", "dshow", "-audio_buffer_size", "50", "-i", "audio=Headset Microphone"]
+  /// );
+  /// ```
+  pub fn input_args(&self) -> Vec<String> {
+    match self {
+      MicSource::Pulse { device } => vec!["-f".into(), "pulse".into(), "-i".into(), device.clone()],
+      MicSource::AvFoundation { device } => vec![
+        "-f".into(),
+        "avfoundation".into(),
+        "-i".into(),
+        format!(":{device}"),
+      ],
+      // Lowers the latency from DirectShow's default of 500ms
+      MicSource::DirectShow { device } => vec![
+        "-f".into(),
+        "dshow".into(),
+        "-audio_buffer_size".into(),
+        "50".into(),
+        "-i".into(),
+        format!("audio={device}"),
+      ],
+    }
+  }
+}
+
+/// The name of the first audio device in `ffmpeg -list_devices` for
+/// DirectShow, e.g. `Headset Microphone` from a line like
+/// `[dshow @ 000001c9babdb000] "Headset Microphone" (audio)`.
+fn first_dshow_audio_device() -> anyhow::Result<String> {
+  FfmpegCommand::new()
+    .hide_banner()
+    .args(["-list_devices", "true"])
+    .format("dshow")
+    .input("dummy")
+    .spawn()?
+    .iter()?
+    .into_ffmpeg_stderr()
+    .find(|line| line.contains("(audio)"))
+    .context("No audio device found")?
+    .split('"')
+    .nth(1)
+    .map(str::to_string)
+    .context("Failed to parse audio device")
+}
+
+/// What a [`Recording`] reports as it runs.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum RecorderEvent {
+  /// The video encoder, chosen when the recording starts. `fallback` is why
+  /// the requested hardware encoder couldn't be used, if it couldn't.
+  EncoderSelected {
+    encoder: String,
+    fallback: Option<HwUnavailable>,
+  },
+  Started,
+  Paused,
+  Resumed,
+  /// The time recorded so far, over every session.
+  Progress {
+    recorded: Duration,
+    fps: f32,
+    size_kb: u32,
+  },
+  /// FFmpeg began writing a new file.
+  FileStarted(PathBuf),
+  /// FFmpeg finished writing a file, because a new segment began or the
+  /// session ended.
+  FileFinished(PathBuf),
+  /// An error logged by FFmpeg.
+  Error(String),
+  /// The recording was stopped; `files` lists every file written, in order.
+  Stopped {
+    files: Vec<PathBuf>,
+    recorded: Duration,
+  },
+}
+
+/// Settings for a screen and microphone recording, started with
+/// [`start`](Self::start).
+///
+/// ```rust,no_run
+/// use ffmpeg_sidecar::{
+///   gpu::Hw,
+///   recorder::{MicSource, Recorder, RecorderEvent},
+/// };
+/// use std::time::Duration;
+///
+/// let mut recording = Recorder::new("recordings")
+///   .mic(MicSource::platform_default()?)
+///   .hardware(Hw::Nvidia)
+///   .segment_time(Duration::from_secs(60))
+///   .start()?;
+///
+/// std::thread::sleep(Duration::from_secs(5));
+/// recording.pause()?;
+/// std::thread::sleep(Duration::from_secs(2));
+/// recording.resume()?;
+/// std::thread::sleep(Duration::from_secs(5));
+/// let files = recording.stop()?;
+///
+/// for event in recording.events() {
+///   if let RecorderEvent::Error(e) = event {
+///     eprintln!("{e}");
+///   }
+/// }
+/// println!("Recorded {files:?}");
+/// # anyhow::Ok(())
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recorder {
+  /// The directory the files are written to, created if it doesn't exist.
+  pub dir: PathBuf,
+  /// The start of every file name, followed by the session and segment
+  /// numbers.
+  pub prefix: String,
+  /// The screen to capture, or `None` to record audio only.
+  pub screen: Option<ScreenSource>,
+  /// The microphone to capture, or `None` to record no audio.
+  pub mic: Option<MicSource>,
+  /// The frame rate the screen is captured at.
+  pub framerate: u32,
+  /// The video codec, encoded in software unless `hardware` is set.
+  pub codec: VideoCodec,
+  /// The GPU to encode on, if available.
+  pub hardware: Option<Hw>,
+  /// The size the screen is scaled to, or `None` to keep its resolution.
+  pub size: Option<(u32, u32)>,
+  /// The length of each segment; without it, each session is one file.
+  pub segment_time: Option<Duration>,
+  /// The file extension, which selects the container.
+  pub container: String,
+}
+
+impl Recorder {
+  /// Record the main screen at 30 fps to `dir`, as H.264 in Matroska files,
+  /// which stay playable if FFmpeg has to be killed.
+  pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+    Self {
+      dir: dir.as_ref().to_path_buf(),
+      prefix: "recording".to_string(),
+      screen: Some(ScreenSource::platform_default()),
+      mic: None,
+      framerate: 30,
+      codec: VideoCodec::H264,
+      hardware: None,
+      size: None,
+      segment_time: None,
+      container: "mkv".to_string(),
+    }
+  }
+
+  /// Start every file name with `prefix` instead of `recording`.
+  pub fn prefix<S: Into<String>>(mut self, prefix: S) -> Self {
+    self.prefix = prefix.into();
+    self
+  }
+
+  /// Capture `screen` instead of the main screen.
+  pub fn screen(mut self, screen: ScreenSource) -> Self {
+    self.screen = Some(screen);
+    self
+  }
+
+  /// Record audio only.
+  pub fn no_screen(mut self) -> Self {
+    self.screen = None;
+    self
+  }
+
+  /// Also record audio from `mic`.
+  pub fn mic(mut self, mic: MicSource) -> Self {
+    self.mic = Some(mic);
+    self
+  }
+
+  /// Capture the screen at `framerate` fps instead of 30.
+  pub fn framerate(mut self, framerate: u32) -> Self {
+    self.framerate = framerate;
+    self
+  }
+
+  /// Encode the video as `codec` instead of H.264.
+  pub fn codec(mut self, codec: VideoCodec) -> Self {
+    self.codec = codec;
+    self
+  }
+
+  /// Encode on the first GPU of this kind, falling back to software if it
+  /// isn't available; see [`HwTranscode::or_software`].
+  pub fn hardware(mut self, hw: Hw) -> Self {
+    self.hardware = Some(hw);
+    self
+  }
+
+  /// Scale the captured screen to `width`x`height`.
+  pub fn size(mut self, width: u32, height: u32) -> Self {
+    self.size = Some((width, height));
+    self
+  }
+
+  /// Split each session into files of `segment_time` each.
+  pub fn segment_time(mut self, segment_time: Duration) -> Self {
+    self.segment_time = Some(segment_time);
+    self
+  }
+
+  /// Write files with `extension`, e.g. `mp4`, instead of `mkv`.
+  pub fn container<S: Into<String>>(mut self, extension: S) -> Self {
+    self.container = extension.into();
+    self
+  }
+
+  /// Choose the encoder and start recording the first session.
+  pub fn start(self) -> anyhow::Result<Recording> {
+    anyhow::ensure!(
+      self.screen.is_some() || self.mic.is_some(),
+      "Nothing to record: no screen or microphone"
+    );
+    fs::create_dir_all(&self.dir)
+      .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+
+    let (tx, rx) = channel();
+    let pipeline = self.hardware.map(|hw| {
+      let mut preset = HwTranscode::new(hw, self.codec);
+      preset.size = self.size;
+      preset.or_software()
+    });
+    if self.screen.is_some() {
+      let (encoder, fallback) = match &pipeline {
+        Some(TranscodePipeline::Hardware(preset)) => (preset.encoder(), None),
+        Some(TranscodePipeline::Software { reason, .. }) => (
+          software_encoder(self.codec).to_string(),
+          Some(reason.clone()),
+        ),
+        None => (software_encoder(self.codec).to_string(), None),
+      };
+      tx.send(RecorderEvent::EncoderSelected { encoder, fallback })
+        .ok();
+    }
+
+    let mut recording = Recording {
+      recorder: self,
+      pipeline,
+      session: None,
+      sessions: 0,
+      recorded: Duration::ZERO,
+      files: Arc::new(Mutex::new(Vec::new())),
+      stopped: false,
+      tx,
+      rx,
+    };
+    recording.start_session()?;
+    recording.tx.send(RecorderEvent::Started).ok();
+    Ok(recording)
+  }
+
+  /// The command which records session number `session` with `pipeline`, or
+  /// the software encoder if `None`.
+  fn command(&self, session: usize, pipeline: Option<&TranscodePipeline>) -> FfmpegCommand {
+    let mut command = FfmpegCommand::new();
+    command.hide_banner().expect_no_output();
+    if let Some(TranscodePipeline::Hardware(preset)) = pipeline {
+      if preset.device.hw == Hw::Vaapi {
+        command.args(["-vaapi_device", &preset.device.device_name()]);
+      }
+    }
+    let mut next_input = 0;
+    let mut map = Vec::new();
+    if let Some(screen) = &self.screen {
+      command.args(screen.input_args(self.framerate));
+      map.push(format!("{next_input}:v"));
+      next_input += 1;
+    }
+    if let Some(mic) = &self.mic {
+      command.args(mic.input_args());
+      map.push(format!("{next_input}:a"));
+    }
+    for map in &map {
+      command.map(map);
+    }
+
+    if self.screen.is_some() {
+      command.args(self.video_args(pipeline));
+    }
+    if self.mic.is_some() {
+      command.codec_audio("aac").args(["-b:a", "160k"]);
+    }
+
+    let name = format!("{}_{session:03}", self.prefix);
+    match self.segment_time {
+      Some(segment_time) => command
+        .format("segment")
+        .args([
+          "-segment_time",
+          &format!("{:.3}", segment_time.as_secs_f64()),
+        ])
+        .args(["-reset_timestamps", "1"])
+        .output_path(self.dir.join(format!("{name}_%03d.{}", self.container))),
+      None => command.output_path(self.file(session)),
+    };
+    command
+  }
+
+  /// The output file of session number `session`, when not segmented.
+  fn file(&self, session: usize) -> PathBuf {
+    let name = format!("{}_{session:03}.{}", self.prefix, self.container);
+    self.dir.join(name)
+  }
+
+  /// Output options which scale and encode the screen capture. Captured
+  /// frames are in system memory, so VA-API needs them uploaded first.
+  fn video_args(&self, pipeline: Option<&TranscodePipeline>) -> Vec<String> {
+    let mut args = Vec::new();
+    let preset = match pipeline {
+      Some(TranscodePipeline::Hardware(preset)) => preset,
+      _ => {
+        if let Some((width, height)) = self.size {
+          args.extend(["-filter:v".into(), format!("scale={width}:{height}")]);
+        }
+        let encoder = software_encoder(self.codec);
+        args.extend([
+          "-c:v".into(),
+          encoder.into(),
+          "-preset".into(),
+          "veryfast".into(),
+        ]);
+        args.extend(["-pix_fmt".into(), "yuv420p".into()]);
+        return args;
+      }
+    };
+    let filter = match (preset.device.hw, self.size) {
+      (Hw::Vaapi, None) => Some("format=nv12,hwupload".to_string()),
+      (Hw::Vaapi, Some((width, height))) => Some(format!(
+        "format=nv12,hwupload,scale_vaapi=w={width}:h={height}"
+      )),
+      (_, Some((width, height))) => Some(format!("scale={width}:{height}")),
+      (_, None) => None,
+    };
+    if let Some(filter) = filter {
+      args.extend(["-filter:v".into(), filter]);
+    }
+    if preset.device.hw != Hw::Vaapi {
+      args.extend(["-pix_fmt".into(), "nv12".into()]);
+    }
+    args.extend(["-c:v".into(), preset.encoder()]);
+    args.extend(preset.device.encode_args());
+    args
+  }
+}
+
+fn software_encoder(codec: VideoCodec) -> &'static str {
+  match codec {
+    VideoCodec::H264 => "libx264",
+    VideoCodec::Hevc => "libx265",
+  }
+}
+
+/// A running FFmpeg session and the thread forwarding its events, which
+/// returns the time it recorded.
+struct Session {
+  child: FfmpegChild,
+  events: JoinHandle<Duration>,
+}
+
+/// A recording started by [`Recorder::start`]. Dropping it stops the
+/// recording.
+pub struct Recording {
+  recorder: Recorder,
+  pipeline: Option<TranscodePipeline>,
+  /// `None` while paused or stopped.
+  session: Option<Session>,
+  sessions: usize,
+  /// The time recorded by the sessions which have ended.
+  recorded: Duration,
+  files: Arc<Mutex<Vec<PathBuf>>>,
+  stopped: bool,
+  tx: Sender<RecorderEvent>,
+  rx: Receiver<RecorderEvent>,
+}
+
+impl Recording {
+  /// The events reported since the last call, without blocking.
+  pub fn events(&self) -> TryIter<'_, RecorderEvent> {
+    self.rx.try_iter()
+  }
+
+  pub fn is_paused(&self) -> bool {
+    self.session.is_none() && !self.stopped
+  }
+
+  /// The time recorded before the current session, i.e. all of it while
+  /// paused. `RecorderEvent::Progress` reports it as the recording runs.
+  pub fn recorded(&self) -> Duration {
+    self.recorded
+  }
+
+  /// End the current session, keeping its files. Does nothing if already
+  /// paused.
+  pub fn pause(&mut self) -> anyhow::Result<()> {
+    anyhow::ensure!(!self.stopped, "The recording has stopped");
+    if self.session.is_some() {
+      self.end_session()?;
+      self.tx.send(RecorderEvent::Paused).ok();
+    }
+    Ok(())
+  }
+
+  /// Start a new session, recording to new files. Does nothing if not
+  /// paused.
+  pub fn resume(&mut self) -> anyhow::Result<()> {
+    anyhow::ensure!(!self.stopped, "The recording has stopped");
+    if self.session.is_none() {
+      self.start_session()?;
+      self.tx.send(RecorderEvent::Resumed).ok();
+    }
+    Ok(())
+  }
+
+  /// End the recording, returning every file written, in order. The final
+  /// events remain available from [`events`](Self::events).
+  pub fn stop(&mut self) -> anyhow::Result<Vec<PathBuf>> {
+    if !self.stopped {
+      self.stopped = true;
+      if self.session.is_some() {
+        self.end_session()?;
+      }
+      self
+        .tx
+        .send(RecorderEvent::Stopped {
+          files: self.files(),
+          recorded: self.recorded,
+        })
+        .ok();
+    }
+    Ok(self.files())
+  }
+
+  /// Every file written so far, in order.
+  pub fn files(&self) -> Vec<PathBuf> {
+    self
+      .files
+      .lock()
+      .map(|files| files.clone())
+      .unwrap_or_default()
+  }
+
+  fn start_session(&mut self) -> anyhow::Result<()> {
+    let session = self.sessions;
+    let mut child = self
+      .recorder
+      .command(session, self.pipeline.as_ref())
+      .spawn()
+      .context("Failed to spawn FFmpeg")?;
+    let iter = child.iter()?;
+    self.sessions += 1;
+
+    // A segmented session's files are named in its log as they're opened
+    let file = match self.recorder.segment_time {
+      Some(_) => None,
+      None => Some(self.recorder.file(session)),
+    };
+    let events = spawn_event_thread(
+      iter,
+      file,
+      self.recorded,
+      self.files.clone(),
+      self.tx.clone(),
+    );
+    self.session = Some(Session { child, events });
+    Ok(())
+  }
+
+  fn end_session(&mut self) -> anyhow::Result<()> {
+    let Some(mut session) = self.session.take() else {
+      return Ok(());
+    };
+    let status = session.child.quit_or_kill(STOP_GRACE)?;
+    let recorded = session.events.join().unwrap_or_default();
+    self.recorded += recorded;
+    if !status.success() {
+      self
+        .tx
+        .send(RecorderEvent::Error(format!("FFmpeg exited with {status}")))
+        .ok();
+    }
+    Ok(())
+  }
+}
+
+impl Drop for Recording {
+  fn drop(&mut self) {
+    self.end_session().ok();
+  }
+}
+
+/// Forward the events of one session as `RecorderEvent`s, with its progress
+/// offset by the time recorded before it. `file` is the session's output if
+/// it isn't segmented.
+fn spawn_event_thread(
+  iter: FfmpegIterator,
+  file: Option<PathBuf>,
+  offset: Duration,
+  files: Arc<Mutex<Vec<PathBuf>>>,
+  tx: Sender<RecorderEvent>,
+) -> JoinHandle<Duration> {
+  std::thread::spawn(move || {
+    let mut current = None;
+    let start_file = |path: PathBuf, current: &mut Option<PathBuf>| {
+      if let Some(previous) = current.replace(path.clone()) {
+        tx.send(RecorderEvent::FileFinished(previous)).ok();
+      }
+      if let Ok(mut files) = files.lock() {
+        files.push(path.clone());
+      }
+      tx.send(RecorderEvent::FileStarted(path)).ok();
+    };
+    if let Some(file) = file {
+      start_file(file, &mut current);
+    }
+
+    let mut recorded = Duration::ZERO;
+    for event in iter {
+      match event {
+        FfmpegEvent::Progress(progress) => {
+          if let Some(elapsed) = progress.elapsed() {
+            recorded = elapsed;
+          }
+          tx.send(RecorderEvent::Progress {
+            recorded: offset + recorded,
+            fps: progress.fps,
+            size_kb: progress.size_kb,
+          })
+          .ok();
+        }
+        FfmpegEvent::Log(LogLevel::Error | LogLevel::Fatal, line) | FfmpegEvent::Error(line) => {
+          tx.send(RecorderEvent::Error(line)).ok();
+        }
+        FfmpegEvent::Log(_, line) => {
+          if let Some(path) = parse_opened_file(&line) {
+            start_file(path, &mut current);
+          }
+        }
+        _ => {}
+      }
+    }
+    if let Some(last) = current {
+      tx.send(RecorderEvent::FileFinished(last)).ok();
+    }
+    recorded
+  })
+}
+
+/// The file in a line like `[segment @ 0x55d8c2a4e880] Opening
+/// 'recording_000_001.mkv' for writing`, logged as each segment begins.
+fn parse_opened_file(line: &str) -> Option<PathBuf> {
+  let (_, rest) = line.split_once("Opening '")?;
+  let path = rest.strip_suffix("' for writing")?;
+  Some(PathBuf::from(path))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn joined_args(command: &FfmpegCommand) -> String {
+    command
+      .get_args()
+      .map(|arg| arg.to_string_lossy().to_string())
+      .collect::<Vec<_>>()
+      .join(" ")
+  }
+
+  #[test]
+  fn test_command() {
+    let recorder = Recorder::new("out")
+      .screen(ScreenSource::X11 {
+        display: ":1".to_string(),
+      })
+      .mic(MicSource::Pulse {
+        device: "default".to_string(),
+      })
+      .size(1280, 720)
+      .segment_time(Duration::from_secs(60));
+    let args = joined_args(&recorder.command(2, None));
+    assert!(args.contains("-f x11grab -framerate 30 -i :1 -f pulse -i default -map 0:v -map 1:a"));
+    assert!(args.contains("-filter:v scale=1280:720 -c:v libx264"));
+    assert!(args.contains("-c:a aac"));
+    assert!(args.contains("-f segment -segment_time 60.000"));
+    let pattern = Path::new("out").join("recording_002_%03d.mkv");
+    assert!(args.ends_with(pattern.to_str().unwrap()));
+
+    let pipeline = TranscodePipeline::Hardware(HwTranscode::new(Hw::Vaapi, VideoCodec::Hevc));
+    let args = joined_args(&recorder.no_screen().command(0, Some(&pipeline)));
+    assert!(!args.contains("x11grab"));
+    assert!(!args.contains("-c:v"));
+    assert!(args.contains("-map 0:a"));
+  }
+
+  #[test]
+  fn test_video_args() {
+    let recorder = Recorder::new("out").size(1280, 720);
+    let pipeline = TranscodePipeline::Hardware(HwTranscode::new(Hw::Vaapi, VideoCodec::Hevc));
+    assert_eq!(
+      recorder.video_args(Some(&pipeline)),
+      [
+        "-filter:v",
+        "format=nv12,hwupload,scale_vaapi=w=1280:h=720",
+        "-c:v",
+        "hevc_vaapi"
+      ]
+    );
+    let pipeline = TranscodePipeline::Hardware(HwTranscode::new(Hw::Nvidia, VideoCodec::H264));
+    assert_eq!(
+      recorder.video_args(Some(&pipeline)),
+      [
+        "-filter:v",
+        "scale=1280:720",
+        "-pix_fmt",
+        "nv12",
+        "-c:v",
+        "h264_nvenc",
+        "-gpu",
+        "0"
+      ]
+    );
+  }
+
+  #[test]
+  fn test_parse_opened_file() {
+    let line = "[segment @ 0x55d8c2a4e880] Opening 'out/recording_000_001.mkv' for writing";
+    assert_eq!(
+      parse_opened_file(line),
+      Some(PathBuf::from("out/recording_000_001.mkv"))
+    );
+    assert_eq!(parse_opened_file("Opening 'in.mp4' for reading"), None);
+  }
+}
@@ -0,0 +1,88 @@
+//! Unix resource limits (`setrlimit`) applied to the FFmpeg child process.
+
+use std::{io, os::unix::process::CommandExt, process::Command, time::Duration};
+
+/// Hard caps on the resources a single FFmpeg process may consume, enforced by
+/// the kernel. A process which exceeds them is killed (CPU time), fails to
+/// allocate (memory) or fails to write (file size), and surfaces as a failed
+/// exit status and/or `FfmpegEvent::Error` log lines.
+///
+/// ```rust
+/// use ffmpeg_sidecar::resource_limits::ResourceLimits;
+/// use std::time::Duration;
+///
+/// let limits = ResourceLimits::default()
+///   .cpu_time(Duration::from_secs(600))
+///   .memory(2 * 1024 * 1024 * 1024);
+/// assert_eq!(limits.file_size_bytes, None);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceLimits {
+  /// `RLIMIT_CPU`, rounded up to whole seconds.
+  pub cpu_time: Option<Duration>,
+  /// `RLIMIT_AS`, the size of the virtual address space in bytes.
+  pub memory_bytes: Option<u64>,
+  /// `RLIMIT_FSIZE`, the largest file the process may write in bytes.
+  pub file_size_bytes: Option<u64>,
+}
+
+impl ResourceLimits {
+  pub fn cpu_time(mut self, limit: Duration) -> Self {
+    self.cpu_time = Some(limit);
+    self
+  }
+
+  pub fn memory(mut self, bytes: u64) -> Self {
+    self.memory_bytes = Some(bytes);
+    self
+  }
+
+  pub fn file_size(mut self, bytes: u64) -> Self {
+    self.file_size_bytes = Some(bytes);
+    self
+  }
+
+  /// The limits as `(resource, value)` pairs for `setrlimit`.
+  fn rlimits(&self) -> Vec<(libc::c_int, libc::rlim_t)> {
+    let cpu_seconds = self
+      .cpu_time
+      .map(|limit| limit.as_secs() + u64::from(limit.subsec_nanos() > 0));
+    [
+      (libc::RLIMIT_CPU as libc::c_int, cpu_seconds),
+      (libc::RLIMIT_AS as libc::c_int, self.memory_bytes),
+      (libc::RLIMIT_FSIZE as libc::c_int, self.file_size_bytes),
+    ]
+    .into_iter()
+    .filter_map(|(resource, limit)| Some((resource, limit? as libc::rlim_t)))
+    .collect()
+  }
+
+  /// Register a `pre_exec` hook on `command` which applies the limits in the
+  /// child, after forking and before FFmpeg starts.
+  pub(crate) fn apply(&self, command: &mut Command) {
+    let rlimits = self.rlimits();
+    if rlimits.is_empty() {
+      return;
+    }
+    let hook = move || {
+      for (resource, limit) in &rlimits {
+        let rlimit = libc::rlimit {
+          rlim_cur: *limit,
+          rlim_max: *limit,
+        };
+        // SAFETY: `setrlimit` is async-signal-safe and `rlimit` is a valid
+        // pointer for the duration of the call. The resource type differs
+        // between libc targets, hence the inferred cast.
+        if unsafe { libc::setrlimit(*resource as _, &rlimit) } != 0 {
+          return Err(io::Error::last_os_error());
+        }
+      }
+      Ok(())
+    };
+    // SAFETY: the hook only calls `setrlimit`, without allocating or taking
+    // locks, so it is safe to run between `fork` and `exec`.
+    unsafe {
+      command.pre_exec(hook);
+    }
+  }
+}
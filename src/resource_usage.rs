@@ -0,0 +1,208 @@
+//! Periodic sampling of the FFmpeg child process' CPU and memory usage.
+
+use std::{
+  sync::mpsc::SyncSender,
+  thread::JoinHandle,
+  time::{Duration, Instant},
+};
+
+use crate::event::FfmpegEvent;
+
+/// A single reading of a process' cumulative CPU time and resident memory.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Sample {
+  cpu_time: Duration,
+  rss_bytes: u64,
+}
+
+/// Spawn a thread which polls the resource usage of process `pid` every
+/// `interval`, emitting `FfmpegEvent::ResourceUsage` events. The thread exits
+/// when the process terminates or the receiving end of `tx` is dropped.
+pub fn spawn_resource_sampler(
+  pid: u32,
+  interval: Duration,
+  tx: SyncSender<FfmpegEvent>,
+) -> JoinHandle<()> {
+  std::thread::spawn(move || {
+    let Some(mut previous) = sample(pid) else {
+      if cfg!(not(any(target_os = "linux", target_os = "macos", windows))) {
+        tx.send(FfmpegEvent::Error(
+          "Resource usage sampling is not supported on this platform".to_string(),
+        ))
+        .ok();
+      }
+      return;
+    };
+    let mut previous_instant = Instant::now();
+
+    loop {
+      std::thread::sleep(interval);
+      let Some(current) = sample(pid) else {
+        break;
+      };
+      let now = Instant::now();
+
+      let elapsed = now.duration_since(previous_instant).as_secs_f32();
+      let cpu_delta = current.cpu_time.saturating_sub(previous.cpu_time);
+      let cpu_percent = match elapsed > 0.0 {
+        true => cpu_delta.as_secs_f32() / elapsed * 100.0,
+        false => 0.0,
+      };
+
+      let event = FfmpegEvent::ResourceUsage {
+        cpu_percent,
+        rss_bytes: current.rss_bytes,
+      };
+      if tx.send(event).is_err() {
+        break;
+      }
+
+      previous = current;
+      previous_instant = now;
+    }
+  })
+}
+
+/// Read `/proc/<pid>/stat` for CPU time and `/proc/<pid>/status` for RSS.
+/// Returns `None` once the process has exited (including zombie state).
+#[cfg(target_os = "linux")]
+fn sample(pid: u32) -> Option<Sample> {
+  // SAFETY: `sysconf` has no preconditions
+  let ticks_per_sec = match unsafe { libc::sysconf(libc::_SC_CLK_TCK) } {
+    ticks if ticks > 0 => ticks as u64,
+    _ => 100,
+  };
+
+  let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+  // The command name in field 2 may contain spaces; skip past its closing paren
+  let mut fields = stat.rsplit_once(')')?.1.split_whitespace();
+  let state = fields.next()?;
+  if state == "Z" || state == "X" {
+    return None;
+  }
+  // utime and stime are fields 14 and 15, i.e. 11 and 12 after the state
+  let mut fields = fields.skip(10);
+  let utime = fields.next()?.parse::<u64>().ok()?;
+  let stime = fields.next()?.parse::<u64>().ok()?;
+  let ticks = utime + stime;
+  let cpu_time = Duration::from_millis(ticks * 1000 / ticks_per_sec);
+
+  let status = std::fs::read_to_string(format!("/proc/{pid}/status")).ok()?;
+  let rss_kb = status
+    .lines()
+    .find_map(|line| line.strip_prefix("VmRSS:"))
+    .and_then(|rest| rest.split_whitespace().next())
+    .and_then(|kb| kb.parse::<u64>().ok())
+    .unwrap_or(0);
+
+  Some(Sample {
+    cpu_time,
+    rss_bytes: rss_kb * 1024,
+  })
+}
+
+/// Query the task info of the process with `proc_pidinfo`, which fails once
+/// the process has exited, including as a zombie.
+#[cfg(target_os = "macos")]
+fn sample(pid: u32) -> Option<Sample> {
+  let mut info = std::mem::MaybeUninit::<libc::proc_taskinfo>::uninit();
+  let size = std::mem::size_of::<libc::proc_taskinfo>() as libc::c_int;
+  // SAFETY: the buffer is valid for writes of `size` bytes
+  let written = unsafe {
+    libc::proc_pidinfo(
+      pid as libc::c_int,
+      libc::PROC_PIDTASKINFO,
+      0,
+      info.as_mut_ptr().cast(),
+      size,
+    )
+  };
+  if written != size {
+    return None;
+  }
+  // SAFETY: `proc_pidinfo` filled in the whole struct
+  let info = unsafe { info.assume_init() };
+
+  // CPU times are in Mach absolute time units, which are only nanoseconds on
+  // Intel Macs
+  #[allow(deprecated)]
+  let mut timebase = libc::mach_timebase_info { numer: 0, denom: 0 };
+  // SAFETY: `timebase` is valid for writes
+  #[allow(deprecated)]
+  let status = unsafe { libc::mach_timebase_info(&mut timebase) };
+  let (numer, denom) = match status == 0 && timebase.denom > 0 {
+    true => (timebase.numer as u128, timebase.denom as u128),
+    false => (1, 1),
+  };
+  let ticks = info.pti_total_user as u128 + info.pti_total_system as u128;
+  let nanos = (ticks * numer / denom) as u64;
+
+  Some(Sample {
+    cpu_time: Duration::from_nanos(nanos),
+    rss_bytes: info.pti_resident_size,
+  })
+}
+
+/// Query the process' CPU times with `GetProcessTimes` and its working set
+/// with `GetProcessMemoryInfo`. Returns `None` once the process has exited.
+#[cfg(windows)]
+fn sample(pid: u32) -> Option<Sample> {
+  use winapi::{
+    shared::minwindef::{DWORD, FALSE, FILETIME},
+    um::{
+      handleapi::CloseHandle,
+      minwinbase::STILL_ACTIVE,
+      processthreadsapi::{GetExitCodeProcess, GetProcessTimes, OpenProcess},
+      psapi::{GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS},
+      winnt::{PROCESS_QUERY_LIMITED_INFORMATION, PROCESS_VM_READ},
+    },
+  };
+
+  // SAFETY: the handle is checked for null and closed before returning, and
+  // every out-pointer is valid for writes
+  unsafe {
+    let handle = OpenProcess(
+      PROCESS_QUERY_LIMITED_INFORMATION | PROCESS_VM_READ,
+      FALSE,
+      pid,
+    );
+    if handle.is_null() {
+      return None;
+    }
+    let sample = (|| {
+      let mut exit_code: DWORD = 0;
+      if GetExitCodeProcess(handle, &mut exit_code) == 0 || exit_code != STILL_ACTIVE {
+        return None;
+      }
+      let mut creation: FILETIME = std::mem::zeroed();
+      let mut exit: FILETIME = std::mem::zeroed();
+      let mut kernel: FILETIME = std::mem::zeroed();
+      let mut user: FILETIME = std::mem::zeroed();
+      if GetProcessTimes(handle, &mut creation, &mut exit, &mut kernel, &mut user) == 0 {
+        return None;
+      }
+      // FILETIMEs count 100ns intervals
+      let hundred_nanos =
+        |time: &FILETIME| ((time.dwHighDateTime as u64) << 32) | time.dwLowDateTime as u64;
+      let cpu_time = Duration::from_nanos((hundred_nanos(&kernel) + hundred_nanos(&user)) * 100);
+
+      let mut counters: PROCESS_MEMORY_COUNTERS = std::mem::zeroed();
+      let size = std::mem::size_of::<PROCESS_MEMORY_COUNTERS>() as DWORD;
+      let rss_bytes = match GetProcessMemoryInfo(handle, &mut counters, size) {
+        0 => 0,
+        _ => counters.WorkingSetSize as u64,
+      };
+      Some(Sample {
+        cpu_time,
+        rss_bytes,
+      })
+    })();
+    CloseHandle(handle);
+    sample
+  }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn sample(_pid: u32) -> Option<Sample> {
+  None
+}
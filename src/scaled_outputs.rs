@@ -0,0 +1,94 @@
+//! The same video at several resolutions at once, e.g. full resolution for
+//! recording alongside a thumbnail for an ML model, decoded only once.
+
+/// One resolution of a [`FfmpegCommand::scaled_outputs`] command.
+///
+/// [`FfmpegCommand::scaled_outputs`]: crate::command::FfmpegCommand::scaled_outputs
+///
+/// ```rust
+/// use ffmpeg_sidecar::scaled_outputs::{scaled_outputs_filter, ScaledOutput};
+///
+/// let outputs = [
+///   ScaledOutput::new("full", 1920, 1080),
+///   ScaledOutput::new("thumb", 224, 224),
+/// ];
+/// assert_eq!(
+///   scaled_outputs_filter("0:v", &outputs),
+///   "[0:v]split=2[scaled_in0][scaled_in1];\
+///    [scaled_in0]scale=1920:1080[scaled0];\
+///    [scaled_in1]scale=224:224[scaled1]"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScaledOutput {
+  /// Copied into `OutputVideoFrame::output_tag` for frames of this output
+  /// read from stdout. Outputs written elsewhere with [`to`](Self::to) are
+  /// read by the caller, so their frames carry no tag.
+  pub tag: String,
+  /// The width to scale to, in pixels.
+  pub width: u32,
+  /// The height to scale to, in pixels.
+  pub height: u32,
+  /// The raw pixel format of the output. Defaults to `rgb24`.
+  pub pix_fmt: String,
+  /// Defaults to stdout (`-`), where frames are read by the iterator.
+  /// Anything else, such as a named pipe or a `TcpOutput` URL, is read by the
+  /// caller.
+  pub url: String,
+}
+
+impl ScaledOutput {
+  /// An output scaled to `width`x`height`, written to stdout as `rgb24`.
+  pub fn new<S: Into<String>>(tag: S, width: u32, height: u32) -> Self {
+    Self {
+      tag: tag.into(),
+      width,
+      height,
+      pix_fmt: "rgb24".to_string(),
+      url: "-".to_string(),
+    }
+  }
+
+  /// Write raw frames in `pix_fmt` instead of `rgb24`.
+  pub fn pix_fmt<S: Into<String>>(mut self, pix_fmt: S) -> Self {
+    self.pix_fmt = pix_fmt.into();
+    self
+  }
+
+  /// Write this output somewhere other than stdout. It isn't read by the
+  /// iterator, so [`tag`](Self::tag) doesn't apply to it.
+  pub fn to<S: Into<String>>(mut self, url: S) -> Self {
+    self.url = url.into();
+    self
+  }
+
+  /// The output label of output `index` in [`scaled_outputs_filter`].
+  pub fn label(index: usize) -> String {
+    format!("[scaled{index}]")
+  }
+}
+
+/// A `-filter_complex` graph which splits the video stream `source` (e.g.
+/// `0:v`) and scales one copy for each output, labeled
+/// [`ScaledOutput::label`] in order.
+///
+/// # Panics
+///
+/// If `outputs` is empty, since FFmpeg rejects a `split` with no outputs.
+pub fn scaled_outputs_filter(source: &str, outputs: &[ScaledOutput]) -> String {
+  assert!(!outputs.is_empty(), "no outputs to scale");
+  let mut split = format!("[{source}]split={}", outputs.len());
+  for i in 0..outputs.len() {
+    split.push_str(&format!("[scaled_in{i}]"));
+  }
+  let mut chains = vec![split];
+  chains.extend(outputs.iter().enumerate().map(|(i, output)| {
+    format!(
+      "[scaled_in{i}]scale={}:{}{}",
+      output.width,
+      output.height,
+      ScaledOutput::label(i)
+    )
+  }));
+  chains.join(";")
+}
@@ -0,0 +1,253 @@
+//! Experimental shared-memory transport for high-bandwidth rawvideo output.
+//!
+//! Instead of a pipe, FFmpeg writes frames to a file on `/dev/shm` (tmpfs),
+//! and the reader memory-maps each frame as soon as it has been fully written.
+//! Frames are exposed as slices of the mapping, without copying through a pipe
+//! buffer. Consumed pages are released with `FALLOC_FL_PUNCH_HOLE`, so memory
+//! use stays bounded to roughly the unread frames even though the file's
+//! logical size keeps growing.
+
+use std::{
+  fs::{File, OpenOptions},
+  io,
+  ops::Deref,
+  os::fd::AsRawFd,
+  path::{Path, PathBuf},
+  time::Duration,
+};
+
+use crate::child::FfmpegChild;
+
+/// How often [`ShmFrameReader::next_frame`] checks for newly written data.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+fn page_size() -> u64 {
+  // SAFETY: `sysconf` has no preconditions.
+  unsafe { libc::sysconf(libc::_SC_PAGESIZE) as u64 }
+}
+
+/// Reads fixed-size rawvideo frames which FFmpeg writes to a shared-memory
+/// file.
+///
+/// ```rust,no_run
+/// use ffmpeg_sidecar::{command::FfmpegCommand, shm_transport::ShmFrameReader};
+///
+/// let frame_size = 3840 * 2160 * 3;
+/// let mut reader = ShmFrameReader::create("4k60", frame_size)?;
+/// let mut child = FfmpegCommand::new()
+///   .format("lavfi")
+///   .input("testsrc=size=3840x2160:rate=60:duration=5")
+///   .format("rawvideo")
+///   .pix_fmt("rgb24")
+///   .shm_output(&reader)
+///   .spawn()?;
+///
+/// while let Some(frame) = reader.next_frame(&mut child)? {
+///   assert_eq!(frame.len(), frame_size);
+/// }
+/// # anyhow::Ok(())
+/// ```
+#[derive(Debug)]
+pub struct ShmFrameReader {
+  path: PathBuf,
+  file: File,
+  frame_size: usize,
+  /// Byte offset of the next unread frame.
+  offset: u64,
+  /// Everything before this offset has been released.
+  released: u64,
+  page_size: u64,
+}
+
+impl ShmFrameReader {
+  /// Create (or truncate) `/dev/shm/ffmpeg-sidecar-{name}` for frames of
+  /// `frame_size` bytes, e.g. `width * height * 3` for `rgb24`.
+  pub fn create(name: &str, frame_size: usize) -> io::Result<Self> {
+    Self::create_at(format!("/dev/shm/ffmpeg-sidecar-{name}"), frame_size)
+  }
+
+  /// Like [`create`](Self::create), at an arbitrary path. Only a tmpfs path
+  /// keeps the frames out of the disk cache.
+  pub fn create_at(path: impl Into<PathBuf>, frame_size: usize) -> io::Result<Self> {
+    if frame_size == 0 {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "frame size must be non-zero",
+      ));
+    }
+    let path = path.into();
+    let file = OpenOptions::new()
+      .read(true)
+      .write(true)
+      .create(true)
+      .truncate(true)
+      .open(&path)?;
+    Ok(Self {
+      path,
+      file,
+      frame_size,
+      offset: 0,
+      released: 0,
+      page_size: page_size(),
+    })
+  }
+
+  /// The file FFmpeg should write to.
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  pub fn frame_size(&self) -> usize {
+    self.frame_size
+  }
+
+  /// The next frame, if FFmpeg has finished writing it, without blocking.
+  pub fn try_next_frame(&mut self) -> io::Result<Option<ShmFrame<'_>>> {
+    let end = self.offset + self.frame_size as u64;
+    if self.file.metadata()?.len() < end {
+      return Ok(None);
+    }
+
+    let map_start = self.offset / self.page_size * self.page_size;
+    let map_len = (end - map_start) as usize;
+    // SAFETY: the range lies within the file, which is only ever appended to
+    // by FFmpeg, and the mapping is read-only and unmapped when the frame is
+    // dropped.
+    let ptr = unsafe {
+      libc::mmap(
+        std::ptr::null_mut(),
+        map_len,
+        libc::PROT_READ,
+        libc::MAP_SHARED,
+        self.file.as_raw_fd(),
+        map_start as libc::off_t,
+      )
+    };
+    if ptr == libc::MAP_FAILED {
+      return Err(io::Error::last_os_error());
+    }
+
+    let data_offset = (self.offset - map_start) as usize;
+    self.offset = end;
+    Ok(Some(ShmFrame {
+      reader: self,
+      ptr,
+      map_len,
+      data_offset,
+    }))
+  }
+
+  /// Wait for the next frame, or `None` once `child` has exited and every
+  /// complete frame has been read.
+  ///
+  /// FFmpeg's log is discarded on a background thread meanwhile, since it
+  /// stops writing frames once its stderr pipe is full. If stderr was already
+  /// taken with `FfmpegChild::take_stderr`, the caller must keep reading it.
+  pub fn next_frame(&mut self, child: &mut FfmpegChild) -> io::Result<Option<ShmFrame<'_>>> {
+    child.drain_stderr();
+    loop {
+      // Check for exit first, so that a frame written just before exiting
+      // is still picked up below.
+      let exited = child.try_wait()?.is_some();
+      let end = self.offset + self.frame_size as u64;
+      if self.file.metadata()?.len() >= end {
+        return self.try_next_frame();
+      }
+      if exited {
+        return Ok(None);
+      }
+      std::thread::sleep(POLL_INTERVAL);
+    }
+  }
+
+  /// Release the pages wholly before the current offset. Best effort: on
+  /// filesystems without hole punching the file simply keeps growing.
+  fn release_consumed(&mut self) {
+    let release_to = self.offset / self.page_size * self.page_size;
+    if release_to <= self.released {
+      return;
+    }
+    // SAFETY: plain syscall on an owned file descriptor.
+    unsafe {
+      libc::fallocate(
+        self.file.as_raw_fd(),
+        libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+        self.released as libc::off_t,
+        (release_to - self.released) as libc::off_t,
+      );
+    }
+    self.released = release_to;
+  }
+}
+
+impl Drop for ShmFrameReader {
+  fn drop(&mut self) {
+    std::fs::remove_file(&self.path).ok();
+  }
+}
+
+/// A zero-copy view of one frame in shared memory. Dropping it unmaps the
+/// frame and releases its pages.
+#[derive(Debug)]
+pub struct ShmFrame<'a> {
+  reader: &'a mut ShmFrameReader,
+  ptr: *mut libc::c_void,
+  map_len: usize,
+  data_offset: usize,
+}
+
+impl Deref for ShmFrame<'_> {
+  type Target = [u8];
+
+  fn deref(&self) -> &[u8] {
+    // SAFETY: `ptr` maps `map_len` readable bytes, of which the frame is the
+    // trailing `frame_size`.
+    unsafe {
+      std::slice::from_raw_parts(
+        (self.ptr as *const u8).add(self.data_offset),
+        self.reader.frame_size,
+      )
+    }
+  }
+}
+
+impl Drop for ShmFrame<'_> {
+  fn drop(&mut self) {
+    // SAFETY: `ptr` and `map_len` came from a successful `mmap`.
+    unsafe {
+      libc::munmap(self.ptr, self.map_len);
+    }
+    self.reader.release_consumed();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::Write;
+
+  #[test]
+  fn test_frames_across_pages() -> io::Result<()> {
+    let path = std::env::temp_dir().join(format!("shm-test-{}", std::process::id()));
+    let frame_size = 5000;
+    let mut reader = ShmFrameReader::create_at(&path, frame_size)?;
+    let mut writer = OpenOptions::new().append(true).open(&path)?;
+
+    assert!(reader.try_next_frame()?.is_none());
+    for i in 0..3u8 {
+      writer.write_all(&vec![i; frame_size])?;
+    }
+    writer.write_all(&[9; 10])?;
+
+    for i in 0..3u8 {
+      let frame = reader.try_next_frame()?.unwrap();
+      assert_eq!(frame.len(), frame_size);
+      assert!(frame.iter().all(|&byte| byte == i));
+    }
+    assert!(reader.try_next_frame()?.is_none());
+
+    drop(reader);
+    assert!(!path.exists());
+    Ok(())
+  }
+}
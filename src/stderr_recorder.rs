@@ -0,0 +1,225 @@
+//! Recording FFmpeg's raw stderr to a file or other writer, alongside the
+//! parsed events.
+//!
+//! FFmpeg ends each progress update with `\r` rather than `\n`, so that it
+//! overwrites the previous one in a terminal. Written verbatim to a file,
+//! a long job leaves thousands of near-duplicate progress lines;
+//! [`CarriageReturnPolicy`] controls how many of them are kept.
+
+use std::{
+  fmt,
+  io::{self, Read, Write},
+  time::{Duration, Instant},
+};
+
+/// What [`StderrRecorder`] does with lines which FFmpeg overwrites with `\r`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CarriageReturnPolicy {
+  /// Write stderr byte for byte, as a terminal would receive it.
+  Keep,
+  /// Drop every overwritten line, keeping only what remains on screen in a
+  /// terminal, such as the final progress update.
+  Collapse,
+  /// Keep at most one overwritten line per interval, each on its own line.
+  Throttle(Duration),
+}
+
+impl Default for CarriageReturnPolicy {
+  /// One progress update per second.
+  fn default() -> Self {
+    Self::Throttle(Duration::from_secs(1))
+  }
+}
+
+/// Writes raw stderr to `writer` line by line, applying a
+/// [`CarriageReturnPolicy`] to overwritten lines. Lines are always written
+/// with a `\n` terminator, unless the policy is `Keep`.
+///
+/// ```rust
+/// use ffmpeg_sidecar::stderr_recorder::{CarriageReturnPolicy, StderrRecorder};
+///
+/// let mut recorder = StderrRecorder::new(Vec::new(), CarriageReturnPolicy::Collapse);
+/// recorder.write_bytes(b"Press [q] to stop\nframe=   10\rframe=   20\r")?;
+/// recorder.write_bytes(b"frame=   30\n")?;
+/// assert_eq!(recorder.finish()?, b"Press [q] to stop\nframe=   30\n");
+/// # anyhow::Ok(())
+/// ```
+pub struct StderrRecorder<W: Write> {
+  writer: W,
+  policy: CarriageReturnPolicy,
+  line: Vec<u8>,
+  /// Whether the last byte was a `\r`, which is either a line ending on
+  /// Windows (`\r\n`) or overwrites the line, depending on the next byte.
+  after_cr: bool,
+  last_overwritten: Option<Instant>,
+}
+
+impl<W: Write> StderrRecorder<W> {
+  pub fn new(writer: W, policy: CarriageReturnPolicy) -> Self {
+    Self {
+      writer,
+      policy,
+      line: Vec::new(),
+      after_cr: false,
+      last_overwritten: None,
+    }
+  }
+
+  /// Record the next bytes read from stderr.
+  pub fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+    if self.policy == CarriageReturnPolicy::Keep {
+      return self.writer.write_all(bytes);
+    }
+    for &byte in bytes {
+      if self.after_cr {
+        self.after_cr = false;
+        match byte {
+          b'\n' => {
+            self.write_line()?;
+            continue;
+          }
+          _ => self.overwrite_line()?,
+        }
+      }
+      match byte {
+        b'\r' => self.after_cr = true,
+        b'\n' => self.write_line()?,
+        _ => self.line.push(byte),
+      }
+    }
+    Ok(())
+  }
+
+  /// Write the last line, which is kept even if it ended with `\r`, and
+  /// return the writer.
+  pub fn finish(mut self) -> io::Result<W> {
+    if self.after_cr || !self.line.is_empty() {
+      self.write_line()?;
+    }
+    self.writer.flush()?;
+    Ok(self.writer)
+  }
+
+  fn write_line(&mut self) -> io::Result<()> {
+    self.line.push(b'\n');
+    let result = self.writer.write_all(&self.line);
+    self.line.clear();
+    result
+  }
+
+  fn overwrite_line(&mut self) -> io::Result<()> {
+    if let CarriageReturnPolicy::Throttle(interval) = self.policy {
+      if self
+        .last_overwritten
+        .map_or(true, |t| t.elapsed() >= interval)
+      {
+        self.last_overwritten = Some(Instant::now());
+        return self.write_line();
+      }
+    }
+    self.line.clear();
+    Ok(())
+  }
+}
+
+impl<W: Write> fmt::Debug for StderrRecorder<W> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("StderrRecorder")
+      .field("policy", &self.policy)
+      .finish_non_exhaustive()
+  }
+}
+
+/// A reader which passes everything read from `inner` through a
+/// [`StderrRecorder`]. Recording stops at the first write error, without
+/// affecting the reader.
+pub(crate) struct RecordingReader<R: Read, W: Write> {
+  inner: R,
+  recorder: Option<StderrRecorder<W>>,
+}
+
+impl<R: Read, W: Write> RecordingReader<R, W> {
+  pub(crate) fn new(inner: R, recorder: StderrRecorder<W>) -> Self {
+    Self {
+      inner,
+      recorder: Some(recorder),
+    }
+  }
+}
+
+impl<R: Read, W: Write> Read for RecordingReader<R, W> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let n = self.inner.read(buf)?;
+    match (n, self.recorder.take()) {
+      (0, Some(recorder)) => {
+        recorder.finish().ok();
+      }
+      (_, Some(mut recorder)) => {
+        if recorder.write_bytes(&buf[..n]).is_ok() {
+          self.recorder = Some(recorder);
+        }
+      }
+      (_, None) => {}
+    }
+    Ok(n)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn record(policy: CarriageReturnPolicy, chunks: &[&[u8]]) -> String {
+    let mut recorder = StderrRecorder::new(Vec::new(), policy);
+    for chunk in chunks {
+      recorder.write_bytes(chunk).unwrap();
+    }
+    String::from_utf8(recorder.finish().unwrap()).unwrap()
+  }
+
+  #[test]
+  fn test_carriage_return_policies() {
+    let stderr: &[&[u8]] = &[b"Output #0\n", b"frame=1\rframe=2\r", b"frame=3\n"];
+    assert_eq!(
+      record(CarriageReturnPolicy::Keep, stderr),
+      "Output #0\nframe=1\rframe=2\rframe=3\n"
+    );
+    assert_eq!(
+      record(CarriageReturnPolicy::Collapse, stderr),
+      "Output #0\nframe=3\n"
+    );
+    assert_eq!(
+      record(CarriageReturnPolicy::Throttle(Duration::ZERO), stderr),
+      "Output #0\nframe=1\nframe=2\nframe=3\n"
+    );
+    assert_eq!(
+      record(
+        CarriageReturnPolicy::Throttle(Duration::from_secs(60)),
+        stderr
+      ),
+      "Output #0\nframe=1\nframe=3\n"
+    );
+  }
+
+  #[test]
+  fn test_windows_line_endings() {
+    // `\r\n` is a line ending, even when split across reads
+    let stderr: &[&[u8]] = &[b"Output #0\r\nframe=1\r", b"\nframe=2\r"];
+    assert_eq!(
+      record(CarriageReturnPolicy::Collapse, stderr),
+      "Output #0\nframe=1\nframe=2\n"
+    );
+  }
+
+  #[test]
+  fn test_recording_reader() {
+    let mut recorded = Vec::new();
+    let recorder = StderrRecorder::new(&mut recorded, CarriageReturnPolicy::Collapse);
+    let mut reader = RecordingReader::new(&b"a\rb\nc"[..], recorder);
+    let mut read = String::new();
+    reader.read_to_string(&mut read).unwrap();
+    assert_eq!(read, "a\rb\nc");
+    drop(reader);
+    assert_eq!(recorded, b"b\nc\n");
+  }
+}
@@ -0,0 +1,306 @@
+//! Conversion between the text subtitle formats SubRip (`.srt`), Advanced
+//! SubStation Alpha (`.ass`) and WebVTT (`.vtt`).
+//!
+//! Bitmap subtitles such as PGS or DVD subtitles can't be converted to text
+//! without OCR, so they're rejected with a [`SubtitleError`].
+
+use std::{ffi::OsStr, fmt, path::Path};
+
+use crate::{
+  command::FfmpegCommand,
+  event::{FfmpegEvent, LogLevel, Stream},
+  paths::file_arg,
+};
+
+/// Subtitle codecs which hold images rather than text.
+const BITMAP_CODECS: &[&str] = &["hdmv_pgs_subtitle", "dvd_subtitle", "dvb_subtitle", "xsub"];
+
+/// File extensions of standalone text subtitles, whose character encoding is
+/// detected before they're read.
+const TEXT_EXTENSIONS: &[&str] = &["srt", "ass", "ssa", "vtt", "sub", "txt"];
+
+/// A text subtitle format to convert to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubtitleFormat {
+  Srt,
+  Ass,
+  WebVtt,
+}
+
+impl SubtitleFormat {
+  /// The format of a file with the extension `extension`, e.g. `vtt`.
+  pub fn from_extension(extension: &str) -> Option<Self> {
+    match extension.to_ascii_lowercase().as_str() {
+      "srt" => Some(SubtitleFormat::Srt),
+      "ass" | "ssa" => Some(SubtitleFormat::Ass),
+      "vtt" => Some(SubtitleFormat::WebVtt),
+      _ => None,
+    }
+  }
+
+  /// The encoder, e.g. `-c:s webvtt`.
+  pub fn codec(&self) -> &'static str {
+    match self {
+      SubtitleFormat::Srt => "srt",
+      SubtitleFormat::Ass => "ass",
+      SubtitleFormat::WebVtt => "webvtt",
+    }
+  }
+
+  /// The muxer, e.g. `-f webvtt`.
+  pub fn muxer(&self) -> &'static str {
+    self.codec()
+  }
+
+  pub fn extension(&self) -> &'static str {
+    match self {
+      SubtitleFormat::Srt => "srt",
+      SubtitleFormat::Ass => "ass",
+      SubtitleFormat::WebVtt => "vtt",
+    }
+  }
+}
+
+/// Why a subtitle stream couldn't be converted.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubtitleError {
+  /// The input has no subtitle stream with the requested index. Holds the
+  /// subtitle streams it does have.
+  StreamNotFound {
+    requested: usize,
+    available: Vec<Stream>,
+  },
+  /// The stream holds images, which can't be converted to text.
+  BitmapSubtitles(Stream),
+}
+
+impl fmt::Display for SubtitleError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      SubtitleError::StreamNotFound {
+        requested,
+        available,
+      } if available.is_empty() => {
+        write!(
+          f,
+          "Subtitle stream {requested} not found; the input has none"
+        )
+      }
+      SubtitleError::StreamNotFound {
+        requested,
+        available,
+      } => {
+        let streams: Vec<String> = available
+          .iter()
+          .enumerate()
+          .map(|(index, stream)| describe(index, stream))
+          .collect();
+        write!(
+          f,
+          "Subtitle stream {requested} not found; available: {}",
+          streams.join(", ")
+        )
+      }
+      SubtitleError::BitmapSubtitles(stream) => write!(
+        f,
+        "Subtitle stream #{}:{} is {}, a bitmap format which can't be converted to text",
+        stream.parent_index, stream.stream_index, stream.format
+      ),
+    }
+  }
+}
+
+impl std::error::Error for SubtitleError {}
+
+fn describe(index: usize, stream: &Stream) -> String {
+  match stream.language.is_empty() {
+    true => format!("{index}: {}", stream.format),
+    false => format!("{index}: {} ({})", stream.format, stream.language),
+  }
+}
+
+/// The character encoding to pass as `-sub_charenc` for a text subtitle file
+/// starting with `bytes`, or `None` if FFmpeg can read it as it is: UTF-8,
+/// and UTF-16 with a byte order mark. Anything else is assumed to be the
+/// most common legacy encoding, Windows-1252.
+///
+/// Only UTF-8 is actually detected. Other legacy encodings, such as
+/// Windows-1251 (Cyrillic), GBK or Shift_JIS, are indistinguishable from
+/// Windows-1252 here and come out garbled rather than failing. When the
+/// encoding of a file is known, pass it to
+/// [`convert_subtitles_with_charset`] instead.
+///
+/// ```rust
+/// use ffmpeg_sidecar::subtitles::detect_charset;
+///
+/// assert_eq!(detect_charset("1\n00:00:01,000 --> 00:00:02,000\nCafé\n".as_bytes()), None);
+/// assert_eq!(detect_charset(b"Caf\xe9\n"), Some("CP1252"));
+/// ```
+pub fn detect_charset(bytes: &[u8]) -> Option<&'static str> {
+  if bytes.starts_with(&[0xff, 0xfe]) || bytes.starts_with(&[0xfe, 0xff]) {
+    return None;
+  }
+  match std::str::from_utf8(bytes) {
+    Ok(_) => None,
+    // A multi-byte character cut off at the end of the sample
+    Err(e) if e.error_len().is_none() => None,
+    Err(_) => Some("CP1252"),
+  }
+}
+
+/// The subtitle streams of `input`, in order, as FFmpeg describes them.
+pub fn subtitle_streams<S: AsRef<OsStr>>(input: S) -> anyhow::Result<Vec<Stream>> {
+  let mut streams = Vec::new();
+  let mut opened = false;
+  let mut errors = Vec::new();
+  // Without an output, FFmpeg describes the input and exits
+  let mut child = FfmpegCommand::new()
+    .hide_banner()
+    .input(input.as_ref())
+    .spawn()?;
+  child.iter()?.for_each(|event| match event {
+    FfmpegEvent::ParsedInput(_) => opened = true,
+    FfmpegEvent::ParsedInputStream(stream) if stream.is_subtitle() => streams.push(stream),
+    FfmpegEvent::Log(LogLevel::Error | LogLevel::Fatal, e) | FfmpegEvent::Error(e) => {
+      errors.push(e)
+    }
+    _ => {}
+  });
+  // Fails for lack of an output either way
+  child.wait()?;
+
+  match opened {
+    true => Ok(streams),
+    false => anyhow::bail!("Can't open the input: {}", errors.join("\n")),
+  }
+}
+
+/// Convert the subtitle stream with index `from_stream` among the subtitle
+/// streams of `input` (as in `-map 0:s:<from_stream>`) to `target_format`,
+/// returning the converted file.
+///
+/// The character encoding of a standalone subtitle file is detected with
+/// [`detect_charset`], which reads any legacy encoding as Windows-1252. Fails
+/// with a [`SubtitleError`] if the stream doesn't exist or holds bitmaps.
+///
+/// ```rust,no_run
+/// use ffmpeg_sidecar::subtitles::{convert_subtitles, SubtitleError, SubtitleFormat};
+///
+/// match convert_subtitles("movie.mkv", 1, SubtitleFormat::WebVtt) {
+///   Ok(vtt) => std::fs::write("movie.vtt", vtt)?,
+///   Err(e) => match e.downcast_ref::<SubtitleError>() {
+///     Some(SubtitleError::StreamNotFound { available, .. }) => {
+///       println!("Pick one of {} subtitle streams", available.len())
+///     }
+///     _ => return Err(e),
+///   },
+/// }
+/// # anyhow::Ok(())
+/// ```
+pub fn convert_subtitles<P: AsRef<Path>>(
+  input: P,
+  from_stream: usize,
+  target_format: SubtitleFormat,
+) -> anyhow::Result<String> {
+  let input = input.as_ref();
+  let is_text_file = input
+    .extension()
+    .and_then(OsStr::to_str)
+    .is_some_and(|ext| TEXT_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()));
+  let charset = match is_text_file {
+    true => detect_charset(&std::fs::read(input)?),
+    false => None,
+  };
+  // Passed as by `FfmpegCommand::input_path`, so that a file named like an
+  // option or a protocol isn't misread
+  convert_subtitles_with_charset(file_arg(input), from_stream, target_format, charset)
+}
+
+/// Like [`convert_subtitles`], with the character encoding of a text
+/// subtitle input given as `charset` (`-sub_charenc`), e.g. `CP1251`, rather
+/// than detected.
+pub fn convert_subtitles_with_charset<S: AsRef<OsStr>>(
+  input: S,
+  from_stream: usize,
+  target_format: SubtitleFormat,
+  charset: Option<&str>,
+) -> anyhow::Result<String> {
+  let available = subtitle_streams(input.as_ref())?;
+  let Some(stream) = available.get(from_stream) else {
+    return Err(
+      SubtitleError::StreamNotFound {
+        requested: from_stream,
+        available,
+      }
+      .into(),
+    );
+  };
+  if BITMAP_CODECS.contains(&stream.format.as_str()) {
+    return Err(SubtitleError::BitmapSubtitles(stream.clone()).into());
+  }
+
+  let mut command = FfmpegCommand::new();
+  command.hide_banner();
+  if let Some(charset) = charset {
+    command.args(["-sub_charenc", charset]);
+  }
+  let mut output = Vec::new();
+  let mut errors = Vec::new();
+  let mut child = command
+    .input(input.as_ref())
+    .map(format!("0:s:{from_stream}"))
+    .codec_subtitle(target_format.codec())
+    .format(target_format.muxer())
+    .output("-")
+    .spawn()?;
+  child.iter()?.for_each(|event| match event {
+    FfmpegEvent::OutputChunk(chunk) => output.extend(chunk),
+    FfmpegEvent::Log(LogLevel::Error | LogLevel::Fatal, e) | FfmpegEvent::Error(e) => {
+      errors.push(e)
+    }
+    _ => {}
+  });
+  let status = child.wait()?;
+  anyhow::ensure!(
+    status.success(),
+    "FFmpeg exited with {status}: {}",
+    errors.join("\n")
+  );
+  Ok(String::from_utf8_lossy(&output).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::event::StreamTypeSpecificData;
+
+  #[test]
+  fn test_stream_not_found() {
+    let stream = |format: &str, language: &str| Stream {
+      format: format.to_string(),
+      language: language.to_string(),
+      parent_index: 0,
+      stream_index: 2,
+      bitrate_kbps: None,
+      encoder: None,
+      type_specific_data: StreamTypeSpecificData::Subtitle(),
+      raw_log_message: String::new(),
+    };
+    let error = SubtitleError::StreamNotFound {
+      requested: 2,
+      available: vec![stream("subrip", "eng"), stream("ass", "")],
+    };
+    assert_eq!(
+      error.to_string(),
+      "Subtitle stream 2 not found; available: 0: subrip (eng), 1: ass"
+    );
+  }
+
+  #[test]
+  fn test_detect_charset() {
+    // UTF-16 is read by FFmpeg itself
+    assert_eq!(detect_charset(&[0xff, 0xfe, b'1', 0]), None);
+    // A character cut off by the end of the sample isn't an error
+    assert_eq!(detect_charset("é".as_bytes().split_at(1).0), None);
+  }
+}
@@ -0,0 +1,133 @@
+//! Loopback TCP listeners for streaming multiple outputs from one FFmpeg
+//! process, as a portable alternative to named pipes.
+
+use std::{
+  io,
+  net::{Ipv4Addr, TcpListener, TcpStream},
+  thread::JoinHandle,
+  time::{Duration, Instant},
+};
+
+/// How often a pending `accept` checks for a connection.
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A listener on an ephemeral loopback port, which FFmpeg connects to when
+/// given [`url`](Self::url) as an output.
+///
+/// ```rust,no_run
+/// use ffmpeg_sidecar::{command::FfmpegCommand, tcp_output::tcp_outputs};
+/// use std::{io::Read, time::Duration};
+///
+/// let [video, audio] = <[_; 2]>::try_from(tcp_outputs(2)?).unwrap();
+/// let mut child = FfmpegCommand::new()
+///   .test_av_source(Duration::from_secs(5))
+///   .map("0:v")
+///   .format("rawvideo")
+///   .output(video.url())
+///   .map("1:a")
+///   .format("s16le")
+///   .output(audio.url())
+///   .spawn()?;
+///
+/// let read_all = |stream: &mut std::net::TcpStream| {
+///   let mut buf = Vec::new();
+///   stream.read_to_end(&mut buf).map(|_| buf.len())
+/// };
+/// let video = video.spawn_reader(Duration::from_secs(5), read_all);
+/// let audio = audio.spawn_reader(Duration::from_secs(5), read_all);
+/// child.wait()?;
+/// println!("video: {} bytes", video.join().unwrap()?);
+/// println!("audio: {} bytes", audio.join().unwrap()?);
+/// # anyhow::Ok(())
+/// ```
+#[derive(Debug)]
+pub struct TcpOutput {
+  listener: TcpListener,
+  url: String,
+}
+
+impl TcpOutput {
+  /// Listen on `127.0.0.1` with a port chosen by the OS.
+  pub fn bind() -> io::Result<Self> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    let url = format!("tcp://{}", listener.local_addr()?);
+    Ok(Self { listener, url })
+  }
+
+  /// The output URL to pass to FFmpeg, e.g. `tcp://127.0.0.1:49152`.
+  pub fn url(&self) -> &str {
+    &self.url
+  }
+
+  pub fn port(&self) -> io::Result<u16> {
+    Ok(self.listener.local_addr()?.port())
+  }
+
+  /// Wait at most `timeout` for FFmpeg to connect. Fails with
+  /// `ErrorKind::TimedOut` otherwise, e.g. if FFmpeg exited before opening
+  /// this output.
+  pub fn accept(&self, timeout: Duration) -> io::Result<TcpStream> {
+    let deadline = Instant::now() + timeout;
+    self.listener.set_nonblocking(true)?;
+    loop {
+      match self.listener.accept() {
+        Ok((stream, _)) => {
+          // Some platforms inherit non-blocking mode from the listener
+          stream.set_nonblocking(false)?;
+          return Ok(stream);
+        }
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+          if Instant::now() >= deadline {
+            return Err(io::Error::new(
+              io::ErrorKind::TimedOut,
+              format!("FFmpeg didn't connect to {} in time", self.url),
+            ));
+          }
+          std::thread::sleep(ACCEPT_POLL_INTERVAL);
+        }
+        Err(e) => return Err(e),
+      }
+    }
+  }
+
+  /// Accept the connection and read it with `reader` on a new thread.
+  pub fn spawn_reader<F, T>(self, timeout: Duration, reader: F) -> JoinHandle<io::Result<T>>
+  where
+    F: FnOnce(&mut TcpStream) -> io::Result<T> + Send + 'static,
+    T: Send + 'static,
+  {
+    std::thread::spawn(move || reader(&mut self.accept(timeout)?))
+  }
+}
+
+/// Bind `count` loopback listeners on ephemeral ports, one per output. See
+/// [`TcpOutput`].
+pub fn tcp_outputs(count: usize) -> io::Result<Vec<TcpOutput>> {
+  (0..count).map(|_| TcpOutput::bind()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::{Read, Write};
+
+  #[test]
+  fn test_accept() -> io::Result<()> {
+    let outputs = tcp_outputs(2)?;
+    assert_ne!(outputs[0].url(), outputs[1].url());
+
+    let address = outputs[0].url().trim_start_matches("tcp://").to_string();
+    let mut client = TcpStream::connect(address)?;
+    client.write_all(b"chunk")?;
+    drop(client);
+
+    let mut received = String::new();
+    let mut stream = outputs[0].accept(Duration::from_secs(1))?;
+    stream.read_to_string(&mut received)?;
+    assert_eq!(received, "chunk");
+
+    let error = outputs[1].accept(Duration::from_millis(30)).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    Ok(())
+  }
+}
@@ -0,0 +1,174 @@
+//! Builder for the `tee` muxer, which writes the same encoded streams to
+//! several destinations at once.
+
+/// What the `tee` muxer does when writing to a destination fails.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TeeOnFail {
+  /// Stop the whole process. This is FFmpeg's default.
+  #[default]
+  Abort,
+  /// Drop the failed destination and keep writing to the others, e.g. so a
+  /// local recording survives a dropped RTMP connection.
+  Ignore,
+}
+
+impl TeeOnFail {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      TeeOnFail::Abort => "abort",
+      TeeOnFail::Ignore => "ignore",
+    }
+  }
+}
+
+/// One destination of a `tee` output, with its own muxer and options.
+///
+/// ```rust
+/// use ffmpeg_sidecar::tee::{tee_arg, TeeDest, TeeOnFail};
+///
+/// let arg = tee_arg(&[
+///   TeeDest::new("rtmp://cdn.example.com/live/key")
+///     .format("flv")
+///     .on_fail(TeeOnFail::Ignore),
+///   TeeDest::new("archive.mp4").format("mp4").option("movflags", "+faststart"),
+/// ]);
+/// assert_eq!(
+///   arg,
+///   "[f=flv:onfail=ignore]rtmp://cdn.example.com/live/key|[f=mp4:movflags=+faststart]archive.mp4"
+/// );
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TeeDest {
+  pub url: String,
+  /// The muxer (`f=`), guessed from the URL by FFmpeg if unset.
+  pub format: Option<String>,
+  /// A stream specifier (`select=`) limiting which streams are written to
+  /// this destination, e.g. `v` or `a:0`.
+  pub select: Option<String>,
+  /// Bitstream filters (`bsfs=`), e.g. `h264_mp4toannexb`.
+  pub bitstream_filters: Option<String>,
+  pub on_fail: TeeOnFail,
+  /// Any other muxer options, in order.
+  pub options: Vec<(String, String)>,
+}
+
+impl TeeDest {
+  pub fn new<S: Into<String>>(url: S) -> Self {
+    Self {
+      url: url.into(),
+      ..Default::default()
+    }
+  }
+
+  pub fn format<S: Into<String>>(mut self, format: S) -> Self {
+    self.format = Some(format.into());
+    self
+  }
+
+  pub fn select<S: Into<String>>(mut self, stream_specifier: S) -> Self {
+    self.select = Some(stream_specifier.into());
+    self
+  }
+
+  pub fn bitstream_filters<S: Into<String>>(mut self, bsfs: S) -> Self {
+    self.bitstream_filters = Some(bsfs.into());
+    self
+  }
+
+  pub fn on_fail(mut self, on_fail: TeeOnFail) -> Self {
+    self.on_fail = on_fail;
+    self
+  }
+
+  /// Add a muxer option for this destination only, e.g. `("movflags",
+  /// "+faststart")`.
+  pub fn option<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+    self.options.push((key.into(), value.into()));
+    self
+  }
+
+  /// The `[options]url` form of this destination within the tee filename.
+  ///
+  /// FFmpeg unescapes the filename twice: once when splitting it into
+  /// destinations at `|`, and again when splitting each option list at `:`.
+  fn to_tee_string(&self) -> String {
+    let mut options = Vec::new();
+    let named = [
+      ("f", self.format.as_deref()),
+      ("select", self.select.as_deref()),
+      ("bsfs", self.bitstream_filters.as_deref()),
+    ];
+    for (key, value) in named {
+      if let Some(value) = value {
+        options.push(format!("{key}={}", escape(value, ":]")));
+      }
+    }
+    // An explicit default keeps a URL starting with `[` from being read as
+    // an option list.
+    if self.on_fail != TeeOnFail::Abort || (options.is_empty() && self.url.starts_with('[')) {
+      options.push(format!("onfail={}", self.on_fail.as_str()));
+    }
+    for (key, value) in &self.options {
+      options.push(format!("{key}={}", escape(value, ":]")));
+    }
+
+    let destination = match options.is_empty() {
+      true => self.url.clone(),
+      false => format!("[{}]{}", options.join(":"), self.url),
+    };
+    escape(&destination, "|")
+  }
+}
+
+/// Backslash-escape `specials`, as well as backslashes and quotes, which
+/// FFmpeg's tokenizer would otherwise interpret.
+pub(crate) fn escape(value: &str, specials: &str) -> String {
+  let mut escaped = String::with_capacity(value.len());
+  for c in value.chars() {
+    if c == '\\' || c == '\'' || specials.contains(c) {
+      escaped.push('\\');
+    }
+    escaped.push(c);
+  }
+  escaped
+}
+
+/// The filename argument of a `tee` output writing to every destination.
+pub fn tee_arg(destinations: &[TeeDest]) -> String {
+  destinations
+    .iter()
+    .map(TeeDest::to_tee_string)
+    .collect::<Vec<_>>()
+    .join("|")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::log_parser::try_parse_output;
+
+  #[test]
+  fn test_escaping_round_trip() {
+    let destinations = [
+      TeeDest::new("rtmp://host/app?key=a|b").format("flv"),
+      TeeDest::new("[weird] name.mkv")
+        .select("v:0")
+        .option("metadata", "title=a:b"),
+      TeeDest::new("[bracketed].ts"),
+    ];
+    let line = format!("[info] Output #0, tee, to '{}':", tee_arg(&destinations));
+    let output = try_parse_output(&line).unwrap();
+
+    let urls: Vec<_> = output.children.iter().map(|c| c.to.as_str()).collect();
+    assert_eq!(
+      urls,
+      [
+        "rtmp://host/app?key=a|b",
+        "[weird] name.mkv",
+        "[bracketed].ts"
+      ]
+    );
+    assert_eq!(output.children[0].format.as_deref(), Some("flv"));
+    assert_eq!(output.children[2].format, None);
+  }
+}
@@ -0,0 +1,44 @@
+//! Uniquely named files in the system's temporary directory, which are
+//! removed again once nothing refers to them.
+
+use std::{
+  fs, io,
+  path::{Path, PathBuf},
+  sync::atomic::{AtomicU64, Ordering},
+};
+
+/// A file which is removed once dropped.
+#[derive(Debug)]
+pub(crate) struct TempFile(PathBuf);
+
+impl TempFile {
+  /// Create a file named after `name` and the current process, with
+  /// `contents`.
+  pub(crate) fn create(name: &str, extension: &str, contents: &[u8]) -> io::Result<Self> {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let safe_name: String = name
+      .chars()
+      .map(|c| match c.is_ascii_alphanumeric() {
+        true => c,
+        false => '_',
+      })
+      .collect();
+    let path = std::env::temp_dir().join(format!(
+      "ffmpeg-sidecar-{safe_name}-{}-{}.{extension}",
+      std::process::id(),
+      COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&path, contents)?;
+    Ok(Self(path))
+  }
+
+  pub(crate) fn path(&self) -> &Path {
+    &self.0
+  }
+}
+
+impl Drop for TempFile {
+  fn drop(&mut self) {
+    fs::remove_file(&self.0).ok();
+  }
+}
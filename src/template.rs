@@ -0,0 +1,540 @@
+//! Reusable snapshots of an `FfmpegCommand`, for spawning the same job
+//! repeatedly, e.g. from a batch queue or a supervisor which restarts it.
+
+use std::{
+  collections::BTreeMap,
+  ffi::{OsStr, OsString},
+  fmt,
+  path::{Path, PathBuf},
+  process::{Command, Stdio},
+  time::Duration,
+};
+
+use anyhow::Context;
+
+use crate::{
+  args::ArgModel,
+  command::{BackgroundCommand, CommandConfig, FfmpegCommand},
+  paths::file_arg,
+  tee::escape,
+};
+
+/// The placeholders which fail [`CommandTemplate::instantiate_with`] when
+/// they have no value, rather than being left alone like other names.
+pub const STANDARD_PLACEHOLDERS: &[&str] = &["input", "output", "index"];
+
+/// The program, arguments, environment and settings of an
+/// [`FfmpegCommand`], captured with [`FfmpegCommand::to_template`]. Unlike
+/// the command itself, a template can be cloned, and instantiated any number
+/// of times into fresh commands.
+///
+/// Arguments may contain placeholders, which are replaced by
+/// [`instantiate_with`](Self::instantiate_with):
+///
+/// - `{name}` is replaced by the value of `name`, e.g. `{input}`, `{output}`,
+///   `{index}` or any custom key.
+/// - `{name:filter}` escapes the value for use as an option inside a
+///   filtergraph, e.g. `subtitles=filename={input:filter}`.
+/// - `{name:04}` zero-pads an integer value to 4 digits.
+/// - `{{` and `}}` are literal braces.
+///
+/// Other braces, such as drawtext's `%{pts}` or those of an argument which
+/// isn't a valid placeholder, are left alone. So is a `{name}` with no value,
+/// e.g. in a filter string or text, except for the [`STANDARD_PLACEHOLDERS`],
+/// which must have one.
+///
+/// ```rust
+/// use ffmpeg_sidecar::{command::FfmpegCommand, template::TemplateVars};
+///
+/// let template = FfmpegCommand::new()
+///   .input("{input}")
+///   .codec_video("libx264")
+///   .output("out_{index:03}.mp4")
+///   .to_template()?;
+///
+/// for (index, input) in ["a.mov", "b.mov"].into_iter().enumerate() {
+///   let command = template.instantiate_with(TemplateVars::new().input(input).index(index))?;
+///   let args: Vec<_> = command.get_args().collect();
+///   assert!(args.contains(&input.as_ref()));
+///   assert!(args.contains(&format!("out_{index:03}.mp4").as_ref()));
+/// }
+/// # anyhow::Ok(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct CommandTemplate {
+  program: OsString,
+  args: Vec<OsString>,
+  envs: Vec<(OsString, Option<OsString>)>,
+  current_dir: Option<PathBuf>,
+  /// The arguments as parsed for the captured command, which knows the
+  /// input-only options among them.
+  model: ArgModel,
+  config: CommandConfig,
+}
+
+impl CommandTemplate {
+  /// Snapshot `inner`, its arg `model` and its `config`. Should only be
+  /// called by `FfmpegCommand::to_template`, which checks that nothing is
+  /// lost.
+  pub(crate) fn capture(inner: &Command, model: &ArgModel, config: &CommandConfig) -> Self {
+    Self {
+      program: inner.get_program().to_owned(),
+      args: inner.get_args().map(OsStr::to_owned).collect(),
+      envs: inner
+        .get_envs()
+        .map(|(key, value)| (key.to_owned(), value.map(OsStr::to_owned)))
+        .collect(),
+      current_dir: inner.get_current_dir().map(PathBuf::from),
+      model: model.clone(),
+      config: config.clone(),
+    }
+  }
+
+  /// A fresh command with exactly the captured arguments, placeholders
+  /// included.
+  pub fn instantiate(&self) -> FfmpegCommand {
+    self.build(self.args.iter().cloned())
+  }
+
+  /// A fresh command, with every placeholder in its arguments replaced by
+  /// the matching value of `vars`.
+  ///
+  /// Arguments are passed to FFmpeg directly rather than through a shell, so
+  /// a value always stays within its argument. Fails if one of the
+  /// [`STANDARD_PLACEHOLDERS`] has no value, or a modifier doesn't apply to a
+  /// value.
+  pub fn instantiate_with(&self, vars: impl Into<TemplateVars>) -> anyhow::Result<FfmpegCommand> {
+    let vars = vars.into();
+    let args = self
+      .args
+      .iter()
+      .map(|arg| substitute(arg, &vars))
+      .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(self.build(args))
+  }
+
+  /// Instantiate a command for every set of `jobs`, setting `{index}` to the
+  /// position of the job unless it sets `index` itself.
+  ///
+  /// ```rust
+  /// use ffmpeg_sidecar::command::FfmpegCommand;
+  ///
+  /// let template = FfmpegCommand::new()
+  ///   .input("{input}")
+  ///   .output("{index}.mp4")
+  ///   .to_template()?;
+  /// let commands = template
+  ///   .instantiate_batch([[("input", "a.mov")], [("input", "b.mov")]])
+  ///   .collect::<anyhow::Result<Vec<_>>>()?;
+  /// assert!(commands[1].get_args().any(|arg| arg == "1.mp4"));
+  /// # anyhow::Ok(())
+  /// ```
+  pub fn instantiate_batch<'a, I>(
+    &'a self,
+    jobs: I,
+  ) -> impl Iterator<Item = anyhow::Result<FfmpegCommand>> + 'a
+  where
+    I: IntoIterator,
+    I::Item: Into<TemplateVars>,
+    I::IntoIter: 'a,
+  {
+    jobs.into_iter().enumerate().map(|(index, vars)| {
+      let mut vars = vars.into();
+      if vars.get("index").is_none() {
+        vars.index(index);
+      }
+      self.instantiate_with(vars)
+    })
+  }
+
+  /// The names of the placeholders in the arguments, sorted and without
+  /// duplicates, e.g. to check a batch definition before running it. Includes
+  /// any `{name}` in literal text, which is left alone without a value.
+  pub fn placeholders(&self) -> Vec<&str> {
+    let mut names: Vec<&str> = self
+      .args
+      .iter()
+      .filter_map(|arg| arg.to_str())
+      .flat_map(segments)
+      .filter_map(|segment| match segment {
+        Segment::Placeholder { name, .. } => Some(name),
+        Segment::Literal(_) => None,
+      })
+      .collect();
+    names.sort_unstable();
+    names.dedup();
+    names
+  }
+
+  /// The path of the FFmpeg binary.
+  pub fn program(&self) -> &OsStr {
+    &self.program
+  }
+
+  /// The arguments, with any placeholders.
+  pub fn args(&self) -> impl Iterator<Item = &OsStr> {
+    self.args.iter().map(OsString::as_os_str)
+  }
+
+  fn build(&self, args: impl IntoIterator<Item = OsString>) -> FfmpegCommand {
+    let mut inner = Command::new(&self.program);
+    inner.args(args);
+    inner.stdin(self.config.stdin_mode.stdio());
+    inner.stderr(Stdio::piped());
+    inner.stdout(Stdio::piped());
+    inner.create_no_window();
+    if self.config.env_clear {
+      inner.env_clear();
+    }
+    for (key, value) in &self.envs {
+      match value {
+        Some(value) => inner.env(key, value),
+        None => inner.env_remove(key),
+      };
+    }
+    if let Some(dir) = &self.current_dir {
+      inner.current_dir(dir);
+    }
+    #[cfg(unix)]
+    for limits in &self.config.resource_limits {
+      limits.apply(&mut inner);
+    }
+
+    FfmpegCommand::from(inner)
+      .with_config(self.config.clone())
+      .with_arg_model_of(&self.model)
+  }
+}
+
+/// A value substituted for a placeholder of a [`CommandTemplate`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemplateValue {
+  Text(String),
+  /// A path, passed through [`file_arg`] so FFmpeg doesn't mistake it for
+  /// an option or a URL. A placeholder making up a whole argument keeps paths
+  /// which aren't valid UTF-8 intact.
+  Path(PathBuf),
+  Integer(i64),
+  Float(f64),
+  /// A duration, written as a number of seconds.
+  Duration(Duration),
+}
+
+impl TemplateValue {
+  fn to_os_string(&self) -> OsString {
+    match self {
+      TemplateValue::Path(path) => file_arg(path).into_owned(),
+      value => value.to_string().into(),
+    }
+  }
+
+  fn render(&self, modifier: Option<&str>) -> anyhow::Result<String> {
+    match (modifier, self) {
+      (None, value) => Ok(value.to_string()),
+      (Some("filter"), value) => Ok(escape(&escape(&value.to_string(), ":"), "[],;")),
+      (Some(width), TemplateValue::Integer(n)) if width.starts_with('0') => {
+        let width: usize = width.parse().context("invalid padding")?;
+        Ok(format!("{n:0width$}"))
+      }
+      (Some(width), _) if width.starts_with('0') => anyhow::bail!("only integers can be padded"),
+      (Some(modifier), _) => anyhow::bail!("unknown modifier `{modifier}`"),
+    }
+  }
+}
+
+impl fmt::Display for TemplateValue {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      TemplateValue::Text(text) => f.write_str(text),
+      TemplateValue::Path(path) => f.write_str(&file_arg(path).to_string_lossy()),
+      TemplateValue::Integer(n) => write!(f, "{n}"),
+      TemplateValue::Float(x) => write!(f, "{x}"),
+      TemplateValue::Duration(duration) => write!(f, "{}", duration.as_secs_f64()),
+    }
+  }
+}
+
+impl From<&str> for TemplateValue {
+  fn from(text: &str) -> Self {
+    TemplateValue::Text(text.to_string())
+  }
+}
+
+impl From<String> for TemplateValue {
+  fn from(text: String) -> Self {
+    TemplateValue::Text(text)
+  }
+}
+
+impl From<&Path> for TemplateValue {
+  fn from(path: &Path) -> Self {
+    TemplateValue::Path(path.to_owned())
+  }
+}
+
+impl From<PathBuf> for TemplateValue {
+  fn from(path: PathBuf) -> Self {
+    TemplateValue::Path(path)
+  }
+}
+
+impl From<i32> for TemplateValue {
+  fn from(n: i32) -> Self {
+    TemplateValue::Integer(n.into())
+  }
+}
+
+impl From<u32> for TemplateValue {
+  fn from(n: u32) -> Self {
+    TemplateValue::Integer(n.into())
+  }
+}
+
+impl From<i64> for TemplateValue {
+  fn from(n: i64) -> Self {
+    TemplateValue::Integer(n)
+  }
+}
+
+impl From<usize> for TemplateValue {
+  fn from(n: usize) -> Self {
+    TemplateValue::Integer(i64::try_from(n).unwrap_or(i64::MAX))
+  }
+}
+
+impl From<f64> for TemplateValue {
+  fn from(x: f64) -> Self {
+    TemplateValue::Float(x)
+  }
+}
+
+impl From<Duration> for TemplateValue {
+  fn from(duration: Duration) -> Self {
+    TemplateValue::Duration(duration)
+  }
+}
+
+/// The values for the placeholders of a [`CommandTemplate`], by name.
+///
+/// Can also be built from an array of `(name, value)` pairs, e.g.
+/// `[("input", "a.mov"), ("output", "a.mp4")]`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TemplateVars {
+  values: BTreeMap<String, TemplateValue>,
+}
+
+impl TemplateVars {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Set the value of the `{name}` placeholder.
+  pub fn set(&mut self, name: impl Into<String>, value: impl Into<TemplateValue>) -> &mut Self {
+    self.values.insert(name.into(), value.into());
+    self
+  }
+
+  /// Set `{input}` to a path.
+  pub fn input(&mut self, path: impl AsRef<Path>) -> &mut Self {
+    self.set("input", path.as_ref())
+  }
+
+  /// Set `{output}` to a path.
+  pub fn output(&mut self, path: impl AsRef<Path>) -> &mut Self {
+    self.set("output", path.as_ref())
+  }
+
+  /// Set `{index}`, e.g. the position of a job in a batch.
+  pub fn index(&mut self, index: usize) -> &mut Self {
+    self.set("index", index)
+  }
+
+  pub fn get(&self, name: &str) -> Option<&TemplateValue> {
+    self.values.get(name)
+  }
+}
+
+impl From<&TemplateVars> for TemplateVars {
+  fn from(vars: &TemplateVars) -> Self {
+    vars.clone()
+  }
+}
+
+impl From<&mut TemplateVars> for TemplateVars {
+  fn from(vars: &mut TemplateVars) -> Self {
+    vars.clone()
+  }
+}
+
+impl<K, V, const N: usize> From<[(K, V); N]> for TemplateVars
+where
+  K: Into<String>,
+  V: Into<TemplateValue>,
+{
+  fn from(pairs: [(K, V); N]) -> Self {
+    pairs.into_iter().collect()
+  }
+}
+
+impl<K, V> FromIterator<(K, V)> for TemplateVars
+where
+  K: Into<String>,
+  V: Into<TemplateValue>,
+{
+  fn from_iter<I: IntoIterator<Item = (K, V)>>(pairs: I) -> Self {
+    let mut vars = Self::new();
+    for (name, value) in pairs {
+      vars.set(name, value);
+    }
+    vars
+  }
+}
+
+#[derive(Debug, PartialEq)]
+enum Segment<'a> {
+  Literal(&'a str),
+  Placeholder {
+    name: &'a str,
+    modifier: Option<&'a str>,
+  },
+}
+
+/// Split an argument into literal text and placeholders, unescaping `{{` and
+/// `}}`.
+fn segments(arg: &str) -> Vec<Segment<'_>> {
+  let bytes = arg.as_bytes();
+  let mut segments = Vec::new();
+  let mut literal_start = 0;
+  let mut i = 0;
+  fn push_literal<'a>(segments: &mut Vec<Segment<'a>>, literal: &'a str) {
+    if !literal.is_empty() {
+      segments.push(Segment::Literal(literal));
+    }
+  }
+  while i < bytes.len() {
+    match bytes[i] {
+      b'{' | b'}' if bytes.get(i + 1) == Some(&bytes[i]) => {
+        push_literal(&mut segments, &arg[literal_start..=i]);
+        i += 2;
+        literal_start = i;
+      }
+      b'{' if i == 0 || bytes[i - 1] != b'%' => match parse_placeholder(&arg[i + 1..]) {
+        Some((len, name, modifier)) => {
+          push_literal(&mut segments, &arg[literal_start..i]);
+          segments.push(Segment::Placeholder { name, modifier });
+          i += len + 2;
+          literal_start = i;
+        }
+        None => i += 1,
+      },
+      _ => i += 1,
+    }
+  }
+  push_literal(&mut segments, &arg[literal_start..]);
+  segments
+}
+
+/// Parse `name}` or `name:modifier}` at the start of `rest`, returning the
+/// length before the closing brace.
+fn parse_placeholder(rest: &str) -> Option<(usize, &str, Option<&str>)> {
+  let len = rest.find('}')?;
+  let (name, modifier) = match rest[..len].split_once(':') {
+    Some((name, modifier)) => (name, Some(modifier)),
+    None => (&rest[..len], None),
+  };
+  let is_word = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+  let valid = is_word(name)
+    && !name.starts_with(|c: char| c.is_ascii_digit())
+    && modifier.map_or(true, is_word);
+  valid.then_some((len, name, modifier))
+}
+
+fn substitute(arg: &OsStr, vars: &TemplateVars) -> anyhow::Result<OsString> {
+  let Some(arg) = arg.to_str() else {
+    return Ok(arg.to_owned());
+  };
+  let segments = segments(arg);
+  if let [Segment::Placeholder {
+    name,
+    modifier: None,
+  }] = segments[..]
+  {
+    if let Some(value) = vars.get(name) {
+      return Ok(value.to_os_string());
+    }
+  }
+
+  let mut substituted = String::with_capacity(arg.len());
+  for segment in segments {
+    match segment {
+      Segment::Literal(text) => substituted.push_str(text),
+      Segment::Placeholder { name, modifier } => match vars.get(name) {
+        Some(value) => {
+          let rendered = value
+            .render(modifier)
+            .with_context(|| format!("can't substitute `{{{name}}}` in `{arg}`"))?;
+          substituted.push_str(&rendered);
+        }
+        None => {
+          anyhow::ensure!(
+            !STANDARD_PLACEHOLDERS.contains(&name),
+            "no value for placeholder `{{{name}}}` in `{arg}`"
+          );
+          substituted.push('{');
+          substituted.push_str(name);
+          if let Some(modifier) = modifier {
+            substituted.push(':');
+            substituted.push_str(modifier);
+          }
+          substituted.push('}');
+        }
+      },
+    }
+  }
+  Ok(substituted.into())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn render(arg: &str, vars: impl Into<TemplateVars>) -> anyhow::Result<String> {
+    let substituted = substitute(arg.as_ref(), &vars.into())?;
+    Ok(substituted.into_string().unwrap())
+  }
+
+  #[test]
+  fn test_substitute() -> anyhow::Result<()> {
+    let vars = TemplateVars::from([("input", "it's: a [test].srt")]);
+    assert_eq!(render("{input}", &vars)?, "it's: a [test].srt");
+    assert_eq!(
+      render("subtitles=filename={input:filter}", &vars)?,
+      r"subtitles=filename=it\\\'s\\: a \[test\].srt"
+    );
+    assert_eq!(
+      render("{{input}} %{pts} {} {1x}", &vars)?,
+      "{input} %{pts} {} {1x}"
+    );
+
+    let vars = TemplateVars::new()
+      .index(7)
+      .set("start", Duration::from_millis(1500))
+      .input("-dash.mov")
+      .clone();
+    assert_eq!(render("out_{index:04}.mp4", &vars)?, "out_0007.mp4");
+    assert_eq!(render("{start}", &vars)?, "1.5");
+    assert_eq!(render("{input}", &vars)?, "./-dash.mov");
+
+    assert!(render("{output}", &vars).is_err());
+    assert!(render("{text}_{output}", &vars).is_err());
+    assert_eq!(render("{text}", &vars)?, "{text}");
+    assert_eq!(
+      render("drawtext=text='{hello} {index}'", &vars)?,
+      "drawtext=text='{hello} 7'"
+    );
+    assert_eq!(render("{text:04}", &vars)?, "{text:04}");
+    assert!(render("{start:04}", &vars).is_err());
+    assert!(render("{index:upper}", &vars).is_err());
+    Ok(())
+  }
+}
@@ -325,7 +325,7 @@ fn test_duration() {
       if let FfmpegEvent::ParsedDuration(duration) = e {
         match duration_received {
           false => {
-            assert!(duration.duration == 5.0);
+            assert!(duration.duration.as_secs_f64() == Some(5.0));
             duration_received = true
           }
           true => panic!("Received multiple duration events."),
@@ -771,3 +771,2686 @@ fn test_wait() -> anyhow::Result<()> {
     .output("-");
   wait_with_timeout(&mut command, 5000)
 }
+
+/// Encoded MPEG-TS output can be tagged with keyframes without re-probing.
+#[test]
+fn test_tagged_chunks_keyframes() -> anyhow::Result<()> {
+  use crate::bitstream::ChunkFormat;
+
+  let chunks: Vec<_> = FfmpegCommand::new()
+    .testsrc()
+    .codec_video("libx264")
+    .args(["-g", "25"])
+    .format("mpegts")
+    .pipe_stdout()
+    .spawn()?
+    .iter()?
+    .filter_tagged_chunks(ChunkFormat::MpegTs)
+    .collect();
+
+  let keyframes = chunks.iter().filter(|c| c.keyframe).count();
+  assert!(keyframes >= 2);
+  assert!(chunks.iter().any(|c| c.pts.is_some()));
+
+  Ok(())
+}
+
+#[test]
+fn test_extract_extradata() -> anyhow::Result<()> {
+  use crate::bitstream::{ChunkFormat, CodecConfig};
+
+  let data: Vec<u8> = FfmpegCommand::new()
+    .testsrc()
+    .codec_video("libx264")
+    .extract_extradata()
+    .format("h264")
+    .pipe_stdout()
+    .spawn()?
+    .iter()?
+    .filter_chunks()
+    .flatten()
+    .collect();
+
+  let config = CodecConfig::from_annex_b(&data, ChunkFormat::H264).unwrap();
+  assert!(!config.sps.is_empty());
+  assert!(!config.pps.is_empty());
+  assert!(config.codec_string().unwrap().starts_with("avc1."));
+
+  Ok(())
+}
+
+#[test]
+fn test_stdout_chunk_size() -> anyhow::Result<()> {
+  let mut iter = FfmpegCommand::new()
+    .testsrc()
+    .codec_video("libx264")
+    .format("mpegts")
+    .stdout_chunk_size(1024)
+    .pipe_stdout()
+    .spawn()?
+    .iter()?;
+  let pool = iter.chunk_pool();
+
+  let mut chunks = 0;
+  for event in iter.by_ref() {
+    if let FfmpegEvent::OutputChunk(chunk) = event {
+      assert!(chunk.len() <= 1024);
+      // Short reads don't pin a full-size buffer
+      assert!(chunk.capacity() <= 2 * chunk.len());
+      chunks += 1;
+      pool.recycle(chunk);
+    }
+  }
+
+  assert!(chunks > 0);
+  assert!(!pool.is_empty());
+  Ok(())
+}
+
+#[test]
+fn test_output_writer_file() -> anyhow::Result<()> {
+  let output_path = "output/test_output_writer.ts";
+  let file = std::fs::File::create(output_path)?;
+
+  let mut child = FfmpegCommand::new()
+    .testsrc()
+    .codec_video("libx264")
+    .format("mpegts")
+    .pipe_stdout()
+    .spawn()?;
+  let copy_thread = child.output_writer(file)?;
+
+  let chunks = child
+    .iter()?
+    .filter(|e| matches!(e, FfmpegEvent::OutputChunk(_)))
+    .count();
+  let bytes_copied = copy_thread.join().unwrap()?;
+
+  assert_eq!(chunks, 0);
+  assert!(bytes_copied > 0);
+  assert_eq!(std::fs::metadata(output_path)?.len(), bytes_copied);
+
+  Ok(())
+}
+
+#[test]
+#[cfg(any(target_os = "linux", target_os = "macos", windows))]
+fn test_resource_usage() -> anyhow::Result<()> {
+  let samples: Vec<(f32, u64)> = FfmpegCommand::new()
+    .realtime()
+    .format("lavfi")
+    .input("testsrc=duration=3:rate=25")
+    .sample_resource_usage(Duration::from_millis(250))
+    .rawvideo()
+    .spawn()?
+    .iter()?
+    .filter_map(|e| match e {
+      FfmpegEvent::ResourceUsage {
+        cpu_percent,
+        rss_bytes,
+      } => Some((cpu_percent, rss_bytes)),
+      _ => None,
+    })
+    .collect();
+
+  assert!(!samples.is_empty());
+  assert!(samples.iter().all(|&(cpu, rss)| cpu >= 0.0 && rss > 0));
+
+  Ok(())
+}
+
+#[test]
+fn test_sync_inputs_unapplied() {
+  let result = FfmpegCommand::new()
+    .testsrc()
+    .sync_inputs([(0, Duration::from_millis(100))])
+    .rawvideo()
+    .spawn();
+  assert!(result.is_err());
+}
+
+#[test]
+fn test_probe_start_times() -> anyhow::Result<()> {
+  use crate::input_sync::probe_start_times;
+
+  let start_times = probe_start_times(
+    FfmpegCommand::new()
+      .testsrc()
+      .format("lavfi")
+      .input("sine=duration=1"),
+  )?;
+  assert_eq!(start_times, vec![Some(0.0), Some(0.0)]);
+
+  Ok(())
+}
+
+/// Progress times remain parseable (and monotonic) when timestamps are
+/// regenerated or replaced by wall clock time.
+#[test]
+fn test_timestamp_presets_progress() -> anyhow::Result<()> {
+  let mut command = FfmpegCommand::new();
+  command
+    .gen_pts()
+    .use_wallclock_timestamps()
+    .realtime()
+    .format("lavfi")
+    .input("testsrc=duration=2:rate=25")
+    .timecode("01:00:00:00")
+    .codec_video("libx264")
+    .overwrite()
+    .output("output/test_timestamps.mov");
+
+  // `-re` is an input option, so it has to precede `-i`
+  let args: Vec<_> = command.get_args().collect();
+  let position = |arg: &str| args.iter().position(|a| *a == arg);
+  assert!(position("-re").unwrap() < position("-i").unwrap());
+
+  let times: Vec<f64> = command
+    .spawn()?
+    .iter()?
+    .filter_progress()
+    .filter_map(|progress| progress.time.as_secs_f64())
+    .collect();
+
+  assert!(!times.is_empty());
+  assert!(times.windows(2).all(|w| w[0] <= w[1]));
+  assert!(*times.last().unwrap() >= 1.0);
+
+  Ok(())
+}
+
+#[test]
+fn test_low_latency_input_args() {
+  let mut command = FfmpegCommand::new();
+  command
+    .low_latency_input()
+    .analyzeduration(Duration::from_millis(500))
+    .format("lavfi")
+    .input("testsrc");
+  let args: Vec<_> = command
+    .get_args()
+    .map(|arg| arg.to_string_lossy().to_string())
+    .collect();
+  let args = args.join(" ");
+  assert!(args.contains("-probesize 32 -analyzeduration 0 -fflags nobuffer -flags low_delay"));
+  assert!(args.contains("-analyzeduration 500000 -f lavfi -i testsrc"));
+}
+
+#[test]
+fn test_latency_harness() -> anyhow::Result<()> {
+  use crate::latency::LatencyTest;
+
+  let test = LatencyTest {
+    frames: 30,
+    ..Default::default()
+  };
+  let report = test.run(|command| {
+    command.args(["-vf", "scale=320:180,scale=640:360"]);
+  })?;
+
+  assert_eq!(report.undecodable, 0);
+  assert_eq!(report.samples.len(), 30);
+  assert!(report.max().unwrap() < Duration::from_secs(5));
+
+  Ok(())
+}
+
+#[test]
+fn test_frame_pump_like() -> anyhow::Result<()> {
+  use crate::frame_pump::{FrameWriter, InputFramePump};
+
+  let mut source = FfmpegCommand::new().testsrc().rawvideo().spawn()?.iter()?;
+  let metadata = source.collect_metadata()?;
+  let pump = InputFramePump::like(&metadata.output_streams[0])?;
+  assert_eq!(pump.pix_fmt, "rgb24");
+
+  let mut sink = FfmpegCommand::new();
+  let mut sink = pump.configure(&mut sink).rawvideo().spawn()?;
+  let stdin = sink.take_stdin().unwrap();
+  let writer = FrameWriter::new(stdin, pump).spawn(source.filter_frames().map(|f| f.data));
+
+  let received = sink.iter()?.filter_frames().count() as u64;
+  let sent = writer.join().unwrap()?;
+  assert_eq!(sent, received);
+
+  Ok(())
+}
+
+#[test]
+fn test_arg_placement_errors() {
+  use crate::command::ArgPlacementError;
+
+  let placement_error = |command: &mut FfmpegCommand| {
+    let error = command.spawn().err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    error
+      .get_ref()
+      .and_then(|e| e.downcast_ref::<ArgPlacementError>())
+      .cloned()
+  };
+
+  let error = placement_error(FfmpegCommand::new().testsrc().stream_loop(2).rawvideo());
+  assert_eq!(
+    error,
+    Some(ArgPlacementError::InputOptionWithoutInput(
+      "-stream_loop".into()
+    ))
+  );
+
+  let error = placement_error(FfmpegCommand::new().shortest().testsrc().rawvideo());
+  assert_eq!(
+    error,
+    Some(ArgPlacementError::OutputOptionBeforeInput(
+      "-shortest".into()
+    ))
+  );
+}
+
+#[test]
+fn test_arg_model_after_raw_edits() -> anyhow::Result<()> {
+  let mut command = FfmpegCommand::new();
+  command.testsrc();
+  command.as_inner_mut().args(["-c:v", "libx264"]);
+  command.output("out.mp4");
+  assert_eq!(
+    command.get_arg_model().outputs[0].option("-c:v"),
+    Some("libx264")
+  );
+
+  // Input-only options are still known after a round trip through a template
+  let mut command = FfmpegCommand::new();
+  command.testsrc().stream_loop(2).rawvideo();
+  let error = command.to_template()?.instantiate().spawn().err().unwrap();
+  assert!(error.to_string().contains("-stream_loop"));
+  Ok(())
+}
+
+#[test]
+fn test_stream_loop_shortest() {
+  let frames = FfmpegCommand::new()
+    .stream_loop(-1)
+    .args(["-f", "lavfi", "-i", "testsrc=duration=1:rate=10"])
+    .format("lavfi")
+    .input("sine=duration=3")
+    .shortest()
+    .rawvideo()
+    .spawn()
+    .unwrap()
+    .iter()
+    .unwrap()
+    .filter_frames()
+    .count();
+  assert!(frames > 10);
+}
+
+#[test]
+fn test_inspect_errs() {
+  let errors = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+  let sink = errors.clone();
+  let frames = FfmpegCommand::new()
+    // output format and pix_fmt are deliberately missing, and cannot be inferred
+    .args("-f lavfi -i testsrc=duration=1:rate=1 -".split(' '))
+    .spawn()
+    .unwrap()
+    .iter()
+    .unwrap()
+    .inspect_errs(move |e| sink.lock().unwrap().push(e.to_string()))
+    .filter_frames()
+    .count();
+
+  assert_eq!(frames, 0);
+  assert!(!errors.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_collect_errors() {
+  let result = FfmpegCommand::new()
+    .args("-f lavfi -i testsrc=duration=1:rate=1 -".split(' '))
+    .spawn()
+    .unwrap()
+    .iter()
+    .unwrap()
+    .collect_errors();
+  assert!(result.is_err());
+
+  let result = FfmpegCommand::new()
+    .testsrc()
+    .rawvideo()
+    .spawn()
+    .unwrap()
+    .iter()
+    .unwrap()
+    .collect_errors();
+  assert!(result.is_ok());
+}
+
+#[test]
+fn test_completed_event() -> anyhow::Result<()> {
+  let events: Vec<FfmpegEvent> = FfmpegCommand::new()
+    .testsrc()
+    .rawvideo()
+    .spawn()?
+    .into_events()?
+    .collect();
+
+  let completed: Vec<_> = events
+    .iter()
+    .filter(|e| matches!(e, FfmpegEvent::Completed { .. }))
+    .collect();
+  assert_eq!(completed.len(), 1);
+  match events.last() {
+    Some(FfmpegEvent::Completed {
+      exit_status,
+      had_output,
+    }) => {
+      assert!(exit_status.unwrap().success());
+      assert!(had_output);
+    }
+    other => panic!("Expected Completed as the last event, got {other:?}"),
+  }
+
+  // Borrowing iterators only complete if asked to, and can't reap the child
+  let mut child = FfmpegCommand::new().testsrc().rawvideo().spawn()?;
+  assert!(matches!(child.iter()?.last(), Some(FfmpegEvent::Done)));
+  assert!(child.wait()?.success());
+  let mut child = FfmpegCommand::new().testsrc().rawvideo().spawn()?;
+  let last = child.iter()?.with_completed().last();
+  assert!(matches!(
+    last,
+    Some(FfmpegEvent::Completed {
+      exit_status: None,
+      had_output: true
+    })
+  ));
+  assert!(child.wait()?.success());
+
+  Ok(())
+}
+
+#[test]
+fn test_ffmetadata_chapters() -> anyhow::Result<()> {
+  use crate::ffmetadata::{self, Chapter, Metadata};
+
+  std::fs::create_dir_all("output")?;
+  let mut metadata = Metadata::default();
+  metadata.tags.push(("title".into(), "Chapters".into()));
+  metadata
+    .chapters
+    .push(Chapter::new(Duration::ZERO, Duration::from_secs(1), "One"));
+  ffmetadata::write(&metadata, "output/chapters_in.txt")?;
+
+  FfmpegCommand::new()
+    .overwrite()
+    .args(["-f", "lavfi", "-i", "testsrc=duration=2"])
+    .format("ffmetadata")
+    .input("output/chapters_in.txt")
+    .map("0")
+    .map_metadata("1")
+    .map_chapters(1)
+    .output("output/chapters.mkv")
+    .spawn()?
+    .wait()?;
+
+  FfmpegCommand::new()
+    .overwrite()
+    .input("output/chapters.mkv")
+    .format("ffmetadata")
+    .output("output/chapters_out.txt")
+    .spawn()?
+    .wait()?;
+
+  let exported = ffmetadata::read("output/chapters_out.txt")?;
+  assert!(exported.tags.contains(&("title".into(), "Chapters".into())));
+  assert_eq!(exported.chapters.len(), 1);
+  assert_eq!(exported.chapters[0].title(), Some("One"));
+
+  Ok(())
+}
+
+#[test]
+fn test_cutlist() -> anyhow::Result<()> {
+  use crate::cutlist::{Cutlist, Segment};
+
+  std::fs::create_dir_all("output")?;
+  let source = "output/cutlist_source.mkv";
+  FfmpegCommand::new()
+    .overwrite()
+    .args(["-f", "lavfi", "-i", "testsrc=duration=6:rate=10"])
+    .args(["-f", "lavfi", "-i", "sine=duration=6"])
+    .args(["-g", "10"])
+    .output(source)
+    .spawn()?
+    .wait()?;
+
+  let mut cutlist = Cutlist::new();
+  cutlist
+    .segment(Segment::new(Duration::from_secs(1), Duration::from_secs(2)))
+    .segment(Segment::new(Duration::from_secs(3), Duration::from_secs(5)).speed(2.0));
+
+  let mut command = FfmpegCommand::new();
+  command.input(source);
+  cutlist.apply_filtergraph(&mut command)?;
+  let frames = command
+    .args(["-f", "rawvideo", "-pix_fmt", "rgb24", "-"])
+    .spawn()?
+    .iter()?
+    .filter_frames()
+    .count();
+  // 1s at normal speed + 2s at double speed, with some leeway for frame rate
+  // conversion at segment boundaries
+  assert!((15..=25).contains(&frames));
+
+  let mut cutlist = Cutlist::new();
+  cutlist
+    .segment(Segment::new(Duration::from_secs(0), Duration::from_secs(2)))
+    .segment(Segment::new(Duration::from_secs(4), Duration::from_secs(5)));
+  cutlist
+    .copy_plan(source, "output/cutlist_copy.mkv", "output/cutlist")?
+    .run()?;
+  assert!(std::path::Path::new("output/cutlist_copy.mkv").exists());
+
+  Ok(())
+}
+
+#[test]
+fn test_frame_grabber() -> anyhow::Result<()> {
+  use crate::frame_grabber::FrameGrabber;
+
+  std::fs::create_dir_all("output")?;
+  let path = "output/frame_grabber.mp4";
+  FfmpegCommand::new()
+    .overwrite()
+    .args(["-f", "lavfi", "-i", "testsrc=duration=5:rate=10"])
+    .output(path)
+    .spawn()?
+    .wait()?;
+
+  let mut grabber = FrameGrabber::open(path)?;
+  assert!(approx_eq(grabber.fps(), 10.0, 0.01));
+
+  let forward = grabber.get_frame_at(Duration::from_millis(3000))?;
+  assert_eq!(forward.frame_num, 30);
+  let ahead = grabber.get_frame_at(Duration::from_millis(3250))?;
+  assert_eq!(ahead.frame_num, 32);
+  let back = grabber.get_frame_at(Duration::from_millis(500))?;
+  assert_eq!(back.frame_num, 5);
+  let cached = grabber.get_frame_at(Duration::from_millis(3000))?;
+  assert_eq!(cached.data, forward.data);
+
+  assert!(grabber.get_frame_at(Duration::from_secs(60)).is_err());
+
+  Ok(())
+}
+
+#[test]
+fn test_make_proxy() -> anyhow::Result<()> {
+  use crate::proxy::{make_proxy, ProxyProfile};
+
+  std::fs::create_dir_all("output")?;
+  let source = "output/proxy_source.mp4";
+  FfmpegCommand::new()
+    .overwrite()
+    .args(["-f", "lavfi", "-i", "testsrc=duration=2:size=1280x720"])
+    .output(source)
+    .spawn()?
+    .wait()?;
+
+  let mut last_progress = 0.0;
+  let proxy = make_proxy(source, ProxyProfile::H264, |p| last_progress = p)?;
+  assert_eq!(
+    proxy,
+    std::path::Path::new("output/proxies/proxy_source_h264.mp4")
+  );
+  assert!(proxy.exists());
+  assert_eq!(last_progress, 1.0);
+
+  Ok(())
+}
+
+#[test]
+fn test_tempo_and_pitch() {
+  let samples = |command: &mut FfmpegCommand| {
+    command
+      .args(["-f", "s16le", "-ac", "1", "-"])
+      .spawn()
+      .unwrap()
+      .iter()
+      .unwrap()
+      .filter_chunks()
+      .map(|chunk| chunk.len() / 2)
+      .sum::<usize>()
+  };
+
+  // 4x speed is beyond a single atempo filter, and quarters the duration
+  let sped_up = samples(
+    FfmpegCommand::new()
+      .format("lavfi")
+      .input("sine=duration=4:sample_rate=8000")
+      .tempo(4.0),
+  );
+  assert!((7_000..=9_000).contains(&sped_up));
+
+  // Pitch shifting preserves the duration
+  let shifted = samples(
+    FfmpegCommand::new()
+      .format("lavfi")
+      .input("sine=duration=1:sample_rate=8000")
+      .pitch(-5.0, 8000),
+  );
+  assert!((7_000..=9_000).contains(&shifted));
+}
+
+#[test]
+fn test_audio_drift_compensation() {
+  // Start the audio 0.5s late, so that `first_pts=0` pads it with silence
+  let corrections = FfmpegCommand::new()
+    .args(["-loglevel", "level+verbose"])
+    .itsoffset("0.5")
+    .format("lavfi")
+    .input("sine=duration=1")
+    .audio_drift_compensation()
+    .args(["-f", "null", "-"])
+    .spawn()
+    .unwrap()
+    .iter()
+    .unwrap()
+    .filter(|e| matches!(e, FfmpegEvent::ParsedDriftCompensation(_)))
+    .count();
+  assert!(corrections > 0);
+}
+
+#[test]
+fn test_detect_crop() -> anyhow::Result<()> {
+  use crate::analysis::{detect_crop, CropRect};
+
+  std::fs::create_dir_all("output")?;
+  let path = "output/letterboxed.mp4";
+  FfmpegCommand::new()
+    .overwrite()
+    .args(["-f", "lavfi", "-i", "testsrc=duration=2:size=640x360"])
+    .filter("pad=640:480:0:60:black")
+    .output(path)
+    .spawn()?
+    .wait()?;
+
+  // `cropdetect` rounds the size down to a multiple of 16 by default
+  let CropRect {
+    width,
+    height,
+    x,
+    y,
+  } = detect_crop(path, Duration::from_secs(2))?;
+  assert_eq!((width, x), (640, 0));
+  assert!((352..=360).contains(&height));
+  assert!((60..=68).contains(&y));
+  Ok(())
+}
+
+#[test]
+fn test_detect_interlace() -> anyhow::Result<()> {
+  use crate::analysis::{detect_interlace, FieldOrder};
+
+  std::fs::create_dir_all("output")?;
+  let progressive = "output/progressive.mp4";
+  FfmpegCommand::new()
+    .overwrite()
+    .args(["-f", "lavfi", "-i", "testsrc=duration=2"])
+    .output(progressive)
+    .spawn()?
+    .wait()?;
+  assert_eq!(detect_interlace(progressive)?.field_order(), None);
+
+  let interlaced = "output/interlaced.mp4";
+  FfmpegCommand::new()
+    .overwrite()
+    .args(["-f", "lavfi", "-i", "testsrc=duration=2:rate=50"])
+    .filter("interlace=scan=tff")
+    .args(["-flags", "+ilme+ildct", "-top", "1"])
+    .output(interlaced)
+    .spawn()?
+    .wait()?;
+  assert_eq!(
+    detect_interlace(interlaced)?.field_order(),
+    Some(FieldOrder::TopFieldFirst)
+  );
+
+  Ok(())
+}
+
+#[test]
+fn test_map_channels() -> anyhow::Result<()> {
+  let mut command = FfmpegCommand::new();
+  command
+    .sine(440.0, Duration::from_secs(1))
+    .sine(880.0, Duration::from_secs(1))
+    .filter_complex("[0:a][1:a]amerge=inputs=2")
+    .args(["-f", "wav", "-"]);
+  let metadata = command.spawn()?.iter()?.collect_metadata()?;
+  assert_eq!(
+    metadata.output_streams[0].audio_data().unwrap().channels,
+    "stereo"
+  );
+
+  let mut command = FfmpegCommand::new();
+  command.map_channels(&[(1, 0), (0, 1)])?;
+  assert!(command.validate_pan(&metadata.output_streams).is_empty());
+  command.pan("stereo", &[[1.0, 0.0, 1.0]]);
+  assert_eq!(command.validate_pan(&metadata.output_streams).len(), 2);
+
+  Ok(())
+}
+
+#[test]
+fn test_source_presets() -> anyhow::Result<()> {
+  let metadata = FfmpegCommand::new()
+    .test_av_source(Duration::from_secs(1))
+    .args(["-f", "null", "-"])
+    .spawn()?
+    .iter()?
+    .collect_metadata()?;
+  assert!(metadata.output_streams.iter().any(|s| s.is_video()));
+  assert!(metadata.output_streams.iter().any(|s| s.is_audio()));
+
+  let presets: [fn(&mut FfmpegCommand) -> &mut FfmpegCommand; 3] = [
+    FfmpegCommand::smptebars,
+    FfmpegCommand::colorbars,
+    FfmpegCommand::mandelbrot,
+  ];
+  for preset in presets {
+    let mut command = FfmpegCommand::new();
+    let frames = preset(&mut command)
+      .frames(3)
+      .rawvideo()
+      .spawn()?
+      .iter()?
+      .filter_frames();
+    assert_eq!(frames.count(), 3);
+  }
+
+  let metadata = FfmpegCommand::new()
+    .sine(440.0, Duration::from_millis(500))
+    .anoisesrc()
+    .args(["-map", "0:a", "-map", "1:a", "-t", "0.5", "-f", "null", "-"])
+    .spawn()?
+    .iter()?
+    .collect_metadata()?;
+  assert_eq!(metadata.output_streams.len(), 2);
+
+  Ok(())
+}
+
+#[test]
+fn test_noise_source_is_deterministic() -> anyhow::Result<()> {
+  let render = |seed| -> anyhow::Result<Vec<u8>> {
+    let frames = FfmpegCommand::new()
+      .noise_source(seed, (32, 32), Duration::from_millis(200))
+      .rawvideo()
+      .spawn()?
+      .iter()?
+      .filter_frames()
+      .flat_map(|frame| frame.data)
+      .collect();
+    Ok(frames)
+  };
+  let first = render(7)?;
+  assert!(!first.is_empty());
+  assert_eq!(first, render(7)?);
+  assert_ne!(first, render(8)?);
+  Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_resource_limits() -> anyhow::Result<()> {
+  use crate::resource_limits::ResourceLimits;
+
+  std::fs::create_dir_all("output")?;
+  let status = FfmpegCommand::new()
+    .current_dir("output")
+    .resource_limits(ResourceLimits::default().file_size(16 * 1024))
+    .overwrite()
+    .testsrc()
+    .codec_video("rawvideo")
+    .output("test_resource_limits.avi")
+    .spawn()?
+    .wait()?;
+  assert!(!status.success());
+  let written = std::fs::metadata("output/test_resource_limits.avi")?.len();
+  assert!(written <= 16 * 1024);
+  Ok(())
+}
+
+#[test]
+fn test_ffmpeg_features() -> anyhow::Result<()> {
+  use crate::version::{ffmpeg_features, ffmpeg_version};
+
+  let features = ffmpeg_features()?;
+  assert_eq!(features.version, ffmpeg_version()?);
+  assert!(!features.configuration.is_empty());
+  assert_eq!(ffmpeg_features()?, features);
+  Ok(())
+}
+
+#[test]
+fn test_ffmpeg_version_ex() -> anyhow::Result<()> {
+  let version = crate::version::ffmpeg_version_ex()?;
+  assert_eq!(version.raw, crate::version::ffmpeg_version()?);
+  assert!(version.at_least(4, 0, 0));
+  Ok(())
+}
+
+#[test]
+fn test_filter_audio_levels() -> anyhow::Result<()> {
+  // The `sine` source has an amplitude of 1/8, i.e. a peak of -18 dBFS and an
+  // RMS level 3 dB lower
+  let levels: Vec<_> = FfmpegCommand::new()
+    .sine(440.0, Duration::from_secs(1))
+    .args(["-f", "s16le", "-"])
+    .spawn()?
+    .iter()?
+    .filter_audio_levels(Duration::from_millis(100))
+    .collect();
+  assert_eq!(levels.len(), 10);
+  for level in levels {
+    assert_eq!(level.channel, 0);
+    assert!((level.peak_db - -18.06).abs() < 0.1, "{level:?}");
+    assert!((level.rms_db - -21.07).abs() < 0.1, "{level:?}");
+  }
+  Ok(())
+}
+
+#[test]
+fn test_audio_stats_clipping() -> anyhow::Result<()> {
+  use crate::analysis::{audio_stats, audio_stats_with_filter};
+
+  std::fs::create_dir_all("output")?;
+  let path = "output/test_audio_stats.wav";
+  FfmpegCommand::new()
+    .overwrite()
+    .sine(440.0, Duration::from_secs(1))
+    .output(path)
+    .spawn()?
+    .wait()?;
+
+  let clean = audio_stats(path)?;
+  assert_eq!(clean.channels.len(), 1);
+  assert!(!clean.is_clipping());
+  assert!((clean.channels[0].peak_db - -18.06).abs() < 0.1);
+
+  let boosted = audio_stats_with_filter(path, Some("volume=24dB"))?;
+  assert!(boosted.is_clipping());
+  assert!(boosted.clipping_peak_count() > 0);
+
+  Ok(())
+}
+
+#[test]
+fn test_stream_mappings() -> anyhow::Result<()> {
+  let metadata = FfmpegCommand::new()
+    .test_av_source(Duration::from_secs(1))
+    .codec_audio("copy")
+    .args(["-f", "nut", "-"])
+    .spawn()?
+    .iter()?
+    .collect_metadata()?;
+  let mappings = &metadata.stream_mappings;
+  assert_eq!(mappings.len(), 2);
+  assert_eq!(
+    (mappings[0].from_input, mappings[0].to_stream),
+    (0, Some(0))
+  );
+  assert!(!mappings[0].copy);
+  assert_eq!(
+    (mappings[1].from_input, mappings[1].to_stream),
+    (1, Some(1))
+  );
+  assert!(mappings[1].copy);
+  Ok(())
+}
+
+#[test]
+#[cfg(feature = "named_pipes")]
+fn test_managed_pipes() -> anyhow::Result<()> {
+  use std::io::Read;
+
+  let mut child = FfmpegCommand::new()
+    .overwrite()
+    .args([
+      "-f",
+      "lavfi",
+      "-i",
+      "testsrc=size=320x240:rate=1:duration=1",
+    ])
+    .frames(1)
+    .format("rawvideo")
+    .pix_fmt("rgb24")
+    .named_pipe_output("test_managed_pipe")
+    .spawn()?;
+  let path = child.pipes().paths()[0].clone();
+  let reader = child.pipes().spawn_reader(&path, |pipe| {
+    let mut buf = Vec::new();
+    pipe.read_to_end(&mut buf).map(|_| buf.len())
+  })?;
+  assert!(child.wait()?.success());
+  assert_eq!(reader.join().unwrap()?, 320 * 240 * 3);
+  assert!(child.pipes().paths().is_empty());
+  #[cfg(unix)]
+  assert!(!std::path::Path::new(&path).exists());
+  Ok(())
+}
+
+#[test]
+fn test_tcp_outputs() -> anyhow::Result<()> {
+  use crate::tcp_output::tcp_outputs;
+  use std::io::Read;
+
+  let [video, audio] = <[_; 2]>::try_from(tcp_outputs(2)?).unwrap();
+  let mut child = FfmpegCommand::new()
+    .test_av_source(Duration::from_secs(1))
+    .map("0:v")
+    .frames(1)
+    .format("rawvideo")
+    .pix_fmt("rgb24")
+    .output(video.url())
+    .map("1:a")
+    .format("s16le")
+    .output(audio.url())
+    .spawn()?;
+  let read_all = |stream: &mut std::net::TcpStream| {
+    let mut buf = Vec::new();
+    stream.read_to_end(&mut buf).map(|_| buf.len())
+  };
+  let video = video.spawn_reader(Duration::from_secs(10), read_all);
+  let audio = audio.spawn_reader(Duration::from_secs(10), read_all);
+  assert!(child.wait()?.success());
+  assert_eq!(video.join().unwrap()?, 320 * 240 * 3);
+  assert!(audio.join().unwrap()? > 0);
+  Ok(())
+}
+
+#[test]
+fn test_progress_over_tcp() -> anyhow::Result<()> {
+  let mut progress = Vec::new();
+  let iter = FfmpegCommand::new()
+    .testsrc()
+    .frames(10)
+    .format("null")
+    .output("-")
+    .quiet()
+    .progress_over_tcp()
+    .spawn()?
+    .iter()?;
+  for event in iter {
+    match event {
+      FfmpegEvent::Progress(p) => progress.push(p),
+      FfmpegEvent::Log(LogLevel::Info, line) => {
+        assert!(!line.contains("frame="), "stderr progress: {line}");
+      }
+      _ => {}
+    }
+  }
+  let last = progress.last().unwrap();
+  assert_eq!(last.frame, 10);
+  assert!(last.raw_log_message.ends_with("progress=end"));
+  Ok(())
+}
+
+#[test]
+fn test_structured_progress() -> anyhow::Result<()> {
+  let progress: Vec<_> = FfmpegCommand::new()
+    .testsrc()
+    .frames(10)
+    .format("null")
+    .output("-")
+    .structured_progress()
+    .spawn()?
+    .iter()?
+    .filter_progress()
+    .collect();
+  let last = progress.last().unwrap();
+  assert_eq!(last.frame, 10);
+  assert!(last.out_time_us.is_some());
+  assert!(last.raw_log_message.ends_with("progress=end"));
+  Ok(())
+}
+
+#[test]
+fn test_progress_over_tcp_wait() -> anyhow::Result<()> {
+  let status = FfmpegCommand::new()
+    .testsrc()
+    .frames(10)
+    .format("null")
+    .output("-")
+    .progress_over_tcp()
+    .spawn()?
+    .wait()?;
+  assert!(status.success());
+  Ok(())
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_shm_output() -> anyhow::Result<()> {
+  use crate::shm_transport::ShmFrameReader;
+
+  let frame_size = 320 * 240 * 3;
+  let mut reader = ShmFrameReader::create("test_shm_output", frame_size)?;
+  let mut child = FfmpegCommand::new()
+    .testsrc()
+    .frames(5)
+    .format("rawvideo")
+    .pix_fmt("rgb24")
+    .shm_output(&reader)
+    .spawn()?;
+  let mut frames = 0;
+  while let Some(frame) = reader.next_frame(&mut child)? {
+    assert_eq!(frame.len(), frame_size);
+    frames += 1;
+  }
+  assert_eq!(frames, 5);
+  Ok(())
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_shm_output_drains_stderr() -> anyhow::Result<()> {
+  use crate::shm_transport::ShmFrameReader;
+  use std::os::unix::fs::PermissionsExt;
+
+  // Stands in for FFmpeg, logging more than a pipe holds before its frame
+  let fake_ffmpeg = crate::temp_file::TempFile::create(
+    "fake_ffmpeg",
+    "sh",
+    b"#!/bin/sh\n\
+      for output; do :; done\n\
+      head -c 1000000 /dev/zero | tr '\\0' x >&2\n\
+      printf abcd >> \"$output\"\n",
+  )?;
+  std::fs::set_permissions(fake_ffmpeg.path(), std::fs::Permissions::from_mode(0o755))?;
+
+  let mut reader = ShmFrameReader::create("test_shm_output_drains_stderr", 4)?;
+  let mut child = FfmpegCommand::new_with_path(fake_ffmpeg.path())
+    .shm_output(&reader)
+    .spawn()?;
+  assert_eq!(
+    reader.next_frame(&mut child)?.as_deref(),
+    Some(&b"abcd"[..])
+  );
+  assert!(reader.next_frame(&mut child)?.is_none());
+  Ok(())
+}
+
+#[test]
+fn test_zero_frames_rawvideo() {
+  let events = FfmpegCommand::new()
+    .testsrc()
+    .frames(0)
+    .rawvideo()
+    .spawn()
+    .unwrap()
+    .iter()
+    .unwrap()
+    .collect::<Vec<_>>();
+  assert!(events.iter().any(|e| matches!(e, FfmpegEvent::Done)));
+  assert!(!events
+    .iter()
+    .any(|e| matches!(e, FfmpegEvent::OutputFrame(_))));
+}
+
+#[test]
+fn test_expect_no_output() {
+  for args in [["-t", "0", "-f", "null"], ["-frames:v", "0", "-f", "null"]] {
+    let events = FfmpegCommand::new()
+      .testsrc()
+      .args(args)
+      .output(if cfg!(windows) { "NUL" } else { "/dev/null" })
+      .expect_no_output()
+      .spawn()
+      .unwrap()
+      .iter()
+      .unwrap()
+      .collect::<Vec<_>>();
+    assert!(events.iter().any(|e| matches!(e, FfmpegEvent::Done)));
+    assert!(!events.iter().any(|e| matches!(e, FfmpegEvent::Error(_))));
+  }
+}
+
+#[test]
+fn test_null_output_done() {
+  let events = FfmpegCommand::new()
+    .testsrc()
+    .frames(5)
+    .format("null")
+    .output("-")
+    .spawn()
+    .unwrap()
+    .iter()
+    .unwrap()
+    .collect::<Vec<_>>();
+  assert!(events.iter().any(|e| matches!(e, FfmpegEvent::Done)));
+  assert!(!events.iter().any(|e| matches!(e, FfmpegEvent::Error(_))));
+}
+
+#[test]
+fn test_tee_outputs() -> anyhow::Result<()> {
+  use crate::tee::{TeeDest, TeeOnFail};
+
+  let dir = std::env::temp_dir().join(format!("tee-test-{}", std::process::id()));
+  std::fs::create_dir_all(&dir)?;
+  let ts = dir.join("a|b.ts");
+  let mkv = dir.join("out.mkv");
+  let status = FfmpegCommand::new()
+    .overwrite()
+    .testsrc()
+    .frames(10)
+    .map("0")
+    .codec_video("mpeg2video")
+    .tee_outputs(&[
+      TeeDest::new(ts.to_string_lossy())
+        .format("mpegts")
+        .on_fail(TeeOnFail::Ignore),
+      TeeDest::new(mkv.to_string_lossy()).format("matroska"),
+    ])
+    .spawn()?
+    .wait()?;
+  assert!(status.success());
+  assert!(std::fs::metadata(&ts)?.len() > 0);
+  assert!(std::fs::metadata(&mkv)?.len() > 0);
+  std::fs::remove_dir_all(dir)?;
+  Ok(())
+}
+
+#[test]
+fn test_sample_throughput() -> anyhow::Result<()> {
+  let mut iter = FfmpegCommand::new()
+    .testsrc()
+    .frames(50)
+    .rawvideo()
+    .sample_throughput(Duration::ZERO)
+    .spawn()?
+    .iter()?;
+  let mut last_total = 0;
+  for event in iter.by_ref() {
+    if let FfmpegEvent::Throughput {
+      bytes_per_sec,
+      total_bytes,
+    } = event
+    {
+      assert!(bytes_per_sec > 0.0);
+      assert!(total_bytes > last_total);
+      last_total = total_bytes;
+    }
+  }
+  assert_eq!(last_total, 50 * 320 * 240 * 3);
+  assert_eq!(iter.bytes_read(), last_total);
+  Ok(())
+}
+
+#[test]
+fn test_input_from_child_stdout() -> anyhow::Result<()> {
+  let mut producer = std::process::Command::new(crate::paths::ffmpeg_path())
+    .args([
+      "-f",
+      "lavfi",
+      "-i",
+      "testsrc=duration=1",
+      "-f",
+      "mpegts",
+      "-",
+    ])
+    .stdout(std::process::Stdio::piped())
+    .stderr(std::process::Stdio::null())
+    .spawn()?;
+  let frames = FfmpegCommand::new()
+    .format("mpegts")
+    .input_from_child_stdout(&mut producer)
+    .rawvideo()
+    .spawn()?
+    .iter()?
+    .filter_frames()
+    .count();
+  assert!(producer.wait()?.success());
+  assert_eq!(frames, 25);
+  Ok(())
+}
+
+#[test]
+fn test_output_to_file() -> anyhow::Result<()> {
+  let path = std::env::temp_dir().join(format!("output-to-file-{}.raw", std::process::id()));
+  let status = FfmpegCommand::new()
+    .testsrc()
+    .frames(2)
+    .format("rawvideo")
+    .pix_fmt("rgb24")
+    .output_to_file(std::fs::File::create(&path)?)
+    .spawn()?
+    .wait()?;
+  assert!(status.success());
+  assert_eq!(std::fs::metadata(&path)?.len(), 2 * 320 * 240 * 3);
+  std::fs::remove_file(path)?;
+  Ok(())
+}
+
+#[test]
+fn test_hw_device_types() -> anyhow::Result<()> {
+  use crate::gpu::{hw_device_types, hw_device_types_with_path};
+
+  // A stand-in for `ffmpeg -init_hw_device list`
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::PermissionsExt;
+
+    let stub = std::env::temp_dir().join(format!("ffmpeg-hw-list-{}", std::process::id()));
+    std::fs::write(
+      &stub,
+      "#!/bin/sh\nprintf 'Supported hw device types:\\ncuda\\nvaapi\\nqsv\\ndrm\\n\\n'\n",
+    )?;
+    std::fs::set_permissions(&stub, std::fs::Permissions::from_mode(0o755))?;
+    let types = hw_device_types_with_path(&stub);
+    std::fs::remove_file(&stub).ok();
+    assert_eq!(types?, ["cuda", "vaapi", "qsv", "drm"]);
+  }
+
+  let types = hw_device_types()?;
+  assert!(types.iter().all(|t| !t.is_empty() && !t.contains(' ')));
+  Ok(())
+}
+
+#[test]
+fn test_hw_transcode_fallback() -> anyhow::Result<()> {
+  use crate::{
+    gpu::Hw,
+    hw_transcode::{HwTranscode, VideoCodec},
+  };
+
+  let path = std::env::temp_dir().join(format!("hw-transcode-{}.mp4", std::process::id()));
+  let pipeline = HwTranscode::new(Hw::Vaapi, VideoCodec::H264)
+    .size(160, 120)
+    .or_software();
+  let status = FfmpegCommand::new()
+    .overwrite()
+    .format("lavfi")
+    .hw_transcode_preset(pipeline, "testsrc=duration=0.2", &path)
+    .spawn()?
+    .wait()?;
+  std::fs::remove_file(&path).ok();
+  assert!(status.success());
+  Ok(())
+}
+
+#[test]
+fn test_dedup_frames() -> anyhow::Result<()> {
+  let frames = FfmpegCommand::new()
+    .format("lavfi")
+    .input("color=c=red:s=64x48:d=1,drawbox=t=fill:c=blue:enable='gte(t,0.5)'")
+    .rawvideo()
+    .spawn()?
+    .iter()?
+    .dedup_frames()
+    .collect::<Vec<_>>();
+  assert_eq!(frames.len(), 2);
+  assert_eq!(frames[1].timestamp, 0.52);
+  Ok(())
+}
+
+#[test]
+fn test_paced() -> anyhow::Result<()> {
+  let start = std::time::Instant::now();
+  let frames = FfmpegCommand::new()
+    .testsrc()
+    .frames(10)
+    .rawvideo()
+    .spawn()?
+    .iter()?
+    .paced(true)
+    .filter(|event| matches!(event, FfmpegEvent::OutputFrame(_)))
+    .count();
+  assert_eq!(frames, 10);
+  // 9 frame intervals at 25 fps
+  assert!(start.elapsed() >= Duration::from_millis(360));
+  Ok(())
+}
+
+#[test]
+fn test_startup_timings() -> anyhow::Result<()> {
+  let events = FfmpegCommand::new()
+    .testsrc()
+    .frames(1)
+    .rawvideo()
+    .spawn()?
+    .iter()?
+    .collect::<Vec<_>>();
+  let index = events
+    .iter()
+    .position(|e| matches!(e, FfmpegEvent::StartupTimings(_)))
+    .unwrap();
+  assert!(matches!(events[index + 1], FfmpegEvent::OutputFrame(_)));
+  let FfmpegEvent::StartupTimings(timings) = events[index] else {
+    unreachable!()
+  };
+  let first_log = timings.first_log.unwrap();
+  let metadata_complete = timings.metadata_complete.unwrap();
+  assert!(first_log <= metadata_complete);
+  assert!(metadata_complete <= timings.first_output.unwrap());
+  Ok(())
+}
+
+#[test]
+fn test_stall_watchdog_kills_stalled_process() -> anyhow::Result<()> {
+  // Stdin stays open without any data, so FFmpeg waits on it indefinitely.
+  let mut child = FfmpegCommand::new()
+    .format("rawvideo")
+    .pix_fmt("rgb24")
+    .size(32, 32)
+    .input("pipe:0")
+    .rawvideo()
+    .spawn()?;
+  child.stall_watchdog(Duration::from_millis(500), true);
+  let stalls = child
+    .iter()?
+    .filter_map(|e| match e {
+      FfmpegEvent::Stalled { since } => Some(since),
+      _ => None,
+    })
+    .collect::<Vec<_>>();
+  assert_eq!(stalls.len(), 1);
+  assert!(stalls[0] >= Duration::from_millis(500));
+  assert!(!child.wait()?.success());
+  Ok(())
+}
+
+#[test]
+fn test_on_event_drops_events_before_channel() -> anyhow::Result<()> {
+  use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+  };
+
+  let seen_progress = Arc::new(AtomicUsize::new(0));
+  let counter = seen_progress.clone();
+  let events = FfmpegCommand::new()
+    .testsrc()
+    .frames(10)
+    .rawvideo()
+    .spawn()?
+    .iter()?
+    .on_event(move |event| match event {
+      FfmpegEvent::Progress(_) => {
+        counter.fetch_add(1, Ordering::Relaxed);
+        false
+      }
+      FfmpegEvent::ParsedOutput(_) => false,
+      _ => true,
+    })
+    .collect::<Vec<_>>();
+  assert!(seen_progress.load(Ordering::Relaxed) > 0);
+  assert!(!events.iter().any(|e| matches!(e, FfmpegEvent::Progress(_))));
+  // Metadata events can't be dropped, so frames are still delivered
+  assert!(events
+    .iter()
+    .any(|e| matches!(e, FfmpegEvent::ParsedOutput(_))));
+  let frames = events
+    .iter()
+    .filter(|e| matches!(e, FfmpegEvent::OutputFrame(_)));
+  assert_eq!(frames.count(), 10);
+  Ok(())
+}
+
+#[test]
+fn test_split_channels() -> anyhow::Result<()> {
+  let (logs, frames) = FfmpegCommand::new()
+    .testsrc()
+    .frames(10)
+    .rawvideo()
+    .spawn()?
+    .iter()?
+    .with_completed()
+    .split_channels();
+  // Not consuming logs until all frames are received must not block them
+  let frames = frames.collect::<Vec<_>>();
+  let frame_count = frames
+    .iter()
+    .filter(|e| matches!(e, FfmpegEvent::OutputFrame(_)))
+    .count();
+  assert_eq!(frame_count, 10);
+  assert!(matches!(frames.last(), Some(FfmpegEvent::Done)));
+
+  let logs = logs.collect::<Vec<_>>();
+  assert!(logs.iter().any(|e| matches!(e, FfmpegEvent::Progress(_))));
+  assert!(!logs
+    .iter()
+    .any(|e| matches!(e, FfmpegEvent::OutputFrame(_))));
+  assert!(matches!(logs.last(), Some(FfmpegEvent::Completed { .. })));
+  Ok(())
+}
+
+#[test]
+fn test_roi() -> anyhow::Result<()> {
+  let frames = FfmpegCommand::new()
+    .testsrc()
+    .frames(2)
+    .roi(10, 20, 31, 17)
+    .rawvideo()
+    .spawn()?
+    .iter()?
+    .filter_frames()
+    .collect::<Vec<_>>();
+  assert_eq!(frames.len(), 2);
+  for frame in frames {
+    assert_eq!((frame.width, frame.height), (31, 17));
+    assert_eq!(frame.data.len(), 31 * 17 * 3);
+  }
+  Ok(())
+}
+
+#[test]
+fn test_scaled_outputs() -> anyhow::Result<()> {
+  use crate::scaled_outputs::ScaledOutput;
+
+  let frames = FfmpegCommand::new()
+    .testsrc()
+    .frames(3)
+    .scaled_outputs(&[
+      ScaledOutput::new("full", 320, 240),
+      ScaledOutput::new("thumb", 32, 24).pix_fmt("gray"),
+    ])?
+    .spawn()?
+    .iter()?
+    .filter_frames()
+    .collect::<Vec<_>>();
+  assert_eq!(frames.len(), 6);
+  for frame in frames {
+    match frame.output_tag.as_deref() {
+      Some("full") => assert_eq!(frame.data.len(), 320 * 240 * 3),
+      Some("thumb") => assert_eq!(frame.data.len(), 32 * 24),
+      tag => panic!("unexpected tag {tag:?}"),
+    }
+  }
+  Ok(())
+}
+
+#[test]
+fn test_no_encoder_fallback_for_codec_names() -> anyhow::Result<()> {
+  let events = FfmpegCommand::new()
+    .testsrc()
+    .frames(1)
+    .codec_video("mpeg4")
+    .format("null")
+    .output("-")
+    .spawn()?
+    .iter()?
+    .collect::<Vec<_>>();
+  let stream = events
+    .iter()
+    .find_map(|e| match e {
+      FfmpegEvent::ParsedOutputStream(stream) => Some(stream),
+      _ => None,
+    })
+    .unwrap();
+  assert_eq!(stream.format, "mpeg4");
+  assert!(!events
+    .iter()
+    .any(|e| matches!(e, FfmpegEvent::EncoderFallback { .. })));
+  Ok(())
+}
+
+#[test]
+fn test_extract_filter_metadata() -> anyhow::Result<()> {
+  use crate::extract::LogExtractor;
+
+  let extracted = FfmpegCommand::new()
+    .testsrc()
+    .frames(3)
+    .args([
+      "-vf",
+      "signalstats,metadata=print:key=lavfi.signalstats.YAVG",
+    ])
+    .format("null")
+    .output("-")
+    .extract(LogExtractor::key_value("yavg", "lavfi.signalstats.YAVG"))
+    .spawn()?
+    .iter()?
+    .filter_map(|e| match e {
+      FfmpegEvent::Extracted(x) => Some(x),
+      _ => None,
+    })
+    .collect::<Vec<_>>();
+  assert_eq!(extracted.len(), 3);
+  assert!(extracted.iter().all(|x| x.name == "yavg"));
+  assert!(extracted[0]
+    .parse::<f64>("lavfi.signalstats.YAVG")
+    .is_some());
+  Ok(())
+}
+
+#[test]
+fn test_filter_metadata() -> anyhow::Result<()> {
+  let metadata = FfmpegCommand::new()
+    .testsrc()
+    .frames(3)
+    .args([
+      "-vf",
+      "signalstats,metadata=print:key=lavfi.signalstats.YAVG",
+    ])
+    .format("null")
+    .output("-")
+    .spawn()?
+    .iter()?
+    .filter_map(|e| match e {
+      FfmpegEvent::FilterMetadata(x) => Some(x),
+      _ => None,
+    })
+    .collect::<Vec<_>>();
+  assert_eq!(metadata.len(), 3);
+  assert!(metadata.iter().all(|x| x.key == "lavfi.signalstats.YAVG"));
+  assert!(metadata
+    .iter()
+    .all(|x| x.filter.starts_with("Parsed_metadata_")));
+  assert!(metadata[0].value.parse::<f64>().is_ok());
+  assert!(metadata[1].pts_time > metadata[0].pts_time);
+  Ok(())
+}
+
+#[test]
+fn test_signal_stats() -> anyhow::Result<()> {
+  use crate::analysis::signal_stats;
+
+  std::fs::create_dir_all("output")?;
+  let path = "output/test_signal_stats.mp4";
+  FfmpegCommand::new()
+    .overwrite()
+    .args(["-f", "lavfi", "-i", "color=black:duration=1:rate=10"])
+    .args(["-f", "lavfi", "-i", "testsrc=duration=1:rate=10"])
+    .args(["-filter_complex", "[0:v][1:v]concat=n=2:v=1"])
+    .output(path)
+    .spawn()?
+    .wait()?;
+
+  let stats = signal_stats(path)?;
+  assert_eq!(stats.frames.len(), 20);
+  assert!(!stats.is_all_black());
+  let black = stats.black_segments();
+  assert_eq!(black.len(), 1);
+  assert_eq!(*black[0].start(), 0.0);
+  assert!(*black[0].end() < 1.0);
+  let brightness = stats.brightness().unwrap();
+  assert!(brightness.min < brightness.max);
+  Ok(())
+}
+
+#[test]
+fn test_record_stderr() -> anyhow::Result<()> {
+  use crate::stderr_recorder::CarriageReturnPolicy;
+
+  std::fs::create_dir_all("output")?;
+  let path = "output/test_record_stderr.log";
+  let mut child = FfmpegCommand::new()
+    .testsrc()
+    .frames(50)
+    .rawvideo()
+    .spawn()?;
+  child.record_stderr(std::fs::File::create(path)?, CarriageReturnPolicy::Collapse);
+  let progress_events = child.iter()?.filter_progress().count();
+  assert!(progress_events > 0);
+
+  let log = std::fs::read_to_string(path)?;
+  assert!(log.contains("Output #0"));
+  assert!(!log.contains('\r'));
+  let progress_lines = log
+    .lines()
+    .filter(|l| l.contains("frame=") && l.contains("speed="));
+  assert_eq!(progress_lines.count(), 1);
+  Ok(())
+}
+
+#[test]
+fn test_quiet() -> anyhow::Result<()> {
+  let chunks = FfmpegCommand::new()
+    .quiet()
+    .testsrc()
+    .frames(5)
+    .rawvideo()
+    .spawn()?
+    .iter()?
+    .filter_chunks()
+    .map(|chunk| chunk.len())
+    .sum::<usize>();
+  assert_eq!(chunks, 5 * 320 * 240 * 3);
+
+  let metadata = FfmpegCommand::new()
+    .args(["-v", "error"])
+    .testsrc()
+    .frames(1)
+    .rawvideo()
+    .spawn()?
+    .iter()?
+    .collect_metadata();
+  assert!(metadata.is_err());
+  Ok(())
+}
+
+#[test]
+fn test_no_stats() -> anyhow::Result<()> {
+  let events = FfmpegCommand::new()
+    .no_stats()
+    .testsrc()
+    .frames(5)
+    .rawvideo()
+    .spawn()?
+    .iter()?
+    .collect::<Vec<_>>();
+  assert!(!events.iter().any(|e| matches!(e, FfmpegEvent::Progress(_))));
+  let frames = events
+    .iter()
+    .filter(|e| matches!(e, FfmpegEvent::OutputFrame(_)));
+  assert_eq!(frames.count(), 5);
+  Ok(())
+}
+
+#[test]
+fn test_wait_until_writing() -> anyhow::Result<()> {
+  use crate::child::ReadinessError;
+
+  let mut child = FfmpegCommand::new()
+    .testsrc()
+    .frames(5)
+    .rawvideo()
+    .spawn()?;
+  child.wait_until_writing(Duration::from_secs(10))?;
+  // Events received while waiting are replayed
+  let events = child.iter()?.collect::<Vec<_>>();
+  assert!(events
+    .iter()
+    .any(|e| matches!(e, FfmpegEvent::ParsedOutput(_))));
+  let frames = events
+    .iter()
+    .filter(|e| matches!(e, FfmpegEvent::OutputFrame(_)));
+  assert_eq!(frames.count(), 5);
+  child.wait()?;
+
+  let mut child = FfmpegCommand::new()
+    .input("does_not_exist.mp4")
+    .rawvideo()
+    .spawn()?;
+  let result = child.wait_until_writing(Duration::from_secs(10));
+  assert!(matches!(result, Err(ReadinessError::Exited(errors)) if !errors.is_empty()));
+  child.wait()?;
+  Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_wait_after_wait_until_writing() -> anyhow::Result<()> {
+  use std::process::{Command, Stdio};
+
+  // A progress update, then far more log than the stderr pipe's buffer holds
+  let script = "\
+    echo 'frame=    1 fps=0.0 q=0.0 size=       0kB time=00:00:00.04 bitrate=   0.0kbits/s speed=N/A' >&2; \
+    i=0; while [ $i -lt 4000 ]; do \
+      echo '[info] A log line to fill the pipe buffer while nothing reads it' >&2; i=$((i+1)); \
+    done";
+  let inner = Command::new("sh")
+    .args(["-c", script])
+    .stdin(Stdio::null())
+    .stdout(Stdio::null())
+    .stderr(Stdio::piped())
+    .spawn()?;
+  let mut child = crate::child::FfmpegChild::from_backend(inner);
+  child.wait_until_writing(Duration::from_secs(10))?;
+
+  let (tx, rx) = mpsc::channel();
+  thread::spawn(move || tx.send(child.wait().map(|status| status.success())));
+  assert!(rx.recv_timeout(Duration::from_secs(10))??);
+  Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_wait_timeout_after_wait_until_writing() -> anyhow::Result<()> {
+  use std::process::{Command, Stdio};
+
+  // A progress update, then far more log than the stderr pipe's buffer holds
+  let script = "\
+    echo 'frame=    1 fps=0.0 q=0.0 size=       0kB time=00:00:00.04 bitrate=   0.0kbits/s speed=N/A' >&2; \
+    i=0; while [ $i -lt 4000 ]; do \
+      echo '[info] A log line to fill the pipe buffer while nothing reads it' >&2; i=$((i+1)); \
+    done";
+  let inner = Command::new("sh")
+    .args(["-c", script])
+    .stdin(Stdio::null())
+    .stdout(Stdio::null())
+    .stderr(Stdio::piped())
+    .spawn()?;
+  let mut child = crate::child::FfmpegChild::from_backend(inner);
+  child.wait_until_writing(Duration::from_secs(10))?;
+
+  let status = child.wait_timeout(Duration::from_secs(10))?;
+  assert!(status.is_some_and(|status| status.success()));
+  Ok(())
+}
+
+#[test]
+fn test_doctor() {
+  use crate::doctor::CheckStatus;
+
+  let report = crate::doctor();
+  assert!(report.is_ok(), "{report}");
+  for name in ["binary", "version", "lavfi", "rawvideo_pipe"] {
+    let check = report.check(name).unwrap();
+    assert!(matches!(check.status, CheckStatus::Passed(_)), "{report}");
+  }
+  assert!(report.check("named_pipe").is_some());
+}
+
+#[test]
+fn test_fake_backend() -> anyhow::Result<()> {
+  let log = "\
+[info] Input #0, lavfi, from 'testsrc':
+[info]   Duration: N/A, start: 0.000000, bitrate: N/A
+[info]   Stream #0:0: Video: wrapped_avframe, rgb24, 4x2 [SAR 1:1 DAR 2:1], 25 fps, 25 tbr, 25 tbn
+[info] Stream mapping:
+[info]   Stream #0:0 -> #0:0 (wrapped_avframe (native) -> rawvideo (native))
+[info] Output #0, rawvideo, to 'pipe:':
+[info]   Stream #0:0: Video: rawvideo (RGB[24] / 0x18424752), rgb24(progressive), 4x2 [SAR 1:1 DAR 2:1], q=2-31, 4800 kb/s, 25 fps, 25 tbn
+[info] frame=    3 fps=0.0 q=-0.0 Lsize=       0KiB time=00:00:00.12 bitrate=   4.8kbits/s speed=  10x
+";
+  let child = fake_child(log, (0..3 * 4 * 2 * 3).map(|i| i as u8).collect());
+
+  let mut frames = Vec::new();
+  let mut exit_status = None;
+  for event in child.into_events()? {
+    match event {
+      FfmpegEvent::OutputFrame(frame) => frames.push(frame),
+      FfmpegEvent::Completed {
+        exit_status: status,
+        ..
+      } => exit_status = status,
+      _ => {}
+    }
+  }
+  assert_eq!(frames.len(), 3);
+  assert_eq!(frames[1].data[0], 24);
+  assert_eq!((frames[2].frame_num, frames[2].width), (2, 4));
+  assert!(exit_status.unwrap().success());
+  Ok(())
+}
+
+/// An in-memory FFmpeg process which writes `log` to stderr and `stdout` to
+/// stdout, then exits successfully.
+fn fake_child(log: &str, stdout: Vec<u8>) -> crate::child::FfmpegChild<FakeProcess> {
+  crate::child::FfmpegChild::from_backend(FakeProcess {
+    stdin: Some(std::io::sink()),
+    stdout: Some(std::io::Cursor::new(stdout)),
+    stderr: Some(std::io::Cursor::new(log.as_bytes().to_vec())),
+  })
+}
+
+struct FakeProcess<Stdout = std::io::Cursor<Vec<u8>>> {
+  stdin: Option<std::io::Sink>,
+  stdout: Option<Stdout>,
+  stderr: Option<std::io::Cursor<Vec<u8>>>,
+}
+
+impl<Stdout: std::io::Read + Send + 'static> crate::backend::ProcessBackend
+  for FakeProcess<Stdout>
+{
+  type Stdin = std::io::Sink;
+  type Stdout = Stdout;
+  type Stderr = std::io::Cursor<Vec<u8>>;
+
+  fn stdin(&mut self) -> &mut Option<Self::Stdin> {
+    &mut self.stdin
+  }
+  fn stdout(&mut self) -> &mut Option<Self::Stdout> {
+    &mut self.stdout
+  }
+  fn stderr(&mut self) -> &mut Option<Self::Stderr> {
+    &mut self.stderr
+  }
+  fn id(&self) -> Option<u32> {
+    None
+  }
+  fn kill(&mut self) -> std::io::Result<()> {
+    Ok(())
+  }
+  fn wait(&mut self) -> std::io::Result<std::process::ExitStatus> {
+    Ok(std::process::ExitStatus::default())
+  }
+  fn try_wait(&mut self) -> std::io::Result<Option<std::process::ExitStatus>> {
+    Ok(Some(std::process::ExitStatus::default()))
+  }
+}
+
+#[test]
+fn test_split_channels_stamps_output_before_split() -> anyhow::Result<()> {
+  let log = "\
+[info] Input #0, lavfi, from 'testsrc':
+[info]   Duration: N/A, start: 0.000000, bitrate: N/A
+[info]   Stream #0:0: Video: wrapped_avframe, rgb24, 4x2 [SAR 1:1 DAR 2:1], 25 fps, 25 tbr, 25 tbn
+[info] Stream mapping:
+[info]   Stream #0:0 -> #0:0 (wrapped_avframe (native) -> rawvideo (native))
+[info] Output #0, rawvideo, to 'pipe:':
+[info]   Stream #0:0: Video: rawvideo (RGB[24] / 0x18424752), rgb24(progressive), 4x2 [SAR 1:1 DAR 2:1], q=2-31, 4800 kb/s, 25 fps, 25 tbn
+[info] frame=    3 fps=0.0 q=-0.0 Lsize=       0KiB time=00:00:00.12 bitrate=   4.8kbits/s speed=  10x
+";
+  let child = fake_child(log, vec![0; 3 * 4 * 2 * 3]);
+
+  // Only the log channel is consumed; the frames are never received
+  let (logs, frames) = child.into_events()?.split_channels();
+  let mut had_output = None;
+  let mut timings = None;
+  for event in logs {
+    match event {
+      FfmpegEvent::Completed {
+        had_output: output, ..
+      } => had_output = Some(output),
+      FfmpegEvent::StartupTimings(startup) => timings = Some(startup),
+      _ => {}
+    }
+  }
+  drop(frames);
+  assert_eq!(had_output, Some(true));
+  let timings = timings.unwrap();
+  assert!(timings.first_log.unwrap() <= timings.metadata_complete.unwrap());
+  assert!(timings.first_output.is_some());
+  Ok(())
+}
+
+#[test]
+fn test_raw_log_lines() -> anyhow::Result<()> {
+  let log = b"[info] Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'caf\xe9.mp4':\n\
+    [info]   Duration: 00:00:10.00, start: 0.000000, bitrate: 1205 kb/s\n";
+  let config = crate::command::CommandConfig {
+    raw_log_lines: true,
+    ..Default::default()
+  };
+  let child = crate::child::FfmpegChild::<FakeProcess>::from_inner(
+    FakeProcess {
+      stdin: Some(std::io::sink()),
+      stdout: None,
+      stderr: Some(std::io::Cursor::new(log.to_vec())),
+    },
+    config,
+  );
+
+  let events: Vec<_> = child.into_events()?.collect();
+  let FfmpegEvent::ParsedInput(input) = &events[0] else {
+    panic!("expected an input, got {:?}", events[0]);
+  };
+  assert!(input.raw_log_message.ends_with("from 'caf\u{FFFD}.mp4':"));
+  let FfmpegEvent::RawLogLine(bytes) = &events[1] else {
+    panic!("expected the raw line, got {:?}", events[1]);
+  };
+  assert!(bytes.ends_with(b"from 'caf\xe9.mp4':"));
+  // Only lines which weren't valid UTF-8 are repeated
+  let raw_lines = events
+    .iter()
+    .filter(|event| matches!(event, FfmpegEvent::RawLogLine(_)));
+  assert_eq!(raw_lines.count(), 1);
+  Ok(())
+}
+
+#[test]
+fn test_encoder_fallback_keeps_startup_timings() -> anyhow::Result<()> {
+  /// Stdout which takes a while to produce its first chunk.
+  struct Delayed(std::io::Cursor<Vec<u8>>);
+
+  impl std::io::Read for Delayed {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+      thread::sleep(Duration::from_millis(50));
+      self.0.read(buf)
+    }
+  }
+
+  // The mapping of a filtergraph output doesn't name its encoder, so the
+  // fallback is only known from the encoder line
+  let log = "\
+[info] Input #0, lavfi, from 'testsrc':
+[info]   Duration: N/A, start: 0.000000, bitrate: N/A
+[info]   Stream #0:0: Video: wrapped_avframe, rgb24, 4x2 [SAR 1:1 DAR 2:1], 25 fps, 25 tbr, 25 tbn
+[info] Stream mapping:
+[info]   Stream #0:0 (wrapped_avframe) -> scale:default
+[info]   scale:default -> Stream #0:0 (libx264)
+[info] Output #0, h264, to 'pipe:':
+[info]   Stream #0:0: Video: h264, yuv444p(progressive), 4x2 [SAR 1:1 DAR 2:1], q=2-31, 25 fps, 25 tbn
+[info]     Metadata:
+[info]       encoder         : Lavc60.3.100 libx264
+[info] frame=    3 fps=0.0 q=-0.0 Lsize=       1KiB time=00:00:00.12 bitrate=  48.0kbits/s speed=  10x
+";
+  let model = crate::args::ArgModel::from_args(["-c:v", "h264_nvenc", "-f", "h264", "-"]);
+  let config = crate::command::CommandConfig {
+    output_args: model.outputs,
+    ..Default::default()
+  };
+  let child = crate::child::FfmpegChild::from_inner(
+    FakeProcess {
+      stdin: Some(std::io::sink()),
+      stdout: Some(Delayed(std::io::Cursor::new(vec![0; 64]))),
+      stderr: Some(std::io::Cursor::new(log.as_bytes().to_vec())),
+    },
+    config,
+  );
+
+  let mut iter = child.into_events()?;
+  let mut events: Vec<_> = iter
+    .by_ref()
+    .take_while(|e| !matches!(e, FfmpegEvent::Log(_, line) if line.ends_with("Metadata:")))
+    .collect();
+  // The encoder line is sent before stdout is read, and received after, so
+  // the startup timings become due along with the fallback
+  thread::sleep(Duration::from_millis(200));
+  events.extend(iter);
+
+  assert!(events
+    .iter()
+    .any(|e| matches!(e, FfmpegEvent::StartupTimings(_))));
+  let fallback = events.iter().find_map(|e| match e {
+    FfmpegEvent::EncoderFallback {
+      requested, actual, ..
+    } => Some((requested.as_str(), actual.as_str())),
+    _ => None,
+  });
+  assert_eq!(fallback, Some(("h264_nvenc", "libx264")));
+  Ok(())
+}
+
+#[test]
+fn test_error_blocks() -> anyhow::Result<()> {
+  let blocks: Vec<_> = FfmpegCommand::new()
+    .testsrc()
+    .codec_video("rawvideo")
+    .format("mp4")
+    .output("-")
+    .error_blocks()
+    .spawn()?
+    .iter()?
+    .filter_map(|event| match event {
+      FfmpegEvent::ErrorBlock { component, lines } => Some((component, lines)),
+      _ => None,
+    })
+    .collect();
+  let (_, lines) = blocks
+    .iter()
+    .find(|(component, _)| component.as_deref() == Some("mp4"))
+    .unwrap();
+  assert!(lines.iter().any(|line| line.contains("Could not find tag")));
+  Ok(())
+}
+
+#[test]
+fn test_command_template() -> anyhow::Result<()> {
+  let mut command = FfmpegCommand::new();
+  command
+    .env_clear()
+    .env("FONTCONFIG_FILE", "fonts.conf")
+    .current_dir("/tmp")
+    .input("{input}")
+    .args(["-vf", "drawtext=text='%{pts}'"])
+    .output("{output}");
+  let template = command.to_template()?;
+
+  let mut instance = template.instantiate_with([("input", "a.mov"), ("output", "a.mp4")])?;
+  let args: Vec<_> = instance.get_args().collect();
+  let expected: Vec<_> = command
+    .get_args()
+    .map(|arg| match arg.to_str() {
+      Some("{input}") => "a.mov".as_ref(),
+      Some("{output}") => "a.mp4".as_ref(),
+      _ => arg,
+    })
+    .collect();
+  assert_eq!(args, expected);
+  assert!(args.contains(&"drawtext=text='%{pts}'".as_ref()));
+  let inner = instance.as_inner();
+  assert_eq!(inner.get_current_dir(), Some("/tmp".as_ref()));
+  let envs: Vec<_> = inner.get_envs().collect();
+  assert_eq!(
+    envs,
+    [("FONTCONFIG_FILE".as_ref(), Some("fonts.conf".as_ref()))]
+  );
+
+  let unchanged = template.clone().instantiate();
+  assert!(unchanged.get_args().any(|arg| arg == "{input}"));
+  assert_eq!(template.placeholders(), ["input", "output"]);
+  assert!(template.instantiate_with([("input", "a.mov")]).is_err());
+
+  let file = std::fs::File::open(std::env::current_exe()?)?;
+  assert!(FfmpegCommand::new()
+    .input_from_stdio(file)
+    .output("out.mp4")
+    .to_template()
+    .is_err());
+  Ok(())
+}
+
+#[test]
+fn test_non_ascii_paths() -> anyhow::Result<()> {
+  use crate::frame_grabber::FrameGrabber;
+  use std::path::Path;
+
+  let dir = Path::new("output").join("vidéo 日本");
+  std::fs::create_dir_all(&dir)?;
+  let source = dir.join("ソース ✓.mp4");
+  let copy = dir.join("copie é.mkv");
+  FfmpegCommand::new()
+    .overwrite()
+    .args(["-f", "lavfi", "-i", "testsrc=duration=1:rate=10"])
+    .output(&source)
+    .spawn()?
+    .wait()?;
+  let status = FfmpegCommand::new()
+    .overwrite()
+    .input(&source)
+    .args(["-c", "copy"])
+    .output(&copy)
+    .spawn()?
+    .wait()?;
+  assert!(status.success());
+  assert!(copy.exists());
+  assert!(FrameGrabber::open(&copy).is_ok());
+  Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn test_non_utf8_path_args() {
+  use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+  let input = OsStr::from_bytes(b"output/caf\xe9.mov");
+  let output = OsStr::from_bytes(b"output/caf\xe9.mp4");
+  let mut command = FfmpegCommand::new();
+  command.input(input).output(output);
+  let args: Vec<_> = command.get_args().collect();
+  assert!(args.ends_with(&[OsStr::new("-i"), input, output]));
+}
+
+#[test]
+fn test_transformed_frame_writer() -> anyhow::Result<()> {
+  use crate::{
+    event::OutputVideoFrame,
+    frame_pump::{InputFramePump, RetimeStats, TransformedFrameWriter},
+  };
+
+  let mut writer = TransformedFrameWriter::new(InputFramePump {
+    pix_fmt: "gray".to_string(),
+    width: 1,
+    height: 1,
+    fps: 10.0,
+  });
+  let frame = |frame_num: u32, value: u8| OutputVideoFrame {
+    width: 1,
+    height: 1,
+    pix_fmt: "gray".to_string(),
+    output_index: 0,
+    data: vec![value],
+    frame_num,
+    timestamp: frame_num as f32 / 10.0,
+    output_tag: None,
+  };
+
+  // Frames 2 and 3 were dropped by the transform, and 5 arrived twice
+  let mut out = Vec::new();
+  let times = [(0, 10), (1, 11), (4, 14), (5, 15), (5, 16), (6, 17)]
+    .into_iter()
+    .map(|(frame_num, value)| writer.push(&mut out, frame(frame_num, value)))
+    .collect::<std::io::Result<Vec<_>>>()?;
+  let stats = writer.finish(&mut out)?;
+
+  assert_eq!(out, [10, 11, 11, 11, 14, 15, 17]);
+  assert_eq!(
+    times,
+    [Some(0.0), Some(0.1), Some(0.4), Some(0.5), None, Some(0.6)]
+  );
+  assert_eq!(
+    stats,
+    RetimeStats {
+      written: 7,
+      duplicated: 2,
+      dropped: 1,
+    }
+  );
+
+  let mut out = Vec::new();
+  let mut writer = TransformedFrameWriter::new(InputFramePump {
+    pix_fmt: "gray".to_string(),
+    width: 2,
+    height: 1,
+    fps: 10.0,
+  });
+  assert!(writer.push(&mut out, frame(0, 0)).is_err());
+  Ok(())
+}
+
+#[test]
+fn test_synced_av_iterator() -> anyhow::Result<()> {
+  use crate::{
+    av_sync::{PcmFormat, SyncedAvIterator},
+    event::VideoStream,
+  };
+  use std::io::Cursor;
+
+  let video_format = VideoStream {
+    pix_fmt: "gray".to_string(),
+    width: 1,
+    height: 1,
+    fps: 10.0,
+  };
+  let audio_format = PcmFormat::new("u8", 25, 1);
+  let pairs = SyncedAvIterator::new(
+    Cursor::new(vec![0, 1, 2]),
+    &video_format,
+    Cursor::new((0..7).collect::<Vec<u8>>()),
+    &audio_format,
+  )?
+  .collect::<std::io::Result<Vec<_>>>()?;
+
+  let frames: Vec<_> = pairs.iter().map(|pair| pair.video_frame.data[0]).collect();
+  assert_eq!(frames, [0, 1, 2]);
+  let audio: Vec<_> = pairs
+    .iter()
+    .map(|pair| pair.audio_samples.clone())
+    .collect();
+  assert_eq!(audio, [vec![0, 1, 2], vec![3, 4], vec![5, 6]]);
+  Ok(())
+}
+
+#[test]
+fn test_synced_av_iterator_from_ffmpeg() -> anyhow::Result<()> {
+  use crate::{
+    av_sync::{PcmFormat, SyncedAvIterator},
+    event::VideoStream,
+    tcp_output::tcp_outputs,
+  };
+
+  let video_format = VideoStream {
+    pix_fmt: "gray".to_string(),
+    width: 32,
+    height: 24,
+    fps: 25.0,
+  };
+  let audio_format = PcmFormat::new("s16le", 8000, 1);
+  let [video, audio] = <[_; 2]>::try_from(tcp_outputs(2)?).unwrap();
+  let mut command = FfmpegCommand::new();
+  command
+    .args(["-f", "lavfi", "-i", "testsrc=duration=1"])
+    .args(["-f", "lavfi", "-i", "sine=duration=1"])
+    .map("0:v")
+    .format("rawvideo")
+    .pix_fmt("gray")
+    .size(32, 24)
+    .rate(25.0)
+    .output(video.url());
+  audio_format
+    .configure(command.map("1:a"))
+    .output(audio.url());
+  let mut child = command.spawn()?;
+
+  let timeout = Duration::from_secs(5);
+  let pairs = SyncedAvIterator::new(
+    video.accept(timeout)?,
+    &video_format,
+    audio.accept(timeout)?,
+    &audio_format,
+  )?
+  .collect::<std::io::Result<Vec<_>>>()?;
+  assert!(child.wait()?.success());
+
+  assert_eq!(pairs.len(), 25);
+  // The resampler may end the audio a few samples short
+  assert!(pairs[..24]
+    .iter()
+    .all(|pair| pair.audio_samples.len() == 8000 / 25 * 2));
+  Ok(())
+}
+
+#[test]
+fn test_consumer_closed_wait() -> anyhow::Result<()> {
+  let mut child = FfmpegCommand::new()
+    .args(["-f", "lavfi", "-i", "testsrc=size=32x24"])
+    .rawvideo()
+    .spawn()?;
+  let frames = child.iter()?.filter_frames().take(3).count();
+  assert_eq!(frames, 3);
+
+  // The endless input would otherwise keep FFmpeg running
+  let start = std::time::Instant::now();
+  child.wait()?;
+  assert!(start.elapsed() < Duration::from_secs(5));
+  Ok(())
+}
+
+#[test]
+fn test_consumer_closed_event() -> anyhow::Result<()> {
+  let mut child = FfmpegCommand::new()
+    .args(["-f", "lavfi", "-i", "testsrc=size=32x24"])
+    .rawvideo()
+    .spawn()?;
+  let (logs, frames) = child.iter()?.split_channels();
+  let frames = frames
+    .filter(|event| matches!(event, FfmpegEvent::OutputFrame(_)))
+    .take(3)
+    .count();
+  assert_eq!(frames, 3);
+
+  let events: Vec<_> = logs.collect();
+  child.wait()?;
+  let closed = events
+    .iter()
+    .filter(|event| **event == FfmpegEvent::ConsumerClosed)
+    .count();
+  assert_eq!(closed, 1);
+  assert!(!events.iter().any(|event| matches!(
+    event,
+    FfmpegEvent::Log(LogLevel::Error | LogLevel::Fatal, line) if line.contains("Broken pipe")
+  )));
+  Ok(())
+}
+
+#[test]
+fn test_transcribe_requires_whisper() -> anyhow::Result<()> {
+  use crate::{
+    transcribe::{transcribe, TranscribeOptions},
+    version::ffmpeg_features,
+  };
+
+  if ffmpeg_features()?.has_whisper {
+    return Ok(());
+  }
+  let err = transcribe(
+    "input.mp4",
+    "ggml-base.en.bin",
+    &TranscribeOptions::default(),
+  )
+  .err()
+  .unwrap();
+  assert!(err.to_string().contains("--enable-whisper"));
+  Ok(())
+}
+
+#[test]
+fn test_capture_filter_output() -> anyhow::Result<()> {
+  use crate::filter_output::FilterOutputCapture;
+
+  let vstats = FilterOutputCapture::file("vstats")?;
+  let segments = FilterOutputCapture::tcp("segments")?;
+  let dir = std::env::temp_dir().join(format!("capture-test-{}", std::process::id()));
+  std::fs::create_dir_all(&dir)?;
+  let events: Vec<_> = FfmpegCommand::new()
+    .args(["-f", "lavfi", "-i", "testsrc=duration=2:size=64x48:rate=10"])
+    .args(["-vstats_file", &vstats.destination()])
+    .codec_video("mpeg4")
+    .args(["-g", "5"])
+    .format("segment")
+    .args(["-segment_time", "1", "-segment_list_type", "flat"])
+    .args(["-segment_list", &segments.destination()])
+    .output_path(dir.join("out_%03d.avi"))
+    .capture_filter_output(&vstats)
+    .capture_filter_output(&segments)
+    .spawn()?
+    .iter()?
+    .collect();
+  std::fs::remove_dir_all(&dir).ok();
+
+  let lines = |capture: &str| -> Vec<String> {
+    events
+      .iter()
+      .filter_map(|event| match event {
+        FfmpegEvent::FilterOutput { name, line } if name == capture => Some(line.clone()),
+        _ => None,
+      })
+      .collect()
+  };
+  assert_eq!(lines("vstats").len(), 20);
+  assert!(lines("vstats")[0].starts_with("frame="));
+  assert!(lines("segments").contains(&"out_000.avi".to_string()));
+  Ok(())
+}
+
+#[test]
+fn test_stdin_mode() -> anyhow::Result<()> {
+  use crate::command::StdinMode;
+
+  let mut command = FfmpegCommand::new();
+  command.stdin_mode(StdinMode::Null).testsrc().rawvideo();
+  let template = command.to_template()?;
+  for mut command in [command, template.instantiate()] {
+    let mut child = command.spawn()?;
+    assert!(child.take_stdin().is_none());
+    let err = child.quit().unwrap_err();
+    assert!(err.to_string().contains("Null"));
+    assert!(child.iter()?.filter_frames().count() > 0);
+    assert!(child.wait()?.success());
+  }
+  Ok(())
+}
+
+#[test]
+fn test_iter_without_stderr() -> anyhow::Result<()> {
+  use crate::iter::IterError;
+
+  // The frame size and rate are probed from the input
+  let mut child = FfmpegCommand::new().testsrc().rawvideo().spawn()?;
+  drop(child.take_stderr());
+  let events: Vec<_> = child.iter()?.collect();
+  let frames: Vec<_> = events
+    .iter()
+    .filter_map(|event| match event {
+      FfmpegEvent::OutputFrame(frame) => Some(frame),
+      _ => None,
+    })
+    .collect();
+  assert_eq!(frames.len(), 250);
+  assert_eq!((frames[0].width, frames[0].height), (320, 240));
+  assert!(events.contains(&FfmpegEvent::LogEOF));
+  child.wait()?;
+
+  let mut child = FfmpegCommand::new().testsrc().rawvideo().spawn()?;
+  child.take_stderr();
+  child.take_stdout();
+  let err = child.iter().err().unwrap();
+  assert_eq!(err.downcast_ref::<IterError>(), Some(&IterError::NoPipes));
+  child.kill()?;
+  Ok(())
+}
+
+#[test]
+fn test_filter_complex_auto() -> anyhow::Result<()> {
+  // A chain too long to pass as an argument
+  let graph = vec!["null"; 5000].join(",");
+  let mut command = FfmpegCommand::new();
+  command
+    .args(["-f", "lavfi", "-i", "testsrc=duration=1"])
+    .filter_complex_auto(&graph)?
+    .format("null")
+    .output("-");
+  let args: Vec<_> = command.get_args().collect();
+  let flag = args
+    .iter()
+    .position(|arg| *arg == "-filter_complex_script")
+    .unwrap();
+  let script = std::path::PathBuf::from(args[flag + 1]);
+
+  let mut child = command.spawn()?;
+  drop(command);
+  assert!(script.exists());
+  assert!(child.wait()?.success());
+  drop(child);
+  assert!(!script.exists());
+  Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_filter_complex_auto_outlives_command() -> anyhow::Result<()> {
+  use std::os::unix::fs::PermissionsExt;
+
+  // Stands in for FFmpeg, only opening the filter script after a delay
+  let fake_ffmpeg = crate::temp_file::TempFile::create(
+    "fake_ffmpeg",
+    "sh",
+    b"#!/bin/sh\n\
+      sleep 0.5\n\
+      while [ $# -gt 0 ]; do\n\
+        [ \"$1\" = -filter_complex_script ] && cat \"$2\" > /dev/null \\\n\
+          && echo '[info] Read the filter script' >&2\n\
+        shift\n\
+      done\n",
+  )?;
+  std::fs::set_permissions(fake_ffmpeg.path(), std::fs::Permissions::from_mode(0o755))?;
+
+  // Both the command and the child are dropped at the end of the statement
+  let graph = vec!["null"; 5000].join(",");
+  let events = FfmpegCommand::new_with_path(fake_ffmpeg.path())
+    .filter_complex_auto(&graph)?
+    .spawn()?
+    .iter()?;
+  let logs: Vec<String> = events
+    .filter_map(|event| match event {
+      FfmpegEvent::Log(_, line) => Some(line),
+      _ => None,
+    })
+    .collect();
+  assert!(
+    logs
+      .iter()
+      .any(|line| line.contains("Read the filter script")),
+    "{logs:?}"
+  );
+  Ok(())
+}
+
+#[test]
+fn test_iter_with_timeout() -> anyhow::Result<()> {
+  // Logs nothing while reading its input in realtime
+  let start = std::time::Instant::now();
+  let events: Vec<_> = FfmpegCommand::new()
+    .quiet()
+    .no_stats()
+    .realtime()
+    .testsrc()
+    .format("null")
+    .output("-")
+    .spawn()?
+    .iter()?
+    .with_timeout(Duration::from_millis(500))
+    .collect();
+  assert!(start.elapsed() < Duration::from_secs(5));
+  assert!(events
+    .iter()
+    .any(|event| matches!(event, FfmpegEvent::Error(e) if e.contains("killed"))));
+  assert!(events.contains(&FfmpegEvent::LogEOF));
+  Ok(())
+}
+
+#[test]
+fn test_wait_timeout() -> anyhow::Result<()> {
+  let mut child = FfmpegCommand::new()
+    .realtime()
+    .testsrc()
+    .format("null")
+    .output("-")
+    .spawn()?;
+  assert_eq!(child.wait_timeout(Duration::from_millis(200))?, None);
+  child.kill()?;
+  assert!(child.wait_timeout(Duration::from_secs(5))?.is_some());
+  Ok(())
+}
+
+#[test]
+fn test_output_audio_samples() -> anyhow::Result<()> {
+  use crate::{audio_levels::SampleFormat, event::AudioSamples};
+
+  let events: Vec<_> = FfmpegCommand::new()
+    .sine(440.0, Duration::from_secs(1))
+    .args(["-ac", "2", "-ar", "8000", "-f", "s16le", "-"])
+    .audio_samples()
+    .spawn()?
+    .iter()?
+    .collect();
+  assert!(!events
+    .iter()
+    .any(|event| matches!(event, FfmpegEvent::OutputChunk(_))));
+  let blocks: Vec<_> = events
+    .into_iter()
+    .filter_map(|event| match event {
+      FfmpegEvent::OutputAudioSamples(block) => Some(block),
+      _ => None,
+    })
+    .collect();
+  assert_eq!(blocks[0].sample_rate, 8000);
+  assert_eq!(blocks[0].channels, 2);
+  assert_eq!(blocks[0].sample_format, SampleFormat::S16Le);
+  assert!(matches!(blocks[0].samples, AudioSamples::S16(_)));
+  let frames: usize = blocks.iter().map(|block| block.frames()).sum();
+  assert_eq!(frames, 8000);
+
+  // Without `audio_samples`, the adapter decodes the chunks itself
+  let frames: usize = FfmpegCommand::new()
+    .sine(440.0, Duration::from_secs(1))
+    .args(["-ar", "8000", "-f", "f32le", "-"])
+    .spawn()?
+    .iter()?
+    .filter_audio()
+    .map(|block| block.frames())
+    .sum();
+  assert_eq!(frames, 8000);
+  Ok(())
+}
+
+#[test]
+fn test_collect_metadata_no_inputs() {
+  use crate::metadata::MetadataError;
+
+  // A capture device which doesn't exist
+  let log = "\
+[dshow @ 000001c4] [error] Could not find video device with name [Missing Camera] among source devices of type video.
+[in#0 @ 000001c3] [error] Error opening input: I/O error
+[error] Error opening input file video=Missing Camera.
+[error] Error opening input files: I/O error
+";
+  let error = fake_child(log, Vec::new())
+    .iter()
+    .unwrap()
+    .collect_metadata()
+    .unwrap_err();
+  match error.downcast_ref::<MetadataError>() {
+    Some(MetadataError::NoInputs(errors)) => {
+      assert!(
+        errors.iter().any(|e| e.contains("Missing Camera")),
+        "{errors:?}"
+      )
+    }
+    other => panic!("{other:?}"),
+  }
+}
+
+#[test]
+fn test_convert_subtitles() -> anyhow::Result<()> {
+  use crate::subtitles::{convert_subtitles, SubtitleError, SubtitleFormat};
+
+  std::fs::create_dir_all("output")?;
+  let path = "output/test_convert_subtitles.srt";
+  // Latin-1 encoded, which is only read correctly with `-sub_charenc`
+  std::fs::write(path, b"1\n00:00:01,000 --> 00:00:02,500\nCaf\xe9\n\n")?;
+
+  let vtt = convert_subtitles(path, 0, SubtitleFormat::WebVtt)?;
+  assert!(vtt.starts_with("WEBVTT"), "{vtt}");
+  assert!(vtt.contains("00:01.000 --> 00:02.500"), "{vtt}");
+  assert!(vtt.contains("Café"), "{vtt}");
+
+  let error = convert_subtitles(path, 1, SubtitleFormat::Ass).unwrap_err();
+  match error.downcast_ref::<SubtitleError>() {
+    Some(SubtitleError::StreamNotFound { available, .. }) => {
+      assert_eq!(available.len(), 1);
+      assert_eq!(available[0].format, "subrip");
+    }
+    other => panic!("{other:?}"),
+  }
+  Ok(())
+}
+
+#[test]
+fn test_parsed_error() -> anyhow::Result<()> {
+  use crate::event::FfmpegError;
+
+  let errors: Vec<_> = FfmpegCommand::new()
+    .input("nonexistent-input.mp4")
+    .output("-")
+    .spawn()?
+    .iter()?
+    .filter_map(|event| match event {
+      FfmpegEvent::ParsedError(error) => Some(error),
+      _ => None,
+    })
+    .collect();
+  assert!(
+    errors
+      .iter()
+      .any(|error| matches!(error, FfmpegError::FileNotFound { .. })),
+    "{errors:?}"
+  );
+  Ok(())
+}
+
+#[test]
+fn test_output_pipe() -> anyhow::Result<()> {
+  let mut command = FfmpegCommand::new();
+  command
+    .args(["-f", "lavfi", "-i", "testsrc=duration=2:size=64x48:rate=10"])
+    .args(["-map", "0:v", "-f", "rawvideo", "-pix_fmt", "rgb24"])
+    .output_pipe("full")
+    .args([
+      "-map", "0:v", "-f", "rawvideo", "-pix_fmt", "gray", "-r", "2",
+    ])
+    .output_pipe("preview")
+    .args(["-map", "0:v", "-c:v", "mpeg4", "-f", "avi"])
+    .output_pipe("encoded");
+  assert!(command.to_template().is_err());
+  let events: Vec<_> = command.spawn()?.iter()?.collect();
+
+  let frames = |tag: &str| {
+    events
+      .iter()
+      .filter(|event| matches!(event, FfmpegEvent::OutputFrame(frame) if frame.output_tag.as_deref() == Some(tag)))
+      .count()
+  };
+  assert_eq!(frames("full"), 20);
+  assert_eq!(frames("preview"), 4);
+  assert!(events.iter().any(|event| matches!(
+    event,
+    FfmpegEvent::OutputPipeChunk { label, chunk } if label == "encoded" && chunk.starts_with(b"RIFF")
+  )));
+  assert!(!events
+    .iter()
+    .any(|event| matches!(event, FfmpegEvent::Error(_))));
+  Ok(())
+}
+
+#[test]
+fn test_output_pipe_mixed_codecs() -> anyhow::Result<()> {
+  // The raw output fills its socket long before x264's lookahead lets the
+  // encoded output be described
+  let events: Vec<_> = FfmpegCommand::new()
+    .args([
+      "-f",
+      "lavfi",
+      "-i",
+      "testsrc=duration=4:size=640x480:rate=25",
+    ])
+    .args(["-map", "0:v", "-f", "rawvideo", "-pix_fmt", "rgb24"])
+    .output_pipe("raw")
+    .args(["-map", "0:v", "-c:v", "libx264", "-f", "h264"])
+    .output_pipe("encoded")
+    .spawn()?
+    .iter()?
+    .collect();
+
+  let raw = events
+    .iter()
+    .filter(|event| matches!(event, FfmpegEvent::OutputFrame(frame) if frame.output_tag.as_deref() == Some("raw")))
+    .count();
+  assert_eq!(raw, 100);
+  assert!(events.iter().any(|event| matches!(
+    event,
+    FfmpegEvent::OutputPipeChunk { label, .. } if label == "encoded"
+  )));
+  assert!(!events
+    .iter()
+    .any(|event| matches!(event, FfmpegEvent::Error(_))));
+  Ok(())
+}
+
+#[test]
+fn test_frame_writer() -> anyhow::Result<()> {
+  use crate::frame_pump::InputFramePump;
+
+  let pump = InputFramePump {
+    pix_fmt: "gray".to_string(),
+    width: 32,
+    height: 24,
+    fps: 10.0,
+  };
+  let mut child = FfmpegCommand::new()
+    .frame_input(&pump)
+    .format("rawvideo")
+    .output("-")
+    .spawn()?;
+  let mut writer = child.frame_writer()?;
+  assert!(child.frame_writer().is_err());
+  let written = std::thread::spawn(move || {
+    for i in 0..10u8 {
+      writer.write_raw(&[i; 32 * 24])?;
+    }
+    writer.finish()
+  });
+
+  let frames: Vec<_> = child.iter()?.filter_frames().collect();
+  assert_eq!(written.join().unwrap()?, 10);
+  assert_eq!(frames.len(), 10);
+  assert_eq!(frames[9].data, [9; 32 * 24]);
+  Ok(())
+}
+
+#[test]
+fn test_select_program_probed() -> anyhow::Result<()> {
+  use crate::ffprobe::{ffprobe_programs, ProgramNotFound};
+
+  let path = std::env::temp_dir().join(format!("programs-{}.ts", std::process::id()));
+  FfmpegCommand::new()
+    .args(["-f", "lavfi", "-i", "testsrc=duration=1:size=64x48:rate=10"])
+    .args([
+      "-f",
+      "lavfi",
+      "-i",
+      "testsrc2=duration=1:size=64x48:rate=10",
+    ])
+    .args(["-map", "0:v", "-map", "1:v", "-c:v", "mpeg2video"])
+    .args([
+      "-program",
+      "program_num=1:st=0",
+      "-program",
+      "program_num=2:st=1",
+    ])
+    .overwrite()
+    .output_path(&path)
+    .spawn()?
+    .wait()?;
+
+  let programs = ffprobe_programs(&path)?;
+  assert_eq!(programs.len(), 2);
+  assert_eq!(programs[1].streams.len(), 1);
+
+  let mut command = FfmpegCommand::new();
+  command.input_path(&path);
+  let error = command.select_program_probed(99).err().unwrap();
+  let not_found = error.downcast_ref::<ProgramNotFound>().unwrap();
+  assert_eq!(not_found.available, programs);
+  command.select_program_probed(programs[1].program_id)?;
+  std::fs::remove_file(&path).ok();
+
+  // Probed with the input's format, rather than as a missing file
+  let mut command = FfmpegCommand::new();
+  command.format("lavfi").input("testsrc=duration=1");
+  let error = command.select_program_probed(1).err().unwrap();
+  let not_found = error.downcast_ref::<ProgramNotFound>().unwrap();
+  assert!(not_found.available.is_empty());
+  Ok(())
+}
+
+#[test]
+fn test_unsupported_codec() -> anyhow::Result<()> {
+  use crate::{container::container_supports_with, event::FfmpegError, lint::LintWarning};
+
+  let mut command = FfmpegCommand::new();
+  command
+    .args(["-f", "lavfi", "-i", "sine=duration=1"])
+    .codec_audio("pcm_s16le")
+    .args(["-f", "mp4", "-movflags", "frag_keyframe+empty_moov"])
+    .output("-");
+  assert!(matches!(
+    command.lint()[..],
+    [LintWarning::UnsupportedCodec { .. }]
+  ));
+
+  let errors: Vec<_> = command
+    .spawn()?
+    .iter()?
+    .filter_map(|event| match event {
+      FfmpegEvent::ParsedError(error) => Some(error),
+      _ => None,
+    })
+    .collect();
+  assert!(
+    errors.contains(&FfmpegError::UnsupportedCodec {
+      codec: "pcm_s16le".to_string(),
+      container: Some("mp4".to_string()),
+    }),
+    "{errors:?}"
+  );
+  assert!(!container_supports_with(
+    command.as_inner().get_program(),
+    "mp4",
+    "pcm_s16le"
+  ));
+  Ok(())
+}
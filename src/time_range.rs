@@ -0,0 +1,369 @@
+//! Time positions and durations for `-ss`, `-t` and `-to`, and validation of
+//! the ranges they select against the length of the inputs.
+//!
+//! FFmpeg doesn't complain about a range which lies partly or entirely past
+//! the end of an input: it silently writes a shorter, or empty, output. See
+//! [`FfmpegCommand::validate_time_ranges`](crate::command::FfmpegCommand::validate_time_ranges).
+
+use std::{fmt, time::Duration};
+
+use crate::{args::ArgModel, log_parser::parse_time_str};
+
+/// Ranges which overshoot the input by less than this are rounding, e.g. of
+/// a duration parsed from the log with centisecond precision.
+const TOLERANCE_SECS: f64 = 0.01;
+
+/// A time duration specification as accepted by FFmpeg, either
+/// `[-][HH:]MM:SS[.m...]` or `[-]S+[.m...][s|ms|us]`; see the [Time duration
+/// section in the ffmpeg-utils(1)
+/// manual](https://ffmpeg.org/ffmpeg-utils.html#time-duration-syntax).
+///
+/// Strings are passed to FFmpeg unchanged, while a [`Duration`] is written
+/// in seconds.
+///
+/// ```rust
+/// use ffmpeg_sidecar::time_range::FfmpegTimeDuration;
+/// use std::time::Duration;
+///
+/// assert_eq!(FfmpegTimeDuration::from("01:02.5").as_secs_f64(), Some(62.5));
+/// assert_eq!(FfmpegTimeDuration::from("-200ms").as_secs_f64(), Some(-0.2));
+/// assert_eq!(FfmpegTimeDuration::from(Duration::from_millis(1500)).as_str(), "1.500000");
+/// assert_eq!(FfmpegTimeDuration::from("soon").as_secs_f64(), None);
+/// assert_eq!(FfmpegTimeDuration::from("90s").as_duration(), Some(Duration::from_secs(90)));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FfmpegTimeDuration(String);
+
+impl FfmpegTimeDuration {
+  /// The specification as passed to FFmpeg.
+  pub fn as_str(&self) -> &str {
+    &self.0
+  }
+
+  /// The number of seconds, or `None` if FFmpeg wouldn't accept the
+  /// specification.
+  pub fn as_secs_f64(&self) -> Option<f64> {
+    let spec = self.0.trim();
+    let (sign, spec) = match spec.strip_prefix('-') {
+      Some(rest) => (-1.0, rest),
+      None => (1.0, spec),
+    };
+    if !spec.starts_with(|c: char| c.is_ascii_digit()) {
+      return None;
+    }
+    let seconds = if spec.contains(':') {
+      parse_time_str(spec)?
+    } else if let Some(millis) = spec.strip_suffix("ms") {
+      millis.parse::<f64>().ok()? / 1e3
+    } else if let Some(micros) = spec.strip_suffix("us") {
+      micros.parse::<f64>().ok()? / 1e6
+    } else {
+      spec.strip_suffix('s').unwrap_or(spec).parse().ok()?
+    };
+    seconds.is_finite().then_some(sign * seconds)
+  }
+
+  /// The length of time, or `None` if it's negative or invalid.
+  pub fn as_duration(&self) -> Option<Duration> {
+    Duration::try_from_secs_f64(self.as_secs_f64()?).ok()
+  }
+}
+
+impl fmt::Display for FfmpegTimeDuration {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.0)
+  }
+}
+
+impl AsRef<str> for FfmpegTimeDuration {
+  fn as_ref(&self) -> &str {
+    &self.0
+  }
+}
+
+impl PartialEq<str> for FfmpegTimeDuration {
+  fn eq(&self, other: &str) -> bool {
+    self.0 == other
+  }
+}
+
+impl PartialEq<&str> for FfmpegTimeDuration {
+  fn eq(&self, other: &&str) -> bool {
+    self.0 == *other
+  }
+}
+
+impl From<&str> for FfmpegTimeDuration {
+  fn from(spec: &str) -> Self {
+    Self(spec.to_string())
+  }
+}
+
+impl From<String> for FfmpegTimeDuration {
+  fn from(spec: String) -> Self {
+    Self(spec)
+  }
+}
+
+impl From<&String> for FfmpegTimeDuration {
+  fn from(spec: &String) -> Self {
+    Self(spec.clone())
+  }
+}
+
+impl From<Duration> for FfmpegTimeDuration {
+  fn from(duration: Duration) -> Self {
+    Self(format!("{:.6}", duration.as_secs_f64()))
+  }
+}
+
+/// The file whose `-ss`, `-t` or `-to` options a [`TimeRangeWarning`] is
+/// about, by its index among the inputs or outputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeRangeFile {
+  Input(usize),
+  Output(usize),
+}
+
+impl fmt::Display for TimeRangeFile {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      TimeRangeFile::Input(index) => write!(f, "input #{index}"),
+      TimeRangeFile::Output(index) => write!(f, "output #{index}"),
+    }
+  }
+}
+
+/// A time range which selects less than requested. Times are in seconds.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimeRangeWarning {
+  /// The value of `flag` isn't a time duration FFmpeg accepts.
+  InvalidTime {
+    file: TimeRangeFile,
+    flag: String,
+    value: String,
+  },
+  /// The range starts at or after the end of the media, so the output will
+  /// be empty.
+  StartsAfterEnd {
+    file: TimeRangeFile,
+    start: f64,
+    duration: f64,
+  },
+  /// The range ends before it starts, so the output will be empty.
+  EmptyRange {
+    file: TimeRangeFile,
+    start: f64,
+    end: f64,
+  },
+  /// The range ends after the end of the media, so the output will be
+  /// shorter than requested.
+  EndsAfterEnd {
+    file: TimeRangeFile,
+    end: f64,
+    duration: f64,
+  },
+}
+
+impl fmt::Display for TimeRangeWarning {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      TimeRangeWarning::InvalidTime { file, flag, value } => {
+        write!(f, "`{flag} {value}` of {file} isn't a valid time duration")
+      }
+      TimeRangeWarning::StartsAfterEnd {
+        file,
+        start,
+        duration,
+      } => write!(
+        f,
+        "{file} starts at {start}s, after the end of the {duration}s media; the output will be empty"
+      ),
+      TimeRangeWarning::EmptyRange { file, start, end } => write!(
+        f,
+        "{file} ends at {end}s, before it starts at {start}s; the output will be empty"
+      ),
+      TimeRangeWarning::EndsAfterEnd {
+        file,
+        end,
+        duration,
+      } => write!(
+        f,
+        "{file} ends at {end}s, after the end of the {duration}s media; the output will be shorter"
+      ),
+    }
+  }
+}
+
+/// Check the `-ss`, `-t` and `-to` options of every input against its
+/// duration, and those of every output against the longest input, after
+/// trimming the inputs by their own options. Inputs with an unknown duration
+/// are skipped.
+///
+/// ```rust
+/// use ffmpeg_sidecar::{
+///   args::ArgModel,
+///   time_range::{validate_time_ranges, TimeRangeFile, TimeRangeWarning},
+/// };
+///
+/// let model = ArgModel::from_args(["-ss", "90", "-i", "clip.mp4", "out.mp4"]);
+/// assert_eq!(
+///   validate_time_ranges(&model, &[Some(60.0)]),
+///   vec![TimeRangeWarning::StartsAfterEnd {
+///     file: TimeRangeFile::Input(0),
+///     start: 90.0,
+///     duration: 60.0,
+///   }]
+/// );
+/// ```
+pub fn validate_time_ranges(
+  model: &ArgModel,
+  input_durations: &[Option<f64>],
+) -> Vec<TimeRangeWarning> {
+  let mut warnings = Vec::new();
+  let mut longest_input: Option<f64> = None;
+  for (index, input) in model.inputs.iter().enumerate() {
+    let file = TimeRangeFile::Input(index);
+    let range = time_range(file, |flag| input.option(flag), &mut warnings);
+    let Some(&Some(duration)) = input_durations.get(index) else {
+      continue;
+    };
+    check_range(file, range, duration, &mut warnings);
+    // An empty input has been warned about already
+    let (start, end) = range;
+    let trimmed = end.unwrap_or(duration).min(duration) - start;
+    if trimmed > 0.0 {
+      longest_input = Some(longest_input.map_or(trimmed, |longest| longest.max(trimmed)));
+    }
+  }
+
+  for (index, output) in model.outputs.iter().enumerate() {
+    let file = TimeRangeFile::Output(index);
+    let range = time_range(file, |flag| output.option(flag), &mut warnings);
+    if let Some(duration) = longest_input {
+      check_range(file, range, duration, &mut warnings);
+    }
+  }
+  warnings
+}
+
+/// The start and end of the range selected by a file's options, recording a
+/// warning for any option which can't be parsed.
+fn time_range<'a>(
+  file: TimeRangeFile,
+  option: impl Fn(&str) -> Option<&'a str>,
+  warnings: &mut Vec<TimeRangeWarning>,
+) -> (f64, Option<f64>) {
+  let mut seconds = |flag: &str| {
+    let value = option(flag)?;
+    let seconds = FfmpegTimeDuration::from(value).as_secs_f64();
+    if seconds.is_none() {
+      warnings.push(TimeRangeWarning::InvalidTime {
+        file,
+        flag: flag.to_string(),
+        value: value.to_string(),
+      });
+    }
+    seconds
+  };
+  let start = seconds("-ss").unwrap_or(0.0);
+  // `-t` takes priority over `-to`
+  let end = match seconds("-t") {
+    Some(duration) => Some(start + duration),
+    None => seconds("-to"),
+  };
+  (start, end)
+}
+
+fn check_range(
+  file: TimeRangeFile,
+  (start, end): (f64, Option<f64>),
+  duration: f64,
+  warnings: &mut Vec<TimeRangeWarning>,
+) {
+  if start >= duration {
+    warnings.push(TimeRangeWarning::StartsAfterEnd {
+      file,
+      start,
+      duration,
+    });
+  } else if let Some(end) = end.filter(|&end| end <= start) {
+    warnings.push(TimeRangeWarning::EmptyRange { file, start, end });
+  } else if let Some(end) = end.filter(|&end| end > duration + TOLERANCE_SECS) {
+    warnings.push(TimeRangeWarning::EndsAfterEnd {
+      file,
+      end,
+      duration,
+    });
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_validate_time_ranges() {
+    let validate = |args: &[&str], durations: &[Option<f64>]| {
+      validate_time_ranges(&ArgModel::from_args(args.iter().copied()), durations)
+    };
+
+    // Within the input
+    assert!(validate(
+      &["-ss", "10", "-t", "20", "-i", "a.mp4", "out.mp4"],
+      &[Some(60.0)]
+    )
+    .is_empty());
+    assert!(validate(
+      &["-i", "a.mp4", "-to", "01:00.00", "out.mp4"],
+      &[Some(60.0)]
+    )
+    .is_empty());
+
+    assert_eq!(
+      validate(
+        &["-ss", "50", "-t", "20", "-i", "a.mp4", "out.mp4"],
+        &[Some(60.0)]
+      ),
+      vec![TimeRangeWarning::EndsAfterEnd {
+        file: TimeRangeFile::Input(0),
+        end: 70.0,
+        duration: 60.0,
+      }]
+    );
+    assert_eq!(
+      validate(
+        &["-i", "a.mp4", "-ss", "30", "-to", "20", "out.mp4"],
+        &[Some(60.0)]
+      ),
+      vec![TimeRangeWarning::EmptyRange {
+        file: TimeRangeFile::Output(0),
+        start: 30.0,
+        end: 20.0,
+      }]
+    );
+
+    // Outputs are checked against the longest input, after its own seek
+    assert_eq!(
+      validate(
+        &["-ss", "40", "-i", "a.mp4", "-i", "b.mp4", "-ss", "25", "out.mp4"],
+        &[Some(60.0), Some(10.0)]
+      ),
+      vec![TimeRangeWarning::StartsAfterEnd {
+        file: TimeRangeFile::Output(0),
+        start: 25.0,
+        duration: 20.0,
+      }]
+    );
+
+    // Unknown durations are skipped, but invalid times are always reported
+    assert_eq!(
+      validate(&["-ss", "later", "-i", "a.mp4", "out.mp4"], &[None]),
+      vec![TimeRangeWarning::InvalidTime {
+        file: TimeRangeFile::Input(0),
+        flag: "-ss".to_string(),
+        value: "later".to_string(),
+      }]
+    );
+  }
+}
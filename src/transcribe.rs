@@ -0,0 +1,332 @@
+//! Speech to text with FFmpeg's `whisper` audio filter, which runs a
+//! [whisper.cpp](https://github.com/ggml-org/whisper.cpp) model. Requires
+//! FFmpeg 8.0 or later, configured with `--enable-whisper`.
+
+use std::{
+  io::{BufRead, BufReader, Lines},
+  path::{Path, PathBuf},
+  process::ChildStdout,
+  thread::JoinHandle,
+  time::Duration,
+};
+
+use anyhow::Context;
+
+use crate::{
+  child::FfmpegChild,
+  command::FfmpegCommand,
+  event::{FfmpegEvent, LogLevel},
+  tee::escape,
+  version::ffmpeg_features,
+};
+
+/// How long FFmpeg is given to quit when a transcription is dropped early.
+const QUIT_GRACE: Duration = Duration::from_secs(2);
+
+/// One segment of speech recognized by the model.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TranscriptSegment {
+  /// The start of the segment, relative to the start of the input.
+  pub start: Duration,
+  pub end: Duration,
+  pub text: String,
+}
+
+/// Options of the `whisper` filter; see the ffmpeg-filters manual. The
+/// defaults detect the language and run on the first GPU.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranscribeOptions {
+  /// The spoken language, e.g. `en`, or `None` to detect it.
+  pub language: Option<String>,
+  /// How much audio is buffered before it's transcribed. Longer is more
+  /// accurate, shorter has less latency; FFmpeg's default is 3 seconds.
+  pub queue: Option<Duration>,
+  /// Whether to run the model on a GPU.
+  pub use_gpu: bool,
+  /// The index of the GPU to run on, or `None` for the first.
+  pub gpu_device: Option<u32>,
+  /// A voice activity detection model, e.g. `ggml-silero-v5.1.2.bin`, to
+  /// split the audio at pauses in speech rather than every `queue`.
+  pub vad_model: Option<PathBuf>,
+  /// The probability above which the VAD model considers audio speech.
+  pub vad_threshold: Option<f32>,
+}
+
+impl Default for TranscribeOptions {
+  fn default() -> Self {
+    Self {
+      language: None,
+      queue: None,
+      use_gpu: true,
+      gpu_device: None,
+      vad_model: None,
+      vad_threshold: None,
+    }
+  }
+}
+
+impl TranscribeOptions {
+  /// Transcribe `language`, e.g. `en`, instead of detecting it.
+  pub fn language<S: Into<String>>(mut self, language: S) -> Self {
+    self.language = Some(language.into());
+    self
+  }
+
+  /// Buffer `queue` of audio before each transcription.
+  pub fn queue(mut self, queue: Duration) -> Self {
+    self.queue = Some(queue);
+    self
+  }
+
+  /// Run the model on the CPU.
+  pub fn cpu_only(mut self) -> Self {
+    self.use_gpu = false;
+    self
+  }
+
+  /// Run the model on the GPU with index `device`.
+  pub fn gpu_device(mut self, device: u32) -> Self {
+    self.gpu_device = Some(device);
+    self
+  }
+
+  /// Split the audio at pauses detected by the VAD model at `model_path`.
+  pub fn vad_model<P: AsRef<Path>>(mut self, model_path: P) -> Self {
+    self.vad_model = Some(model_path.as_ref().to_path_buf());
+    self
+  }
+
+  /// Set the speech probability threshold of the VAD model.
+  pub fn vad_threshold(mut self, threshold: f32) -> Self {
+    self.vad_threshold = Some(threshold);
+    self
+  }
+}
+
+/// The `whisper` filter which writes each segment to stdout as a line of
+/// JSON, for [`transcribe`] to parse.
+///
+/// ```rust
+/// use ffmpeg_sidecar::transcribe::{whisper_filter, TranscribeOptions};
+/// use std::path::Path;
+///
+/// let options = TranscribeOptions::default().language("en").cpu_only();
+/// assert_eq!(
+///   whisper_filter(Path::new("models/ggml-base.en.bin"), &options),
+///   r"whisper=model=models/ggml-base.en.bin:destination=pipe\\:1:format=json:language=en:use_gpu=false"
+/// );
+/// ```
+pub fn whisper_filter(model_path: &Path, options: &TranscribeOptions) -> String {
+  let mut filter_options = vec![
+    ("model", model_path.to_string_lossy().to_string()),
+    ("destination", "pipe:1".to_string()),
+    ("format", "json".to_string()),
+  ];
+  if let Some(language) = &options.language {
+    filter_options.push(("language", language.clone()));
+  }
+  if let Some(queue) = options.queue {
+    filter_options.push(("queue", format!("{}ms", queue.as_millis())));
+  }
+  if !options.use_gpu {
+    filter_options.push(("use_gpu", "false".to_string()));
+  }
+  if let Some(device) = options.gpu_device {
+    filter_options.push(("gpu_device", device.to_string()));
+  }
+  if let Some(vad_model) = &options.vad_model {
+    filter_options.push(("vad_model", vad_model.to_string_lossy().to_string()));
+  }
+  if let Some(threshold) = options.vad_threshold {
+    filter_options.push(("vad_threshold", threshold.to_string()));
+  }
+
+  let filter_options: Vec<String> = filter_options
+    .into_iter()
+    .map(|(key, value)| format!("{key}={}", escape(&escape(&value, ":"), "[],;")))
+    .collect();
+  format!("whisper={}", filter_options.join(":"))
+}
+
+/// Transcribe the speech in the first audio stream of `input` with the
+/// whisper.cpp model at `model_path`, e.g. `ggml-base.en.bin`.
+///
+/// Segments are yielded as the model produces them, so a long input can be
+/// processed incrementally. If FFmpeg fails, the last item is the error.
+///
+/// ```rust,no_run
+/// use ffmpeg_sidecar::transcribe::{transcribe, TranscribeOptions};
+///
+/// let options = TranscribeOptions::default().language("en");
+/// for segment in transcribe("interview.mp4", "models/ggml-base.en.bin", &options)? {
+///   let segment = segment?;
+///   println!("[{:?} - {:?}] {}", segment.start, segment.end, segment.text);
+/// }
+/// # anyhow::Ok(())
+/// ```
+pub fn transcribe<I: AsRef<Path>, M: AsRef<Path>>(
+  input: I,
+  model_path: M,
+  options: &TranscribeOptions,
+) -> anyhow::Result<Transcription> {
+  if !ffmpeg_features()?.has_whisper {
+    anyhow::bail!(
+      "FFmpeg was built without the whisper filter (FFmpeg 8.0+ with --enable-whisper)"
+    );
+  }
+  let model_path = model_path.as_ref();
+  anyhow::ensure!(
+    model_path.is_file(),
+    "Model not found: {}",
+    model_path.display()
+  );
+
+  let mut child = FfmpegCommand::new()
+    .hide_banner()
+    .input_path(input)
+    .args(["-vn", "-af", &whisper_filter(model_path, options)])
+    .format("null")
+    .output("-")
+    .spawn()?;
+  let stdout = child.take_stdout().context("No stdout channel")?;
+
+  // The log has to be drained for FFmpeg to make progress
+  let iter = child.iter()?;
+  let errors = std::thread::spawn(move || {
+    iter
+      .filter_map(|event| match event {
+        FfmpegEvent::Log(LogLevel::Error | LogLevel::Fatal, line) | FfmpegEvent::Error(line) => {
+          Some(line)
+        }
+        _ => None,
+      })
+      .collect()
+  });
+
+  Ok(Transcription {
+    lines: BufReader::new(stdout).lines(),
+    child,
+    errors: Some(errors),
+  })
+}
+
+/// An iterator over the segments of a [`transcribe`] in progress. Dropping
+/// it stops FFmpeg.
+pub struct Transcription {
+  lines: Lines<BufReader<ChildStdout>>,
+  child: FfmpegChild,
+  /// The errors logged by FFmpeg, until the end of the output.
+  errors: Option<JoinHandle<Vec<String>>>,
+}
+
+impl Transcription {
+  /// Reap FFmpeg, returning an error if it failed.
+  fn finish(&mut self) -> anyhow::Result<()> {
+    let status = self.child.wait()?;
+    let errors = self
+      .errors
+      .take()
+      .and_then(|errors| errors.join().ok())
+      .unwrap_or_default();
+    match status.success() {
+      true => Ok(()),
+      false => anyhow::bail!("FFmpeg exited with {status}: {}", errors.join("\n")),
+    }
+  }
+}
+
+impl Iterator for Transcription {
+  type Item = anyhow::Result<TranscriptSegment>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    self.errors.as_ref()?;
+    for line in self.lines.by_ref() {
+      let line = match line {
+        Ok(line) => line,
+        Err(e) => return Some(Err(e.into())),
+      };
+      if line.trim().is_empty() {
+        continue;
+      }
+      return Some(parse_segment(&line).with_context(|| format!("Invalid segment: {line}")));
+    }
+    self.finish().err().map(Err)
+  }
+}
+
+impl Drop for Transcription {
+  fn drop(&mut self) {
+    if self.errors.take().is_some() {
+      self.child.quit_or_kill(QUIT_GRACE).ok();
+    }
+  }
+}
+
+/// Parse a segment written by the `whisper` filter in JSON format, like
+/// `{"start":0,"end":2480,"text":"Hello there."}` with times in milliseconds.
+fn parse_segment(line: &str) -> Option<TranscriptSegment> {
+  let millis = |key: &str| -> Option<u64> {
+    let (_, rest) = line.split_once(&format!("\"{key}\":"))?;
+    let digits = rest.trim_start();
+    let end = digits
+      .find(|c: char| !c.is_ascii_digit())
+      .unwrap_or(digits.len());
+    digits[..end].parse().ok()
+  };
+  let (_, text) = line.split_once("\"text\":\"")?;
+  let text = text.trim_end().strip_suffix("\"}")?;
+  Some(TranscriptSegment {
+    start: Duration::from_millis(millis("start")?),
+    end: Duration::from_millis(millis("end")?),
+    text: unescape_json(text).trim().to_string(),
+  })
+}
+
+/// Resolve the backslash escapes of a JSON string.
+fn unescape_json(text: &str) -> String {
+  let mut unescaped = String::with_capacity(text.len());
+  let mut chars = text.chars();
+  while let Some(c) = chars.next() {
+    if c != '\\' {
+      unescaped.push(c);
+      continue;
+    }
+    match chars.next() {
+      Some('n') => unescaped.push('\n'),
+      Some('t') => unescaped.push('\t'),
+      Some('r') => unescaped.push('\r'),
+      Some('u') => {
+        let code: String = chars.by_ref().take(4).collect();
+        let c = u32::from_str_radix(&code, 16).ok().and_then(char::from_u32);
+        unescaped.push(c.unwrap_or(char::REPLACEMENT_CHARACTER));
+      }
+      Some(c) => unescaped.push(c),
+      None => {}
+    }
+  }
+  unescaped
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_segment() {
+    let segment = parse_segment(r#"{"start":1520,"end":4000,"text":" She said \"hi\" é"}"#);
+    assert_eq!(
+      segment,
+      Some(TranscriptSegment {
+        start: Duration::from_millis(1520),
+        end: Duration::from_millis(4000),
+        text: "She said \"hi\" é".to_string(),
+      })
+    );
+
+    // Quotes within the text may be left unescaped
+    let segment = parse_segment(r#"{"start":0,"end":800,"text":"A "quote""}"#).unwrap();
+    assert_eq!(segment.text, "A \"quote\"");
+
+    assert_eq!(parse_segment("[info] not a segment"), None);
+  }
+}
@@ -4,8 +4,11 @@ use anyhow::Context;
 
 use crate::command::BackgroundCommand;
 use crate::{event::FfmpegEvent, log_parser::FfmpegLogParser, paths::ffmpeg_path};
-use std::ffi::OsStr;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
 use std::process::{Command, Stdio};
+use std::sync::{Mutex, OnceLock};
 
 /// Alias for `ffmpeg -version`, parsing the version number and returning it.
 pub fn ffmpeg_version() -> anyhow::Result<String> {
@@ -15,6 +18,206 @@ pub fn ffmpeg_version() -> anyhow::Result<String> {
 /// Lower level variant of `ffmpeg_version` that exposes a customized path
 /// to the ffmpeg binary.
 pub fn ffmpeg_version_with_path<S: AsRef<OsStr>>(path: S) -> anyhow::Result<String> {
+  let (version, _) = parse_version_output(path)?;
+  Ok(version)
+}
+
+/// Alias for `ffmpeg -version`, parsing the version string into a
+/// comparable [`VersionInfo`].
+///
+/// ```rust,no_run
+/// use ffmpeg_sidecar::version::ffmpeg_version_ex;
+///
+/// let version = ffmpeg_version_ex()?;
+/// if !version.at_least(5, 0, 0) {
+///   anyhow::bail!("FFmpeg 5.0 or newer is required, found {}", version.raw);
+/// }
+/// # anyhow::Ok(())
+/// ```
+pub fn ffmpeg_version_ex() -> anyhow::Result<VersionInfo> {
+  ffmpeg_version_ex_with_path(ffmpeg_path())
+}
+
+/// Lower level variant of `ffmpeg_version_ex` that exposes a customized path
+/// to the ffmpeg binary.
+pub fn ffmpeg_version_ex_with_path<S: AsRef<OsStr>>(path: S) -> anyhow::Result<VersionInfo> {
+  let version = ffmpeg_version_with_path(path)?;
+  VersionInfo::parse(&version).with_context(|| format!("Unrecognized ffmpeg version: {version}"))
+}
+
+/// A parsed FFmpeg version string.
+///
+/// Release builds look like `6.1.1-full_build-www.gyan.dev`, while builds
+/// from the development branch look like `N-109875-geabc304d12-tessus`: a
+/// revision number, a git hash and a builder suffix, with no release number.
+/// Some development builds, like Gyan's git builds, are named after the date
+/// of their commit instead of its revision, e.g.
+/// `2024-01-24-git-00b288da73-full_build-www.gyan.dev`.
+///
+/// Development builds compare greater than every release, and are ordered by
+/// revision, or by date, among themselves.
+///
+/// ```rust
+/// use ffmpeg_sidecar::version::VersionInfo;
+///
+/// let release = VersionInfo::parse("6.1.1-full_build-www.gyan.dev").unwrap();
+/// assert_eq!((release.major, release.minor, release.patch), (6, 1, 1));
+/// assert_eq!(release.builder.as_deref(), Some("full_build-www.gyan.dev"));
+///
+/// let nightly = VersionInfo::parse("N-109875-geabc304d12-tessus").unwrap();
+/// assert_eq!(nightly.git_hash.as_deref(), Some("eabc304d12"));
+/// assert_eq!(nightly.builder.as_deref(), Some("tessus"));
+///
+/// assert!(nightly > release);
+/// assert!(release > VersionInfo::parse("5.1").unwrap());
+///
+/// let dated = VersionInfo::parse("2024-01-24-git-00b288da73-full_build-www.gyan.dev").unwrap();
+/// assert_eq!(dated.date.as_deref(), Some("2024-01-24"));
+/// assert!(dated.is_development());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct VersionInfo {
+  /// Zero for development builds.
+  pub major: u32,
+  pub minor: u32,
+  pub patch: u32,
+  /// The revision number of a development build, counting commits on the
+  /// development branch.
+  pub revision: Option<u32>,
+  /// The date of the commit of a development build named after it, as
+  /// `YYYY-MM-DD`.
+  pub date: Option<String>,
+  /// The abbreviated git commit hash of a development build.
+  pub git_hash: Option<String>,
+  /// Whatever the packager appended, e.g. `full_build-www.gyan.dev`,
+  /// `static` or `0ubuntu0.22.04.1`.
+  pub builder: Option<String>,
+  /// The original version string.
+  pub raw: String,
+}
+
+impl VersionInfo {
+  pub fn parse(version: &str) -> Option<Self> {
+    let raw = version.to_string();
+    let non_empty = |s: String| (!s.is_empty()).then_some(s);
+
+    if let Some(rest) = version.strip_prefix("N-") {
+      let mut parts = rest.split('-');
+      let revision = parts.next()?.parse().ok()?;
+      let git_hash = parts
+        .next()
+        .map(|hash| hash.trim_start_matches('g').to_string());
+      let builder = non_empty(parts.collect::<Vec<_>>().join("-"));
+      return Some(Self {
+        major: 0,
+        minor: 0,
+        patch: 0,
+        revision: Some(revision),
+        date: None,
+        git_hash,
+        builder,
+        raw,
+      });
+    }
+
+    if let Some(dated) = Self::parse_dated(version) {
+      return Some(dated);
+    }
+
+    // Builds from release tags are sometimes named after the tag, e.g. `n6.1`
+    let version = version.strip_prefix('n').unwrap_or(version);
+    let (numbers, builder) = match version.split_once('-') {
+      Some((numbers, builder)) => (numbers, non_empty(builder.to_string())),
+      None => (version, None),
+    };
+    let mut numbers = numbers.split('.');
+    let major = numbers.next()?.parse().ok()?;
+    let minor = numbers.next().map_or(Some(0), |n| n.parse().ok())?;
+    let patch = numbers.next().map_or(Some(0), |n| n.parse().ok())?;
+    Some(Self {
+      major,
+      minor,
+      patch,
+      revision: None,
+      date: None,
+      git_hash: None,
+      builder,
+      raw,
+    })
+  }
+
+  /// Parse a development build named after its date, e.g.
+  /// `2024-01-24-git-00b288da73-full_build-www.gyan.dev`.
+  fn parse_dated(version: &str) -> Option<Self> {
+    let mut parts = version.splitn(6, '-');
+    let mut number = |len: usize| {
+      let part = parts.next()?;
+      (part.len() == len && part.chars().all(|c| c.is_ascii_digit())).then_some(part)
+    };
+    let date = [number(4)?, number(2)?, number(2)?].join("-");
+    if parts.next()? != "git" {
+      return None;
+    }
+    let git_hash = parts.next().map(str::to_string);
+    let builder = parts.next().map(str::to_string);
+    Some(Self {
+      major: 0,
+      minor: 0,
+      patch: 0,
+      revision: None,
+      date: Some(date),
+      git_hash,
+      builder,
+      raw: version.to_string(),
+    })
+  }
+
+  /// Whether this is a build of the development branch (`N-...`, or named
+  /// after its date).
+  pub fn is_development(&self) -> bool {
+    self.revision.is_some() || self.date.is_some()
+  }
+
+  /// Whether this version is `major.minor.patch` or newer. Always `true` for
+  /// development builds.
+  pub fn at_least(&self, major: u32, minor: u32, patch: u32) -> bool {
+    self.is_development() || (self.major, self.minor, self.patch) >= (major, minor, patch)
+  }
+
+  fn sort_key(&self) -> (bool, u32, u32, u32, Option<u32>, Option<&str>) {
+    let Self {
+      major,
+      minor,
+      patch,
+      revision,
+      ..
+    } = *self;
+    let date = self.date.as_deref();
+    (self.is_development(), major, minor, patch, revision, date)
+  }
+}
+
+/// Versions are ordered by release number (or revision or date for
+/// development builds). Builds of the same version by different packagers
+/// are unordered, and so are development builds named after a revision and
+/// after a date.
+impl PartialOrd for VersionInfo {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    if self.revision.is_some() && other.date.is_some()
+      || self.date.is_some() && other.revision.is_some()
+    {
+      return None;
+    }
+    match self.sort_key().cmp(&other.sort_key()) {
+      Ordering::Equal if self != other => None,
+      ordering => Some(ordering),
+    }
+  }
+}
+
+/// Run `ffmpeg -version`, returning the version string and configuration
+/// flags.
+fn parse_version_output<S: AsRef<OsStr>>(path: S) -> anyhow::Result<(String, Vec<String>)> {
   let mut cmd = Command::new(&path)
     .create_no_window()
     .arg("-version")
@@ -24,9 +227,11 @@ pub fn ffmpeg_version_with_path<S: AsRef<OsStr>>(path: S) -> anyhow::Result<Stri
   let mut parser = FfmpegLogParser::new(stdout);
 
   let mut version: Option<String> = None;
+  let mut configuration = Vec::new();
   while let Ok(event) = parser.parse_next_event() {
     match event {
       FfmpegEvent::ParsedVersion(v) => version = Some(v.version),
+      FfmpegEvent::ParsedConfiguration(c) => configuration = c.configuration,
       FfmpegEvent::LogEOF => break,
       _ => {}
     }
@@ -35,5 +240,154 @@ pub fn ffmpeg_version_with_path<S: AsRef<OsStr>>(path: S) -> anyhow::Result<Stri
   if !exit_status.success() {
     anyhow::bail!("ffmpeg -version exited with non-zero status");
   }
-  version.context("Failed to parse ffmpeg version")
+  let version = version.context("Failed to parse ffmpeg version")?;
+  Ok((version, configuration))
+}
+
+/// The capabilities of an FFmpeg build, derived from its version and
+/// `configuration:` flags.
+///
+/// ```rust
+/// use ffmpeg_sidecar::version::FfmpegFeatures;
+///
+/// let features = FfmpegFeatures::new(
+///   "6.1.1-full_build-www.gyan.dev",
+///   vec!["--enable-gpl".into(), "--enable-libx265".into(), "--enable-nvenc".into()],
+/// );
+/// assert!(features.has_libx265);
+/// assert!(features.has_nvenc);
+/// assert!(!features.has_libass);
+/// assert!(features.version_at_least(5, 0));
+/// assert!(!features.version_at_least(7, 0));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct FfmpegFeatures {
+  /// The version string, e.g. `6.1.1-full_build-www.gyan.dev`.
+  pub version: String,
+  /// Every flag of the `configuration:` line, e.g. `--enable-gpl`.
+  pub configuration: Vec<String>,
+  /// The `libx265` HEVC encoder.
+  pub has_libx265: bool,
+  /// NVIDIA hardware encoders such as `h264_nvenc` and `hevc_nvenc`.
+  pub has_nvenc: bool,
+  /// The `libass` subtitle renderer, required by the `subtitles` and `ass`
+  /// filters.
+  pub has_libass: bool,
+  /// The `whisper` speech recognition filter of FFmpeg 8.0 and later; see
+  /// [`transcribe`](crate::transcribe::transcribe).
+  pub has_whisper: bool,
+}
+
+impl FfmpegFeatures {
+  pub fn new<S: Into<String>>(version: S, configuration: Vec<String>) -> Self {
+    let mut features = Self {
+      version: version.into(),
+      configuration,
+      has_libx265: false,
+      has_nvenc: false,
+      has_libass: false,
+      has_whisper: false,
+    };
+    features.has_libx265 = features.is_enabled("libx265");
+    features.has_nvenc = features.is_enabled("nvenc") || features.is_enabled("ffnvcodec");
+    features.has_libass = features.is_enabled("libass");
+    features.has_whisper = features.is_enabled("whisper");
+    features
+  }
+
+  /// Whether the build was configured with `--enable-<name>`, e.g.
+  /// `is_enabled("libvpx")`.
+  pub fn is_enabled(&self, name: &str) -> bool {
+    let flag = format!("--enable-{name}");
+    self.configuration.iter().any(|arg| *arg == flag)
+  }
+
+  /// The parsed version, or `None` if the version string isn't recognized.
+  pub fn version_info(&self) -> Option<VersionInfo> {
+    VersionInfo::parse(&self.version)
+  }
+
+  /// Whether the version is at least `major.minor`. Development builds
+  /// (`N-...`) have no release number and always return `true`, since they
+  /// are newer than any release they could be compared against in practice.
+  pub fn version_at_least(&self, major: u32, minor: u32) -> bool {
+    self
+      .version_info()
+      .is_some_and(|version| version.at_least(major, minor, 0))
+  }
+}
+
+type FeatureCache = Mutex<HashMap<OsString, FfmpegFeatures>>;
+
+/// Alias for `ffmpeg -version`, parsing the version and configuration into an
+/// [`FfmpegFeatures`]. The result is cached for the process lifetime, so
+/// repeated calls don't spawn FFmpeg again.
+pub fn ffmpeg_features() -> anyhow::Result<FfmpegFeatures> {
+  ffmpeg_features_with_path(ffmpeg_path())
+}
+
+/// Lower level variant of `ffmpeg_features` that exposes a customized path
+/// to the ffmpeg binary. Results are cached per path.
+pub fn ffmpeg_features_with_path<S: AsRef<OsStr>>(path: S) -> anyhow::Result<FfmpegFeatures> {
+  static CACHE: OnceLock<FeatureCache> = OnceLock::new();
+  let cache = CACHE.get_or_init(Default::default);
+  let key = path.as_ref().to_os_string();
+  if let Some(features) = cache.lock().unwrap().get(&key) {
+    return Ok(features.clone());
+  }
+  let (version, configuration) = parse_version_output(&key)?;
+  let features = FfmpegFeatures::new(version, configuration);
+  cache.lock().unwrap().insert(key, features.clone());
+  Ok(features)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_version_info() {
+    let ubuntu = VersionInfo::parse("4.4.2-0ubuntu0.22.04.1").unwrap();
+    assert_eq!((ubuntu.major, ubuntu.minor, ubuntu.patch), (4, 4, 2));
+    assert_eq!(ubuntu.builder.as_deref(), Some("0ubuntu0.22.04.1"));
+
+    let static_build = VersionInfo::parse("7.0-static").unwrap();
+    assert_eq!((static_build.major, static_build.minor), (7, 0));
+    assert_eq!(static_build.builder.as_deref(), Some("static"));
+
+    let tag = VersionInfo::parse("n6.1").unwrap();
+    assert_eq!((tag.major, tag.minor, tag.builder), (6, 1, None));
+
+    let btbn = VersionInfo::parse("N-112000-g4b9a8a3c3c-20230916").unwrap();
+    assert_eq!(btbn.revision, Some(112000));
+    assert!(btbn.is_development());
+    assert!(btbn.at_least(99, 0, 0));
+
+    assert_eq!(VersionInfo::parse("git-2020-01-01"), None);
+
+    let gyan = VersionInfo::parse("2024-01-24-git-00b288da73-full_build-www.gyan.dev").unwrap();
+    assert_eq!(gyan.date.as_deref(), Some("2024-01-24"));
+    assert_eq!(gyan.git_hash.as_deref(), Some("00b288da73"));
+    assert_eq!(gyan.builder.as_deref(), Some("full_build-www.gyan.dev"));
+    assert_eq!((gyan.major, gyan.revision), (0, None));
+    assert!(gyan.at_least(99, 0, 0));
+  }
+
+  #[test]
+  fn test_version_info_ordering() {
+    let parse = |version| VersionInfo::parse(version).unwrap();
+    assert!(parse("6.1.1") > parse("6.1"));
+    assert!(parse("6.10") > parse("6.9.9"));
+    assert!(parse("N-109875-geabc304d12") < parse("N-112000-g4b9a8a3c3c"));
+    assert!(parse("2023-12-31-git-1e3d8ea1b5-essentials_build") > parse("7.0"));
+    assert!(parse("2023-12-31-git-1e3d8ea1b5") < parse("2024-01-24-git-00b288da73"));
+    assert_eq!(
+      parse("2024-01-24-git-00b288da73").partial_cmp(&parse("N-112000-g4b9a8a3c3c")),
+      None
+    );
+    assert_eq!(
+      parse("6.1-static").partial_cmp(&parse("6.1-full_build")),
+      None
+    );
+  }
 }
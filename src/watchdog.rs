@@ -0,0 +1,210 @@
+//! Detection of FFmpeg processes which stop making progress without exiting,
+//! e.g. a live input which silently stops delivering data.
+
+use std::{
+  io,
+  process::ExitStatus,
+  sync::{
+    mpsc::{channel, RecvTimeoutError, Sender, SyncSender},
+    Arc, Mutex,
+  },
+  time::{Duration, Instant},
+};
+
+use crate::event::FfmpegEvent;
+
+/// Settings from `FfmpegChild::stall_watchdog`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct StallWatchdogConfig {
+  pub(crate) timeout: Duration,
+  pub(crate) kill: bool,
+}
+
+/// When FFmpeg last made progress, stamped by the threads which read its
+/// output as they read it, so that a slow consumer of the events doesn't
+/// look like a stalled process.
+#[derive(Debug, Clone)]
+pub(crate) struct ActivityClock(Arc<Mutex<Instant>>);
+
+impl Default for ActivityClock {
+  fn default() -> Self {
+    Self(Arc::new(Mutex::new(Instant::now())))
+  }
+}
+
+impl ActivityClock {
+  /// Record that the process made progress.
+  pub(crate) fn touch(&self) {
+    if let Ok(mut instant) = self.0.lock() {
+      *instant = Instant::now();
+    }
+  }
+
+  /// How long ago the process last made progress.
+  pub(crate) fn elapsed(&self) -> Option<Duration> {
+    self.0.lock().ok().map(|instant| instant.elapsed())
+  }
+}
+
+/// Kills FFmpeg from threads which don't own its child, like the watchdog's.
+/// The child disarms it before reaping the process, after which its ID may
+/// belong to another process.
+#[derive(Debug, Clone)]
+pub(crate) struct ProcessKiller {
+  pid: Option<u32>,
+  armed: Arc<Mutex<bool>>,
+}
+
+impl ProcessKiller {
+  pub(crate) fn new(pid: Option<u32>) -> Self {
+    Self {
+      pid,
+      armed: Arc::new(Mutex::new(true)),
+    }
+  }
+
+  /// Kill the process, unless it has already been reaped.
+  pub(crate) fn kill(&self) {
+    let (Some(pid), Ok(armed)) = (self.pid, self.armed.lock()) else {
+      return;
+    };
+    // Holding the lock keeps the process from being reaped meanwhile
+    if *armed {
+      kill_process(pid);
+    }
+  }
+
+  /// Reap the process with `try_wait` if it has exited, disarming this at
+  /// the same time.
+  pub(crate) fn try_reap(
+    &self,
+    try_wait: impl FnOnce() -> io::Result<Option<ExitStatus>>,
+  ) -> io::Result<Option<ExitStatus>> {
+    let mut armed = self.armed.lock().ok();
+    let status = try_wait()?;
+    if let (Some(armed), Some(_)) = (armed.as_mut(), status) {
+      **armed = false;
+    }
+    Ok(status)
+  }
+
+  /// Disarm this before reaping a process which is known to have exited.
+  pub(crate) fn disarm(&self) {
+    if let Ok(mut armed) = self.armed.lock() {
+      *armed = false;
+    }
+  }
+}
+
+/// A background thread which emits `FfmpegEvent::Stalled` when the
+/// [`ActivityClock`] hasn't been touched for longer than the timeout. The
+/// thread exits when this is dropped.
+pub(crate) struct StallWatchdog {
+  _stop: Sender<()>,
+}
+
+impl StallWatchdog {
+  pub(crate) fn spawn(
+    killer: ProcessKiller,
+    config: StallWatchdogConfig,
+    activity: ActivityClock,
+    tx: SyncSender<FfmpegEvent>,
+  ) -> Self {
+    let (stop, stopped) = channel::<()>();
+    let poll_interval =
+      (config.timeout / 4).clamp(Duration::from_millis(10), Duration::from_secs(1));
+
+    std::thread::spawn(move || {
+      let mut reported = false;
+      // Only a timeout means the watchdog is still wanted; the sender is
+      // dropped to stop it.
+      while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(poll_interval) {
+        let Some(since) = activity.elapsed() else {
+          break;
+        };
+        if since < config.timeout {
+          reported = false;
+          continue;
+        }
+        if reported {
+          continue;
+        }
+        reported = true;
+        if config.kill {
+          killer.kill();
+        }
+        if tx.send(FfmpegEvent::Stalled { since }).is_err() {
+          break;
+        }
+      }
+    });
+
+    Self { _stop: stop }
+  }
+}
+
+/// Forcefully terminate a process by ID, since the `Child` is owned by the
+/// consumer rather than the watchdog thread.
+fn kill_process(pid: u32) {
+  #[cfg(unix)]
+  // SAFETY: sending a signal has no memory safety preconditions.
+  unsafe {
+    libc::kill(pid as libc::pid_t, libc::SIGKILL);
+  }
+  #[cfg(windows)]
+  {
+    use crate::command::BackgroundCommand;
+    std::process::Command::new("taskkill")
+      .create_no_window()
+      .args(["/F", "/PID", &pid.to_string()])
+      .stdout(std::process::Stdio::null())
+      .stderr(std::process::Stdio::null())
+      .status()
+      .ok();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::{process::Command, sync::mpsc::sync_channel};
+
+  #[cfg(unix)]
+  #[test]
+  fn test_killer_spares_reaped_process() -> anyhow::Result<()> {
+    let mut child = Command::new("sleep").arg("10").spawn()?;
+    let killer = ProcessKiller::new(Some(child.id()));
+    killer.disarm();
+    killer.kill();
+    assert!(child.try_wait()?.is_none());
+
+    let killer = ProcessKiller::new(Some(child.id()));
+    killer.kill();
+    assert!(killer.try_reap(|| child.wait().map(Some))?.is_some());
+    assert!(!*killer.armed.lock().unwrap());
+    Ok(())
+  }
+
+  #[test]
+  fn test_reports_stall_once_until_activity() {
+    let (tx, rx) = sync_channel(0);
+    let config = StallWatchdogConfig {
+      timeout: Duration::from_millis(40),
+      kill: false,
+    };
+    let activity = ActivityClock::default();
+    let watchdog = StallWatchdog::spawn(ProcessKiller::new(None), config, activity.clone(), tx);
+
+    let Ok(FfmpegEvent::Stalled { since }) = rx.recv() else {
+      panic!("expected a stall");
+    };
+    assert!(since >= config.timeout);
+    assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+
+    activity.touch();
+    assert!(matches!(rx.recv(), Ok(FfmpegEvent::Stalled { .. })));
+
+    drop(watchdog);
+    assert!(rx.recv().is_err());
+  }
+}